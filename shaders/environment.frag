@@ -0,0 +1,113 @@
+#version 430 core
+
+in vec2 uv;
+
+out vec4 finalColor;
+
+// The sky, mipmapped, and what to filter it into, see src/environment.rs
+uniform samplerCube environment;
+const int IRRADIANCE = 0;
+const int SPECULAR = 1;
+uniform int environmentFilter;
+uniform int face;        // Of the cube map being drawn, in OpenGL's order
+uniform float roughness; // Of the surfaces the specular level being drawn is for
+
+const float PI = 3.14159265;
+const uint SPECULAR_SAMPLES = 128u;
+
+// The direction through the fragment, like skybox::face_direction
+vec3 faceDirection()
+{
+    float u = uv.x * 2.0 - 1.0;
+    float v = uv.y * 2.0 - 1.0;
+    switch (face) {
+        case 0: return vec3(1.0, -v, -u);
+        case 1: return vec3(-1.0, -v, u);
+        case 2: return vec3(u, 1.0, v);
+        case 3: return vec3(u, -1.0, -v);
+        case 4: return vec3(u, -v, 1.0);
+        default: return vec3(-u, -v, -1.0);
+    }
+}
+
+// Two axes across `normal`, so directions around it can be made up from angles
+mat3 around(vec3 normal)
+{
+    vec3 up = abs(normal.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 right = normalize(cross(up, normal));
+    return mat3(right, cross(normal, right), normal);
+}
+
+// The sky's light over the half of it facing `normal`, weighted by the angle it comes in at, and
+// divided by PI, so it is the diffuse light of a white surface facing that way. The sky is looked up
+// blurred to about as many texels as there are samples, so small bright spots aren't missed.
+vec3 irradiance(vec3 normal)
+{
+    const float STEP = 0.05;
+    mat3 axes = around(normal);
+    float texels = float(textureSize(environment, 0).x);
+    float lod = max(log2(texels * STEP * 2.0), 0.0);
+    vec3 sum = vec3(0.0);
+    float samples = 0.0;
+    for (float phi = 0.0; phi < 2.0 * PI; phi += STEP) {
+        for (float theta = 0.0; theta < 0.5 * PI; theta += STEP) {
+            vec3 local = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            vec3 light = textureLod(environment, axes * local, lod).rgb;
+            sum += light * cos(theta) * sin(theta);
+            samples += 1.0;
+        }
+    }
+    return PI * sum / samples;
+}
+
+// The `i`th of `count` points spread evenly over a square, Hammersley's sequence
+vec2 hammersley(uint i, uint count)
+{
+    uint bits = bitfieldReverse(i);
+    return vec2(float(i) / float(count), float(bits) * 2.3283064365386963e-10);
+}
+
+// The sky reflected around `reflected` by surfaces of `roughness`, with the camera taken to look
+// straight at them (Karis' split sum). The directions sampled are picked by the GGX distribution
+// of the microfacets, each looked up as blurred as the part of the sky it stands for is large.
+vec3 prefiltered(vec3 reflected)
+{
+    if (roughness == 0.0) {
+        return textureLod(environment, reflected, 0.0).rgb;
+    }
+    mat3 axes = around(reflected);
+    float alpha = roughness * roughness;
+    float texels = float(textureSize(environment, 0).x);
+    float texelAngle = 4.0 * PI / (6.0 * texels * texels);
+    vec3 sum = vec3(0.0);
+    float weight = 0.0;
+    for (uint i = 0u; i < SPECULAR_SAMPLES; i++) {
+        vec2 point = hammersley(i, SPECULAR_SAMPLES);
+        float phi = 2.0 * PI * point.x;
+        float cosTheta = sqrt((1.0 - point.y) / (1.0 + (alpha * alpha - 1.0) * point.y));
+        float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+        vec3 halfway = axes * vec3(sinTheta * cos(phi), sinTheta * sin(phi), cosTheta);
+        vec3 toLight = reflect(-reflected, halfway);
+        float lightAngle = dot(reflected, toLight);
+        if (lightAngle > 0.0) {
+            // With the camera along `reflected`, the halfway's angle to it is that to the normal
+            float d = cosTheta * cosTheta * (alpha * alpha - 1.0) + 1.0;
+            float pdf = alpha * alpha / (PI * d * d) / 4.0;
+            float sampleAngle = 1.0 / (float(SPECULAR_SAMPLES) * pdf);
+            float lod = max(0.5 * log2(sampleAngle / texelAngle) + 1.0, 0.0);
+            sum += textureLod(environment, toLight, lod).rgb * lightAngle;
+            weight += lightAngle;
+        }
+    }
+    return sum / max(weight, 1e-4);
+}
+
+void main()
+{
+    vec3 direction = normalize(faceDirection());
+    if (environmentFilter == IRRADIANCE) {
+        finalColor = vec4(irradiance(direction), 1.0);
+    } else {
+        finalColor = vec4(prefiltered(direction), 1.0);
+    }
+}
@@ -0,0 +1,12 @@
+#version 430 core
+
+// Where on the face of the cube map being drawn the fragment is, from 0 to 1 across and down
+out vec2 uv;
+
+// A triangle covering the face, made up from the vertex's index, as nothing is drawn from a mesh
+void main()
+{
+    vec2 corner = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    uv = corner;
+    gl_Position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,10 @@
+#version 430 core
+
+// The depth of the scene drawn into the G-buffer, copied out so more can be drawn over the lit scene
+// (see src/deferred.rs)
+uniform sampler2D gDepth;
+
+void main()
+{
+    gl_FragDepth = texelFetch(gDepth, ivec2(gl_FragCoord.xy), 0).r;
+}
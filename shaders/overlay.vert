@@ -0,0 +1,16 @@
+#version 430 core
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec4 vertexColor;
+
+out vec4 fragColor;
+
+// Maps pixels from the top left of the screen to clip space
+uniform mat4 transformMatrix;
+
+void main()
+{
+    gl_Position = transformMatrix * vec4(position, 0.0, 1.0);
+
+    fragColor = vertexColor;
+}
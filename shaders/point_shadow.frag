@@ -0,0 +1,17 @@
+#version 430 core
+
+in vec3 worldPosition;
+
+uniform vec3 lightPosition;
+uniform float lightRange;
+uniform float opacity = 1.0;
+
+void main()
+{
+    // Mostly see-through surfaces, like the discs of spinning rotors, cast no shadow
+    if (opacity < 0.5) {
+        discard;
+    }
+    // How far from the light rather than how deep, so it can be compared in any direction
+    gl_FragDepth = distance(worldPosition, lightPosition) / lightRange;
+}
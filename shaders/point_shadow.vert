@@ -0,0 +1,26 @@
+#version 430 core
+
+layout(location = 0) in vec3 position;
+layout(location = 5) in vec4 joints;
+layout(location = 6) in vec4 weights;
+
+// Just the model matrix, as point_shadow.geom projects onto every face of the cube map itself
+uniform mat4 transformMatrix;
+
+// Posed like in simple.vert, so skinned meshes cast the shadows of their pose
+uniform bool skinned = false;
+uniform mat4 jointMatrices[64];
+
+void main()
+{
+    mat4 skin = mat4(1.0);
+    if (skinned) {
+        skin = weights.x * jointMatrices[int(joints.x)]
+             + weights.y * jointMatrices[int(joints.y)]
+             + weights.z * jointMatrices[int(joints.z)]
+             + weights.w * jointMatrices[int(joints.w)];
+    }
+
+    // In the world
+    gl_Position = transformMatrix * skin * vec4(position, 1.0);
+}
@@ -0,0 +1,188 @@
+#version 430 core
+
+in vec2 uv;
+
+out vec4 finalColor;
+
+// Which step of post-processing this is, see src/post.rs
+const int BRIGHT = 0;
+const int BLUR = 1;
+const int COMPOSITE = 2;
+const int DEPTH_OF_FIELD = 3;
+const int MOTION_BLUR = 4;
+const int FXAA = 5;
+uniform int postStep;
+
+uniform sampler2D source; // What the step filters
+uniform sampler2D bloom;  // The blurred bright parts of the scene, for the composite step
+
+uniform float bloomThreshold = 1.0;
+uniform float bloomIntensity = 0.0;
+uniform vec2 blurDirection; // Across or up
+
+// The scene's depth, turned back into distances from the camera with the third row of the
+// projection it was drawn with, and where and how sharply the camera is focused
+uniform sampler2D depth;
+uniform vec2 depthProjection;
+uniform float focusDistance = 40.0;
+uniform float focusRange = 30.0;
+uniform float maxFocusBlur = 10.0; // In pixels across
+const int FOCUS_SAMPLES = 48;
+const float GOLDEN_ANGLE = 2.39996323;
+
+// How far each pixel moved since the last frame, across the screen from 0 to 1, with alpha 0 where
+// no mesh was drawn, and how the sky moved with the camera instead
+uniform sampler2D velocity;
+uniform mat4 skyReprojection;
+uniform float shutter = 0.5;
+uniform float maxMotionBlur = 32.0; // In pixels along
+const int MOTION_SAMPLES = 16;
+
+// How far FXAA follows an edge, in pixels either way, and how little contrast it leaves alone
+const float FXAA_SPAN_MAX = 8.0;
+const float FXAA_REDUCE_MUL = 1.0 / 8.0;
+const float FXAA_REDUCE_MIN = 1.0 / 128.0;
+
+// How the colors are brought into what the screen shows, as post::ToneMapping
+const int CLIP = 0;
+const int REINHARD = 1;
+const int ACES = 2;
+uniform int toneMapping = CLIP;
+uniform float exposure = 1.0;
+
+// A Gaussian blur's weights, from the middle texel out to four texels away on either side
+const float BLUR_WEIGHTS[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+vec3 toneMapped(vec3 color)
+{
+    if (toneMapping == REINHARD) {
+        return color / (1.0 + color);
+    }
+    if (toneMapping == ACES) {
+        return clamp(color * (2.51 * color + 0.03) / (color * (2.43 * color + 0.59) + 0.14), 0.0, 1.0);
+    }
+    return clamp(color, 0.0, 1.0);
+}
+
+// How far from the camera what is at `at` on the screen is
+float distanceAt(vec2 at)
+{
+    float ndc = texture(depth, at).r * 2.0 - 1.0;
+    return depthProjection.y / (ndc + depthProjection.x);
+}
+
+// The radius of the circle of confusion of what is `distance` from the camera, in pixels
+float confusion(float distance)
+{
+    return clamp(abs(distance - focusDistance) / focusRange, 0.0, 1.0) * 0.5 * maxFocusBlur;
+}
+
+// The source gathered over the circle of confusion at `uv`, from samples on a spiral
+vec3 focused()
+{
+    vec2 texel = 1.0 / vec2(textureSize(source, 0));
+    float distance = distanceAt(uv);
+    float radius = confusion(distance);
+    vec3 color = texture(source, uv).rgb;
+    float weight = 1.0;
+    for (int i = 1; i < FOCUS_SAMPLES; i++) {
+        float reach = 0.5 * maxFocusBlur * sqrt(float(i) / float(FOCUS_SAMPLES));
+        float angle = float(i) * GOLDEN_ANGLE;
+        vec2 at = uv + vec2(cos(angle), sin(angle)) * reach * texel;
+        float sampleDistance = distanceAt(at);
+        // What is behind is only spread as far as the pixel's own circle reaches
+        float sampleRadius = confusion(sampleDistance);
+        if (sampleDistance > distance) {
+            sampleRadius = min(sampleRadius, radius);
+        }
+        float covers = smoothstep(reach - 1.0, reach + 1.0, sampleRadius);
+        color += texture(source, at).rgb * covers;
+        weight += covers;
+    }
+    return color / weight;
+}
+
+// The source averaged along the way `uv` moved while the shutter was open
+vec3 blurredByMotion()
+{
+    vec4 moved = texture(velocity, uv);
+    if (moved.a == 0.0) {
+        vec4 previous = skyReprojection * vec4(uv * 2.0 - 1.0, 1.0, 1.0);
+        moved.xy = uv - (previous.xy / previous.w * 0.5 + 0.5);
+    }
+    vec2 size = vec2(textureSize(source, 0));
+    vec2 streak = moved.xy * shutter * size;
+    float pixels = length(streak);
+    if (pixels < 0.5) {
+        return texture(source, uv).rgb;
+    }
+    streak *= min(pixels, maxMotionBlur) / pixels / size;
+    // Centred on the pixel, so what moved is smeared both ways, and stays where it is drawn
+    vec3 color = vec3(0.0);
+    for (int i = 0; i < MOTION_SAMPLES; i++) {
+        float t = float(i) / float(MOTION_SAMPLES - 1) - 0.5;
+        color += texture(source, uv + streak * t).rgb;
+    }
+    return color / float(MOTION_SAMPLES);
+}
+
+float luma(vec3 color)
+{
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+// The source smoothed across the edge through `uv`, if there is one, after Lottes: the edge runs
+// across the way the brightness of the four diagonal neighbours changes, and the source is averaged
+// along it, over a shorter span if that one strays outside the neighbours' range of brightness
+vec3 antialiased()
+{
+    vec2 texel = 1.0 / vec2(textureSize(source, 0));
+    float lumaNW = luma(texture(source, uv + vec2(-1.0, -1.0) * texel).rgb);
+    float lumaNE = luma(texture(source, uv + vec2(1.0, -1.0) * texel).rgb);
+    float lumaSW = luma(texture(source, uv + vec2(-1.0, 1.0) * texel).rgb);
+    float lumaSE = luma(texture(source, uv + vec2(1.0, 1.0) * texel).rgb);
+    float lumaM = luma(texture(source, uv).rgb);
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    vec2 direction = vec2(-((lumaNW + lumaNE) - (lumaSW + lumaSE)), (lumaNW + lumaSW) - (lumaNE + lumaSE));
+    float reduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, -FXAA_SPAN_MAX, FXAA_SPAN_MAX) * texel;
+
+    vec3 near = 0.5 * (texture(source, uv + direction * (1.0 / 3.0 - 0.5)).rgb
+                     + texture(source, uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 far = 0.5 * near + 0.25 * (texture(source, uv - direction * 0.5).rgb
+                                  + texture(source, uv + direction * 0.5).rgb);
+    float lumaFar = luma(far);
+    return lumaFar < lumaMin || lumaFar > lumaMax ? near : far;
+}
+
+void main()
+{
+    if (postStep == BRIGHT) {
+        // Sampled between four texels of the scene, which are averaged by the linear filtering.
+        // What is above the threshold is kept, with the color it has.
+        vec3 color = texture(source, uv).rgb;
+        float brightness = max(color.r, max(color.g, color.b));
+        color *= max(brightness - bloomThreshold, 0.0) / max(brightness, 1e-4);
+        finalColor = vec4(color, 1.0);
+    } else if (postStep == BLUR) {
+        vec2 step = blurDirection / vec2(textureSize(source, 0));
+        vec3 color = texture(source, uv).rgb * BLUR_WEIGHTS[0];
+        for (int i = 1; i < 5; i++) {
+            color += texture(source, uv + step * i).rgb * BLUR_WEIGHTS[i];
+            color += texture(source, uv - step * i).rgb * BLUR_WEIGHTS[i];
+        }
+        finalColor = vec4(color, 1.0);
+    } else if (postStep == DEPTH_OF_FIELD) {
+        finalColor = vec4(focused(), 1.0);
+    } else if (postStep == MOTION_BLUR) {
+        finalColor = vec4(blurredByMotion(), 1.0);
+    } else if (postStep == FXAA) {
+        finalColor = vec4(antialiased(), 1.0);
+    } else {
+        vec3 color = texture(source, uv).rgb + bloomIntensity * texture(bloom, uv).rgb;
+        finalColor = vec4(toneMapped(exposure * color), 1.0);
+    }
+}
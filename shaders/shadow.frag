@@ -0,0 +1,13 @@
+#version 430 core
+
+// Only depth is drawn into the shadow map, so there is no color to write
+
+uniform float opacity = 1.0;
+
+void main()
+{
+    // Mostly see-through surfaces, like the discs of spinning rotors, cast no shadow
+    if (opacity < 0.5) {
+        discard;
+    }
+}
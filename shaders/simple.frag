@@ -2,23 +2,353 @@
 
 in vec4 fragColor;
 in vec3 fragNormal;
+in vec3 fragPosition;
+in vec2 fragUv;
+in vec4 fragTangent;
+in vec4 fragClip;
+in vec4 fragPreviousClip;
 
-out vec4 finalColor;
+// Lit fragments, or drawing the G-buffer, the surface in its textures: where it is into this one
+layout(location = 0) out vec4 finalColor;
+layout(location = 1) out vec4 gBufferNormal;
+layout(location = 2) out vec4 gBufferAlbedo;
+layout(location = 3) out vec4 gBufferEmissive;
 
-void main()
+// Which pass this is (see src/deferred.rs): lighting the mesh as it is drawn, drawing it unlit into
+// the G-buffer, lighting what the G-buffer has at every pixel of the screen, or how far the mesh
+// moved on the screen since the last frame
+const int FORWARD = 0;
+const int GEOMETRY = 1;
+const int RESOLVE = 2;
+const int VELOCITY = 3;
+uniform int renderPass = FORWARD;
+// What the G-buffer has at every pixel, as laid out in deferred::GBUFFER_FORMATS
+uniform sampler2D gPosition;
+uniform sampler2D gNormal;
+uniform sampler2D gAlbedo;
+uniform sampler2D gEmissive;
+uniform sampler2D gDepth;
+
+uniform float highlight;
+uniform float opacity = 1.0;
+
+// Bumps too fine for the mesh, as normals in the space of the surface: X along the tangent, Y along
+// the bitangent and Z out of the surface, each mapped from [-1, 1] to [0, 1]
+uniform bool normalMapped = false;
+uniform sampler2D normalMap;
+
+// What the surface is made of, for nodes shaded physically rather than with Phong (see
+// src/material.rs)
+struct Material {
+    vec3 albedo;
+    float metallic;
+    float roughness;
+    vec3 emissive;
+    float reflectivity;
+};
+uniform bool physicallyBased = false;
+uniform Material material;
+
+const float PI = 3.14159265;
+
+// What is seen at the fragment: the mesh being drawn, or what the G-buffer has there
+struct Surface {
+    vec3 position;
+    vec3 normal;     // Bent by the normal map
+    vec3 meshNormal; // For biasing the shadows, as the bumps don't change the depth of the mesh
+    vec3 albedo;
+    bool physicallyBased;
+    float metallic;
+    float roughness;
+    vec3 emissive;
+    float highlight;
+};
+
+// The light, which is set from the light nodes in the scene (see src/lighting.rs): the direction it
+// travels in and its color times its intensity. Then where the camera is, for specular highlights.
+uniform vec3 lightDirection = vec3(0.8, -0.5, 0.6);
+uniform vec3 lightColor = vec3(1.0);
+uniform vec3 cameraPosition;
+
+// Point lights, shining every way from where they are and fading to nothing at their range. Read
+// from a uniform buffer laid out as lighting::PointLightBlock, with room for as many lights as
+// lighting::MAX_POINT_LIGHTS.
+struct PointLight {
+    vec4 positionRange; // The range in w
+    vec4 color;         // Times its intensity, with the cube map of its shadows in w, -1 for none
+};
+layout(std140, binding = 0) uniform PointLights {
+    int pointLightCount;
+    PointLight pointLights[16];
+};
+
+// The sun's shadows (see src/shadow.rs), in a layer for each slice of the camera's view: how far
+// ahead of the camera each slice ends and which way ahead is, where fragments are in the sun's
+// view of each, and the depth of what is nearest the sun there, compared with theirs when sampled.
+// As many slices as shadow::CASCADES.
+uniform bool shadows = false;
+uniform float cascadeSplits[4];
+uniform vec3 cameraForward;
+uniform mat4 lightSpaceMatrices[4];
+uniform sampler2DArrayShadow shadowMap;
+// The shadows of point lights: how far from each light what is nearest it is in every direction, as
+// a fraction of its range
+uniform samplerCubeArrayShadow pointShadowMaps;
+// The sky's light, for physically shaded surfaces (see src/environment.rs): the diffuse light of a
+// surface facing every way, and the sky as reflected by surfaces as rough as their mip levels are,
+// of which there are `specularLevels`
+uniform bool imageBasedLighting = false;
+uniform samplerCube irradianceMap;
+uniform samplerCube specularMap;
+uniform float specularLevels;
+// The scene mirrored in the plane of reflective surfaces, as seen from the camera, see
+// src/reflection.rs
+uniform bool reflections = false;
+uniform sampler2D reflectionMap;
+
+// How the surfaces are lit, tweakable while the demo runs (see src/tweaks.rs)
+uniform vec3 ambientColor = vec3(0.15, 0.15, 0.18); // @tweak color
+uniform float specularStrength = 0.3; // @tweak 0 1
+uniform float shininess = 24.0; // @tweak 1 128
+uniform float shadowBias = 0.001; // @tweak 0 0.01
+uniform float pointShadowBias = 0.15; // @tweak 0 1
+uniform float normalMapStrength = 1.0; // @tweak 0 2
+uniform float environmentStrength = 1.0; // @tweak 0 4
+
+// The normal of the surface at the fragment, bent by the normal map if there is one
+vec3 surfaceNormal(vec3 normal)
+{
+    if (!normalMapped) {
+        return normal;
+    }
+    vec3 tangent = normalize(fragTangent.xyz - normal * dot(normal, fragTangent.xyz));
+    vec3 bitangent = cross(normal, tangent) * fragTangent.w;
+    vec3 bump = texture(normalMap, fragUv).xyz * 2.0 - 1.0;
+    bump.xy *= normalMapStrength;
+    return normalize(mat3(tangent, bitangent, normal) * bump);
+}
+
+// How much of the sun reaches the surface, 0 in full shadow. The comparisons of 3 by 3 texels of
+// the map are averaged, which softens the edges of the shadows.
+float sunlight(Surface surface, vec3 toLight)
 {
-    vec3 normalizedNormal = normalize(fragNormal);
+    if (!shadows) {
+        return 1.0;
+    }
+    float ahead = dot(surface.position - cameraPosition, cameraForward);
+    int cascade = 0;
+    while (cascade < 4 && ahead > cascadeSplits[cascade]) {
+        cascade++;
+    }
+    if (cascade == 4) {
+        return 1.0;
+    }
+    vec4 lightSpace = lightSpaceMatrices[cascade] * vec4(surface.position, 1.0);
+    vec3 coords = lightSpace.xyz / lightSpace.w * 0.5 + 0.5;
+    if (coords.z > 1.0) {
+        return 1.0;
+    }
+    // Surfaces the light grazes are biased the most, as they are the likeliest to shadow themselves
+    float bias = shadowBias * max(1.0 - dot(surface.meshNormal, toLight), 0.25);
+    vec2 texel = 1.0 / vec2(textureSize(shadowMap, 0).xy);
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec2 offset = coords.xy + vec2(x, y) * texel;
+            lit += texture(shadowMap, vec4(offset, cascade, coords.z - bias));
+        }
+    }
+    return lit / 9.0;
+}
 
+// How much of point light `i` reaches the surface, which is `toLight` from it, 0 in full shadow
+float pointLightReach(int i, vec3 toLight, float lightDistance)
+{
+    float cubeMap = pointLights[i].color.w;
+    if (cubeMap < 0.0) {
+        return 1.0;
+    }
+    float range = pointLights[i].positionRange.w;
+    float compared = (lightDistance - pointShadowBias) / range;
+    return texture(pointShadowMaps, vec4(-toLight, cubeMap), compared);
+}
 
-    /* Excercise3 Task1 c)
-    The reason for adding 1.0 is to convert the range from [-1, 1] to [0, 2], 
-    and scaling it by 0.5 brings it to [0, 1], which is suitable for RGB colors.*/
+// Phong: light falls off with the angle it hits the surface at, and is reflected brightest
+// towards the camera in a highlight, the tighter the shinier the surface is
+vec3 phong(Surface surface, vec3 toCamera, vec3 toLight, vec3 light)
+{
+    float diffuse = max(0.0, dot(surface.normal, toLight));
+    float specular = 0.0;
+    if (diffuse > 0.0) {
+        specular = pow(max(0.0, dot(reflect(-toLight, surface.normal), toCamera)), shininess);
+    }
+    return surface.albedo * diffuse * light + specularStrength * specular * light;
+}
 
-    vec3 colorFromNormal = (normalizedNormal + 1.0) * 0.5;
+// Cook-Torrance: the surface is made of tiny mirrors facing every which way, the more so the
+// rougher it is (GGX), some of them hidden from the light or the camera by others (Smith), which
+// reflect more towards grazing angles (Schlick). What they don't reflect scatters back out evenly,
+// unless the surface is metal, which absorbs it. Lights are as bright as a white surface facing
+// them looks, like with Phong, which makes them PI times the radiance of a physical light.
+vec3 cookTorrance(Surface surface, vec3 toCamera, vec3 toLight, vec3 light)
+{
+    vec3 normal = surface.normal;
+    float lightAngle = max(dot(normal, toLight), 0.0);
+    if (lightAngle == 0.0) {
+        return vec3(0.0);
+    }
+    float viewAngle = max(dot(normal, toCamera), 1e-4);
+    vec3 halfway = normalize(toCamera + toLight);
 
-    vec3 lightDirection = normalize(vec3(0.8, -0.5, 0.6));
+    float alpha = surface.roughness * surface.roughness;
+    float facing = max(dot(normal, halfway), 0.0);
+    float d = facing * facing * (alpha * alpha - 1.0) + 1.0;
+    float distribution = alpha * alpha / (PI * d * d);
+
+    float k = (surface.roughness + 1.0) * (surface.roughness + 1.0) / 8.0;
+    float geometry = lightAngle / (lightAngle * (1.0 - k) + k)
+                   * viewAngle / (viewAngle * (1.0 - k) + k);
+
+    vec3 reflectance = mix(vec3(0.04), surface.albedo, surface.metallic);
+    vec3 fresnel = reflectance
+                 + (1.0 - reflectance) * pow(1.0 - max(dot(halfway, toCamera), 0.0), 5.0);
+
+    vec3 specular = distribution * geometry * fresnel / (4.0 * lightAngle * viewAngle);
+    vec3 diffuse = (1.0 - fresnel) * (1.0 - surface.metallic) * surface.albedo / PI;
+    return PI * (diffuse + specular) * light * lightAngle;
+}
+
+vec3 shade(Surface surface, vec3 toCamera, vec3 toLight, vec3 light)
+{
+    if (surface.physicallyBased) {
+        return cookTorrance(surface, toCamera, toLight, light);
+    }
+    return phong(surface, toCamera, toLight, light);
+}
+
+// How much of the light reflected towards the camera from around the mirror direction reaches it,
+// as a scale and a bias of the surface's reflectance: Karis' fit of the integral of Cook-Torrance
+// over the sky, for mobile, rather than the lookup table it is usually read from
+vec2 environmentBrdf(float roughness, float viewAngle)
+{
+    vec4 c0 = vec4(-1.0, -0.0275, -0.572, 0.022);
+    vec4 c1 = vec4(1.0, 0.0425, 1.04, -0.04);
+    vec4 r = roughness * c0 + c1;
+    float a004 = min(r.x * r.x, exp2(-9.28 * viewAngle)) * r.x + r.y;
+    return vec2(-1.04, 1.04) * a004 + r.zw;
+}
+
+// The sky's light diffused and reflected by a physically shaded surface
+vec3 environmentLight(Surface surface, vec3 toCamera)
+{
+    float viewAngle = max(dot(surface.normal, toCamera), 1e-4);
+    vec3 reflectance = mix(vec3(0.04), surface.albedo, surface.metallic);
+    vec2 brdf = environmentBrdf(surface.roughness, viewAngle);
+    vec3 specularPart = reflectance * brdf.x + brdf.y;
+
+    vec3 diffuse = texture(irradianceMap, surface.normal).rgb * surface.albedo;
+    vec3 mirrored = reflect(-toCamera, surface.normal);
+    float level = surface.roughness * (specularLevels - 1.0);
+    vec3 specular = textureLod(specularMap, mirrored, level).rgb * specularPart;
+    return environmentStrength * ((1.0 - specularPart) * (1.0 - surface.metallic) * diffuse
+                                  + specular);
+}
+
+// The surface lit by the ambient light, the sky, the sun and the point lights
+vec3 lit(Surface surface)
+{
+    vec3 toCamera = normalize(cameraPosition - surface.position);
+
+    // The ambient light stands in for what is bounced off the ground, which the sky doesn't have
+    vec3 color = surface.albedo * ambientColor;
+    if (imageBasedLighting && surface.physicallyBased) {
+        color += environmentLight(surface, toCamera);
+    }
+    vec3 toSun = -normalize(lightDirection);
+    color += sunlight(surface, toSun) * shade(surface, toCamera, toSun, lightColor);
+    for (int i = 0; i < min(pointLightCount, 16); i++) {
+        vec3 toLight = pointLights[i].positionRange.xyz - surface.position;
+        float lightDistance = length(toLight);
+        // Quadratically down to nothing at the range, so lights end without a visible edge
+        float fade = clamp(1.0 - lightDistance / pointLights[i].positionRange.w, 0.0, 1.0);
+        if (fade > 0.0) {
+            vec3 light = pointLights[i].color.rgb * fade * fade
+                * pointLightReach(i, toLight, lightDistance);
+            color += shade(surface, toCamera, toLight / lightDistance, light);
+        }
+    }
+    color += surface.emissive;
+
+    // Tint the node that has been picked with the mouse
+    return mix(color, vec3(1.0, 0.8, 0.2), 0.5 * surface.highlight);
+}
+
+// The surface the G-buffer has at the fragment
+Surface storedSurface()
+{
+    ivec2 texel = ivec2(gl_FragCoord.xy);
+    vec4 position = texelFetch(gPosition, texel, 0);
+    vec4 normal = texelFetch(gNormal, texel, 0);
+    vec4 albedo = texelFetch(gAlbedo, texel, 0);
+    vec3 emissive = texelFetch(gEmissive, texel, 0).rgb;
+    bool physical = normal.w >= 0.0;
+    return Surface(position.xyz, normal.xyz, normal.xyz, albedo.rgb, physical,
+                   max(normal.w, 0.0), position.w, emissive, albedo.a);
+}
+
+// The surface of the mesh being drawn at the fragment
+Surface drawnSurface()
+{
+    vec3 meshNormal = normalize(fragNormal);
+    vec3 albedo = fragColor.rgb;
+    if (physicallyBased) {
+        albedo *= material.albedo;
+    }
+    vec3 emissive = physicallyBased ? material.emissive : vec3(0.0);
+    return Surface(fragPosition, surfaceNormal(meshNormal), meshNormal, albedo, physicallyBased,
+                   material.metallic, material.roughness, emissive, highlight);
+}
+
+// `color` with the reflection mixed in over it, as much as the material reflects, and more towards
+// grazing angles
+vec3 reflected(Surface surface, vec3 color)
+{
+    if (!reflections || !physicallyBased || material.reflectivity <= 0.0) {
+        return color;
+    }
+    vec2 screen = gl_FragCoord.xy / vec2(textureSize(reflectionMap, 0));
+    vec3 reflection = texture(reflectionMap, screen).rgb;
+    float viewAngle = max(dot(surface.normal, normalize(cameraPosition - surface.position)), 0.0);
+    float amount = mix(material.reflectivity, 1.0, pow(1.0 - viewAngle, 5.0));
+    return mix(color, reflection, amount);
+}
+
+void main()
+{
+    if (renderPass == RESOLVE) {
+        // Where nothing was drawn, what is behind shows through
+        if (texelFetch(gDepth, ivec2(gl_FragCoord.xy), 0).r == 1.0) {
+            discard;
+        }
+        finalColor = vec4(lit(storedSurface()), 1.0);
+        return;
+    }
 
-    float lightIntensity = max(0.0, dot(normalizedNormal, -lightDirection));
+    if (renderPass == VELOCITY) {
+        // Halved, from across the screen in clip space to across it in texture coordinates. Alpha
+        // tells the mesh from the sky, which moves with the camera alone.
+        vec2 moved = fragClip.xy / fragClip.w - fragPreviousClip.xy / fragPreviousClip.w;
+        finalColor = vec4(0.5 * moved, 0.0, 1.0);
+        return;
+    }
 
-    finalColor = vec4(lightIntensity * colorFromNormal, 1.0);
+    Surface surface = drawnSurface();
+    if (renderPass == GEOMETRY) {
+        finalColor = vec4(surface.position, surface.roughness);
+        gBufferNormal = vec4(surface.normal, surface.physicallyBased ? surface.metallic : -1.0);
+        gBufferAlbedo = vec4(surface.albedo, surface.highlight);
+        gBufferEmissive = vec4(surface.emissive, 1.0);
+        return;
+    }
+    finalColor = vec4(reflected(surface, lit(surface)), fragColor.a * opacity);
 }
@@ -3,19 +3,71 @@
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec4 vertexColor;
 layout(location = 2) in vec3 normal;
+layout(location = 3) in vec2 uv;
+layout(location = 4) in vec4 tangent; // With which way the bitangent goes in w, see mesh::compute_tangents
+layout(location = 5) in vec4 joints;
+layout(location = 6) in vec4 weights;
 
 out vec4 fragColor;
 out vec3 fragNormal;
+out vec3 fragPosition; // In the world, for lighting
+out vec2 fragUv;
+out vec4 fragTangent;
+// Where the vertex is on the screen, and was last frame, for the velocity pass, see src/post.rs
+out vec4 fragClip;
+out vec4 fragPreviousClip;
 
 uniform mat4 transformMatrix;
 
 uniform mat4 modelMatrix;
 
+// Where the mesh was in the world last frame, and how the camera saw it, for motion blur. Skinned
+// meshes are taken to have been posed as they are now.
+uniform mat4 previousModelMatrix;
+uniform mat4 previousViewProjection;
+
+// The plane what is below is cut off at while drawing a planar reflection, see src/reflection.rs.
+// Only used while GL_CLIP_DISTANCE0 is enabled.
+uniform vec4 clipPlane = vec4(0.0);
+
+// Skinned meshes are posed by blending the matrices of the four joints each vertex follows, see
+// src/skeleton.rs. As many joints as skeleton::MAX_JOINTS.
+uniform bool skinned = false;
+uniform mat4 jointMatrices[64];
+
+// Which pass this is, see simple.frag. Lighting the G-buffer draws a triangle covering the screen,
+// made up from the vertex's index, as nothing is drawn from a mesh.
+const int RESOLVE = 2;
+uniform int renderPass = 0;
+
 void main()
 {
-    gl_Position = transformMatrix * vec4(position, 1.0);
+    if (renderPass == RESOLVE) {
+        vec2 corner = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+        gl_Position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+        return;
+    }
+
+    mat4 skin = mat4(1.0);
+    if (skinned) {
+        skin = weights.x * jointMatrices[int(joints.x)]
+             + weights.y * jointMatrices[int(joints.y)]
+             + weights.z * jointMatrices[int(joints.z)]
+             + weights.w * jointMatrices[int(joints.w)];
+    }
+
+    gl_Position = transformMatrix * skin * vec4(position, 1.0);
+    fragClip = gl_Position;
+    fragPreviousClip = previousViewProjection * previousModelMatrix * skin * vec4(position, 1.0);
+
+    fragPosition = (modelMatrix * skin * vec4(position, 1.0)).xyz;
+    gl_ClipDistance[0] = dot(clipPlane, vec4(fragPosition, 1.0));
     
     fragColor = vertexColor;
     
-    fragNormal = normalize(mat3(modelMatrix) * normal);
+    fragNormal = normalize(mat3(modelMatrix) * mat3(skin) * normal);
+
+    // Only meaningful for meshes with UVs and tangents, which are zero for the rest
+    fragUv = uv;
+    fragTangent = vec4(mat3(modelMatrix) * mat3(skin) * tangent.xyz, tangent.w);
 }
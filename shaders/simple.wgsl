@@ -0,0 +1,70 @@
+// The wgpu version of simple.vert and simple.frag
+
+struct Uniforms {
+    transform: mat4x4<f32>,
+    model: mat4x4<f32>,
+    highlight: f32,
+    opacity: f32,
+    skinned: f32, // 1 for skinned meshes, 0 for the rest
+}
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+// Skinned meshes are posed by blending the matrices of the four joints each vertex follows, see
+// src/skeleton.rs. As many joints as skeleton::MAX_JOINTS.
+@group(0) @binding(1)
+var<uniform> joint_matrices: array<mat4x4<f32>, 64>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) normal: vec3<f32>,
+}
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) joints: vec4<f32>,
+    @location(4) weights: vec4<f32>,
+) -> VertexOutput {
+    var skin = mat4x4<f32>(
+        vec4<f32>(1.0, 0.0, 0.0, 0.0),
+        vec4<f32>(0.0, 1.0, 0.0, 0.0),
+        vec4<f32>(0.0, 0.0, 1.0, 0.0),
+        vec4<f32>(0.0, 0.0, 0.0, 1.0),
+    );
+    if (uniforms.skinned > 0.5) {
+        skin = weights.x * joint_matrices[u32(joints.x)]
+             + weights.y * joint_matrices[u32(joints.y)]
+             + weights.z * joint_matrices[u32(joints.z)]
+             + weights.w * joint_matrices[u32(joints.w)];
+    }
+
+    var out: VertexOutput;
+    out.position = uniforms.transform * skin * vec4<f32>(position, 1.0);
+    out.color = color;
+    let model = uniforms.model * skin;
+    out.normal = normalize(mat3x3<f32>(model[0].xyz, model[1].xyz, model[2].xyz) * normal);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = normalize(in.normal);
+
+    // Lit like simple.frag by the sun as the demo starts out, without the specular highlights,
+    // which would need the camera position, or the point lights
+    let light_direction = normalize(vec3<f32>(0.8, -0.5, 0.6));
+    let diffuse = max(0.0, dot(normal, -light_direction));
+    let ambient = vec3<f32>(0.15, 0.15, 0.18);
+
+    var color = in.color.rgb * (ambient + diffuse * vec3<f32>(1.0, 0.97, 0.92));
+
+    // Tint the node that has been picked with the mouse
+    color = mix(color, vec3<f32>(1.0, 0.8, 0.2), 0.5 * uniforms.highlight);
+
+    return vec4<f32>(color, in.color.a * uniforms.opacity);
+}
@@ -0,0 +1,15 @@
+#version 430 core
+
+in vec2 screenPosition;
+
+out vec4 finalColor;
+
+// From the screen back to the world, as seen by the camera turned but not moved, see src/skybox.rs
+uniform mat4 inverseViewProjection;
+uniform samplerCube sky;
+
+void main()
+{
+    vec4 far = inverseViewProjection * vec4(screenPosition, 1.0, 1.0);
+    finalColor = vec4(texture(sky, far.xyz / far.w).rgb, 1.0);
+}
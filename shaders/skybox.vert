@@ -0,0 +1,13 @@
+#version 430 core
+
+// Where on the screen the fragment is, from -1 to 1 across and up
+out vec2 screenPosition;
+
+// A triangle covering the screen at the far plane, made up from the vertex's index, as nothing is
+// drawn from a mesh
+void main()
+{
+    vec2 corner = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    screenPosition = corner * 2.0 - 1.0;
+    gl_Position = vec4(screenPosition, 1.0, 1.0);
+}
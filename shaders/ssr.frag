@@ -0,0 +1,112 @@
+#version 430 core
+
+in vec2 uv;
+
+out vec4 finalColor;
+
+// Which step of the screen-space reflections this is, see src/ssr.rs
+const int TRACE = 0;
+const int COMPOSITE = 1;
+uniform int ssrStep;
+
+// The lit scene for tracing, the traced reflections for the composite step
+uniform sampler2D source;
+
+// The surfaces drawn deferred, see src/deferred.rs
+uniform sampler2D gPosition;
+uniform sampler2D gNormal;
+uniform sampler2D gAlbedo;
+uniform sampler2D gDepth;
+
+uniform mat4 viewProjection;
+uniform vec3 cameraPosition;
+uniform float maxDistance = 60.0;
+uniform float thickness = 1.5;
+uniform int steps = 48;
+uniform float intensity = 1.0;
+
+// Where `position` is on the screen, from 0 to 1, or outside that if it isn't on it
+vec2 onScreen(vec3 position)
+{
+    vec4 clip = viewProjection * vec4(position, 1.0);
+    if (clip.w <= 0.0) {
+        return vec2(-1.0);
+    }
+    return clip.xy / clip.w * 0.5 + 0.5;
+}
+
+// How far behind the surface on the screen at `screen` `position` is, negative in front of it,
+// or None, as a huge negative number, where there is nothing
+float behind(vec3 position, vec2 screen)
+{
+    ivec2 texel = ivec2(screen * vec2(textureSize(gPosition, 0)));
+    if (texelFetch(gDepth, texel, 0).r == 1.0) {
+        return -1e9;
+    }
+    vec3 surface = texelFetch(gPosition, texel, 0).xyz;
+    return distance(cameraPosition, position) - distance(cameraPosition, surface);
+}
+
+// The reflection at the fragment, already scaled by how much of it shows, which is in alpha
+vec4 traced()
+{
+    ivec2 texel = ivec2(gl_FragCoord.xy);
+    vec4 normal = texelFetch(gNormal, texel, 0);
+    // Nothing drawn there, or shaded with Phong, whose metallic is -1
+    if (texelFetch(gDepth, texel, 0).r == 1.0 || normal.w < 0.0) {
+        return vec4(0.0);
+    }
+    vec4 position = texelFetch(gPosition, texel, 0);
+    float gloss = (1.0 - position.w) * (1.0 - position.w);
+    vec3 toCamera = normalize(cameraPosition - position.xyz);
+    vec3 direction = reflect(-toCamera, normal.xyz);
+    // Rays coming back towards the camera hit the back of things, which the G-buffer doesn't have
+    float away = 1.0 - max(dot(direction, toCamera), 0.0);
+    if (gloss * away < 0.1) {
+        return vec4(0.0);
+    }
+
+    float stepLength = maxDistance / float(steps);
+    vec3 previous = position.xyz;
+    for (int i = 1; i <= steps; i++) {
+        vec3 point = position.xyz + direction * stepLength * float(i);
+        vec2 screen = onScreen(point);
+        if (any(lessThan(screen, vec2(0.0))) || any(greaterThan(screen, vec2(1.0)))) {
+            break;
+        }
+        float depth = behind(point, screen);
+        if (depth > 0.0 && depth < thickness) {
+            // Halve the way back to where the ray was in front, to hit nearer where it crossed
+            vec3 front = previous;
+            for (int j = 0; j < 5; j++) {
+                vec3 middle = 0.5 * (front + point);
+                if (behind(middle, onScreen(middle)) > 0.0) {
+                    point = middle;
+                } else {
+                    front = middle;
+                }
+            }
+            screen = onScreen(point);
+            vec2 edges = smoothstep(0.0, 0.1, screen) * smoothstep(0.0, 0.1, 1.0 - screen);
+            float fade = edges.x * edges.y * (1.0 - float(i) / float(steps)) * away;
+
+            vec3 albedo = texelFetch(gAlbedo, texel, 0).rgb;
+            vec3 reflectance = mix(vec3(0.04), albedo, normal.w);
+            float viewAngle = max(dot(normal.xyz, toCamera), 0.0);
+            vec3 fresnel = reflectance + (1.0 - reflectance) * pow(1.0 - viewAngle, 5.0);
+            vec3 amount = clamp(intensity * gloss * fade * fresnel, 0.0, 1.0);
+            return vec4(texture(source, screen).rgb * amount, max(amount.r, max(amount.g, amount.b)));
+        }
+        previous = point;
+    }
+    return vec4(0.0);
+}
+
+void main()
+{
+    if (ssrStep == TRACE) {
+        finalColor = traced();
+    } else {
+        finalColor = texture(source, uv);
+    }
+}
@@ -0,0 +1,215 @@
+use gloom_rs::toolbox::Transform;
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+use std::sync::Arc;
+
+// Helicopters flying between waypoints on their own, without flying through each other.
+// Each heads for its next waypoint at cruising speed, and on for the one after once it is close,
+// round and round. Helicopters closer to each other than `SEPARATION_RADIUS` steer apart, the more
+// the closer they are, which comes before getting anywhere, and they all keep clear of the ground.
+// Steering only ever accelerates them so much, so they turn smoothly, and their bodies pitch and
+// bank with how they fly like those following a `FlightPattern`.
+
+const CRUISE_SPEED: f32 = 25.0; // Units per second
+const MAX_ACCELERATION: f32 = 12.0;
+// How quickly the velocity is steered towards the one wanted, per second
+const STEERING: f32 = 1.5;
+// Close enough to a waypoint to head for the next, measured across the ground
+const ARRIVAL_RADIUS: f32 = 15.0;
+const SEPARATION_RADIUS: f32 = 25.0;
+// Acceleration away from a helicopter right on top of another, falling off to nothing at the
+// separation radius
+const SEPARATION: f32 = 40.0;
+// Height above the ground to keep at the least, and how quickly to climb back up to it per unit
+// below
+const CLEARANCE: f32 = 8.0;
+const CLIMB: f32 = 1.0;
+// Nose down per unit per second of speed, and roll per radian per second of turning, up to limits
+const PITCH: f32 = 0.25 / 30.0;
+const MAX_PITCH: f32 = 0.35;
+const BANK: f32 = 0.35;
+const MAX_BANK: f32 = 0.5;
+// How quickly the body follows the pitch and bank of how it flies, per second
+const ATTITUDE_RESPONSE: f32 = 4.0;
+
+// A helicopter flying round a loop of waypoints
+#[derive(Clone, Debug)]
+pub struct WaypointPilot {
+    pub waypoints: Arc<Vec<glm::Vec3>>,
+    pub next: usize, // The waypoint being headed for
+    pub velocity: glm::Vec3,
+}
+
+impl WaypointPilot {
+    // Heading for waypoint `first` of `waypoints`, which must not be empty, moving at `velocity`
+    pub fn new(waypoints: Arc<Vec<glm::Vec3>>, first: usize, velocity: glm::Vec3) -> WaypointPilot {
+        let next = first % waypoints.len();
+        WaypointPilot {
+            waypoints,
+            next,
+            velocity,
+        }
+    }
+
+    // Advance `body` by a step of `delta_time` seconds, steering clear of the helicopters at
+    // `neighbours` and of `ground`, the height of the terrain below it if known
+    pub fn step(
+        &mut self,
+        body: &mut Transform,
+        neighbours: impl Iterator<Item = glm::Vec3>,
+        ground: Option<f32>,
+        delta_time: f32,
+    ) {
+        let position = body.position;
+        let mut waypoint = self.waypoints[self.next];
+        if glm::distance(&waypoint.xz(), &position.xz()) < ARRIVAL_RADIUS {
+            self.next = (self.next + 1) % self.waypoints.len();
+            waypoint = self.waypoints[self.next];
+        }
+
+        let mut separation = glm::Vec3::zeros();
+        for neighbour in neighbours {
+            let away = position - neighbour;
+            let distance = glm::length(&away);
+            if distance > 1e-3 && distance < SEPARATION_RADIUS {
+                separation += away / distance * SEPARATION * (1.0 - distance / SEPARATION_RADIUS);
+            }
+        }
+        let separation = clamp_length(separation, MAX_ACCELERATION);
+
+        let to_waypoint = waypoint - position;
+        let mut wanted = if glm::length(&to_waypoint) > 1e-3 {
+            glm::normalize(&to_waypoint) * CRUISE_SPEED
+        } else {
+            glm::Vec3::zeros()
+        };
+        if let Some(ground) = ground {
+            wanted.y = wanted.y.max((ground + CLEARANCE - position.y) * CLIMB);
+        }
+        let seeking = clamp_length(
+            (wanted - self.velocity) * STEERING,
+            MAX_ACCELERATION - glm::length(&separation),
+        );
+        self.velocity += (separation + seeking) * delta_time;
+        body.position += self.velocity * delta_time;
+
+        // Models face -Z. Hovering keeps the heading it had.
+        let speed = glm::length(&self.velocity.xz());
+        let (yaw, turn_rate) = if speed > 0.1 {
+            let heading = (-self.velocity.x).atan2(-self.velocity.z);
+            let yaw = body.rotation.y + (heading - body.rotation.y + PI).rem_euclid(TAU) - PI;
+            (yaw, (yaw - body.rotation.y) / delta_time.max(1e-6))
+        } else {
+            (body.rotation.y, 0.0)
+        };
+        let attitude = glm::vec2(
+            -(speed * PITCH).min(MAX_PITCH),
+            (turn_rate * BANK).clamp(-MAX_BANK, MAX_BANK),
+        );
+        let response = 1.0 - (-ATTITUDE_RESPONSE * delta_time).exp();
+        body.rotation.x += (attitude.x - body.rotation.x) * response;
+        body.rotation.y = yaw;
+        body.rotation.z += (attitude.y - body.rotation.z) * response;
+    }
+}
+
+fn clamp_length(vector: glm::Vec3, max: f32) -> glm::Vec3 {
+    let length = glm::length(&vector);
+    if length > max {
+        vector * (max.max(0.0) / length)
+    } else {
+        vector
+    }
+}
+
+// Where the helicopters are, in cubes as wide as the separation radius, so only the helicopters in
+// the cubes around one need to be looked at to find those close to it
+pub struct Neighbourhood {
+    positions: Vec<glm::Vec3>,
+    cells: HashMap<[i32; 3], Vec<usize>>,
+}
+
+impl Neighbourhood {
+    pub fn new(positions: Vec<glm::Vec3>) -> Neighbourhood {
+        let mut cells: HashMap<[i32; 3], Vec<usize>> = HashMap::new();
+        for (i, position) in positions.iter().enumerate() {
+            cells.entry(cell(position)).or_default().push(i);
+        }
+        Neighbourhood { positions, cells }
+    }
+
+    // The positions of the helicopters within the separation radius of helicopter `index`
+    pub fn near(&self, index: usize) -> impl Iterator<Item = glm::Vec3> + '_ {
+        let position = self.positions[index];
+        let [x, y, z] = cell(&position);
+        (-1..=1)
+            .flat_map(move |dx| {
+                (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| [x + dx, y + dy, z + dz]))
+            })
+            .filter_map(move |cell| self.cells.get(&cell))
+            .flatten()
+            .filter(move |&&other| other != index)
+            .map(move |&other| self.positions[other])
+            .filter(move |other| glm::distance(other, &position) < SEPARATION_RADIUS)
+    }
+}
+
+fn cell(position: &glm::Vec3) -> [i32; 3] {
+    let cell = position / SEPARATION_RADIUS;
+    [
+        cell.x.floor() as i32,
+        cell.y.floor() as i32,
+        cell.z.floor() as i32,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(position: glm::Vec3) -> Transform {
+        Transform {
+            position,
+            ..Transform::default()
+        }
+    }
+
+    #[test]
+    fn helicopters_close_together_steer_apart() {
+        let ahead = |x: f32| Arc::new(vec![glm::vec3(x, 50.0, -1000.0)]);
+        let mut pilots = [
+            WaypointPilot::new(ahead(0.0), 0, glm::Vec3::zeros()),
+            WaypointPilot::new(ahead(5.0), 0, glm::Vec3::zeros()),
+        ];
+        let mut bodies = [at(glm::vec3(0.0, 50.0, 0.0)), at(glm::vec3(5.0, 50.0, 0.0))];
+        assert_eq!(cell(&bodies[0].position), cell(&bodies[1].position));
+        for _ in 0..20 {
+            let neighbourhood =
+                Neighbourhood::new(bodies.iter().map(|body| body.position).collect());
+            for (i, (pilot, body)) in pilots.iter_mut().zip(&mut bodies).enumerate() {
+                pilot.step(body, neighbourhood.near(i), None, 0.1);
+            }
+        }
+        // Alone, each would have flown straight on
+        assert!(pilots[0].velocity.x < 0.0 && pilots[1].velocity.x > 0.0);
+        assert!(bodies[1].position.x - bodies[0].position.x > 6.0);
+    }
+
+    #[test]
+    fn neighbours_are_the_others_within_the_separation_radius() {
+        let neighbourhood = Neighbourhood::new(vec![
+            glm::vec3(1.0, 1.0, 1.0),
+            glm::vec3(6.0, 1.0, 1.0),  // In the same cell
+            glm::vec3(25.0, 1.0, 1.0), // In the next cell, but within the radius
+            glm::vec3(1.0, 1.0, 30.0), // In the next cell, beyond the radius
+            glm::vec3(1.0, 40.0, 1.0), // Two cells away
+        ]);
+        let mut near: Vec<glm::Vec3> = neighbourhood.near(0).collect();
+        near.sort_by(|a, b| a.x.total_cmp(&b.x));
+        assert_eq!(
+            near,
+            vec![glm::vec3(6.0, 1.0, 1.0), glm::vec3(25.0, 1.0, 1.0)]
+        );
+        assert_eq!(neighbourhood.near(3).count(), 0);
+    }
+}
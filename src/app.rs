@@ -0,0 +1,1134 @@
+// Running an application built on gloom-rs.
+//
+// An application implements `GloomApp` and is started with `run`. The event loop runs on the main
+// thread and creates the window, then forwards input to a render thread, which owns the OpenGL
+// context and calls the application's `update` and `render` once per frame. Everything around
+// that is handled here: vsync and frame pacing, pausing in the background, recording and replaying
+// input, capturing frames, F12 screenshots, restarting the renderer with F5, headless rendering
+// and the debug view window.
+use crate::arena::FrameArena;
+use crate::assets::Assets;
+use crate::backend::{gl::GlBackend, Backend};
+use crate::capture;
+use crate::crash;
+use crate::debug_view;
+use crate::error::RenderError;
+use crate::input::{FrameInput, InputCollector, InputEvent, WindowSnapshot};
+use crate::logging;
+use crate::offscreen;
+use crate::quality::{Quality, QualityGovernor};
+use crate::renderer;
+use crate::replay;
+use crate::scene_graph::SceneNode;
+use crate::screenshot;
+use crate::timing;
+use crate::util::GlCapabilities;
+use crate::window::{self, GlContext};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use winit::application::ApplicationHandler;
+use winit::dpi::{LogicalSize, PhysicalPosition, PhysicalSize, Size};
+use winit::event::{
+    DeviceEvent, DeviceId, ElementState::Pressed, KeyEvent, MouseScrollDelta, WindowEvent,
+};
+use winit::event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
+use winit::window::{Fullscreen, Window, WindowAttributes, WindowId};
+
+// How the application is run, usually filled in from the command line
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub title: String,
+    pub width: u32, // Initial window width in logical pixels, or in pixels when headless
+    pub height: u32, // Initial window height in logical pixels, or in pixels when headless
+    pub fullscreen: bool,
+    pub monitor: Option<usize>, // Monitor to open the window on, the one the OS picks if None
+    pub msaa: u16,              // Samples per pixel for multisampling, 0 to disable it
+    pub vsync: bool,
+    pub adaptive_vsync: bool,
+    pub fps_cap: Option<u32>,
+    pub target_fps: Option<u32>, // Lower the quality while frames can't keep up with this rate
+    pub background_fps: u32, // Frame rate while the window doesn't have focus, 0 pauses rendering
+    pub screenshot_scale: u32, // Render F12 screenshots at this multiple of the window size
+    pub debug_view: bool,    // Open a second window showing the scene from above
+    pub headless: bool,      // Render without a window, capturing the frames to `output`
+    pub print_caps: bool,    // Print what the OpenGL context can do and exit, without rendering
+    pub frames: u32,         // Number of frames to render when headless
+    pub output: PathBuf,
+    pub capture: Option<PathBuf>, // Capture every frame to a directory of PNG files or a video
+    pub capture_fps: u32,
+    pub record: Option<String>, // Record input to this file
+    pub replay: Option<String>, // Replay input from this file instead of reading it live
+    // Render the same frames on every run, for graphics debugger captures and golden images: the
+    // clock advances by a fixed step per frame and nothing adapts to how fast frames are rendered
+    pub deterministic: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            title: "Gloom-rs".to_string(),
+            width: 800,
+            height: 600,
+            fullscreen: false,
+            monitor: None,
+            msaa: 0,
+            vsync: true,
+            adaptive_vsync: false,
+            fps_cap: None,
+            target_fps: None,
+            background_fps: 15,
+            screenshot_scale: 1,
+            debug_view: false,
+            headless: false,
+            print_caps: false,
+            frames: 1,
+            output: PathBuf::from("frames"),
+            capture: None,
+            capture_fps: 60,
+            record: None,
+            replay: None,
+            deterministic: false,
+        }
+    }
+}
+
+// An application drawing with OpenGL. All methods are called on the render thread, with the
+// context current.
+pub trait GloomApp: Sized {
+    // Whatever the application needs to set itself up, handed over from the main thread
+    type Options: Send + 'static;
+
+    // Load the scene and upload it to the GPU. Errors end the program with their message.
+    fn setup(ctx: &mut Context, options: Self::Options) -> Result<Self, RenderError>;
+
+    // React to this frame's input and advance the scene by `delta_time` seconds
+    fn update(&mut self, ctx: &mut Context, input: &FrameInput, delta_time: f32);
+
+    // Draw the scene. Usually called once per update, but screenshots larger than the window draw
+    // the same frame again at a different viewport size.
+    fn render(&mut self, ctx: &mut Context);
+
+    // The context was recreated, so every OpenGL object is gone. Everything in `ctx.assets` has
+    // been uploaded again, and `vao_ids` says which new VAO replaces which old one.
+    fn context_recreated(
+        &mut self,
+        _ctx: &mut Context,
+        _vao_ids: &HashMap<u32, u32>,
+    ) -> Result<(), RenderError> {
+        Ok(())
+    }
+
+    // Meshes were reloaded after their files changed on disk, see `Assets::watch`. Their VAOs are
+    // the same, but `index_counts` has the new index count of each.
+    fn meshes_reloaded(&mut self, _ctx: &mut Context, _index_counts: &HashMap<u32, i32>) {}
+
+    // The scene to show in the debug view window, and the point it looks down at
+    fn debug_view_target(&self) -> Option<(&SceneNode, glm::Vec3)> {
+        None
+    }
+}
+
+// What an application works with on the render thread
+pub struct Context {
+    pub gl: GlContext,
+    pub backend: GlBackend,
+    // What the context can do, for turning features that need more than OpenGL 4.1 on or off
+    pub capabilities: GlCapabilities,
+    // Meshes, textures and pipelines, uploaded again when the context is recreated
+    pub assets: Assets,
+    // For data that is only needed until the frame has been drawn, emptied before every update
+    pub arena: FrameArena,
+    pub viewport_size: (u32, u32), // Size of the framebuffer being drawn to, in physical pixels
+    pub elapsed: f32,              // Seconds since the first frame, on the same clock as updates
+    // Whether the clock follows real time. While replaying input, capturing frames or running
+    // deterministically it advances by a fixed step per frame instead, so work done on other
+    // threads in real time, like a `simulation::Simulator` spawned on its own thread, would make
+    // the frames differ between runs.
+    pub real_time: bool,
+    // Samples per pixel for the application's own framebuffers, 0 without multisampling. What
+    // `Settings::msaa` asked for, as far as the context supports it.
+    pub samples: u32,
+    quality: Quality,
+    vsync: bool,
+    adaptive_vsync: bool,
+    text_input_active: Arc<AtomicBool>,
+}
+
+impl Context {
+    // The window, unless rendering headless
+    pub fn window(&self) -> Option<&Window> {
+        self.gl.window()
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.viewport_size.0 as f32 / self.viewport_size.1.max(1) as f32
+    }
+
+    // How much to render, see `quality::QualityGovernor`. Full quality unless `target_fps` is set.
+    pub fn quality(&self) -> Quality {
+        self.quality
+    }
+
+    pub fn vsync(&self) -> bool {
+        self.vsync
+    }
+
+    // Turn vsync on or off, preferring adaptive vsync if it was asked for and the driver supports it
+    pub fn set_vsync(&mut self, vsync: bool) -> Result<(), glutin::error::Error> {
+        if vsync && self.adaptive_vsync {
+            match self.gl.set_adaptive_vsync() {
+                Ok(()) => {
+                    self.vsync = true;
+                    return Ok(());
+                }
+                Err(e) => warn!("{}, using regular vsync", e),
+            }
+        }
+        self.gl.set_vsync(vsync)?;
+        self.vsync = vsync;
+        Ok(())
+    }
+
+    // Tell the event loop that keys are being used for typing text, so Escape and Q don't exit
+    pub fn set_text_input_active(&self, active: bool) {
+        self.text_input_active.store(active, Ordering::Relaxed);
+    }
+}
+
+// Open the window and run the application until it is closed, or render `settings.frames` frames
+// without a window if `settings.headless` is set. Fails if the window or context can't be created,
+// or the application or render loop fails, after the render thread has cleaned up.
+pub fn run<A: GloomApp + 'static>(
+    settings: Settings,
+    options: A::Options,
+) -> Result<(), RenderError> {
+    // Input events are sent from the event loop to the render thread as they happen, and collected
+    // into a snapshot once per frame, so neither thread ever waits for the other
+    let (input_sender, input_receiver) = mpsc::channel::<InputEvent>();
+    // Except for the window size and mouse movement, which arrive in bursts and are shared as the
+    // latest state instead
+    let arc_window_snapshot = Arc::new(WindowSnapshot::new((settings.width, settings.height)));
+    // Make a reference of it to send to the render thread
+    let window_snapshot = Arc::clone(&arc_window_snapshot);
+
+    // Set up shared flag telling the event loop that keys are being used for typing text, so
+    // it shouldn't treat them as shortcuts
+    let arc_text_input_active = Arc::new(AtomicBool::new(false));
+    // Make a reference of this flag to send to the render thread
+    let text_input_active = Arc::clone(&arc_text_input_active);
+
+    // Set up shared flags for whether the window has focus, and whether it is minimized or
+    // otherwise hidden, so the render thread can slow down or pause in the background
+    let arc_window_focused = Arc::new(AtomicBool::new(true));
+    let arc_window_occluded = Arc::new(AtomicBool::new(false));
+    // Make references of these flags to send to the render thread
+    let window_focused = Arc::clone(&arc_window_focused);
+    let window_occluded = Arc::clone(&arc_window_occluded);
+
+    // Set up shared flag telling the render thread to stop, so it can finish writing files and
+    // release its resources before the program exits
+    let arc_shutdown = Arc::new(AtomicBool::new(false));
+    // Make a reference of this flag to send to the render thread
+    let shutdown = Arc::clone(&arc_shutdown);
+
+    // Set up shared flag for when the debug view window is closed
+    let arc_debug_view_closed = Arc::new(AtomicBool::new(false));
+    // Make a reference of this flag to send to the render thread
+    let debug_view_closed = Arc::clone(&arc_debug_view_closed);
+
+    // Recording writes this session's input to a file, replaying feeds a recording back in place
+    // of live input, advancing the clock with a fixed timestep
+    let mut input_recorder = settings
+        .record
+        .as_ref()
+        .map(|path| {
+            replay::InputRecorder::create(path).map_err(|source| RenderError::Record {
+                path: path.clone(),
+                source,
+            })
+        })
+        .transpose()?;
+
+    // The render loop runs on its own thread once the window has been created, so event handling
+    // doesn't block rendering
+    let render_settings = settings.clone();
+    let render_loop = move |gl_window: window::GlWindow,
+                            debug_gl_window: Option<window::GlWindow>,
+                            proxy: Option<Proxy>| {
+        let settings = render_settings;
+
+        // Acquire the OpenGL Context and load the function pointers.
+        // This has to be done inside of the rendering thread, because
+        // an active OpenGL context cannot safely traverse a thread boundary
+        let gl_context = gl_window.make_current()?;
+        crash::install();
+        let initial_window_size = gl_context.size();
+        let initial_window_size = (initial_window_size.width, initial_window_size.height);
+
+        let mut input_replay = settings
+            .replay
+            .as_ref()
+            .map(|path| replay::InputReplay::load(path, initial_window_size))
+            .transpose()
+            .map_err(RenderError::Replay)?;
+
+        let mut input_collector = InputCollector::new(initial_window_size);
+
+        let mut viewport_size = (0, 0); // Set up on the first frame
+
+        // Set up openGL
+        let capabilities = unsafe { renderer::init_gl(settings.msaa > 0) };
+        if settings.print_caps {
+            print!("{}", capabilities);
+            return Ok(());
+        }
+        if settings.msaa as u32 > capabilities.max_samples {
+            warn!(
+                "{}x MSAA was asked for, but at most {}x is supported",
+                settings.msaa, capabilities.max_samples
+            );
+        }
+        let samples = (settings.msaa as u32).min(capabilities.max_samples);
+
+        let mut ctx = Context {
+            gl: gl_context,
+            backend: GlBackend::new(&capabilities.features),
+            capabilities,
+            assets: Assets::new(),
+            arena: FrameArena::new(),
+            viewport_size: initial_window_size,
+            elapsed: 0.0,
+            real_time: settings.replay.is_none()
+                && settings.capture.is_none()
+                && !settings.headless
+                && !settings.deterministic,
+            samples,
+            quality: Quality::FULL,
+            vsync: settings.vsync,
+            // Frames missing a refresh would tear in a different place every run
+            adaptive_vsync: settings.adaptive_vsync && !settings.deterministic,
+            text_input_active,
+        };
+
+        // Without vsync, the frame rate is limited to the monitor's refresh rate instead
+        let mut refresh_rate_cap = None;
+        if let Err(e) = ctx.set_vsync(settings.vsync) {
+            warn!("Failed to set vsync: {}", e);
+            refresh_rate_cap = settings
+                .vsync
+                .then(|| ctx.window()?.current_monitor()?.refresh_rate_millihertz())
+                .flatten()
+                .map(|millihertz| (millihertz + 500) / 1000);
+            if let Some(refresh_rate) = refresh_rate_cap {
+                info!("Limiting the frame rate to {} FPS instead", refresh_rate);
+            }
+        }
+
+        let mut app = A::setup(&mut ctx, options)?;
+
+        // Headless contexts have no default framebuffer, so they render into one of their own,
+        // created along with the viewport
+        let mut offscreen_target: Option<offscreen::OffscreenTarget> = None;
+
+        // Frames are rendered at a lower resolution while they can't keep up with `target_fps`,
+        // into a framebuffer of the scaled size that is stretched over the window. Frames must
+        // look the same every time while replaying or capturing, so the quality is fixed there.
+        // Multisampled windows can't be blitted into, so they keep their full resolution, and
+        // there is nothing to govern with the resolution fixed.
+        let mut quality_governor = settings
+            .target_fps
+            .filter(|_| ctx.real_time && settings.msaa == 0)
+            .map(QualityGovernor::new);
+        if settings.target_fps.is_some() && settings.msaa > 0 {
+            warn!(
+                "The quality is fixed with multisampling, so frames may not reach the target rate"
+            );
+        }
+        let mut scaled_target: Option<(offscreen::OffscreenTarget, (u32, u32))> = None;
+
+        // Every frame is saved when capturing, which headless mode always does
+        let capture_path = settings
+            .capture
+            .clone()
+            .or_else(|| settings.headless.then(|| settings.output.clone()));
+        let mut frame_capture = capture_path
+            .map(|path| {
+                capture::FrameCapture::new(&path, settings.capture_fps)
+                    .map_err(|source| RenderError::Capture { path, source })
+            })
+            .transpose()?;
+
+        // F12 saves a screenshot, rendered larger than the window if `screenshot_scale` is set
+        let mut screenshots = screenshot::Screenshots::new();
+
+        // The optional second window showing the scene from above
+        let mut debug_view = debug_gl_window.and_then(|debug_gl_window| {
+            debug_view::DebugView::new(
+                debug_gl_window,
+                &ctx.gl,
+                settings.msaa > 0,
+                debug_view_closed,
+            )
+            .map_err(|e| warn!("Failed to set up the debug view: {}", e))
+            .ok()
+        });
+
+        // The main rendering loop
+        let first_frame_time = std::time::Instant::now();
+        let mut previous_frame_time = first_frame_time;
+
+        let mut replayed_window_size = initial_window_size;
+        let mut deterministic_frames = 0;
+        let mut frame_pacer = settings
+            .fps_cap
+            .or(refresh_rate_cap)
+            .map(timing::FramePacer::new);
+        let mut background_frame_pacer = timing::FramePacer::new(settings.background_fps.max(1));
+        let mut frame_stats = timing::FrameStatsCounter::new(std::time::Duration::from_secs(1));
+
+        // Errors stop the loop, but files being written are still finished below
+        let result = loop {
+            // Nothing is rendered while the window is minimized or hidden, or unfocused with a
+            // `background_fps` of 0. Replays don't depend on the window, so they never pause.
+            if input_replay.is_none() {
+                let mut paused = false;
+                loop {
+                    // Keep collecting input while paused, to notice the window being restored
+                    input_collector.extend(input_receiver.try_iter());
+                    input_collector.read_window(&window_snapshot);
+                    let size = input_collector.window_size();
+                    let minimized =
+                        window_occluded.load(Ordering::Relaxed) || size.0 == 0 || size.1 == 0;
+                    let focused = window_focused.load(Ordering::Relaxed);
+                    if !minimized && (focused || settings.background_fps > 0)
+                        || shutdown.load(Ordering::Relaxed)
+                    {
+                        break;
+                    }
+                    paused = true;
+                    thread::sleep(std::time::Duration::from_millis(50));
+                }
+                // The time spent paused shouldn't make the animation jump ahead
+                if paused {
+                    previous_frame_time = std::time::Instant::now();
+                }
+            }
+
+            if shutdown.load(Ordering::Relaxed) {
+                break Ok(());
+            }
+            crash::next_frame();
+
+            // Compute time passed since the previous frame and since the start of the program
+            let now = std::time::Instant::now();
+            let mut elapsed = now.duration_since(first_frame_time).as_secs_f32();
+            let mut delta_time = now.duration_since(previous_frame_time).as_secs_f32();
+            previous_frame_time = now;
+
+            // Gather this frame's input. Keys pressed since the last frame are only reported once.
+            let input = if let Some(replay) = input_replay.as_mut() {
+                if replay.is_finished() {
+                    info!("Replay finished after {:.2}s", replay.time());
+                    break Ok(());
+                }
+                let input = replay.next_frame();
+                elapsed = replay.time();
+                delta_time = replay::REPLAY_TIMESTEP;
+                if input.window_size != replayed_window_size {
+                    replayed_window_size = input.window_size;
+                    if let Some(window) = ctx.window() {
+                        let _ = window.request_inner_size(PhysicalSize::new(
+                            input.window_size.0,
+                            input.window_size.1,
+                        ));
+                    }
+                }
+                input
+            } else {
+                input_collector.extend(input_receiver.try_iter());
+                input_collector.read_window(&window_snapshot);
+                input_collector.take_frame()
+            };
+            // Captured frames advance the clock by a fixed step, so the video doesn't depend on how
+            // fast the frames were rendered
+            if let (Some(capture), None) = (frame_capture.as_ref(), input_replay.as_ref()) {
+                elapsed = capture.frames() as f32 * capture.timestep();
+                delta_time = capture.timestep();
+            } else if settings.deterministic && input_replay.is_none() {
+                // The same clock as replays, so a recording made now replays frame for frame
+                elapsed = replay::fixed_step_time(deterministic_frames);
+                delta_time = replay::REPLAY_TIMESTEP;
+                deterministic_frames += 1;
+            }
+            if let Some(recorder) = input_recorder.as_mut() {
+                if let Err(e) = recorder.record(elapsed, &input) {
+                    warn!("Failed to record input: {}", e);
+                    input_recorder = None;
+                }
+            }
+            // Recreate the context when the driver reports it lost, e.g. after a GPU reset, or when
+            // a restart of the renderer is requested with F5
+            let context_lost = gl::GetGraphicsResetStatus::is_loaded()
+                && unsafe { gl::GetGraphicsResetStatus() } != gl::NO_ERROR;
+            if context_lost || input.keys.just_pressed(KeyCode::F5) {
+                if context_lost {
+                    warn!("OpenGL context lost, recreating it");
+                } else {
+                    info!("Restarting the renderer");
+                }
+                if let Err(e) = ctx.gl.recreate(None) {
+                    break Err(e);
+                }
+                ctx.capabilities = unsafe { renderer::init_gl(settings.msaa > 0) };
+                ctx.samples = (settings.msaa as u32).min(ctx.capabilities.max_samples);
+                ctx.backend = GlBackend::new(&ctx.capabilities.features);
+                let vao_ids = match ctx.assets.reupload(&mut ctx.backend) {
+                    Ok(vao_ids) => vao_ids,
+                    Err(e) => break Err(e.into()),
+                };
+                if let Err(e) = ctx.set_vsync(ctx.vsync) {
+                    warn!("Failed to set vsync: {}", e);
+                }
+                if let Err(e) = app.context_recreated(&mut ctx, &vao_ids) {
+                    break Err(e);
+                }
+                viewport_size = (0, 0); // Set up the viewport again below
+                screenshots = screenshot::Screenshots::new(); // Pending ones are lost
+                offscreen_target = None; // Went away with the old context
+                scaled_target = None;
+                if let Some(view) = debug_view.as_mut() {
+                    if let Err(e) = view.recreate(&ctx.gl) {
+                        warn!("Failed to recreate the debug view: {}", e);
+                        debug_view = None;
+                    }
+                }
+            }
+
+            // Follow the window size, skipping zero sizes which happen while minimized
+            if input.window_size != viewport_size
+                && input.window_size.0 > 0
+                && input.window_size.1 > 0
+            {
+                viewport_size = input.window_size;
+                ctx.viewport_size = viewport_size;
+                // Some platforms (e.g. Wayland and macOS) need the surface resized explicitly
+                ctx.gl
+                    .resize(PhysicalSize::new(viewport_size.0, viewport_size.1));
+                ctx.backend.resize(viewport_size.0, viewport_size.1);
+                if settings.headless {
+                    if let Some(previous) = offscreen_target.take() {
+                        unsafe { previous.delete() };
+                    }
+                    match unsafe {
+                        offscreen::OffscreenTarget::new(viewport_size.0, viewport_size.1)
+                    } {
+                        Ok(target) => offscreen_target = Some(target),
+                        Err(e) => break Err(e),
+                    }
+                }
+            }
+
+            let index_counts = ctx.assets.hot_reload(&mut ctx.backend);
+            if !index_counts.is_empty() {
+                app.meshes_reloaded(&mut ctx, &index_counts);
+            }
+
+            let scale = ctx.quality.resolution_scale;
+            let render_size = (
+                ((viewport_size.0 as f32 * scale) as u32).max(1),
+                ((viewport_size.1 as f32 * scale) as u32).max(1),
+            );
+            if render_size == viewport_size || settings.headless || settings.msaa > 0 {
+                if let Some((previous, _)) = scaled_target.take() {
+                    unsafe { previous.delete() };
+                }
+            } else if let Some((target, _)) = scaled_target
+                .as_ref()
+                .filter(|(_, size)| *size == render_size)
+            {
+                unsafe { target.bind() };
+            } else {
+                if let Some((previous, _)) = scaled_target.take() {
+                    unsafe { previous.delete() };
+                }
+                match unsafe { offscreen::OffscreenTarget::new(render_size.0, render_size.1) } {
+                    Ok(target) => scaled_target = Some((target, render_size)),
+                    Err(e) => {
+                        warn!("{}, rendering at full resolution", e);
+                        quality_governor = None;
+                        ctx.quality = Quality::FULL;
+                    }
+                }
+            }
+            if scaled_target.is_some() {
+                ctx.viewport_size = render_size;
+                ctx.backend.resize(render_size.0, render_size.1);
+            }
+
+            ctx.elapsed = elapsed;
+            ctx.arena.reset();
+            app.update(&mut ctx, &input, delta_time);
+            // Meshes and textures added during the update are needed for drawing it
+            ctx.assets.upload(&mut ctx.backend);
+            app.render(&mut ctx);
+
+            if let Some((target, size)) = scaled_target.as_ref() {
+                ctx.backend.push_group("Upscale");
+                unsafe { target.blit_to_window(*size, viewport_size)? };
+                ctx.backend.pop_group();
+                ctx.viewport_size = viewport_size;
+                ctx.backend.resize(viewport_size.0, viewport_size.1);
+            }
+            if let Some(governor) = quality_governor.as_mut() {
+                if let Some(quality) = governor.frame(delta_time) {
+                    ctx.quality = quality;
+                }
+            }
+
+            unsafe {
+                if let Some(capture) = frame_capture.as_mut() {
+                    if let Err(e) = capture.write_frame(viewport_size.0, viewport_size.1) {
+                        if settings.headless {
+                            break Err(e);
+                        }
+                        warn!("{}, stopping the capture", e);
+                        frame_capture = None;
+                    }
+                }
+
+                if input.keys.just_pressed(KeyCode::F12) {
+                    let largest_side = viewport_size.0.max(viewport_size.1).max(1);
+                    let scale = settings
+                        .screenshot_scale
+                        .min(ctx.capabilities.max_renderbuffer_size / largest_side);
+                    let size = (viewport_size.0 * scale, viewport_size.1 * scale);
+                    let previous_framebuffer = offscreen::bound_framebuffer();
+                    let target = if scale > 1 {
+                        offscreen::OffscreenTarget::new(size.0, size.1)
+                            .map_err(|e| {
+                                warn!("{}, taking the screenshot at the window size", e);
+                                gl::BindFramebuffer(gl::FRAMEBUFFER, previous_framebuffer);
+                            })
+                            .ok()
+                    } else {
+                        None
+                    };
+                    if let Some(target) = target {
+                        // Draw the frame again into a larger framebuffer with the same projection
+                        ctx.viewport_size = size;
+                        ctx.backend.resize(size.0, size.1);
+                        ctx.backend.push_group("Screenshot");
+                        app.render(&mut ctx);
+                        ctx.backend.pop_group();
+                        screenshots.capture(size.0, size.1);
+                        target.delete();
+                        gl::BindFramebuffer(gl::FRAMEBUFFER, previous_framebuffer);
+                        ctx.viewport_size = viewport_size;
+                        ctx.backend.resize(viewport_size.0, viewport_size.1);
+                    } else {
+                        screenshots.capture(viewport_size.0, viewport_size.1);
+                    }
+                }
+
+                if let Err(e) = ctx.gl.swap_buffers() {
+                    break Err(e.into());
+                }
+                screenshots.poll();
+            }
+
+            // The event loop shows the statistics in the window title
+            if let (Some(mut stats), Some(proxy)) = (frame_stats.frame(ctx.backend.stats()), &proxy)
+            {
+                stats.memory = ctx.backend.memory_usage();
+                let _ = proxy.send_event(UserEvent::FrameStats(stats));
+            }
+
+            if settings.headless
+                && frame_capture.as_ref().map(|c| c.frames()) == Some(settings.frames)
+            {
+                break Ok(());
+            }
+
+            // Closing the debug view window or failing to render it drops it for good
+            if let (Some(view), Some((root, center))) =
+                (debug_view.as_mut(), app.debug_view_target())
+            {
+                let keep = !view.is_closed()
+                    && view
+                        .render(root, &ctx.assets, &center, &ctx.gl)
+                        .map_err(|e| warn!("Failed to render the debug view: {}", e))
+                        .is_ok();
+                if !keep {
+                    debug_view = None;
+                }
+            }
+
+            // Throttle to `background_fps` while another window has focus
+            if input_replay.is_none() && !window_focused.load(Ordering::Relaxed) {
+                background_frame_pacer.wait();
+            } else if let Some(frame_pacer) = frame_pacer.as_mut() {
+                frame_pacer.wait();
+            }
+        };
+
+        // Finish writing screenshots and captured frames before the program exits. Everything else
+        // on the GPU goes away along with the contexts.
+        unsafe {
+            screenshots.finish();
+            if let Some(target) = offscreen_target.take() {
+                target.delete();
+            }
+            if let Some((target, _)) = scaled_target.take() {
+                target.delete();
+            }
+        }
+        drop(frame_capture);
+        drop(debug_view);
+        drop(app);
+        drop(ctx);
+        result
+    };
+
+    // Headless mode renders on this thread, without a window or an event loop
+    if settings.headless {
+        let gl_window = window::create_headless(settings.width, settings.height)?;
+        arc_window_snapshot.set_size(settings.width, settings.height);
+        return render_loop(gl_window, None, None);
+    }
+
+    // Set up the event loop. The window is created once it is running, see `App::resumed`.
+    let el = EventLoop::<UserEvent>::with_user_event().build()?;
+
+    let mut app = App {
+        window_attributes: window_attributes(
+            LogicalSize::new(settings.width, settings.height).into(),
+            settings.fullscreen,
+        )
+        .with_title(settings.title.clone()),
+        msaa: settings.msaa,
+        monitor: settings.monitor,
+        debug_view: settings.debug_view,
+        render_loop: Some(Box::new(render_loop)),
+        proxy: el.create_proxy(),
+        input: input_sender,
+        window_snapshot: arc_window_snapshot,
+        text_input_active: arc_text_input_active,
+        shutdown: arc_shutdown,
+        window_focused: arc_window_focused,
+        window_occluded: arc_window_occluded,
+        debug_view_closed: arc_debug_view_closed,
+        modifiers: ModifiersState::empty(),
+        last_cursor_position: None,
+        scale_factor: 1.0,
+        main_window_id: None,
+        window: None,
+        error: None,
+        warning: None,
+    };
+
+    // Start the event loop -- This is where window events are initially handled
+    el.run_app(&mut app)?;
+    match app.error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+// How long a warning mirrored from the log is shown in the window title
+const WARNING_DURATION: std::time::Duration = std::time::Duration::from_secs(5);
+
+// Messages from the render thread to the event loop
+enum UserEvent {
+    // The render thread has stopped, so the event loop should exit, with the error that stopped it
+    // if any
+    RenderThreadStopped(Option<RenderError>),
+    // Frame statistics from the render thread, sent once per second for the window title
+    FrameStats(timing::FrameStats),
+}
+
+fn window_attributes(size: Size, fullscreen: bool) -> WindowAttributes {
+    Window::default_attributes()
+        .with_resizable(true)
+        .with_inner_size(size)
+        .with_fullscreen(if fullscreen {
+            Some(Fullscreen::Borderless(None))
+        } else {
+            None
+        })
+}
+
+// The render thread's body, given the main window, the debug view window if one was requested, and
+// a way to send events to the event loop unless rendering headless
+type RenderLoop = dyn FnOnce(window::GlWindow, Option<window::GlWindow>, Option<Proxy>) -> Result<(), RenderError>
+    + Send;
+
+// Wakes up the event loop from other threads with a `UserEvent`
+type Proxy = EventLoopProxy<UserEvent>;
+
+// The event loop's side of the program. It creates the window once the event loop is running,
+// hands it to the render thread, and forwards input to the render thread as `InputEvent`s, or
+// through the `WindowSnapshot` for resizing and mouse movement.
+struct App {
+    window_attributes: WindowAttributes,
+    msaa: u16,
+    monitor: Option<usize>, // Monitor to open the window on, the one the OS picks if None
+    debug_view: bool,       // Whether to open the debug view window as well
+    render_loop: Option<Box<RenderLoop>>,
+    proxy: Proxy,
+
+    input: mpsc::Sender<InputEvent>,
+    window_snapshot: Arc<WindowSnapshot>,
+    text_input_active: Arc<AtomicBool>,
+    shutdown: Arc<AtomicBool>,
+    window_focused: Arc<AtomicBool>,
+    window_occluded: Arc<AtomicBool>,
+    debug_view_closed: Arc<AtomicBool>,
+
+    // Currently held modifier keys, used to tell pinch gestures apart from scrolling
+    modifiers: ModifiersState,
+    // Last known cursor position, used to turn CursorMoved events into deltas
+    last_cursor_position: Option<PhysicalPosition<f64>>,
+    scale_factor: f64,
+    // The window events are for, as opposed to the debug view window
+    main_window_id: Option<WindowId>,
+    // The main window, kept for updating its title
+    window: Option<Arc<Window>>,
+    // What stopped the program, returned from `run` once the event loop exits
+    error: Option<RenderError>,
+    // The last warning mirrored from the log, and when it was logged
+    warning: Option<(String, std::time::Instant)>,
+}
+
+impl App {
+    // Forward input to the render thread. Input arriving after it has stopped is dropped.
+    fn send_input(&self, event: InputEvent) {
+        let _ = self.input.send(event);
+    }
+
+    // List the monitors, and move the window onto the one chosen in the settings
+    fn place_on_monitor(
+        &self,
+        event_loop: &ActiveEventLoop,
+        mut attributes: WindowAttributes,
+    ) -> WindowAttributes {
+        let monitors: Vec<_> = event_loop.available_monitors().collect();
+        let primary = event_loop.primary_monitor();
+        for (i, monitor) in monitors.iter().enumerate() {
+            let size = monitor.size();
+            info!(
+                "Monitor {}: {} {}x{} at {}{}",
+                i,
+                monitor.name().unwrap_or_else(|| "unnamed".to_string()),
+                size.width,
+                size.height,
+                monitor
+                    .refresh_rate_millihertz()
+                    .map(|millihertz| format!("{:.2} Hz", millihertz as f32 / 1000.0))
+                    .unwrap_or_else(|| "an unknown refresh rate".to_string()),
+                if primary.as_ref() == Some(monitor) {
+                    " (primary)"
+                } else {
+                    ""
+                }
+            );
+        }
+
+        let index = match self.monitor {
+            Some(index) => index,
+            None => return attributes,
+        };
+        let monitor = match monitors.get(index) {
+            Some(monitor) => monitor.clone(),
+            None => {
+                warn!("There is no monitor {}, using the default one", index);
+                return attributes;
+            }
+        };
+        if attributes.fullscreen.is_some() {
+            attributes.fullscreen = Some(Fullscreen::Borderless(Some(monitor)));
+        } else {
+            // Center the window on the monitor
+            let window_size = attributes
+                .inner_size
+                .map(|size| size.to_physical::<i32>(monitor.scale_factor()))
+                .unwrap_or(PhysicalSize::new(0, 0));
+            let monitor_size = monitor.size();
+            attributes.position = Some(
+                PhysicalPosition::new(
+                    monitor.position().x + (monitor_size.width as i32 - window_size.width) / 2,
+                    monitor.position().y + (monitor_size.height as i32 - window_size.height) / 2,
+                )
+                .into(),
+            );
+        }
+        attributes
+    }
+
+    // Ask the render thread to stop. The event loop exits once it has, see
+    // `UserEvent::RenderThreadStopped`.
+    fn exit(&self, event_loop: &ActiveEventLoop) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if self.render_loop.is_some() {
+            // The render thread was never started
+            event_loop.exit();
+        }
+    }
+}
+
+impl ApplicationHandler<UserEvent> for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        // The window is only created the first time the event loop resumes
+        let render_loop = match self.render_loop.take() {
+            Some(render_loop) => render_loop,
+            None => return,
+        };
+        let attributes = self.place_on_monitor(event_loop, self.window_attributes.clone());
+        let gl_window = match window::create(event_loop, attributes, self.msaa) {
+            Ok(gl_window) => gl_window,
+            Err(e) => {
+                self.error = Some(e);
+                event_loop.exit();
+                return;
+            }
+        };
+
+        // The window was requested in logical pixels, but everything that touches the framebuffer
+        // (viewport, projection, picking) works in physical pixels, so the real size is read back.
+        // On HiDPI displays it is larger than the requested size by the scale factor.
+        let window = gl_window.window().expect("Windowed contexts have a window");
+        let size = window.inner_size();
+        self.scale_factor = window.scale_factor();
+        info!(
+            "Window size: {}x{} (scale factor {})",
+            size.width, size.height, self.scale_factor
+        );
+        self.window_snapshot.set_size(size.width, size.height);
+
+        self.main_window_id = Some(window.id());
+        self.window = gl_window.shared_window();
+
+        let debug_gl_window = if self.debug_view {
+            let attributes = window_attributes(LogicalSize::new(400, 400).into(), false)
+                .with_title(format!("{} debug view", self.window_attributes.title));
+            window::create_shared(event_loop, attributes, &gl_window)
+                .map_err(|e| warn!("Failed to create the debug view window: {}", e))
+                .ok()
+        } else {
+            None
+        };
+
+        // Spawn a separate thread for rendering, so event handling doesn't block rendering
+        let proxy = self.proxy.clone();
+        let render_thread =
+            thread::spawn(move || render_loop(gl_window, debug_gl_window, Some(proxy)));
+
+        // Keep track of the health of the rendering thread. It stops with an error or by panicking,
+        // when an input replay is done, or when asked to shut down, and in all cases the event loop
+        // is woken up to exit.
+        let proxy = self.proxy.clone();
+        thread::spawn(move || {
+            let error = match render_thread.join() {
+                Ok(result) => result.err(),
+                Err(_) => Some(RenderError::RenderThreadPanicked {
+                    report: crash::take_report_path(),
+                }),
+            };
+            let _ = proxy.send_event(UserEvent::RenderThreadStopped(error));
+        });
+    }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
+        match event {
+            UserEvent::RenderThreadStopped(error) => {
+                self.error = error;
+                event_loop.exit();
+            }
+            UserEvent::FrameStats(stats) => {
+                // Warnings mirrored from the log stay in the title for a while
+                if let Some(warning) = logging::take_warnings().pop() {
+                    self.warning = Some((warning, std::time::Instant::now()));
+                }
+                let warning = self
+                    .warning
+                    .as_ref()
+                    .filter(|(_, logged)| logged.elapsed() < WARNING_DURATION)
+                    .map(|(warning, _)| format!(" - {}", warning))
+                    .unwrap_or_default();
+                let megabytes = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+                let memory = &stats.memory;
+                let available = memory
+                    .available_bytes
+                    .map(|bytes| format!(", {:.0} MB free", megabytes(bytes)))
+                    .unwrap_or_default();
+                if let Some(window) = self.window.as_ref() {
+                    window.set_title(&format!(
+                        "{} - {:.0} FPS - {:.2} ms (max {:.2} ms) - {} draw calls - \
+                         {} meshes {:.1} MB, {} textures {:.1} MB{}{}",
+                        self.window_attributes.title,
+                        stats.fps,
+                        stats.frame_time * 1000.0,
+                        stats.longest_frame_time * 1000.0,
+                        stats.draw_calls,
+                        memory.meshes,
+                        megabytes(memory.buffer_bytes),
+                        memory.textures,
+                        megabytes(memory.texture_bytes),
+                        available,
+                        warning
+                    ));
+                }
+            }
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, id: WindowId, event: WindowEvent) {
+        // The debug view only needs to know when it is closed or focused, and the render thread
+        // picks up its size by itself
+        if Some(id) != self.main_window_id {
+            match event {
+                WindowEvent::CloseRequested => {
+                    self.debug_view_closed.store(true, Ordering::Relaxed)
+                }
+                // Looking at the debug view shouldn't slow down rendering
+                WindowEvent::Focused(focused) => {
+                    self.window_focused.store(focused, Ordering::Relaxed)
+                }
+                _ => {}
+            }
+            return;
+        }
+        match event {
+            WindowEvent::Resized(physical_size) => {
+                debug!(
+                    "New window size received: {}x{}",
+                    physical_size.width, physical_size.height
+                );
+                self.window_snapshot
+                    .set_size(physical_size.width, physical_size.height);
+            }
+            // Moving the window to a display with a different scale factor is followed by a resize
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                debug!("New scale factor received: {}", scale_factor);
+                self.scale_factor = scale_factor;
+            }
+            WindowEvent::Focused(focused) => {
+                self.window_focused.store(focused, Ordering::Relaxed);
+            }
+            // Minimized windows, or windows hidden behind others, are reported as occluded on
+            // some platforms
+            WindowEvent::Occluded(occluded) => {
+                self.window_occluded.store(occluded, Ordering::Relaxed);
+            }
+            // Dropped files are handed to the application with the next frame's input
+            WindowEvent::DroppedFile(path) => {
+                self.send_input(InputEvent::DroppedFile(path));
+            }
+            WindowEvent::CloseRequested => {
+                self.exit(event_loop);
+            }
+            // Keep track of currently pressed keys to send to the rendering thread
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key,
+                        state: key_state,
+                        text,
+                        ..
+                    },
+                ..
+            } => {
+                // Collect typed characters for text input
+                if key_state == Pressed {
+                    if let Some(text) = text {
+                        for c in text.chars() {
+                            self.send_input(InputEvent::Char(c));
+                        }
+                    }
+                }
+
+                let keycode = match physical_key {
+                    PhysicalKey::Code(keycode) => keycode,
+                    PhysicalKey::Unidentified(_) => return,
+                };
+                self.send_input(InputEvent::Key(keycode, key_state == Pressed));
+
+                // Handle Escape and Q keys separately, unless they are being used to type text
+                if key_state == Pressed && !self.text_input_active.load(Ordering::Relaxed) {
+                    match keycode {
+                        KeyCode::Escape => {
+                            self.exit(event_loop);
+                        }
+                        KeyCode::KeyQ => {
+                            self.exit(event_loop);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            // Keep track of currently pressed mouse buttons to send to the rendering thread
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.send_input(InputEvent::Button(button, state == Pressed));
+            }
+            // Accumulate cursor movement within the window. The position stays in physical pixels to
+            // match the framebuffer, while movement is converted to logical pixels so looking around
+            // feels the same regardless of the display's scale factor.
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some(last) = self.last_cursor_position {
+                    self.send_input(InputEvent::Cursor(
+                        ((position.x - last.x) / self.scale_factor) as f32,
+                        ((position.y - last.y) / self.scale_factor) as f32,
+                    ));
+                }
+                self.last_cursor_position = Some(position);
+                self.send_input(InputEvent::Position(Some((
+                    position.x as f32,
+                    position.y as f32,
+                ))));
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.last_cursor_position = None;
+                self.send_input(InputEvent::Position(None));
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            // Accumulate scroll wheel movement. Touchpads report scrolling in pixels, and some
+            // platforms report pinch gestures as scrolling with Ctrl held, so those become pan and
+            // zoom instead.
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(_, y) if self.modifiers.control_key() => {
+                    self.send_input(InputEvent::Zoom(y * 0.1));
+                }
+                MouseScrollDelta::LineDelta(_, y) => {
+                    self.send_input(InputEvent::Scroll(y));
+                }
+                MouseScrollDelta::PixelDelta(position) if self.modifiers.control_key() => {
+                    let position = position.to_logical::<f64>(self.scale_factor);
+                    self.send_input(InputEvent::Zoom(position.y as f32 / 200.0));
+                }
+                MouseScrollDelta::PixelDelta(position) => {
+                    let position = position.to_logical::<f64>(self.scale_factor);
+                    self.send_input(InputEvent::Pan(position.x as f32, position.y as f32));
+                }
+            },
+            // Touchpad pinch gestures, on platforms that report them as such
+            WindowEvent::PinchGesture { delta, .. } => {
+                self.send_input(InputEvent::Zoom(delta as f32));
+            }
+            _ => {}
+        }
+    }
+
+    fn device_event(&mut self, _: &ActiveEventLoop, _: DeviceId, event: DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.window_snapshot
+                .add_mouse_delta(delta.0 as f32, delta.1 as f32);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+// Memory for data that only lives for a frame, like the bookkeeping for recording draws.
+//
+// Allocating from the arena just bumps a pointer, and everything in it is freed at once when the
+// render loop resets it before the next frame. After the first few frames it holds enough memory
+// for a whole frame, so frames stop allocating from the heap altogether. Nothing in the arena is
+// ever dropped, so only plain data should go in it.
+use bumpalo::Bump;
+
+#[derive(Default)]
+pub struct FrameArena {
+    bump: Bump,
+}
+
+impl FrameArena {
+    pub fn new() -> FrameArena {
+        FrameArena::default()
+    }
+
+    // Free everything allocated since the last reset, keeping the memory for the next frame
+    pub fn reset(&mut self) {
+        self.bump.reset();
+    }
+
+    // A slice of `items`, valid until the next reset
+    pub fn alloc_slice<T, I>(&self, items: I) -> &mut [T]
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.bump.alloc_slice_fill_iter(items)
+    }
+
+    // Memory held by the arena, used or not
+    pub fn allocated_bytes(&self) -> usize {
+        self.bump.allocated_bytes()
+    }
+}
@@ -0,0 +1,587 @@
+// Meshes, textures and shader pipelines, owned in one place and referred to by typed handles.
+//
+// Everything the GPU needs is registered here rather than kept in loose locals, so it can be
+// uploaded again when the context is recreated, copied to the debug view's context, and freed once
+// nothing uses it anymore. Adding a mesh or a texture only queues it, and `upload` creates the GPU
+// objects in one go on the render thread, the only thread with a context. Loading the same file or
+// pipeline twice gives the same handle and counts another reference, and `release` drops one.
+// Released slots are reused under a new generation, so a stale handle finds nothing instead of
+// someone else's asset.
+//
+// With `watch`, models and textures loaded from files are reloaded when the files change, and
+// their new data is uploaded under the same GPU handles, so scene nodes keep drawing them.
+use crate::backend::{Backend, MeshHandle, PipelineHandle, TextureHandle};
+use crate::error::{MeshError, ShaderError, TextureError};
+use crate::loader::{HotReloader, ModelLoader, Reloaded, WatchedFile};
+use crate::mesh::Mesh;
+use crate::renderer::Vao;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// A reference to an asset of type `T` in `Assets`
+pub struct Handle<T> {
+    index: u32,
+    generation: u32,
+    kind: PhantomData<fn() -> T>,
+}
+
+// Implemented by hand, as deriving would require `T` to implement these as well
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.index, self.generation).hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({}v{})", self.index, self.generation)
+    }
+}
+
+// An image for sampling in shaders
+pub struct Texture {
+    pub image: image::RgbaImage,
+}
+
+impl Texture {
+    pub fn load(path: &Path) -> Result<Texture, TextureError> {
+        let image = image::open(path)
+            .map_err(|source| TextureError::Load {
+                path: path.to_path_buf(),
+                source,
+            })?
+            .to_rgba8();
+        Ok(Texture { image })
+    }
+}
+
+// A shader pipeline, compiled from the shaders named like in `Backend::create_pipeline`
+pub struct Pipeline {
+    pub name: String,
+}
+
+// Types of assets, and what they become on the GPU
+pub trait Asset: Sized {
+    type Gpu: Copy;
+
+    fn pool(assets: &Assets) -> &Pool<Self>;
+
+    fn pool_mut(assets: &mut Assets) -> &mut Pool<Self>;
+
+    fn delete(gpu: Self::Gpu, backend: &mut dyn Backend);
+}
+
+impl Asset for Mesh {
+    type Gpu = MeshHandle;
+
+    fn pool(assets: &Assets) -> &Pool<Mesh> {
+        &assets.meshes
+    }
+
+    fn pool_mut(assets: &mut Assets) -> &mut Pool<Mesh> {
+        &mut assets.meshes
+    }
+
+    fn delete(mesh: MeshHandle, backend: &mut dyn Backend) {
+        backend.delete_mesh(mesh);
+    }
+}
+
+impl Asset for Texture {
+    type Gpu = TextureHandle;
+
+    fn pool(assets: &Assets) -> &Pool<Texture> {
+        &assets.textures
+    }
+
+    fn pool_mut(assets: &mut Assets) -> &mut Pool<Texture> {
+        &mut assets.textures
+    }
+
+    fn delete(texture: TextureHandle, backend: &mut dyn Backend) {
+        backend.delete_texture(texture);
+    }
+}
+
+impl Asset for Pipeline {
+    type Gpu = PipelineHandle;
+
+    fn pool(assets: &Assets) -> &Pool<Pipeline> {
+        &assets.pipelines
+    }
+
+    fn pool_mut(assets: &mut Assets) -> &mut Pool<Pipeline> {
+        &mut assets.pipelines
+    }
+
+    fn delete(pipeline: PipelineHandle, backend: &mut dyn Backend) {
+        backend.delete_pipeline(pipeline);
+    }
+}
+
+struct Entry<T: Asset> {
+    asset: T,
+    gpu: Option<T::Gpu>,    // None until uploaded
+    source: Option<String>, // The file or name it was loaded from, if any
+}
+
+struct Slot<T: Asset> {
+    generation: u32,
+    refs: u32,
+    entry: Option<Entry<T>>,
+}
+
+// The assets of one type
+pub struct Pool<T: Asset> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    by_source: HashMap<String, Handle<T>>,
+    pending: Vec<Handle<T>>, // Waiting for `upload`
+}
+
+impl<T: Asset> Default for Pool<T> {
+    fn default() -> Self {
+        Pool {
+            slots: vec![],
+            free: vec![],
+            by_source: HashMap::new(),
+            pending: vec![],
+        }
+    }
+}
+
+impl<T: Asset> Pool<T> {
+    fn insert(&mut self, asset: T, gpu: Option<T::Gpu>, source: Option<String>) -> Handle<T> {
+        let entry = Entry { asset, gpu, source };
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    refs: 0,
+                    entry: None,
+                });
+                self.slots.len() as u32 - 1
+            }
+        };
+        let slot = &mut self.slots[index as usize];
+        slot.refs = 1;
+        let handle = Handle {
+            index,
+            generation: slot.generation,
+            kind: PhantomData,
+        };
+        if entry.gpu.is_none() {
+            self.pending.push(handle);
+        }
+        if let Some(source) = &entry.source {
+            self.by_source.insert(source.clone(), handle);
+        }
+        slot.entry = Some(entry);
+        handle
+    }
+
+    fn slot(&self, handle: Handle<T>) -> Option<&Slot<T>> {
+        self.slots
+            .get(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation && slot.entry.is_some())
+    }
+
+    fn slot_mut(&mut self, handle: Handle<T>) -> Option<&mut Slot<T>> {
+        self.slots
+            .get_mut(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation && slot.entry.is_some())
+    }
+
+    fn entry(&self, handle: Handle<T>) -> Option<&Entry<T>> {
+        self.slot(handle)?.entry.as_ref()
+    }
+
+    fn entry_mut(&mut self, handle: Handle<T>) -> Option<&mut Entry<T>> {
+        self.slot_mut(handle)?.entry.as_mut()
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &Entry<T>> {
+        self.slots.iter().filter_map(|slot| slot.entry.as_ref())
+    }
+
+    fn entries_mut(&mut self) -> impl Iterator<Item = &mut Entry<T>> {
+        self.slots.iter_mut().filter_map(|slot| slot.entry.as_mut())
+    }
+
+    // Take another reference to an asset loaded from `source` before, if there is one
+    fn reuse(&mut self, source: &str) -> Option<Handle<T>> {
+        let handle = *self.by_source.get(source)?;
+        self.slot_mut(handle)?.refs += 1;
+        Some(handle)
+    }
+
+    // Drop a reference, returning the entry once the last one is gone
+    fn release(&mut self, handle: Handle<T>) -> Option<Entry<T>> {
+        let slot = self.slot_mut(handle)?;
+        slot.refs -= 1;
+        if slot.refs > 0 {
+            return None;
+        }
+        let entry = slot.entry.take()?;
+        slot.generation += 1;
+        self.free.push(handle.index);
+        if let Some(source) = &entry.source {
+            self.by_source.remove(source);
+        }
+        Some(entry)
+    }
+}
+
+// Which file a mesh was loaded from, and which of the meshes built from the file it is
+struct MeshSource {
+    path: PathBuf,
+    part: usize,
+}
+
+#[derive(Default)]
+pub struct Assets {
+    meshes: Pool<Mesh>,
+    textures: Pool<Texture>,
+    pipelines: Pool<Pipeline>,
+    mesh_sources: HashMap<Handle<Mesh>, MeshSource>,
+    model_loaders: HashMap<PathBuf, ModelLoader>,
+    reloader: Option<HotReloader>,
+}
+
+impl Assets {
+    pub fn new() -> Assets {
+        Assets::default()
+    }
+
+    // Queue a mesh for upload. It has a VAO after the next `upload`.
+    pub fn add_mesh(&mut self, mesh: Mesh) -> Handle<Mesh> {
+        self.meshes.insert(mesh, None, None)
+    }
+
+    // Load the meshes of a model file with `load` and queue them for upload
+    pub fn load_model(
+        &mut self,
+        path: &Path,
+        load: ModelLoader,
+    ) -> Result<Vec<Handle<Mesh>>, MeshError> {
+        let meshes = load(path)?;
+        Ok(self.add_model(path, load, meshes))
+    }
+
+    // Queue meshes loaded from a model file with `load` elsewhere, e.g. on the asset thread
+    pub fn add_model(
+        &mut self,
+        path: &Path,
+        load: ModelLoader,
+        meshes: Vec<Mesh>,
+    ) -> Vec<Handle<Mesh>> {
+        if let Some(reloader) = &self.reloader {
+            reloader.watch(path, WatchedFile::Model(load));
+        }
+        self.model_loaders.insert(path.to_path_buf(), load);
+        meshes
+            .into_iter()
+            .enumerate()
+            .map(|(part, mesh)| {
+                // Named for graphics debuggers, see `Backend::label_mesh`
+                let name = format!("{}[{}]", path.display(), part);
+                let handle = self.meshes.insert(mesh, None, Some(name));
+                let source = MeshSource {
+                    path: path.to_path_buf(),
+                    part,
+                };
+                self.mesh_sources.insert(handle, source);
+                handle
+            })
+            .collect()
+    }
+
+    // Queue a texture for upload, e.g. one generated rather than loaded
+    pub fn add_texture(&mut self, texture: Texture) -> Handle<Texture> {
+        self.textures.insert(texture, None, None)
+    }
+
+    // Load an image and queue it for upload
+    pub fn load_texture(&mut self, path: &Path) -> Result<Handle<Texture>, TextureError> {
+        let source = path.to_string_lossy().into_owned();
+        if let Some(handle) = self.textures.reuse(&source) {
+            return Ok(handle);
+        }
+        let texture = Texture::load(path)?;
+        if let Some(reloader) = &self.reloader {
+            reloader.watch(path, WatchedFile::Texture);
+        }
+        Ok(self.textures.insert(texture, None, Some(source)))
+    }
+
+    // Pipelines are compiled right away rather than queued, so mistakes in the shaders are
+    // reported where the pipeline is loaded
+    pub fn load_pipeline(
+        &mut self,
+        backend: &mut dyn Backend,
+        name: &str,
+    ) -> Result<Handle<Pipeline>, ShaderError> {
+        if let Some(handle) = self.pipelines.reuse(name) {
+            return Ok(handle);
+        }
+        let pipeline = backend.create_pipeline(name)?;
+        let asset = Pipeline {
+            name: name.to_string(),
+        };
+        Ok(self
+            .pipelines
+            .insert(asset, Some(pipeline), Some(name.to_string())))
+    }
+
+    // Compile every pipeline's shaders again, e.g. after editing them. Pipelines whose shaders
+    // fail to compile keep their old version, and the first error is returned after trying the
+    // rest. Pipelines get new handles, so look them up again with `gpu` before drawing.
+    pub fn reload_pipelines(&mut self, backend: &mut dyn Backend) -> Result<usize, ShaderError> {
+        let mut reloaded = 0;
+        let mut first_error = None;
+        for entry in self.pipelines.entries_mut() {
+            match backend.create_pipeline(&entry.asset.name) {
+                Ok(pipeline) => {
+                    if let Some(old) = entry.gpu.replace(pipeline) {
+                        backend.delete_pipeline(old);
+                    }
+                    reloaded += 1;
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(reloaded),
+        }
+    }
+
+    // Reload models and textures from files under `dir` when they change on disk, see `hot_reload`
+    pub fn watch(&mut self, dir: &Path) {
+        let reloader = HotReloader::spawn(dir.to_path_buf(), Duration::from_millis(500));
+        for (path, &load) in &self.model_loaders {
+            reloader.watch(path, WatchedFile::Model(load));
+        }
+        for source in self.textures.by_source.keys() {
+            reloader.watch(Path::new(source), WatchedFile::Texture);
+        }
+        info!("Watching {} for changes", dir.display());
+        self.reloader = Some(reloader);
+    }
+
+    // Upload the models and textures that were reloaded since the last call in place of the old
+    // ones. Scene nodes drawing the meshes need their index counts updated, so the new index
+    // count of each changed VAO is returned.
+    pub fn hot_reload(&mut self, backend: &mut dyn Backend) -> HashMap<u32, i32> {
+        let mut index_counts = HashMap::new();
+        let reloaded = match &self.reloader {
+            Some(reloader) => reloader.finished(),
+            None => return index_counts,
+        };
+        for reloaded in reloaded {
+            match reloaded {
+                Reloaded::Model { path, meshes } => {
+                    let meshes = match meshes {
+                        Ok(meshes) => meshes,
+                        Err(e) => {
+                            warn!("Keeping the old version: {}", e);
+                            continue;
+                        }
+                    };
+                    info!("Reloaded {}", path.display());
+                    let pool = &mut self.meshes;
+                    self.mesh_sources.retain(|&handle, source| {
+                        let entry = match pool.entry_mut(handle) {
+                            Some(entry) => entry,
+                            None => return false, // Released
+                        };
+                        if source.path != path {
+                            return true;
+                        }
+                        let mesh = match meshes.get(source.part) {
+                            Some(mesh) => mesh.clone(),
+                            None => {
+                                warn!("{} no longer has mesh {}", path.display(), source.part);
+                                return true;
+                            }
+                        };
+                        entry.asset = mesh;
+                        if let Some(gpu) = entry.gpu {
+                            backend.update_mesh(gpu, &entry.asset);
+                            index_counts.insert(gpu.0, entry.asset.index_count);
+                        }
+                        true
+                    });
+                }
+                Reloaded::Texture { path, texture } => {
+                    let texture = match texture {
+                        Ok(texture) => texture,
+                        Err(e) => {
+                            warn!("Keeping the old version: {}", e);
+                            continue;
+                        }
+                    };
+                    info!("Reloaded {}", path.display());
+                    let handle = self
+                        .textures
+                        .by_source
+                        .get(&*path.to_string_lossy())
+                        .copied();
+                    if let Some(entry) = handle.and_then(|handle| self.textures.entry_mut(handle)) {
+                        entry.asset = texture;
+                        if let Some(gpu) = entry.gpu {
+                            backend.update_texture(gpu, &entry.asset.image);
+                        }
+                    }
+                }
+            }
+        }
+        index_counts
+    }
+
+    // Create the GPU objects of the meshes and textures added since the last call. The render loop
+    // does this before drawing every frame, and applications call it when they need the objects
+    // right away, e.g. for building scene nodes from the meshes' VAOs.
+    pub fn upload(&mut self, backend: &mut dyn Backend) {
+        for handle in std::mem::take(&mut self.meshes.pending) {
+            if let Some(entry) = self.meshes.entry_mut(handle) {
+                entry.gpu = Some(create_mesh(backend, entry));
+            }
+        }
+        for handle in std::mem::take(&mut self.textures.pending) {
+            if let Some(entry) = self.textures.entry_mut(handle) {
+                entry.gpu = Some(create_texture(backend, entry));
+            }
+        }
+    }
+
+    pub fn get<T: Asset>(&self, handle: Handle<T>) -> Option<&T> {
+        T::pool(self).entry(handle).map(|entry| &entry.asset)
+    }
+
+    // The uploaded meshes by VAO, for finding the meshes scene nodes draw
+    pub fn meshes_by_vao(&self) -> HashMap<u32, &Mesh> {
+        self.meshes
+            .entries()
+            .filter_map(|entry| Some((entry.gpu?.0, &entry.asset)))
+            .collect()
+    }
+
+    // The GPU object of an asset, if it has been uploaded
+    pub fn gpu<T: Asset>(&self, handle: Handle<T>) -> Option<T::Gpu> {
+        T::pool(self).entry(handle)?.gpu
+    }
+
+    // The VAO of a mesh for a scene node, with an id of 0 (nothing to draw) if it hasn't been
+    // uploaded yet
+    pub fn vao(&self, mesh: Handle<Mesh>) -> Vao {
+        Vao {
+            id: self.gpu(mesh).map_or(0, |mesh| mesh.0),
+            index_count: self.get(mesh).map_or(0, |mesh| mesh.index_count),
+        }
+    }
+
+    // Take another reference to an asset, so it stays loaded until released once more
+    pub fn acquire<T: Asset>(&mut self, handle: Handle<T>) {
+        if let Some(slot) = T::pool_mut(self).slot_mut(handle) {
+            slot.refs += 1;
+        }
+    }
+
+    // Drop a reference to an asset, deleting it along with its GPU object once unused
+    pub fn release<T: Asset>(&mut self, backend: &mut dyn Backend, handle: Handle<T>) {
+        if let Some(gpu) = T::pool_mut(self)
+            .release(handle)
+            .and_then(|entry| entry.gpu)
+        {
+            T::delete(gpu, backend);
+        }
+    }
+
+    // The context was recreated, so every GPU object is gone. Upload everything again, returning
+    // which new VAO replaces which old one for remapping scene nodes.
+    pub fn reupload(
+        &mut self,
+        backend: &mut dyn Backend,
+    ) -> Result<HashMap<u32, u32>, ShaderError> {
+        let mut vao_ids = HashMap::new();
+        for entry in self.meshes.entries_mut() {
+            let new_mesh = create_mesh(backend, entry);
+            if let Some(old_mesh) = entry.gpu.replace(new_mesh) {
+                vao_ids.insert(old_mesh.0, new_mesh.0);
+            }
+        }
+        self.meshes.pending.clear();
+        for entry in self.textures.entries_mut() {
+            entry.gpu = Some(create_texture(backend, entry));
+        }
+        self.textures.pending.clear();
+        for entry in self.pipelines.entries_mut() {
+            entry.gpu = Some(backend.create_pipeline(&entry.asset.name)?);
+        }
+        Ok(vao_ids)
+    }
+
+    // Bring the copies of the meshes in another context up to date, e.g. the debug view's, by
+    // uploading new meshes and deleting released ones. `vao_ids` maps VAOs in the main context to
+    // their copies.
+    pub fn sync_meshes(&self, backend: &mut dyn Backend, vao_ids: &mut HashMap<u32, u32>) {
+        let mut uploaded = HashSet::new();
+        for entry in self.meshes.entries() {
+            if let Some(mesh) = entry.gpu {
+                uploaded.insert(mesh.0);
+                vao_ids
+                    .entry(mesh.0)
+                    .or_insert_with(|| create_mesh(backend, entry).0);
+            }
+        }
+        vao_ids.retain(|vao, copy| {
+            let keep = uploaded.contains(vao);
+            if !keep {
+                backend.delete_mesh(MeshHandle(*copy));
+            }
+            keep
+        });
+    }
+}
+
+// Upload a mesh, naming it after its source, if any
+fn create_mesh(backend: &mut dyn Backend, entry: &Entry<Mesh>) -> MeshHandle {
+    let mesh = backend.create_mesh(&entry.asset);
+    if let Some(source) = &entry.source {
+        backend.label_mesh(mesh, source);
+    }
+    mesh
+}
+
+fn create_texture(backend: &mut dyn Backend, entry: &Entry<Texture>) -> TextureHandle {
+    let texture = backend.create_texture(&entry.asset.image);
+    if let Some(source) = &entry.source {
+        backend.label_texture(texture, source);
+    }
+    texture
+}
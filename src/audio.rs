@@ -0,0 +1,548 @@
+// Sounds placed in the scene: quieter the further they are from the camera, and panned between the
+// left and right speakers by where they are relative to it.
+//
+// Nodes make a sound by having a `Sound`, looped for as long as they are in the scene graph. Every
+// frame `SpatialAudio::update_scene` walks the graph like the renderer does, starting a voice for
+// each node that began to sound, stopping those of nodes that stopped or went away, and setting
+// the gains of the rest from where their nodes are now. Voices are played by an `AudioOutput`,
+// which only has to loop clips at a speed with a gain per speaker. A `Mixer` loops them into one
+// stereo stream, which `DeviceOutput` plays through the audio device with rodio when built with
+// the `audio` feature. `SilentOutput` plays nothing, for running without an audio device, e.g.
+// headless.
+use crate::scene_graph::SceneNode;
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_4, TAU};
+use std::sync::{Arc, Mutex};
+
+pub const DEVICE_SAMPLE_RATE: u32 = 48000;
+// Stereo frames mixed at a time, over which changes in gain fade in
+#[cfg(feature = "audio")]
+const MIX_FRAMES: usize = 512;
+
+// Mono samples, -1 to 1, looped seamlessly from the last back to the first
+pub struct Clip {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+impl Clip {
+    // The whop of rotor blades slapping the air `blade_passes` times a second, over the whine of
+    // the engine, a second long. Played faster or slower as the rotor speeds up or slows down.
+    pub fn rotor(sample_rate: u32, blade_passes: u32) -> Clip {
+        let mut noise_state = 0x2545_f491_u32;
+        let mut noise = move || {
+            noise_state ^= noise_state << 13;
+            noise_state ^= noise_state >> 17;
+            noise_state ^= noise_state << 5;
+            noise_state as f32 / u32::MAX as f32 * 2.0 - 1.0
+        };
+        let period = sample_rate as f32 / blade_passes.max(1) as f32;
+        let mut low_passed = 0.0;
+        let samples = (0..sample_rate)
+            .map(|i| {
+                let time = i as f32 / sample_rate as f32;
+                // Each pass thumps and dies down well before the next, so the loop joins up
+                let since_pass = (i as f32 % period) / period;
+                let thump = (-since_pass * 12.0).exp();
+                low_passed += (noise() - low_passed) * 0.08;
+                let whine = (TAU * 440.0 * time).sin() * 0.04;
+                (low_passed * 2.5 * thump + (TAU * 55.0 * time).sin() * 0.3 * thump + whine)
+                    .clamp(-1.0, 1.0)
+            })
+            .collect();
+        Clip {
+            samples,
+            sample_rate,
+        }
+    }
+}
+
+// What a node sounds like
+#[derive(Clone)]
+pub struct Sound {
+    pub clip: Arc<Clip>,
+    pub volume: f32, // Gain right next to the node, 0 for silent
+    pub speed: f32,  // How fast the clip is played, 1 for as it was recorded
+}
+
+// How loud a voice is in each speaker
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gains {
+    pub left: f32,
+    pub right: f32,
+}
+
+pub type VoiceId = u64;
+
+// Where sounds are played, like the audio device
+pub trait AudioOutput {
+    // Start looping `clip`, silent until its gains are set
+    fn play(&mut self, clip: &Arc<Clip>) -> VoiceId;
+    fn set(&mut self, voice: VoiceId, gains: Gains, speed: f32);
+    fn stop(&mut self, voice: VoiceId);
+}
+
+// Plays nothing, but keeps track of the voices as if it did
+#[derive(Default)]
+pub struct SilentOutput {
+    next_voice: VoiceId,
+    pub voices: HashMap<VoiceId, (Gains, f32)>,
+}
+
+impl AudioOutput for SilentOutput {
+    fn play(&mut self, _: &Arc<Clip>) -> VoiceId {
+        self.next_voice += 1;
+        let silent = Gains {
+            left: 0.0,
+            right: 0.0,
+        };
+        self.voices.insert(self.next_voice, (silent, 1.0));
+        self.next_voice
+    }
+
+    fn set(&mut self, voice: VoiceId, gains: Gains, speed: f32) {
+        self.voices.insert(voice, (gains, speed));
+    }
+
+    fn stop(&mut self, voice: VoiceId) {
+        self.voices.remove(&voice);
+    }
+}
+
+struct Voice {
+    clip: Arc<Clip>,
+    position: f64, // In samples of the clip, between two of them when it is played at another rate
+    gains: Gains,
+    speed: f32,
+    heard: Gains, // Gains at the end of the last mix, faded to `gains` over the next one
+}
+
+// Loops voices into one stereo stream at `sample_rate`
+pub struct Mixer {
+    pub sample_rate: u32,
+    next_voice: VoiceId,
+    voices: HashMap<VoiceId, Voice>,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: u32) -> Mixer {
+        Mixer {
+            sample_rate,
+            next_voice: 0,
+            voices: HashMap::new(),
+        }
+    }
+
+    // Fill `out` with interleaved left and right samples. New gains fade in over the length of
+    // `out` so that changing them between mixes doesn't click.
+    pub fn mix(&mut self, out: &mut [f32]) {
+        out.iter_mut().for_each(|sample| *sample = 0.0);
+        let frames = out.len() / 2;
+        for voice in self.voices.values_mut() {
+            let samples = &voice.clip.samples;
+            if samples.is_empty() {
+                continue;
+            }
+            let step = voice.speed as f64 * voice.clip.sample_rate as f64 / self.sample_rate as f64;
+            for (i, frame) in out.chunks_exact_mut(2).enumerate() {
+                let i0 = voice.position as usize;
+                let i1 = (i0 + 1) % samples.len();
+                let t = (voice.position - i0 as f64) as f32;
+                let sample = samples[i0] + (samples[i1] - samples[i0]) * t;
+
+                let fade = (i + 1) as f32 / frames as f32;
+                frame[0] +=
+                    sample * (voice.heard.left + (voice.gains.left - voice.heard.left) * fade);
+                frame[1] +=
+                    sample * (voice.heard.right + (voice.gains.right - voice.heard.right) * fade);
+                voice.position = (voice.position + step) % samples.len() as f64;
+            }
+            voice.heard = voice.gains;
+        }
+        out.iter_mut()
+            .for_each(|sample| *sample = sample.clamp(-1.0, 1.0));
+    }
+}
+
+impl AudioOutput for Mixer {
+    fn play(&mut self, clip: &Arc<Clip>) -> VoiceId {
+        self.next_voice += 1;
+        let silent = Gains {
+            left: 0.0,
+            right: 0.0,
+        };
+        let voice = Voice {
+            clip: clip.clone(),
+            position: 0.0,
+            gains: silent,
+            speed: 1.0,
+            heard: silent,
+        };
+        self.voices.insert(self.next_voice, voice);
+        self.next_voice
+    }
+
+    fn set(&mut self, voice: VoiceId, gains: Gains, speed: f32) {
+        if let Some(voice) = self.voices.get_mut(&voice) {
+            voice.gains = gains;
+            voice.speed = speed;
+        }
+    }
+
+    fn stop(&mut self, voice: VoiceId) {
+        self.voices.remove(&voice);
+    }
+}
+
+// Plays through the default audio device. The device pulls the stream from a `Mixer` that the
+// voices are started, set and stopped on, and rodio converts it to the device's sample rate.
+pub struct DeviceOutput {
+    mixer: Arc<Mutex<Mixer>>,
+    #[cfg(feature = "audio")]
+    _sink: rodio::MixerDeviceSink, // Playing for as long as it is kept
+}
+
+impl DeviceOutput {
+    #[cfg(feature = "audio")]
+    pub fn open() -> Result<DeviceOutput, String> {
+        let mut sink = rodio::DeviceSinkBuilder::open_default_sink().map_err(|e| e.to_string())?;
+        sink.log_on_drop(false);
+        let mixer = Arc::new(Mutex::new(Mixer::new(DEVICE_SAMPLE_RATE)));
+        sink.mixer().add(MixerSource {
+            mixer: mixer.clone(),
+            mixed: vec![0.0; MIX_FRAMES * 2],
+            next: MIX_FRAMES * 2,
+        });
+        Ok(DeviceOutput { mixer, _sink: sink })
+    }
+
+    #[cfg(not(feature = "audio"))]
+    pub fn open() -> Result<DeviceOutput, String> {
+        Err("built without the `audio` feature".to_string())
+    }
+}
+
+impl AudioOutput for DeviceOutput {
+    fn play(&mut self, clip: &Arc<Clip>) -> VoiceId {
+        self.mixer.lock().unwrap().play(clip)
+    }
+
+    fn set(&mut self, voice: VoiceId, gains: Gains, speed: f32) {
+        self.mixer.lock().unwrap().set(voice, gains, speed);
+    }
+
+    fn stop(&mut self, voice: VoiceId) {
+        self.mixer.lock().unwrap().stop(voice);
+    }
+}
+
+// The endless stream of a `Mixer`, mixed `MIX_FRAMES` at a time on rodio's thread
+#[cfg(feature = "audio")]
+struct MixerSource {
+    mixer: Arc<Mutex<Mixer>>,
+    mixed: Vec<f32>,
+    next: usize, // The sample of `mixed` to play next
+}
+
+#[cfg(feature = "audio")]
+impl Iterator for MixerSource {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<rodio::Sample> {
+        if self.next == self.mixed.len() {
+            self.mixer.lock().unwrap().mix(&mut self.mixed);
+            self.next = 0;
+        }
+        self.next += 1;
+        Some(self.mixed[self.next - 1] as rodio::Sample)
+    }
+}
+
+#[cfg(feature = "audio")]
+impl rodio::Source for MixerSource {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        rodio::ChannelCount::new(2).unwrap()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        rodio::SampleRate::new(DEVICE_SAMPLE_RATE).unwrap()
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+// How sounds fade with distance. Up to `reference_distance` they are at full volume, then the gain
+// falls off inversely with the distance, faster the larger `rolloff` is, until they are cut off at
+// `max_distance`.
+#[derive(Clone, Copy, Debug)]
+pub struct Attenuation {
+    pub reference_distance: f32,
+    pub rolloff: f32,
+    pub max_distance: f32,
+}
+
+impl Default for Attenuation {
+    fn default() -> Attenuation {
+        Attenuation {
+            reference_distance: 10.0,
+            rolloff: 1.0,
+            max_distance: 500.0,
+        }
+    }
+}
+
+impl Attenuation {
+    pub fn gain(&self, distance: f32) -> f32 {
+        if distance >= self.max_distance {
+            return 0.0;
+        }
+        let beyond = (distance - self.reference_distance).max(0.0);
+        self.reference_distance / (self.reference_distance + self.rolloff * beyond)
+    }
+}
+
+// The gains of a sound at `source` heard from a camera at `listener`, whose right is `right`,
+// panned with equal power so it is as loud in total wherever it is around the camera
+pub fn spatialize(
+    source: &glm::Vec3,
+    listener: &glm::Vec3,
+    right: &glm::Vec3,
+    attenuation: &Attenuation,
+) -> Gains {
+    let offset = source - listener;
+    let distance = glm::length(&offset);
+    let pan = if distance > 1e-4 {
+        glm::dot(&(offset / distance), &glm::normalize(right)).clamp(-1.0, 1.0)
+    } else {
+        0.0
+    };
+    let gain = attenuation.gain(distance);
+    let angle = (pan + 1.0) * FRAC_PI_4;
+    Gains {
+        left: gain * angle.cos(),
+        right: gain * angle.sin(),
+    }
+}
+
+// Keeps a voice playing for every node with a sound
+pub struct SpatialAudio {
+    pub output: Box<dyn AudioOutput>,
+    pub attenuation: Attenuation,
+    pub volume: f32, // Of everything, 0 to mute
+    voices: HashMap<*const SceneNode, VoiceId>,
+}
+
+impl SpatialAudio {
+    pub fn new(output: Box<dyn AudioOutput>) -> SpatialAudio {
+        SpatialAudio {
+            output,
+            attenuation: Attenuation::default(),
+            volume: 1.0,
+            voices: HashMap::new(),
+        }
+    }
+
+    pub fn voice_count(&self) -> usize {
+        self.voices.len()
+    }
+
+    // Bring the voices up to date with the nodes below `root`, heard from the camera of
+    // `view_matrix`
+    pub fn update_scene(&mut self, root: &SceneNode, view_matrix: &glm::Mat4) {
+        let camera = glm::inverse(view_matrix);
+        let listener = camera.column(3).xyz();
+        let right = camera.column(0).xyz();
+        let mut sounding = HashMap::new();
+        self.update_node(root, &glm::identity(), &listener, &right, &mut sounding);
+        for (_, voice) in self.voices.drain() {
+            self.output.stop(voice);
+        }
+        self.voices = sounding;
+    }
+
+    fn update_node(
+        &mut self,
+        node: &SceneNode,
+        transformation_so_far: &glm::Mat4,
+        listener: &glm::Vec3,
+        right: &glm::Vec3,
+        sounding: &mut HashMap<*const SceneNode, VoiceId>,
+    ) {
+        let transform = transformation_so_far * node.local_transform();
+        if let Some(sound) = &node.sound {
+            let key = node as *const SceneNode;
+            let voice = match self.voices.remove(&key) {
+                Some(voice) => voice,
+                None => self.output.play(&sound.clip),
+            };
+            let position = transform.column(3).xyz();
+            let gains = spatialize(&position, listener, right, &self.attenuation);
+            let volume = sound.volume * self.volume;
+            let gains = Gains {
+                left: gains.left * volume,
+                right: gains.right * volume,
+            };
+            self.output.set(voice, gains, sound.speed);
+            sounding.insert(key, voice);
+        }
+        for &child in &node.children {
+            self.update_node(unsafe { &*child }, &transform, listener, right, sounding);
+        }
+    }
+}
+
+impl Drop for SpatialAudio {
+    fn drop(&mut self) {
+        for (_, voice) in self.voices.drain() {
+            self.output.stop(voice);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn silent() -> Arc<Clip> {
+        Arc::new(Clip {
+            samples: vec![0.0; 4],
+            sample_rate: 4,
+        })
+    }
+
+    #[test]
+    fn sounds_fade_with_distance_and_pan_to_their_side() {
+        let attenuation = Attenuation::default();
+        assert_eq!(attenuation.gain(5.0), 1.0);
+        assert!((attenuation.gain(20.0) - 0.5).abs() < 1e-6);
+        assert_eq!(attenuation.gain(600.0), 0.0);
+
+        let (listener, right) = (glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 0.0, 0.0));
+        let to_the_right = spatialize(&glm::vec3(5.0, 0.0, 0.0), &listener, &right, &attenuation);
+        assert!(to_the_right.left.abs() < 1e-6 && (to_the_right.right - 1.0).abs() < 1e-6);
+        let ahead = spatialize(&glm::vec3(0.0, 0.0, -5.0), &listener, &right, &attenuation);
+        assert!((ahead.left - ahead.right).abs() < 1e-6);
+        assert!((ahead.left.powi(2) + ahead.right.powi(2) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn rotor_clips_loop_a_second() {
+        let clip = Clip::rotor(8000, 6);
+        assert_eq!(clip.samples.len(), 8000);
+        assert!(clip
+            .samples
+            .iter()
+            .all(|sample| (-1.0..=1.0).contains(sample)));
+        assert!(clip.samples.iter().any(|sample| sample.abs() > 0.1));
+    }
+
+    #[test]
+    fn voices_follow_the_nodes_with_sounds() {
+        let mut root = SceneNode::new();
+        let mut emitter = SceneNode::new();
+        emitter.position = glm::vec3(-20.0, 0.0, 0.0);
+        emitter.sound = Some(Sound {
+            clip: silent(),
+            volume: 0.5,
+            speed: 2.0,
+        });
+        root.add_child(&emitter);
+
+        let mut audio = SpatialAudio::new(Box::new(SilentOutput::default()));
+        audio.update_scene(&root, &glm::identity());
+        assert_eq!(audio.voice_count(), 1);
+        audio.update_scene(&root, &glm::identity());
+        assert_eq!(audio.voice_count(), 1);
+
+        emitter.sound = None;
+        audio.update_scene(&root, &glm::identity());
+        assert_eq!(audio.voice_count(), 0);
+    }
+
+    #[test]
+    fn mixed_voices_loop_at_their_speed_with_their_gains() {
+        let ramp = Arc::new(Clip {
+            samples: vec![0.0, 0.25, 0.5, 0.75],
+            sample_rate: 4,
+        });
+        let mut mixer = Mixer::new(4);
+        let voice = mixer.play(&ramp);
+        let gains = Gains {
+            left: 1.0,
+            right: 0.5,
+        };
+        mixer.set(voice, gains, 0.5);
+
+        // The first mix fades in from silence, the next plays at the full gains. At half speed
+        // every other sample is halfway between two of the clip's.
+        let mut out = vec![0.0; 8];
+        mixer.mix(&mut out);
+        assert_eq!(out[6..], [0.375, 0.1875]);
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.5, 0.25, 0.625, 0.3125, 0.75, 0.375, 0.375, 0.1875]);
+
+        // Twice as long as the clip later, it starts over
+        mixer.mix(&mut out);
+        assert_eq!(out[..4], [0.0, 0.0, 0.125, 0.0625]);
+
+        mixer.stop(voice);
+        mixer.mix(&mut out);
+        assert!(out.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn mixed_voices_add_up_without_clipping_past_full_scale() {
+        let loud = Arc::new(Clip {
+            samples: vec![0.8],
+            sample_rate: DEVICE_SAMPLE_RATE,
+        });
+        let mut mixer = Mixer::new(DEVICE_SAMPLE_RATE);
+        let full = Gains {
+            left: 1.0,
+            right: 0.5,
+        };
+        for _ in 0..2 {
+            let voice = mixer.play(&loud);
+            mixer.set(voice, full, 1.0);
+        }
+        let mut out = vec![0.0; 4];
+        mixer.mix(&mut out);
+        mixer.mix(&mut out);
+        assert_eq!(out, [1.0, 0.8, 1.0, 0.8]);
+    }
+
+    #[cfg(feature = "audio")]
+    #[test]
+    fn the_device_is_fed_the_mixed_stream_a_block_at_a_time() {
+        let mixer = Arc::new(Mutex::new(Mixer::new(DEVICE_SAMPLE_RATE)));
+        let mut source = MixerSource {
+            mixer: mixer.clone(),
+            mixed: vec![0.0; 4],
+            next: 4,
+        };
+        let half = Arc::new(Clip {
+            samples: vec![0.5],
+            sample_rate: DEVICE_SAMPLE_RATE,
+        });
+        let voice = mixer.lock().unwrap().play(&half);
+        let set = |gain| {
+            let gains = Gains {
+                left: gain,
+                right: gain,
+            };
+            mixer.lock().unwrap().set(voice, gains, 1.0)
+        };
+        set(1.0);
+        let mut next = || source.next().unwrap();
+        assert_eq!([next(), next()], [0.25, 0.25]);
+        // Gains set halfway through a block are heard from the next one on, fading over it
+        set(0.0);
+        assert_eq!([next(), next(), next(), next()], [0.5, 0.5, 0.25, 0.25]);
+    }
+}
@@ -0,0 +1,612 @@
+// The OpenGL backend. All methods must be called on the thread where the context is current.
+use super::{
+    Backend, DrawCall, MemoryUsage, MeshHandle, PipelineHandle, RenderStats, StatsCounter,
+    TextureHandle,
+};
+use crate::error::ShaderError;
+use crate::frame_dump::DrawRecord;
+use crate::mesh::Mesh;
+use crate::renderer::Vao;
+use crate::shader;
+use crate::skeleton;
+use crate::util;
+use std::collections::{HashMap, VecDeque};
+
+// Frames whose pass timings the GPU may still be working on. Older ones are given up on.
+const MAX_PENDING_FRAMES: usize = 4;
+
+// Where draws bind their normal map, clear of the shadow maps', see `shadow`
+pub const NORMAL_MAP_TEXTURE_UNIT: u32 = 3;
+
+#[derive(Default)]
+pub struct GlBackend {
+    pipelines: Vec<Option<Pipeline>>, // None once deleted
+    current_pipeline: Option<PipelineHandle>,
+    stats: StatsCounter,
+    // Timestamp queries at the start and end of this frame's passes, and those of earlier frames
+    // not yet read back. The latest GPU time of each pass is kept by name.
+    pass_queries: Vec<PassQueries>,
+    pending_queries: VecDeque<Vec<PassQueries>>,
+    free_queries: Vec<u32>,
+    gpu_times: HashMap<String, f32>,
+    // Bytes held by each mesh and texture, by name
+    mesh_bytes: HashMap<u32, u64>,
+    texture_bytes: HashMap<u32, u64>,
+    video_memory_info: util::VideoMemoryInfo,
+    // Draws recorded since `record_draws`
+    recorded_draws: Option<Vec<DrawRecord>>,
+}
+
+struct PassQueries {
+    name: String,
+    start: u32,
+    end: u32,
+}
+
+// A shader program, along with the locations of the uniforms set for every draw, so they aren't
+// looked up by name each time
+struct Pipeline {
+    name: String,
+    shader: shader::Shader,
+    transform_location: i32,
+    model_location: i32,
+    previous_model_location: i32,
+    highlight_location: i32,
+    opacity_location: i32,
+    skinned_location: i32,
+    joints_location: i32,
+    normal_mapped_location: i32,
+    material: MaterialLocations,
+}
+
+// Where the members of the `material` uniform are, see `material::Material`
+struct MaterialLocations {
+    physically_based: i32,
+    albedo: i32,
+    metallic: i32,
+    roughness: i32,
+    emissive: i32,
+    reflectivity: i32,
+}
+
+impl GlBackend {
+    pub fn new(features: &util::GlFeatures) -> GlBackend {
+        GlBackend {
+            pipelines: vec![],
+            current_pipeline: None,
+            stats: StatsCounter::default(),
+            pass_queries: vec![],
+            pending_queries: VecDeque::new(),
+            free_queries: vec![],
+            gpu_times: HashMap::new(),
+            mesh_bytes: HashMap::new(),
+            texture_bytes: HashMap::new(),
+            video_memory_info: features.video_memory_info,
+            recorded_draws: None,
+        }
+    }
+
+    // The shader program of a pipeline, for setting uniforms the backend doesn't know about
+    pub fn program_id(&self, pipeline: PipelineHandle) -> u32 {
+        self.pipeline(pipeline).shader.program_id
+    }
+
+    // The uniforms a pipeline's shaders use, by name
+    pub fn uniforms(&self, pipeline: PipelineHandle) -> &HashMap<String, shader::ActiveUniform> {
+        self.pipeline(pipeline).shader.uniforms()
+    }
+
+    // Draw `count` vertices of `vao` as lines with the current pipeline. Lines aren't part of
+    // `Backend`, as only debugging aids like `debug_lines` draw them.
+    pub fn draw_lines(&mut self, vao: u32, count: i32, transform: &glm::Mat4) {
+        self.draw_arrays(gl::LINES, vao, count, transform);
+    }
+
+    // Draw `count` vertices of `vao` as unindexed triangles with the current pipeline, like
+    // `draw_lines`, e.g. for text
+    pub fn draw_triangles(&mut self, vao: u32, count: i32, transform: &glm::Mat4) {
+        self.draw_arrays(gl::TRIANGLES, vao, count, transform);
+    }
+
+    fn draw_arrays(&mut self, mode: u32, vao: u32, count: i32, transform: &glm::Mat4) {
+        let pipeline = self.pipeline(self.current_pipeline.expect("No pipeline set"));
+        let model: glm::Mat4 = glm::identity();
+        unsafe {
+            gl::UniformMatrix4fv(
+                pipeline.transform_location,
+                1,
+                gl::FALSE,
+                transform.as_ptr(),
+            );
+            gl::UniformMatrix4fv(pipeline.model_location, 1, gl::FALSE, model.as_ptr());
+            gl::UniformMatrix4fv(
+                pipeline.previous_model_location,
+                1,
+                gl::FALSE,
+                model.as_ptr(),
+            );
+            gl::Uniform1f(pipeline.highlight_location, 0.0);
+            gl::Uniform1f(pipeline.opacity_location, 1.0);
+            gl::Uniform1i(pipeline.skinned_location, 0);
+            gl::BindVertexArray(vao);
+            gl::DrawArrays(mode, 0, count);
+            gl::BindVertexArray(0);
+        }
+        let pipeline = self.current_pipeline.expect("No pipeline set");
+        self.stats.draw(pipeline, MeshHandle(vao), count);
+        let mode = if mode == gl::LINES {
+            "lines"
+        } else {
+            "unindexed triangles"
+        };
+        self.record_draw(mode, vao, count, &model);
+    }
+
+    // Keep a record of every draw from now on, until `take_recorded_draws`, see `frame_dump`
+    pub fn record_draws(&mut self) {
+        self.recorded_draws = Some(vec![]);
+    }
+
+    // The draws made since `record_draws`, which stops recording
+    pub fn take_recorded_draws(&mut self) -> Vec<DrawRecord> {
+        self.recorded_draws.take().unwrap_or_default()
+    }
+
+    // Record a draw with the current pipeline, reading back the uniforms it was made with
+    fn record_draw(&mut self, mode: &'static str, vao: u32, count: i32, model: &glm::Mat4) {
+        if self.recorded_draws.is_none() {
+            return;
+        }
+        let pipeline = self.pipeline(self.current_pipeline.expect("No pipeline set"));
+        let program = pipeline.shader.program_id;
+        let mut uniforms: Vec<(String, Vec<f32>)> = pipeline
+            .shader
+            .uniforms()
+            .iter()
+            // Arrays are listed under their bare name as well
+            .filter(|(name, _)| !name.ends_with("[0]"))
+            .map(|(name, uniform)| {
+                let mut values = [0.0; 16];
+                unsafe { gl::GetUniformfv(program, uniform.location, values.as_mut_ptr()) };
+                (name.clone(), values[..components(uniform.kind)].to_vec())
+            })
+            .collect();
+        uniforms.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let record = DrawRecord {
+            group: self.stats.groups().join("/"),
+            pipeline: pipeline.name.clone(),
+            program,
+            vao,
+            mode,
+            count,
+            uniforms,
+            node: None,
+            world: *model,
+        };
+        if let Some(recorded_draws) = &mut self.recorded_draws {
+            recorded_draws.push(record);
+        }
+    }
+
+    // A timestamp query written once the GPU gets to it
+    unsafe fn timestamp(&mut self) -> u32 {
+        let query = self.free_queries.pop().unwrap_or_else(|| {
+            let mut query = 0;
+            gl::GenQueries(1, &mut query);
+            query
+        });
+        gl::QueryCounter(query, gl::TIMESTAMP);
+        query
+    }
+
+    // Read back the pass timings of the frames the GPU has finished, without waiting for the rest
+    unsafe fn read_gpu_times(&mut self) {
+        while let Some(frame) = self.pending_queries.front() {
+            let finished = frame.last().is_none_or(|last| {
+                let mut available = 0;
+                gl::GetQueryObjectiv(last.end, gl::QUERY_RESULT_AVAILABLE, &mut available);
+                available != 0
+            });
+            if !finished {
+                break;
+            }
+            for pass in self.pending_queries.pop_front().unwrap_or_default() {
+                let (mut start, mut end) = (0, 0);
+                gl::GetQueryObjectui64v(pass.start, gl::QUERY_RESULT, &mut start);
+                gl::GetQueryObjectui64v(pass.end, gl::QUERY_RESULT, &mut end);
+                let nanoseconds = end.saturating_sub(start);
+                self.gpu_times.insert(pass.name, nanoseconds as f32 * 1e-9);
+                self.free_queries.extend([pass.start, pass.end]);
+            }
+        }
+    }
+
+    fn pipeline(&self, pipeline: PipelineHandle) -> &Pipeline {
+        self.pipelines[pipeline.0]
+            .as_ref()
+            .expect("Pipeline has been deleted")
+    }
+}
+
+// The shader files the pipeline named `name` is compiled from: a vertex and a fragment shader, and
+// a geometry shader in between if there is one
+pub fn shader_paths(name: &str) -> Vec<String> {
+    let geometry_path = format!("shaders/{}.geom", name);
+    let mut paths = vec![format!("shaders/{}.vert", name)];
+    if std::path::Path::new(&geometry_path).exists() {
+        paths.push(geometry_path);
+    }
+    paths.push(format!("shaders/{}.frag", name));
+    paths
+}
+
+impl Backend for GlBackend {
+    fn create_mesh(&mut self, mesh: &Mesh) -> MeshHandle {
+        // Only fails in debug builds, on a mesh OpenGL can't take, which is a bug to fix right away
+        let vao = unsafe { Vao::from_mesh(mesh) }
+            .unwrap_or_else(|e| panic!("Failed to upload a mesh: {}", e));
+        self.mesh_bytes.insert(vao.id, super::mesh_bytes(mesh));
+        MeshHandle(vao.id)
+    }
+
+    // The VAO keeps its buffers bound, so they are filled again in place. The optional attributes
+    // are only updated if the VAO was created with them.
+    fn update_mesh(&mut self, mesh: MeshHandle, data: &Mesh) {
+        let [indices, vertices, colors, normals, uvs, tangents, joints, weights] =
+            mesh_buffers(mesh);
+        self.mesh_bytes.insert(mesh.0, super::mesh_bytes(data));
+        unsafe {
+            gl::BindVertexArray(mesh.0);
+            buffer_data(gl::ELEMENT_ARRAY_BUFFER, indices, &data.indices);
+            buffer_data(gl::ARRAY_BUFFER, vertices, &data.vertices);
+            buffer_data(gl::ARRAY_BUFFER, colors, &data.colors);
+            buffer_data(gl::ARRAY_BUFFER, normals, &data.normals);
+            if uvs != 0 {
+                buffer_data(gl::ARRAY_BUFFER, uvs, &data.uvs);
+            }
+            if tangents != 0 {
+                buffer_data(gl::ARRAY_BUFFER, tangents, &data.tangents);
+            }
+            if joints != 0 {
+                buffer_data(gl::ARRAY_BUFFER, joints, &data.joints);
+            }
+            if weights != 0 {
+                buffer_data(gl::ARRAY_BUFFER, weights, &data.weights);
+            }
+            gl::BindVertexArray(0);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+    }
+
+    fn delete_mesh(&mut self, mesh: MeshHandle) {
+        let buffers = mesh_buffers(mesh);
+        self.mesh_bytes.remove(&mesh.0);
+        unsafe {
+            gl::DeleteBuffers(buffers.len() as i32, buffers.as_ptr());
+            gl::DeleteVertexArrays(1, &mesh.0);
+        }
+    }
+
+    fn create_texture(&mut self, image: &image::RgbaImage) -> TextureHandle {
+        let mut texture = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            texture_image(image);
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MIN_FILTER,
+                gl::LINEAR_MIPMAP_LINEAR as i32,
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+        self.texture_bytes
+            .insert(texture, super::texture_bytes(image.width(), image.height()));
+        TextureHandle(texture)
+    }
+
+    fn update_texture(&mut self, texture: TextureHandle, image: &image::RgbaImage) {
+        self.texture_bytes.insert(
+            texture.0,
+            super::texture_bytes(image.width(), image.height()),
+        );
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, texture.0);
+            texture_image(image);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+    }
+
+    fn delete_texture(&mut self, texture: TextureHandle) {
+        self.texture_bytes.remove(&texture.0);
+        unsafe { gl::DeleteTextures(1, &texture.0) };
+    }
+
+    fn create_pipeline(&mut self, name: &str) -> Result<PipelineHandle, ShaderError> {
+        let mut builder = unsafe { shader::ShaderBuilder::new() };
+        for path in shader_paths(name) {
+            builder = unsafe { builder.attach_file(&path)? };
+        }
+        let shader = unsafe { builder.link()? };
+        unsafe {
+            util::label_object(gl::PROGRAM, shader.program_id, name);
+            let normal_map = shader.get_uniform_location("normalMap");
+            if normal_map != -1 {
+                gl::ProgramUniform1i(
+                    shader.program_id,
+                    normal_map,
+                    NORMAL_MAP_TEXTURE_UNIT as i32,
+                );
+            }
+        }
+        self.pipelines.push(Some(Pipeline {
+            name: name.to_string(),
+            transform_location: shader.get_uniform_location("transformMatrix"),
+            model_location: shader.get_uniform_location("modelMatrix"),
+            previous_model_location: shader.get_uniform_location("previousModelMatrix"),
+            highlight_location: shader.get_uniform_location("highlight"),
+            opacity_location: shader.get_uniform_location("opacity"),
+            skinned_location: shader.get_uniform_location("skinned"),
+            joints_location: shader.get_uniform_location("jointMatrices"),
+            normal_mapped_location: shader.get_uniform_location("normalMapped"),
+            material: MaterialLocations {
+                physically_based: shader.get_uniform_location("physicallyBased"),
+                albedo: shader.get_uniform_location("material.albedo"),
+                metallic: shader.get_uniform_location("material.metallic"),
+                roughness: shader.get_uniform_location("material.roughness"),
+                emissive: shader.get_uniform_location("material.emissive"),
+                reflectivity: shader.get_uniform_location("material.reflectivity"),
+            },
+            shader,
+        }));
+        Ok(PipelineHandle(self.pipelines.len() - 1))
+    }
+
+    fn delete_pipeline(&mut self, pipeline: PipelineHandle) {
+        if let Some(deleted) = self.pipelines[pipeline.0].take() {
+            unsafe { gl::DeleteProgram(deleted.shader.program_id) };
+        }
+        if self.current_pipeline == Some(pipeline) {
+            self.current_pipeline = None;
+        }
+    }
+
+    fn label_mesh(&mut self, mesh: MeshHandle, label: &str) {
+        let buffers = mesh_buffers(mesh);
+        let kinds = [
+            "indices",
+            "positions",
+            "colors",
+            "normals",
+            "uvs",
+            "tangents",
+            "joints",
+            "weights",
+        ];
+        unsafe {
+            util::label_object(gl::VERTEX_ARRAY, mesh.0, label);
+            for (&buffer, kind) in buffers.iter().zip(kinds) {
+                if buffer != 0 {
+                    util::label_object(gl::BUFFER, buffer, &format!("{} {}", label, kind));
+                }
+            }
+        }
+    }
+
+    fn label_texture(&mut self, texture: TextureHandle, label: &str) {
+        unsafe { util::label_object(gl::TEXTURE, texture.0, label) };
+    }
+
+    // Top-level groups are timed on the GPU as well
+    fn push_group(&mut self, name: &str) {
+        unsafe {
+            if self.stats.push_group(name) {
+                let start = self.timestamp();
+                self.pass_queries.push(PassQueries {
+                    name: name.to_string(),
+                    start,
+                    end: 0,
+                });
+            }
+            util::push_debug_group(name);
+        }
+    }
+
+    fn pop_group(&mut self) {
+        unsafe {
+            util::pop_debug_group();
+            if let Some(pass) = self.stats.pop_group() {
+                pass.gpu_time = self.gpu_times.get(&pass.name).copied();
+                let end = self.timestamp();
+                if let Some(queries) = self.pass_queries.last_mut() {
+                    queries.end = end;
+                }
+            }
+        }
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        unsafe { gl::Viewport(0, 0, width as i32, height as i32) };
+    }
+
+    fn begin_frame(&mut self, clear_color: &glm::Vec4) {
+        self.stats.begin_frame();
+        unsafe {
+            self.read_gpu_times();
+            gl::ClearColor(clear_color.x, clear_color.y, clear_color.z, clear_color.w);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+    }
+
+    fn set_pipeline(&mut self, pipeline: PipelineHandle) {
+        unsafe { self.pipeline(pipeline).shader.activate() };
+        self.current_pipeline = Some(pipeline);
+    }
+
+    fn draw(&mut self, call: &DrawCall) {
+        let pipeline = self.pipeline(self.current_pipeline.expect("No pipeline set"));
+        unsafe {
+            gl::UniformMatrix4fv(
+                pipeline.transform_location,
+                1,
+                gl::FALSE,
+                call.transform.as_ptr(),
+            );
+            gl::UniformMatrix4fv(pipeline.model_location, 1, gl::FALSE, call.model.as_ptr());
+            gl::UniformMatrix4fv(
+                pipeline.previous_model_location,
+                1,
+                gl::FALSE,
+                call.previous_model.as_ptr(),
+            );
+            gl::Uniform1f(pipeline.highlight_location, call.highlight);
+            gl::Uniform1f(pipeline.opacity_location, call.opacity);
+            // Skeletons with more joints than the shader has room for are cut short
+            let joint_count = call.joints.len().min(skeleton::MAX_JOINTS);
+            gl::Uniform1i(pipeline.skinned_location, (joint_count > 0) as i32);
+            if joint_count > 0 {
+                gl::UniformMatrix4fv(
+                    pipeline.joints_location,
+                    joint_count as i32,
+                    gl::FALSE,
+                    call.joints.as_ptr() as *const f32,
+                );
+            }
+            gl::Uniform1i(
+                pipeline.normal_mapped_location,
+                call.normal_map.is_some() as i32,
+            );
+            if let Some(normal_map) = call.normal_map {
+                gl::ActiveTexture(gl::TEXTURE0 + NORMAL_MAP_TEXTURE_UNIT);
+                gl::BindTexture(gl::TEXTURE_2D, normal_map.0);
+                gl::ActiveTexture(gl::TEXTURE0);
+            }
+            let locations = &pipeline.material;
+            gl::Uniform1i(locations.physically_based, call.material.is_some() as i32);
+            if let Some(material) = &call.material {
+                gl::Uniform3fv(locations.albedo, 1, material.albedo.as_ptr());
+                gl::Uniform1f(locations.metallic, material.metallic);
+                gl::Uniform1f(locations.roughness, material.roughness);
+                gl::Uniform3fv(locations.emissive, 1, material.emissive.as_ptr());
+                gl::Uniform1f(locations.reflectivity, material.reflectivity);
+            }
+
+            // What shows through a translucent mesh mustn't be hidden by it if drawn afterwards
+            let translucent = call.opacity < 1.0;
+            if translucent {
+                gl::DepthMask(gl::FALSE);
+            }
+            gl::BindVertexArray(call.mesh.0);
+            gl::DrawElements(
+                gl::TRIANGLES,
+                call.index_count,
+                gl::UNSIGNED_INT,
+                std::ptr::null(),
+            );
+            gl::BindVertexArray(0);
+            if translucent {
+                gl::DepthMask(gl::TRUE);
+            }
+        }
+        let pipeline = self.current_pipeline.expect("No pipeline set");
+        self.stats.draw(pipeline, call.mesh, call.index_count);
+        self.record_draw("triangles", call.mesh.0, call.index_count, call.model);
+    }
+
+    fn end_frame(&mut self) {
+        // Passes still open at the end of the frame have no end to time
+        let (queries, open): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pass_queries)
+            .into_iter()
+            .partition(|pass| pass.end != 0);
+        self.free_queries
+            .extend(open.into_iter().map(|pass| pass.start));
+        self.pending_queries.push_back(queries);
+        if self.pending_queries.len() > MAX_PENDING_FRAMES {
+            for pass in self.pending_queries.pop_front().unwrap_or_default() {
+                self.free_queries.extend([pass.start, pass.end]);
+            }
+        }
+    }
+
+    fn stats(&self) -> &RenderStats {
+        self.stats.stats()
+    }
+
+    fn count_culled(&mut self, nodes: usize) {
+        self.stats.count_culled(nodes);
+    }
+
+    fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            meshes: self.mesh_bytes.len() as u32,
+            buffer_bytes: self.mesh_bytes.values().sum(),
+            textures: self.texture_bytes.len() as u32,
+            texture_bytes: self.texture_bytes.values().sum(),
+            available_bytes: unsafe { util::get_available_video_memory(self.video_memory_info) },
+        }
+    }
+}
+
+// Number of values in a uniform of GLSL type `kind`, e.g. 16 for a mat4. Scalars, samplers and
+// types without a case of their own count as one.
+fn components(kind: u32) -> usize {
+    match kind {
+        gl::FLOAT_VEC2 | gl::INT_VEC2 | gl::UNSIGNED_INT_VEC2 | gl::BOOL_VEC2 => 2,
+        gl::FLOAT_VEC3 | gl::INT_VEC3 | gl::UNSIGNED_INT_VEC3 | gl::BOOL_VEC3 => 3,
+        gl::FLOAT_VEC4 | gl::INT_VEC4 | gl::UNSIGNED_INT_VEC4 | gl::BOOL_VEC4 => 4,
+        gl::FLOAT_MAT2 => 4,
+        gl::FLOAT_MAT3 => 9,
+        gl::FLOAT_MAT4 => 16,
+        _ => 1,
+    }
+}
+
+// `Vao` doesn't keep track of its buffers, so they are found through the VAO's bindings: the index
+// buffer, then the position, color, normal, UV, tangent, joint and weight buffers. Missing buffers
+// are 0, which deleting ignores.
+fn mesh_buffers(mesh: MeshHandle) -> [u32; 8] {
+    let mut buffers = [0; 8];
+    unsafe {
+        gl::BindVertexArray(mesh.0);
+        gl::GetIntegerv(gl::ELEMENT_ARRAY_BUFFER_BINDING, &mut buffers[0]);
+        for attribute in 0..7 {
+            gl::GetVertexAttribiv(
+                attribute,
+                gl::VERTEX_ATTRIB_ARRAY_BUFFER_BINDING,
+                &mut buffers[attribute as usize + 1],
+            );
+        }
+        gl::BindVertexArray(0);
+    }
+    buffers.map(|buffer| buffer as u32)
+}
+
+// Fill `buffer` with `data`. Element array buffers are bound to the current VAO as well.
+unsafe fn buffer_data<T>(target: u32, buffer: u32, data: &[T]) {
+    gl::BindBuffer(target, buffer);
+    gl::BufferData(
+        target,
+        std::mem::size_of_val(data) as isize,
+        data.as_ptr() as *const std::ffi::c_void,
+        gl::STATIC_DRAW,
+    );
+}
+
+// Upload the image of the bound texture, along with its mipmaps
+unsafe fn texture_image(image: &image::RgbaImage) {
+    gl::TexImage2D(
+        gl::TEXTURE_2D,
+        0,
+        gl::RGBA8 as i32,
+        image.width() as i32,
+        image.height() as i32,
+        0,
+        gl::RGBA,
+        gl::UNSIGNED_BYTE,
+        image.as_ptr() as *const std::ffi::c_void,
+    );
+    gl::GenerateMipmap(gl::TEXTURE_2D);
+}
@@ -0,0 +1,271 @@
+// Rendering backends.
+//
+// The scene graph and the mesh code only deal in mesh and pipeline handles, and draw through the
+// `Backend` trait, so they don't depend on a particular graphics API. The OpenGL backend is what
+// the application renders with. The wgpu backend, enabled with the `wgpu` feature, runs the same
+// scene on Metal, Vulkan or DX12 where OpenGL is deprecated or unavailable.
+use crate::error::ShaderError;
+use crate::material::Material;
+use crate::mesh::Mesh;
+use std::time::Instant;
+
+pub mod gl;
+#[cfg(feature = "wgpu")]
+pub mod wgpu;
+
+// A mesh uploaded to the GPU. For the OpenGL backend this is the name of the mesh's VAO, so it
+// can be stored in a scene node's `vao_id`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MeshHandle(pub u32);
+
+// A compiled shader pipeline
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineHandle(pub usize);
+
+// An RGBA image uploaded to the GPU, with mipmaps. For the OpenGL backend this is the texture name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureHandle(pub u32);
+
+// GPU memory held by a backend's meshes and textures, to make leaks and duplicated uploads visible
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryUsage {
+    pub meshes: u32,
+    pub buffer_bytes: u64,
+    pub textures: u32,
+    pub texture_bytes: u64,           // Including mipmaps
+    pub available_bytes: Option<u64>, // Free video memory, if the driver reports it
+}
+
+// What a backend did to draw a frame, for the overlay, the window title and tests alike, see
+// `Backend::stats`
+#[derive(Clone, Debug, Default)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub triangles: u64,
+    pub state_changes: u32,     // Pipelines and meshes switched between draws
+    pub culled_nodes: usize,    // Counted by the application, see `Backend::count_culled`
+    pub passes: Vec<PassStats>, // One per top-level debug group, in the order they were drawn
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PassStats {
+    pub name: String,
+    pub cpu_time: f32, // Seconds spent issuing the pass's draws
+    // Seconds the GPU spent on the pass, for backends that can measure it. The GPU finishes frames
+    // after they are issued, so this is from a frame or two before.
+    pub gpu_time: Option<f32>,
+}
+
+// Counts what a backend does during a frame into `RenderStats`, timing passes on the CPU. Backends
+// that can time passes on the GPU fill that in when a pass ends.
+#[derive(Default)]
+pub struct StatsCounter {
+    stats: RenderStats,
+    last_draw: Option<(PipelineHandle, MeshHandle)>,
+    groups: Vec<String>, // Debug groups pushed and not yet popped, outermost first
+    pass_start: Option<Instant>,
+}
+
+impl StatsCounter {
+    pub fn begin_frame(&mut self) {
+        self.stats = RenderStats::default();
+        self.last_draw = None;
+        self.groups.clear();
+        self.pass_start = None;
+    }
+
+    pub fn draw(&mut self, pipeline: PipelineHandle, mesh: MeshHandle, index_count: i32) {
+        let (last_pipeline, last_mesh) = self.last_draw.unzip();
+        self.stats.state_changes +=
+            (last_pipeline != Some(pipeline)) as u32 + (last_mesh != Some(mesh)) as u32;
+        self.last_draw = Some((pipeline, mesh));
+        self.stats.draw_calls += 1;
+        self.stats.triangles += index_count as u64 / 3;
+    }
+
+    pub fn count_culled(&mut self, nodes: usize) {
+        self.stats.culled_nodes += nodes;
+    }
+
+    // Returns whether the group starts a pass
+    pub fn push_group(&mut self, name: &str) -> bool {
+        self.groups.push(name.to_string());
+        let starts_pass = self.groups.len() == 1;
+        if starts_pass {
+            self.pass_start = Some(Instant::now());
+        }
+        starts_pass
+    }
+
+    // Returns the pass the group ended, if it was one
+    pub fn pop_group(&mut self) -> Option<&mut PassStats> {
+        let name = self.groups.pop()?;
+        if !self.groups.is_empty() {
+            return None;
+        }
+        let start = self.pass_start.take()?;
+        self.stats.passes.push(PassStats {
+            name,
+            cpu_time: start.elapsed().as_secs_f32(),
+            gpu_time: None,
+        });
+        self.stats.passes.last_mut()
+    }
+
+    // The debug groups the next draw is in, outermost first
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+
+    pub fn stats(&self) -> &RenderStats {
+        &self.stats
+    }
+}
+
+// Size of a mesh's vertex and index buffers
+pub fn mesh_bytes(mesh: &Mesh) -> u64 {
+    (std::mem::size_of_val(&mesh.vertices[..])
+        + std::mem::size_of_val(&mesh.colors[..])
+        + std::mem::size_of_val(&mesh.normals[..])
+        + std::mem::size_of_val(&mesh.uvs[..])
+        + std::mem::size_of_val(&mesh.tangents[..])
+        + std::mem::size_of_val(&mesh.joints[..])
+        + std::mem::size_of_val(&mesh.weights[..])
+        + std::mem::size_of_val(&mesh.indices[..])) as u64
+}
+
+// Size of an RGBA8 texture with a full chain of mipmaps
+pub fn texture_bytes(width: u32, height: u32) -> u64 {
+    let (mut width, mut height) = (width.max(1) as u64, height.max(1) as u64);
+    let mut bytes = 0;
+    loop {
+        bytes += width * height * 4;
+        if width == 1 && height == 1 {
+            return bytes;
+        }
+        width = (width / 2).max(1);
+        height = (height / 2).max(1);
+    }
+}
+
+// Everything needed to draw one mesh with the current pipeline
+pub struct DrawCall<'a> {
+    pub mesh: MeshHandle,
+    pub index_count: i32,
+    pub transform: &'a glm::Mat4,      // Model-view-projection matrix
+    pub model: &'a glm::Mat4,          // Model matrix, used to transform normals
+    pub previous_model: &'a glm::Mat4, // The model matrix last frame, for motion blur
+    pub highlight: f32, // How much to tint the mesh to show it is selected, in [0, 1]
+    pub opacity: f32,   // 1 for solid meshes, less to see through them
+    pub joints: &'a [glm::Mat4], // Posing a skinned mesh, empty for other meshes, see `skeleton`
+    // Bending the mesh's normals per texel, for meshes with UVs and tangents
+    pub normal_map: Option<TextureHandle>,
+    // What the surface is made of, for physically based shading, or None to shade it with Phong
+    pub material: Option<Material>,
+}
+
+pub trait Backend {
+    // Upload a mesh's vertex and index buffers
+    fn create_mesh(&mut self, mesh: &Mesh) -> MeshHandle;
+
+    // Replace a mesh's vertices and indices, keeping its handle, so nodes drawing it draw the new
+    // mesh
+    fn update_mesh(&mut self, mesh: MeshHandle, data: &Mesh);
+
+    // Free a mesh's buffers. The handle must not be drawn again.
+    fn delete_mesh(&mut self, mesh: MeshHandle);
+
+    fn create_texture(&mut self, image: &image::RgbaImage) -> TextureHandle;
+
+    // Replace a texture's image, keeping its handle
+    fn update_texture(&mut self, texture: TextureHandle, image: &image::RgbaImage);
+
+    fn delete_texture(&mut self, texture: TextureHandle);
+
+    // Compile the pipeline named `name`. Each backend loads its own shaders for it from `shaders/`,
+    // e.g. `simple.vert` and `simple.frag` for OpenGL, or `simple.wgsl` for wgpu.
+    fn create_pipeline(&mut self, name: &str) -> Result<PipelineHandle, ShaderError>;
+
+    fn delete_pipeline(&mut self, pipeline: PipelineHandle);
+
+    // Name a mesh's or a texture's GPU objects after the asset they were made from, for graphics
+    // debuggers like RenderDoc or apitrace
+    fn label_mesh(&mut self, mesh: MeshHandle, label: &str);
+
+    fn label_texture(&mut self, texture: TextureHandle, label: &str);
+
+    // Group what is drawn until the matching `pop_group` under `name` in graphics debuggers, e.g.
+    // one group per render pass. Groups nest.
+    fn push_group(&mut self, name: &str);
+
+    fn pop_group(&mut self);
+
+    // The framebuffer was resized, in physical pixels
+    fn resize(&mut self, width: u32, height: u32);
+
+    // Start a frame by clearing the color and depth buffers
+    fn begin_frame(&mut self, clear_color: &glm::Vec4);
+
+    fn set_pipeline(&mut self, pipeline: PipelineHandle);
+
+    fn draw(&mut self, call: &DrawCall);
+
+    // Submit the frame. Presenting it is left to whoever owns the window surface.
+    fn end_frame(&mut self);
+
+    // What was drawn since the frame began, or in the last frame once it has ended
+    fn stats(&self) -> &RenderStats;
+
+    // Add nodes the application culled this frame to the stats, as backends only see what is drawn.
+    // Must be called after `begin_frame`.
+    fn count_culled(&mut self, nodes: usize);
+
+    fn memory_usage(&self) -> MemoryUsage;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switching_pipelines_and_meshes_counts_as_state_changes() {
+        let mut counter = StatsCounter::default();
+        counter.begin_frame();
+        counter.draw(PipelineHandle(0), MeshHandle(1), 6);
+        counter.draw(PipelineHandle(0), MeshHandle(1), 6);
+        counter.draw(PipelineHandle(0), MeshHandle(2), 3);
+        counter.draw(PipelineHandle(1), MeshHandle(1), 3);
+        let stats = counter.stats();
+        assert_eq!(stats.draw_calls, 4);
+        assert_eq!(stats.triangles, 6);
+        assert_eq!(stats.state_changes, 2 + 1 + 2);
+
+        counter.begin_frame();
+        assert_eq!(counter.stats().draw_calls, 0);
+        assert_eq!(counter.stats().state_changes, 0);
+    }
+
+    #[test]
+    fn passes_are_the_outermost_groups() {
+        let mut counter = StatsCounter::default();
+        counter.begin_frame();
+        assert!(counter.push_group("Scene"));
+        assert!(!counter.push_group("Helicopter"));
+        assert_eq!(counter.groups(), ["Scene", "Helicopter"]);
+        assert!(counter.pop_group().is_none());
+        let pass = counter.pop_group().unwrap();
+        assert_eq!(pass.name, "Scene");
+        pass.gpu_time = Some(0.001);
+        assert!(counter.push_group("Overlay"));
+        assert!(counter.pop_group().is_some());
+        assert!(counter.pop_group().is_none());
+
+        let passes: Vec<_> = counter
+            .stats()
+            .passes
+            .iter()
+            .map(|pass| (pass.name.as_str(), pass.gpu_time))
+            .collect();
+        assert_eq!(passes, vec![("Scene", Some(0.001)), ("Overlay", None)]);
+    }
+}
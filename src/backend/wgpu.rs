@@ -0,0 +1,677 @@
+// The wgpu backend, which renders through Metal, Vulkan or DX12 depending on the platform.
+//
+// Draw calls are recorded during the frame and encoded into a single render pass in `end_frame`.
+// Every draw gets its own slice of a uniform buffer, selected with a dynamic offset. Skinned meshes
+// get a slice of a second buffer with their joint matrices as well, while other meshes all share
+// its first slice.
+use super::{
+    Backend, DrawCall, MemoryUsage, MeshHandle, PipelineHandle, RenderStats, StatsCounter,
+    TextureHandle,
+};
+use crate::error::ShaderError;
+use crate::mesh::Mesh;
+use crate::skeleton;
+use log::info;
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+use winit::window::Window;
+
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+// glm builds OpenGL projection matrices, which map depth to [-1, 1] rather than wgpu's [0, 1]
+#[rustfmt::skip]
+const OPENGL_TO_WGPU_MATRIX: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+];
+
+// Matches `Uniforms` in the WGSL shaders: two matrices, the highlight, the opacity and whether the
+// mesh is skinned, padded to 16 bytes
+const UNIFORM_SIZE: u64 = (16 + 16 + 4) * 4;
+// Matches `joint_matrices` in the WGSL shaders, which is a multiple of any offset alignment
+const JOINTS_SIZE: u64 = skeleton::MAX_JOINTS as u64 * 16 * 4;
+
+struct GpuMesh {
+    positions: wgpu::Buffer,
+    colors: wgpu::Buffer,
+    normals: wgpu::Buffer,
+    indices: wgpu::Buffer,
+    // The joints and weights of skinned meshes. Other meshes draw with `WgpuBackend::unskinned`.
+    skin: Option<(wgpu::Buffer, wgpu::Buffer)>,
+}
+
+struct RecordedDraw {
+    pipeline: PipelineHandle,
+    mesh: MeshHandle,
+    index_count: u32,
+    joints_offset: u32, // Into the joint buffer, 0 for meshes that aren't skinned
+}
+
+pub struct WgpuBackend {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    surface: wgpu::Surface<'static>,
+    surface_config: wgpu::SurfaceConfiguration,
+    depth_view: wgpu::TextureView,
+
+    uniform_layout: wgpu::BindGroupLayout,
+    uniform_stride: u64,
+    uniform_capacity: u64, // Number of draws the uniform buffer has room for
+    uniform_buffer: wgpu::Buffer,
+    joint_capacity: u64, // Number of skinned draws the joint buffer has room for
+    joint_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    // Zeros standing in for the joints and weights of meshes that aren't skinned, for as many
+    // vertices as the largest of them has
+    unskinned: wgpu::Buffer,
+
+    // Deleted objects leave an empty slot, so handles stay valid indices
+    meshes: Vec<Option<GpuMesh>>,
+    pipelines: Vec<Option<wgpu::RenderPipeline>>,
+    textures: Vec<Option<wgpu::Texture>>,
+
+    // The frame being recorded
+    clear_color: wgpu::Color,
+    current_pipeline: Option<PipelineHandle>,
+    draws: Vec<RecordedDraw>,
+    uniforms: Vec<f32>,
+    joints: Vec<f32>, // Of the skinned draws, from the joint buffer's second slice on
+    stats: StatsCounter,
+}
+
+impl WgpuBackend {
+    pub fn new(window: Arc<Window>) -> Result<WgpuBackend, String> {
+        let size = window.inner_size();
+        let instance = wgpu::Instance::new(
+            wgpu::InstanceDescriptor::new_with_display_handle_from_env(Box::new(window.clone())),
+        );
+        let surface = instance
+            .create_surface(window)
+            .map_err(|e| format!("Failed to create a surface: {}", e))?;
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            compatible_surface: Some(&surface),
+            ..Default::default()
+        }))
+        .map_err(|e| format!("No suitable graphics adapter: {}", e))?;
+        let info = adapter.get_info();
+        info!("{}: {} ({:?})", info.vendor, info.name, info.backend);
+
+        let (device, queue) = pollster::block_on(adapter.request_device(&Default::default()))
+            .map_err(|e| format!("Failed to create a device: {}", e))?;
+
+        let surface_config = surface
+            .get_default_config(&adapter, size.width.max(1), size.height.max(1))
+            .ok_or("The surface is not supported by the adapter")?;
+        surface.configure(&device, &surface_config);
+        let depth_view = create_depth_view(&device, &surface_config);
+
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("uniforms"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(UNIFORM_SIZE),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(JOINTS_SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let uniform_stride = UNIFORM_SIZE.div_ceil(alignment) * alignment;
+        let uniform_buffer = create_uniform_buffer(&device, "uniforms", uniform_stride * 64);
+        let joint_buffer = create_uniform_buffer(&device, "joints", JOINTS_SIZE * 2);
+        let uniform_bind_group =
+            create_bind_group(&device, &uniform_layout, &uniform_buffer, &joint_buffer);
+        let unskinned = create_unskinned(&device, 0);
+
+        Ok(WgpuBackend {
+            device,
+            queue,
+            surface,
+            surface_config,
+            depth_view,
+            uniform_layout,
+            uniform_stride,
+            uniform_capacity: 64,
+            uniform_buffer,
+            joint_capacity: 2,
+            joint_buffer,
+            uniform_bind_group,
+            unskinned,
+            meshes: vec![],
+            pipelines: vec![],
+            textures: vec![],
+            clear_color: wgpu::Color::BLACK,
+            current_pipeline: None,
+            draws: vec![],
+            uniforms: vec![],
+            joints: vec![],
+            stats: StatsCounter::default(),
+        })
+    }
+}
+
+impl WgpuBackend {
+    fn upload_mesh(&self, mesh: &Mesh) -> GpuMesh {
+        let buffer = |label, contents: &[u8], usage| {
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents,
+                    usage,
+                })
+        };
+        GpuMesh {
+            positions: buffer(
+                "positions",
+                bytemuck::cast_slice(&mesh.vertices),
+                wgpu::BufferUsages::VERTEX,
+            ),
+            colors: buffer(
+                "colors",
+                bytemuck::cast_slice(&mesh.colors),
+                wgpu::BufferUsages::VERTEX,
+            ),
+            normals: buffer(
+                "normals",
+                bytemuck::cast_slice(&mesh.normals),
+                wgpu::BufferUsages::VERTEX,
+            ),
+            indices: buffer(
+                "indices",
+                bytemuck::cast_slice(&mesh.indices),
+                wgpu::BufferUsages::INDEX,
+            ),
+            skin: (!mesh.joints.is_empty()).then(|| {
+                (
+                    buffer(
+                        "joints",
+                        bytemuck::cast_slice(&mesh.joints),
+                        wgpu::BufferUsages::VERTEX,
+                    ),
+                    buffer(
+                        "weights",
+                        bytemuck::cast_slice(&mesh.weights),
+                        wgpu::BufferUsages::VERTEX,
+                    ),
+                )
+            }),
+        }
+    }
+
+    // Make sure the stand-in joints and weights cover every vertex of `mesh`, if it isn't skinned
+    fn cover_unskinned(&mut self, mesh: &Mesh) {
+        let size = (mesh.vertices.len() / 3 * 4 * 4) as u64;
+        if mesh.joints.is_empty() && size > self.unskinned.size() {
+            self.unskinned = create_unskinned(&self.device, size.next_power_of_two());
+        }
+    }
+
+    fn upload_texture(&self, image: &image::RgbaImage) -> wgpu::Texture {
+        let size = wgpu::Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+        let mip_level_count = size.max_mips(wgpu::TextureDimension::D2);
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("texture"),
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        // wgpu doesn't generate mipmaps, so the smaller levels are downscaled on the CPU
+        let mut level_image = image.clone();
+        for level in 0..mip_level_count {
+            if level > 0 {
+                level_image = image::imageops::resize(
+                    &level_image,
+                    (level_image.width() / 2).max(1),
+                    (level_image.height() / 2).max(1),
+                    image::imageops::FilterType::Triangle,
+                );
+            }
+            self.queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level: level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &level_image,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * level_image.width()),
+                    rows_per_image: Some(level_image.height()),
+                },
+                wgpu::Extent3d {
+                    width: level_image.width(),
+                    height: level_image.height(),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        texture
+    }
+}
+
+impl Backend for WgpuBackend {
+    fn create_mesh(&mut self, mesh: &Mesh) -> MeshHandle {
+        let gpu_mesh = self.upload_mesh(mesh);
+        self.cover_unskinned(mesh);
+        self.meshes.push(Some(gpu_mesh));
+        MeshHandle(self.meshes.len() as u32 - 1)
+    }
+
+    // Buffers can't be resized, so the mesh gets new ones under the same handle
+    fn update_mesh(&mut self, mesh: MeshHandle, data: &Mesh) {
+        self.meshes[mesh.0 as usize] = Some(self.upload_mesh(data));
+        self.cover_unskinned(data);
+    }
+
+    fn delete_mesh(&mut self, mesh: MeshHandle) {
+        self.meshes[mesh.0 as usize] = None;
+    }
+
+    fn create_texture(&mut self, image: &image::RgbaImage) -> TextureHandle {
+        let texture = self.upload_texture(image);
+        self.textures.push(Some(texture));
+        TextureHandle(self.textures.len() as u32 - 1)
+    }
+
+    fn update_texture(&mut self, texture: TextureHandle, image: &image::RgbaImage) {
+        self.textures[texture.0 as usize] = Some(self.upload_texture(image));
+    }
+
+    fn delete_texture(&mut self, texture: TextureHandle) {
+        self.textures[texture.0 as usize] = None;
+    }
+
+    fn create_pipeline(&mut self, name: &str) -> Result<PipelineHandle, ShaderError> {
+        let path = format!("shaders/{}.wgsl", name);
+        let source =
+            std::fs::read_to_string(&path).map_err(|source| ShaderError::Read { path, source })?;
+        let module = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(name),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+        let layout = self
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(name),
+                bind_group_layouts: &[Some(&self.uniform_layout)],
+                immediate_size: 0,
+            });
+
+        // Positions, colors, normals, joints and weights live in separate buffers, like the VAOs of
+        // the OpenGL backend
+        const POSITIONS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x3];
+        const COLORS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![1 => Float32x4];
+        const NORMALS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![2 => Float32x3];
+        const JOINTS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![3 => Float32x4];
+        const WEIGHTS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![4 => Float32x4];
+        let buffer_layout = |attributes: &'static [wgpu::VertexAttribute], components: u64| {
+            Some(wgpu::VertexBufferLayout {
+                array_stride: components * 4,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes,
+            })
+        };
+        let buffers = [
+            buffer_layout(&POSITIONS, 3),
+            buffer_layout(&COLORS, 4),
+            buffer_layout(&NORMALS, 3),
+            buffer_layout(&JOINTS, 4),
+            buffer_layout(&WEIGHTS, 4),
+        ];
+
+        let pipeline = self
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(name),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &buffers,
+                },
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: Some(wgpu::Face::Back),
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: Some(true),
+                    depth_compare: Some(wgpu::CompareFunction::Less),
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                multisample: Default::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: Some("fs_main"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: self.surface_config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview_mask: None,
+                cache: None,
+            });
+        self.pipelines.push(Some(pipeline));
+        Ok(PipelineHandle(self.pipelines.len() - 1))
+    }
+
+    fn delete_pipeline(&mut self, pipeline: PipelineHandle) {
+        self.pipelines[pipeline.0] = None;
+    }
+
+    // wgpu objects can only be labeled when they are created, and draws are recorded into a single
+    // render pass at the end of the frame, so neither labels nor groups are passed on. Groups are
+    // still timed as passes on the CPU, but not on the GPU, which would need timestamp queries.
+    fn label_mesh(&mut self, _mesh: MeshHandle, _label: &str) {}
+
+    fn label_texture(&mut self, _texture: TextureHandle, _label: &str) {}
+
+    fn push_group(&mut self, name: &str) {
+        self.stats.push_group(name);
+    }
+
+    fn pop_group(&mut self) {
+        self.stats.pop_group();
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.surface_config.width = width;
+        self.surface_config.height = height;
+        self.surface.configure(&self.device, &self.surface_config);
+        self.depth_view = create_depth_view(&self.device, &self.surface_config);
+    }
+
+    fn begin_frame(&mut self, clear_color: &glm::Vec4) {
+        self.clear_color = wgpu::Color {
+            r: clear_color.x as f64,
+            g: clear_color.y as f64,
+            b: clear_color.z as f64,
+            a: clear_color.w as f64,
+        };
+        self.draws.clear();
+        self.uniforms.clear();
+        self.joints.clear();
+        self.stats.begin_frame();
+    }
+
+    fn set_pipeline(&mut self, pipeline: PipelineHandle) {
+        self.current_pipeline = Some(pipeline);
+    }
+
+    fn draw(&mut self, call: &DrawCall) {
+        let transform = glm::make_mat4(&OPENGL_TO_WGPU_MATRIX) * call.transform;
+        let start = self.uniforms.len();
+        self.uniforms.extend_from_slice(transform.as_slice());
+        self.uniforms.extend_from_slice(call.model.as_slice());
+        let skinned = !call.joints.is_empty();
+        self.uniforms
+            .extend_from_slice(&[call.highlight, call.opacity, skinned as u8 as f32, 0.0]);
+        // Translucent meshes still write depth, as it is part of the pipeline here. Normal maps are
+        // left out, as the shader has no textures, and materials, as it only shades with Phong.
+        // Pad to the dynamic offset alignment
+        self.uniforms
+            .resize(start + self.uniform_stride as usize / 4, 0.0);
+
+        // Skeletons with more joints than the shader has room for are cut short
+        let mut joints_offset = 0;
+        if skinned {
+            let start = self.joints.len();
+            joints_offset = (JOINTS_SIZE + start as u64 * 4) as u32;
+            for matrix in call.joints.iter().take(skeleton::MAX_JOINTS) {
+                self.joints.extend_from_slice(matrix.as_slice());
+            }
+            self.joints.resize(start + JOINTS_SIZE as usize / 4, 0.0);
+        }
+
+        let pipeline = self.current_pipeline.expect("No pipeline set");
+        self.stats.draw(pipeline, call.mesh, call.index_count);
+        self.draws.push(RecordedDraw {
+            pipeline,
+            mesh: call.mesh,
+            index_count: call.index_count as u32,
+            joints_offset,
+        });
+    }
+
+    fn end_frame(&mut self) {
+        let surface_texture = match self.surface.get_current_texture() {
+            wgpu::CurrentSurfaceTexture::Success(texture)
+            | wgpu::CurrentSurfaceTexture::Suboptimal(texture) => texture,
+            wgpu::CurrentSurfaceTexture::Outdated | wgpu::CurrentSurfaceTexture::Lost => {
+                self.surface.configure(&self.device, &self.surface_config);
+                return;
+            }
+            _ => return, // Skip the frame, e.g. while the window is occluded
+        };
+        let view = surface_texture.texture.create_view(&Default::default());
+
+        let mut resized = false;
+        if self.draws.len() as u64 > self.uniform_capacity {
+            self.uniform_capacity = (self.draws.len() as u64).next_power_of_two();
+            self.uniform_buffer = create_uniform_buffer(
+                &self.device,
+                "uniforms",
+                self.uniform_stride * self.uniform_capacity,
+            );
+            resized = true;
+        }
+        // The first slice is left for the draws that aren't skinned
+        let joint_slices = 1 + self.joints.len() as u64 * 4 / JOINTS_SIZE;
+        if joint_slices > self.joint_capacity {
+            self.joint_capacity = joint_slices.next_power_of_two();
+            self.joint_buffer =
+                create_uniform_buffer(&self.device, "joints", JOINTS_SIZE * self.joint_capacity);
+            resized = true;
+        }
+        if resized {
+            self.uniform_bind_group = create_bind_group(
+                &self.device,
+                &self.uniform_layout,
+                &self.uniform_buffer,
+                &self.joint_buffer,
+            );
+        }
+        self.queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&self.uniforms),
+        );
+        if !self.joints.is_empty() {
+            self.queue.write_buffer(
+                &self.joint_buffer,
+                JOINTS_SIZE,
+                bytemuck::cast_slice(&self.joints),
+            );
+        }
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("scene"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.clear_color),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            for (i, draw) in self.draws.iter().enumerate() {
+                let mesh = self.meshes[draw.mesh.0 as usize].as_ref();
+                let pipeline = self.pipelines[draw.pipeline.0].as_ref();
+                let (mesh, pipeline) = match (mesh, pipeline) {
+                    (Some(mesh), Some(pipeline)) => (mesh, pipeline),
+                    _ => continue, // Deleted while the frame was being recorded
+                };
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(
+                    0,
+                    &self.uniform_bind_group,
+                    &[(i as u64 * self.uniform_stride) as u32, draw.joints_offset],
+                );
+                pass.set_vertex_buffer(0, mesh.positions.slice(..));
+                pass.set_vertex_buffer(1, mesh.colors.slice(..));
+                pass.set_vertex_buffer(2, mesh.normals.slice(..));
+                let (joints, weights) = match &mesh.skin {
+                    Some((joints, weights)) => (joints, weights),
+                    None => (&self.unskinned, &self.unskinned),
+                };
+                pass.set_vertex_buffer(3, joints.slice(..));
+                pass.set_vertex_buffer(4, weights.slice(..));
+                pass.set_index_buffer(mesh.indices.slice(..), wgpu::IndexFormat::Uint32);
+                pass.draw_indexed(0..draw.index_count, 0, 0..1);
+            }
+        }
+        self.queue.submit([encoder.finish()]);
+        self.queue.present(surface_texture);
+    }
+
+    // wgpu has no way to ask for free video memory
+    fn memory_usage(&self) -> MemoryUsage {
+        let meshes = self.meshes.iter().flatten();
+        let textures = self.textures.iter().flatten();
+        MemoryUsage {
+            meshes: meshes.clone().count() as u32,
+            buffer_bytes: meshes
+                .map(|mesh| {
+                    mesh.positions.size()
+                        + mesh.colors.size()
+                        + mesh.normals.size()
+                        + mesh.indices.size()
+                        + mesh
+                            .skin
+                            .as_ref()
+                            .map_or(0, |(joints, weights)| joints.size() + weights.size())
+                })
+                .sum(),
+            textures: textures.clone().count() as u32,
+            texture_bytes: textures
+                .map(|texture| super::texture_bytes(texture.width(), texture.height()))
+                .sum(),
+            available_bytes: None,
+        }
+    }
+
+    fn stats(&self) -> &RenderStats {
+        self.stats.stats()
+    }
+
+    fn count_culled(&mut self, nodes: usize) {
+        self.stats.count_culled(nodes);
+    }
+}
+
+fn create_depth_view(
+    device: &wgpu::Device,
+    surface_config: &wgpu::SurfaceConfiguration,
+) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("depth"),
+            size: wgpu::Extent3d {
+                width: surface_config.width,
+                height: surface_config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        })
+        .create_view(&Default::default())
+}
+
+fn create_uniform_buffer(device: &wgpu::Device, label: &str, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// Selects the uniforms of one draw at a time from `uniforms`, and the joint matrices of one
+// skinned draw from `joints`
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniforms: &wgpu::Buffer,
+    joints: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    let entry = |binding, buffer, size| wgpu::BindGroupEntry {
+        binding,
+        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer,
+            offset: 0,
+            size: wgpu::BufferSize::new(size),
+        }),
+    };
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("uniforms"),
+        layout,
+        entries: &[
+            entry(0, uniforms, UNIFORM_SIZE),
+            entry(1, joints, JOINTS_SIZE),
+        ],
+    })
+}
+
+// New buffers are zeroed, so the joints and weights are all zeros without writing them
+fn create_unskinned(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("unskinned"),
+        size: size.max(16),
+        usage: wgpu::BufferUsages::VERTEX,
+        mapped_at_creation: false,
+    })
+}
@@ -0,0 +1,135 @@
+// Merging the meshes of static nodes into one, so they take a single draw call together.
+//
+// Nodes that never move, like props placed around the scene, don't need their own draw call and
+// model matrix. Their meshes are transformed to where the nodes are and merged into a single mesh,
+// drawn by one node. Everything is drawn with the same pipeline and the colors are per vertex, so
+// any static nodes can share a batch. The merged nodes stay in the scene graph so they can still be
+// picked, but are marked as batched so the renderer skips them, unless selected. Moving one of
+// them, or changing its mesh, means building the batch again.
+use crate::mesh::{self, Mesh};
+use crate::scene_graph::SceneNode;
+use std::collections::HashMap;
+
+// Merge the meshes of `nodes` and their children into one mesh in the space of their parent,
+// whose transformation is `parent_transform`, and mark the nodes as batched. `meshes` has the
+// meshes by VAO, see `Assets::meshes_by_vao`. Returns None if there was nothing to merge.
+pub fn merge_static<'a>(
+    nodes: impl IntoIterator<Item = &'a mut SceneNode>,
+    parent_transform: &glm::Mat4,
+    meshes: &HashMap<u32, &Mesh>,
+) -> Option<Mesh> {
+    let mut merged = Mesh {
+        vertices: vec![],
+        normals: vec![],
+        colors: vec![],
+        uvs: vec![],
+        tangents: vec![],
+        joints: vec![],
+        weights: vec![],
+        indices: vec![],
+        index_count: 0,
+    };
+    for node in nodes {
+        merge_node(node, parent_transform, meshes, &mut merged);
+    }
+    drop_partial_attributes(&mut merged);
+    merged.index_count = merged.indices.len() as i32;
+    (merged.index_count > 0).then_some(merged)
+}
+
+// A copy of `mesh` with `transform` applied to it, e.g. for placing a prop before it is uploaded
+pub fn transformed(mesh: &Mesh, transform: &glm::Mat4) -> Mesh {
+    let mut placed = Mesh {
+        vertices: vec![],
+        normals: vec![],
+        colors: vec![],
+        uvs: vec![],
+        tangents: vec![],
+        joints: vec![],
+        weights: vec![],
+        indices: vec![],
+        index_count: mesh.index_count,
+    };
+    append_transformed(&mut placed, mesh, transform);
+    drop_partial_attributes(&mut placed);
+    placed
+}
+
+fn merge_node(
+    node: &mut SceneNode,
+    transformation_so_far: &glm::Mat4,
+    meshes: &HashMap<u32, &Mesh>,
+    merged: &mut Mesh,
+) {
+    let transform = transformation_so_far * node.local_transform();
+    // Batches are drawn solid and with the batch's material, so see-through nodes and those giving
+    // off light are left to be drawn on their own
+    let glows = node
+        .material
+        .is_some_and(|material| material.emissive != glm::Vec3::zeros());
+    let mesh = meshes
+        .get(&node.vao_id)
+        .filter(|_| node.opacity >= 1.0 && !glows);
+    if let Some(mesh) = mesh {
+        append_transformed(merged, mesh, &transform);
+        node.batched = true;
+    }
+    for &child in &node.children {
+        merge_node(unsafe { &mut *child }, &transform, meshes, merged);
+    }
+}
+
+fn append_transformed(merged: &mut Mesh, mesh: &Mesh, transform: &glm::Mat4) {
+    let first_vertex = (merged.vertices.len() / 3) as u32;
+    // Normals follow the inverse transpose, so they stay perpendicular under non-uniform scaling
+    let normal_matrix = glm::mat4_to_mat3(&glm::inverse_transpose(*transform));
+    for position in mesh.vertices.chunks_exact(3) {
+        let position = transform * glm::vec4(position[0], position[1], position[2], 1.0);
+        merged
+            .vertices
+            .extend_from_slice(&[position.x, position.y, position.z]);
+    }
+    // The merged buffers must line up, so meshes without a normal per vertex get generated ones
+    let generated;
+    let normals = if mesh.normals.len() == mesh.vertices.len() {
+        &mesh.normals
+    } else {
+        generated = mesh::compute_normals(&mesh.vertices, &mesh.indices);
+        &generated
+    };
+    for normal in normals.chunks_exact(3) {
+        let normal = glm::normalize(&(normal_matrix * glm::vec3(normal[0], normal[1], normal[2])));
+        merged
+            .normals
+            .extend_from_slice(&[normal.x, normal.y, normal.z]);
+    }
+    merged.colors.extend_from_slice(&mesh.colors);
+    merged.uvs.extend_from_slice(&mesh.uvs);
+    // Tangents follow the surface, and mirroring the mesh turns which way its V grows
+    let tangent_matrix = glm::mat4_to_mat3(transform);
+    let mirrored = glm::determinant(&tangent_matrix) < 0.0;
+    for tangent in mesh.tangents.chunks_exact(4) {
+        let direction =
+            glm::normalize(&(tangent_matrix * glm::vec3(tangent[0], tangent[1], tangent[2])));
+        let handedness = if mirrored { -tangent[3] } else { tangent[3] };
+        merged
+            .tangents
+            .extend_from_slice(&[direction.x, direction.y, direction.z, handedness]);
+    }
+    // Skinned meshes are merged in their bind pose, as a batch has no skeleton to pose them with
+    merged
+        .indices
+        .extend(mesh.indices.iter().map(|&i| first_vertex + i));
+}
+
+// UVs and tangents are optional, so if only some of the merged meshes had them, they no longer line
+// up with the vertices and are left out
+fn drop_partial_attributes(merged: &mut Mesh) {
+    let vertex_count = merged.vertices.len() / 3;
+    if merged.uvs.len() != vertex_count * 2 {
+        merged.uvs.clear();
+    }
+    if merged.tangents.len() != vertex_count * 4 {
+        merged.tangents.clear();
+    }
+}
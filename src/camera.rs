@@ -0,0 +1,254 @@
+// Cameras used by the render loop, camera paths for recorded fly-throughs and camera shake.
+use crate::toolbox::{self, Easing, Keyframe, Noise, Spline};
+use log::{info, warn};
+
+// A free-flying camera steered with the mouse. Yaw is measured around the Y axis with zero
+// looking down -Z, and pitch is positive when looking up.
+#[derive(Clone, Copy, Debug)]
+pub struct FreeCamera {
+    pub position: glm::Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl FreeCamera {
+    pub fn new(position: glm::Vec3, yaw: f32, pitch: f32) -> FreeCamera {
+        FreeCamera {
+            position,
+            yaw,
+            pitch,
+        }
+    }
+
+    // Place the camera at `position`, looking towards `target`
+    pub fn looking_at(position: glm::Vec3, target: glm::Vec3) -> FreeCamera {
+        let direction = glm::normalize(&(target - position));
+        let yaw = (-direction.x).atan2(-direction.z);
+        let pitch = direction.y.clamp(-1.0, 1.0).asin();
+        FreeCamera::new(position, yaw, pitch)
+    }
+
+    // The camera at `position`, turned by `orientation` from looking down -Z. Cameras don't roll,
+    // so only where it looks is kept.
+    pub fn with_orientation(position: glm::Vec3, orientation: &glm::Quat) -> FreeCamera {
+        let forward = glm::quat_rotate_vec3(orientation, &glm::vec3(0.0, 0.0, -1.0));
+        FreeCamera::looking_at(position, position + forward)
+    }
+
+    pub fn orientation(&self) -> glm::Quat {
+        toolbox::look_rotation(&self.forward(), &glm::vec3(0.0, 1.0, 0.0))
+    }
+
+    pub fn forward(&self) -> glm::Vec3 {
+        glm::vec3(
+            -self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            -self.yaw.cos() * self.pitch.cos(),
+        )
+    }
+
+    pub fn right(&self) -> glm::Vec3 {
+        glm::vec3(self.yaw.cos(), 0.0, -self.yaw.sin())
+    }
+
+    // Rotate by a look delta in radians, as returned by `input::look_delta`
+    pub fn rotate(&mut self, look_x: f32, look_y: f32) {
+        self.yaw -= look_x;
+        self.pitch = (self.pitch - look_y).clamp(-1.5, 1.5);
+    }
+
+    pub fn view_matrix(&self) -> glm::Mat4 {
+        glm::look_at(
+            &self.position,
+            &(self.position + self.forward()),
+            &glm::vec3(0.0, 1.0, 0.0),
+        )
+    }
+}
+
+// A camera following behind a target, which the mouse can orbit around it
+pub struct ChaseCamera {
+    pub distance: f32,
+    pub height: f32,
+    pub orbit_yaw: f32,
+    pub orbit_pitch: f32,
+}
+
+impl ChaseCamera {
+    pub fn new(distance: f32, height: f32) -> ChaseCamera {
+        ChaseCamera {
+            distance,
+            height,
+            orbit_yaw: 0.0,
+            orbit_pitch: 0.0,
+        }
+    }
+
+    // Rotate by a look delta in radians, as returned by `input::look_delta`
+    pub fn rotate(&mut self, look_x: f32, look_y: f32) {
+        self.orbit_yaw -= look_x;
+        self.orbit_pitch = (self.orbit_pitch + look_y).clamp(-0.4, 1.2);
+    }
+
+    // Move closer to or further away from the target. Positive amounts zoom in.
+    pub fn zoom(&mut self, amount: f32) {
+        self.distance = (self.distance / (1.0 + amount).max(0.1)).clamp(5.0, 200.0);
+    }
+
+    // Calculate the camera position behind a target based on its yaw, offset by the orbit angles
+    pub fn eye(&self, target_position: &glm::Vec3, target_yaw: f32) -> glm::Vec3 {
+        let yaw = target_yaw + self.orbit_yaw;
+        let offset = glm::vec3(
+            yaw.sin() * self.orbit_pitch.cos() * self.distance,
+            self.height + self.orbit_pitch.sin() * self.distance,
+            yaw.cos() * self.orbit_pitch.cos() * self.distance,
+        );
+        target_position + offset
+    }
+
+    // Make the camera look at the target
+    pub fn view_matrix(&self, target_position: &glm::Vec3, target_yaw: f32) -> glm::Mat4 {
+        glm::look_at(
+            &self.eye(target_position, target_yaw),
+            &(target_position + glm::vec3(0.0, self.height, 0.0)),
+            &glm::vec3(0.0, 1.0, 0.0),
+        )
+    }
+}
+
+// A fly-through made of camera keyframes. Positions and orientations are interpolated along
+// Catmull-Rom splines, and the whole path is eased, in and out by default, so playback starts and
+// stops smoothly.
+pub struct CameraPath {
+    pub keyframes: Vec<FreeCamera>,
+    pub seconds_per_keyframe: f32,
+    pub easing: Easing,
+    playback_time: Option<f32>,
+}
+
+impl CameraPath {
+    pub fn new(seconds_per_keyframe: f32) -> CameraPath {
+        CameraPath {
+            keyframes: vec![],
+            seconds_per_keyframe,
+            easing: Easing::Smooth,
+            playback_time: None,
+        }
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> CameraPath {
+        self.easing = easing;
+        self
+    }
+
+    pub fn record(&mut self, pose: FreeCamera) {
+        self.keyframes.push(pose);
+        info!("Recorded camera keyframe {}", self.keyframes.len());
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+        self.playback_time = None;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playback_time.is_some()
+    }
+
+    // Start playback from the beginning. A path needs at least two keyframes to play.
+    pub fn play(&mut self) {
+        if self.keyframes.len() >= 2 {
+            self.playback_time = Some(0.0);
+        } else {
+            warn!("A camera path needs at least two keyframes");
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.playback_time = None;
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.len().saturating_sub(1) as f32 * self.seconds_per_keyframe
+    }
+
+    // Advance playback and return the camera pose for this frame, or None once the path is done
+    pub fn advance(&mut self, delta_time: f32) -> Option<FreeCamera> {
+        let time = self.playback_time? + delta_time;
+        if time >= self.duration() {
+            self.playback_time = None;
+            return self.keyframes.last().copied();
+        }
+        self.playback_time = Some(time);
+        Some(self.sample(time / self.duration()))
+    }
+
+    // Sample the path at u in [0, 1] along its whole length. Positions are spaced by distance, so
+    // the camera moves at the same speed all the way apart from easing in and out.
+    pub fn sample(&self, u: f32) -> FreeCamera {
+        let positions: Vec<_> = self.keyframes.iter().map(|key| key.position).collect();
+        let spline = Spline::catmull_rom(positions, false);
+        let segments = self.keyframes.len() - 1;
+        // A camera turning on the spot only has its angles to go by
+        let s = if spline.length() > 1e-3 {
+            spline.parameter_at(self.easing.apply(u) * spline.length())
+        } else {
+            self.easing.apply(u) * segments as f32
+        };
+        let i = (s.floor() as usize).min(segments - 1);
+        let t = s - i as f32;
+
+        // Orientations follow a spline of their own, so turns take the short way around
+        let key = |i: isize| self.keyframes[i.clamp(0, segments as isize) as usize].orientation();
+        let i = i as isize;
+        let orientation = glm::Quat::cubic(&key(i - 1), &key(i), &key(i + 1), &key(i + 2), t);
+        FreeCamera::with_orientation(spline.point(s), &orientation)
+    }
+}
+
+// Shaking the view, e.g. when something heavy lands nearby. Each jolt adds trauma, which wears off
+// over time, and the shake grows with its square so small jolts barely register. The view turns
+// with smooth noise rather than random jumps, which would look like a broken frame rate.
+pub struct CameraShake {
+    trauma: f32, // 0-1
+    time: f32,
+    noise: Noise,
+}
+
+impl CameraShake {
+    // Largest turn in each direction, in radians
+    const MAX_ANGLE: f32 = 0.06;
+    // How fast the view wobbles, in noise cells per second
+    const FREQUENCY: f32 = 12.0;
+    // Trauma worn off per second
+    const RECOVERY: f32 = 0.8;
+
+    pub fn new(seed: u64) -> CameraShake {
+        CameraShake {
+            trauma: 0.0,
+            time: 0.0,
+            noise: Noise::new(seed),
+        }
+    }
+
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.trauma = (self.trauma - Self::RECOVERY * delta_time).max(0.0);
+        self.time += delta_time;
+    }
+
+    // The turn to apply on top of the view matrix
+    pub fn matrix(&self) -> glm::Mat4 {
+        if self.trauma <= 0.0 {
+            return glm::Mat4::identity();
+        }
+        let strength = self.trauma * self.trauma * Self::MAX_ANGLE;
+        // One noise row per direction
+        let angle = |row: f32| strength * self.noise.noise2(self.time * Self::FREQUENCY, row);
+        let angles = glm::vec3(angle(0.5), angle(10.5), angle(20.5));
+        glm::quat_to_mat4(&toolbox::quat_from_euler(&angles))
+    }
+}
@@ -0,0 +1,140 @@
+// Capturing every rendered frame, for videos of the animation that play back smoothly no matter
+// how long each frame took to render. The render loop advances its clock by a fixed step per
+// frame while capturing.
+use crate::error::RenderError;
+use crate::offscreen;
+use log::{error, info};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+// Captures to paths with these extensions are encoded to video by ffmpeg
+const VIDEO_EXTENSIONS: [&str; 4] = ["mp4", "mkv", "webm", "mov"];
+
+enum Sink {
+    // Numbered PNG files in a directory
+    Images(PathBuf),
+    // Raw frames piped to ffmpeg, which is started once the frame size is known
+    Ffmpeg {
+        path: PathBuf,
+        process: Option<Child>,
+    },
+}
+
+pub struct FrameCapture {
+    sink: Sink,
+    fps: u32,
+    frame_size: Option<(u32, u32)>,
+    frames: u32,
+}
+
+impl FrameCapture {
+    pub fn new(path: &Path, fps: u32) -> std::io::Result<FrameCapture> {
+        let is_video = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| VIDEO_EXTENSIONS.contains(&extension));
+        let sink = if is_video {
+            Sink::Ffmpeg {
+                path: path.to_path_buf(),
+                process: None,
+            }
+        } else {
+            std::fs::create_dir_all(path)?;
+            Sink::Images(path.to_path_buf())
+        };
+        Ok(FrameCapture {
+            sink,
+            fps,
+            frame_size: None,
+            frames: 0,
+        })
+    }
+
+    // Time between two captured frames, in seconds
+    pub fn timestep(&self) -> f32 {
+        1.0 / self.fps as f32
+    }
+
+    // Number of frames captured so far
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    // Capture the framebuffer bound for reading. Videos can't change size halfway through, so
+    // every frame must have the size of the first one.
+    pub unsafe fn write_frame(&mut self, width: u32, height: u32) -> Result<(), RenderError> {
+        let frame_size = *self.frame_size.get_or_insert((width, height));
+        if frame_size != (width, height) {
+            return Err(RenderError::CaptureSizeChanged {
+                from: frame_size,
+                to: (width, height),
+            });
+        }
+        let image = offscreen::read_pixels(width, height)?;
+
+        match &mut self.sink {
+            Sink::Images(directory) => {
+                let path = directory.join(format!("frame_{:05}.png", self.frames));
+                image
+                    .save(&path)
+                    .map_err(|source| RenderError::SaveFrame { path, source })?;
+            }
+            Sink::Ffmpeg { path, process } => {
+                if process.is_none() {
+                    *process = Some(spawn_ffmpeg(path, width, height, self.fps)?);
+                }
+                let stdin = process.as_mut().and_then(|process| process.stdin.as_mut());
+                stdin
+                    .expect("ffmpeg is started with a piped stdin")
+                    .write_all(image.as_raw())
+                    .map_err(RenderError::Ffmpeg)?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+impl Drop for FrameCapture {
+    // Closing ffmpeg's input makes it finish the video
+    fn drop(&mut self) {
+        match &mut self.sink {
+            Sink::Images(directory) => {
+                info!("Saved {} frames to {}", self.frames, directory.display())
+            }
+            Sink::Ffmpeg {
+                path,
+                process: Some(process),
+            } => {
+                drop(process.stdin.take());
+                match process.wait() {
+                    Ok(status) if status.success() => {
+                        info!("Saved {} frames to {}", self.frames, path.display())
+                    }
+                    Ok(status) => {
+                        error!("ffmpeg failed to encode {}: {}", path.display(), status)
+                    }
+                    Err(e) => error!("Failed to wait for ffmpeg: {}", e),
+                }
+            }
+            Sink::Ffmpeg { process: None, .. } => {}
+        }
+    }
+}
+
+fn spawn_ffmpeg(path: &Path, width: u32, height: u32, fps: u32) -> Result<Child, RenderError> {
+    Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-y"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24"])
+        .args(["-s", &format!("{}x{}", width, height)])
+        .args(["-r", &fps.to_string()])
+        .args(["-i", "-"])
+        // Most encoders need even dimensions
+        .args(["-vf", "crop=trunc(iw/2)*2:trunc(ih/2)*2"])
+        .args(["-pix_fmt", "yuv420p"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(RenderError::Ffmpeg)
+}
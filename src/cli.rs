@@ -0,0 +1,148 @@
+// Command line options, e.g. `cargo run -- --width 1280 --height 720 --msaa 4`
+use clap::Parser;
+use std::path::PathBuf;
+
+#[derive(Parser, Clone, Debug)]
+#[command(about = "Helicopters flying over the lunar surface")]
+pub struct Args {
+    /// Initial window width in logical pixels
+    #[arg(long, default_value_t = 800)]
+    pub width: u32,
+
+    /// Initial window height in logical pixels
+    #[arg(long, default_value_t = 600)]
+    pub height: u32,
+
+    /// Start in borderless fullscreen (toggle at runtime with F11)
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Start with vsync disabled (toggle at runtime with V)
+    #[arg(long)]
+    pub no_vsync: bool,
+
+    /// Use adaptive vsync where the driver supports it: frames that miss a refresh are shown right
+    /// away, tearing briefly instead of stuttering
+    #[arg(long, conflicts_with = "no_vsync")]
+    pub adaptive_vsync: bool,
+
+    /// Monitor to open the window on, as numbered in the list printed at startup
+    #[arg(long, value_name = "INDEX")]
+    pub monitor: Option<usize>,
+
+    /// Limit the frame rate, mostly useful together with --no-vsync
+    #[arg(long, value_name = "FPS", value_parser = clap::value_parser!(u32).range(1..))]
+    pub fps_cap: Option<u32>,
+
+    /// Lower the resolution and detail while frames can't keep up with this frame rate, and raise
+    /// them again once they can. Should be at most the monitor's refresh rate and --fps-cap.
+    /// Ignored with --msaa, where the resolution can't be lowered.
+    #[arg(long, value_name = "FPS", value_parser = clap::value_parser!(u32).range(1..))]
+    pub target_fps: Option<u32>,
+
+    /// Number of samples per pixel for multisample anti-aliasing, 0 to disable
+    #[arg(long, default_value_t = 0, value_parser = parse_msaa)]
+    pub msaa: u16,
+
+    /// Terrain model to load, relative to the resource directory
+    #[arg(long, default_value = "lunarsurface.obj")]
+    pub scene: PathBuf,
+
+    /// Sky to draw behind the scene instead of the generated starfield: a directory with six images
+    /// named right, left, top, bottom, front and back, or one equirectangular image, e.g. an HDR
+    /// panorama
+    #[arg(long, value_name = "PATH")]
+    pub skybox: Option<PathBuf>,
+
+    /// Number of helicopters flying over the terrain
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    pub helicopters: u32,
+
+    /// Number of terrain tiles to stream in around the lunar surface in every direction, each with
+    /// a crashed helicopter on it. Tiles are loaded as the camera comes near.
+    #[arg(long, value_name = "TILES", default_value_t = 0)]
+    pub world_radius: u32,
+
+    /// Draw parked helicopters and dropped models one node at a time instead of merged into a
+    /// single mesh, e.g. for comparing draw calls
+    #[arg(long)]
+    pub no_batching: bool,
+
+    /// Directory to load models from
+    #[arg(long, default_value = "resources")]
+    pub resources: PathBuf,
+
+    /// Open a second window showing the scene from above
+    #[arg(long)]
+    pub debug_view: bool,
+
+    /// Show warnings in the window title as well as in the log. Set RUST_LOG to choose what is
+    /// logged, e.g. RUST_LOG=debug or RUST_LOG=warn,gloom_rs::app=debug
+    #[arg(long)]
+    pub show_warnings: bool,
+
+    /// Render without a window, saving the frames as PNG images instead. The size is in pixels.
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Print the OpenGL version, limits and extensions of a headless context and exit
+    #[arg(long)]
+    pub print_caps: bool,
+
+    /// Number of frames to render in headless mode
+    #[arg(long, default_value_t = 1, requires = "headless", value_parser = clap::value_parser!(u32).range(1..))]
+    pub frames: u32,
+
+    /// Directory to save the frames rendered in headless mode to, unless --capture is given
+    #[arg(
+        long,
+        value_name = "DIR",
+        default_value = "frames",
+        requires = "headless"
+    )]
+    pub output: PathBuf,
+
+    /// Save every frame, advancing time by a fixed step per frame. Paths ending in .mp4, .mkv,
+    /// .webm or .mov are encoded to video with ffmpeg, other paths are directories of PNG files.
+    #[arg(long, value_name = "PATH")]
+    pub capture: Option<PathBuf>,
+
+    /// Frame rate of the capture, which sets the fixed step time advances by
+    #[arg(long, value_name = "FPS", default_value_t = 60, value_parser = clap::value_parser!(u32).range(1..))]
+    pub capture_fps: u32,
+
+    /// Record input events to this file
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<String>,
+
+    /// Replay input events from this file with a fixed timestep
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<String>,
+
+    /// Render the same frames on every run, e.g. for apitrace or RenderDoc captures and golden
+    /// images: time advances by a fixed step per frame, models are loaded before they are needed
+    /// and --target-fps and --adaptive-vsync are ignored
+    #[arg(long)]
+    pub deterministic: bool,
+}
+
+impl Args {
+    // The terrain model, resolved against the resource directory
+    pub fn scene_path(&self) -> PathBuf {
+        self.resources.join(&self.scene)
+    }
+
+    pub fn resource_path(&self, name: &str) -> PathBuf {
+        self.resources.join(name)
+    }
+}
+
+// The context builder only accepts sample counts that are powers of two
+fn parse_msaa(value: &str) -> Result<u16, String> {
+    let samples: u16 = value.parse().map_err(|e| format!("{}", e))?;
+    if samples == 0 || samples.is_power_of_two() {
+        Ok(samples)
+    } else {
+        Err(format!("{} is not a power of two", samples))
+    }
+}
@@ -0,0 +1,549 @@
+// Finding out what touches what: rotors striking the ground, the camera going into it, and the
+// mouse picking a node.
+//
+// Queries run in two phases. The broad phase cheaply narrows things down by their bounds: the
+// octree for scene nodes, and for the triangles of one large mesh like the terrain, a bounding
+// volume hierarchy built once by `MeshCollider`. The narrow phase then tests the few candidates
+// left exactly, rays against triangles and spheres against boxes and triangles.
+//
+// Contacts are worked out afresh every frame, and `ContactTracker` turns them into events for when
+// they begin and end, so the application reacts once to a contact rather than every frame of it.
+use crate::mesh::Mesh;
+use crate::octree::Octree;
+use crate::scene_graph::SceneNode;
+use crate::toolbox::{self, Aabb, Ray};
+use std::collections::HashMap;
+
+// Triangles in a leaf of a `MeshCollider`'s hierarchy
+const LEAF_SIZE: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sphere {
+    pub center: glm::Vec3,
+    pub radius: f32,
+}
+
+// Where a sphere touches something, and which way to push it out
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    // The point of the other thing closest to the sphere's center
+    pub point: glm::Vec3,
+    // From there out towards the sphere's center
+    pub normal: glm::Vec3,
+    // How far the sphere reaches past the point
+    pub depth: f32,
+}
+
+// Whether and where a sphere touches a box. A sphere with its center inside is pushed out
+// through the nearest face.
+pub fn sphere_aabb(sphere: &Sphere, aabb: &Aabb) -> Option<Contact> {
+    let closest = glm::clamp_vec(&sphere.center, &aabb.min, &aabb.max);
+    let offset = sphere.center - closest;
+    let distance = glm::length(&offset);
+    if distance > sphere.radius {
+        return None;
+    }
+    if distance > 0.0 {
+        return Some(Contact {
+            point: closest,
+            normal: offset / distance,
+            depth: sphere.radius - distance,
+        });
+    }
+    let mut nearest: Option<(f32, Contact)> = None;
+    for axis in 0..3 {
+        for (face, side) in [(aabb.min[axis], -1.0), (aabb.max[axis], 1.0)] {
+            let distance = (face - sphere.center[axis]).abs();
+            if nearest.is_none_or(|(nearest, _)| distance < nearest) {
+                let (mut point, mut normal) = (sphere.center, glm::Vec3::zeros());
+                point[axis] = face;
+                normal[axis] = side;
+                let depth = sphere.radius + distance;
+                nearest = Some((
+                    distance,
+                    Contact {
+                        point,
+                        normal,
+                        depth,
+                    },
+                ));
+            }
+        }
+    }
+    nearest.map(|(_, contact)| contact)
+}
+
+// The point of triangle abc closest to `p`, found by which of the regions around the corners and
+// the edges it is in, as in Ericson's Real-Time Collision Detection
+pub fn closest_point_on_triangle(
+    p: &glm::Vec3,
+    a: &glm::Vec3,
+    b: &glm::Vec3,
+    c: &glm::Vec3,
+) -> glm::Vec3 {
+    let (ab, ac, ap) = (b - a, c - a, p - a);
+    let (d1, d2) = (glm::dot(&ab, &ap), glm::dot(&ac, &ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return *a;
+    }
+    let bp = p - b;
+    let (d3, d4) = (glm::dot(&ab, &bp), glm::dot(&ac, &bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return *b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = p - c;
+    let (d5, d6) = (glm::dot(&ab, &cp), glm::dot(&ac, &cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return *c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denominator = 1.0 / (va + vb + vc);
+    a + ab * (vb * denominator) + ac * (vc * denominator)
+}
+
+// Whether and where a sphere touches triangle abc. A center right on the triangle is pushed out
+// of its front, where the corners go counterclockwise.
+pub fn sphere_triangle(
+    sphere: &Sphere,
+    a: &glm::Vec3,
+    b: &glm::Vec3,
+    c: &glm::Vec3,
+) -> Option<Contact> {
+    let point = closest_point_on_triangle(&sphere.center, a, b, c);
+    let offset = sphere.center - point;
+    let distance = glm::length(&offset);
+    if distance > sphere.radius {
+        return None;
+    }
+    let normal = if distance > 1e-6 {
+        offset / distance
+    } else {
+        let face_normal = glm::cross(&(b - a), &(c - a));
+        if glm::length(&face_normal) == 0.0 {
+            return None;
+        }
+        glm::normalize(&face_normal)
+    };
+    Some(Contact {
+        point,
+        normal,
+        depth: sphere.radius - distance,
+    })
+}
+
+struct BvhNode {
+    bounds: Aabb,
+    // A leaf holds the triangles `first..first + count`, and other nodes have no triangles of
+    // their own but two children, `first` and `first + 1`
+    first: usize,
+    count: usize,
+}
+
+// The triangles of a mesh in a bounding volume hierarchy, so rays and spheres are only tested
+// against the triangles near them. Meant for large meshes that stay put, like the terrain, and
+// queried in the mesh's own space.
+pub struct MeshCollider {
+    triangles: Vec<[glm::Vec3; 3]>,
+    nodes: Vec<BvhNode>, // The first is the root
+}
+
+impl MeshCollider {
+    pub fn new(vertices: &[f32], indices: &[u32]) -> MeshCollider {
+        let vertex = |i: u32| {
+            let i = i as usize * 3;
+            glm::vec3(vertices[i], vertices[i + 1], vertices[i + 2])
+        };
+        let mut triangles: Vec<[glm::Vec3; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                [
+                    vertex(triangle[0]),
+                    vertex(triangle[1]),
+                    vertex(triangle[2]),
+                ]
+            })
+            .collect();
+        let mut nodes = vec![];
+        if !triangles.is_empty() {
+            nodes.push(BvhNode {
+                bounds: bounds_of(&triangles),
+                first: 0,
+                count: 0,
+            });
+            build(&mut triangles, 0, &mut nodes, 0);
+        }
+        MeshCollider { triangles, nodes }
+    }
+
+    pub fn from_mesh(mesh: &Mesh) -> MeshCollider {
+        MeshCollider::new(&mesh.vertices, &mesh.indices)
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.nodes.first().map(|root| root.bounds)
+    }
+
+    // Distance along the ray to where it first hits a triangle, like `toolbox::intersect_mesh`
+    pub fn raycast(&self, ray: &Ray) -> Option<f32> {
+        let mut closest: Option<f32> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.nodes.get(index) else {
+                break;
+            };
+            match node.bounds.intersect_ray(ray) {
+                Some(t) if closest.is_none_or(|closest| t < closest) => {}
+                _ => continue,
+            }
+            if node.count == 0 {
+                stack.extend([node.first, node.first + 1]);
+                continue;
+            }
+            for [a, b, c] in &self.triangles[node.first..node.first + node.count] {
+                if let Some(t) = toolbox::intersect_triangle(ray, a, b, c) {
+                    if closest.is_none_or(|closest| t < closest) {
+                        closest = Some(t);
+                    }
+                }
+            }
+        }
+        closest
+    }
+
+    // The deepest of the contacts between a sphere and the triangles
+    pub fn sphere_contact(&self, sphere: &Sphere) -> Option<Contact> {
+        let mut deepest: Option<Contact> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.nodes.get(index) else {
+                break;
+            };
+            if !node.bounds.intersects_sphere(&sphere.center, sphere.radius) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.extend([node.first, node.first + 1]);
+                continue;
+            }
+            for [a, b, c] in &self.triangles[node.first..node.first + node.count] {
+                if let Some(contact) = sphere_triangle(sphere, a, b, c) {
+                    if deepest.is_none_or(|deepest| contact.depth > deepest.depth) {
+                        deepest = Some(contact);
+                    }
+                }
+            }
+        }
+        deepest
+    }
+}
+
+fn bounds_of(triangles: &[[glm::Vec3; 3]]) -> Aabb {
+    let mut bounds = Aabb {
+        min: triangles[0][0],
+        max: triangles[0][0],
+    };
+    for corner in triangles.iter().flatten() {
+        bounds.min = glm::min2(&bounds.min, corner);
+        bounds.max = glm::max2(&bounds.max, corner);
+    }
+    bounds
+}
+
+// Make `nodes[node]`, whose bounds are already set, the root of a hierarchy over `triangles`,
+// which start at `first` in the collider's list. Triangles are split in two halves along the
+// axis their bounds are the longest on, until few enough are left for a leaf.
+fn build(triangles: &mut [[glm::Vec3; 3]], first: usize, nodes: &mut Vec<BvhNode>, node: usize) {
+    if triangles.len() <= LEAF_SIZE {
+        nodes[node].first = first;
+        nodes[node].count = triangles.len();
+        return;
+    }
+    let size = nodes[node].bounds.max - nodes[node].bounds.min;
+    let axis = if size.x >= size.y && size.x >= size.z {
+        0
+    } else if size.y >= size.z {
+        1
+    } else {
+        2
+    };
+    let middle = triangles.len() / 2;
+    let centroid =
+        |triangle: &[glm::Vec3; 3]| triangle[0][axis] + triangle[1][axis] + triangle[2][axis];
+    triangles.select_nth_unstable_by(middle, |a, b| centroid(a).total_cmp(&centroid(b)));
+
+    let (lower, upper) = triangles.split_at_mut(middle);
+    let children = nodes.len();
+    nodes[node].first = children;
+    for half in [&*lower, &*upper] {
+        nodes.push(BvhNode {
+            bounds: bounds_of(half),
+            first: 0,
+            count: 0,
+        });
+    }
+    build(lower, first, nodes, children);
+    build(upper, first + middle, nodes, children + 1);
+}
+
+// The closest node hit by a world-space ray. The octree finds the nodes whose bounds the ray goes
+// through, nearest first, and those with a mesh in `meshes`, by VAO, are hit only where the ray
+// meets one of its triangles. The rest are hit at their bounds.
+pub fn pick(
+    octree: &Octree,
+    ray: &Ray,
+    meshes: &HashMap<u32, &Mesh>,
+) -> Option<(f32, *mut SceneNode)> {
+    let mut closest: Option<(f32, *mut SceneNode)> = None;
+    for (entry, node) in octree.along_ray(ray) {
+        if closest.is_some_and(|(closest, _)| closest < entry) {
+            break;
+        }
+        let Some(transform) = octree.transform(node) else {
+            continue;
+        };
+        let local_ray = ray.transformed(&glm::inverse(transform));
+        let hit = match meshes.get(&unsafe { &*node }.vao_id) {
+            Some(mesh) => toolbox::intersect_mesh(&local_ray, &mesh.vertices, &mesh.indices),
+            None => Some(entry),
+        };
+        if let Some(t) = hit {
+            if closest.is_none_or(|(closest, _)| t < closest) {
+                closest = Some((t, node));
+            }
+        }
+    }
+    closest
+}
+
+// The nodes a world-space sphere touches the bounds of, with where, in world space. The bounds
+// are boxes in each node's own space, so they turn with it.
+pub fn sphere_nodes(octree: &Octree, sphere: &Sphere) -> Vec<(*mut SceneNode, Contact)> {
+    octree
+        .within(&sphere.center, sphere.radius)
+        .into_iter()
+        .filter_map(|node| {
+            let transform = octree.transform(node)?;
+            let bounds = unsafe { &*node }.bounds?;
+            // Scaled nodes scale the sphere alike, by their largest scale to be safe
+            let scale = (0..3)
+                .map(|column| glm::length(&glm::column(transform, column).xyz()))
+                .fold(0.0, f32::max);
+            let local = Sphere {
+                center: (glm::inverse(transform) * sphere.center.push(1.0)).xyz(),
+                radius: sphere.radius / scale,
+            };
+            let contact = sphere_aabb(&local, &bounds)?;
+            let point = (transform * contact.point.push(1.0)).xyz();
+            let normal = glm::normalize(&(transform * contact.normal.push(0.0)).xyz());
+            let contact = Contact {
+                point,
+                normal,
+                depth: contact.depth * scale,
+            };
+            Some((node, contact))
+        })
+        .collect()
+}
+
+// A contact, or a contact of `K` that has ended
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CollisionEvent<K> {
+    Began(K),
+    Ended(K),
+}
+
+// Which contacts there were at the last update, to tell when they begin and end. `K` says what
+// touches what, e.g. which helicopter and which obstacle.
+pub struct ContactTracker<K> {
+    touching: Vec<K>,
+}
+
+impl<K: PartialEq + Clone> ContactTracker<K> {
+    pub fn new() -> ContactTracker<K> {
+        ContactTracker { touching: vec![] }
+    }
+
+    // Compare the contacts there are now with those at the last update. Contacts beginning come
+    // first, in the order given, and then those ending.
+    pub fn update(&mut self, touching: impl IntoIterator<Item = K>) -> Vec<CollisionEvent<K>> {
+        let mut now: Vec<K> = vec![];
+        for key in touching {
+            if !now.contains(&key) {
+                now.push(key);
+            }
+        }
+        let began = now
+            .iter()
+            .filter(|key| !self.touching.contains(key))
+            .map(|key| CollisionEvent::Began(key.clone()));
+        let ended = self
+            .touching
+            .iter()
+            .filter(|key| !now.contains(key))
+            .map(|key| CollisionEvent::Ended(key.clone()));
+        let events = began.chain(ended).collect();
+        self.touching = now;
+        events
+    }
+
+    pub fn is_touching(&self, key: &K) -> bool {
+        self.touching.contains(key)
+    }
+}
+
+impl<K: PartialEq + Clone> Default for ContactTracker<K> {
+    fn default() -> ContactTracker<K> {
+        ContactTracker::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &glm::Vec3, b: &glm::Vec3) {
+        assert!(glm::distance(a, b) < 1e-4, "{} is not close to {}", a, b);
+    }
+
+    // A bumpy grid of `size` by `size` squares, two triangles each
+    fn grid(size: u32) -> (Vec<f32>, Vec<u32>) {
+        let mut vertices = vec![];
+        for z in 0..=size {
+            for x in 0..=size {
+                let height = ((x * 7 + z * 13) % 5) as f32 * 0.3;
+                vertices.extend_from_slice(&[x as f32, height, z as f32]);
+            }
+        }
+        let mut indices = vec![];
+        for z in 0..size {
+            for x in 0..size {
+                let corner = z * (size + 1) + x;
+                let below = corner + size + 1;
+                indices.extend_from_slice(&[
+                    corner,
+                    below,
+                    corner + 1,
+                    corner + 1,
+                    below,
+                    below + 1,
+                ]);
+            }
+        }
+        (vertices, indices)
+    }
+
+    #[test]
+    fn closest_points_on_triangles_lie_in_each_region() {
+        let (a, b, c) = (
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(2.0, 0.0, 0.0),
+            glm::vec3(0.0, 2.0, 0.0),
+        );
+        let closest = |x, y, z| closest_point_on_triangle(&glm::vec3(x, y, z), &a, &b, &c);
+        assert_close(&closest(-1.0, -1.0, 3.0), &a);
+        assert_close(&closest(3.0, -1.0, 0.0), &b);
+        assert_close(&closest(1.0, -1.0, 0.0), &glm::vec3(1.0, 0.0, 0.0));
+        assert_close(&closest(2.0, 2.0, 1.0), &glm::vec3(1.0, 1.0, 0.0));
+        assert_close(&closest(0.5, 0.5, -4.0), &glm::vec3(0.5, 0.5, 0.0));
+    }
+
+    #[test]
+    fn spheres_are_pushed_out_of_boxes_the_shortest_way() {
+        let unit = Aabb {
+            min: glm::vec3(-1.0, -1.0, -1.0),
+            max: glm::vec3(1.0, 1.0, 1.0),
+        };
+        let touching = Sphere {
+            center: glm::vec3(1.5, 0.0, 0.0),
+            radius: 1.0,
+        };
+        let contact = sphere_aabb(&touching, &unit).unwrap();
+        assert_close(&contact.normal, &glm::vec3(1.0, 0.0, 0.0));
+        assert!((contact.depth - 0.5).abs() < 1e-5);
+
+        let inside = Sphere {
+            center: glm::vec3(0.0, -0.75, 0.0),
+            radius: 0.5,
+        };
+        let contact = sphere_aabb(&inside, &unit).unwrap();
+        assert_close(&contact.normal, &glm::vec3(0.0, -1.0, 0.0));
+        assert!((contact.depth - 0.75).abs() < 1e-5);
+
+        let apart = Sphere {
+            center: glm::vec3(2.0, 2.0, 0.0),
+            radius: 1.0,
+        };
+        assert!(sphere_aabb(&apart, &unit).is_none());
+    }
+
+    #[test]
+    fn mesh_colliders_find_what_testing_every_triangle_finds() {
+        let (vertices, indices) = grid(20);
+        let collider = MeshCollider::new(&vertices, &indices);
+        for i in 0..50 {
+            let i = i as f32;
+            let ray = Ray {
+                origin: glm::vec3(i * 0.37 % 20.0, 5.0, i * 0.71 % 20.0),
+                direction: glm::vec3((i * 0.3).sin(), -1.0, (i * 0.7).cos()),
+            };
+            let expected = toolbox::intersect_mesh(&ray, &vertices, &indices);
+            let found = collider.raycast(&ray);
+            match (expected, found) {
+                (Some(expected), Some(found)) => assert!((expected - found).abs() < 1e-4),
+                (expected, found) => assert_eq!(expected.is_some(), found.is_some()),
+            }
+        }
+
+        // Resting on the surface, a little into it
+        let height = ((3 * 7 + 4 * 13) % 5) as f32 * 0.3;
+        let sphere = Sphere {
+            center: glm::vec3(3.0, height + 0.4, 4.0),
+            radius: 0.5,
+        };
+        let contact = collider.sphere_contact(&sphere).unwrap();
+        assert!(contact.depth > 0.0 && contact.normal.y > 0.0);
+        let above = Sphere {
+            center: glm::vec3(3.0, 10.0, 4.0),
+            radius: 0.5,
+        };
+        assert!(collider.sphere_contact(&above).is_none());
+        assert!(MeshCollider::new(&[], &[]).raycast(&ray_down()).is_none());
+    }
+
+    fn ray_down() -> Ray {
+        Ray {
+            origin: glm::vec3(0.0, 10.0, 0.0),
+            direction: glm::vec3(0.0, -1.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn contacts_begin_and_end_once() {
+        let mut tracker = ContactTracker::new();
+        assert_eq!(
+            tracker.update(["rotor"]),
+            vec![CollisionEvent::Began("rotor")]
+        );
+        assert_eq!(tracker.update(["rotor", "rotor"]), vec![]);
+        assert!(tracker.is_touching(&"rotor"));
+        assert_eq!(
+            tracker.update(["skid"]),
+            vec![
+                CollisionEvent::Began("skid"),
+                CollisionEvent::Ended("rotor")
+            ]
+        );
+        assert_eq!(tracker.update([]), vec![CollisionEvent::Ended("skid")]);
+    }
+}
@@ -0,0 +1,118 @@
+// Rendering work recorded as data, to be replayed on the thread that owns the context.
+//
+// Traversing the scene graph, computing matrices and deciding what to draw needs no OpenGL, so it
+// can run anywhere: on rayon's thread pool, on an update thread, or ahead of time. What it
+// produces is a `CommandList`, which the render thread executes against a `Backend` in order.
+use crate::backend::{Backend, DrawCall, MeshHandle, PipelineHandle, TextureHandle};
+use crate::material::Material;
+use std::sync::Arc;
+
+// An owned `DrawCall`, so it can be sent between threads
+#[derive(Clone, Debug)]
+pub struct DrawCommand {
+    pub mesh: MeshHandle,
+    pub index_count: i32,
+    pub transform: glm::Mat4, // Model-view-projection matrix
+    pub model: glm::Mat4,
+    pub previous_model: glm::Mat4, // Where the mesh was last frame, for motion blur
+    pub highlight: f32,
+    pub opacity: f32,
+    pub joints: Option<Arc<[glm::Mat4]>>, // Shared with the node, as skinned meshes are few
+    pub normal_map: Option<TextureHandle>,
+    pub material: Option<Material>,
+}
+
+// Draws are nearly all of the commands, so boxing them would only allocate for each one
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug)]
+pub enum Command {
+    SetPipeline(PipelineHandle),
+    Draw(DrawCommand),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CommandList {
+    commands: Vec<Command>,
+}
+
+impl CommandList {
+    pub fn new() -> CommandList {
+        CommandList { commands: vec![] }
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: PipelineHandle) {
+        self.commands.push(Command::SetPipeline(pipeline));
+    }
+
+    pub fn draw(&mut self, draw: DrawCommand) {
+        self.commands.push(Command::Draw(draw));
+    }
+
+    // Append `count` empty draws and return them to be filled in, e.g. from several threads at once
+    pub fn append_slots(&mut self, count: usize) -> &mut [Command] {
+        let start = self.commands.len();
+        let empty = Command::Draw(DrawCommand {
+            mesh: MeshHandle(0),
+            index_count: 0,
+            transform: glm::zero(),
+            model: glm::zero(),
+            previous_model: glm::zero(),
+            highlight: 0.0,
+            opacity: 1.0,
+            joints: None,
+            normal_map: None,
+            material: None,
+        });
+        self.commands.resize(start + count, empty);
+        &mut self.commands[start..]
+    }
+
+    // Remove every command, keeping the memory for recording the next frame
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    // Move the commands of `other` to the end of this list
+    pub fn append(&mut self, mut other: CommandList) {
+        self.commands.append(&mut other.commands);
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    // Replay the commands. The list is kept, e.g. for drawing the same frame again at another size.
+    pub fn execute(&self, backend: &mut dyn Backend) {
+        self.execute_where(backend, |_| true);
+    }
+
+    // Replay the commands, leaving out the draws `keep` says no to, e.g. the see-through ones
+    pub fn execute_where(&self, backend: &mut dyn Backend, keep: impl Fn(&DrawCommand) -> bool) {
+        for command in &self.commands {
+            match command {
+                Command::SetPipeline(pipeline) => backend.set_pipeline(*pipeline),
+                Command::Draw(draw) if !keep(draw) => {}
+                Command::Draw(draw) => backend.draw(&DrawCall {
+                    mesh: draw.mesh,
+                    index_count: draw.index_count,
+                    transform: &draw.transform,
+                    model: &draw.model,
+                    previous_model: &draw.previous_model,
+                    highlight: draw.highlight,
+                    opacity: draw.opacity,
+                    joints: draw.joints.as_deref().unwrap_or_default(),
+                    normal_map: draw.normal_map,
+                    material: draw.material,
+                }),
+            }
+        }
+    }
+}
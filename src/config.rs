@@ -0,0 +1,279 @@
+use crate::camera::FreeCamera;
+use crate::error::ConfigError;
+use crate::util::{DebugFilter, DEBUG_SOURCES};
+use log::warn;
+use std::fs;
+
+// Settings that can be changed at runtime and are persisted between runs.
+//
+// The config file is a plain text file with one `key = value` pair per line. Lines starting
+// with `#` are comments. Missing keys keep their default value, so an empty or missing file is
+// perfectly valid.
+pub const CONFIG_PATH: &str = "gloom.cfg";
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub look_sensitivity: f32, // radians per pixel of mouse movement
+    pub invert_y: bool,        // Flip vertical look direction
+    pub stick_deadzone: f32,   // Sticks nearer their middle than this, 0-1, count as centered
+    pub raw_mouse_input: bool, // Look with raw device motion rather than OS-accelerated cursor movement
+    pub fullscreen: bool,      // Start in borderless fullscreen, toggle with F11
+    pub screenshot_scale: u32, // Render F12 screenshots at this multiple of the window size, 1-4
+    pub background_fps: u32,   // Frame rate while the window doesn't have focus, 0 pauses rendering
+    pub gl_debug: DebugFilter, // Which OpenGL debug messages are logged, see `util::set_debug_filter`
+
+    pub bookmarks: [Option<FreeCamera>; 9], // Saved camera viewpoints, recalled with 1..9
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            look_sensitivity: 0.005,
+            invert_y: false,
+            stick_deadzone: 0.15,
+            raw_mouse_input: true,
+            fullscreen: false,
+            screenshot_scale: 1,
+            background_fps: 15,
+            gl_debug: DebugFilter::default(),
+            bookmarks: [None; 9],
+        }
+    }
+}
+
+impl Config {
+    // Load the config from disk, falling back to defaults for anything missing or malformed
+    pub fn load(path: &str) -> Config {
+        match fs::read_to_string(path) {
+            Ok(contents) => Config::parse(&contents, path),
+            Err(_) => Config::default(),
+        }
+    }
+
+    // Read the `contents` of a config file, naming `path` in the warnings for malformed lines
+    pub fn parse(contents: &str, path: &str) -> Config {
+        let mut config = Config::default();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    warn!("{}:{}: {}", path, line_number + 1, ConfigError::Syntax);
+                    continue;
+                }
+            };
+            if let Err(e) = config.set(key, value) {
+                warn!("{}:{}: {}", path, line_number + 1, e);
+            }
+        }
+
+        config
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), ConfigError> {
+        fs::write(path, self.to_string()).map_err(|source| ConfigError::Save {
+            path: path.to_string(),
+            source,
+        })
+    }
+
+    // Set a single setting from its textual representation
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "look_sensitivity" => self.look_sensitivity = parse(key, value)?,
+            "invert_y" => self.invert_y = parse(key, value)?,
+            "stick_deadzone" => {
+                let deadzone = parse(key, value)?;
+                if !(0.0..1.0).contains(&deadzone) {
+                    return Err(ConfigError::OutOfRange {
+                        key: key.to_string(),
+                        range: "at least 0 and less than 1",
+                        value: value.to_string(),
+                    });
+                }
+                self.stick_deadzone = deadzone;
+            }
+            // Earlier versions applied a deadzone to the mouse, which only lost slow movement
+            "look_deadzone" => {}
+            "raw_mouse_input" => self.raw_mouse_input = parse(key, value)?,
+            "fullscreen" => self.fullscreen = parse(key, value)?,
+            "screenshot_scale" => {
+                let scale = parse(key, value)?;
+                if !(1..=4).contains(&scale) {
+                    return Err(ConfigError::OutOfRange {
+                        key: key.to_string(),
+                        range: "1-4",
+                        value: value.to_string(),
+                    });
+                }
+                self.screenshot_scale = scale;
+            }
+            "background_fps" => self.background_fps = parse(key, value)?,
+            "gl_debug_severity" => self.gl_debug.min_severity = parse(key, value)?,
+            "gl_debug_ignore_sources" => {
+                self.gl_debug.ignored_sources = value
+                    .split_whitespace()
+                    .map(|name| {
+                        DEBUG_SOURCES
+                            .iter()
+                            .find(|&&(_, source_name)| source_name == name)
+                            .map(|&(source, _)| source)
+                            .ok_or_else(|| ConfigError::InvalidValue {
+                                key: key.to_string(),
+                                value: name.to_string(),
+                            })
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            "gl_debug_ignore_ids" => {
+                self.gl_debug.ignored_ids = value
+                    .split_whitespace()
+                    .map(|id| parse(key, id))
+                    .collect::<Result<_, _>>()?;
+            }
+            "gl_debug_panic" => self.gl_debug.panic_on_error = parse(key, value)?,
+            "gl_debug_rate_limit" => self.gl_debug.rate_limit = parse(key, value)?,
+            _ if key.starts_with("bookmark") => {
+                let slot: usize = parse(key, &key["bookmark".len()..])?;
+                if !(1..=9).contains(&slot) {
+                    return Err(ConfigError::OutOfRange {
+                        key: "bookmark slot".to_string(),
+                        range: "1-9",
+                        value: slot.to_string(),
+                    });
+                }
+                let numbers = value
+                    .split_whitespace()
+                    .map(|n| parse(key, n))
+                    .collect::<Result<Vec<f32>, _>>()?;
+                if numbers.len() != 5 {
+                    return Err(ConfigError::WrongFormat {
+                        key: key.to_string(),
+                        format: "x y z yaw pitch",
+                    });
+                }
+                self.bookmarks[slot - 1] = Some(FreeCamera::new(
+                    glm::vec3(numbers[0], numbers[1], numbers[2]),
+                    numbers[3],
+                    numbers[4],
+                ));
+            }
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "# gloom-rs settings")?;
+        writeln!(f, "look_sensitivity = {}", self.look_sensitivity)?;
+        writeln!(f, "invert_y = {}", self.invert_y)?;
+        writeln!(f, "stick_deadzone = {}", self.stick_deadzone)?;
+        writeln!(f, "raw_mouse_input = {}", self.raw_mouse_input)?;
+        writeln!(f, "fullscreen = {}", self.fullscreen)?;
+        writeln!(f, "screenshot_scale = {}", self.screenshot_scale)?;
+        writeln!(f, "background_fps = {}", self.background_fps)?;
+        let gl_debug = &self.gl_debug;
+        writeln!(f, "gl_debug_severity = {}", gl_debug.min_severity.name())?;
+        let sources: Vec<_> = DEBUG_SOURCES
+            .iter()
+            .filter(|(source, _)| gl_debug.ignored_sources.contains(source))
+            .map(|&(_, name)| name)
+            .collect();
+        writeln!(f, "gl_debug_ignore_sources = {}", sources.join(" "))?;
+        let ids: Vec<_> = gl_debug.ignored_ids.iter().map(u32::to_string).collect();
+        writeln!(f, "gl_debug_ignore_ids = {}", ids.join(" "))?;
+        writeln!(f, "gl_debug_panic = {}", gl_debug.panic_on_error)?;
+        writeln!(f, "gl_debug_rate_limit = {}", gl_debug.rate_limit)?;
+        for (i, bookmark) in self.bookmarks.iter().enumerate() {
+            if let Some(camera) = bookmark {
+                writeln!(
+                    f,
+                    "bookmark{} = {} {} {} {} {}",
+                    i + 1,
+                    camera.position.x,
+                    camera.position.y,
+                    camera.position.z,
+                    camera.yaw,
+                    camera.pitch
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| ConfigError::InvalidValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::DebugSeverity;
+
+    #[test]
+    fn saved_settings_are_loaded_back() {
+        let mut config = Config {
+            look_sensitivity: 0.0125,
+            invert_y: true,
+            stick_deadzone: 0.25,
+            raw_mouse_input: false,
+            fullscreen: true,
+            screenshot_scale: 3,
+            background_fps: 0,
+            ..Config::default()
+        };
+        config.gl_debug.min_severity = DebugSeverity::High;
+        config.gl_debug.ignored_sources = vec![DEBUG_SOURCES[1].0];
+        config.gl_debug.ignored_ids = vec![131185, 7];
+        config.bookmarks[4] = Some(FreeCamera::new(glm::vec3(1.0, -2.5, 30.0), 0.5, -0.25));
+
+        let path = std::env::temp_dir().join(format!("gloom-config-{}.cfg", std::process::id()));
+        let path = path.to_string_lossy();
+        config.save(&path).unwrap();
+        let loaded = Config::load(&path);
+        std::fs::remove_file(&*path).unwrap();
+        assert_eq!(loaded.to_string(), config.to_string());
+        assert!(loaded.invert_y && !loaded.raw_mouse_input);
+        assert_eq!(loaded.gl_debug.ignored_ids, vec![131185, 7]);
+        let bookmark = loaded.bookmarks[4].unwrap();
+        assert_eq!(bookmark.position, glm::vec3(1.0, -2.5, 30.0));
+        assert!(loaded.bookmarks.iter().filter(|b| b.is_some()).count() == 1);
+    }
+
+    #[test]
+    fn malformed_lines_keep_their_defaults() {
+        let contents = "\
+            # A comment\n\
+            screenshot_scale = 9\n\
+            stick_deadzone = 1\n\
+            invert_y = maybe\n\
+            background_fps\n\
+            no_such_key = 1\n\
+            bookmark3 = 1 2 3\n\
+            look_deadzone = 0.5\n\
+            fullscreen = true\n";
+        let config = Config::parse(contents, "test.cfg");
+        let defaults = Config::default();
+        assert_eq!(config.screenshot_scale, defaults.screenshot_scale);
+        assert_eq!(config.stick_deadzone, defaults.stick_deadzone);
+        assert_eq!(config.invert_y, defaults.invert_y);
+        assert_eq!(config.background_fps, defaults.background_fps);
+        assert!(config.bookmarks[2].is_none());
+        assert!(config.fullscreen);
+    }
+
+    #[test]
+    fn missing_files_give_the_defaults() {
+        let config = Config::load("/nonexistent/gloom.cfg");
+        assert_eq!(config.to_string(), Config::default().to_string());
+    }
+}
@@ -0,0 +1,415 @@
+// A drop-down developer console, opened and closed with the key left of 1, ` or ~ on US layouts.
+//
+// Lines typed into it run commands from a `CommandRegistry`, which applications fill with commands
+// of their own, like `set fov 60` or `spawn helicopter 3`. Command names can be several words
+// long and lines run the command with the longest name they start with, so modules can each add
+// their own `set` or `toggle` commands without a central list of them. `help` lists the commands,
+// and `clear` empties the console.
+use crate::app::Context;
+use crate::backend::gl::GlBackend;
+use crate::backend::PipelineHandle;
+use crate::error::CommandError;
+use crate::input::{FrameInput, TextInput};
+use crate::text::TextRenderer;
+use std::collections::VecDeque;
+use std::str::FromStr;
+use winit::keyboard::KeyCode;
+
+pub const TOGGLE_KEY: KeyCode = KeyCode::Backquote;
+
+const SCROLLBACK: usize = 200; // Lines of output kept
+const HISTORY: usize = 50; // Lines typed before that can be brought back with Up and Down
+const VISIBLE_LINES: usize = 16;
+// Screen pixels per font pixel
+const SCALE: f32 = 2.0;
+const MARGIN: f32 = 8.0;
+
+const BACKGROUND_COLOR: [f32; 4] = [0.02, 0.03, 0.06, 0.85];
+const INPUT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const OUTPUT_COLOR: [f32; 4] = [0.75, 0.85, 0.95, 1.0];
+const ERROR_COLOR: [f32; 4] = [1.0, 0.45, 0.4, 1.0];
+
+// The words after a command's name
+pub struct Arguments<'a> {
+    words: Vec<&'a str>,
+}
+
+impl Arguments<'_> {
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    // The argument at `index` as a `T`. Missing or malformed arguments make the command fail with
+    // its usage.
+    pub fn get<T: FromStr>(&self, index: usize) -> Result<T, CommandError> {
+        self.words
+            .get(index)
+            .and_then(|word| word.parse().ok())
+            .ok_or(CommandError::Usage)
+    }
+
+    // Like `get`, but `default` if there are no more than `index` arguments
+    pub fn get_or<T: FromStr>(&self, index: usize, default: T) -> Result<T, CommandError> {
+        if index < self.words.len() {
+            self.get(index)
+        } else {
+            Ok(default)
+        }
+    }
+}
+
+// Runs a command on the application `T`, returning what to print
+type Handler<T> = Box<dyn Fn(&mut T, &mut Context, &Arguments) -> Result<String, CommandError>>;
+
+struct Command<T> {
+    name: String,
+    usage: String, // The arguments, e.g. `<degrees>`
+    help: String,
+    handler: Handler<T>,
+}
+
+impl<T> Command<T> {
+    // `e` with its usage in place of `CommandError::Usage`
+    fn explain(&self, e: CommandError) -> CommandError {
+        match e {
+            CommandError::Usage => {
+                CommandError::Failed(format!("Usage: {} {}", self.name, self.usage))
+            }
+            e => e,
+        }
+    }
+}
+
+pub struct CommandRegistry<T> {
+    commands: Vec<Command<T>>,
+}
+
+impl<T> Default for CommandRegistry<T> {
+    fn default() -> Self {
+        CommandRegistry::new()
+    }
+}
+
+impl<T> CommandRegistry<T> {
+    pub fn new() -> CommandRegistry<T> {
+        CommandRegistry { commands: vec![] }
+    }
+
+    // Add a command called `name`, one or more words, taking the arguments described by `usage`,
+    // e.g. `[count]` for an optional one. A command registered under the name of another replaces
+    // it.
+    pub fn register<F>(&mut self, name: &str, usage: &str, help: &str, handler: F)
+    where
+        F: Fn(&mut T, &mut Context, &Arguments) -> Result<String, CommandError> + 'static,
+    {
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        self.commands.retain(|command| command.name != name);
+        self.commands.push(Command {
+            name,
+            usage: usage.to_string(),
+            help: help.to_string(),
+            handler: Box::new(handler),
+        });
+        self.commands.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    // Run the command `line` starts with on `target`, returning what it printed
+    pub fn execute(
+        &self,
+        target: &mut T,
+        ctx: &mut Context,
+        line: &str,
+    ) -> Result<String, CommandError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first() {
+            None => return Ok(String::new()),
+            Some(&"help") => return Ok(self.help(&words[1..].join(" "))),
+            Some(_) => {}
+        }
+        let (command, arguments) = self.find(&words)?;
+        (command.handler)(target, ctx, &arguments).map_err(|e| command.explain(e))
+    }
+
+    // The command with the longest name `words` start with, and the words after its name
+    fn find<'a>(&self, words: &[&'a str]) -> Result<(&Command<T>, Arguments<'a>), CommandError> {
+        let command = self
+            .commands
+            .iter()
+            .filter(|command| {
+                let name = command.name.split(' ');
+                name.clone().count() <= words.len() && name.zip(words).all(|(a, &b)| a == b)
+            })
+            .max_by_key(|command| command.name.len())
+            .ok_or_else(|| CommandError::Unknown(words[0].to_string()))?;
+        let arguments = Arguments {
+            words: words[command.name.split(' ').count()..].to_vec(),
+        };
+        Ok((command, arguments))
+    }
+
+    // A line for each command whose name starts with `topic`
+    fn help(&self, topic: &str) -> String {
+        let lines: Vec<String> = self
+            .commands
+            .iter()
+            .filter(|command| command.name.starts_with(topic))
+            .map(|command| format!("{} {} - {}", command.name, command.usage, command.help))
+            .collect();
+        if lines.is_empty() {
+            format!("No commands start with `{}`", topic)
+        } else {
+            lines.join("\n")
+        }
+    }
+
+    // `line` completed as far as the names of the commands it could be the start of agree
+    fn complete(&self, line: &str) -> Option<String> {
+        let mut names = self
+            .commands
+            .iter()
+            .map(|command| command.name.as_str())
+            .chain(["help", "clear"].iter().copied())
+            .filter(|name| name.starts_with(line));
+        let first = names.next()?;
+        let common = names.fold(first, |common, name| {
+            let length = common
+                .char_indices()
+                .zip(name.chars())
+                .take_while(|((_, a), b)| a == b)
+                .last()
+                .map_or(0, |((i, a), _)| i + a.len_utf8());
+            &common[..length]
+        });
+        // A single match is complete, ready for its arguments
+        let completed = if common == first && first.len() > line.len() {
+            format!("{} ", common)
+        } else {
+            common.to_string()
+        };
+        Some(completed).filter(|completed| completed.len() > line.len())
+    }
+}
+
+pub struct Console {
+    pub open: bool,
+    input: TextInput,
+    output: VecDeque<(String, [f32; 4])>,
+    history: VecDeque<String>,    // The latest last
+    history_index: Option<usize>, // Which line of the history is being edited, if any
+    text: TextRenderer,
+}
+
+impl Console {
+    pub unsafe fn new() -> Console {
+        Console {
+            open: false,
+            input: TextInput::default(),
+            output: VecDeque::with_capacity(SCROLLBACK),
+            history: VecDeque::with_capacity(HISTORY),
+            history_index: None,
+            text: TextRenderer::new(),
+        }
+    }
+
+    pub fn print(&mut self, text: &str) {
+        self.print_colored(text, OUTPUT_COLOR);
+    }
+
+    fn print_colored(&mut self, text: &str, color: [f32; 4]) {
+        for line in text.lines() {
+            if self.output.len() == SCROLLBACK {
+                self.output.pop_front();
+            }
+            self.output.push_back((line.to_string(), color));
+        }
+    }
+
+    // Open or close the console with `TOGGLE_KEY`, and edit the line being typed while it is
+    // open. Returns the line when Return is pressed, to be run with `CommandRegistry::execute` and
+    // its result handed to `report`. The console uses every key while open, so the application
+    // should ignore them, see `Context::set_text_input_active`.
+    pub fn update<T>(
+        &mut self,
+        input: &FrameInput,
+        commands: &CommandRegistry<T>,
+    ) -> Option<String> {
+        let keys = &input.keys;
+        if keys.just_pressed(TOGGLE_KEY) || (self.open && keys.just_pressed(KeyCode::Escape)) {
+            // The toggle key's character isn't typed
+            self.open = !self.open;
+            self.input.cancel();
+            self.input.active = self.open;
+            self.history_index = None;
+            return None;
+        }
+        if !self.open {
+            return None;
+        }
+
+        self.input.type_text(&input.text);
+        if keys.just_pressed(KeyCode::Tab) {
+            if let Some(completed) = commands.complete(&self.input.buffer) {
+                self.input.buffer = completed;
+            }
+        }
+        if keys.just_pressed(KeyCode::ArrowUp) && !self.history.is_empty() {
+            let index = self.history_index.map_or(self.history.len(), |i| i).max(1) - 1;
+            self.history_index = Some(index);
+            self.input.buffer = self.history[index].clone();
+        }
+        if keys.just_pressed(KeyCode::ArrowDown) {
+            if let Some(index) = self.history_index {
+                self.history_index = Some(index + 1).filter(|&i| i < self.history.len());
+                self.input.buffer = match self.history_index {
+                    Some(i) => self.history[i].clone(),
+                    None => String::new(),
+                };
+            }
+        }
+        if !keys.just_pressed(KeyCode::Enter) {
+            return None;
+        }
+
+        let line = self.input.submit();
+        self.input.active = true;
+        self.history_index = None;
+        self.print_colored(&format!("> {}", line), INPUT_COLOR);
+        let line = line.trim().to_string();
+        if line.is_empty() {
+            return None;
+        }
+        if self.history.back() != Some(&line) {
+            if self.history.len() == HISTORY {
+                self.history.pop_front();
+            }
+            self.history.push_back(line.clone());
+        }
+        if line == "clear" {
+            self.output.clear();
+            return None;
+        }
+        Some(line)
+    }
+
+    // Print what running a line returned by `update` printed, or why it failed
+    pub fn report(&mut self, result: Result<String, CommandError>) {
+        match result {
+            Ok(output) => self.print(&output),
+            Err(e) => self.print_colored(&e.to_string(), ERROR_COLOR),
+        }
+    }
+
+    // Draw the console over everything drawn so far, if it is open, with `pipeline` loaded from
+    // the `overlay` shaders
+    pub unsafe fn draw(
+        &mut self,
+        backend: &mut GlBackend,
+        pipeline: PipelineHandle,
+        viewport_size: (u32, u32),
+    ) {
+        if !self.open {
+            return;
+        }
+        let line_height = TextRenderer::line_height(SCALE);
+        let width = viewport_size.0 as f32;
+        let height = (VISIBLE_LINES + 1) as f32 * line_height + 2.0 * MARGIN;
+        self.text.rect(0.0, 0.0, width, height, BACKGROUND_COLOR);
+
+        let visible = self.output.len().min(VISIBLE_LINES);
+        let first_visible = self.output.len() - visible;
+        for (i, (line, color)) in self.output.iter().skip(first_visible).enumerate() {
+            let y = MARGIN + (VISIBLE_LINES - visible + i) as f32 * line_height;
+            self.text.text(MARGIN, y, SCALE, *color, line);
+        }
+        let prompt = format!("> {}_", self.input.buffer);
+        let y = MARGIN + VISIBLE_LINES as f32 * line_height;
+        self.text.text(MARGIN, y, SCALE, INPUT_COLOR, &prompt);
+
+        self.text.draw(backend, pipeline, viewport_size);
+    }
+
+    // The old buffers went away with the context
+    pub unsafe fn context_recreated(&mut self) {
+        self.text = TextRenderer::new();
+    }
+
+    pub unsafe fn delete(&mut self) {
+        self.text.delete();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> CommandRegistry<()> {
+        let mut registry = CommandRegistry::new();
+        for (name, usage) in [
+            ("set", "<name> <value>"),
+            ("set fov", "<degrees>"),
+            ("spawn helicopter", "[count]"),
+            ("spawn", "<what>"),
+            ("sprängämne", ""),
+            ("sprängöl", ""),
+            ("sprint", ""),
+        ]
+        .iter()
+        .copied()
+        {
+            registry.register(name, usage, "", |_, _, _| Ok(String::new()));
+        }
+        registry
+    }
+
+    #[test]
+    fn lines_run_the_command_with_the_longest_name_they_start_with() {
+        let registry = registry();
+        let (command, arguments) = registry.find(&["set", "fov", "60"]).unwrap();
+        assert_eq!(
+            (command.name.as_str(), arguments.words),
+            ("set fov", vec!["60"])
+        );
+        let (command, arguments) = registry.find(&["set", "fog", "1"]).unwrap();
+        assert_eq!(
+            (command.name.as_str(), arguments.words),
+            ("set", vec!["fog", "1"])
+        );
+        // Names match whole words only
+        let (command, _) = registry.find(&["spawn", "helicopters"]).unwrap();
+        assert_eq!(command.name, "spawn");
+        match registry.find(&["fly", "away"]) {
+            Err(CommandError::Unknown(word)) => assert_eq!(word, "fly"),
+            _ => panic!("`fly` is not a command"),
+        }
+    }
+
+    #[test]
+    fn lines_are_completed_as_far_as_the_commands_agree() {
+        let registry = registry();
+        assert_eq!(registry.complete("sp"), None);
+        assert_eq!(
+            registry.complete("spawn h"),
+            Some("spawn helicopter ".to_string())
+        );
+        assert_eq!(registry.complete("he"), Some("help ".to_string()));
+        // Common prefixes can run past multi-byte characters and end before differing ones
+        assert_eq!(registry.complete("sprä"), Some("spräng".to_string()));
+        assert_eq!(registry.complete("språ"), None);
+        assert_eq!(registry.complete("spawn helicopter "), None);
+        assert_eq!(registry.complete("x"), None);
+    }
+
+    #[test]
+    fn usage_errors_show_the_commands_usage() {
+        let registry = registry();
+        let (command, arguments) = registry.find(&["spawn", "helicopter", "many"]).unwrap();
+        let e = command.explain(arguments.get::<u32>(0).unwrap_err());
+        assert_eq!(e.to_string(), "Usage: spawn helicopter [count]");
+        let e = command.explain(CommandError::Failed("No room".to_string()));
+        assert_eq!(e.to_string(), "No room");
+    }
+}
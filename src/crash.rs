@@ -0,0 +1,161 @@
+// Crash reports for panics on the render thread.
+//
+// On its own, "The render thread panicked" says little about what went wrong. `install` adds a
+// panic hook which, for panics on the render thread, writes a report to a `crash_*.txt` file in
+// the working directory, with the panic message and a backtrace, the frame being rendered, the
+// shader program and VAO bound at the time with their labels, the last OpenGL debug messages and
+// whatever the application noted about its state with `note`, like where the camera was. The
+// report is written before the thread unwinds, so it is on disk by the time `run` returns the
+// error, which names the file.
+use crate::screenshot;
+use crate::util;
+use log::error;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::panic::{self, PanicHookInfo};
+use std::path::PathBuf;
+use std::sync::{Mutex, Once};
+
+// What the render thread was doing, for the report
+#[derive(Default)]
+struct RenderState {
+    frame: u64,
+    notes: Vec<(&'static str, String)>,
+}
+
+thread_local! {
+    // Only set on the render thread
+    static RENDER_STATE: RefCell<Option<RenderState>> = const { RefCell::new(None) };
+}
+
+static INSTALL_HOOK: Once = Once::new();
+// The last report written, taken by `take_report_path`
+static REPORT_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+// Write crash reports for panics on the calling thread, which must be the render thread with the
+// context current. Panics on other threads are only printed, like before.
+pub fn install() {
+    INSTALL_HOOK.call_once(|| {
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous_hook(info);
+            write_report(info);
+        }));
+    });
+    RENDER_STATE.with(|state| *state.borrow_mut() = Some(RenderState::default()));
+}
+
+// Count a new frame on the render thread
+pub fn next_frame() {
+    with_state(|state| state.frame += 1);
+}
+
+// Remember something about the application's state for the report, replacing what was noted
+// under `key` before, e.g. `note("Camera", ...)` every frame. Does nothing on other threads than
+// the render thread.
+pub fn note(key: &'static str, value: String) {
+    with_state(
+        |state| match state.notes.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, old_value)) => *old_value = value,
+            None => state.notes.push((key, value)),
+        },
+    );
+}
+
+// Where the report of the last panic was written, if one was
+pub fn take_report_path() -> Option<PathBuf> {
+    REPORT_PATH.lock().ok()?.take()
+}
+
+fn with_state(f: impl FnOnce(&mut RenderState)) {
+    RENDER_STATE.with(|state| {
+        if let Some(state) = state.borrow_mut().as_mut() {
+            f(state);
+        }
+    });
+}
+
+fn write_report(info: &PanicHookInfo) {
+    // A panic while noting something leaves the state borrowed, which is reported without it
+    let report = RENDER_STATE.with(|state| match state.try_borrow() {
+        Ok(state) => state.as_ref().map(|state| report(info, Some(state))),
+        Err(_) => Some(report(info, None)),
+    });
+    let report = match report {
+        Some(report) => report,
+        None => return, // Not the render thread
+    };
+
+    let path = PathBuf::from(format!("crash_{}.txt", screenshot::timestamp()));
+    match std::fs::write(&path, report) {
+        Ok(()) => {
+            error!("Wrote a crash report to {}", path.display());
+            if let Ok(mut report_path) = REPORT_PATH.lock() {
+                *report_path = Some(path);
+            }
+        }
+        Err(e) => error!(
+            "Failed to write a crash report to {}: {}",
+            path.display(),
+            e
+        ),
+    }
+}
+
+fn report(info: &PanicHookInfo, state: Option<&RenderState>) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "gloom-rs crash report\n");
+    let _ = writeln!(report, "Panic: {}", panic_message(info));
+    if let Some(location) = info.location() {
+        let _ = writeln!(report, "At: {}", location);
+    }
+    if let Some(state) = state {
+        let _ = writeln!(report, "Frame: {}", state.frame);
+        for (key, value) in &state.notes {
+            let _ = writeln!(report, "{}: {}", key, value);
+        }
+    }
+
+    // The render thread has the context current whenever it runs application code
+    let (program, vao) = unsafe {
+        (
+            bound_object(gl::CURRENT_PROGRAM, gl::PROGRAM),
+            bound_object(gl::VERTEX_ARRAY_BINDING, gl::VERTEX_ARRAY),
+        )
+    };
+    let _ = writeln!(report, "Shader program: {}", program);
+    let _ = writeln!(report, "VAO: {}", vao);
+
+    let messages = util::recent_debug_messages();
+    let _ = writeln!(report, "\nLast OpenGL debug messages, oldest first:");
+    if messages.is_empty() {
+        let _ = writeln!(report, "None");
+    }
+    for message in messages {
+        let _ = writeln!(report, "{}", message);
+    }
+
+    let _ = writeln!(report, "\nBacktrace:\n{}", Backtrace::force_capture());
+    report
+}
+
+fn panic_message(info: &PanicHookInfo) -> String {
+    let payload = info.payload();
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Unknown".to_string())
+}
+
+// The object bound to `binding`, e.g. `3 (simple)`, with its label if it has one
+unsafe fn bound_object(binding: u32, identifier: u32) -> String {
+    let mut name = 0;
+    gl::GetIntegerv(binding, &mut name);
+    match util::object_label(identifier, name as u32) {
+        Some(label) => format!("{} ({})", name, label),
+        None if name == 0 => "None".to_string(),
+        None => name.to_string(),
+    }
+}
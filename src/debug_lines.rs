@@ -0,0 +1,124 @@
+// Line segments drawn over the scene for debugging, e.g. the bounds of every node. They are
+// collected anew every frame and streamed to the GPU through a `StreamBuffer`.
+use crate::backend::gl::GlBackend;
+use crate::scene_graph::SceneNode;
+use crate::stream_buffer::StreamBuffer;
+use crate::toolbox::Aabb;
+use std::ffi::c_void;
+
+// Floats per vertex: position, color and normal, interleaved in the layout of the simple pipeline
+const VERTEX_FLOATS: usize = 10;
+
+// The simple pipeline lights the lines like any surface, so they get a normal facing the sun as the
+// demo starts out, drawing them at full brightness
+const NORMAL: [f32; 3] = [-0.716, 0.447, -0.537];
+
+pub struct DebugLines {
+    vertices: Vec<f32>,
+    stream: StreamBuffer,
+    vao: u32,
+}
+
+impl DebugLines {
+    pub unsafe fn new() -> DebugLines {
+        let mut vao = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        for attribute in 0..3 {
+            gl::EnableVertexAttribArray(attribute);
+        }
+        gl::BindVertexArray(0);
+        DebugLines {
+            vertices: vec![],
+            stream: StreamBuffer::new(64 * 1024),
+            vao,
+        }
+    }
+
+    pub fn line(&mut self, from: &glm::Vec3, to: &glm::Vec3) {
+        for point in [from, to] {
+            self.vertices
+                .extend_from_slice(&[point.x, point.y, point.z]);
+            self.vertices.extend_from_slice(&[1.0, 1.0, 1.0, 1.0]);
+            self.vertices.extend_from_slice(&NORMAL);
+        }
+    }
+
+    // The edges of `bounds`, transformed by `transform`
+    pub fn aabb(&mut self, bounds: &Aabb, transform: &glm::Mat4) {
+        let corner = |i: usize| {
+            let x = if i & 1 == 0 {
+                bounds.min.x
+            } else {
+                bounds.max.x
+            };
+            let y = if i & 2 == 0 {
+                bounds.min.y
+            } else {
+                bounds.max.y
+            };
+            let z = if i & 4 == 0 {
+                bounds.min.z
+            } else {
+                bounds.max.z
+            };
+            (transform * glm::vec4(x, y, z, 1.0)).xyz()
+        };
+        // Corners whose indices differ in a single bit share an edge
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(&corner(i), &corner(i | bit));
+                }
+            }
+        }
+    }
+
+    // The bounds of `node` and of every node below it
+    pub fn scene_bounds(&mut self, node: &SceneNode, transformation_so_far: &glm::Mat4) {
+        let transform = transformation_so_far * node.local_transform();
+        if let Some(bounds) = &node.bounds {
+            self.aabb(bounds, &transform);
+        }
+        for &child in &node.children {
+            self.scene_bounds(unsafe { &*child }, &transform);
+        }
+    }
+
+    // Draw the lines collected since the last call with the current pipeline, and clear them
+    pub unsafe fn draw(&mut self, backend: &mut GlBackend, view_projection: &glm::Mat4) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        let offset = self.stream.write(&self.vertices);
+
+        // Point the attributes at this frame's region of the buffer
+        let stride = (VERTEX_FLOATS * std::mem::size_of::<f32>()) as i32;
+        let attributes = [(0, 3, 0), (1, 4, 3), (2, 3, 7)];
+        gl::BindVertexArray(self.vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.stream.buffer());
+        for (location, size, first_float) in attributes {
+            let attribute_offset = offset + first_float * std::mem::size_of::<f32>();
+            gl::VertexAttribPointer(
+                location,
+                size,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                attribute_offset as *const c_void,
+            );
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        gl::BindVertexArray(0);
+
+        let count = (self.vertices.len() / VERTEX_FLOATS) as i32;
+        backend.draw_lines(self.vao, count, view_projection);
+        self.stream.fence();
+        self.vertices.clear();
+    }
+
+    pub unsafe fn delete(&mut self) {
+        self.stream.delete();
+        gl::DeleteVertexArrays(1, &self.vao);
+    }
+}
@@ -0,0 +1,169 @@
+// A second window showing the scene from straight above, for keeping an eye on what happens
+// outside the main camera's view while flying, e.g. when debugging culling or shadows.
+//
+// Its context shares objects with the main context, but vertex array objects can't be shared, so
+// every mesh gets a VAO of its own here. Rendering happens on the render thread, switching between
+// the two contexts every frame.
+use crate::assets::Assets;
+use crate::backend::{self, gl::GlBackend, Backend, MeshHandle, PipelineHandle};
+use crate::error::RenderError;
+use crate::renderer;
+use crate::scene_graph::SceneNode;
+use crate::window::{GlContext, GlWindow};
+use log::warn;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use winit::dpi::PhysicalSize;
+
+// Width of the area shown, in world units
+const MAP_EXTENT: f32 = 600.0;
+
+pub struct DebugView {
+    context: GlContext,
+    backend: GlBackend,
+    pipeline: PipelineHandle,
+    vao_ids: HashMap<u32, u32>, // VAOs in the main context -> VAOs in this context
+    viewport_size: (u32, u32),
+    multisampling: bool,
+    closed: Arc<AtomicBool>, // Set by the event loop when the window is closed
+}
+
+impl DebugView {
+    // Set up the view's context. `main` is made current again afterwards.
+    pub fn new(
+        gl_window: GlWindow,
+        main: &GlContext,
+        multisampling: bool,
+        closed: Arc<AtomicBool>,
+    ) -> Result<DebugView, RenderError> {
+        let context = gl_window.make_current()?;
+        // Waiting for vsync in both windows would halve the frame rate of the main window
+        if let Err(e) = context.set_vsync(false) {
+            warn!("Failed to disable vsync for the debug view: {}", e);
+        }
+        let mut debug_view = DebugView {
+            context,
+            backend: GlBackend::default(), // Replaced in `init`
+            pipeline: PipelineHandle(0),
+            vao_ids: HashMap::new(),
+            viewport_size: (0, 0),
+            multisampling,
+            closed,
+        };
+        debug_view.init()?;
+        main.make_current()?;
+        Ok(debug_view)
+    }
+
+    fn init(&mut self) -> Result<(), RenderError> {
+        let capabilities = unsafe { renderer::init_gl(self.multisampling) };
+        self.backend = GlBackend::new(&capabilities.features);
+        self.pipeline = self.backend.create_pipeline("simple")?;
+        self.vao_ids.clear();
+        self.viewport_size = (0, 0);
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    // Create the context again after the main context was recreated
+    pub fn recreate(&mut self, main: &GlContext) -> Result<(), RenderError> {
+        self.context.recreate(Some(main))?;
+        self.init()?;
+        main.make_current()?;
+        Ok(())
+    }
+
+    // Draw the scene seen from above `center`, then make `main` current again
+    pub fn render(
+        &mut self,
+        root: &SceneNode,
+        assets: &Assets,
+        center: &glm::Vec3,
+        main: &GlContext,
+    ) -> Result<(), RenderError> {
+        self.context.make_current()?;
+
+        let size = self.context.size();
+        if (size.width, size.height) != self.viewport_size && size.width > 0 && size.height > 0 {
+            self.viewport_size = (size.width, size.height);
+            self.context
+                .resize(PhysicalSize::new(size.width, size.height));
+            self.backend.resize(size.width, size.height);
+        }
+
+        // Models dropped onto the main window show up here as well, and removed ones disappear
+        assets.sync_meshes(&mut self.backend, &mut self.vao_ids);
+
+        let aspect = self.viewport_size.0 as f32 / self.viewport_size.1.max(1) as f32;
+        let half_height = MAP_EXTENT * 0.5 / aspect.max(1e-3);
+        let projection = glm::ortho(
+            -MAP_EXTENT * 0.5,
+            MAP_EXTENT * 0.5,
+            -half_height,
+            half_height,
+            1.0,
+            2000.0,
+        );
+        let view = glm::look_at(
+            &(center + glm::vec3(0.0, 1000.0, 0.0)),
+            center,
+            &glm::vec3(0.0, 0.0, -1.0),
+        );
+
+        self.backend.begin_frame(&glm::vec4(0.02, 0.02, 0.02, 1.0));
+        self.backend.set_pipeline(self.pipeline);
+        self.backend.push_group("Debug view");
+        draw_node(
+            root,
+            &(projection * view),
+            &glm::identity(),
+            &self.vao_ids,
+            &mut self.backend,
+        );
+        self.backend.pop_group();
+        self.backend.end_frame();
+        self.context.swap_buffers()?;
+
+        main.make_current()?;
+        Ok(())
+    }
+}
+
+fn draw_node(
+    node: &SceneNode,
+    view_projection: &glm::Mat4,
+    transform_so_far: &glm::Mat4,
+    vao_ids: &HashMap<u32, u32>,
+    backend: &mut dyn Backend,
+) {
+    let transform = transform_so_far * node.local_transform();
+    let vao = vao_ids.get(&node.vao_id).filter(|_| node.is_drawn());
+    if let Some(&vao) = vao {
+        backend.draw(&backend::DrawCall {
+            mesh: MeshHandle(vao),
+            index_count: node.index_count,
+            transform: &(view_projection * transform),
+            model: &transform,
+            previous_model: &transform,
+            highlight: if node.selected { 1.0 } else { 0.0 },
+            opacity: node.opacity,
+            joints: node.joints.as_deref().unwrap_or_default(),
+            // The textures belong to the main window's context
+            normal_map: None,
+            material: node.material,
+        });
+    }
+    for &child in &node.children {
+        draw_node(
+            unsafe { &*child },
+            view_projection,
+            &transform,
+            vao_ids,
+            backend,
+        );
+    }
+}
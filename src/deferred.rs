@@ -0,0 +1,209 @@
+// Deferred shading: drawing what is where first, and lighting it afterwards.
+//
+// Drawn forward, every fragment of every mesh is lit by every light as it is drawn, even where
+// something nearer is drawn over it later. Deferred, the scene is first drawn into a `GBuffer`
+// without any lighting: for every pixel, where the surface nearest the camera is, which way it
+// faces, its color and what it is made of. The lighting is then worked out once per pixel in a
+// resolve pass over the whole screen, so what it costs grows with the pixels and the lights, no
+// longer with how many times the scene is drawn over itself.
+//
+// Both passes are drawn by the simple pipeline, told which one it is by `renderPass` (see `Pass`),
+// so the scene is shaded just like forward, with the same tweaks, lights and shadows. The G-buffer
+// only has room for the nearest surface, so see-through meshes are drawn forward after the resolve
+// pass, over the depth of the scene, which the `gbuffer_depth` pipeline copies out of the G-buffer.
+// The G-buffer isn't multisampled, so the edges of what is drawn deferred aren't smoothed.
+use crate::backend::gl::GlBackend;
+use crate::backend::{Backend, PipelineHandle};
+use crate::error::RenderError;
+use crate::offscreen::{self, Framebuffer};
+use std::fmt;
+use std::str::FromStr;
+
+// Where the G-buffer's textures are bound for the resolve pass, in the order of `GBUFFER_SAMPLERS`,
+// clear of the shadow maps' and the normal maps' units
+pub const GBUFFER_TEXTURE_UNIT: u32 = 4;
+
+// The G-buffer's color textures, as attached: where the surface is, with its roughness in w, the
+// way it faces, with how metallic it is in w or -1 for Phong, its albedo, with how highlighted it
+// is in alpha, and the light it gives off. Positions need the full precision of a float, as the
+// shadows are looked up by them.
+const GBUFFER_FORMATS: [u32; 4] = [gl::RGBA32F, gl::RGBA16F, gl::RGBA8, gl::RGBA16F];
+// What the simple fragment shader calls them, then the depth texture
+const GBUFFER_SAMPLERS: [&str; 5] = ["gPosition", "gNormal", "gAlbedo", "gEmissive", "gDepth"];
+
+// How the scene is lit, see above
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderPath {
+    #[default]
+    Forward,
+    Deferred,
+}
+
+impl RenderPath {
+    pub fn name(self) -> &'static str {
+        match self {
+            RenderPath::Forward => "forward",
+            RenderPath::Deferred => "deferred",
+        }
+    }
+}
+
+impl fmt::Display for RenderPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for RenderPath {
+    type Err = ();
+    fn from_str(name: &str) -> Result<RenderPath, ()> {
+        [RenderPath::Forward, RenderPath::Deferred]
+            .iter()
+            .copied()
+            .find(|path| path.name() == name)
+            .ok_or(())
+    }
+}
+
+// What the simple pipeline draws, as its `renderPass` uniform
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    Forward = 0,  // Lit fragments, into the framebuffer
+    Geometry = 1, // Unlit surfaces, into the G-buffer
+    Resolve = 2,  // The surfaces in the G-buffer lit, over the whole screen
+    Velocity = 3, // How far the surfaces moved on the screen since the last frame, see `post`
+}
+
+// Have `pipeline`, whose shaders declare `renderPass`, draw `pass`
+pub unsafe fn set_pass(backend: &GlBackend, pipeline: PipelineHandle, pass: Pass) {
+    let location = backend
+        .uniforms(pipeline)
+        .get("renderPass")
+        .map_or(-1, |uniform| uniform.location);
+    gl::ProgramUniform1i(backend.program_id(pipeline), location, pass as i32);
+}
+
+pub struct GBuffer {
+    framebuffer: Framebuffer,
+    vao: u32, // Empty, as the vertex shaders make the triangle covering the screen up themselves
+    previous_framebuffer: u32,
+    previous_viewport: [i32; 4],
+}
+
+impl GBuffer {
+    pub unsafe fn new(size: (u32, u32)) -> Result<GBuffer, RenderError> {
+        let framebuffer = Framebuffer::new(size, &GBUFFER_FORMATS, Some(gl::DEPTH_COMPONENT24))?;
+        let mut vao = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        Ok(GBuffer {
+            framebuffer,
+            vao,
+            previous_framebuffer: 0,
+            previous_viewport: [0; 4],
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.framebuffer.size()
+    }
+
+    // Draw the surfaces into the G-buffer with `pipeline` from now on, until `end`. Blending is off,
+    // as the alpha of the textures isn't opacity.
+    pub unsafe fn begin(&mut self, backend: &GlBackend, pipeline: PipelineHandle) {
+        self.previous_framebuffer = offscreen::bound_framebuffer();
+        gl::GetIntegerv(gl::VIEWPORT, self.previous_viewport.as_mut_ptr());
+        self.framebuffer.bind();
+        for i in 0..GBUFFER_FORMATS.len() as i32 {
+            gl::ClearBufferfv(gl::COLOR, i, [0.0; 4].as_ptr());
+        }
+        gl::ClearBufferfv(gl::DEPTH, 0, &1.0);
+        gl::Disable(gl::BLEND);
+        set_pass(backend, pipeline, Pass::Geometry);
+    }
+
+    // Go back to drawing where `begin` was called, forward
+    pub unsafe fn end(&self, backend: &GlBackend, pipeline: PipelineHandle) {
+        gl::Enable(gl::BLEND);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.previous_framebuffer);
+        let [x, y, width, height] = self.previous_viewport;
+        gl::Viewport(x, y, width, height);
+        set_pass(backend, pipeline, Pass::Forward);
+    }
+
+    // Light the surfaces in the G-buffer with `pipeline`, the simple one, into where `begin` was
+    // called, and copy their depth there with `depth_pipeline`, so more can be drawn over them.
+    // Where nothing was drawn, what was there shows through.
+    pub unsafe fn resolve(
+        &self,
+        backend: &mut GlBackend,
+        pipeline: PipelineHandle,
+        depth_pipeline: PipelineHandle,
+    ) {
+        self.bind_textures(backend, &[pipeline, depth_pipeline]);
+        gl::BindVertexArray(self.vao);
+        // The triangle covers the screen even when the scene is drawn in wireframe
+        let mut polygon_mode = [gl::FILL as i32; 2];
+        gl::GetIntegerv(gl::POLYGON_MODE, polygon_mode.as_mut_ptr());
+        gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+
+        set_pass(backend, pipeline, Pass::Resolve);
+        backend.set_pipeline(pipeline);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::Enable(gl::DEPTH_TEST);
+        set_pass(backend, pipeline, Pass::Forward);
+
+        backend.set_pipeline(depth_pipeline);
+        gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+        gl::DepthFunc(gl::ALWAYS);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::DepthFunc(gl::LESS);
+        gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+
+        gl::PolygonMode(gl::FRONT_AND_BACK, polygon_mode[0] as u32);
+        gl::BindVertexArray(0);
+        backend.set_pipeline(pipeline);
+    }
+
+    // Bind the G-buffer's textures for `pipelines` to read, to those of the samplers named in
+    // `GBUFFER_SAMPLERS` their shaders declare
+    pub unsafe fn bind_textures(&self, backend: &GlBackend, pipelines: &[PipelineHandle]) {
+        let textures = (0..GBUFFER_FORMATS.len())
+            .map(|i| self.framebuffer.color_texture(i))
+            .chain(self.framebuffer.depth_texture());
+        for (i, texture) in textures.enumerate() {
+            gl::ActiveTexture(gl::TEXTURE0 + GBUFFER_TEXTURE_UNIT + i as u32);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+        }
+        gl::ActiveTexture(gl::TEXTURE0);
+        for &pipeline in pipelines {
+            let program = backend.program_id(pipeline);
+            let uniforms = backend.uniforms(pipeline);
+            for (i, name) in GBUFFER_SAMPLERS.iter().enumerate() {
+                if let Some(uniform) = uniforms.get(*name) {
+                    let unit = GBUFFER_TEXTURE_UNIT + i as u32;
+                    gl::ProgramUniform1i(program, uniform.location, unit as i32);
+                }
+            }
+        }
+    }
+
+    pub unsafe fn delete(self) {
+        self.framebuffer.delete();
+        gl::DeleteVertexArrays(1, &self.vao);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_paths_are_named_both_ways() {
+        for path in [RenderPath::Forward, RenderPath::Deferred] {
+            assert_eq!(path.to_string().parse(), Ok(path));
+        }
+        assert_eq!("sideways".parse::<RenderPath>(), Err(()));
+        assert_eq!(RenderPath::default(), RenderPath::Forward);
+    }
+}
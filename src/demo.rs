@@ -0,0 +1,3277 @@
+// The demo scene: helicopters flying over the lunar surface.
+//
+// The first helicopter is flown with the keyboard or a gamepad while the chase camera follows it,
+// and H switches to a free camera. Left click selects a node and Return renames it, Shift+click
+// parks a helicopter on the terrain, O opens or closes the door of the selected or the nearest
+// helicopter, and models dropped onto the window are added in front of the camera. Ctrl+S saves
+// the parked helicopters and dropped models, which are placed again on the next run, see `props`.
+// The cameras are kept out of the terrain, and the flown helicopter's rotor stalls when it strikes
+// the ground or a prop. The rotors are heard from where they are around the camera, and a windsock
+// by the start sways in the wind as its rig in windsock.gltf is animated, see `skeleton` and
+// `gltf`. G starts and stops the landing game, see `game`. The ~ key opens a console for commands
+// like `spawn helicopter 3`, see `console_commands`, R compiles the shaders again after editing
+// them and F9 writes every draw of a frame to a JSON file. With the `egui` feature, F1 shows panels
+// for tweaking the shader's uniforms, the camera and the rendering and for inspecting the scene
+// graph.
+use crate::cli;
+use crate::fleet::{self, Fleet, FleetInput};
+use crate::game::{self, LandingGame};
+use crate::pilot;
+use crate::props::{self, PlacedModel, Props};
+use gloom_rs::app::{Context, GloomApp};
+use gloom_rs::assets::{Assets, Handle, Pipeline, Texture};
+use gloom_rs::audio::{self, SpatialAudio};
+use gloom_rs::backend::Backend;
+use gloom_rs::batching;
+use gloom_rs::camera;
+use gloom_rs::collision::{self, CollisionEvent, ContactTracker, MeshCollider};
+use gloom_rs::commands::{CommandList, DrawCommand};
+use gloom_rs::config::{self, Config};
+use gloom_rs::console::{Arguments, CommandRegistry, Console};
+use gloom_rs::crash;
+use gloom_rs::debug_lines::DebugLines;
+use gloom_rs::deferred::{GBuffer, RenderPath};
+use gloom_rs::environment::EnvironmentMaps;
+use gloom_rs::error::{CommandError, RenderError};
+use gloom_rs::frame_dump;
+use gloom_rs::gamepad::{self, Gamepad};
+use gloom_rs::gltf;
+use gloom_rs::input::{self, FrameInput};
+use gloom_rs::lighting::{Light, LightBuffer, Lighting, PointLight, MAX_SHADOWED_POINT_LIGHTS};
+use gloom_rs::loader;
+use gloom_rs::material::Material;
+use gloom_rs::mesh::{self, Mesh};
+use gloom_rs::octree::Octree;
+use gloom_rs::overlay::{DebugOverlay, OverlayStats};
+use gloom_rs::post::{
+    BloomSettings, DepthOfField, MotionBlur, PostProcess, ToneMapping, MAX_BLUR_PASSES,
+    MAX_FOCUS_BLUR, MAX_MOTION_BLUR,
+};
+use gloom_rs::reflection::{self, PlanarReflection};
+use gloom_rs::renderer;
+use gloom_rs::scene_graph::{self, Node, SceneNode};
+use gloom_rs::shadow::{self, PointShadowMaps, ShadowMap};
+use gloom_rs::simulation::Simulator;
+use gloom_rs::skeleton::{SkeletalAnimation, Skeleton};
+use gloom_rs::skybox::{self, Skybox};
+use gloom_rs::ssr::{ScreenSpaceReflections, SsrSettings, MAX_SSR_STEPS};
+use gloom_rs::streaming::{RegionId, RegionLoader, StreamEvent, Streamer};
+use gloom_rs::text::TextRenderer;
+use gloom_rs::timing;
+use gloom_rs::toolbox::{self, Fbm, Noise};
+use gloom_rs::tweaks::Tweaks;
+#[cfg(feature = "egui")]
+use gloom_rs::ui::Ui;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::mem::ManuallyDrop;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use winit::event::MouseButton;
+use winit::keyboard::KeyCode;
+use winit::window::Fullscreen;
+
+const BOOKMARK_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+const CAMERA_SHAKE_SEED: u64 = 1;
+const PARKING_TRAUMA: f32 = 0.4;
+// Hills rolled into the streamed terrain tiles, so they don't all look the same: up to
+// `RELIEF_HEIGHT` up or down, about `RELIEF_SCALE` across
+const TERRAIN_SEED: u64 = 2;
+const RELIEF_HEIGHT: f32 = 15.0;
+const RELIEF_SCALE: f32 = 120.0;
+// Craters too small for the terrain's mesh, in a normal map repeating every `DETAIL_TILE` units
+// across it, with `DETAIL_CRATERS` in each repeat
+const DETAIL_TILE: f32 = 16.0;
+const DETAIL_MAP_SIZE: u32 = 256;
+const DETAIL_CRATERS: usize = 24;
+const DETAIL_SEED: u64 = 3;
+// The stars in the sky unless another skybox is given, in faces `STARFIELD_SIZE` texels across,
+// which is also how large the faces made from a panorama are
+const STARFIELD_SIZE: u32 = 512;
+const STARFIELD_STARS: u32 = 4000;
+const STARFIELD_SEED: u64 = 4;
+// Where the tail rotor turns about the X axis, in the helicopter's model space
+const TAIL_ROTOR_HUB: [f32; 3] = [0.35, 2.3, 10.4];
+const ROTOR_DISC_SEGMENTS: u32 = 48;
+// Units between the samples of the terrain's height the flown helicopter lands on
+const GROUND_SPACING: f32 = 4.0;
+// How close the cameras get to the terrain, a little more than the near plane
+const CAMERA_RADIUS: f32 = 1.5;
+// The main rotor's blade tips are checked for strikes as a ring of spheres this big
+const ROTOR_TIP_COUNT: usize = 12;
+const ROTOR_TIP_RADIUS: f32 = 0.4;
+const ROTOR_STRIKE_TRAUMA: f32 = 0.8;
+// Share of its full speed a rotor needs to do any damage
+const ROTOR_STRIKE_SPEED: f32 = 0.2;
+// The waypoints helicopters fly between with `fly <helicopter> waypoints`, around the patrol in a
+// ring with every other one further out, this high above the terrain
+const WAYPOINT_COUNT: usize = 8;
+const WAYPOINT_RADII: [f32; 2] = [70.0, 130.0];
+const WAYPOINT_ALTITUDE: f32 = 20.0;
+// The rotor sound is a second of blades passing this many times, at full rotor speed
+const ROTOR_SOUND_RATE: u32 = 22050;
+const ROTOR_BLADE_PASSES: u32 = 6;
+const PAD_SEGMENTS: u32 = 48;
+const PAD_RING_COLOR: [f32; 4] = [1.0, 0.6, 0.1, 1.0];
+const CRASH_TRAUMA: f32 = 0.6;
+// Where the windsock stands, in front of the camera at the start, and how it is built, which has to
+// match the skeleton in windsock.gltf
+const WINDSOCK_SPOT: [f32; 2] = [12.0, 30.0];
+const WINDSOCK_POLE_HEIGHT: f32 = 6.0;
+const WINDSOCK_LENGTH: f32 = 4.0;
+const WINDSOCK_JOINTS: u32 = 5; // Along the sock
+const WINDSOCK_SEGMENTS: u32 = 16;
+
+// The frozen pond mirroring the scene, where it lies, how large it is and how high above the
+// ground at its middle, so most of it is clear of the bumps around it
+const ICE_SPOT: [f32; 2] = [-20.0, -10.0];
+const ICE_SIZE: f32 = 40.0;
+const ICE_HEIGHT: f32 = 0.3;
+const ICE_COLOR: [f32; 4] = [0.75, 0.85, 0.95, 1.0];
+
+// The color behind everything when there is no skybox
+const SKY_COLOR: [f32; 4] = [0.035, 0.046, 0.078, 1.0];
+// How far the exposure can be turned down and up, for scenes drawn in high dynamic range, and where
+// it starts, a little under 1 so ACES leaves the middle tones about as bright as they were
+const MIN_EXPOSURE: f32 = 0.1;
+const MAX_EXPOSURE: f32 = 10.0;
+const EXPOSURE: f32 = 0.8;
+// How much nearer or further each press of Page Down or Page Up focuses the camera, and how near
+// and far it can focus
+const FOCUS_STEP: f32 = 1.2;
+const MIN_FOCUS_DISTANCE: f32 = 2.0;
+const MAX_FOCUS_DISTANCE: f32 = 500.0;
+// The way the sun shines, and its color
+const SUN_DIRECTION: [f32; 3] = [0.8, -0.5, 0.6];
+const SUN_COLOR: [f32; 3] = [1.0, 0.97, 0.92];
+// The lamp under each helicopter's nose, lighting up the ground around where it flies
+const LAMP_POSITION: [f32; 3] = [0.0, -1.2, -3.5];
+const LAMP_COLOR: [f32; 3] = [1.0, 0.85, 0.6];
+const LAMP_INTENSITY: f32 = 0.6;
+const LAMP_RANGE: f32 = 30.0;
+// The lamp's bulb, glowing just above where the light comes from so it doesn't shadow the ground,
+// bright enough to bloom
+const BULB_RADIUS: f32 = 0.35;
+const BULB_GLOW: f32 = 4.0;
+// What the surfaces are made of: dust on the ground, paint on the helicopters and the props, which
+// share a batch, bare metal on the rotors, weathered paint on the wrecks and concrete on the pads
+const GROUND_MATERIAL: Material = Material::new(0.0, 0.9);
+const PAINT_MATERIAL: Material = Material::new(0.3, 0.45);
+const ROTOR_MATERIAL: Material = Material::new(0.9, 0.35);
+const WRECK_MATERIAL: Material = Material::new(0.3, 0.8);
+const PAD_MATERIAL: Material = Material::new(0.0, 0.7);
+// Light given off by the landing pad to land on next
+const TARGET_PAD_GLOW: glm::Vec3 = glm::Vec3::new(0.1, 0.6, 0.25);
+const WINDSOCK_MATERIAL: Material = Material::new(0.0, 0.8);
+const ICE_MATERIAL: Material = Material::new(0.0, 0.1).with_reflectivity(0.3);
+// Texels across each slice of the sun's shadow map at full detail, and how far ahead of the camera
+// it reaches
+const SHADOW_MAP_SIZE: u32 = 1024;
+const SHADOW_DISTANCE: f32 = 400.0;
+// Texels across each face of the cube maps of the lamps' shadows at full detail
+const POINT_SHADOW_MAP_SIZE: u32 = 512;
+// The shadow maps don't shrink below this many texels across with less detail
+const MIN_SHADOW_MAP_SIZE: u32 = 128;
+
+// What the flown helicopter's rotor can strike
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Obstacle {
+    Terrain,
+    Node(*mut SceneNode), // A parked helicopter or a dropped model
+}
+
+// The parts of the helicopter model, shared by every helicopter in the scene
+struct HelicopterMeshes {
+    body: Handle<Mesh>,
+    door: Handle<Mesh>,
+    main_rotor: Handle<Mesh>,
+    tail_rotor: Handle<Mesh>,
+    // The discs the rotors sweep, shown when they turn fast
+    main_rotor_disc: Handle<Mesh>,
+    tail_rotor_disc: Handle<Mesh>,
+    bulb: Handle<Mesh>,
+    rotor_sound: Arc<audio::Clip>,
+}
+
+pub struct Demo {
+    config: Config,
+    // Models dropped onto the window are loaded in the background
+    asset_loader: loader::AssetLoader,
+
+    terrain_node: Node,
+    // Bumps on the terrain and the streamed tiles, see `crater_normal_map`
+    detail_normal_map: Handle<Texture>,
+    // The terrain's triangles, for picking points on it and keeping things out of it
+    terrain_collider: MeshCollider,
+    // The terrain's heights, as the fleet stands helicopters on it
+    ground: Option<toolbox::HeightField>,
+    helicopter_meshes: HelicopterMeshes,
+    helicopters: Vec<Node>,
+    waypoints: Arc<Vec<glm::Vec3>>,
+    // Helicopters placed on the terrain with Shift+click. They are not animated.
+    parked_helicopters: Vec<Node>,
+    // Models dropped onto the window, with the files they were loaded from
+    dropped_models: Vec<(PathBuf, Node)>,
+    // Props saved by an earlier run that are yet to be placed. The helicopters are parked by the
+    // first update, and the models once they have loaded.
+    saved_props: Props,
+    // Parent of the parked helicopters and dropped models. They never move once placed, so their
+    // meshes are merged into one drawn by `static_batch_node`, which comes after it so selected
+    // props are drawn on top of their batched copy.
+    props_node: Node,
+    static_batch: Option<Handle<Mesh>>,
+    static_batch_node: Node,
+    static_batching: bool,
+    // Terrain tiles around the lunar surface and the props on them, loaded near the camera
+    streamer: Streamer,
+    streamed_node: Node,
+    streamed_nodes: HashMap<RegionId, Vec<Node>>,
+    root_node: Node,
+    // Where the nodes with bounds are, for culling and picking without testing every node
+    octree: Octree,
+    culled_nodes: usize, // By the octree, this frame
+    simple_pipeline: Handle<Pipeline>,
+    overlay_pipeline: Handle<Pipeline>,
+    // Uniforms of the simple shaders tagged with @tweak, like the ambient light
+    tweaks: Tweaks,
+    // The sun, a directional light aimed with `set light`, and the lights found in the scene this
+    // frame, set on the simple pipeline by `render`
+    sun_node: Node,
+    lighting: Lighting,
+    light_buffer: LightBuffer,
+    // The sun's shadows, drawn into each slice of the map from the draws recorded for it in
+    // `shadow_commands`, which is empty without shadows. None if the map couldn't be created.
+    shadow_pipeline: Handle<Pipeline>,
+    shadow_map: Option<ShadowMap>,
+    shadow_commands: Vec<CommandList>,
+    // The quality's detail the shadow maps were made for, see `create_shadow_map`
+    shadow_detail: f32,
+    // The shadows of the nearest lamps, drawn into a cube map each from the draws recorded for them
+    point_shadow_pipeline: Handle<Pipeline>,
+    point_shadow_maps: Option<PointShadowMaps>,
+    shadowed_points: Vec<PointLight>,
+    point_shadow_commands: Vec<CommandList>,
+    shadows: bool,
+    // Drawn deferred, the scene is drawn into the G-buffer, sized to the viewport, and lit from it
+    // by the simple pipeline, then its depth is copied out by the gbuffer_depth one
+    render_path: RenderPath,
+    gbuffer: Option<GBuffer>,
+    gbuffer_depth_pipeline: Handle<Pipeline>,
+    // With bloom, tone mapping or another exposure, the scene is drawn into the post-processing's
+    // texture, sized to the viewport, and filtered into the framebuffer by the post pipeline
+    bloom: bool,
+    bloom_settings: BloomSettings,
+    tone_mapping: ToneMapping,
+    exposure: f32,
+    // F blurs what the camera isn't focused on, Page Up and Page Down focus it further and nearer
+    depth_of_field: bool,
+    focus: DepthOfField,
+    // N smears what moves along the way it moved since the last frame, from the scene drawn once
+    // more as velocities into the post-processing's textures
+    motion_blur: bool,
+    motion_blur_settings: MotionBlur,
+    // Without multisampling, the edges are smoothed by FXAA after tone mapping instead
+    fxaa: bool,
+    post_process: Option<PostProcess>,
+    post_process_samples: u32, // Asked for when the post-processing was made, see `Context::samples`
+    post_pipeline: Handle<Pipeline>,
+    // Drawn deferred, shiny surfaces reflect the lit scene, traced through it by the ssr pipeline
+    // into a texture sized to the viewport. As it is looked up in the post-processing's texture,
+    // the scene is post-processed with them.
+    screen_space_reflections: bool,
+    ssr_settings: SsrSettings,
+    ssr: Option<ScreenSpaceReflections>,
+    ssr_pipeline: Handle<Pipeline>,
+
+    // The sky, drawn by the skybox pipeline from the faces at `skybox_path`, or the starfield if
+    // there is none. None if it couldn't be created, which leaves `SKY_COLOR` behind the scene.
+    skybox: Option<Skybox>,
+    skybox_path: Option<PathBuf>,
+    skybox_pipeline: Handle<Pipeline>,
+    // The sky's light on physically shaded surfaces, filtered out of the skybox by the environment
+    // pipeline. None without a skybox or if it couldn't be filtered.
+    environment: Option<EnvironmentMaps>,
+    environment_pipeline: Handle<Pipeline>,
+    image_based_lighting: bool,
+    // The ice mirrors the scene, drawn into the planar reflection, sized to the viewport, from the
+    // draws recorded for the camera mirrored in the ice's plane, seen with `reflection_view`
+    reflections: bool,
+    reflection: Option<PlanarReflection>,
+    reflection_commands: CommandList,
+    reflection_view: glm::Mat4,
+    ice_plane: glm::Vec4,
+
+    field_of_view: f32, // Vertical, in degrees
+    view_matrix: glm::Mat4,
+    projection_matrix: glm::Mat4,
+    view_projection: glm::Mat4,
+    // What `view_projection` was when the last frame was drawn, for motion blur, or None before it
+    previous_view_projection: Option<glm::Mat4>,
+    // The scene's draws, recorded at the end of `update` and replayed by `render`
+    commands: CommandList,
+    // The pose the camera is currently viewed from, used for recording keyframes
+    current_camera: camera::FreeCamera,
+    selected_node: Option<*mut scene_graph::SceneNode>,
+    text_input: input::TextInput,
+
+    // Animation and flight are simulated in fixed steps on the update thread, so helicopter
+    // motion doesn't depend on the frame rate. Frames are rendered between the last two steps.
+    fleet: Simulator<Fleet>,
+    // P pauses the animation clock, . steps a single frame, [ and ] scale time
+    animation_paused: bool,
+    time_scale: f32,
+    wireframe: bool,
+    // B shows the bounds of every pickable node and the waypoints
+    show_bounds: bool,
+    debug_lines: DebugLines,
+    // F3 shows renderer statistics over the scene
+    overlay: DebugOverlay,
+    // F9 writes every draw of the next frame to a JSON file, see `frame_dump`
+    dump_frame: bool,
+    console: Console,
+    console_commands: CommandRegistry<Demo>,
+    // F1 shows panels for tweaking the scene. Only None while they are being laid out.
+    #[cfg(feature = "egui")]
+    ui: Option<Ui>,
+    #[cfg(feature = "egui")]
+    ui_visible: bool,
+    // The cursor starts out free. Tab confines it to the window and hides it, and holding the right
+    // mouse button grabs it temporarily.
+    cursor_captured: bool,
+
+    // In pilot mode the keyboard and the gamepad fly the first helicopter and the chase camera
+    // follows it, otherwise all helicopters follow their animation and the camera flies freely.
+    pilot_mode: bool,
+    // None without one, and while replaying or capturing, as recordings only hold the keyboard
+    // and the mouse
+    gamepad: Option<Box<dyn Gamepad>>,
+    chase_camera: camera::ChaseCamera,
+    free_camera: camera::FreeCamera,
+    // Free camera speed in units per second, adjusted with the scroll wheel
+    camera_base_speed: f32,
+    // Double-tapping W sprints until W is released
+    double_tap: input::DoubleTap,
+    double_tap_sprint: bool,
+
+    // Fly-through recorded from keyframes: K records the current view, L plays/stops, J clears
+    camera_path: camera::CameraPath,
+    // Smooth transition when jumping to a camera bookmark
+    camera_transition: camera::CameraPath,
+    // Parking a helicopter and rotor strikes shake the camera
+    camera_shake: camera::CameraShake,
+    rotor_strikes: ContactTracker<Obstacle>,
+    // The landing game, while it is played, with a node for each of its pads
+    game: Option<LandingGame>,
+    landing_pad: Handle<Mesh>,
+    pads_node: Node,
+    pad_nodes: Vec<Node>,
+    hud_text: TextRenderer,
+    // The windsock, posed every frame by `clock` from the animation of its rig in windsock.gltf.
+    // The clock keeps the time of the animations played here rather than in the fleet.
+    windsock_node: Node,
+    windsock_skeleton: Skeleton,
+    windsock_animation: SkeletalAnimation,
+    clock: toolbox::AnimationClock,
+    // The rotors' sound, heard from the camera
+    audio: SpatialAudio,
+}
+
+impl GloomApp for Demo {
+    type Options = (cli::Args, Config);
+
+    fn setup(ctx: &mut Context, (args, config): Self::Options) -> Result<Demo, RenderError> {
+        // Edited models are reloaded while the demo runs, except where frames must be repeatable
+        if ctx.real_time {
+            ctx.assets.watch(&args.resources);
+        }
+
+        // Load the terrain and the helicopter, and upload them so nodes can be built from them
+        let terrain = ctx.assets.load_model(&args.scene_path(), |path| {
+            let mut terrain = mesh::Terrain::load(path)?;
+            add_detail_uvs(&mut terrain, &glm::zero());
+            Ok(vec![terrain])
+        })?[0];
+        let detail_normal_map = ctx.assets.add_texture(Texture {
+            image: crater_normal_map(DETAIL_MAP_SIZE, DETAIL_SEED),
+        });
+
+        let helicopter = ctx
+            .assets
+            .load_model(&args.resource_path("helicopter.obj"), |path| {
+                let helicopter = mesh::Helicopter::load(path)?;
+                Ok(vec![
+                    helicopter.body,
+                    helicopter.door,
+                    helicopter.main_rotor,
+                    helicopter.tail_rotor,
+                ])
+            })?;
+
+        let mut rotor_disc = |rotor, axis, hub: glm::Vec3| {
+            let rotor = ctx
+                .assets
+                .get(rotor)
+                .expect("The helicopter was just loaded");
+            let disc = mesh::rotor_disc(rotor, axis, &hub, ROTOR_DISC_SEGMENTS);
+            ctx.assets.add_mesh(disc)
+        };
+        let helicopter_meshes = HelicopterMeshes {
+            body: helicopter[0],
+            door: helicopter[1],
+            main_rotor: helicopter[2],
+            tail_rotor: helicopter[3],
+            main_rotor_disc: rotor_disc(helicopter[2], 1, glm::zero()),
+            tail_rotor_disc: rotor_disc(helicopter[3], 0, TAIL_ROTOR_HUB.into()),
+            bulb: ctx
+                .assets
+                .add_mesh(mesh::sphere(BULB_RADIUS, 8, 12, [1.0; 4])),
+            rotor_sound: Arc::new(audio::Clip::rotor(ROTOR_SOUND_RATE, ROTOR_BLADE_PASSES)),
+        };
+        let landing_pad = ctx.assets.add_mesh(mesh::landing_pad(
+            game::PAD_RADIUS,
+            PAD_SEGMENTS,
+            PAD_RING_COLOR,
+        ));
+        // The sock swings round on its pole as the wind turns, and flaps and droops down its length
+        // as the wind gusts and drops
+        let windsock_rig = gltf::load(&args.resource_path("windsock.gltf"))?;
+        let windsock_animation = windsock_rig
+            .animation("windsock")
+            .cloned()
+            .filter(|_| windsock_rig.skin_joints.len() > WINDSOCK_JOINTS as usize)
+            .ok_or(RenderError::Unsupported(
+                "windsock.gltf is not a windsock with its animation",
+            ))?;
+        let mut windsock_mesh = mesh::windsock(
+            WINDSOCK_POLE_HEIGHT,
+            WINDSOCK_LENGTH,
+            WINDSOCK_JOINTS,
+            WINDSOCK_SEGMENTS,
+        );
+        // The mesh is skinned to the skin's joints, which the skeleton may have in another order
+        for joint in &mut windsock_mesh.joints {
+            *joint = windsock_rig.skin_joints[*joint as usize] as f32;
+        }
+        let windsock = ctx.assets.add_mesh(windsock_mesh);
+
+        let ice = ctx.assets.add_mesh(mesh::square(ICE_SIZE, ICE_COLOR));
+
+        ctx.assets.upload(&mut ctx.backend);
+
+        let mut terrain_node = SceneNode::from_vao(ctx.assets.vao(terrain));
+        terrain_node.name = "terrain".to_string();
+        terrain_node.normal_map = ctx.assets.gpu(detail_normal_map);
+        terrain_node.material = Some(GROUND_MATERIAL);
+        let terrain_collider = match ctx.assets.get(terrain) {
+            Some(terrain) => MeshCollider::from_mesh(terrain),
+            None => MeshCollider::new(&[], &[]),
+        };
+
+        let mut helicopters: Vec<Node> = Vec::new();
+        // Create multiple helicopters
+        for _i in 0..args.helicopters {
+            helicopters.push(create_helicopter(&ctx.assets, &helicopter_meshes));
+        }
+
+        let mut root_node = SceneNode::new();
+        root_node.name = "root".to_string();
+
+        root_node.add_child(&terrain_node);
+        for helicopter in helicopters.iter() {
+            root_node.add_child(helicopter);
+        }
+
+        let mut props_node = SceneNode::new();
+        props_node.name = "props".to_string();
+        root_node.add_child(&props_node);
+        let mut static_batch_node = SceneNode::new();
+        static_batch_node.name = "static batch".to_string();
+        static_batch_node.material = Some(PAINT_MATERIAL);
+        root_node.add_child(&static_batch_node);
+
+        let mut pads_node = SceneNode::new();
+        pads_node.name = "landing pads".to_string();
+        root_node.add_child(&pads_node);
+
+        let mut sun_node = SceneNode::new();
+        sun_node.name = "sun".to_string();
+        sun_node.rotation = aim(&SUN_DIRECTION.into());
+        sun_node.light = Some(Light::Directional {
+            color: SUN_COLOR.into(),
+            intensity: 1.0,
+        });
+        root_node.add_child(&sun_node);
+
+        let mut streamed_node = SceneNode::new();
+        streamed_node.name = "streamed".to_string();
+        root_node.add_child(&streamed_node);
+        let terrain_bounds = ctx.assets.get(terrain).and_then(|mesh| mesh.bounds());
+        let mut streamer = match terrain_bounds {
+            Some(bounds) => create_streamer(&args, &bounds),
+            None => Streamer::new(1.0, 2.0, 1),
+        };
+        streamer.set_blocking(!ctx.real_time);
+
+        let simple_pipeline = ctx.assets.load_pipeline(&mut ctx.backend, "simple")?;
+        let overlay_pipeline = ctx.assets.load_pipeline(&mut ctx.backend, "overlay")?;
+        let shadow_pipeline = ctx.assets.load_pipeline(&mut ctx.backend, "shadow")?;
+        let point_shadow_pipeline = ctx.assets.load_pipeline(&mut ctx.backend, "point_shadow")?;
+        let gbuffer_depth_pipeline = ctx
+            .assets
+            .load_pipeline(&mut ctx.backend, "gbuffer_depth")?;
+        let post_pipeline = ctx.assets.load_pipeline(&mut ctx.backend, "post")?;
+        let skybox_pipeline = ctx.assets.load_pipeline(&mut ctx.backend, "skybox")?;
+        let environment_pipeline = ctx.assets.load_pipeline(&mut ctx.backend, "environment")?;
+        let ssr_pipeline = ctx.assets.load_pipeline(&mut ctx.backend, "ssr")?;
+        let skybox = create_skybox(args.skybox.as_deref());
+        let environment = create_environment(ctx, environment_pipeline, skybox.as_ref());
+
+        if let Some(pipeline) = ctx.assets.gpu(simple_pipeline) {
+            ctx.backend.set_pipeline(pipeline);
+        }
+        let tweaks = load_tweaks(ctx, simple_pipeline);
+
+        // Replays and captures step the simulation by the frame clock to stay deterministic
+        let ground = ctx.assets.get(terrain).and_then(|terrain| {
+            toolbox::HeightField::from_mesh(&terrain.vertices, &terrain.indices, GROUND_SPACING)
+        });
+        let waypoints = Arc::new(place_waypoints(ground.as_ref()));
+
+        let mut windsock_node = SceneNode::from_vao(ctx.assets.vao(windsock));
+        windsock_node.name = "windsock".to_string();
+        windsock_node.material = Some(WINDSOCK_MATERIAL);
+        let [x, z] = WINDSOCK_SPOT;
+        let height = ground.as_ref().and_then(|ground| ground.height_at(x, z));
+        windsock_node.position = glm::vec3(x, height.unwrap_or(0.0), z);
+        root_node.add_child(&windsock_node);
+        let mut ice_node = SceneNode::from_vao(ctx.assets.vao(ice));
+        ice_node.name = "ice".to_string();
+        ice_node.material = Some(ICE_MATERIAL);
+        let [x, z] = ICE_SPOT;
+        let height = ground.as_ref().and_then(|ground| ground.height_at(x, z));
+        ice_node.position = glm::vec3(x, height.unwrap_or(0.0) + ICE_HEIGHT, z);
+        root_node.add_child(&ice_node);
+        let ice_plane = glm::vec4(0.0, 1.0, 0.0, -ice_node.position.y);
+        let fleet = Fleet::new(helicopters.len(), true, ground.clone());
+        let fleet = if ctx.real_time {
+            Simulator::spawn(fleet, timing::SIMULATION_TIMESTEP)
+        } else {
+            Simulator::inline(fleet, timing::SIMULATION_TIMESTEP)
+        };
+
+        let asset_loader = loader::AssetLoader::spawn();
+        let saved_props = Props::load(props::SCENE_PATH).unwrap_or_else(|e| {
+            warn!("{}", e);
+            Props::default()
+        });
+        for model in &saved_props.models {
+            let _ = asset_loader.requester().send(model.path.clone());
+        }
+
+        Ok(Demo {
+            config,
+            asset_loader,
+            terrain_node,
+            detail_normal_map,
+            terrain_collider,
+            ground,
+            helicopter_meshes,
+            helicopters,
+            waypoints,
+            parked_helicopters: Vec::new(),
+            dropped_models: Vec::new(),
+            saved_props,
+            props_node,
+            static_batch: None,
+            static_batch_node,
+            static_batching: !args.no_batching,
+            streamer,
+            streamed_node,
+            streamed_nodes: HashMap::new(),
+            root_node,
+            octree: Octree::new(toolbox::Aabb {
+                min: glm::vec3(-500.0, -100.0, -500.0),
+                max: glm::vec3(500.0, 200.0, 500.0),
+            }),
+            culled_nodes: 0,
+            simple_pipeline,
+            overlay_pipeline,
+            tweaks,
+            sun_node,
+            lighting: Lighting::default(),
+            light_buffer: unsafe { LightBuffer::new() },
+            shadow_pipeline,
+            shadow_map: create_shadow_map(ctx.quality().detail),
+            shadow_commands: vec![],
+            shadow_detail: ctx.quality().detail,
+            point_shadow_pipeline,
+            point_shadow_maps: create_point_shadow_maps(ctx.quality().detail),
+            shadowed_points: vec![],
+            point_shadow_commands: vec![],
+            shadows: true,
+            render_path: RenderPath::default(),
+            gbuffer: None,
+            gbuffer_depth_pipeline,
+            bloom: true,
+            bloom_settings: BloomSettings::default(),
+            tone_mapping: ToneMapping::default(),
+            exposure: EXPOSURE,
+            depth_of_field: false,
+            focus: DepthOfField::default(),
+            motion_blur: true,
+            motion_blur_settings: MotionBlur::default(),
+            fxaa: ctx.samples == 0,
+            post_process: None,
+            post_process_samples: 0,
+            post_pipeline,
+            screen_space_reflections: true,
+            ssr_settings: SsrSettings::default(),
+            ssr: None,
+            ssr_pipeline,
+            skybox,
+            skybox_path: args.skybox.clone(),
+            skybox_pipeline,
+            environment,
+            environment_pipeline,
+            image_based_lighting: true,
+            reflections: true,
+            reflection: None,
+            reflection_commands: CommandList::new(),
+            reflection_view: glm::identity(),
+            ice_plane,
+            field_of_view: 45.0,
+            view_matrix: glm::identity(),
+            projection_matrix: glm::identity(),
+            view_projection: glm::identity(),
+            previous_view_projection: None,
+            commands: CommandList::new(),
+            current_camera: camera::FreeCamera::new(glm::vec3(0.0, 20.0, 60.0), 0.0, -0.2),
+            selected_node: None,
+            text_input: input::TextInput::default(),
+            fleet,
+            animation_paused: false,
+            time_scale: 1.0,
+            wireframe: false,
+            show_bounds: false,
+            debug_lines: unsafe { DebugLines::new() },
+            overlay: unsafe { DebugOverlay::new() },
+            dump_frame: false,
+            console: unsafe { Console::new() },
+            console_commands: console_commands(),
+            #[cfg(feature = "egui")]
+            ui: Some(unsafe { Ui::new(&ctx.gl)? }),
+            #[cfg(feature = "egui")]
+            ui_visible: false,
+            cursor_captured: false,
+            pilot_mode: true,
+            gamepad: if ctx.real_time { gamepad::open() } else { None },
+            chase_camera: camera::ChaseCamera::new(30.0, 5.0),
+            free_camera: camera::FreeCamera::new(glm::vec3(0.0, 20.0, 60.0), 0.0, -0.2),
+            camera_base_speed: 50.0,
+            double_tap: input::DoubleTap::new(0.3),
+            double_tap_sprint: false,
+            camera_path: camera::CameraPath::new(2.0),
+            camera_transition: camera::CameraPath::new(1.0)
+                .with_easing(toolbox::Easing::Cubic(toolbox::Ease::InOut)),
+            camera_shake: camera::CameraShake::new(CAMERA_SHAKE_SEED),
+            rotor_strikes: ContactTracker::new(),
+            audio: SpatialAudio::new(open_audio_output(ctx)),
+            game: None,
+            landing_pad,
+            pads_node,
+            pad_nodes: vec![],
+            hud_text: unsafe { TextRenderer::new() },
+            windsock_node,
+            windsock_skeleton: windsock_rig.skeleton,
+            windsock_animation,
+            clock: toolbox::AnimationClock::new(),
+        })
+    }
+
+    fn update(&mut self, ctx: &mut Context, input: &FrameInput, delta_time: f32) {
+        // The panels go first, so the scene leaves alone the input they use
+        #[cfg(feature = "egui")]
+        let (ui_pointer, ui_keyboard) = self.update_ui(ctx, input);
+        #[cfg(not(feature = "egui"))]
+        let (ui_pointer, ui_keyboard) = (false, false);
+
+        // The console takes every key while it is open
+        if !ui_keyboard {
+            if let Some(line) = self.console.update(input, &self.console_commands) {
+                let commands = std::mem::take(&mut self.console_commands);
+                let result = commands.execute(self, ctx, &line);
+                self.console_commands = commands;
+                self.console.report(result);
+            }
+        }
+        let typing = ui_keyboard || self.console.open;
+
+        // Return renames the selected node. While typing, the regular key bindings are suspended.
+        if !typing {
+            self.update_rename(input);
+        }
+        ctx.set_text_input_active(self.text_input.active || typing);
+
+        let no_keys = input::KeyState::new();
+        let keys = if self.text_input.active || typing {
+            &no_keys
+        } else {
+            &input.keys
+        };
+        let no_buttons = input::MouseButtonState::new();
+        let buttons = if ui_pointer {
+            &no_buttons
+        } else {
+            &input.buttons
+        };
+
+        if keys.just_pressed(KeyCode::KeyP) {
+            self.animation_paused = !self.animation_paused;
+            self.fleet.send(FleetInput::Paused(self.animation_paused));
+            self.clock.paused = self.animation_paused;
+            info!(
+                "Animation {}",
+                if self.animation_paused {
+                    "paused"
+                } else {
+                    "resumed"
+                }
+            );
+        }
+        if keys.just_pressed(KeyCode::Period) && self.animation_paused {
+            self.fleet.send(FleetInput::Step);
+            self.clock.step();
+        }
+        let time_scale = if keys.just_pressed(KeyCode::BracketRight) {
+            self.time_scale * 2.0
+        } else if keys.just_pressed(KeyCode::BracketLeft) {
+            self.time_scale / 2.0
+        } else {
+            self.time_scale
+        };
+        if time_scale != self.time_scale {
+            self.time_scale = time_scale.clamp(
+                toolbox::AnimationClock::MIN_TIME_SCALE,
+                toolbox::AnimationClock::MAX_TIME_SCALE,
+            );
+            self.fleet.send(FleetInput::TimeScale(self.time_scale));
+            self.clock.set_time_scale(self.time_scale);
+            info!("Time scale: {:.2}x", self.time_scale);
+        }
+
+        self.double_tap.update(keys, ctx.elapsed);
+
+        // Ctrl+S saves the props placed in the scene
+        let chord_keys = save_chord(keys);
+        if chord_keys.is_some() {
+            match self.props().save(props::SCENE_PATH) {
+                Ok(()) => info!("Saved scene to {}", props::SCENE_PATH),
+                Err(e) => warn!("Failed to save scene: {}", e),
+            }
+        }
+        let keys = chord_keys.as_ref().unwrap_or(keys);
+
+        // Toggle cursor grab and visibility. The window belongs to this thread's context, so
+        // the event loop only forwards the key press and the change is applied here.
+        if keys.just_pressed(KeyCode::Tab) {
+            self.cursor_captured = !self.cursor_captured;
+            input::set_cursor_captured(ctx.window(), self.cursor_captured);
+        }
+
+        // Holding the right mouse button grabs the cursor for free-look while it is held
+        if !self.cursor_captured && buttons.just_pressed(MouseButton::Right) {
+            input::set_cursor_captured(ctx.window(), true);
+        }
+        if !self.cursor_captured && buttons.just_released(MouseButton::Right) {
+            input::set_cursor_captured(ctx.window(), false);
+        }
+        let free_look = self.cursor_captured || buttons.is_held(MouseButton::Right);
+
+        // Toggle borderless fullscreen. The window reports its new size through the event
+        // loop, which updates the viewport like any other resize.
+        if let Some(window) = ctx.window().filter(|_| keys.just_pressed(KeyCode::F11)) {
+            if window.fullscreen().is_some() {
+                window.set_fullscreen(None);
+            } else {
+                window.set_fullscreen(Some(Fullscreen::Borderless(window.current_monitor())));
+            }
+        }
+
+        // Toggle vsync
+        if keys.just_pressed(KeyCode::KeyV) {
+            match ctx.set_vsync(!ctx.vsync()) {
+                Ok(()) => info!("VSync: {}", if ctx.vsync() { "on" } else { "off" }),
+                Err(e) => warn!("Failed to change vsync: {}", e),
+            }
+        }
+
+        // Compile the shaders again, whether or not the files are watched
+        if keys.just_pressed(KeyCode::KeyR) {
+            let _ = reload_shaders(self, ctx);
+        }
+
+        if keys.just_pressed(KeyCode::KeyB) {
+            self.show_bounds = !self.show_bounds;
+        }
+
+        if keys.just_pressed(KeyCode::KeyF) {
+            self.depth_of_field = !self.depth_of_field;
+            info!("Depth of field: {}", on_off(self.depth_of_field));
+        }
+        if keys.just_pressed(KeyCode::KeyN) {
+            self.motion_blur = !self.motion_blur;
+            info!("Motion blur: {}", on_off(self.motion_blur));
+        }
+        let focus_distance = if keys.just_pressed(KeyCode::PageUp) {
+            self.focus.focus_distance * FOCUS_STEP
+        } else if keys.just_pressed(KeyCode::PageDown) {
+            self.focus.focus_distance / FOCUS_STEP
+        } else {
+            self.focus.focus_distance
+        };
+        if focus_distance != self.focus.focus_distance {
+            self.focus.focus_distance =
+                focus_distance.clamp(MIN_FOCUS_DISTANCE, MAX_FOCUS_DISTANCE);
+            info!("Focused {:.1} away", self.focus.focus_distance);
+        }
+
+        if keys.just_pressed(KeyCode::F3) {
+            self.overlay.visible = !self.overlay.visible;
+        }
+        self.overlay.frame(delta_time);
+
+        if keys.just_pressed(KeyCode::F9) {
+            self.dump_frame = true;
+        }
+
+        if keys.just_pressed(KeyCode::KeyG) {
+            let message = self.toggle_game(ctx);
+            self.overlay.status(&message, false);
+        }
+
+        if keys.just_pressed(KeyCode::KeyO) {
+            if let Some(index) = self.door_target() {
+                self.fleet.send(FleetInput::ToggleDoor(index));
+            }
+        }
+
+        // Toggle wireframe rendering
+        if keys.just_pressed(KeyCode::KeyZ) {
+            self.wireframe = !self.wireframe;
+            unsafe { set_wireframe(self.wireframe) };
+        }
+
+        // Toggle between flying the first helicopter and a free camera
+        if keys.just_pressed(KeyCode::KeyH) {
+            self.pilot_mode = !self.pilot_mode;
+            self.fleet.send(FleetInput::PilotMode(self.pilot_mode));
+            info!("Pilot mode: {}", if self.pilot_mode { "on" } else { "off" });
+        }
+
+        // Fly the first helicopter, and pose every helicopter between the last two steps
+        let sticks = self.gamepad.as_mut().map(|gamepad| gamepad.poll());
+        let sticks = sticks
+            .unwrap_or_default()
+            .with_deadzone(self.config.stick_deadzone);
+        let controls = pilot::Controls::from_keys(keys).with_gamepad(&sticks);
+        self.fleet.send(FleetInput::Controls(controls));
+        self.fleet.advance(delta_time);
+        for (helicopter, state) in self.helicopters.iter_mut().zip(self.fleet.snapshot()) {
+            state.apply_to(helicopter);
+        }
+        self.clock.tick(delta_time);
+        self.windsock_node.animate_skeleton(
+            &self.windsock_skeleton,
+            &self.windsock_animation,
+            self.clock.time,
+        );
+
+        if self.pilot_mode {
+            self.play_game(delta_time);
+        }
+
+        // Select the first helicopter for control (helicopters[0])
+        let controlled_helicopter = self.helicopters[0].as_mut();
+        let controlled_body_node =
+            unsafe { controlled_helicopter.get_unchecked_mut() }.get_child(0);
+
+        // Start the free camera where the chase camera left off
+        if keys.just_pressed(KeyCode::KeyH) && !self.pilot_mode {
+            self.free_camera = camera::FreeCamera::looking_at(
+                self.chase_camera.eye(
+                    &controlled_body_node.position,
+                    controlled_body_node.rotation.y,
+                ),
+                controlled_body_node.position,
+            );
+        }
+
+        // Handle mouse movement. The mouse only steers the camera during free-look, so it stays usable otherwise.
+        // The right stick looks around with the free camera, and flies the helicopter in pilot mode.
+        let mut look = if free_look {
+            let mouse_delta = if self.config.raw_mouse_input {
+                input.mouse_delta
+            } else {
+                input.cursor_delta
+            };
+            input::look_delta(mouse_delta, &self.config)
+        } else {
+            (0.0, 0.0)
+        };
+        if !self.pilot_mode {
+            let stick_look = input::stick_look_delta(sticks.right_stick, &self.config, delta_time);
+            look = (look.0 + stick_look.0, look.1 + stick_look.1);
+        }
+
+        // Scrolling and gestures over the panels are theirs
+        let (scroll_delta, pan_delta, zoom_delta) = if ui_pointer {
+            (0.0, (0.0, 0.0), 0.0)
+        } else {
+            (input.scroll_delta, input.pan_delta, input.zoom_delta)
+        };
+
+        // Scroll to change the free camera's base speed
+        self.camera_base_speed =
+            (self.camera_base_speed * 1.1_f32.powf(scroll_delta)).clamp(1.0, 1000.0);
+
+        // Touchpad gestures: pinch to zoom, two-finger scroll to pan
+        let pan = (pan_delta.0 * 0.005, pan_delta.1 * 0.005);
+
+        if self.pilot_mode {
+            self.chase_camera.rotate(look.0, look.1);
+            self.chase_camera.rotate(-pan.0, -pan.1);
+            self.chase_camera.zoom(zoom_delta);
+        } else {
+            let free_camera = &mut self.free_camera;
+            free_camera.rotate(look.0, look.1);
+            let pan_distance = self.camera_base_speed * 0.2;
+            free_camera.position += free_camera.forward() * zoom_delta * pan_distance * 5.0
+                - free_camera.right() * pan.0 * pan_distance
+                + glm::vec3(0.0, pan.1 * pan_distance, 0.0);
+
+            // Hold Ctrl or double-tap W to sprint, and hold Alt for precision
+            if self.double_tap.double_tapped(KeyCode::KeyW) {
+                self.double_tap_sprint = true;
+            }
+            if !keys.is_held(KeyCode::KeyW) {
+                self.double_tap_sprint = false;
+            }
+            let mut speed_multiplier = 1.0;
+            if keys.modifier_held(input::Modifier::Ctrl) || self.double_tap_sprint {
+                speed_multiplier *= 4.0;
+            }
+            if keys.modifier_held(input::Modifier::Alt) {
+                speed_multiplier *= 0.2;
+            }
+
+            let camera_move_speed = self.camera_base_speed * speed_multiplier * delta_time;
+            let forward = free_camera.forward();
+            let right = free_camera.right();
+            free_camera.position += (forward * sticks.left_stick.1
+                + right * sticks.left_stick.0
+                + free_camera_movement(keys, forward, right))
+                * camera_move_speed;
+            self.free_camera.position = push_out_of_terrain(
+                &self.terrain_collider,
+                &self.terrain_node.local_transform(),
+                &self.free_camera.position,
+            );
+        }
+
+        // Adjust look settings at runtime, saving them so they survive a restart
+        let config = &mut self.config;
+        let mut config_changed = false;
+        if keys.just_pressed(KeyCode::Equal) {
+            config.look_sensitivity *= 1.25;
+            config_changed = true;
+        }
+        if keys.just_pressed(KeyCode::Minus) {
+            config.look_sensitivity /= 1.25;
+            config_changed = true;
+        }
+        if keys.just_pressed(KeyCode::KeyI) {
+            config.invert_y = !config.invert_y;
+            config_changed = true;
+        }
+        if keys.just_pressed(KeyCode::KeyM) {
+            config.raw_mouse_input = !config.raw_mouse_input;
+            config_changed = true;
+        }
+        if config_changed {
+            info!(
+                "Look sensitivity: {:.4}, invert Y: {}, raw mouse input: {}",
+                config.look_sensitivity, config.invert_y, config.raw_mouse_input
+            );
+            if let Err(e) = config.save(config::CONFIG_PATH) {
+                warn!("{}", e);
+            }
+        }
+
+        // The chase camera comes in front of the terrain rather than looking through it
+        let chase_target =
+            controlled_body_node.position + glm::vec3(0.0, self.chase_camera.height, 0.0);
+        let chase_eye = clip_to_terrain(
+            &self.terrain_collider,
+            &self.terrain_node.local_transform(),
+            &chase_target,
+            &self.chase_camera.eye(
+                &controlled_body_node.position,
+                controlled_body_node.rotation.y,
+            ),
+        );
+        let current_camera = if self.pilot_mode {
+            camera::FreeCamera::looking_at(chase_eye, chase_target)
+        } else {
+            self.free_camera
+        };
+        self.current_camera = current_camera;
+        crash::note(
+            "Camera",
+            format!(
+                "{} camera at ({:.2}, {:.2}, {:.2}), yaw {:.3}, pitch {:.3}, field of view {}",
+                if self.pilot_mode { "Chase" } else { "Free" },
+                current_camera.position.x,
+                current_camera.position.y,
+                current_camera.position.z,
+                current_camera.yaw,
+                current_camera.pitch,
+                self.field_of_view
+            ),
+        );
+
+        if keys.just_pressed(KeyCode::KeyK) {
+            self.camera_path.record(current_camera);
+        }
+        if keys.just_pressed(KeyCode::KeyJ) {
+            self.camera_path.clear();
+            info!("Cleared camera path");
+        }
+        if keys.just_pressed(KeyCode::KeyL) {
+            if self.camera_path.is_playing() {
+                self.camera_path.stop();
+            } else {
+                self.camera_path.play();
+            }
+        }
+
+        // Load files dropped onto the window in the background
+        for path in &input.dropped_files {
+            let _ = self.asset_loader.requester().send(path.clone());
+        }
+
+        // Merged into the static batch again at the end of the update if props were added
+        let mut props_changed = false;
+
+        // Park the helicopters saved by an earlier run
+        for position in std::mem::take(&mut self.saved_props.helicopters) {
+            let mut parked_helicopter = create_helicopter(&ctx.assets, &self.helicopter_meshes);
+            parked_helicopter.position = position;
+            self.props_node.add_child(&parked_helicopter);
+            self.parked_helicopters.push(parked_helicopter);
+            props_changed = true;
+        }
+
+        // Attach models that were dropped onto the window in front of the camera, scaled so
+        // they fit nicely in view, and saved models where they were
+        for model in self.asset_loader.finished() {
+            let saved = self
+                .saved_props
+                .models
+                .iter()
+                .position(|saved| saved.path == model.path);
+            let saved = saved.map(|i| self.saved_props.models.remove(i));
+            let meshes = match model.meshes {
+                Ok(meshes) => meshes,
+                Err(e) => {
+                    warn!("{}", e);
+                    continue;
+                }
+            };
+            let mut model_node = SceneNode::new();
+            model_node.name = model
+                .path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let mut model_bounds: Option<toolbox::Aabb> = None;
+            let meshes = ctx.assets.add_model(&model.path, mesh::load_obj, meshes);
+            ctx.assets.upload(&mut ctx.backend);
+            for mesh in meshes {
+                let mut mesh_node = mesh_node(&ctx.assets, mesh);
+                mesh_node.material = Some(PAINT_MATERIAL);
+                if let Some(bounds) = mesh_node.bounds {
+                    model_bounds = Some(match model_bounds {
+                        Some(b) => toolbox::Aabb {
+                            min: glm::min2(&b.min, &bounds.min),
+                            max: glm::max2(&b.max, &bounds.max),
+                        },
+                        None => bounds,
+                    });
+                }
+                model_node.add_child(&mesh_node);
+            }
+            if let Some(saved) = saved {
+                model_node.scale = glm::vec3(saved.scale, saved.scale, saved.scale);
+                model_node.position = saved.position;
+            } else if let Some(bounds) = model_bounds {
+                let size = glm::comp_max(&(bounds.max - bounds.min)).max(1e-3);
+                let scale = 10.0 / size;
+                model_node.scale = glm::vec3(scale, scale, scale);
+                // Center the model on the point in front of the camera
+                model_node.position = current_camera.position + current_camera.forward() * 30.0
+                    - (bounds.min + bounds.max) * 0.5 * scale;
+            }
+            info!("Added {} to the scene", model.path.display());
+            self.props_node.add_child(&model_node);
+            self.dropped_models.push((model.path, model_node));
+            props_changed = true;
+        }
+
+        // Ctrl+1..9 saves the current view to a bookmark, 1..9 flies the free camera back to it
+        let ctrl_held = keys.modifier_held(input::Modifier::Ctrl);
+        for (slot, &key) in BOOKMARK_KEYS.iter().enumerate() {
+            if !keys.just_pressed(key) {
+                continue;
+            }
+            if ctrl_held {
+                self.config.bookmarks[slot] = Some(current_camera);
+                match self.config.save(config::CONFIG_PATH) {
+                    Ok(()) => info!("Saved camera bookmark {}", slot + 1),
+                    Err(e) => warn!("{}", e),
+                }
+            } else if let Some(bookmark) = self.config.bookmarks[slot] {
+                self.camera_transition.clear();
+                self.camera_transition.keyframes = vec![current_camera, bookmark];
+                self.camera_transition.play();
+                self.free_camera = bookmark;
+                self.pilot_mode = false;
+                self.fleet.send(FleetInput::PilotMode(false));
+            } else {
+                warn!("Camera bookmark {} is empty", slot + 1);
+            }
+        }
+
+        let look_at_matrix = if let Some(path_camera) = self.camera_path.advance(delta_time) {
+            path_camera.view_matrix()
+        } else if let Some(transition_camera) = self.camera_transition.advance(delta_time) {
+            transition_camera.view_matrix()
+        } else if self.pilot_mode {
+            glm::look_at(&chase_eye, &chase_target, &glm::vec3(0.0, 1.0, 0.0))
+        } else {
+            self.free_camera.view_matrix()
+        };
+
+        // == // Please compute camera transforms here (exercise 2 & 3)
+
+        // Excercise2 Task4 Part b)
+        let projection_matrix = glm::perspective(
+            ctx.aspect_ratio(),
+            self.field_of_view.to_radians(),
+            1.0,
+            1000.0,
+        );
+
+        self.camera_shake.update(delta_time);
+        let view_matrix = self.camera_shake.matrix() * look_at_matrix;
+
+        let combined_matrix = projection_matrix * view_matrix;
+        self.view_matrix = view_matrix;
+        self.projection_matrix = projection_matrix;
+        self.view_projection = combined_matrix;
+        self.audio.update_scene(&self.root_node, &view_matrix);
+        let camera_position = glm::inverse(&view_matrix).column(3).xyz();
+        self.lighting = Lighting::gather(&self.root_node, &camera_position);
+
+        let stream_events =
+            self.streamer
+                .update(&current_camera.position, &mut ctx.assets, &mut ctx.backend);
+        if !stream_events.is_empty() {
+            self.streamed(ctx, stream_events);
+        }
+
+        self.octree.update_scene(&self.root_node);
+        if self.pilot_mode {
+            self.check_rotor_strikes(ctx);
+        }
+
+        // Shift+left click parks a new helicopter where the cursor points at the terrain
+        let shift_held = keys.modifier_held(input::Modifier::Shift);
+        if !free_look && shift_held && buttons.just_pressed(MouseButton::Left) {
+            if let Some(cursor) = input.cursor_position {
+                let ray = toolbox::Ray::from_screen(cursor, input.window_size, &combined_matrix);
+                let terrain_ray =
+                    ray.transformed(&glm::inverse(&self.terrain_node.local_transform()));
+                if let Some(t) = self.terrain_collider.raycast(&terrain_ray) {
+                    let mut parked_helicopter =
+                        create_helicopter(&ctx.assets, &self.helicopter_meshes);
+                    parked_helicopter.position = ray.at(t);
+                    self.props_node.add_child(&parked_helicopter);
+                    self.parked_helicopters.push(parked_helicopter);
+                    self.camera_shake.add_trauma(PARKING_TRAUMA);
+                    props_changed = true;
+                }
+            }
+        }
+
+        // Left click selects the scene node under the cursor, where its triangles are rather than
+        // anywhere in its bounds
+        if !free_look && !shift_held && buttons.just_pressed(MouseButton::Left) {
+            if let Some(cursor) = input.cursor_position {
+                let ray = toolbox::Ray::from_screen(cursor, input.window_size, &combined_matrix);
+                let hit = collision::pick(&self.octree, &ray, &ctx.assets.meshes_by_vao());
+                self.select(hit.map(|(_, node)| node));
+            }
+        }
+
+        if props_changed {
+            self.rebuild_static_batch(ctx);
+        }
+
+        // The shadow maps are made again at another size when the quality's detail changes
+        let detail = ctx.quality().detail;
+        if detail != self.shadow_detail {
+            self.shadow_detail = detail;
+            if let Some(shadow_map) = self.shadow_map.take() {
+                unsafe { shadow_map.delete() };
+            }
+            if let Some(point_shadow_maps) = self.point_shadow_maps.take() {
+                unsafe { point_shadow_maps.delete() };
+            }
+            self.shadow_map = create_shadow_map(detail);
+            self.point_shadow_maps = create_point_shadow_maps(detail);
+        }
+
+        // Shadows are cast by what the sun sees rather than the camera, so its draws are recorded
+        // first, culled to its view of each slice of the camera's. Culling for the camera
+        // afterwards leaves the nodes as it needs.
+        let sun = self.lighting.directional.first();
+        let shadow_pipeline = ctx.assets.gpu(self.shadow_pipeline);
+        if let (Some(shadow_map), Some(sun), Some(pipeline), true) =
+            (&mut self.shadow_map, sun, shadow_pipeline, self.shadows)
+        {
+            shadow_map.fit(
+                &sun.direction,
+                &view_matrix,
+                self.field_of_view.to_radians(),
+                ctx.aspect_ratio(),
+                1.0,
+                SHADOW_DISTANCE,
+            );
+            self.shadow_commands
+                .resize_with(shadow::CASCADES, CommandList::new);
+            for (light_space, commands) in shadow_map
+                .light_spaces
+                .iter()
+                .zip(&mut self.shadow_commands)
+            {
+                self.octree
+                    .cull(&toolbox::Frustum::from_matrix(light_space));
+                commands.clear();
+                commands.set_pipeline(pipeline);
+                renderer::record_scene(&self.root_node, light_space, commands, &ctx.arena);
+            }
+        } else {
+            self.shadow_commands.clear();
+        }
+
+        // Likewise for the lamps casting shadows, culled to what they reach. Their draws are made
+        // in the world, as the geometry shader projects them onto each face of the cube map.
+        let point_shadow_pipeline = ctx.assets.gpu(self.point_shadow_pipeline);
+        self.shadowed_points = match (&self.point_shadow_maps, point_shadow_pipeline) {
+            (Some(_), Some(_)) if self.shadows => self
+                .lighting
+                .shadowed_point_lights(MAX_SHADOWED_POINT_LIGHTS),
+            _ => vec![],
+        };
+        self.point_shadow_commands
+            .resize_with(self.shadowed_points.len(), CommandList::new);
+        for (light, commands) in self
+            .shadowed_points
+            .iter()
+            .zip(&mut self.point_shadow_commands)
+        {
+            let reach = shadow::reach_matrix(&light.position, light.range);
+            self.octree.cull(&toolbox::Frustum::from_matrix(&reach));
+            commands.clear();
+            if let Some(pipeline) = point_shadow_pipeline {
+                commands.set_pipeline(pipeline);
+            }
+            renderer::record_scene(&self.root_node, &glm::identity(), commands, &ctx.arena);
+        }
+
+        // The ice reflects what the camera mirrored in it sees, unless the camera is below it
+        self.reflection_commands.clear();
+        let camera_position = self.lighting.camera_position;
+        let above_ice = glm::dot(&self.ice_plane.xyz(), &camera_position) + self.ice_plane.w > 0.0;
+        if self.reflections && above_ice {
+            self.reflection_view = view_matrix * reflection::mirror(&self.ice_plane);
+            let view_projection = projection_matrix * self.reflection_view;
+            self.octree
+                .cull(&toolbox::Frustum::from_matrix(&view_projection));
+            if let Some(pipeline) = ctx.assets.gpu(self.simple_pipeline) {
+                self.reflection_commands.set_pipeline(pipeline);
+            }
+            renderer::record_scene(
+                &self.root_node,
+                &view_projection,
+                &mut self.reflection_commands,
+                &ctx.arena,
+            );
+        }
+
+        self.culled_nodes = self
+            .octree
+            .cull(&toolbox::Frustum::from_matrix(&self.view_projection));
+
+        // Traversal needs no OpenGL, so the draws are recorded here and `render` only replays them
+        self.commands.clear();
+        if let Some(pipeline) = ctx.assets.gpu(self.simple_pipeline) {
+            self.commands.set_pipeline(pipeline);
+        }
+        renderer::record_scene(
+            &self.root_node,
+            &self.view_projection,
+            &mut self.commands,
+            &ctx.arena,
+        );
+        // Every view has been recorded with where the nodes were, so they can move on from there
+        self.root_node.remember_transforms(&glm::identity());
+    }
+
+    fn render(&mut self, ctx: &mut Context) {
+        // == // Issue the necessary gl:: commands to draw your scene here
+
+        if self.dump_frame {
+            ctx.backend.record_draws();
+        }
+        ctx.backend.begin_frame(&SKY_COLOR.into());
+        ctx.backend.count_culled(self.culled_nodes);
+
+        if let Some(shadow_map) = &mut self.shadow_map {
+            if !self.shadow_commands.is_empty() {
+                ctx.backend.push_group("Shadows");
+                unsafe { shadow_map.begin() };
+                for (cascade, commands) in self.shadow_commands.iter().enumerate() {
+                    unsafe { shadow_map.begin_cascade(cascade) };
+                    commands.execute(&mut ctx.backend);
+                }
+                unsafe { shadow_map.end() };
+                ctx.backend.pop_group();
+            }
+        }
+        let point_shadow_pipeline = ctx.assets.gpu(self.point_shadow_pipeline);
+        if let (Some(maps), Some(pipeline)) = (&mut self.point_shadow_maps, point_shadow_pipeline) {
+            if !self.shadowed_points.is_empty() {
+                ctx.backend.push_group("Point shadows");
+                unsafe { maps.begin() };
+                let lights = self.shadowed_points.iter().zip(&self.point_shadow_commands);
+                for (i, (light, commands)) in lights.enumerate() {
+                    unsafe { maps.set_light(&ctx.backend, pipeline, i, light) };
+                    commands.execute(&mut ctx.backend);
+                }
+                unsafe { maps.end() };
+                ctx.backend.pop_group();
+            }
+        }
+
+        let reflecting = !self.reflection_commands.is_empty();
+        if reflecting {
+            self.fit_reflection(ctx.viewport_size);
+        }
+        if let Some(pipeline) = ctx.assets.gpu(self.simple_pipeline) {
+            unsafe {
+                self.tweaks.apply(&ctx.backend, pipeline);
+                self.lighting.apply(&ctx.backend, pipeline);
+                self.light_buffer
+                    .upload(&self.lighting, self.shadowed_points.len());
+                if let Some(shadow_map) = &self.shadow_map {
+                    let enabled = !self.shadow_commands.is_empty();
+                    shadow_map.apply(&ctx.backend, pipeline, enabled);
+                }
+                if let Some(maps) = &self.point_shadow_maps {
+                    maps.apply(&ctx.backend, pipeline);
+                }
+                if let Some(environment) = &self.environment {
+                    environment.apply(&ctx.backend, pipeline, self.image_based_lighting);
+                }
+                if let Some(reflection) = &self.reflection {
+                    reflection.apply(&ctx.backend, pipeline, reflecting);
+                }
+            }
+        }
+
+        let simple_pipeline = ctx.assets.gpu(self.simple_pipeline);
+        let reflection = self.reflection.as_mut().filter(|_| reflecting);
+        if let (Some(reflection), Some(pipeline)) = (reflection, simple_pipeline) {
+            ctx.backend.push_group("Reflection");
+            let camera_position = &self.lighting.camera_position;
+            unsafe {
+                reflection.begin(&SKY_COLOR.into());
+                reflection::set_mirror(
+                    &ctx.backend,
+                    pipeline,
+                    Some(&self.ice_plane),
+                    camera_position,
+                );
+            }
+            self.reflection_commands
+                .execute_where(&mut ctx.backend, |draw| !reflects(draw));
+            unsafe { reflection::set_mirror(&ctx.backend, pipeline, None, camera_position) };
+            let skybox_pipeline = ctx.assets.gpu(self.skybox_pipeline);
+            if let (Some(skybox), Some(skybox_pipeline)) = (&self.skybox, skybox_pipeline) {
+                unsafe {
+                    skybox.draw(
+                        &mut ctx.backend,
+                        skybox_pipeline,
+                        &self.reflection_view,
+                        &self.projection_matrix,
+                    )
+                };
+            }
+            unsafe { reflection.end() };
+            ctx.backend.pop_group();
+        }
+
+        let post_processing = self.post_processing();
+        if post_processing {
+            self.fit_post_process(ctx.viewport_size, ctx.samples);
+        }
+        let post_pipeline = ctx
+            .assets
+            .gpu(self.post_pipeline)
+            .filter(|_| post_processing);
+        let post_process = self.post_process.as_mut().zip(post_pipeline);
+        if let Some((post_process, _)) = post_process {
+            unsafe { post_process.begin(&SKY_COLOR.into()) };
+        }
+
+        let pipelines = (
+            ctx.assets.gpu(self.simple_pipeline),
+            ctx.assets.gpu(self.gbuffer_depth_pipeline),
+        );
+        if self.render_path == RenderPath::Deferred {
+            self.fit_gbuffer(ctx.viewport_size);
+        }
+        if self.render_path == RenderPath::Deferred && self.screen_space_reflections {
+            self.fit_ssr(ctx.viewport_size);
+        }
+        let deferred = self.render_path == RenderPath::Deferred;
+        let gbuffer = self.gbuffer.as_mut().filter(|_| deferred);
+        if let (Some(gbuffer), (Some(pipeline), Some(depth_pipeline))) = (gbuffer, pipelines) {
+            ctx.backend.push_group("G-buffer");
+            unsafe { gbuffer.begin(&ctx.backend, pipeline) };
+            self.commands
+                .execute_where(&mut ctx.backend, |draw| !drawn_last(draw));
+            unsafe { gbuffer.end(&ctx.backend, pipeline) };
+            ctx.backend.pop_group();
+
+            ctx.backend.push_group("Lighting");
+            unsafe { gbuffer.resolve(&mut ctx.backend, pipeline, depth_pipeline) };
+            ctx.backend.pop_group();
+
+            let ssr_pipeline = ctx.assets.gpu(self.ssr_pipeline);
+            let scene = (self.post_process.as_ref())
+                .filter(|_| post_pipeline.is_some())
+                .map(|post_process| unsafe { post_process.resolve_scene() });
+            if let (Some(ssr), Some(ssr_pipeline), Some(scene)) = (&self.ssr, ssr_pipeline, scene) {
+                ctx.backend.push_group("Screen-space reflections");
+                unsafe {
+                    ssr.draw(
+                        &mut ctx.backend,
+                        ssr_pipeline,
+                        gbuffer,
+                        scene,
+                        &self.view_projection,
+                        &self.lighting.camera_position,
+                        &self.ssr_settings,
+                    )
+                };
+                ctx.backend.pop_group();
+            }
+        } else {
+            ctx.backend.push_group("Scene");
+            self.commands
+                .execute_where(&mut ctx.backend, |draw| !drawn_last(draw));
+            ctx.backend.pop_group();
+        }
+
+        let skybox_pipeline = ctx.assets.gpu(self.skybox_pipeline);
+        if let (Some(skybox), Some(pipeline)) = (&self.skybox, skybox_pipeline) {
+            ctx.backend.push_group("Sky");
+            unsafe {
+                skybox.draw(
+                    &mut ctx.backend,
+                    pipeline,
+                    &self.view_matrix,
+                    &self.projection_matrix,
+                )
+            };
+            ctx.backend.pop_group();
+        }
+
+        // See-through meshes last, over what is behind them, the sky too, and reflective ones with
+        // them. The G-buffer has no room for either.
+        ctx.backend.push_group("See-through");
+        self.commands.execute_where(&mut ctx.backend, drawn_last);
+        ctx.backend.pop_group();
+
+        if self.show_bounds {
+            ctx.backend.push_group("Bounds");
+            self.debug_lines
+                .scene_bounds(&self.root_node, &glm::identity());
+            for (i, waypoint) in self.waypoints.iter().enumerate() {
+                let marker = toolbox::Aabb {
+                    min: waypoint - glm::vec3(1.0, 1.0, 1.0),
+                    max: waypoint + glm::vec3(1.0, 1.0, 1.0),
+                };
+                self.debug_lines.aabb(&marker, &glm::identity());
+                let next = self.waypoints[(i + 1) % self.waypoints.len()];
+                self.debug_lines.line(waypoint, &next);
+            }
+            unsafe {
+                self.debug_lines
+                    .draw(&mut ctx.backend, &self.view_projection)
+            };
+            ctx.backend.pop_group();
+        }
+
+        // How far everything moved since the last frame, the see-through meshes too, so the rotors'
+        // blades are blurred along the way they turn
+        let moving = post_pipeline.is_some() && self.motion_blur;
+        let post_process = self.post_process.as_mut().filter(|_| moving);
+        if let (Some(post_process), Some(pipeline)) = (post_process, simple_pipeline) {
+            ctx.backend.push_group("Velocity");
+            let previous = self
+                .previous_view_projection
+                .unwrap_or(self.view_projection);
+            unsafe {
+                post_process.begin_velocity(
+                    &ctx.backend,
+                    pipeline,
+                    &self.view_projection,
+                    &previous,
+                )
+            };
+            self.commands.execute(&mut ctx.backend);
+            unsafe { post_process.end_velocity(&ctx.backend, pipeline) };
+            ctx.backend.pop_group();
+        }
+        self.previous_view_projection = Some(self.view_projection);
+
+        // The HUD and the rest are drawn over the filtered scene, so they don't glow
+        if let (Some(post_process), Some(pipeline)) = (&self.post_process, post_pipeline) {
+            ctx.backend.push_group("Post-processing");
+            let focus = Some(&self.focus).filter(|_| self.depth_of_field);
+            let motion_blur = Some(&self.motion_blur_settings).filter(|_| self.motion_blur);
+            let bloom = Some(&self.bloom_settings).filter(|_| self.bloom);
+            unsafe {
+                post_process.end(
+                    &mut ctx.backend,
+                    pipeline,
+                    focus,
+                    motion_blur,
+                    bloom,
+                    self.tone_mapping,
+                    self.exposure,
+                    &self.projection_matrix,
+                    self.fxaa,
+                )
+            };
+            ctx.backend.pop_group();
+        }
+
+        let overlay_pipeline = ctx.assets.gpu(self.overlay_pipeline);
+        if let (Some(game), Some(pipeline)) = (&self.game, overlay_pipeline) {
+            let body = &self.helicopters[0][0];
+            game.draw_hud(
+                &mut self.hud_text,
+                ctx.viewport_size,
+                &body.position,
+                body.rotation.y,
+            );
+            ctx.backend.push_group("HUD");
+            unsafe {
+                self.hud_text
+                    .draw(&mut ctx.backend, pipeline, ctx.viewport_size)
+            };
+            ctx.backend.pop_group();
+        }
+
+        if let Some(pipeline) = overlay_pipeline.filter(|_| self.overlay.is_shown()) {
+            // Statistics of the scene, before the overlay adds its own draw
+            let stats = OverlayStats {
+                render: ctx.backend.stats().clone(),
+                memory: ctx.backend.memory_usage(),
+                camera_position: self.current_camera.position,
+            };
+            ctx.backend.push_group("Overlay");
+            unsafe {
+                self.overlay
+                    .draw(&mut ctx.backend, pipeline, ctx.viewport_size, &stats)
+            };
+            ctx.backend.pop_group();
+        }
+
+        if let Some(pipeline) = overlay_pipeline.filter(|_| self.console.open) {
+            ctx.backend.push_group("Console");
+            unsafe {
+                self.console
+                    .draw(&mut ctx.backend, pipeline, ctx.viewport_size)
+            };
+            ctx.backend.pop_group();
+        }
+
+        #[cfg(feature = "egui")]
+        match &mut self.ui {
+            Some(ui) if self.ui_visible => {
+                ctx.backend.push_group("UI");
+                unsafe { ui.paint(ctx.viewport_size) };
+                ctx.backend.pop_group();
+            }
+            _ => {}
+        }
+
+        if std::mem::take(&mut self.dump_frame) {
+            let mut draws = ctx.backend.take_recorded_draws();
+            frame_dump::name_scene_draws(&mut draws, &self.root_node);
+            match frame_dump::write(&draws) {
+                Ok(path) => {
+                    let message = format!("Wrote {} draws to {}", draws.len(), path.display());
+                    info!("{}", message);
+                    self.overlay.status(&message, false);
+                }
+                Err(e) => {
+                    warn!("Failed to write the frame's draws: {}", e);
+                    self.overlay.status(&e.to_string(), true);
+                }
+            }
+        }
+
+        ctx.backend.end_frame();
+    }
+
+    fn context_recreated(
+        &mut self,
+        ctx: &mut Context,
+        vao_ids: &HashMap<u32, u32>,
+    ) -> Result<(), RenderError> {
+        self.root_node.remap_vao_ids(vao_ids);
+        // The textures were uploaded again too
+        let detail_normal_map = ctx.assets.gpu(self.detail_normal_map);
+        let streamed = self.streamed_nodes.values_mut().flatten();
+        for node in std::iter::once(&mut self.terrain_node).chain(streamed) {
+            if node.normal_map.is_some() {
+                node.normal_map = detail_normal_map;
+            }
+        }
+        if let Some(pipeline) = ctx.assets.gpu(self.simple_pipeline) {
+            ctx.backend.set_pipeline(pipeline);
+        }
+        unsafe { set_wireframe(self.wireframe) };
+        // The old buffers went away with the context
+        self.debug_lines = unsafe { DebugLines::new() };
+        self.light_buffer = unsafe { LightBuffer::new() };
+        self.shadow_map = create_shadow_map(self.shadow_detail);
+        self.point_shadow_maps = create_point_shadow_maps(self.shadow_detail);
+        self.gbuffer = None; // Made again at the next frame's size
+        self.post_process = None;
+        self.ssr = None;
+        self.skybox = create_skybox(self.skybox_path.as_deref());
+        self.reflection = None;
+        self.environment = create_environment(ctx, self.environment_pipeline, self.skybox.as_ref());
+        unsafe { self.overlay.context_recreated() };
+        unsafe { self.console.context_recreated() };
+        #[cfg(feature = "egui")]
+        if let Some(ui) = &mut self.ui {
+            unsafe { ui.context_recreated(&ctx.gl)? };
+        }
+        input::set_cursor_captured(ctx.window(), self.cursor_captured);
+        Ok(())
+    }
+
+    fn meshes_reloaded(&mut self, ctx: &mut Context, index_counts: &HashMap<u32, i32>) {
+        self.root_node.update_index_counts(index_counts);
+        self.rebuild_static_batch(ctx);
+    }
+
+    fn debug_view_target(&self) -> Option<(&SceneNode, glm::Vec3)> {
+        Some((&self.root_node, self.current_camera.position))
+    }
+}
+
+impl Demo {
+    // Return renames the selected node, with the name typed into the status line of the overlay
+    fn update_rename(&mut self, input: &FrameInput) {
+        self.text_input.type_text(&input.text);
+        if self.text_input.active {
+            if input.keys.just_pressed(KeyCode::Enter) {
+                let name = self.text_input.submit();
+                if let Some(node) = self.selected_node {
+                    unsafe { (*node).name = name.clone() };
+                }
+                let message = format!("Renamed node to \"{}\"", name);
+                info!("{}", message);
+                self.overlay.status(&message, false);
+            } else if input.keys.just_pressed(KeyCode::Escape) {
+                self.text_input.cancel();
+                self.overlay.status("Rename cancelled", false);
+            } else {
+                self.overlay
+                    .prompt(&format!("Rename: {}_", self.text_input.buffer));
+            }
+        } else if input.keys.just_pressed(KeyCode::Enter) {
+            if let Some(node) = self.selected_node {
+                self.text_input.begin(unsafe { &(*node).name });
+                self.overlay
+                    .prompt(&format!("Rename: {}_", self.text_input.buffer));
+            }
+        }
+    }
+
+    // The flying helicopter whose door O opens or closes: the one the selected node is part of, or
+    // else the one nearest to the camera
+    fn door_target(&self) -> Option<usize> {
+        if let Some(node) = self.selected_node {
+            let selected = self
+                .helicopters
+                .iter()
+                .position(|helicopter| helicopter.contains(node));
+            if selected.is_some() {
+                return selected;
+            }
+        }
+        let distance = |helicopter: &Node| {
+            glm::distance(&helicopter[0].position, &self.current_camera.position)
+        };
+        (0..self.helicopters.len()).min_by(|&a, &b| {
+            distance(&self.helicopters[a]).total_cmp(&distance(&self.helicopters[b]))
+        })
+    }
+
+    // The props placed so far, as saved with Ctrl+S, including saved models still loading
+    fn props(&self) -> Props {
+        let dropped = self.dropped_models.iter().map(|(path, model)| PlacedModel {
+            path: path.clone(),
+            position: model.position,
+            scale: model.scale.x,
+        });
+        Props {
+            helicopters: self
+                .parked_helicopters
+                .iter()
+                .map(|helicopter| helicopter.position)
+                .collect(),
+            models: dropped
+                .chain(self.saved_props.models.iter().cloned())
+                .collect(),
+        }
+    }
+
+    // Start the landing game with pads placed ahead of the flown helicopter, or stop it. Returns
+    // what happened.
+    fn toggle_game(&mut self, ctx: &mut Context) -> String {
+        for node in std::mem::take(&mut self.pad_nodes) {
+            let pointer = &**node as *const SceneNode as *mut SceneNode;
+            if self.selected_node == Some(pointer) {
+                self.select(None);
+            }
+            self.pads_node.children.retain(|&child| child != pointer);
+            drop(ManuallyDrop::into_inner(node));
+        }
+        if self.game.take().is_some() {
+            self.fleet.send(FleetInput::LandingPads(vec![]));
+            return "Stopped the landing game".to_string();
+        }
+        let Some(ground) = &self.ground else {
+            return "There is no ground to land on".to_string();
+        };
+
+        let pads = game::place_pads(ground, &self.helicopters[0][0].position);
+        for (i, (pad, depth)) in pads.iter().enumerate() {
+            let mut node = SceneNode::from_vao(ctx.assets.vao(self.landing_pad));
+            node.name = format!("landing pad {}", i + 1);
+            node.material = Some(PAD_MATERIAL);
+            node.position = pad.center;
+            node.scale.y = *depth;
+            self.pads_node.add_child(&node);
+            self.pad_nodes.push(node);
+        }
+        let pads: Vec<game::LandingPad> = pads.into_iter().map(|(pad, _)| pad).collect();
+        self.fleet.send(FleetInput::LandingPads(pads.clone()));
+        let count = pads.len();
+        self.game = Some(LandingGame::new(pads));
+        if !self.pilot_mode {
+            self.pilot_mode = true;
+            self.fleet.send(FleetInput::PilotMode(true));
+        }
+        format!("Land softly on the {} pads in turn", count)
+    }
+
+    // Score the flown helicopter's landings, and light up the pad it is to land on next
+    fn play_game(&mut self, delta_time: f32) {
+        let Some(game) = &mut self.game else {
+            return;
+        };
+        let position = self.helicopters[0][0].position;
+        let ground = game::ground_at(self.ground.as_ref(), &game.pads, position.x, position.z);
+        let skid_height = pilot::FlightModel::default().skid_height;
+        let outcome = game.update(&position, skid_height, ground, delta_time);
+        match outcome {
+            Some(game::Outcome::Landed { points, descent }) => {
+                info!("Landed at {:.1} m/s for {} points", descent, points)
+            }
+            Some(game::Outcome::Crashed { descent }) => {
+                info!("Landed too hard at {:.1} m/s", descent);
+                self.camera_shake.add_trauma(CRASH_TRAUMA);
+            }
+            Some(game::Outcome::OutOfTime) => {
+                info!("Ran out of time for landing pad {}", game.target)
+            }
+            None => {}
+        }
+        if outcome.is_some() && game.is_over() {
+            info!("Landing game over with {} points", game.score);
+        }
+        for (i, node) in self.pad_nodes.iter_mut().enumerate() {
+            let glow = if i == game.target {
+                TARGET_PAD_GLOW
+            } else {
+                glm::zero()
+            };
+            node.material = Some(PAD_MATERIAL.with_emissive(glow));
+        }
+    }
+
+    // Test the tips of the flown helicopter's main rotor against the terrain and the props while
+    // it turns, and stall it when it strikes something
+    fn check_rotor_strikes(&mut self, ctx: &Context) {
+        let Some(disc) = ctx
+            .assets
+            .get(self.helicopter_meshes.main_rotor_disc)
+            .and_then(|disc| disc.bounds())
+        else {
+            return;
+        };
+        let hub = (disc.min + disc.max) * 0.5;
+        let radius = (disc.max.x - disc.min.x) * 0.5;
+        let helicopter = &self.helicopters[0];
+        let to_world = helicopter.local_transform() * helicopter[0].local_transform();
+        let to_terrain = glm::inverse(&self.terrain_node.local_transform()) * to_world;
+        let tip_count = if self.fleet.snapshot()[0].rotor_speed > ROTOR_STRIKE_SPEED {
+            ROTOR_TIP_COUNT
+        } else {
+            0
+        };
+
+        let mut touching = vec![];
+        for i in 0..tip_count {
+            let angle = i as f32 / ROTOR_TIP_COUNT as f32 * std::f32::consts::TAU;
+            let tip = hub + glm::vec3(angle.cos(), 0.0, angle.sin()) * radius;
+            let terrain_tip = collision::Sphere {
+                center: (to_terrain * tip.push(1.0)).xyz(),
+                radius: ROTOR_TIP_RADIUS,
+            };
+            if self.terrain_collider.sphere_contact(&terrain_tip).is_some() {
+                touching.push(Obstacle::Terrain);
+            }
+            let world_tip = collision::Sphere {
+                center: (to_world * tip.push(1.0)).xyz(),
+                radius: ROTOR_TIP_RADIUS,
+            };
+            for (node, _) in collision::sphere_nodes(&self.octree, &world_tip) {
+                if self.props_node.contains(node) {
+                    touching.push(Obstacle::Node(node));
+                }
+            }
+        }
+
+        for event in self.rotor_strikes.update(touching) {
+            let CollisionEvent::Began(obstacle) = event else {
+                continue;
+            };
+            let what = match obstacle {
+                Obstacle::Terrain => "the ground".to_string(),
+                Obstacle::Node(node) => format!("\"{}\"", unsafe { &*node }.name),
+            };
+            let message = format!("Rotor strike against {}", what);
+            info!("{}", message);
+            self.overlay.status(&message, true);
+            self.camera_shake.add_trauma(ROTOR_STRIKE_TRAUMA);
+            self.fleet.send(FleetInput::RotorStrike(0));
+        }
+    }
+
+    // Highlight `node` instead of the node selected so far
+    fn select(&mut self, node: Option<*mut SceneNode>) {
+        unsafe {
+            if let Some(previous) = self.selected_node {
+                (*previous).selected = false;
+            }
+            self.selected_node = node;
+            if let Some(node) = self.selected_node {
+                (*node).selected = true;
+            }
+        }
+    }
+
+    // Add nodes for the regions that were streamed in, and remove those of the ones streamed out
+    fn streamed(&mut self, ctx: &mut Context, events: Vec<StreamEvent>) {
+        // The nodes need the meshes' VAOs. The streamer only hands out a few regions per frame.
+        ctx.assets.upload(&mut ctx.backend);
+        for event in events {
+            match event {
+                StreamEvent::Loaded { region, meshes } => {
+                    let nodes: Vec<Node> = meshes
+                        .into_iter()
+                        .map(|mesh| {
+                            // With bounds, so the octree culls and picks them like the rest
+                            let mut node = mesh_node(&ctx.assets, mesh);
+                            node.name = "streamed".to_string();
+                            // The tiles' ground has tangents for the craters, the wrecks don't
+                            if ctx
+                                .assets
+                                .get(mesh)
+                                .is_some_and(|mesh| !mesh.tangents.is_empty())
+                            {
+                                node.normal_map = ctx.assets.gpu(self.detail_normal_map);
+                                node.material = Some(GROUND_MATERIAL);
+                            } else {
+                                node.material = Some(WRECK_MATERIAL);
+                            }
+                            self.streamed_node.add_child(&node);
+                            node
+                        })
+                        .collect();
+                    self.streamed_nodes.insert(region, nodes);
+                }
+                StreamEvent::Unloaded(region) => {
+                    for node in self.streamed_nodes.remove(&region).unwrap_or_default() {
+                        let pointer = &**node as *const SceneNode as *mut SceneNode;
+                        if self.selected_node == Some(pointer) {
+                            self.select(None);
+                        }
+                        self.octree.remove(pointer);
+                        self.streamed_node
+                            .children
+                            .retain(|&child| child != pointer);
+                        // Unlike the rest of the scene, streamed nodes don't live forever
+                        drop(ManuallyDrop::into_inner(node));
+                    }
+                }
+            }
+        }
+    }
+
+    // Whether the scene is filtered on its way to the screen, rather than drawn straight to it
+    fn post_processing(&self) -> bool {
+        let reflecting = self.screen_space_reflections && self.render_path == RenderPath::Deferred;
+        self.bloom
+            || self.tone_mapping != ToneMapping::Clip
+            || self.exposure != 1.0
+            || self.depth_of_field
+            || self.motion_blur
+            || self.fxaa
+            || reflecting
+    }
+
+    // Make the post-processing's textures again when the viewport's size changed, multisampled with
+    // `samples` samples per pixel if they can be, as the window would be. If they can't be made,
+    // the scene is drawn straight to the screen, without bloom or tone mapping.
+    fn fit_post_process(&mut self, size: (u32, u32), samples: u32) {
+        // Made without multisampling when it isn't supported, so only asking for other samples
+        // makes them again
+        let fits = |post_process: &PostProcess| {
+            post_process.size() == size && self.post_process_samples == samples
+        };
+        if (self.post_process.as_ref()).is_some_and(|post_process| !fits(post_process)) {
+            unsafe { self.post_process.take().unwrap().delete() };
+        }
+        if self.post_process.is_none() {
+            self.post_process_samples = samples;
+            let multisampled = unsafe { PostProcess::new(size, samples) };
+            let post_process = multisampled.or_else(|e| {
+                if samples == 0 {
+                    return Err(e);
+                }
+                warn!("No multisampling while post-processing: {}", e);
+                unsafe { PostProcess::new(size, 0) }
+            });
+            match post_process {
+                Ok(post_process) => self.post_process = Some(post_process),
+                Err(e) => {
+                    warn!("No bloom or tone mapping: {}", e);
+                    self.bloom = false;
+                    self.motion_blur = false;
+                    self.fxaa = false;
+                    self.tone_mapping = ToneMapping::Clip;
+                    self.exposure = 1.0;
+                }
+            }
+        }
+    }
+
+    // Make the reflection again when the viewport's size changed. If it can't be made, the ice is
+    // drawn without it.
+    fn fit_reflection(&mut self, size: (u32, u32)) {
+        if (self.reflection.as_ref()).is_some_and(|reflection| reflection.size() != size) {
+            unsafe { self.reflection.take().unwrap().delete() };
+        }
+        if self.reflection.is_none() {
+            match unsafe { PlanarReflection::new(size) } {
+                Ok(reflection) => self.reflection = Some(reflection),
+                Err(e) => {
+                    warn!("No reflections: {}", e);
+                    self.reflections = false;
+                }
+            }
+        }
+    }
+
+    // Make the screen-space reflections' texture again when the viewport's size changed. If it
+    // can't be made, nothing is reflected.
+    fn fit_ssr(&mut self, size: (u32, u32)) {
+        if (self.ssr.as_ref()).is_some_and(|ssr| ssr.size() != size) {
+            unsafe { self.ssr.take().unwrap().delete() };
+        }
+        if self.ssr.is_none() {
+            match unsafe { ScreenSpaceReflections::new(size) } {
+                Ok(ssr) => self.ssr = Some(ssr),
+                Err(e) => {
+                    warn!("No screen-space reflections: {}", e);
+                    self.screen_space_reflections = false;
+                }
+            }
+        }
+    }
+
+    // Make the G-buffer again when the viewport's size changed. If it can't be made, the scene goes
+    // back to being drawn forward.
+    fn fit_gbuffer(&mut self, size: (u32, u32)) {
+        if self
+            .gbuffer
+            .as_ref()
+            .is_some_and(|gbuffer| gbuffer.size() != size)
+        {
+            unsafe { self.gbuffer.take().unwrap().delete() };
+        }
+        if self.gbuffer.is_none() {
+            match unsafe { GBuffer::new(size) } {
+                Ok(gbuffer) => self.gbuffer = Some(gbuffer),
+                Err(e) => {
+                    warn!("Drawing forward, as there is no G-buffer: {}", e);
+                    self.render_path = RenderPath::Forward;
+                }
+            }
+        }
+    }
+
+    // Merge the props into one mesh again after they changed
+    fn rebuild_static_batch(&mut self, ctx: &mut Context) {
+        if !self.static_batching {
+            return;
+        }
+        if let Some(old_batch) = self.static_batch.take() {
+            ctx.assets.release(&mut ctx.backend, old_batch);
+        }
+        let merged = {
+            let meshes = ctx.assets.meshes_by_vao();
+            let props = self
+                .parked_helicopters
+                .iter_mut()
+                .chain(self.dropped_models.iter_mut().map(|(_, model)| model))
+                .map(|prop| &mut ***prop);
+            batching::merge_static(props, &self.props_node.local_transform(), &meshes)
+        };
+        self.static_batch_node.vao_id = 0;
+        if let Some(merged) = merged {
+            let batch = ctx.assets.add_mesh(merged);
+            ctx.assets.upload(&mut ctx.backend);
+            let vao = ctx.assets.vao(batch);
+            self.static_batch_node.vao_id = vao.id;
+            self.static_batch_node.index_count = vao.index_count;
+            self.static_batch = Some(batch);
+        }
+    }
+}
+
+// The panels shown with F1
+#[cfg(feature = "egui")]
+impl Demo {
+    // Lay out the panels with this frame's input, and return whether they use the mouse and the
+    // keyboard
+    fn update_ui(&mut self, ctx: &mut Context, input: &FrameInput) -> (bool, bool) {
+        let mut ui = self
+            .ui
+            .take()
+            .expect("The panels are already being laid out");
+        // F1 types nothing, but a focused text field still gets to keep it
+        if input.keys.just_pressed(KeyCode::F1) && !(self.ui_visible && ui.wants_keyboard_input()) {
+            self.ui_visible = !self.ui_visible;
+        }
+        let used = if self.ui_visible {
+            let pixels_per_point = ctx
+                .window()
+                .map_or(1.0, |window| window.scale_factor() as f32);
+            ui.run(input, ctx.elapsed, pixels_per_point, |egui| {
+                self.panels(egui, ctx)
+            });
+            (ui.wants_pointer_input(), ui.wants_keyboard_input())
+        } else {
+            (false, false)
+        };
+        self.ui = Some(ui);
+        used
+    }
+
+    fn panels(&mut self, egui: &egui::Context, ctx: &mut Context) {
+        egui::Window::new("Tweaks")
+            .default_pos(egui::pos2(10.0, 10.0))
+            .show(egui, |ui| {
+                egui::CollapsingHeader::new("Shader")
+                    .default_open(true)
+                    .show(ui, |ui| self.tweaks.show(ui));
+
+                egui::CollapsingHeader::new("Camera")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        let field_of_view =
+                            egui::Slider::new(&mut self.field_of_view, 20.0..=120.0);
+                        ui.add(field_of_view.text("Field of view"));
+                        let speed = egui::Slider::new(&mut self.camera_base_speed, 1.0..=1000.0);
+                        ui.add(speed.logarithmic(true).text("Speed"));
+                        let config = &mut self.config;
+                        let sensitivity =
+                            egui::Slider::new(&mut config.look_sensitivity, 0.0005..=0.05);
+                        let sensitivity =
+                            ui.add(sensitivity.logarithmic(true).text("Look sensitivity"));
+                        let invert_y = ui.checkbox(&mut config.invert_y, "Invert Y");
+                        let raw_mouse_input =
+                            ui.checkbox(&mut config.raw_mouse_input, "Raw mouse input");
+                        // Saved like the keys save them, once the slider is let go
+                        let sensitivity_changed = sensitivity.changed() && !sensitivity.dragged()
+                            || sensitivity.drag_stopped();
+                        if sensitivity_changed || invert_y.changed() || raw_mouse_input.changed() {
+                            if let Err(e) = config.save(config::CONFIG_PATH) {
+                                warn!("{}", e);
+                            }
+                        }
+                    });
+
+                egui::CollapsingHeader::new("Rendering")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        if ui.checkbox(&mut self.wireframe, "Wireframe").changed() {
+                            unsafe { set_wireframe(self.wireframe) };
+                        }
+                        ui.checkbox(&mut self.shadows, "Shadows");
+                        ui.checkbox(&mut self.bloom, "Bloom");
+                        ui.checkbox(&mut self.image_based_lighting, "Image-based lighting");
+                        ui.checkbox(&mut self.reflections, "Reflections");
+                        ui.checkbox(
+                            &mut self.screen_space_reflections,
+                            "Screen-space reflections",
+                        );
+                        egui::ComboBox::from_label("Tone mapping")
+                            .selected_text(self.tone_mapping.name())
+                            .show_ui(ui, |ui| {
+                                for tone_mapping in ToneMapping::ALL {
+                                    let name = tone_mapping.name();
+                                    ui.selectable_value(&mut self.tone_mapping, tone_mapping, name);
+                                }
+                            });
+                        let exposure =
+                            egui::Slider::new(&mut self.exposure, MIN_EXPOSURE..=MAX_EXPOSURE);
+                        ui.add(exposure.logarithmic(true).text("Exposure"));
+                        ui.checkbox(&mut self.depth_of_field, "Depth of field");
+                        let focus = egui::Slider::new(
+                            &mut self.focus.focus_distance,
+                            MIN_FOCUS_DISTANCE..=MAX_FOCUS_DISTANCE,
+                        );
+                        ui.add_enabled(
+                            self.depth_of_field,
+                            focus.logarithmic(true).text("Focus distance"),
+                        );
+                        ui.checkbox(&mut self.motion_blur, "Motion blur");
+                        let shutter =
+                            egui::Slider::new(&mut self.motion_blur_settings.shutter, 0.0..=1.0);
+                        ui.add_enabled(self.motion_blur, shutter.text("Shutter"));
+                        ui.checkbox(&mut self.fxaa, "FXAA");
+                        ui.horizontal(|ui| {
+                            for path in [RenderPath::Forward, RenderPath::Deferred] {
+                                ui.radio_value(&mut self.render_path, path, path.name());
+                            }
+                        });
+                        ui.checkbox(&mut self.show_bounds, "Bounds");
+                        ui.checkbox(&mut self.overlay.visible, "Statistics");
+                        let mut vsync = ctx.vsync();
+                        if ui.checkbox(&mut vsync, "VSync").changed() {
+                            if let Err(e) = ctx.set_vsync(vsync) {
+                                warn!("Failed to change vsync: {}", e);
+                            }
+                        }
+                    });
+            });
+
+        egui::Window::new("Scene")
+            .default_pos(egui::pos2(300.0, 10.0))
+            .default_width(240.0)
+            .show(egui, |ui| {
+                let root = &**self.root_node as *const SceneNode as *mut SceneNode;
+                let mut clicked = None;
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        node_tree(ui, root, self.selected_node, &mut clicked)
+                    });
+                if clicked.is_some() {
+                    self.select(clicked);
+                }
+
+                if let Some(node) = self.selected_node {
+                    let node = unsafe { &mut *node };
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label("Name");
+                        ui.text_edit_singleline(&mut node.name);
+                    });
+                    vec3_row(ui, "Position", &mut node.position, 0.1);
+                    vec3_row(ui, "Rotation", &mut node.rotation, 0.01);
+                    vec3_row(ui, "Scale", &mut node.scale, 0.01);
+                }
+            });
+    }
+}
+
+// A row in the scene panel for `node`, with its children folded away beneath it. Sets `clicked`
+// to the node whose name was clicked.
+#[cfg(feature = "egui")]
+fn node_tree(
+    ui: &mut egui::Ui,
+    node: *mut SceneNode,
+    selected: Option<*mut SceneNode>,
+    clicked: &mut Option<*mut SceneNode>,
+) {
+    let scene_node = unsafe { &*node };
+    let name = if scene_node.name.is_empty() {
+        "(unnamed)"
+    } else {
+        &scene_node.name
+    };
+    let mut label = |ui: &mut egui::Ui| {
+        if ui.selectable_label(selected == Some(node), name).clicked() {
+            *clicked = Some(node);
+        }
+    };
+    if scene_node.children.is_empty() {
+        label(ui);
+        return;
+    }
+    let id = ui.make_persistent_id(node as usize);
+    egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, false)
+        .show_header(ui, label)
+        .body(|ui| {
+            for &child in &scene_node.children {
+                node_tree(ui, child, selected, clicked);
+            }
+        });
+}
+
+#[cfg(feature = "egui")]
+fn vec3_row(ui: &mut egui::Ui, label: &str, value: &mut glm::Vec3, speed: f64) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        for component in value.iter_mut() {
+            ui.add(egui::DragValue::new(component).speed(speed));
+        }
+    });
+}
+
+// The commands of the console opened with ~
+fn console_commands() -> CommandRegistry<Demo> {
+    let mut commands = CommandRegistry::new();
+    commands.register(
+        "spawn helicopter",
+        "[count]",
+        "Add helicopters following the others",
+        |demo: &mut Demo, ctx: &mut Context, args: &Arguments| {
+            let count: usize = args.get_or(0, 1)?;
+            for _ in 0..count {
+                let helicopter = create_helicopter(&ctx.assets, &demo.helicopter_meshes);
+                demo.root_node.add_child(&helicopter);
+                demo.helicopters.push(helicopter);
+            }
+            demo.fleet.send(FleetInput::Spawn(count));
+            Ok(format!("{} helicopters", demo.helicopters.len()))
+        },
+    );
+    commands.register(
+        "fly",
+        "<helicopter|all> <patrol|eight|orbit|land|waypoints>",
+        "Have a helicopter fly a pattern or round the waypoints, 0 being the one flown in pilot mode",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            let pattern: String = args.get(1)?;
+            if !["patrol", "eight", "orbit", "land", "waypoints"].contains(&pattern.as_str()) {
+                return Err(CommandError::Failed(format!("Unknown pattern {}", pattern)));
+            }
+            let indices = if args.get::<String>(0)? == "all" {
+                0..demo.helicopters.len()
+            } else {
+                let index: usize = args.get(0)?;
+                if index >= demo.helicopters.len() {
+                    return Err(CommandError::Failed(format!(
+                        "There are only {} helicopters",
+                        demo.helicopters.len()
+                    )));
+                }
+                index..index + 1
+            };
+            for index in indices {
+                let body = demo.helicopters[index][0].position;
+                let pattern = match pattern.as_str() {
+                    "patrol" => fleet::patrol(),
+                    "eight" => fleet::figure_eight(),
+                    "orbit" => fleet::orbit(),
+                    "land" => {
+                        let spot = ground_below(demo, &body).ok_or_else(|| {
+                            CommandError::Failed("There is no ground to land on".to_string())
+                        })?;
+                        fleet::landing(body, spot)
+                    }
+                    _ => {
+                        let waypoints = demo.waypoints.clone();
+                        demo.fleet.send(FleetInput::FlyWaypoints(index, waypoints));
+                        continue;
+                    }
+                };
+                demo.fleet.send(FleetInput::Fly(index, pattern));
+            }
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "toggle game",
+        "",
+        "Start or stop the landing game",
+        |demo: &mut Demo, ctx: &mut Context, _: &Arguments| Ok(demo.toggle_game(ctx)),
+    );
+    commands.register(
+        "set volume",
+        "<volume>",
+        "How loud the sounds are, 0 to mute and 1 for full volume",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            demo.audio.volume = args.get::<f32>(0)?.clamp(0.0, 1.0);
+            Ok(format!("Volume: {:.2}", demo.audio.volume))
+        },
+    );
+    commands.register(
+        "set fov",
+        "<degrees>",
+        "Vertical field of view",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            let field_of_view: f32 = args.get(0)?;
+            if !(1.0..=179.0).contains(&field_of_view) {
+                return Err(CommandError::Failed(
+                    "The field of view must be between 1 and 179 degrees".to_string(),
+                ));
+            }
+            demo.field_of_view = field_of_view;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "set speed",
+        "<units per second>",
+        "Speed of the free camera",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            demo.camera_base_speed = args.get::<f32>(0)?.clamp(1.0, 1000.0);
+            Ok(format!("Camera speed: {}", demo.camera_base_speed))
+        },
+    );
+    commands.register(
+        "set time_scale",
+        "<scale>",
+        "How fast the animation runs",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            demo.time_scale = args.get::<f32>(0)?.clamp(
+                toolbox::AnimationClock::MIN_TIME_SCALE,
+                toolbox::AnimationClock::MAX_TIME_SCALE,
+            );
+            demo.fleet.send(FleetInput::TimeScale(demo.time_scale));
+            demo.clock.set_time_scale(demo.time_scale);
+            Ok(format!("Time scale: {:.2}x", demo.time_scale))
+        },
+    );
+    commands.register(
+        "set light",
+        "<x> <y> <z>",
+        "Direction the sun shines in",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            let direction = glm::vec3(args.get(0)?, args.get(1)?, args.get(2)?);
+            if direction == glm::Vec3::zeros() {
+                return Err(CommandError::Failed(
+                    "The light needs a direction".to_string(),
+                ));
+            }
+            demo.sun_node.rotation = aim(&direction);
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "set material",
+        "<metallic> <roughness> | phong",
+        "What the selected node is made of, each from 0 to 1, or shade it with Phong instead",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            let Some(node) = demo.selected_node else {
+                return Err(CommandError::Failed("No node is selected".to_string()));
+            };
+            let node = unsafe { &mut *node };
+            if args.get::<String>(0)? == "phong" {
+                node.material = None;
+                return Ok(format!("{} is shaded with Phong", node.name));
+            }
+            let old = node.material.unwrap_or_default();
+            let material = Material::new(args.get(0)?, args.get(1)?)
+                .with_albedo(old.albedo)
+                .with_emissive(old.emissive);
+            node.material = Some(material);
+            Ok(format!(
+                "{}: metallic {:.2}, roughness {:.2}",
+                node.name, material.metallic, material.roughness
+            ))
+        },
+    );
+    commands.register(
+        "set path",
+        "<forward|deferred>",
+        "Light the scene as it is drawn, or draw it into a G-buffer first and light that",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            let name: String = args.get(0)?;
+            demo.render_path = name
+                .parse()
+                .map_err(|_| CommandError::Failed(format!("No render path is called {}", name)))?;
+            Ok(format!("Drawing {}", demo.render_path))
+        },
+    );
+    commands.register(
+        "set uniform",
+        "<name> <values...>",
+        "Tweak a uniform of the simple shaders tagged with @tweak",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            let name: String = args.get(0)?;
+            let values = (1..args.len())
+                .map(|i| args.get(i))
+                .collect::<Result<Vec<f32>, _>>()?;
+            set_tweak(demo, &name, &values)
+        },
+    );
+    commands.register(
+        "export uniforms",
+        "",
+        "Write the tweaked uniforms into the shaders as their initial values",
+        |demo: &mut Demo, _: &mut Context, _: &Arguments| {
+            let exported = demo
+                .tweaks
+                .export()
+                .map_err(|e| CommandError::Failed(e.to_string()))?;
+            Ok(format!("Exported {} uniforms", exported))
+        },
+    );
+    commands.register(
+        "reload shaders",
+        "",
+        "Compile the shaders again after editing them, starting the tweaks over",
+        |demo: &mut Demo, ctx: &mut Context, _: &Arguments| reload_shaders(demo, ctx),
+    );
+    commands.register(
+        "dump frame",
+        "",
+        "Write every draw of the next frame to a JSON file",
+        |demo: &mut Demo, _: &mut Context, _: &Arguments| {
+            demo.dump_frame = true;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "shake",
+        "[trauma]",
+        "Shake the camera, more the closer the trauma is to 1",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            demo.camera_shake.add_trauma(args.get_or(0, 0.5)?);
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "toggle wireframe",
+        "",
+        "Draw the edges of the triangles only",
+        |demo: &mut Demo, _: &mut Context, _: &Arguments| {
+            demo.wireframe = !demo.wireframe;
+            unsafe { set_wireframe(demo.wireframe) };
+            Ok(format!("Wireframe: {}", on_off(demo.wireframe)))
+        },
+    );
+    commands.register(
+        "toggle shadows",
+        "",
+        "Let the sun and the lamps cast shadows",
+        |demo: &mut Demo, _: &mut Context, _: &Arguments| {
+            demo.shadows = !demo.shadows;
+            Ok(format!("Shadows: {}", on_off(demo.shadows)))
+        },
+    );
+    commands.register(
+        "toggle ibl",
+        "",
+        "Light the helicopters with the sky, image-based",
+        |demo: &mut Demo, _: &mut Context, _: &Arguments| {
+            demo.image_based_lighting = !demo.image_based_lighting;
+            Ok(format!(
+                "Image-based lighting: {}",
+                on_off(demo.image_based_lighting)
+            ))
+        },
+    );
+    commands.register(
+        "toggle reflections",
+        "",
+        "Let the ice mirror the scene",
+        |demo: &mut Demo, _: &mut Context, _: &Arguments| {
+            demo.reflections = !demo.reflections;
+            Ok(format!("Reflections: {}", on_off(demo.reflections)))
+        },
+    );
+    commands.register(
+        "toggle ssr",
+        "",
+        "Let shiny surfaces reflect what is on the screen, when drawn deferred",
+        |demo: &mut Demo, _: &mut Context, _: &Arguments| {
+            demo.screen_space_reflections = !demo.screen_space_reflections;
+            Ok(format!(
+                "Screen-space reflections: {}",
+                on_off(demo.screen_space_reflections)
+            ))
+        },
+    );
+    commands.register(
+        "set ssr",
+        "<max distance> <thickness> [steps] [intensity]",
+        "How far screen-space reflections are traced, and how thick what they hit is taken to be",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            let settings = &mut demo.ssr_settings;
+            settings.max_distance = args.get::<f32>(0)?.max(0.1);
+            settings.thickness = args.get::<f32>(1)?.max(0.0);
+            if args.len() > 2 {
+                settings.steps = args.get::<u32>(2)?.clamp(1, MAX_SSR_STEPS);
+            }
+            if args.len() > 3 {
+                settings.intensity = args.get::<f32>(3)?.max(0.0);
+            }
+            Ok(format!(
+                "Reflections traced {:.1} far, {:.2} thick, in {} steps, intensity {:.2}",
+                settings.max_distance, settings.thickness, settings.steps, settings.intensity
+            ))
+        },
+    );
+    commands.register(
+        "toggle dof",
+        "",
+        "Blur what the camera isn't focused on, like a lens does",
+        |demo: &mut Demo, _: &mut Context, _: &Arguments| {
+            demo.depth_of_field = !demo.depth_of_field;
+            Ok(format!("Depth of field: {}", on_off(demo.depth_of_field)))
+        },
+    );
+    commands.register(
+        "set focus",
+        "<distance> [range] [max blur]",
+        "Focus the camera this far away, fully blurring what is the range nearer or further",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            let focus = &mut demo.focus;
+            focus.focus_distance = args
+                .get::<f32>(0)?
+                .clamp(MIN_FOCUS_DISTANCE, MAX_FOCUS_DISTANCE);
+            if args.len() > 1 {
+                focus.focus_range = args.get::<f32>(1)?.max(0.1);
+            }
+            if args.len() > 2 {
+                focus.max_blur = args.get::<f32>(2)?.clamp(0.0, MAX_FOCUS_BLUR);
+            }
+            demo.depth_of_field = true;
+            Ok(format!(
+                "Focused {:.1} away, fully blurred {:.1} nearer or further, {:.1} pixels across",
+                focus.focus_distance, focus.focus_range, focus.max_blur
+            ))
+        },
+    );
+    commands.register(
+        "toggle motion blur",
+        "",
+        "Smear what moves along the way it moved since the last frame",
+        |demo: &mut Demo, _: &mut Context, _: &Arguments| {
+            demo.motion_blur = !demo.motion_blur;
+            Ok(format!("Motion blur: {}", on_off(demo.motion_blur)))
+        },
+    );
+    commands.register(
+        "set motion blur",
+        "<shutter> [max blur]",
+        "Open the shutter for this share of the frame, smearing at most max blur pixels",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            let settings = &mut demo.motion_blur_settings;
+            settings.shutter = args.get::<f32>(0)?.clamp(0.0, 1.0);
+            if args.len() > 1 {
+                settings.max_blur = args.get::<f32>(1)?.clamp(0.0, MAX_MOTION_BLUR);
+            }
+            demo.motion_blur = true;
+            Ok(format!(
+                "Shutter open {:.0}% of the frame, smearing at most {:.0} pixels",
+                settings.shutter * 100.0,
+                settings.max_blur
+            ))
+        },
+    );
+    commands.register(
+        "toggle fxaa",
+        "",
+        "Smooth the jagged edges of what is drawn, after tone mapping",
+        |demo: &mut Demo, _: &mut Context, _: &Arguments| {
+            demo.fxaa = !demo.fxaa;
+            Ok(format!("FXAA: {}", on_off(demo.fxaa)))
+        },
+    );
+    commands.register(
+        "toggle bloom",
+        "",
+        "Make the brightest parts of the scene glow",
+        |demo: &mut Demo, _: &mut Context, _: &Arguments| {
+            demo.bloom = !demo.bloom;
+            Ok(format!("Bloom: {}", on_off(demo.bloom)))
+        },
+    );
+    commands.register(
+        "set bloom",
+        "<threshold> <intensity> [blur passes]",
+        "How bright colors must be to glow, 1 being white, and how strongly they glow",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            let settings = &mut demo.bloom_settings;
+            settings.threshold = args.get::<f32>(0)?.max(0.0);
+            settings.intensity = args.get::<f32>(1)?.max(0.0);
+            if args.len() > 2 {
+                settings.blur_passes = args.get::<u32>(2)?.min(MAX_BLUR_PASSES);
+            }
+            Ok(format!(
+                "Bloom above {:.2}, intensity {:.2}, {} blur passes",
+                settings.threshold, settings.intensity, settings.blur_passes
+            ))
+        },
+    );
+    commands.register(
+        "set tonemap",
+        "<clip|reinhard|aces> [exposure]",
+        "How colors brighter than white are shown, after being multiplied by the exposure",
+        |demo: &mut Demo, _: &mut Context, args: &Arguments| {
+            let name: String = args.get(0)?;
+            demo.tone_mapping = name
+                .parse()
+                .map_err(|_| CommandError::Failed(format!("No tone mapping is called {}", name)))?;
+            if args.len() > 1 {
+                demo.exposure = args.get::<f32>(1)?.clamp(MIN_EXPOSURE, MAX_EXPOSURE);
+            }
+            Ok(format!(
+                "Tone mapping: {}, exposure {:.2}",
+                demo.tone_mapping, demo.exposure
+            ))
+        },
+    );
+    commands.register(
+        "toggle bounds",
+        "",
+        "Show the bounds of every pickable node and the waypoints",
+        |demo: &mut Demo, _: &mut Context, _: &Arguments| {
+            demo.show_bounds = !demo.show_bounds;
+            Ok(format!("Bounds: {}", on_off(demo.show_bounds)))
+        },
+    );
+    commands.register(
+        "toggle overlay",
+        "",
+        "Show renderer statistics",
+        |demo: &mut Demo, _: &mut Context, _: &Arguments| {
+            demo.overlay.visible = !demo.overlay.visible;
+            Ok(format!("Overlay: {}", on_off(demo.overlay.visible)))
+        },
+    );
+    commands.register(
+        "toggle vsync",
+        "",
+        "Wait for the display's refresh",
+        |_: &mut Demo, ctx: &mut Context, _: &Arguments| {
+            ctx.set_vsync(!ctx.vsync())
+                .map_err(|e| CommandError::Failed(e.to_string()))?;
+            Ok(format!("VSync: {}", on_off(ctx.vsync())))
+        },
+    );
+    commands
+}
+
+// Compile every pipeline's shaders again, reporting how it went on the overlay. Pipelines that
+// fail to compile keep their previous program.
+fn reload_shaders(demo: &mut Demo, ctx: &mut Context) -> Result<String, CommandError> {
+    match ctx.assets.reload_pipelines(&mut ctx.backend) {
+        Ok(reloaded) => {
+            demo.tweaks = load_tweaks(ctx, demo.simple_pipeline);
+            let message = format!("Reloaded {} pipelines", reloaded);
+            info!("{}", message);
+            demo.overlay.status(&message, false);
+            Ok(message)
+        }
+        Err(e) => {
+            warn!(
+                "Failed to reload the shaders, keeping the previous ones: {}",
+                e
+            );
+            demo.overlay.status(&e.to_string(), true);
+            Err(CommandError::Failed(e.to_string()))
+        }
+    }
+}
+
+// The tweakable uniforms of `pipeline`, loaded from the simple shaders
+fn load_tweaks(ctx: &Context, pipeline: Handle<Pipeline>) -> Tweaks {
+    match ctx.assets.gpu(pipeline) {
+        Some(pipeline) => unsafe { Tweaks::load(&ctx.backend, pipeline, "simple") },
+        None => Tweaks::default(),
+    }
+}
+
+fn set_tweak(demo: &mut Demo, name: &str, values: &[f32]) -> Result<String, CommandError> {
+    let tweak = demo.tweaks.get_mut(name).ok_or_else(|| {
+        CommandError::Failed(format!("`{}` isn't a uniform tagged with @tweak", name))
+    })?;
+    if values.len() != tweak.values.len() {
+        return Err(CommandError::Failed(format!(
+            "`{}` takes {} values",
+            name,
+            tweak.values.len()
+        )));
+    }
+    tweak.values.copy_from_slice(values);
+    Ok(String::new())
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+// Stream tiles of the terrain, each with hills of its own and a crashed helicopter on it, around the
+// one the demo starts on. `bounds` are those of the terrain.
+fn create_streamer(args: &cli::Args, bounds: &toolbox::Aabb) -> Streamer {
+    let size = bounds.max - bounds.min;
+    let tile_size = size.x.max(size.z);
+    let mut streamer = Streamer::new(tile_size * 1.2, tile_size * 1.5, 1);
+
+    let radius = args.world_radius as i32;
+    let scene_path = args.scene_path();
+    let helicopter_path = args.resource_path("helicopter.obj");
+    let noise = Noise::new(TERRAIN_SEED);
+    for x in -radius..=radius {
+        for z in -radius..=radius {
+            if x == 0 && z == 0 {
+                continue; // Always loaded
+            }
+            let offset = glm::vec3(x as f32 * size.x, 0.0, z as f32 * size.z);
+            let scene_path = scene_path.clone();
+            let helicopter_path = helicopter_path.clone();
+            let (noise, bounds) = (noise.clone(), *bounds);
+            let loader: RegionLoader = Arc::new(move || {
+                let mut terrain = mesh::Terrain::load(&scene_path)?;
+                roughen(&mut terrain, &offset, &bounds, &noise);
+                add_detail_uvs(&mut terrain, &offset);
+                let wreck = mesh::Helicopter::load(&helicopter_path)?.body;
+                let wreck = place_wreck(&terrain, &wreck, (x * 31 + z * 17) as f32);
+                // Moved into place here, so the nodes don't need to know where the tile is
+                let tile = glm::translation(&offset);
+                Ok(vec![
+                    batching::transformed(&terrain, &tile),
+                    batching::transformed(&wreck, &tile),
+                ])
+            });
+            streamer.add_region(bounds.center() + offset, loader);
+        }
+    }
+    streamer
+}
+
+// Roll hills of noise into a copy of `terrain` moved by `offset`. Hills are placed by where the
+// vertices end up in the world, so they carry on across tiles, and flatten out towards the tile the
+// demo starts on within `start`, which has none.
+fn roughen(terrain: &mut Mesh, offset: &glm::Vec3, start: &toolbox::Aabb, noise: &Noise) {
+    let size = start.max - start.min;
+    let fade_distance = size.x.max(size.z) / 2.0;
+    let fbm = Fbm::default();
+    for vertex in terrain.vertices.chunks_exact_mut(3) {
+        let (x, z) = (vertex[0] + offset.x, vertex[2] + offset.z);
+        let outside = ((x - start.center().x).abs() - size.x / 2.0)
+            .max((z - start.center().z).abs() - size.z / 2.0)
+            .max(0.0);
+        let weight = toolbox::ease_in_out(outside / fade_distance);
+        vertex[1] += weight * RELIEF_HEIGHT * noise.fbm2(x / RELIEF_SCALE, z / RELIEF_SCALE, &fbm);
+    }
+    terrain.normals = mesh::compute_normals(&terrain.vertices, &terrain.indices);
+}
+
+// Lay the detail normal map over `terrain` from above, by where its vertices end up in the world
+// once it is moved by `offset`, so the craters carry on across tiles. Needs the terrain's normals.
+fn add_detail_uvs(terrain: &mut Mesh, offset: &glm::Vec3) {
+    terrain.uvs = terrain
+        .vertices
+        .chunks_exact(3)
+        .flat_map(|vertex| {
+            let (x, z) = (vertex[0] + offset.x, vertex[2] + offset.z);
+            [x / DETAIL_TILE, z / DETAIL_TILE]
+        })
+        .collect();
+    terrain.tangents = mesh::compute_tangents(
+        &terrain.vertices,
+        &terrain.normals,
+        &terrain.uvs,
+        &terrain.indices,
+    );
+}
+
+// A normal map of small craters in a bumpy plain, `size` texels across, which repeats without seams:
+// craters near an edge carry on at the other, and the bumps are blended with themselves a repeat
+// over, so they match up too
+fn crater_normal_map(size: u32, seed: u64) -> image::RgbaImage {
+    // Shuffled with splitmix64, like `Noise`
+    let mut state = seed;
+    let mut random = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (z ^ (z >> 31)) as f32 / u64::MAX as f32
+    };
+    let craters: Vec<(glm::Vec2, f32)> = (0..DETAIL_CRATERS)
+        .map(|_| {
+            let center = glm::vec2(random(), random());
+            (center, 0.02 + 0.1 * random() * random())
+        })
+        .collect();
+
+    // Heights in repeats of the map, so their slopes are as steep as they look
+    let noise = Noise::new(seed);
+    let bumps = |x: f32, y: f32| 0.004 * noise.noise2(x * 24.0, y * 24.0);
+    let height = |x: f32, y: f32| {
+        let mut height = bumps(x, y) * (1.0 - x) * (1.0 - y)
+            + bumps(x - 1.0, y) * x * (1.0 - y)
+            + bumps(x, y - 1.0) * (1.0 - x) * y
+            + bumps(x - 1.0, y - 1.0) * x * y;
+        for (center, radius) in &craters {
+            // The nearest of the crater's repeats
+            let offset = glm::vec2(x, y) - center;
+            let offset = offset.map(|d| d - d.round());
+            let d = glm::length(&offset) / radius;
+            // A bowl, with a rim thrown up around it
+            let bowl = if d < 1.0 { 0.3 * (d * d - 1.0) } else { 0.0 };
+            let rim = 0.12 * (-((d - 1.0) * 4.0).powi(2)).exp();
+            height += radius * (bowl + rim);
+        }
+        height
+    };
+    let heights: Vec<f32> = (0..size * size)
+        .map(|i| {
+            height(
+                (i % size) as f32 / size as f32,
+                (i / size) as f32 / size as f32,
+            )
+        })
+        .collect();
+
+    let step = 1.0 / size as f32;
+    let at = |x: u32, y: u32| heights[((y % size) * size + x % size) as usize];
+    image::RgbaImage::from_fn(size, size, |x, y| {
+        let slope_x = (at(x + 1, y) - at(x + size - 1, y)) / (2.0 * step);
+        let slope_y = (at(x, y + 1) - at(x, y + size - 1)) / (2.0 * step);
+        let normal = glm::normalize(&glm::vec3(-slope_x, -slope_y, 1.0));
+        let channel = |n: f32| ((n * 0.5 + 0.5) * 255.0).round() as u8;
+        image::Rgba([channel(normal.x), channel(normal.y), channel(normal.z), 255])
+    })
+}
+
+// A sky of `stars` stars scattered evenly all around, with a faint, patchy band of the Milky Way
+// across it, in faces `size` texels across. A few stars are bright enough to bloom.
+fn starfield(size: u32, stars: u32, seed: u64) -> skybox::Faces {
+    // Shuffled with splitmix64, like `Noise`
+    let mut state = seed;
+    let mut random = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (z ^ (z >> 31)) as f32 / u64::MAX as f32
+    };
+
+    let noise = Noise::new(seed);
+    let band_axis = glm::normalize(&glm::vec3(0.3, 1.0, 0.5));
+    let texel = |i: u32| 2.0 * (i as f32 + 0.5) / size as f32 - 1.0;
+    let mut faces: skybox::Faces = std::array::from_fn(|face| {
+        image::Rgb32FImage::from_fn(size, size, |x, y| {
+            let direction = glm::normalize(&skybox::face_direction(face, texel(x), texel(y)));
+            let across = glm::dot(&direction, &band_axis);
+            let patches =
+                0.6 + 0.4 * noise.noise3(direction.x * 6.0, direction.y * 6.0, direction.z * 6.0);
+            let glow = 0.02 * (-across * across / 0.015).exp() * patches;
+            image::Rgb([0.8 * glow, 0.85 * glow, glow])
+        })
+    });
+
+    for _ in 0..stars {
+        // Evenly over the sphere, as the heights along any axis are
+        let height = 2.0 * random() - 1.0;
+        let angle = std::f32::consts::TAU * random();
+        let radius = (1.0 - height * height).sqrt();
+        let direction = glm::vec3(radius * angle.cos(), height, radius * angle.sin());
+        let (face, u, v) = skybox::face_at(&direction);
+        let [x, y] = [u, v].map(|t| (((t + 1.0) / 2.0 * size as f32) as u32).min(size - 1));
+        // Mostly faint, and from reddish to bluish
+        let brightness = 0.05 + 2.5 * random().powi(16);
+        let color = glm::lerp(
+            &glm::vec3(1.0, 0.8, 0.6),
+            &glm::vec3(0.7, 0.8, 1.0),
+            random(),
+        );
+        let pixel = faces[face].get_pixel_mut(x, y);
+        for (channel, value) in pixel.0.iter_mut().zip(color.iter()) {
+            *channel += brightness * value;
+        }
+    }
+    faces
+}
+
+// Where the terrain is straight below `point`, if anywhere
+fn ground_below(demo: &Demo, point: &glm::Vec3) -> Option<glm::Vec3> {
+    let down = toolbox::Ray {
+        origin: *point + glm::vec3(0.0, 1000.0, 0.0),
+        direction: glm::vec3(0.0, -1.0, 0.0),
+    };
+    let terrain_transform = demo.terrain_node.local_transform();
+    let terrain_ray = down.transformed(&glm::inverse(&terrain_transform));
+    let t = demo.terrain_collider.raycast(&terrain_ray)?;
+    Some(down.at(t))
+}
+
+// Where a camera at `eye` looking at `target` can be without the terrain coming in between
+fn clip_to_terrain(
+    terrain: &MeshCollider,
+    terrain_transform: &glm::Mat4,
+    target: &glm::Vec3,
+    eye: &glm::Vec3,
+) -> glm::Vec3 {
+    let ray = toolbox::Ray {
+        origin: *target,
+        direction: eye - target,
+    };
+    let terrain_ray = ray.transformed(&glm::inverse(terrain_transform));
+    let distance = glm::length(&ray.direction).max(1e-6);
+    match terrain.raycast(&terrain_ray) {
+        Some(t) if t < 1.0 => ray.at((t - CAMERA_RADIUS / distance).max(0.0)),
+        _ => push_out_of_terrain(terrain, terrain_transform, eye),
+    }
+}
+
+// Move a camera at `position` out of the terrain, if it went in
+// The keys without S on the frame Ctrl+S is pressed, so the chord doesn't also move the cameras
+// and the piloted helicopter back. Held on, S moves them back again, as fast as Ctrl sprints.
+fn save_chord(keys: &input::KeyState) -> Option<input::KeyState> {
+    if keys.chord(&[input::Modifier::Ctrl], KeyCode::KeyS) {
+        Some(keys.without(KeyCode::KeyS))
+    } else {
+        None
+    }
+}
+
+// Which way the held keys move the free camera, whose forward and right are given, as a step of
+// its speed along each key's direction
+fn free_camera_movement(keys: &input::KeyState, forward: glm::Vec3, right: glm::Vec3) -> glm::Vec3 {
+    let mut movement = glm::Vec3::zeros();
+    for key in keys.held() {
+        match key {
+            KeyCode::KeyW => movement += forward,
+            KeyCode::KeyS => movement -= forward,
+            KeyCode::KeyA => movement -= right,
+            KeyCode::KeyD => movement += right,
+            KeyCode::Space => movement.y += 1.0,
+            KeyCode::ShiftLeft => movement.y -= 1.0,
+            _ => {}
+        }
+    }
+    movement
+}
+
+fn push_out_of_terrain(
+    terrain: &MeshCollider,
+    terrain_transform: &glm::Mat4,
+    position: &glm::Vec3,
+) -> glm::Vec3 {
+    let camera = collision::Sphere {
+        center: (glm::inverse(terrain_transform) * position.push(1.0)).xyz(),
+        radius: CAMERA_RADIUS,
+    };
+    match terrain.sphere_contact(&camera) {
+        Some(contact) => {
+            let pushed = camera.center + contact.normal * contact.depth;
+            (terrain_transform * pushed.push(1.0)).xyz()
+        }
+        None => *position,
+    }
+}
+
+// Waypoints in a ring around the patrol, above the terrain where it is known
+fn place_waypoints(ground: Option<&toolbox::HeightField>) -> Vec<glm::Vec3> {
+    (0..WAYPOINT_COUNT)
+        .map(|i| {
+            let angle = i as f32 / WAYPOINT_COUNT as f32 * std::f32::consts::TAU;
+            let radius = WAYPOINT_RADII[i % WAYPOINT_RADII.len()];
+            let (x, z) = (angle.sin() * radius, angle.cos() * radius);
+            let height = ground.and_then(|ground| ground.height_at(x, z));
+            glm::vec3(x, height.unwrap_or(0.0) + WAYPOINT_ALTITUDE, z)
+        })
+        .collect()
+}
+
+// A copy of `wreck` lying tilted on `terrain`, somewhere depending on `seed`
+fn place_wreck(terrain: &Mesh, wreck: &Mesh, seed: f32) -> Mesh {
+    let bounds = terrain.bounds().unwrap_or(toolbox::Aabb {
+        min: glm::zero(),
+        max: glm::zero(),
+    });
+    let spot = glm::vec3(
+        glm::lerp_scalar(bounds.min.x, bounds.max.x, 0.5 + 0.3 * seed.sin()),
+        bounds.max.y + 1.0,
+        glm::lerp_scalar(bounds.min.z, bounds.max.z, 0.5 + 0.3 * seed.cos()),
+    );
+    let down = toolbox::Ray {
+        origin: spot,
+        direction: glm::vec3(0.0, -1.0, 0.0),
+    };
+    let ground = toolbox::intersect_mesh(&down, &terrain.vertices, &terrain.indices)
+        .map_or(bounds.min, |t| down.at(t));
+    let transform = glm::translation(&ground)
+        * glm::rotation(seed, &glm::vec3(0.0, 1.0, 0.0))
+        * glm::rotation(0.6, &glm::vec3(0.0, 0.0, 1.0));
+    batching::transformed(wreck, &transform)
+}
+
+// A node drawing `mesh`, with bounds for picking it with the mouse
+// The rotation of a node facing down -Z, like a camera or a directional light, to face `direction`
+fn aim(direction: &glm::Vec3) -> glm::Vec3 {
+    toolbox::euler_angles(&toolbox::look_rotation(
+        direction,
+        &glm::vec3(0.0, 1.0, 0.0),
+    ))
+}
+
+fn mesh_node(assets: &Assets, mesh: Handle<Mesh>) -> Node {
+    let mut node = SceneNode::from_vao(assets.vao(mesh));
+    node.bounds = assets.get(mesh).and_then(|mesh| mesh.bounds());
+    node
+}
+
+// Build the node hierarchy for one helicopter: root -> body -> (door, main rotor, tail rotor, lamp),
+// with the disc each rotor sweeps under it, hidden until `HelicopterState::apply_to` shows it, and
+// the lamp's bulb under the lamp
+fn create_helicopter(assets: &Assets, meshes: &HelicopterMeshes) -> Node {
+    let mut helicopter_root_node = SceneNode::new();
+
+    let mut helicopter_body_node = mesh_node(assets, meshes.body);
+    helicopter_body_node.reference_point = glm::vec3(0.0, 0.0, 0.0);
+
+    let mut helicopter_door_node = mesh_node(assets, meshes.door);
+    let mut helicopter_main_rotor_node = mesh_node(assets, meshes.main_rotor);
+    helicopter_main_rotor_node.reference_point = glm::vec3(0.0, 0.0, 0.0);
+    // Silent until the rotor turns
+    helicopter_main_rotor_node.sound = Some(audio::Sound {
+        clip: meshes.rotor_sound.clone(),
+        volume: 0.0,
+        speed: 1.0,
+    });
+
+    let mut helicopter_tail_rotor_node = mesh_node(assets, meshes.tail_rotor);
+    helicopter_tail_rotor_node.reference_point = TAIL_ROTOR_HUB.into();
+
+    // Not pickable, so clicking a spinning rotor picks the blades
+    let mut helicopter_main_rotor_disc_node =
+        SceneNode::from_vao(assets.vao(meshes.main_rotor_disc));
+    let mut helicopter_tail_rotor_disc_node =
+        SceneNode::from_vao(assets.vao(meshes.tail_rotor_disc));
+    helicopter_main_rotor_disc_node.opacity = 0.0;
+    helicopter_tail_rotor_disc_node.opacity = 0.0;
+
+    let mut helicopter_bulb_node = mesh_node(assets, meshes.bulb);
+    helicopter_bulb_node.position = glm::vec3(0.0, BULB_RADIUS * 1.5, 0.0);
+    let glow = glm::Vec3::from(LAMP_COLOR) * BULB_GLOW;
+    helicopter_bulb_node.material = Some(Material::default().with_emissive(glow));
+
+    let mut helicopter_lamp_node = SceneNode::new();
+    helicopter_lamp_node.position = LAMP_POSITION.into();
+    helicopter_lamp_node.light = Some(Light::Point {
+        color: LAMP_COLOR.into(),
+        intensity: LAMP_INTENSITY,
+        range: LAMP_RANGE,
+        casts_shadows: true,
+    });
+
+    helicopter_root_node.name = "helicopter".to_string();
+    helicopter_body_node.name = "body".to_string();
+    helicopter_door_node.name = "door".to_string();
+    helicopter_main_rotor_node.name = "main rotor".to_string();
+    helicopter_tail_rotor_node.name = "tail rotor".to_string();
+    helicopter_main_rotor_disc_node.name = "main rotor disc".to_string();
+    helicopter_tail_rotor_disc_node.name = "tail rotor disc".to_string();
+    helicopter_lamp_node.name = "lamp".to_string();
+    helicopter_bulb_node.name = "bulb".to_string();
+
+    helicopter_body_node.material = Some(PAINT_MATERIAL);
+    helicopter_door_node.material = Some(PAINT_MATERIAL);
+    helicopter_main_rotor_node.material = Some(ROTOR_MATERIAL);
+    helicopter_tail_rotor_node.material = Some(ROTOR_MATERIAL);
+    helicopter_main_rotor_disc_node.material = Some(ROTOR_MATERIAL);
+    helicopter_tail_rotor_disc_node.material = Some(ROTOR_MATERIAL);
+
+    helicopter_main_rotor_node.add_child(&helicopter_main_rotor_disc_node);
+    helicopter_tail_rotor_node.add_child(&helicopter_tail_rotor_disc_node);
+    helicopter_lamp_node.add_child(&helicopter_bulb_node);
+
+    helicopter_body_node.add_child(&helicopter_door_node);
+    helicopter_body_node.add_child(&helicopter_main_rotor_node);
+    helicopter_body_node.add_child(&helicopter_tail_rotor_node);
+    helicopter_body_node.add_child(&helicopter_lamp_node);
+
+    helicopter_root_node.add_child(&helicopter_body_node);
+
+    helicopter_root_node
+}
+
+// The default audio device, or silence when running headless or when there is no device to play on
+fn open_audio_output(ctx: &Context) -> Box<dyn audio::AudioOutput> {
+    if ctx.window().is_none() {
+        return Box::new(audio::SilentOutput::default());
+    }
+    match audio::DeviceOutput::open() {
+        Ok(output) => Box::new(output),
+        Err(e) => {
+            warn!("No audio output, playing silently: {}", e);
+            Box::new(audio::SilentOutput::default())
+        }
+    }
+}
+
+// The number of texels across a shadow map of `full_size` at `detail`, a power of two
+fn shadow_map_size(full_size: u32, detail: f32) -> u32 {
+    let size = (full_size as f32 * detail.clamp(0.0, 1.0)) as u32;
+    size.next_power_of_two()
+        .clamp(MIN_SHADOW_MAP_SIZE, full_size)
+}
+
+// The sun's shadow map for the quality's `detail`, or None if it can't be created, which leaves the
+// scene without shadows
+fn create_shadow_map(detail: f32) -> Option<ShadowMap> {
+    unsafe { ShadowMap::new(shadow_map_size(SHADOW_MAP_SIZE, detail)) }
+        .inspect_err(|e| warn!("No shadows: {}", e))
+        .ok()
+}
+
+// The cube maps of the lamps' shadows for the quality's `detail`, or None if they can't be created,
+// which leaves the lamps without shadows
+fn create_point_shadow_maps(detail: f32) -> Option<PointShadowMaps> {
+    unsafe { PointShadowMaps::new(shadow_map_size(POINT_SHADOW_MAP_SIZE, detail)) }
+        .inspect_err(|e| warn!("No shadows for point lights: {}", e))
+        .ok()
+}
+
+// The sky from the faces at `path`, or the starfield without one or if they can't be loaded. None
+// if the cube map can't be created, which leaves the scene in front of `SKY_COLOR`.
+fn create_skybox(path: Option<&Path>) -> Option<Skybox> {
+    let faces = path
+        .and_then(|path| {
+            skybox::load(path, STARFIELD_SIZE)
+                .inspect_err(|e| warn!("Drawing the starfield instead: {}", e))
+                .ok()
+        })
+        .unwrap_or_else(|| starfield(STARFIELD_SIZE, STARFIELD_STARS, STARFIELD_SEED));
+    unsafe { Skybox::new(&faces) }
+        .inspect_err(|e| warn!("No skybox: {}", e))
+        .ok()
+}
+
+// The sky's light filtered out of `skybox`, or None without one or if it can't be filtered, which
+// leaves the scene lit by the ambient light alone
+fn create_environment(
+    ctx: &mut Context,
+    pipeline: Handle<Pipeline>,
+    skybox: Option<&Skybox>,
+) -> Option<EnvironmentMaps> {
+    let pipeline = ctx.assets.gpu(pipeline)?;
+    unsafe { EnvironmentMaps::new(&mut ctx.backend, pipeline, skybox?) }
+        .inspect_err(|e| warn!("No image-based lighting: {}", e))
+        .ok()
+}
+
+// Whether `draw` is left out of the G-buffer and drawn after the sky: see-through and reflective
+// meshes
+fn drawn_last(draw: &DrawCommand) -> bool {
+    draw.opacity < 1.0 || reflects(draw)
+}
+
+fn reflects(draw: &DrawCommand) -> bool {
+    draw.material.is_some_and(|material| material.reflects())
+}
+
+unsafe fn set_wireframe(wireframe: bool) {
+    gl::PolygonMode(
+        gl::FRONT_AND_BACK,
+        if wireframe { gl::LINE } else { gl::FILL },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holding_ctrl_s_saves_once_and_then_sprints_backwards() {
+        let (forward, right) = (glm::vec3(0.0, 0.0, -1.0), glm::vec3(1.0, 0.0, 0.0));
+        let mut keys = input::KeyState::new();
+        keys.press(KeyCode::ControlLeft);
+        keys.press(KeyCode::KeyS);
+        let frame = keys.take_frame();
+        let chord_keys = save_chord(&frame).unwrap();
+        assert_eq!(
+            free_camera_movement(&chord_keys, forward, right),
+            glm::Vec3::zeros()
+        );
+
+        for _ in 0..2 {
+            let frame = keys.take_frame();
+            assert!(save_chord(&frame).is_none());
+            assert!(frame.modifier_held(input::Modifier::Ctrl));
+            assert_eq!(free_camera_movement(&frame, forward, right), -forward);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+// Image-based lighting: the sky lighting the scene, not only the lights in it.
+//
+// Light reaches a surface from everywhere around it, the whole sky included. How much of it is
+// scattered back towards the camera is worked out ahead of time from the skybox's cube map, by the
+// environment pipeline, into two more cube maps. The irradiance map has, for every way a surface
+// can face, the sky's light summed over the half of it the surface faces, weighted by the angle it
+// comes in at, which is the diffuse light of a surface facing that way. The specular map has the
+// sky as reflected by surfaces of increasing roughness in its mip levels, each level blurred more:
+// the light coming in around the reflected direction, picked where the GGX distribution of
+// microfacets says rough surfaces reflect it from (importance sampling).
+//
+// The simple fragment shader looks both up for physically shaded surfaces, the irradiance in the
+// way the surface faces and the specular map in the way the camera's view is reflected, at the
+// level for the surface's roughness. How much of that reflection reaches the camera is approximated
+// analytically, after Karis, rather than looked up in another texture. The ambient light is kept
+// alongside, standing in for the light bounced off the ground, which the sky doesn't have.
+use crate::backend::gl::GlBackend;
+use crate::backend::{Backend, PipelineHandle};
+use crate::error::{GlError, RenderError};
+use crate::gl_check;
+use crate::offscreen;
+use crate::skybox::{Skybox, SKYBOX_TEXTURE_UNIT};
+
+// Where the maps are bound while drawing, clear of the skybox's unit
+pub const IRRADIANCE_TEXTURE_UNIT: u32 = 12;
+pub const SPECULAR_TEXTURE_UNIT: u32 = 13;
+
+// Texels across the faces of the maps. Irradiance changes slowly with the way a surface faces, so
+// its map can be small. The specular map halves with every level, from mirror-like to fully rough.
+const IRRADIANCE_SIZE: i32 = 32;
+const SPECULAR_SIZE: i32 = 128;
+const SPECULAR_LEVELS: i32 = 5;
+
+// What the environment pipeline draws, as its `environmentFilter` uniform
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Filter {
+    Irradiance = 0,
+    Specular = 1,
+}
+
+pub struct EnvironmentMaps {
+    irradiance: u32,
+    specular: u32,
+}
+
+impl EnvironmentMaps {
+    // Filter the sky of `skybox` into the maps with `pipeline`, the environment one
+    pub unsafe fn new(
+        backend: &mut GlBackend,
+        pipeline: PipelineHandle,
+        skybox: &Skybox,
+    ) -> Result<EnvironmentMaps, RenderError> {
+        let mut textures = [0; 2];
+        gl::GenTextures(2, textures.as_mut_ptr());
+        let maps = EnvironmentMaps {
+            irradiance: textures[0],
+            specular: textures[1],
+        };
+        let result = maps.filter(backend, pipeline, skybox);
+        match result {
+            Ok(()) => Ok(maps),
+            Err(e) => {
+                maps.delete();
+                Err(e)
+            }
+        }
+    }
+
+    unsafe fn filter(
+        &self,
+        backend: &mut GlBackend,
+        pipeline: PipelineHandle,
+        skybox: &Skybox,
+    ) -> Result<(), RenderError> {
+        let maps = [
+            (self.irradiance, Filter::Irradiance, IRRADIANCE_SIZE, 1),
+            (
+                self.specular,
+                Filter::Specular,
+                SPECULAR_SIZE,
+                SPECULAR_LEVELS,
+            ),
+        ];
+        for (texture, _, size, levels) in maps {
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, texture);
+            gl_check!(gl::TexStorage2D(
+                gl::TEXTURE_CUBE_MAP,
+                levels,
+                gl::RGB16F,
+                size,
+                size
+            ))?;
+            let min_filter = if levels > 1 {
+                gl::LINEAR_MIPMAP_LINEAR
+            } else {
+                gl::LINEAR
+            };
+            gl::TexParameteri(
+                gl::TEXTURE_CUBE_MAP,
+                gl::TEXTURE_MIN_FILTER,
+                min_filter as i32,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_CUBE_MAP,
+                gl::TEXTURE_MAG_FILTER,
+                gl::LINEAR as i32,
+            );
+            for wrap in [gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+                gl::TexParameteri(gl::TEXTURE_CUBE_MAP, wrap, gl::CLAMP_TO_EDGE as i32);
+            }
+        }
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+
+        let previous_framebuffer = offscreen::bound_framebuffer();
+        let mut previous_viewport = [0; 4];
+        gl::GetIntegerv(gl::VIEWPORT, previous_viewport.as_mut_ptr());
+        let mut fbo = 0;
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        let mut vao = 0; // Empty, as the vertex shader makes the triangle up itself
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::BLEND);
+        let mut polygon_mode = [gl::FILL as i32; 2];
+        gl::GetIntegerv(gl::POLYGON_MODE, polygon_mode.as_mut_ptr());
+        gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+
+        backend.set_pipeline(pipeline);
+        let program = backend.program_id(pipeline);
+        let uniforms = backend.uniforms(pipeline);
+        let location = |name: &str| uniforms.get(name).map_or(-1, |uniform| uniform.location);
+        gl::ProgramUniform1i(program, location("environment"), SKYBOX_TEXTURE_UNIT as i32);
+        gl::ActiveTexture(gl::TEXTURE0 + SKYBOX_TEXTURE_UNIT);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, skybox.texture());
+        gl::ActiveTexture(gl::TEXTURE0);
+
+        let mut result: Result<(), RenderError> = Ok(());
+        'maps: for (texture, filter, size, levels) in maps {
+            gl::ProgramUniform1i(program, location("environmentFilter"), filter as i32);
+            for level in 0..levels {
+                let roughness = level as f32 / (levels - 1).max(1) as f32;
+                gl::ProgramUniform1f(program, location("roughness"), roughness);
+                gl::Viewport(0, 0, size >> level, size >> level);
+                for face in 0..6 {
+                    gl::FramebufferTexture2D(
+                        gl::FRAMEBUFFER,
+                        gl::COLOR_ATTACHMENT0,
+                        gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                        texture,
+                        level,
+                    );
+                    let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+                    if status != gl::FRAMEBUFFER_COMPLETE {
+                        result = Err(RenderError::IncompleteFramebuffer(status));
+                        break 'maps;
+                    }
+                    gl::ProgramUniform1i(program, location("face"), face as i32);
+                    let draw: Result<(), GlError> = gl_check!(gl::DrawArrays(gl::TRIANGLES, 0, 3));
+                    if let Err(e) = draw {
+                        result = Err(e.into());
+                        break 'maps;
+                    }
+                }
+            }
+        }
+
+        gl::PolygonMode(gl::FRONT_AND_BACK, polygon_mode[0] as u32);
+        gl::Enable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::BindVertexArray(0);
+        gl::DeleteVertexArrays(1, &vao);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, previous_framebuffer);
+        gl::DeleteFramebuffers(1, &fbo);
+        let [x, y, width, height] = previous_viewport;
+        gl::Viewport(x, y, width, height);
+        result
+    }
+
+    // Have `pipeline`, the simple one, light physically shaded surfaces with the maps, or not
+    pub unsafe fn apply(&self, backend: &GlBackend, pipeline: PipelineHandle, enabled: bool) {
+        let program = backend.program_id(pipeline);
+        let uniforms = backend.uniforms(pipeline);
+        let location = |name: &str| uniforms.get(name).map_or(-1, |uniform| uniform.location);
+        gl::ProgramUniform1i(program, location("imageBasedLighting"), enabled as i32);
+        gl::ProgramUniform1i(
+            program,
+            location("irradianceMap"),
+            IRRADIANCE_TEXTURE_UNIT as i32,
+        );
+        gl::ProgramUniform1i(
+            program,
+            location("specularMap"),
+            SPECULAR_TEXTURE_UNIT as i32,
+        );
+        gl::ProgramUniform1f(program, location("specularLevels"), SPECULAR_LEVELS as f32);
+        gl::ActiveTexture(gl::TEXTURE0 + IRRADIANCE_TEXTURE_UNIT);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.irradiance);
+        gl::ActiveTexture(gl::TEXTURE0 + SPECULAR_TEXTURE_UNIT);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.specular);
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+
+    pub unsafe fn delete(self) {
+        gl::DeleteTextures(2, [self.irradiance, self.specular].as_ptr());
+    }
+}
@@ -0,0 +1,150 @@
+// Errors that can happen while loading assets and setting up rendering.
+//
+// Functions that read files or create OpenGL objects return these instead of panicking, so a
+// missing model or a typo in a shader ends the program with a readable message, see `app::run`.
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MeshError {
+    #[error("Failed to load {}: {source}", path.display())]
+    Load {
+        path: PathBuf,
+        source: tobj::LoadError,
+    },
+    #[error("{} does not contain any triangle meshes", path.display())]
+    Empty { path: PathBuf },
+    #[error("{} has {count} meshes, but a single one was expected", path.display())]
+    NotSingleMesh { path: PathBuf, count: usize },
+    #[error("{} has no model named {name}", path.display())]
+    MissingModel { path: PathBuf, name: &'static str },
+}
+
+#[derive(Debug, Error)]
+pub enum ShaderError {
+    #[error("Failed to read shader source {path}: {source}")]
+    Read { path: String, source: io::Error },
+    #[error("Unknown shader type of {path}, expected .vert, .frag, .tcs, .tes or .geom")]
+    UnknownType { path: String },
+    #[error("Shader {path} failed to compile:\n{log}")]
+    Compile { path: String, log: String },
+    #[error("Shader program failed to link:\n{log}")]
+    Link { log: String },
+}
+
+#[derive(Debug, Error)]
+pub enum TextureError {
+    #[error("Failed to load texture {}: {source}", path.display())]
+    Load {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    #[error("The faces of the skybox in {} aren't squares of one size", path.display())]
+    Faces { path: PathBuf },
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("expected `key = value`")]
+    Syntax,
+    #[error("unknown setting `{0}`")]
+    UnknownKey(String),
+    #[error("invalid value `{value}` for `{key}`")]
+    InvalidValue { key: String, value: String },
+    #[error("`{key}` must be {range}, got {value}")]
+    OutOfRange {
+        key: String,
+        range: &'static str,
+        value: String,
+    },
+    #[error("`{key}` expects `{format}`")]
+    WrongFormat { key: String, format: &'static str },
+    #[error("Failed to save config to {path}: {source}")]
+    Save { path: String, source: io::Error },
+}
+
+// A rig and its animations that couldn't be read, see `gltf::load`
+#[derive(Debug, Error)]
+pub enum AnimationError {
+    #[error("Failed to read {}: {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("{} is not a glTF file with a skin: {message}", path.display())]
+    Invalid { path: PathBuf, message: String },
+}
+
+// A console command that couldn't be run, see `console::CommandRegistry`
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error("Unknown command `{0}`, `help` lists them all")]
+    Unknown(String),
+    // Missing or malformed arguments. The registry adds the command's usage to the message.
+    #[error("Wrong arguments")]
+    Usage,
+    #[error("{0}")]
+    Failed(String),
+}
+
+// An OpenGL call that raised an error, see `gl_check!`
+#[derive(Debug, Error)]
+#[error("{call} failed with {} at {file}:{line}", crate::util::gl_error_name(*.code))]
+pub struct GlError {
+    pub call: &'static str,
+    pub code: u32,
+    pub file: &'static str,
+    pub line: u32,
+}
+
+#[derive(Debug, Error)]
+pub enum RenderError {
+    #[error(transparent)]
+    Mesh(#[from] MeshError),
+    #[error(transparent)]
+    Shader(#[from] ShaderError),
+    #[error(transparent)]
+    Texture(#[from] TextureError),
+    #[error(transparent)]
+    Animation(#[from] AnimationError),
+    #[error("Failed to start the event loop: {0}")]
+    EventLoop(#[from] winit::error::EventLoopError),
+    // Window system errors aren't always thread safe, so only their message is kept
+    #[error("Failed to create a window: {0}")]
+    Window(String),
+    #[error("No OpenGL {major}.{minor} core profile context available: {source}")]
+    NoContext {
+        major: u8,
+        minor: u8,
+        source: glutin::error::Error,
+    },
+    #[error("OpenGL context error: {0}")]
+    Context(#[from] glutin::error::Error),
+    #[error("{0}")]
+    Unsupported(&'static str),
+    #[error(transparent)]
+    Gl(#[from] GlError),
+    #[cfg(feature = "egui")]
+    #[error("Failed to set up the UI painter: {0}")]
+    Ui(#[from] egui_glow::PainterError),
+    #[error("Framebuffer is incomplete: 0x{0:x}")]
+    IncompleteFramebuffer(u32),
+    #[error("Failed to load input recording: {0}")]
+    Replay(String),
+    #[error("Failed to create input recording {path}: {source}")]
+    Record { path: String, source: io::Error },
+    #[error("Failed to capture to {}: {source}", path.display())]
+    Capture { path: PathBuf, source: io::Error },
+    #[error("Frame size changed from {from:?} to {to:?} during capture")]
+    CaptureSizeChanged { from: (u32, u32), to: (u32, u32) },
+    #[error("Failed to save {}: {source}", path.display())]
+    SaveFrame {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    #[error("ffmpeg failed: {0}")]
+    Ffmpeg(io::Error),
+    #[error(
+        "The render thread panicked{}",
+        report.as_ref().map(|path| format!(", see {}", path.display())).unwrap_or_default()
+    )]
+    RenderThreadPanicked { report: Option<PathBuf> },
+}
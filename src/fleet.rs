@@ -0,0 +1,420 @@
+// The helicopters' animation and flight, simulated on the update thread.
+//
+// The demo sends the controls for flying the first helicopter and changes to the animation clock,
+// and poses the helicopter nodes from the snapshots published after every step. The helicopter
+// flown with the controls is simulated with `pilot::FlightPhysics`, standing on the terrain's height field
+// and the landing pads, and those sent to fly between waypoints are steered by
+// `ai::WaypointPilot`.
+use crate::ai;
+use crate::game::{self, LandingPad};
+use crate::pilot;
+use gloom_rs::scene_graph::SceneNode;
+use gloom_rs::simulation::{Interpolate, Simulation};
+use gloom_rs::toolbox::{self, Ease, Easing, FlightPattern, HeightField, Spline, Transform, Tween};
+use rayon::prelude::*;
+use std::f32::consts::{PI, TAU};
+use std::sync::Arc;
+
+// The figure eight the helicopters patrol, through these points and back to the first
+#[rustfmt::skip]
+const PATROL_ROUTE: [[f32; 3]; 16] = [
+    [  0.000, 0.0,  45.000], [ 10.607, 0.0,  41.575], [ 15.000, 0.0,  31.820], [ 10.607, 0.0,  17.221],
+    [  0.000, 0.0,   0.000], [-10.607, 0.0, -17.221], [-15.000, 0.0, -31.820], [-10.607, 0.0, -41.575],
+    [  0.000, 0.0, -45.000], [ 10.607, 0.0, -41.575], [ 15.000, 0.0, -31.820], [ 10.607, 0.0, -17.221],
+    [  0.000, 0.0,   0.000], [-10.607, 0.0,  17.221], [-15.000, 0.0,  31.820], [-10.607, 0.0,  41.575],
+];
+const PATROL_SPEED: f32 = 30.0; // Units per second
+
+// Each helicopter is this many seconds ahead of the one before it
+const SPACING: f32 = 0.8;
+const DOOR_OPEN: f32 = 2.0;
+const DOOR_TIME: f32 = 0.8; // Seconds to slide all the way open or shut
+
+// Radians per second the main rotor turns at full speed. The tail rotor turns twice as fast.
+const ROTOR_SPEED: f32 = 10.0;
+// Seconds for the rotors to get most of the way up to speed, and to wind down to a halt
+const SPIN_UP_TIME: f32 = 1.5;
+const SPIN_DOWN_TIME: f32 = 4.0;
+// Share of full speed above which the blades blur into a disc, and how solid the disc and the
+// blades are once fully blurred
+const BLUR_SPEED: f32 = 0.4;
+const DISC_OPACITY: f32 = 0.35;
+const BLURRED_BLADE_OPACITY: f32 = 0.2;
+// The rotor sound plays slower as the rotor winds down, but no slower than this
+const MIN_SOUND_SPEED: f32 = 0.1;
+
+pub enum FleetInput {
+    Controls(pilot::Controls), // For flying the first helicopter
+    PilotMode(bool),
+    Paused(bool),
+    TimeScale(f32),
+    Step,              // Advance the paused animation by a single step
+    Spawn(usize),      // Add this many helicopters, following the path behind the others
+    ToggleDoor(usize), // Slide a helicopter's door open, or shut if it is open or opening
+    // Have a helicopter fly a pattern. Landings start right away, while patterns going round and
+    // round are flown as far ahead as the helicopter was.
+    Fly(usize, FlightPattern),
+    // Have a helicopter fly round the waypoints on its own, starting with the waypoint as far along
+    // them as the helicopter is along the fleet
+    FlyWaypoints(usize, Arc<Vec<glm::Vec3>>),
+    // The pads of the landing game, which the helicopters stand on like the terrain
+    LandingPads(Vec<LandingPad>),
+    // A helicopter's rotor struck something and stopped dead. The flown one also loses its lift.
+    RotorStrike(usize),
+}
+
+// The patrol every helicopter starts out on
+pub fn patrol() -> FlightPattern {
+    let waypoints = PATROL_ROUTE.iter().map(|&point| point.into()).collect();
+    FlightPattern::Patrol {
+        route: Spline::catmull_rom(waypoints, true),
+        speed: PATROL_SPEED,
+    }
+}
+
+// Around the same area as the patrol
+pub fn figure_eight() -> FlightPattern {
+    FlightPattern::FigureEight {
+        center: glm::zero(),
+        half_width: 15.0,
+        half_length: 45.0,
+        lap_time: 8.0,
+    }
+}
+
+pub fn orbit() -> FlightPattern {
+    FlightPattern::Orbit {
+        center: glm::vec3(0.0, 10.0, 0.0),
+        radius: 40.0,
+        lap_time: 10.0,
+    }
+}
+
+// A landing on `spot` from `from`, at about patrol speed
+pub fn landing(from: glm::Vec3, spot: glm::Vec3) -> FlightPattern {
+    FlightPattern::Land {
+        from,
+        to: spot,
+        duration: (glm::distance(&from, &spot) / PATROL_SPEED * 2.0).max(3.0),
+    }
+}
+
+// What a helicopter flies, and how many seconds ahead of the fleet's clock it is in it. Helicopters
+// flying between waypoints follow those instead of their pattern.
+#[derive(Clone)]
+struct Flight {
+    pattern: Arc<FlightPattern>,
+    time_offset: f32,
+    waypoints: Option<ai::WaypointPilot>,
+}
+
+// Where a helicopter and its moving parts are
+#[derive(Clone, Copy, Default)]
+pub struct HelicopterState {
+    pub body: Transform,
+    pub door: f32,        // How far the door has slid open, 0-2
+    pub main_rotor: f32,  // How far the main rotor has turned about Y, 0-2π
+    pub tail_rotor: f32,  // How far the tail rotor has turned about X, 0-2π
+    pub rotor_speed: f32, // Share of full speed the rotors turn at, 0-1
+}
+
+impl HelicopterState {
+    // Pose a helicopter built by `demo::create_helicopter`. Blades turning fast fade into the
+    // translucent disc they sweep, like they would blur on camera, and the faster they turn the
+    // louder they sound.
+    pub fn apply_to(&self, helicopter: &mut SceneNode) {
+        let blur = ((self.rotor_speed - BLUR_SPEED) / (1.0 - BLUR_SPEED)).clamp(0.0, 1.0);
+        let blade_opacity = 1.0_f32.interpolate(&BLURRED_BLADE_OPACITY, blur);
+        let body_node = helicopter.get_child(0);
+        body_node.set_transform(&self.body);
+        body_node.get_child(0).position.z = self.door;
+        let main_rotor_node = body_node.get_child(1);
+        main_rotor_node.rotation.y = self.main_rotor;
+        main_rotor_node.opacity = blade_opacity;
+        main_rotor_node.get_child(0).opacity = DISC_OPACITY * blur;
+        if let Some(sound) = &mut main_rotor_node.sound {
+            sound.volume = self.rotor_speed;
+            sound.speed = self.rotor_speed.max(MIN_SOUND_SPEED);
+        }
+        let tail_rotor_node = body_node.get_child(2);
+        tail_rotor_node.rotation.x = self.tail_rotor;
+        tail_rotor_node.opacity = blade_opacity;
+        tail_rotor_node.get_child(0).opacity = DISC_OPACITY * blur;
+    }
+}
+
+// Blending from `angle` to `next` the short way around, as the angles wrap
+fn interpolate_angle(angle: f32, next: f32, t: f32) -> f32 {
+    let next = angle + (next - angle + PI).rem_euclid(TAU) - PI;
+    angle.interpolate(&next, t)
+}
+
+impl Interpolate for HelicopterState {
+    fn interpolate(&self, next: &HelicopterState, t: f32) -> HelicopterState {
+        HelicopterState {
+            body: self.body.interpolate(&next.body, t),
+            door: self.door.interpolate(&next.door, t),
+            main_rotor: interpolate_angle(self.main_rotor, next.main_rotor, t),
+            tail_rotor: interpolate_angle(self.tail_rotor, next.tail_rotor, t),
+            rotor_speed: self.rotor_speed.interpolate(&next.rotor_speed, t),
+        }
+    }
+}
+
+pub struct Fleet {
+    // Animations run on their own clock: P pauses, . steps a single frame, [ and ] scale time
+    clock: toolbox::AnimationClock,
+    flights: Vec<Flight>, // One per helicopter
+    patrol: Arc<FlightPattern>,
+    doors: Vec<Tween<f32>>, // One per helicopter
+    controls: pilot::Controls,
+    // In pilot mode the first helicopter is flown with the controls instead of following its path
+    pilot_mode: bool,
+    physics: pilot::FlightPhysics,
+    ground: Option<HeightField>,
+    pads: Vec<LandingPad>,
+    helicopters: Vec<HelicopterState>,
+}
+
+impl Fleet {
+    pub fn new(count: usize, pilot_mode: bool, ground: Option<HeightField>) -> Fleet {
+        let mut fleet = Fleet {
+            clock: toolbox::AnimationClock::new(),
+            flights: vec![],
+            patrol: Arc::new(patrol()),
+            doors: vec![],
+            controls: pilot::Controls::default(),
+            pilot_mode,
+            physics: pilot::FlightPhysics::new(pilot::FlightModel::default(), glm::zero()),
+            ground,
+            pads: vec![],
+            helicopters: vec![],
+        };
+        fleet.spawn(count);
+        fleet
+    }
+
+    // Add helicopters on patrol, each a bit ahead of the last, their rotors still at rest
+    fn spawn(&mut self, count: usize) {
+        for _ in 0..count {
+            self.flights.push(Flight {
+                pattern: self.patrol.clone(),
+                time_offset: self.helicopters.len() as f32 * SPACING,
+                waypoints: None,
+            });
+            self.doors.push(Tween::at_rest(0.0, Easing::Linear));
+            self.helicopters.push(HelicopterState::default());
+        }
+        self.animate();
+    }
+
+    // Move the helicopters along their patterns. Large fleets are animated in parallel, in batches
+    // big enough to be worth handing out.
+    fn animate(&mut self) {
+        let time = self.clock.time;
+        let pilot_mode = self.pilot_mode;
+        self.helicopters
+            .par_iter_mut()
+            .zip(self.flights.par_iter())
+            .with_min_len(64)
+            .enumerate()
+            .for_each(|(i, (helicopter, flight))| {
+                let helicopter_elapsed = time + flight.time_offset;
+
+                // Make the other helicopters apart from the one we are controlling follow path
+                if (i != 0 || !pilot_mode) && flight.waypoints.is_none() {
+                    flight
+                        .pattern
+                        .apply(helicopter_elapsed, &mut helicopter.body);
+                }
+            });
+    }
+
+    // Bring the rotors up to speed, or let them wind down on helicopters that have landed and on
+    // all of them while the animation is paused, and turn them by `delta_time` seconds' worth
+    fn spin_rotors(&mut self, delta_time: f32) {
+        let time = self.clock.time;
+        let paused = self.clock.paused;
+        let pilot_mode = self.pilot_mode;
+        self.helicopters
+            .par_iter_mut()
+            .zip(self.flights.par_iter())
+            .with_min_len(64)
+            .enumerate()
+            .for_each(|(i, (helicopter, flight))| {
+                let flown = i == 0 && pilot_mode;
+                let landed = !flown
+                    && flight.waypoints.is_none()
+                    && flight.pattern.has_landed(time + flight.time_offset);
+                let target = if paused || landed { 0.0 } else { 1.0 };
+                let response_time = if target > helicopter.rotor_speed {
+                    SPIN_UP_TIME
+                } else {
+                    SPIN_DOWN_TIME
+                };
+                helicopter.rotor_speed +=
+                    (target - helicopter.rotor_speed) * (1.0 - (-delta_time / response_time).exp());
+
+                let turn = helicopter.rotor_speed * ROTOR_SPEED * delta_time;
+                helicopter.main_rotor = (helicopter.main_rotor + turn).rem_euclid(TAU);
+                helicopter.tail_rotor = (helicopter.tail_rotor + 2.0 * turn).rem_euclid(TAU);
+            });
+    }
+
+    // Steer the helicopters flying between waypoints by `delta_time` seconds, each away from the
+    // others around it, whatever those are flying
+    fn fly_waypoints(&mut self, delta_time: f32) {
+        if delta_time <= 0.0 || self.flights.iter().all(|flight| flight.waypoints.is_none()) {
+            return;
+        }
+        let positions = self
+            .helicopters
+            .iter()
+            .map(|helicopter| helicopter.body.position);
+        let neighbourhood = ai::Neighbourhood::new(positions.collect());
+        let (ground, pads) = (self.ground.as_ref(), &self.pads);
+        let pilot_mode = self.pilot_mode;
+        self.helicopters
+            .par_iter_mut()
+            .zip(self.flights.par_iter_mut())
+            .with_min_len(64)
+            .enumerate()
+            .for_each(|(i, (helicopter, flight))| {
+                let Some(pilot) = flight.waypoints.as_mut().filter(|_| i != 0 || !pilot_mode)
+                else {
+                    return;
+                };
+                let position = helicopter.body.position;
+                let ground = game::ground_at(ground, pads, position.x, position.z);
+                pilot.step(
+                    &mut helicopter.body,
+                    neighbourhood.near(i),
+                    ground,
+                    delta_time,
+                );
+            });
+    }
+}
+
+impl Simulation for Fleet {
+    type Input = FleetInput;
+    type Snapshot = Vec<HelicopterState>;
+
+    fn input(&mut self, input: FleetInput) {
+        match input {
+            FleetInput::Controls(controls) => self.controls = controls,
+            // Taking over starts from wherever the animation left the helicopter, as fast as it
+            // went, and letting go rejoins its pattern at the point closest to where it was flown,
+            // if it goes round
+            FleetInput::PilotMode(pilot_mode) => {
+                if !self.pilot_mode && pilot_mode {
+                    if let Some(flight) = self.flights.first() {
+                        let velocity = match &flight.waypoints {
+                            Some(pilot) => pilot.velocity,
+                            None => flight
+                                .pattern
+                                .velocity(self.clock.time + flight.time_offset),
+                        };
+                        self.physics = pilot::FlightPhysics::new(self.physics.model, velocity);
+                    }
+                }
+                if self.pilot_mode && !pilot_mode {
+                    if let (Some(controlled), Some(flight)) =
+                        (self.helicopters.first(), self.flights.first_mut())
+                    {
+                        if let Some(pilot) = &mut flight.waypoints {
+                            pilot.velocity = self.physics.velocity;
+                        } else if let Some(time) =
+                            flight.pattern.closest_time(&controlled.body.position)
+                        {
+                            flight.time_offset = time - self.clock.time;
+                        }
+                    }
+                }
+                self.pilot_mode = pilot_mode;
+            }
+            FleetInput::Paused(paused) => self.clock.paused = paused,
+            FleetInput::TimeScale(time_scale) => self.clock.set_time_scale(time_scale),
+            FleetInput::Step => {
+                self.clock.step();
+                self.fly_waypoints(toolbox::AnimationClock::STEP);
+                self.animate();
+            }
+            FleetInput::Spawn(count) => self.spawn(count),
+            // Doors ease open, and bounce a little against the frame when they slam shut
+            FleetInput::ToggleDoor(index) => {
+                if let Some(door) = self.doors.get_mut(index) {
+                    let (target, easing) = if door.to > 0.0 {
+                        (0.0, Easing::Bounce(Ease::Out))
+                    } else {
+                        (DOOR_OPEN, Easing::Cubic(Ease::InOut))
+                    };
+                    let distance = (target - door.value()).abs();
+                    door.easing = easing;
+                    door.retarget(target, DOOR_TIME * distance / DOOR_OPEN);
+                }
+            }
+            FleetInput::Fly(index, pattern) => {
+                if let Some(flight) = self.flights.get_mut(index) {
+                    if let FlightPattern::Land { .. } = pattern {
+                        flight.time_offset = -self.clock.time;
+                    }
+                    flight.pattern = Arc::new(pattern);
+                    flight.waypoints = None;
+                    self.animate();
+                }
+            }
+            FleetInput::FlyWaypoints(index, waypoints) => {
+                let first = index * waypoints.len() / self.flights.len().max(1);
+                if let (Some(flight), false) = (self.flights.get_mut(index), waypoints.is_empty()) {
+                    let velocity = match &flight.waypoints {
+                        Some(pilot) => pilot.velocity,
+                        None => flight
+                            .pattern
+                            .velocity(self.clock.time + flight.time_offset),
+                    };
+                    flight.waypoints = Some(ai::WaypointPilot::new(waypoints, first, velocity));
+                }
+            }
+            FleetInput::LandingPads(pads) => self.pads = pads,
+            FleetInput::RotorStrike(index) => {
+                if let Some(helicopter) = self.helicopters.get_mut(index) {
+                    helicopter.rotor_speed = 0.0;
+                }
+                if index == 0 && self.pilot_mode {
+                    self.physics.collective = 0.0;
+                }
+            }
+        }
+    }
+
+    fn step(&mut self, delta_time: f32) {
+        // Paused rotors wind down in real time
+        let animation_delta_time = self.clock.tick(delta_time);
+        self.spin_rotors(if self.clock.paused {
+            delta_time
+        } else {
+            animation_delta_time
+        });
+        self.fly_waypoints(animation_delta_time);
+        for (helicopter, door) in self.helicopters.iter_mut().zip(&mut self.doors) {
+            if !door.is_done() {
+                helicopter.door = door.advance(delta_time);
+            }
+        }
+        if let Some(controlled) = self.helicopters.first_mut() {
+            if self.pilot_mode {
+                let position = controlled.body.position;
+                let ground =
+                    game::ground_at(self.ground.as_ref(), &self.pads, position.x, position.z);
+                self.physics
+                    .step(&mut controlled.body, &self.controls, ground, delta_time);
+            }
+        }
+        self.animate();
+    }
+
+    fn snapshot(&self) -> Vec<HelicopterState> {
+        self.helicopters.clone()
+    }
+}
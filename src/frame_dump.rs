@@ -0,0 +1,171 @@
+// Every draw of a single frame written to a JSON file, a poor man's frame debugger.
+//
+// `GlBackend::record_draws` makes the backend keep a `DrawRecord` of each draw it makes until
+// `take_recorded_draws`: the debug groups it was in, the pipeline, the VAO, how many indices or
+// vertices were drawn and the value of every active uniform at the time. Draws don't know which
+// scene node they came from, so `name_scene_draws` matches them up with the scene graph afterwards,
+// and `write` saves them to a `frame_*.json` file in the working directory for offline inspection.
+// What is drawn with OpenGL directly, bypassing the backend, like egui's panels, is left out.
+use crate::renderer;
+use crate::scene_graph::SceneNode;
+use crate::screenshot;
+use std::fmt::Write as _;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug)]
+pub struct DrawRecord {
+    pub group: String, // The debug groups the draw was in, e.g. `Scene` or `Bounds`
+    pub pipeline: String,
+    pub program: u32,
+    pub vao: u32,
+    pub mode: &'static str, // `triangles`, `unindexed triangles` or `lines`
+    pub count: i32,         // Indices drawn, or vertices for unindexed draws
+    // Each active uniform with its components, sorted by name. Only the first element of arrays.
+    pub uniforms: Vec<(String, Vec<f32>)>,
+    pub node: Option<String>, // The scene node drawn, once named by `name_scene_draws`
+    pub world: glm::Mat4,     // Model matrix
+}
+
+// Name the draws of the nodes under `root`, which must have been drawn with
+// `renderer::record_scene` since the last change to the scene. Draws of other things in between
+// are skipped.
+pub fn name_scene_draws(records: &mut [DrawRecord], root: &SceneNode) {
+    let mut next = 0;
+    for node in renderer::drawn_nodes(root) {
+        let found = records[next..]
+            .iter()
+            .position(|record| record.vao == node.vao_id && record.count == node.index_count);
+        if let Some(offset) = found {
+            records[next + offset].node = Some(node.name.clone());
+            next += offset + 1;
+        }
+    }
+}
+
+// Write the draws to a new `frame_*.json` file, returning its path
+pub fn write(records: &[DrawRecord]) -> io::Result<PathBuf> {
+    let path = PathBuf::from(format!("frame_{}.json", screenshot::timestamp()));
+    std::fs::write(&path, to_json(records))?;
+    Ok(path)
+}
+
+fn to_json(records: &[DrawRecord]) -> String {
+    let mut json = String::new();
+    let _ = writeln!(json, "{{\n  \"draws\": [");
+    for (i, record) in records.iter().enumerate() {
+        let node = match &record.node {
+            Some(node) => string(node),
+            None => "null".to_string(),
+        };
+        let uniforms: Vec<String> = record
+            .uniforms
+            .iter()
+            .map(|(name, values)| format!("{}: {}", string(name), numbers(values)))
+            .collect();
+        let _ = writeln!(json, "    {{");
+        let _ = writeln!(json, "      \"group\": {},", string(&record.group));
+        let _ = writeln!(json, "      \"pipeline\": {},", string(&record.pipeline));
+        let _ = writeln!(json, "      \"program\": {},", record.program);
+        let _ = writeln!(json, "      \"vao\": {},", record.vao);
+        let _ = writeln!(json, "      \"mode\": {},", string(record.mode));
+        let _ = writeln!(json, "      \"count\": {},", record.count);
+        let _ = writeln!(json, "      \"node\": {},", node);
+        let _ = writeln!(
+            json,
+            "      \"world\": {},",
+            numbers(record.world.as_slice())
+        );
+        let _ = writeln!(json, "      \"uniforms\": {{{}}}", uniforms.join(", "));
+        let separator = if i + 1 < records.len() { "," } else { "" };
+        let _ = writeln!(json, "    }}{}", separator);
+    }
+    let _ = writeln!(json, "  ]\n}}");
+    json
+}
+
+// A JSON string literal
+fn string(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(literal, "\\u{:04x}", c as u32);
+            }
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+// A JSON array of numbers, with null for NaN and infinities, which JSON has no numbers for
+fn numbers(values: &[f32]) -> String {
+    let values: Vec<String> = values
+        .iter()
+        .map(|value| {
+            if value.is_finite() {
+                format!("{:?}", value)
+            } else {
+                "null".to_string()
+            }
+        })
+        .collect();
+    format!("[{}]", values.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::Vao;
+
+    fn record(vao: u32, count: i32) -> DrawRecord {
+        DrawRecord {
+            group: "Scene".to_string(),
+            pipeline: "simple".to_string(),
+            program: 1,
+            vao,
+            mode: "triangles",
+            count,
+            uniforms: vec![],
+            node: None,
+            world: glm::Mat4::identity(),
+        }
+    }
+
+    #[test]
+    fn scene_draws_are_named_in_drawing_order() {
+        let mut root = SceneNode::new();
+        let mut first = SceneNode::from_vao(Vao {
+            id: 2,
+            index_count: 6,
+        });
+        let mut second = SceneNode::from_vao(Vao {
+            id: 2,
+            index_count: 6,
+        });
+        first.name = "first".to_string();
+        second.name = "second".to_string();
+        root.add_child(&first);
+        root.add_child(&second);
+
+        // A draw of something else in between, with the same VAO but another index count
+        let mut records = vec![record(2, 6), record(2, 3), record(2, 6)];
+        name_scene_draws(&mut records, &root);
+        let names: Vec<_> = records
+            .iter()
+            .map(|record| record.node.as_deref())
+            .collect();
+        assert_eq!(names, vec![Some("first"), None, Some("second")]);
+    }
+
+    #[test]
+    fn strings_and_numbers_are_valid_json() {
+        assert_eq!(string("a \"b\"\\\n\t"), "\"a \\\"b\\\"\\\\\\n\\u0009\"");
+        assert_eq!(numbers(&[1.0, -0.5, f32::NAN]), "[1.0, -0.5, null]");
+        assert_eq!(numbers(&[]), "[]");
+    }
+}
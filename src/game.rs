@@ -0,0 +1,399 @@
+use gloom_rs::text::TextRenderer;
+use gloom_rs::toolbox::HeightField;
+
+// The landing game: touch the flown helicopter down softly on landing pads around the terrain, one
+// after the other, each within a time limit.
+// Landing on the pad being aimed for slower than `SOFT_DESCENT` scores, more for landing closer to
+// its middle and for time to spare, and moves on to the next pad. Landing harder scores nothing,
+// and running out of time moves on without scoring. The pads are solid: the fleet stands the
+// helicopter on them through `ground_at`, and touchdowns are told by the same ground.
+
+pub const PAD_RADIUS: f32 = 8.0;
+const PAD_COUNT: usize = 5;
+// How far the pads are from each other, and from where the game starts for the first
+const PAD_DISTANCES: [f32; 3] = [70.0, 110.0, 150.0];
+const PAD_TIME: f32 = 45.0; // Seconds to land on each pad
+
+// Metres per second of descent and of drifting over the ground that still count as soft
+const SOFT_DESCENT: f32 = 2.5;
+const SOFT_DRIFT: f32 = 3.0;
+// Points for a soft landing, and at most for landing in the middle and per second to spare
+const LANDING_POINTS: u32 = 100;
+const ACCURACY_POINTS: f32 = 100.0;
+const TIME_POINTS: f32 = 5.0;
+// Within this of the ground the helicopter counts as standing on it
+const TOUCHING: f32 = 0.05;
+// How far pads stand above the highest of the ground sampled under them, for the bumps in between
+const PAD_CLEARANCE: f32 = 0.3;
+// How long the outcome of a landing is shown, in seconds
+const MESSAGE_TIME: f32 = 3.0;
+
+// Screen pixels per font pixel, and the colors of the HUD
+const SCALE: f32 = 2.0;
+const MESSAGE_SCALE: f32 = 4.0;
+const MARGIN: f32 = 8.0;
+const TEXT_COLOR: [f32; 4] = [0.9, 0.95, 1.0, 1.0];
+const GOOD_COLOR: [f32; 4] = [0.4, 0.95, 0.5, 1.0];
+const BAD_COLOR: [f32; 4] = [1.0, 0.45, 0.4, 1.0];
+const BACKGROUND_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+
+// A round landing pad, standing on the ground with its top at `center`
+#[derive(Clone, Copy, Debug)]
+pub struct LandingPad {
+    pub center: glm::Vec3,
+    pub radius: f32,
+}
+
+impl LandingPad {
+    // A pad around `x`, `z` just above the highest of the ground under it, and how far its side
+    // needs to reach down to meet the lowest
+    pub fn on_ground(ground: &HeightField, x: f32, z: f32) -> Option<(LandingPad, f32)> {
+        let steps = 4;
+        let (mut lowest, mut highest) = (f32::INFINITY, f32::NEG_INFINITY);
+        for i in -steps..=steps {
+            for j in -steps..=steps {
+                let offset = glm::vec2(i as f32, j as f32) * PAD_RADIUS / steps as f32;
+                if glm::length(&offset) > PAD_RADIUS {
+                    continue;
+                }
+                if let Some(height) = ground.height_at(x + offset.x, z + offset.y) {
+                    lowest = lowest.min(height);
+                    highest = highest.max(height);
+                }
+            }
+        }
+        if highest == f32::NEG_INFINITY {
+            return None;
+        }
+        let pad = LandingPad {
+            center: glm::vec3(x, highest + PAD_CLEARANCE, z),
+            radius: PAD_RADIUS,
+        };
+        Some((pad, highest + PAD_CLEARANCE - lowest + 1.0))
+    }
+
+    pub fn covers(&self, x: f32, z: f32) -> bool {
+        glm::distance(&self.center.xz(), &glm::vec2(x, z)) <= self.radius
+    }
+}
+
+// The height of the ground at `x`, `z`, on top of any pad there
+pub fn ground_at(ground: Option<&HeightField>, pads: &[LandingPad], x: f32, z: f32) -> Option<f32> {
+    let terrain = ground.and_then(|ground| ground.height_at(x, z));
+    pads.iter()
+        .filter(|pad| pad.covers(x, z))
+        .map(|pad| pad.center.y)
+        .chain(terrain)
+        .reduce(f32::max)
+}
+
+// Pads in a chain from `start`, each some way from the last and turning a different way, so the
+// flights between them differ
+pub fn place_pads(ground: &HeightField, start: &glm::Vec3) -> Vec<(LandingPad, f32)> {
+    let mut pads = vec![];
+    let mut from = start.xz();
+    let mut heading: f32 = 0.0;
+    for i in 0..PAD_COUNT {
+        heading += 1.1 + 0.7 * (i % 3) as f32;
+        let distance = PAD_DISTANCES[i % PAD_DISTANCES.len()];
+        let spot = from + glm::vec2(heading.sin(), -heading.cos()) * distance;
+        if let Some(pad) = LandingPad::on_ground(ground, spot.x, spot.y) {
+            pads.push(pad);
+            from = spot;
+        }
+    }
+    pads
+}
+
+// What became of a landing attempt
+pub enum Outcome {
+    Landed { points: u32, descent: f32 }, // Softly on the pad being aimed for
+    Crashed { descent: f32 },             // Onto it too hard
+    OutOfTime,
+}
+
+struct Message {
+    text: String,
+    good: bool,
+    remaining: f32, // In seconds
+}
+
+pub struct LandingGame {
+    pub pads: Vec<LandingPad>,
+    pub target: usize, // The pad being aimed for, past the last once the game is over
+    pub score: u32,
+    time_left: f32,
+    // Where the helicopter was the last frame and whether it was standing, to tell how fast it
+    // came down when it touches down
+    last_position: Option<glm::Vec3>,
+    velocity: glm::Vec3,
+    standing: bool,
+    message: Option<Message>,
+}
+
+impl LandingGame {
+    pub fn new(pads: Vec<LandingPad>) -> LandingGame {
+        LandingGame {
+            pads,
+            target: 0,
+            score: 0,
+            time_left: PAD_TIME,
+            last_position: None,
+            velocity: glm::zero(),
+            standing: true,
+            message: None,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.target >= self.pads.len()
+    }
+
+    // Follow the flown helicopter, whose body is at `position` with its skids `skid_height` below,
+    // over `ground`, the height of the ground under it with the pads
+    pub fn update(
+        &mut self,
+        position: &glm::Vec3,
+        skid_height: f32,
+        ground: Option<f32>,
+        delta_time: f32,
+    ) -> Option<Outcome> {
+        if let Some(message) = &mut self.message {
+            message.remaining -= delta_time;
+            if message.remaining <= 0.0 {
+                self.message = None;
+            }
+        }
+        if self.is_over() || delta_time <= 0.0 {
+            return None;
+        }
+        // Velocity before touching the ground, as the ground stops the fall right away
+        let standing = ground.is_some_and(|ground| position.y - skid_height - ground <= TOUCHING);
+        let landed_now = standing && !self.standing;
+        if let (Some(last), false) = (self.last_position, standing) {
+            self.velocity = (position - last) / delta_time;
+        }
+        self.last_position = Some(*position);
+        self.standing = standing;
+
+        self.time_left -= delta_time;
+        let pad = self.pads[self.target];
+        let outcome = if landed_now && pad.covers(position.x, position.z) {
+            let descent = (-self.velocity.y).max(0.0);
+            let drift = glm::length(&self.velocity.xz());
+            if descent <= SOFT_DESCENT && drift <= SOFT_DRIFT {
+                let off_center = glm::distance(&pad.center.xz(), &position.xz()) / pad.radius;
+                let points = LANDING_POINTS as f32
+                    + ACCURACY_POINTS * (1.0 - off_center)
+                    + TIME_POINTS * self.time_left.max(0.0);
+                Outcome::Landed {
+                    points: points.round() as u32,
+                    descent,
+                }
+            } else {
+                Outcome::Crashed { descent }
+            }
+        } else if self.time_left <= 0.0 {
+            Outcome::OutOfTime
+        } else {
+            return None;
+        };
+
+        let text = match outcome {
+            Outcome::Landed { points, descent } => {
+                self.score += points;
+                format!("Landed at {:.1} m/s: +{}", descent, points)
+            }
+            Outcome::Crashed { descent } => format!("Too hard at {:.1} m/s", descent),
+            Outcome::OutOfTime => "Out of time".to_string(),
+        };
+        if !matches!(outcome, Outcome::Crashed { .. }) {
+            self.target += 1;
+            self.time_left = PAD_TIME;
+        }
+        let text = if self.is_over() {
+            format!("{}. Final score: {}", text, self.score)
+        } else {
+            text
+        };
+        self.message = Some(Message {
+            text,
+            good: matches!(outcome, Outcome::Landed { .. }),
+            remaining: MESSAGE_TIME,
+        });
+        Some(outcome)
+    }
+
+    // The score and how the current landing is going at the top right, and the outcome of the
+    // last one in the middle of the screen. `position` and `yaw` are the flown helicopter's.
+    pub fn draw_hud(
+        &self,
+        text: &mut TextRenderer,
+        viewport_size: (u32, u32),
+        position: &glm::Vec3,
+        yaw: f32,
+    ) {
+        let mut lines = vec![(format!("Score {}", self.score), TEXT_COLOR)];
+        if let Some(pad) = self.pads.get(self.target) {
+            lines.push((
+                format!("Pad {} of {}", self.target + 1, self.pads.len()),
+                TEXT_COLOR,
+            ));
+            let time_color = if self.time_left < 10.0 {
+                BAD_COLOR
+            } else {
+                TEXT_COLOR
+            };
+            lines.push((format!("Time {:.1}", self.time_left.max(0.0)), time_color));
+
+            // Which way the pad is, relative to where the helicopter faces, which is -Z
+            let offset = pad.center - position;
+            let bearing = (-offset.x).atan2(-offset.z) - yaw;
+            let bearing = bearing.to_degrees().rem_euclid(360.0);
+            let side = match bearing {
+                b if !(15.0..=345.0).contains(&b) => "ahead",
+                b if b < 165.0 => "left",
+                b if b <= 195.0 => "behind",
+                _ => "right",
+            };
+            let whereabouts = if pad.covers(position.x, position.z) {
+                format!("Over the pad, {:+.0} m", offset.y)
+            } else {
+                format!("{:.0} m {}, {:+.0} m", offset.xz().norm(), side, offset.y)
+            };
+            lines.push((whereabouts, TEXT_COLOR));
+            let descent = (-self.velocity.y).max(0.0);
+            let descent_color = if self.standing {
+                TEXT_COLOR
+            } else if descent <= SOFT_DESCENT {
+                GOOD_COLOR
+            } else {
+                BAD_COLOR
+            };
+            lines.push((format!("Descent {:.1} m/s", descent), descent_color));
+        } else {
+            lines.push(("Game over".to_string(), TEXT_COLOR));
+        }
+
+        let line_height = TextRenderer::line_height(SCALE);
+        let width = lines
+            .iter()
+            .map(|(line, _)| TextRenderer::text_width(line, SCALE))
+            .fold(0.0, f32::max);
+        let x = viewport_size.0 as f32 - width - 2.0 * MARGIN;
+        text.rect(
+            x - MARGIN,
+            MARGIN,
+            width + 2.0 * MARGIN,
+            lines.len() as f32 * line_height + MARGIN,
+            BACKGROUND_COLOR,
+        );
+        for (i, (line, color)) in lines.iter().enumerate() {
+            let y = 1.5 * MARGIN + i as f32 * line_height;
+            text.text(x, y, SCALE, *color, line);
+        }
+
+        if let Some(message) = &self.message {
+            let width = TextRenderer::text_width(&message.text, MESSAGE_SCALE);
+            let x = (viewport_size.0 as f32 - width) / 2.0;
+            let y = viewport_size.1 as f32 * 0.3;
+            let mut color = if message.good { GOOD_COLOR } else { BAD_COLOR };
+            color[3] = message.remaining.min(1.0);
+            text.text(x, y, MESSAGE_SCALE, color, &message.text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SKID_HEIGHT: f32 = 1.0;
+    const STEP: f32 = 0.1;
+
+    fn game() -> LandingGame {
+        let pad = |x| LandingPad {
+            center: glm::vec3(x, 2.0, 0.0),
+            radius: PAD_RADIUS,
+        };
+        LandingGame::new(vec![pad(0.0), pad(100.0)])
+    }
+
+    // Come straight down at `speed` onto the ground 2 m up at `x`, from 10 m above it. Returns the
+    // outcome of the touchdown.
+    fn descend(game: &mut LandingGame, x: f32, speed: f32) -> Option<Outcome> {
+        let ground = Some(2.0);
+        let mut height = 10.0;
+        loop {
+            height = (height - speed * STEP).max(0.0);
+            let position = glm::vec3(x, 2.0 + SKID_HEIGHT + height, 0.0);
+            let outcome = game.update(&position, SKID_HEIGHT, ground, STEP);
+            if outcome.is_some() || height == 0.0 {
+                return outcome;
+            }
+        }
+    }
+
+    // Take off again, so the next touchdown counts as a new landing
+    fn lift_off(game: &mut LandingGame) {
+        let position = glm::vec3(50.0, 20.0, 0.0);
+        game.update(&position, SKID_HEIGHT, Some(2.0), STEP);
+    }
+
+    #[test]
+    fn soft_landings_on_the_target_score_and_move_on() {
+        let mut game = game();
+        lift_off(&mut game);
+        let Some(Outcome::Landed { points, descent }) = descend(&mut game, 0.0, 2.0) else {
+            panic!("expected a landing");
+        };
+        assert!((descent - 2.0).abs() < 1e-3);
+        // In the middle of the pad with about 41 of the 45 seconds left
+        assert!((400..=410).contains(&points), "{}", points);
+        assert_eq!(game.score, points);
+        assert_eq!(game.target, 1);
+
+        // Off center scores less
+        lift_off(&mut game);
+        let Some(Outcome::Landed {
+            points: off_center, ..
+        }) = descend(&mut game, 104.0, 2.0)
+        else {
+            panic!("expected a landing");
+        };
+        assert!(off_center < points - 40);
+        assert!(game.is_over());
+        assert!(game.update(&glm::zero(), SKID_HEIGHT, None, STEP).is_none());
+    }
+
+    #[test]
+    fn hard_landings_score_nothing_and_stay_on_the_pad() {
+        let mut game = game();
+        lift_off(&mut game);
+        let Some(Outcome::Crashed { descent }) = descend(&mut game, 0.0, 6.0) else {
+            panic!("expected a crash");
+        };
+        assert!((descent - 6.0).abs() < 1e-3);
+        assert_eq!((game.score, game.target), (0, 0));
+
+        // Nor does landing softly somewhere else than the pad being aimed for
+        lift_off(&mut game);
+        assert!(descend(&mut game, 100.0, 1.0).is_none());
+        assert_eq!((game.score, game.target), (0, 0));
+    }
+
+    #[test]
+    fn running_out_of_time_moves_on_without_scoring() {
+        let mut game = game();
+        let hovering = glm::vec3(50.0, 20.0, 0.0);
+        let mut outcome = None;
+        let mut frames = 0;
+        while outcome.is_none() {
+            outcome = game.update(&hovering, SKID_HEIGHT, Some(2.0), STEP);
+            frames += 1;
+        }
+        assert!(matches!(outcome, Some(Outcome::OutOfTime)));
+        assert!((frames as f32 * STEP - PAD_TIME).abs() < 1.5 * STEP);
+        assert_eq!((game.score, game.target), (0, 1));
+    }
+}
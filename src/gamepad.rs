@@ -0,0 +1,187 @@
+// Gamepad sticks and triggers.
+//
+// Applications poll a `Gamepad` once per frame for the positions of its sticks and triggers, and
+// map them to their controls themselves, like they do with keys. `open` finds the first gamepad
+// plugged in. On Linux that is the joystick device the kernel makes for it, read without blocking
+// so a frame never waits on the pad. Elsewhere there is no gamepad support yet, and other sources,
+// e.g. a gamepad crate, can be plugged in by implementing `Gamepad`.
+
+// Where the sticks and triggers of a gamepad are. Stick axes go from -1 to 1, with x to the right
+// and y up, and triggers from 0 when let go to 1 when pulled all the way.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GamepadState {
+    pub left_stick: (f32, f32),
+    pub right_stick: (f32, f32),
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+}
+
+impl GamepadState {
+    // The same state with the sticks centered while within `deadzone` of their middle, and
+    // rescaled from its edge, so they don't drift when let go and don't jump when pushed
+    pub fn with_deadzone(self, deadzone: f32) -> GamepadState {
+        let stick = |(x, y): (f32, f32)| {
+            let length = (x * x + y * y).sqrt();
+            if length <= deadzone {
+                return (0.0, 0.0);
+            }
+            let scale = ((length - deadzone) / (1.0 - deadzone)).min(1.0) / length;
+            (x * scale, y * scale)
+        };
+        GamepadState {
+            left_stick: stick(self.left_stick),
+            right_stick: stick(self.right_stick),
+            ..self
+        }
+    }
+}
+
+pub trait Gamepad {
+    // The state after every change since the last call
+    fn poll(&mut self) -> GamepadState;
+}
+
+// The first gamepad plugged in, if there is one
+#[cfg(target_os = "linux")]
+pub fn open() -> Option<Box<dyn Gamepad>> {
+    linux::Joystick::open("/dev/input/js0").map(|joystick| Box::new(joystick) as Box<dyn Gamepad>)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn open() -> Option<Box<dyn Gamepad>> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{Gamepad, GamepadState};
+    use log::{info, warn};
+    use std::fs::{File, OpenOptions};
+    use std::io::{ErrorKind, Read};
+    use std::os::unix::fs::OpenOptionsExt;
+
+    // The kernel's joystick events, see linux/joystick.h: a timestamp, the value, the type and
+    // the number of the axis or button
+    const EVENT_SIZE: usize = 8;
+    const EVENT_AXIS: u8 = 0x02;
+    const EVENT_INIT: u8 = 0x80; // Set on the events describing the state when the device opens
+
+    // Axis numbers of the usual layout, as the xpad driver reports an Xbox controller
+    const LEFT_X: u8 = 0;
+    const LEFT_Y: u8 = 1;
+    const LEFT_TRIGGER: u8 = 2;
+    const RIGHT_X: u8 = 3;
+    const RIGHT_Y: u8 = 4;
+    const RIGHT_TRIGGER: u8 = 5;
+
+    pub struct Joystick {
+        file: Option<File>, // None once the pad was unplugged
+        state: GamepadState,
+    }
+
+    impl Joystick {
+        pub fn open(path: &str) -> Option<Joystick> {
+            let file = OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(path)
+                .ok()?;
+            info!("Using the gamepad at {}", path);
+            Some(Joystick {
+                file: Some(file),
+                state: GamepadState::default(),
+            })
+        }
+    }
+
+    impl Gamepad for Joystick {
+        fn poll(&mut self) -> GamepadState {
+            let mut event = [0; EVENT_SIZE];
+            while let Some(file) = &mut self.file {
+                match file.read_exact(&mut event) {
+                    Ok(()) => apply(&mut self.state, &event),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        warn!("Lost the gamepad: {}", e);
+                        self.file = None;
+                        self.state = GamepadState::default();
+                    }
+                }
+            }
+            self.state
+        }
+    }
+
+    fn apply(state: &mut GamepadState, event: &[u8; EVENT_SIZE]) {
+        let value = i16::from_le_bytes([event[4], event[5]]) as f32 / i16::MAX as f32;
+        let value = value.clamp(-1.0, 1.0);
+        if event[6] & !EVENT_INIT != EVENT_AXIS {
+            return;
+        }
+        match event[7] {
+            LEFT_X => state.left_stick.0 = value,
+            LEFT_Y => state.left_stick.1 = -value,
+            RIGHT_X => state.right_stick.0 = value,
+            RIGHT_Y => state.right_stick.1 = -value,
+            LEFT_TRIGGER => state.left_trigger = (value + 1.0) * 0.5,
+            RIGHT_TRIGGER => state.right_trigger = (value + 1.0) * 0.5,
+            _ => {}
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn event(kind: u8, number: u8, value: i16) -> [u8; EVENT_SIZE] {
+            let value = value.to_le_bytes();
+            [0, 0, 0, 0, value[0], value[1], kind, number]
+        }
+
+        #[test]
+        fn axis_events_move_the_sticks_and_triggers() {
+            let mut state = GamepadState::default();
+            apply(
+                &mut state,
+                &event(EVENT_AXIS | EVENT_INIT, LEFT_TRIGGER, i16::MIN),
+            );
+            assert_eq!(state.left_trigger, 0.0);
+            apply(&mut state, &event(EVENT_AXIS, RIGHT_Y, i16::MIN));
+            apply(&mut state, &event(EVENT_AXIS, LEFT_X, i16::MAX));
+            apply(&mut state, &event(EVENT_AXIS, RIGHT_TRIGGER, i16::MAX));
+            assert_eq!(state.right_stick, (0.0, 1.0)); // Pushed forwards
+            assert_eq!(state.left_stick, (1.0, 0.0));
+            assert_eq!(state.right_trigger, 1.0);
+
+            // Buttons are left alone
+            let before = state;
+            apply(&mut state, &event(0x01, LEFT_X, 1));
+            assert_eq!(state, before);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sticks_are_centered_within_the_deadzone_and_rescaled_beyond_it() {
+        let state = GamepadState {
+            left_stick: (0.1, -0.1),
+            right_stick: (0.0, 0.6),
+            left_trigger: 0.5,
+            right_trigger: 0.0,
+        };
+        let state = state.with_deadzone(0.2);
+        assert_eq!(state.left_stick, (0.0, 0.0));
+        assert!((state.right_stick.1 - 0.5).abs() < 1e-6);
+        assert_eq!(state.left_trigger, 0.5);
+        let full = GamepadState {
+            right_stick: (1.0, 1.0),
+            ..GamepadState::default()
+        };
+        let (x, y) = full.with_deadzone(0.2).right_stick;
+        assert!(((x * x + y * y).sqrt() - 1.0).abs() < 1e-6);
+    }
+}
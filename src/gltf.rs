@@ -0,0 +1,949 @@
+// Skeletons and their animations read from glTF 2.0 files, for rigged models made in e.g. Blender.
+//
+// `load` reads the first skin of a .gltf or .glb file as a `Skeleton`, and every animation in the
+// file as a `SkeletalAnimation` of it. The nodes above the skin's joints, like the armature object
+// Blender exports, become joints as well, so the skeleton is placed the way the file places it.
+// Joints are put in an order with parents first, see `Rig::skin_joints` for where each joint of the
+// skin ended up. Buffers are read from files next to the .gltf, from base64 data URIs or from the
+// binary chunk of a .glb. Meshes, materials, sparse accessors, accessors without a buffer view and
+// morph target weights are left out.
+//
+// The keys of STEP samplers are held until the next, and CUBICSPLINE samplers are played through
+// their values as `Interpolation::Cubic` tracks, leaving out their tangents.
+use crate::error::AnimationError;
+use crate::skeleton::{Joint, SkeletalAnimation, Skeleton, MAX_JOINTS};
+use crate::toolbox::{
+    self, AnimationClip, Channel, Interpolation, Keyframe, Looping, Track, Transform,
+};
+use std::fs;
+use std::path::Path;
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const GLB_JSON_CHUNK: u32 = 0x4e4f_534a;
+const GLB_BIN_CHUNK: u32 = 0x004e_4942;
+
+// Accessor component types, as in OpenGL
+const FLOAT: usize = 5126;
+const BYTE: usize = 5120;
+const UNSIGNED_BYTE: usize = 5121;
+const SHORT: usize = 5122;
+const UNSIGNED_SHORT: usize = 5123;
+
+// The largest integer a JSON number holds exactly, beyond which counts and offsets make no sense
+const MAX_INTEGER: f64 = 9_007_199_254_740_991.0;
+// Arrays and objects nested deeper than this are taken as malformed rather than parsed until the
+// stack runs out. glTF documents nest a handful deep.
+const MAX_NESTING: usize = 64;
+
+pub struct Rig {
+    pub skeleton: Skeleton,
+    // The joint of the skeleton for each joint of the skin, which the JOINTS_0 attribute of the
+    // skinned meshes refers to
+    pub skin_joints: Vec<usize>,
+    pub animations: Vec<SkeletalAnimation>,
+}
+
+impl Rig {
+    pub fn animation(&self, name: &str) -> Option<&SkeletalAnimation> {
+        self.animations
+            .iter()
+            .find(|animation| animation.name == name)
+    }
+}
+
+pub fn load(path: &Path) -> Result<Rig, AnimationError> {
+    let bytes = fs::read(path).map_err(|source| AnimationError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let invalid = |message: String| AnimationError::Invalid {
+        path: path.to_path_buf(),
+        message,
+    };
+    let (json, binary) = if bytes.starts_with(GLB_MAGIC) {
+        split_glb(&bytes).map_err(invalid)?
+    } else {
+        let json = std::str::from_utf8(&bytes).map_err(|_| invalid("not UTF-8".to_string()))?;
+        (json, None)
+    };
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    read_rig(json, binary, directory).map_err(invalid)
+}
+
+// The JSON and the binary chunk of a .glb
+fn split_glb(bytes: &[u8]) -> Result<(&str, Option<&[u8]>), String> {
+    let word = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+    };
+    if word(4) != Some(2) {
+        return Err("only glTF 2.0 is supported".to_string());
+    }
+    let (mut json, mut binary) = (None, None);
+    let mut at = 12;
+    while let (Some(length), Some(kind)) = (word(at), word(at + 4)) {
+        let chunk = bytes
+            .get(at + 8..at + 8 + length as usize)
+            .ok_or("a chunk runs past the end of the file")?;
+        match kind {
+            GLB_JSON_CHUNK => json = Some(chunk),
+            GLB_BIN_CHUNK => binary = Some(chunk),
+            _ => {}
+        }
+        at += 8 + length as usize;
+    }
+    let json = std::str::from_utf8(json.ok_or("no JSON chunk")?)
+        .map_err(|_| "the JSON chunk is not UTF-8")?;
+    Ok((json, binary))
+}
+
+fn read_rig(json: &str, binary: Option<&[u8]>, directory: &Path) -> Result<Rig, String> {
+    let document = Json::parse(json)?;
+    let buffers = read_buffers(&document, binary, directory)?;
+    let nodes = document.get("nodes").as_array();
+    let mut parents = vec![None; nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        for child in node.get("children").as_array() {
+            let child = child
+                .as_usize()
+                .filter(|&child| child < nodes.len())
+                .ok_or_else(|| format!("node {} has a child that doesn't exist", i))?;
+            parents[child] = Some(i);
+        }
+    }
+    let depth = |mut node: usize| -> Result<usize, String> {
+        let mut depth = 0;
+        while let Some(parent) = parents[node] {
+            node = parent;
+            depth += 1;
+            if depth > nodes.len() {
+                return Err("the nodes are their own ancestors".to_string());
+            }
+        }
+        Ok(depth)
+    };
+
+    let skin = document.get("skins").index(0);
+    let skin_nodes = skin
+        .get("joints")
+        .as_array()
+        .iter()
+        .map(|joint| joint.as_usize().filter(|&joint| joint < nodes.len()))
+        .collect::<Option<Vec<usize>>>()
+        .ok_or("the skin has a joint that doesn't exist")?;
+    if skin_nodes.is_empty() {
+        return Err("there is no skin with joints".to_string());
+    }
+
+    // The skin's joints and the nodes above them, parents first
+    let mut included = vec![false; nodes.len()];
+    for &joint in &skin_nodes {
+        let mut node = Some(joint);
+        while let Some(i) = node.filter(|&i| !included[i]) {
+            included[i] = true;
+            node = parents[i];
+        }
+    }
+    let mut order = vec![];
+    for node in (0..nodes.len()).filter(|&node| included[node]) {
+        order.push((depth(node)?, node));
+    }
+    order.sort();
+    if order.len() > MAX_JOINTS {
+        return Err(format!(
+            "the skin has {} joints with the nodes above them, but at most {} are supported",
+            order.len(),
+            MAX_JOINTS
+        ));
+    }
+    let mut joint_of_node = vec![None; nodes.len()];
+    for (joint, &(_, node)) in order.iter().enumerate() {
+        joint_of_node[node] = Some(joint);
+    }
+
+    let mut joints: Vec<Joint> = order
+        .iter()
+        .map(|&(_, node)| {
+            let name = nodes[node]
+                .get("name")
+                .as_str()
+                .map_or_else(|| format!("node {}", node), str::to_string);
+            let parent = parents[node].and_then(|parent| joint_of_node[parent]);
+            Joint::new(&name, parent, node_transform(&nodes[node]))
+        })
+        .collect();
+    let skin_joints: Vec<usize> = skin_nodes
+        .iter()
+        .filter_map(|&node| joint_of_node[node])
+        .collect();
+    // Without inverse bind matrices they are identities, which `Joint::new` starts out with
+    if let Some(accessor) = skin.get("inverseBindMatrices").as_usize() {
+        let matrices = read_accessor(&document, &buffers, accessor, 16)?;
+        for (&joint, matrix) in skin_joints.iter().zip(matrices.chunks(16)) {
+            joints[joint].inverse_bind = glm::make_mat4(matrix);
+        }
+    }
+
+    let mut animations = vec![];
+    for (i, animation) in document.get("animations").as_array().iter().enumerate() {
+        let name = animation
+            .get("name")
+            .as_str()
+            .map_or_else(|| format!("animation {}", i), str::to_string);
+        let samplers = animation.get("samplers").as_array();
+        let mut clips: Vec<(usize, Vec<Channel>)> = vec![];
+        let mut duration: f32 = 0.0;
+        for channel in animation.get("channels").as_array() {
+            let target = channel.get("target");
+            // Channels animating nodes outside the skeleton, or morph target weights, are skipped
+            let joint = match target
+                .get("node")
+                .as_usize()
+                .and_then(|node| joint_of_node.get(node))
+            {
+                Some(&Some(joint)) => joint,
+                _ => continue,
+            };
+            let components = match target.get("path").as_str() {
+                Some("translation") | Some("scale") => 3,
+                Some("rotation") => 4,
+                _ => continue,
+            };
+            let sampler = channel
+                .get("sampler")
+                .as_usize()
+                .and_then(|sampler| samplers.get(sampler))
+                .ok_or_else(|| format!("{} has a channel without a sampler", name))?;
+            let accessor = |key: &str| {
+                sampler
+                    .get(key)
+                    .as_usize()
+                    .ok_or_else(|| format!("{} has a sampler without an {}", name, key))
+            };
+            let times = read_accessor(&document, &buffers, accessor("input")?, 1)?;
+            let values = read_accessor(&document, &buffers, accessor("output")?, components)?;
+            let interpolation = sampler.get("interpolation").as_str().unwrap_or("LINEAR");
+            let mut values: Vec<&[f32]> = values.chunks(components).collect();
+            if interpolation == "CUBICSPLINE" {
+                // Each key has an in-tangent, a value and an out-tangent
+                values = values.iter().skip(1).step_by(3).copied().collect();
+            }
+            if times.is_empty() || times.len() != values.len() {
+                return Err(format!(
+                    "{} has a sampler with {} keys and {} values",
+                    name,
+                    times.len(),
+                    values.len()
+                ));
+            }
+            duration = duration.max(times[times.len() - 1]);
+
+            let vector = |value: &&[f32]| glm::vec3(value[0], value[1], value[2]);
+            let channel = match target.get("path").as_str() {
+                Some("translation") => {
+                    Channel::Position(track(interpolation, &times, values.iter().map(vector)))
+                }
+                Some("scale") => {
+                    Channel::Scale(track(interpolation, &times, values.iter().map(vector)))
+                }
+                _ => {
+                    let quat = |value: &&[f32]| glm::quat(value[0], value[1], value[2], value[3]);
+                    Channel::Orientation(track(interpolation, &times, values.iter().map(quat)))
+                }
+            };
+            match clips
+                .iter_mut()
+                .find(|(clip_joint, _)| *clip_joint == joint)
+            {
+                Some((_, channels)) => channels.push(channel),
+                None => clips.push((joint, vec![channel])),
+            }
+        }
+        // The clips of an animation last as long as its longest channel, so they loop together
+        let mut skeletal_animation = SkeletalAnimation::new(&name);
+        for (joint, channels) in clips {
+            let mut clip = AnimationClip::new(duration, Looping::Repeat);
+            clip.channels = channels;
+            skeletal_animation = skeletal_animation.with_joint(joint, clip);
+        }
+        animations.push(skeletal_animation);
+    }
+
+    Ok(Rig {
+        skeleton: Skeleton::new(joints),
+        skin_joints,
+        animations,
+    })
+}
+
+// The keys of a sampler, with STEP keys held until the next
+fn track<T: Keyframe>(
+    interpolation: &str,
+    times: &[f32],
+    values: impl Iterator<Item = T>,
+) -> Track<T> {
+    let mut keys: Vec<(f32, T)> = vec![];
+    for (&time, value) in times.iter().zip(values) {
+        if interpolation == "STEP" {
+            if let Some(&(_, held)) = keys.last() {
+                keys.push((time, held));
+            }
+        }
+        keys.push((time, value));
+    }
+    let interpolation = if interpolation == "CUBICSPLINE" {
+        Interpolation::Cubic
+    } else {
+        Interpolation::Linear
+    };
+    Track::new(interpolation, keys)
+}
+
+// Where a node is relative to its parent, given as a matrix or as its translation, rotation and
+// scale
+fn node_transform(node: &Json) -> Transform {
+    let numbers = |key: &str| -> Vec<f32> {
+        node.get(key)
+            .as_array()
+            .iter()
+            .filter_map(|number| number.as_f64())
+            .map(|number| number as f32)
+            .collect()
+    };
+    let mut transform = Transform::default();
+    let matrix = numbers("matrix");
+    if matrix.len() == 16 {
+        let matrix = glm::make_mat4(&matrix);
+        let columns: Vec<glm::Vec3> = (0..3).map(|i| matrix.column(i).xyz()).collect();
+        transform.position = matrix.column(3).xyz();
+        transform.scale = glm::vec3(
+            glm::length(&columns[0]),
+            glm::length(&columns[1]),
+            glm::length(&columns[2]),
+        );
+        let rotation = glm::Mat3::from_columns(&[
+            columns[0] / transform.scale.x,
+            columns[1] / transform.scale.y,
+            columns[2] / transform.scale.z,
+        ]);
+        transform.rotation = toolbox::euler_angles(&glm::mat3_to_quat(&rotation));
+        return transform;
+    }
+    if let [x, y, z] = numbers("translation")[..] {
+        transform.position = glm::vec3(x, y, z);
+    }
+    if let [x, y, z, w] = numbers("rotation")[..] {
+        transform.rotation = toolbox::euler_angles(&glm::quat(x, y, z, w));
+    }
+    if let [x, y, z] = numbers("scale")[..] {
+        transform.scale = glm::vec3(x, y, z);
+    }
+    transform
+}
+
+fn read_buffers(
+    document: &Json,
+    binary: Option<&[u8]>,
+    directory: &Path,
+) -> Result<Vec<Vec<u8>>, String> {
+    let mut buffers = vec![];
+    for (i, buffer) in document.get("buffers").as_array().iter().enumerate() {
+        let data = match buffer.get("uri").as_str() {
+            Some(uri) if uri.starts_with("data:") => {
+                let (_, data) = uri
+                    .split_once(";base64,")
+                    .ok_or_else(|| format!("buffer {} is a data URI without base64", i))?;
+                decode_base64(data)?
+            }
+            Some(uri) => {
+                let path = directory.join(uri);
+                fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?
+            }
+            // The first buffer of a .glb is its binary chunk
+            None if i == 0 => binary
+                .ok_or("buffer 0 has no URI and there is no binary chunk")?
+                .to_vec(),
+            None => return Err(format!("buffer {} has no URI", i)),
+        };
+        buffers.push(data);
+    }
+    Ok(buffers)
+}
+
+// The elements of accessor `index` as floats, `components` of them each. Integer components have
+// to be normalized. Nothing is allocated before the elements are known to fit in their buffer, so
+// a malformed count can't run off with the memory.
+fn read_accessor(
+    document: &Json,
+    buffers: &[Vec<u8>],
+    index: usize,
+    components: usize,
+) -> Result<Vec<f32>, String> {
+    let accessor = document.get("accessors").index(index);
+    let invalid = |what: &str| format!("accessor {} {}", index, what);
+    let count = accessor
+        .get("count")
+        .as_usize()
+        .ok_or_else(|| invalid("has no count"))?;
+    let found = match accessor.get("type").as_str() {
+        Some("SCALAR") => 1,
+        Some("VEC2") => 2,
+        Some("VEC3") => 3,
+        Some("VEC4") => 4,
+        Some("MAT4") => 16,
+        _ => 0,
+    };
+    if found != components {
+        return Err(invalid(&format!("should have {} components", components)));
+    }
+    if accessor.get("sparse") != &Json::Null {
+        return Err(invalid("is sparse, which is not supported"));
+    }
+    let normalized = accessor.get("normalized") == &Json::Bool(true);
+    let (size, read): (usize, fn(&[u8]) -> f32) = match accessor.get("componentType").as_usize() {
+        Some(FLOAT) => (4, |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        Some(BYTE) if normalized => (1, |b| (b[0] as i8 as f32 / 127.0).max(-1.0)),
+        Some(UNSIGNED_BYTE) if normalized => (1, |b| b[0] as f32 / 255.0),
+        Some(SHORT) if normalized => (2, |b| {
+            (i16::from_le_bytes([b[0], b[1]]) as f32 / 32767.0).max(-1.0)
+        }),
+        Some(UNSIGNED_SHORT) if normalized => {
+            (2, |b| u16::from_le_bytes([b[0], b[1]]) as f32 / 65535.0)
+        }
+        _ => return Err(invalid("is neither float nor normalized")),
+    };
+    // Accessors without a buffer view are zeros, only of use with sparse values laid over them
+    let view = accessor
+        .get("bufferView")
+        .as_usize()
+        .map(|view| document.get("bufferViews").index(view))
+        .ok_or_else(|| invalid("has no buffer view, which is not supported"))?;
+    let buffer = view
+        .get("buffer")
+        .as_usize()
+        .and_then(|buffer| buffers.get(buffer))
+        .ok_or_else(|| invalid("has a buffer view without a buffer"))?;
+    let element_size = size * components;
+    let stride = view.get("byteStride").as_usize().unwrap_or(element_size);
+    if stride < element_size {
+        return Err(invalid("has elements overlapping in its buffer view"));
+    }
+    if count == 0 {
+        return Ok(vec![]);
+    }
+    let view_start = view.get("byteOffset").as_usize().unwrap_or(0);
+    let view_end = view_start.checked_add(view.get("byteLength").as_usize().unwrap_or(0));
+    let start = view_start.checked_add(accessor.get("byteOffset").as_usize().unwrap_or(0));
+    // Every element takes at least `element_size` bytes, so ending within the buffer bounds the
+    // count by its length
+    let end = start.and_then(|start| {
+        (stride.checked_mul(count - 1)?)
+            .checked_add(start)?
+            .checked_add(element_size)
+    });
+    let start = match (start, end, view_end) {
+        (Some(start), Some(end), Some(view_end)) if end <= view_end && end <= buffer.len() => start,
+        _ => return Err(invalid("runs past the end of its buffer view")),
+    };
+    let mut values = Vec::with_capacity(count * components);
+    for element in 0..count {
+        let element_start = start + element * stride;
+        for component in 0..components {
+            let at = element_start + component * size;
+            values.push(read(&buffer[at..at + size]));
+        }
+    }
+    Ok(values)
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut bit_count) = (0u32, 0);
+    for c in text.bytes().filter(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(format!("`{}` in base64 data", c as char)),
+        };
+        bits = (bits << 6 | value as u32) & 0xff_ffff;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+// Just enough JSON for glTF documents
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+static NULL: Json = Json::Null;
+
+impl Json {
+    fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = JsonParser {
+            text: text.as_bytes(),
+            at: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.at < parser.text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    // The member called `key`, or null without one, so missing properties can be looked into
+    // further without checking every step
+    fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map_or(&NULL, |(_, value)| value),
+            _ => &NULL,
+        }
+    }
+
+    fn index(&self, index: usize) -> &Json {
+        self.as_array().get(index).unwrap_or(&NULL)
+    }
+
+    // Empty if not an array
+    fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    fn as_usize(&self) -> Option<usize> {
+        self.as_f64()
+            .filter(|number| (0.0..=MAX_INTEGER).contains(number) && number.fract() == 0.0)
+            .map(|number| number as usize)
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    text: &'a [u8],
+    at: usize,
+    depth: usize, // Of the arrays and objects being parsed
+}
+
+impl JsonParser<'_> {
+    fn error(&self, what: &str) -> String {
+        format!("{} at byte {} of the JSON", what, self.at)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.text.get(self.at), Some(b' ' | b'\n' | b'\r' | b'\t')) {
+            self.at += 1;
+        }
+    }
+
+    // Skip `byte` if it is next
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let next = self.text.get(self.at) == Some(&byte);
+        if next {
+            self.at += 1;
+        }
+        next
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.text.get(self.at) {
+            Some(b'{' | b'[') => {
+                if self.depth == MAX_NESTING {
+                    return Err(self.error("nested too deep"));
+                }
+                self.depth += 1;
+                let container = self.container();
+                self.depth -= 1;
+                container
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.at;
+                while matches!(
+                    self.text.get(self.at),
+                    Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+                ) {
+                    self.at += 1;
+                }
+                std::str::from_utf8(&self.text[start..self.at])
+                    .ok()
+                    .and_then(|number| number.parse().ok())
+                    .map(Json::Number)
+                    .ok_or_else(|| self.error("malformed number"))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    // The object or array whose opening brace or bracket is next
+    fn container(&mut self) -> Result<Json, String> {
+        if self.text[self.at] == b'{' {
+            self.at += 1;
+            let mut members = vec![];
+            if self.eat(b'}') {
+                return Ok(Json::Object(members));
+            }
+            loop {
+                self.skip_whitespace();
+                let key = self.string()?;
+                if !self.eat(b':') {
+                    return Err(self.error("expected `:`"));
+                }
+                members.push((key, self.value()?));
+                if self.eat(b'}') {
+                    return Ok(Json::Object(members));
+                }
+                if !self.eat(b',') {
+                    return Err(self.error("expected `,` or `}`"));
+                }
+            }
+        }
+        self.at += 1;
+        let mut items = vec![];
+        if self.eat(b']') {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            if self.eat(b']') {
+                return Ok(Json::Array(items));
+            }
+            if !self.eat(b',') {
+                return Err(self.error("expected `,` or `]`"));
+            }
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if self.text[self.at..].starts_with(word.as_bytes()) {
+            self.at += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("expected a value"))
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.text.get(self.at) != Some(&b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.at += 1;
+        let mut string = String::new();
+        loop {
+            // The text came from a `str`, so it can be split at quotes and backslashes
+            let start = self.at;
+            while !matches!(self.text.get(self.at), Some(b'"' | b'\\') | None) {
+                self.at += 1;
+            }
+            string.push_str(&String::from_utf8_lossy(&self.text[start..self.at]));
+            match self.text.get(self.at) {
+                Some(b'"') => {
+                    self.at += 1;
+                    return Ok(string);
+                }
+                Some(_) => {
+                    let escaped = self.text.get(self.at + 1).copied();
+                    self.at += 2;
+                    string.push(match escaped {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.error("unknown escape")),
+                    });
+                }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    // The character of a `\u` escape whose four hex digits are next, and of the low surrogate
+    // escape after them if they are a high surrogate
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let mut code = self.hex()?;
+        if (0xd800..0xdc00).contains(&code) && self.text[self.at..].starts_with(b"\\u") {
+            self.at += 2;
+            let low = self.hex()?;
+            code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+        }
+        Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.at..self.at + 4)
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("malformed \\u escape"))?;
+        self.at += 4;
+        Ok(digits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_4;
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        let alphabet = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in bytes.chunks(3) {
+            let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+                bits | (byte as u32) << (16 - 8 * i)
+            });
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    text.push(alphabet[(bits >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    text.push('=');
+                }
+            }
+        }
+        text
+    }
+
+    // An armature lifted 1 up, with an arm above it and a hand at the end of the arm, listed hand
+    // first in the skin. The arm turns 90° about Z over a second, and the hand doubles in size
+    // halfway through it.
+    fn arm(uri: Option<&str>) -> (String, Vec<u8>) {
+        let floats: Vec<f32> = [
+            // Inverse bind matrices of the hand at (0, 3, 0) and of the arm at (0, 1, 0)
+            &[
+                1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, -3.0, 0.0, 1.0,
+            ][..],
+            &[
+                1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, -1.0, 0.0, 1.0,
+            ],
+            &[0.0, 1.0], // Turning
+            &[
+                0.0,
+                0.0,
+                0.0,
+                1.0,
+                0.0,
+                0.0,
+                FRAC_PI_4.sin(),
+                FRAC_PI_4.cos(),
+            ],
+            &[0.0, 0.5], // Growing
+            &[1.0, 1.0, 1.0, 2.0, 2.0, 2.0],
+        ]
+        .concat();
+        let bytes: Vec<u8> = floats.iter().flat_map(|f| f.to_le_bytes()).collect();
+        let uri = uri.map_or(String::new(), |uri| format!(r#""uri": "{}","#, uri));
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "nodes": [
+                    {{"name": "Armature", "translation": [0, 1, 0], "children": [2]}},
+                    {{"name": "hand", "translation": [0, 2, 0]}},
+                    {{"name": "arm", "children": [1]}}
+                ],
+                "skins": [{{"joints": [1, 2], "inverseBindMatrices": 0}}],
+                "buffers": [{{{} "byteLength": {}}}],
+                "bufferViews": [{{"buffer": 0, "byteLength": {}}}],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 2, "type": "MAT4"}},
+                    {{"bufferView": 0, "byteOffset": 128, "componentType": 5126,
+                     "count": 2, "type": "SCALAR"}},
+                    {{"bufferView": 0, "byteOffset": 136, "componentType": 5126,
+                     "count": 2, "type": "VEC4"}},
+                    {{"bufferView": 0, "byteOffset": 168, "componentType": 5126,
+                     "count": 2, "type": "SCALAR"}},
+                    {{"bufferView": 0, "byteOffset": 176, "componentType": 5126,
+                     "count": 2, "type": "VEC3"}}
+                ],
+                "animations": [{{
+                    "name": "wave",
+                    "samplers": [
+                        {{"input": 1, "output": 2}},
+                        {{"input": 3, "output": 4, "interpolation": "STEP"}}
+                    ],
+                    "channels": [
+                        {{"sampler": 0, "target": {{"node": 2, "path": "rotation"}}}},
+                        {{"sampler": 1, "target": {{"node": 1, "path": "scale"}}}},
+                        {{"sampler": 0, "target": {{"node": 1, "path": "weights"}}}}
+                    ]
+                }}]
+            }}"#,
+            uri,
+            bytes.len(),
+            bytes.len()
+        );
+        (json, bytes)
+    }
+
+    fn check_arm(rig: &Rig) {
+        let skeleton = &rig.skeleton;
+        let names: Vec<&str> = skeleton
+            .joints
+            .iter()
+            .map(|joint| joint.name.as_str())
+            .collect();
+        assert_eq!(names, ["Armature", "arm", "hand"]);
+        assert_eq!(rig.skin_joints, [2, 1]);
+        // Modelled in the rest pose
+        let matrices = skeleton.joint_matrices(&skeleton.rest_pose());
+        for &joint in &rig.skin_joints {
+            assert!((matrices[joint] - glm::Mat4::identity()).abs().max() < 1e-5);
+        }
+
+        let wave = rig.animation("wave").unwrap();
+        assert_eq!(wave.duration(), 1.0);
+        assert_eq!(wave.channels.len(), 2);
+        let pose = wave.sample(skeleton, 0.25);
+        assert!((pose[1].rotation.z - FRAC_PI_4 / 2.0).abs() < 1e-5);
+        assert_eq!(pose[2].scale, glm::vec3(1.0, 1.0, 1.0));
+        assert_eq!(pose[2].position, glm::vec3(0.0, 2.0, 0.0));
+        let pose = wave.sample(skeleton, 0.75);
+        assert!((pose[1].rotation.z - 3.0 * FRAC_PI_4 / 2.0).abs() < 1e-5);
+        assert_eq!(pose[2].scale, glm::vec3(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn skins_and_their_animations_are_read_from_gltf_files() {
+        let (_, bytes) = arm(None);
+        let uri = format!(
+            "data:application/octet-stream;base64,{}",
+            encode_base64(&bytes)
+        );
+        let (json, _) = arm(Some(&uri));
+        check_arm(&read_rig(&json, None, Path::new("")).unwrap());
+
+        let error = read_rig(r#"{"nodes": [{}]}"#, None, Path::new("")).err();
+        assert_eq!(error.as_deref(), Some("there is no skin with joints"));
+    }
+
+    #[test]
+    fn malformed_accessors_are_errors() {
+        let (_, bytes) = arm(None);
+        let uri = format!(
+            "data:application/octet-stream;base64,{}",
+            encode_base64(&bytes)
+        );
+        let (json, _) = arm(Some(&uri));
+        let matrices = r#"{"bufferView": 0, "componentType": 5126, "count": 2, "type": "MAT4"}"#;
+        let read = |accessor: &str| {
+            let json = json.replacen(matrices, accessor, 1);
+            read_rig(&json, None, Path::new("")).err().unwrap()
+        };
+        for count in ["1e30", "4611686018427387904", "9007199254740991"].iter() {
+            let accessor = matrices.replace("\"count\": 2", &format!("\"count\": {}", count));
+            assert!(read(&accessor).starts_with("accessor 0"), "{}", count);
+        }
+        let unviewed = matrices.replace("\"bufferView\": 0, ", "");
+        assert_eq!(
+            read(&unviewed),
+            "accessor 0 has no buffer view, which is not supported"
+        );
+        let json = json.replacen("\"buffer\": 0,", "\"buffer\": 0, \"byteStride\": 0,", 1);
+        let error = read_rig(&json, None, Path::new("")).err();
+        assert_eq!(
+            error.as_deref(),
+            Some("accessor 0 has elements overlapping in its buffer view")
+        );
+    }
+
+    #[test]
+    fn glb_files_are_read_with_their_binary_chunk() {
+        let (mut json, mut bytes) = arm(None);
+        while json.len() % 4 != 0 {
+            json.push(' ');
+        }
+        bytes.resize(bytes.len().div_ceil(4) * 4, 0);
+        let mut glb = vec![];
+        let length = 12 + 8 + json.len() + 8 + bytes.len();
+        for word in [
+            0x4654_6c67,
+            2,
+            length as u32,
+            json.len() as u32,
+            GLB_JSON_CHUNK,
+        ]
+        .iter()
+        {
+            glb.extend_from_slice(&word.to_le_bytes());
+        }
+        glb.extend_from_slice(json.as_bytes());
+        glb.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&GLB_BIN_CHUNK.to_le_bytes());
+        glb.extend_from_slice(&bytes);
+
+        let path = std::env::temp_dir().join(format!("gloom-arm-{}.glb", std::process::id()));
+        fs::write(&path, &glb).unwrap();
+        let rig = load(&path);
+        fs::remove_file(&path).unwrap();
+        check_arm(&rig.unwrap());
+
+        let missing = load(Path::new("missing.glb")).err().unwrap();
+        assert!(matches!(missing, AnimationError::Read { .. }));
+    }
+
+    #[test]
+    fn json_is_parsed_with_its_escapes() {
+        let text = r#" {"a": [1, -2.5e1, true, null], "bå": "\"\n🚁\ud83d\ude81\u00e5" } "#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(
+            json.get("a").as_array(),
+            [
+                Json::Number(1.0),
+                Json::Number(-25.0),
+                Json::Bool(true),
+                Json::Null
+            ]
+        );
+        assert_eq!(json.get("bå").as_str(), Some("\"\n🚁🚁å"));
+        assert_eq!(json.get("c").index(3), &Json::Null);
+        let nested = "[".repeat(100_000);
+        for malformed in ["", "[1,]", "{\"a\" 1}", "\"open", "[1] 2", "tru", &nested].iter() {
+            assert!(Json::parse(malformed).is_err(), "{}", malformed);
+        }
+        assert!(Json::parse(&format!("{}{}", "[".repeat(64), "]".repeat(64))).is_ok());
+    }
+}
@@ -0,0 +1,501 @@
+use log::warn;
+use std::sync::atomic::{AtomicU64, Ordering};
+use winit::event::MouseButton;
+use winit::keyboard::KeyCode;
+use winit::window::{CursorGrabMode, Window};
+
+// Keyboard or mouse button state, built up by the render thread from input events.
+//
+// `held` contains every button that is currently down, while `pressed` and `released` only contain
+// the buttons that changed state since the render thread last called `take_frame`. This lets the
+// render thread react to a key exactly once (toggles) as well as every frame (movement).
+#[derive(Clone)]
+pub struct ButtonState<T> {
+    held: Vec<T>,
+    pressed: Vec<T>,
+    released: Vec<T>,
+}
+
+pub type KeyState = ButtonState<KeyCode>;
+pub type MouseButtonState = ButtonState<MouseButton>;
+
+impl<T: Copy + PartialEq> Default for ButtonState<T> {
+    fn default() -> Self {
+        ButtonState::new()
+    }
+}
+
+impl<T: Copy + PartialEq> ButtonState<T> {
+    pub fn new() -> ButtonState<T> {
+        ButtonState {
+            held: Vec::with_capacity(10),
+            pressed: Vec::with_capacity(10),
+            released: Vec::with_capacity(10),
+        }
+    }
+
+    // Called when a key goes down. Key repeat events are ignored.
+    pub fn press(&mut self, key: T) {
+        if !self.held.contains(&key) {
+            self.held.push(key);
+            self.pressed.push(key);
+        }
+    }
+
+    // Called when a key goes up
+    pub fn release(&mut self, key: T) {
+        if let Some(i) = self.held.iter().position(|&k| k == key) {
+            self.held.remove(i);
+            self.released.push(key);
+        }
+    }
+
+    // Copy out the state for the current frame and clear the transitions, so that a key that
+    // was pressed and released between two frames is still seen exactly once.
+    pub fn take_frame(&mut self) -> ButtonState<T> {
+        ButtonState {
+            held: self.held.clone(),
+            pressed: std::mem::take(&mut self.pressed),
+            released: std::mem::take(&mut self.released),
+        }
+    }
+
+    pub fn held(&self) -> &[T] {
+        &self.held
+    }
+
+    pub fn pressed(&self) -> &[T] {
+        &self.pressed
+    }
+
+    pub fn released(&self) -> &[T] {
+        &self.released
+    }
+
+    pub fn is_held(&self, key: T) -> bool {
+        self.held.contains(&key)
+    }
+
+    pub fn just_pressed(&self, key: T) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    pub fn just_released(&self, key: T) -> bool {
+        self.released.contains(&key)
+    }
+
+    // The same state with `key` left out, as if it had never been pressed
+    pub fn without(&self, key: T) -> ButtonState<T> {
+        let others = |keys: &[T]| keys.iter().copied().filter(|&k| k != key).collect();
+        ButtonState {
+            held: others(&self.held),
+            pressed: others(&self.pressed),
+            released: others(&self.released),
+        }
+    }
+}
+
+// Modifier keys, matching either the left or the right variant
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Modifier {
+    Ctrl,
+    Shift,
+    Alt,
+}
+
+impl Modifier {
+    fn keys(self) -> [KeyCode; 2] {
+        match self {
+            Modifier::Ctrl => [KeyCode::ControlLeft, KeyCode::ControlRight],
+            Modifier::Shift => [KeyCode::ShiftLeft, KeyCode::ShiftRight],
+            Modifier::Alt => [KeyCode::AltLeft, KeyCode::AltRight],
+        }
+    }
+}
+
+impl KeyState {
+    pub fn modifier_held(&self, modifier: Modifier) -> bool {
+        modifier.keys().iter().any(|&key| self.is_held(key))
+    }
+
+    // True on the frame `key` is pressed while all of `modifiers` are held, e.g. Ctrl+S
+    pub fn chord(&self, modifiers: &[Modifier], key: KeyCode) -> bool {
+        self.just_pressed(key) && modifiers.iter().all(|&m| self.modifier_held(m))
+    }
+}
+
+// Detects keys pressed twice in quick succession. Call `update` once per frame.
+pub struct DoubleTap {
+    pub max_interval: f32, // seconds allowed between the two presses
+    last_press: Vec<(KeyCode, f32)>,
+    tapped: Vec<KeyCode>,
+}
+
+impl DoubleTap {
+    pub fn new(max_interval: f32) -> DoubleTap {
+        DoubleTap {
+            max_interval,
+            last_press: vec![],
+            tapped: vec![],
+        }
+    }
+
+    pub fn update(&mut self, keys: &KeyState, time: f32) {
+        self.tapped.clear();
+        for &key in keys.pressed() {
+            match self.last_press.iter().position(|&(k, _)| k == key) {
+                Some(i) if time - self.last_press[i].1 <= self.max_interval => {
+                    // A third press starts counting from scratch
+                    self.last_press.remove(i);
+                    self.tapped.push(key);
+                }
+                Some(i) => self.last_press[i].1 = time,
+                None => self.last_press.push((key, time)),
+            }
+        }
+    }
+
+    // True on the frame `key` was pressed for the second time within `max_interval`
+    pub fn double_tapped(&self, key: KeyCode) -> bool {
+        self.tapped.contains(&key)
+    }
+}
+
+// Everything the render thread needs to know about user input for a single frame
+#[derive(Clone, Default)]
+pub struct FrameInput {
+    pub keys: KeyState,
+    pub buttons: MouseButtonState,
+    pub mouse_delta: (f32, f32), // Raw mouse movement since the last frame in device units
+    pub cursor_delta: (f32, f32), // Cursor movement within the window since the last frame in logical pixels
+    pub cursor_position: Option<(f32, f32)>, // Cursor position in physical pixels, if inside the window
+    pub scroll_delta: f32,                   // Scroll wheel movement since the last frame in lines
+    pub pan_delta: (f32, f32), // Two-finger touchpad scrolling since the last frame in logical pixels
+    pub zoom_delta: f32,       // Pinch zoom since the last frame, positive when zooming in
+    pub text: String,          // Characters typed since the last frame
+    pub dropped_files: Vec<std::path::PathBuf>, // Files dropped onto the window since the last frame
+    pub window_size: (u32, u32),                // Framebuffer size in physical pixels
+}
+
+// A single change in user input. The event loop sends these to the render thread over a channel
+// as they happen, and input recordings are replayed as the same events.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    Key(KeyCode, bool),              // true when pressed
+    Button(MouseButton, bool),       // true when pressed
+    Motion(f32, f32),                // Raw mouse movement in device units
+    Cursor(f32, f32),                // Cursor movement within the window in logical pixels
+    Position(Option<(f32, f32)>),    // Cursor position in physical pixels, None when it leaves
+    Scroll(f32),                     // Scroll wheel movement in lines
+    Pan(f32, f32),                   // Two-finger touchpad scrolling in logical pixels
+    Zoom(f32),                       // Pinch zoom, positive when zooming in
+    Char(char),                      // A typed character
+    DroppedFile(std::path::PathBuf), // A file dropped onto the window
+    Resize(u32, u32),                // New framebuffer size in physical pixels
+}
+
+// The framebuffer size and raw mouse movement, shared by the event loop and the render thread
+// without locking. Resizing and fast mouse movement come in storms of events, so instead of queueing
+// every one, the event loop overwrites the size and adds up the movement in place, and the render
+// thread picks up the latest state once per frame with `InputCollector::read_window`.
+pub struct WindowSnapshot {
+    size: AtomicU64,        // Width in the upper half, height in the lower
+    mouse_delta: AtomicU64, // The bits of the x movement in the upper half, y in the lower
+}
+
+impl WindowSnapshot {
+    pub fn new(size: (u32, u32)) -> WindowSnapshot {
+        WindowSnapshot {
+            size: AtomicU64::new(pack(size.0, size.1)),
+            mouse_delta: AtomicU64::new(pack(0.0_f32.to_bits(), 0.0_f32.to_bits())),
+        }
+    }
+
+    pub fn set_size(&self, width: u32, height: u32) {
+        self.size.store(pack(width, height), Ordering::Relaxed);
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        unpack(self.size.load(Ordering::Relaxed))
+    }
+
+    pub fn add_mouse_delta(&self, dx: f32, dy: f32) {
+        let _ = self
+            .mouse_delta
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |delta| {
+                let (x, y) = unpack(delta);
+                let x = f32::from_bits(x) + dx;
+                let y = f32::from_bits(y) + dy;
+                Some(pack(x.to_bits(), y.to_bits()))
+            });
+    }
+
+    // The movement added up since the last call
+    pub fn take_mouse_delta(&self) -> (f32, f32) {
+        let zero = pack(0.0_f32.to_bits(), 0.0_f32.to_bits());
+        let (x, y) = unpack(self.mouse_delta.swap(zero, Ordering::Relaxed));
+        (f32::from_bits(x), f32::from_bits(y))
+    }
+}
+
+fn pack(high: u32, low: u32) -> u64 {
+    (high as u64) << 32 | low as u64
+}
+
+fn unpack(packed: u64) -> (u32, u32) {
+    ((packed >> 32) as u32, packed as u32)
+}
+
+// Folds input events into per-frame snapshots. Events are applied as they arrive, and
+// `take_frame` hands out everything since the previous frame, so the event loop never has to wait
+// for the render thread.
+#[derive(Default)]
+pub struct InputCollector {
+    keys: KeyState,
+    buttons: MouseButtonState,
+    cursor_position: Option<(f32, f32)>,
+    window_size: (u32, u32),
+    frame: FrameInput,
+}
+
+impl InputCollector {
+    pub fn new(window_size: (u32, u32)) -> InputCollector {
+        InputCollector {
+            window_size,
+            ..Default::default()
+        }
+    }
+
+    pub fn apply(&mut self, event: InputEvent) {
+        let frame = &mut self.frame;
+        match event {
+            InputEvent::Key(key, true) => self.keys.press(key),
+            InputEvent::Key(key, false) => self.keys.release(key),
+            InputEvent::Button(button, true) => self.buttons.press(button),
+            InputEvent::Button(button, false) => self.buttons.release(button),
+            InputEvent::Motion(dx, dy) => {
+                frame.mouse_delta.0 += dx;
+                frame.mouse_delta.1 += dy;
+            }
+            InputEvent::Cursor(dx, dy) => {
+                frame.cursor_delta.0 += dx;
+                frame.cursor_delta.1 += dy;
+            }
+            InputEvent::Position(position) => self.cursor_position = position,
+            InputEvent::Scroll(lines) => frame.scroll_delta += lines,
+            InputEvent::Pan(dx, dy) => {
+                frame.pan_delta.0 += dx;
+                frame.pan_delta.1 += dy;
+            }
+            InputEvent::Zoom(zoom) => frame.zoom_delta += zoom,
+            InputEvent::Char(c) => frame.text.push(c),
+            InputEvent::DroppedFile(path) => frame.dropped_files.push(path),
+            InputEvent::Resize(width, height) => self.window_size = (width, height),
+        }
+    }
+
+    // Pick up the latest size and the mouse movement since the last call from the event loop
+    pub fn read_window(&mut self, window: &WindowSnapshot) {
+        let size = window.size();
+        self.apply(InputEvent::Resize(size.0, size.1));
+        let (dx, dy) = window.take_mouse_delta();
+        if dx != 0.0 || dy != 0.0 {
+            self.apply(InputEvent::Motion(dx, dy));
+        }
+    }
+
+    // The latest framebuffer size, also between frames
+    pub fn window_size(&self) -> (u32, u32) {
+        self.window_size
+    }
+
+    // Everything that happened since the last call
+    pub fn take_frame(&mut self) -> FrameInput {
+        let mut input = std::mem::take(&mut self.frame);
+        input.keys = self.keys.take_frame();
+        input.buttons = self.buttons.take_frame();
+        input.cursor_position = self.cursor_position;
+        input.window_size = self.window_size;
+        input
+    }
+}
+
+impl Extend<InputEvent> for InputCollector {
+    fn extend<I: IntoIterator<Item = InputEvent>>(&mut self, events: I) {
+        for event in events {
+            self.apply(event);
+        }
+    }
+}
+
+// A line of text being typed, e.g. into the console or when renaming a node. While active, the
+// render thread should ignore regular key bindings so typing doesn't move the camera.
+#[derive(Default)]
+pub struct TextInput {
+    pub active: bool,
+    pub buffer: String,
+}
+
+impl TextInput {
+    pub fn begin(&mut self, initial: &str) {
+        self.active = true;
+        self.buffer = initial.to_string();
+    }
+
+    pub fn cancel(&mut self) {
+        self.active = false;
+        self.buffer.clear();
+    }
+
+    // Finish typing and return the line
+    pub fn submit(&mut self) -> String {
+        self.active = false;
+        std::mem::take(&mut self.buffer)
+    }
+
+    // Append typed characters. Enter and Escape are handled as keys, so only backspace is
+    // interpreted here and other control characters are dropped.
+    pub fn type_text(&mut self, text: &str) {
+        if !self.active {
+            return;
+        }
+        for c in text.chars() {
+            match c {
+                '\u{8}' | '\u{7f}' => {
+                    self.buffer.pop();
+                }
+                c if c.is_control() => {}
+                c => self.buffer.push(c),
+            }
+        }
+    }
+}
+
+// Turn mouse movement in pixels into a camera rotation in radians using the user's look settings.
+// Every bit of movement counts, however slow.
+pub fn look_delta(raw: (f32, f32), config: &crate::config::Config) -> (f32, f32) {
+    let invert = if config.invert_y { -1.0 } else { 1.0 };
+    let scale = config.look_sensitivity;
+    (raw.0 * scale, raw.1 * scale * invert)
+}
+
+// Radians per second a stick pushed all the way turns the camera at the default look sensitivity
+pub const STICK_LOOK_SPEED: f32 = 2.5;
+
+// Turn a stick's position, with x right and y up, into a camera rotation in radians over a frame
+// of `delta_time` seconds. Sticks turn the camera at a rate rather than by a distance, which the
+// look sensitivity scales relative to its default, so = and - speed up the sticks and the mouse
+// alike.
+pub fn stick_look_delta(
+    stick: (f32, f32),
+    config: &crate::config::Config,
+    delta_time: f32,
+) -> (f32, f32) {
+    let invert = if config.invert_y { -1.0 } else { 1.0 };
+    let sensitivity = config.look_sensitivity / crate::config::Config::default().look_sensitivity;
+    let scale = STICK_LOOK_SPEED * sensitivity * delta_time;
+    (stick.0 * scale, -stick.1 * scale * invert)
+}
+
+// Confine the cursor to the window and hide it, or release it again. Platforms support different
+// grab modes, so fall back to locking the cursor in place if confining it is unsupported. Headless
+// contexts have no window, and so no cursor to capture.
+pub fn set_cursor_captured(window: Option<&Window>, captured: bool) {
+    let window = match window {
+        Some(window) => window,
+        None => return,
+    };
+    if captured {
+        let grabbed = window
+            .set_cursor_grab(CursorGrabMode::Confined)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Locked));
+        if let Err(e) = grabbed {
+            warn!("Failed to grab cursor: {}", e);
+        }
+    } else if let Err(e) = window.set_cursor_grab(CursorGrabMode::None) {
+        warn!("Failed to release cursor: {}", e);
+    }
+    window.set_cursor_visible(!captured);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(keys: &mut KeyState, pressed: &[KeyCode]) -> KeyState {
+        for &key in pressed {
+            keys.press(key);
+        }
+        keys.take_frame()
+    }
+
+    #[test]
+    fn chords_need_the_key_pressed_while_the_modifiers_are_held() {
+        let mut keys = KeyState::new();
+        let ctrl = [Modifier::Ctrl];
+        assert!(!press(&mut keys, &[KeyCode::KeyS]).chord(&ctrl, KeyCode::KeyS));
+        keys.release(KeyCode::KeyS);
+
+        let frame = press(&mut keys, &[KeyCode::ControlRight]);
+        assert!(!frame.chord(&ctrl, KeyCode::KeyS));
+        let frame = press(&mut keys, &[KeyCode::KeyS]);
+        assert!(frame.chord(&ctrl, KeyCode::KeyS));
+        assert!(!frame.chord(&[Modifier::Ctrl, Modifier::Shift], KeyCode::KeyS));
+        // Only on the frame the key went down
+        assert!(!keys.take_frame().chord(&ctrl, KeyCode::KeyS));
+
+        let without = frame.without(KeyCode::KeyS);
+        assert!(!without.is_held(KeyCode::KeyS) && !without.just_pressed(KeyCode::KeyS));
+        assert!(without.modifier_held(Modifier::Ctrl));
+    }
+
+    #[test]
+    fn double_taps_are_two_presses_within_the_interval() {
+        let mut keys = KeyState::new();
+        let mut double_tap = DoubleTap::new(0.3);
+        let mut tap = |time: f32| {
+            let frame = press(&mut keys, &[KeyCode::KeyW]);
+            keys.release(KeyCode::KeyW);
+            double_tap.update(&frame, time);
+            double_tap.double_tapped(KeyCode::KeyW)
+        };
+        assert!(!tap(0.0));
+        assert!(tap(0.2));
+        // A third press starts counting from scratch
+        assert!(!tap(0.3));
+        assert!(tap(0.5));
+        // Too slow, but the slow press counts as the first of the next pair
+        assert!(!tap(1.0));
+        assert!(!tap(2.0));
+        assert!(tap(2.1));
+    }
+
+    #[test]
+    fn double_taps_last_one_frame() {
+        let mut keys = KeyState::new();
+        let mut double_tap = DoubleTap::new(0.3);
+        for time in [0.0, 0.1].iter().copied() {
+            let frame = press(&mut keys, &[KeyCode::KeyW]);
+            keys.release(KeyCode::KeyW);
+            double_tap.update(&frame, time);
+        }
+        assert!(double_tap.double_tapped(KeyCode::KeyW));
+        double_tap.update(&keys.take_frame(), 0.15);
+        assert!(!double_tap.double_tapped(KeyCode::KeyW));
+    }
+
+    #[test]
+    fn look_sensitivity_scales_the_sticks_like_the_mouse() {
+        let mut config = crate::config::Config::default();
+        let mouse = look_delta((10.0, 10.0), &config);
+        let stick = stick_look_delta((1.0, 1.0), &config, 0.5);
+        assert_eq!(stick, (STICK_LOOK_SPEED * 0.5, -STICK_LOOK_SPEED * 0.5));
+
+        config.look_sensitivity *= 2.0;
+        config.invert_y = true;
+        let faster_mouse = look_delta((10.0, 10.0), &config);
+        let faster_stick = stick_look_delta((1.0, 1.0), &config, 0.5);
+        assert_eq!(faster_mouse, (mouse.0 * 2.0, -mouse.1 * 2.0));
+        assert_eq!(faster_stick, (stick.0 * 2.0, -stick.1 * 2.0));
+    }
+}
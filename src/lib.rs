@@ -0,0 +1,67 @@
+// gloom-rs: a small OpenGL framework for scenes built from a scene graph.
+//
+// Implement `app::GloomApp` for your scene and start it with `app::run`, which takes care of the
+// window, input, the render thread and frame timing. `src/demo.rs` is a complete example with
+// helicopters flying over the lunar surface.
+
+// All unsafe functions in the crate are unsafe because they call OpenGL, and need a current
+// context on the calling thread
+#![allow(clippy::missing_safety_doc)]
+
+extern crate nalgebra_glm as glm;
+
+pub mod app;
+pub mod arena;
+pub mod assets;
+pub mod audio;
+pub mod backend;
+pub mod batching;
+pub mod camera;
+pub mod capture;
+pub mod collision;
+pub mod commands;
+pub mod config;
+pub mod console;
+pub mod crash;
+pub mod debug_lines;
+pub mod debug_view;
+pub mod deferred;
+pub mod environment;
+pub mod error;
+pub mod frame_dump;
+pub mod gamepad;
+pub mod gltf;
+pub mod input;
+pub mod lighting;
+pub mod loader;
+pub mod logging;
+pub mod material;
+pub mod mesh;
+pub mod octree;
+pub mod offscreen;
+pub mod overlay;
+pub mod post;
+pub mod quality;
+pub mod reflection;
+pub mod renderer;
+pub mod replay;
+pub mod scene_graph;
+pub mod screenshot;
+pub mod shader;
+pub mod shadow;
+pub mod simulation;
+pub mod skeleton;
+pub mod skybox;
+pub mod ssr;
+pub mod stream_buffer;
+pub mod streaming;
+pub mod text;
+pub mod timing;
+pub mod toolbox;
+pub mod tweaks;
+#[cfg(feature = "egui")]
+pub mod ui;
+pub mod util;
+pub mod window;
+
+pub use app::{run, Context, GloomApp, Settings};
@@ -0,0 +1,340 @@
+// Lights placed in the scene graph, and shading by them.
+//
+// A node gives off light by having a `Light`, and is moved and turned like any other node to aim
+// it. Every frame `Lighting::gather` walks the graph like the renderer does to find where the
+// lights are, and `Lighting::apply` sets them on the simple pipeline, which shades with Phong's
+// model: the surface's color lit by some ambient light, by diffuse light falling off with the
+// angle it hits the surface at, and by specular highlights where it reflects towards the camera.
+// Nodes with a material are shaded physically instead, see `material`. The shader is lit by a
+// single directional light, the first one found, and by up to `MAX_POINT_LIGHTS` point lights.
+// Those are many more values than are worth setting one uniform at a time, so `LightBuffer`
+// uploads them all at once into a uniform buffer the shader reads them from, laid out as
+// `PointLightBlock`. Up to `MAX_SHADOWED_POINT_LIGHTS` of them can cast shadows, into cube maps
+// drawn by `shadow::PointShadowMaps`.
+use crate::backend::gl::GlBackend;
+use crate::backend::PipelineHandle;
+use crate::scene_graph::SceneNode;
+use std::ptr;
+
+// As many point lights as the `PointLights` block in `simple.frag` has room for
+pub const MAX_POINT_LIGHTS: usize = 16;
+// The uniform buffer binding the block is read from, as in its `layout(binding = ...)`
+pub const POINT_LIGHT_BINDING: u32 = 0;
+// As many point lights as there are cube maps for their shadows
+pub const MAX_SHADOWED_POINT_LIGHTS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Light {
+    // From so far away that it shines the same way everywhere, like the sun. It shines down the
+    // node's -Z, so it can be aimed like a camera.
+    Directional {
+        color: glm::Vec3,
+        intensity: f32,
+    },
+    // Shining every way from the node's origin, like a lamp, fading to nothing `range` away.
+    // Shadows are costly for them, as the scene is drawn six more times for each, so only those
+    // that cast them are given any.
+    Point {
+        color: glm::Vec3,
+        intensity: f32,
+        range: f32,
+        casts_shadows: bool,
+    },
+}
+
+// A directional light as it shines in the world
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirectionalLight {
+    pub direction: glm::Vec3, // The way the light travels, normalized
+    pub color: glm::Vec3,     // Times its intensity
+}
+
+// A point light as it shines in the world
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointLight {
+    pub position: glm::Vec3,
+    pub color: glm::Vec3, // Times its intensity
+    pub range: f32,
+    pub casts_shadows: bool,
+}
+
+// What the simple pipeline is lit with in a frame
+#[derive(Clone, Debug, Default)]
+pub struct Lighting {
+    pub directional: Vec<DirectionalLight>, // In the order they are in the scene graph
+    pub points: Vec<PointLight>,            // Likewise, however many there are
+    pub camera_position: glm::Vec3,         // For the specular highlights
+}
+
+// The `PointLights` block of `simple.frag`, laid out by the std140 rules: the count padded to a
+// vec4, then a position and range and a color per light, each a vec4. The color's w is the cube
+// map of the light's shadows, or -1 for none.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointLightBlock {
+    pub count: [i32; 4],
+    pub lights: [[[f32; 4]; 2]; MAX_POINT_LIGHTS],
+}
+
+impl Lighting {
+    // The lights below `root`, seen from a camera at `camera_position`
+    pub fn gather(root: &SceneNode, camera_position: &glm::Vec3) -> Lighting {
+        let mut lighting = Lighting {
+            directional: vec![],
+            points: vec![],
+            camera_position: *camera_position,
+        };
+        lighting.gather_node(root, &glm::identity());
+        lighting
+    }
+
+    fn gather_node(&mut self, node: &SceneNode, transformation_so_far: &glm::Mat4) {
+        let transform = transformation_so_far * node.local_transform();
+        match node.light {
+            Some(Light::Directional { color, intensity }) => {
+                let forward = transform * glm::vec4(0.0, 0.0, -1.0, 0.0);
+                if glm::length(&forward.xyz()) > 1e-6 {
+                    self.directional.push(DirectionalLight {
+                        direction: glm::normalize(&forward.xyz()),
+                        color: color * intensity,
+                    });
+                }
+            }
+            Some(Light::Point {
+                color,
+                intensity,
+                range,
+                casts_shadows,
+            }) => self.points.push(PointLight {
+                position: transform.column(3).xyz(),
+                color: color * intensity,
+                range,
+                casts_shadows,
+            }),
+            None => {}
+        }
+        for &child in &node.children {
+            self.gather_node(unsafe { &*child }, &transform);
+        }
+    }
+
+    // Set the lights on `pipeline`, whose shaders declare `lightDirection`, `lightColor` and
+    // `cameraPosition`. Without a directional light, only the ambient light is left.
+    pub unsafe fn apply(&self, backend: &GlBackend, pipeline: PipelineHandle) {
+        let program = backend.program_id(pipeline);
+        let uniforms = backend.uniforms(pipeline);
+        let location = |name: &str| uniforms.get(name).map_or(-1, |uniform| uniform.location);
+        let sun = self
+            .directional
+            .first()
+            .copied()
+            .unwrap_or(DirectionalLight {
+                direction: glm::vec3(0.0, -1.0, 0.0),
+                color: glm::zero(),
+            });
+        gl::ProgramUniform3fv(
+            program,
+            location("lightDirection"),
+            1,
+            sun.direction.as_ptr(),
+        );
+        gl::ProgramUniform3fv(program, location("lightColor"), 1, sun.color.as_ptr());
+        gl::ProgramUniform3fv(
+            program,
+            location("cameraPosition"),
+            1,
+            self.camera_position.as_ptr(),
+        );
+    }
+
+    // The point lights for the shader, nearest the camera first. When there are more than it has
+    // room for, the furthest are left out, as the nearest light most of what is seen.
+    pub fn nearest_point_lights(&self) -> Vec<PointLight> {
+        let mut points = self.points.clone();
+        let distance = |light: &PointLight| glm::distance2(&light.position, &self.camera_position);
+        points.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+        points.truncate(MAX_POINT_LIGHTS);
+        points
+    }
+
+    // Those of the point lights for the shader that get one of `shadow_maps` cube maps for their
+    // shadows, in the order of the maps
+    pub fn shadowed_point_lights(&self, shadow_maps: usize) -> Vec<PointLight> {
+        self.nearest_point_lights()
+            .into_iter()
+            .filter(|light| light.casts_shadows)
+            .take(shadow_maps.min(MAX_SHADOWED_POINT_LIGHTS))
+            .collect()
+    }
+
+    // The point lights as the shader reads them, with `shadow_maps` cube maps drawn for the
+    // `shadowed_point_lights`
+    pub fn point_light_block(&self, shadow_maps: usize) -> PointLightBlock {
+        let points = self.nearest_point_lights();
+        let mut block = PointLightBlock {
+            count: [points.len() as i32, 0, 0, 0],
+            lights: [[[0.0; 4]; 2]; MAX_POINT_LIGHTS],
+        };
+        let shadow_maps = shadow_maps.min(MAX_SHADOWED_POINT_LIGHTS);
+        let mut next_map = 0;
+        for (slot, light) in block.lights.iter_mut().zip(&points) {
+            let map = if light.casts_shadows && next_map < shadow_maps {
+                next_map += 1;
+                (next_map - 1) as f32
+            } else {
+                -1.0
+            };
+            let (position, color) = (light.position, light.color);
+            *slot = [
+                [position.x, position.y, position.z, light.range],
+                [color.x, color.y, color.z, map],
+            ];
+        }
+        block
+    }
+}
+
+// The uniform buffer the point lights are read from
+pub struct LightBuffer {
+    buffer: u32,
+}
+
+impl LightBuffer {
+    pub unsafe fn new() -> LightBuffer {
+        let mut buffer = 0;
+        gl::GenBuffers(1, &mut buffer);
+        gl::BindBuffer(gl::UNIFORM_BUFFER, buffer);
+        gl::BufferData(
+            gl::UNIFORM_BUFFER,
+            size_of::<PointLightBlock>() as isize,
+            ptr::null(),
+            gl::DYNAMIC_DRAW,
+        );
+        gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        LightBuffer { buffer }
+    }
+
+    // Upload the point lights of `lighting`, `shadow_maps` of which have had their shadows drawn,
+    // and bind them for the draws that follow
+    pub unsafe fn upload(&self, lighting: &Lighting, shadow_maps: usize) {
+        let block = lighting.point_light_block(shadow_maps);
+        gl::BindBuffer(gl::UNIFORM_BUFFER, self.buffer);
+        gl::BufferSubData(
+            gl::UNIFORM_BUFFER,
+            0,
+            size_of::<PointLightBlock>() as isize,
+            &block as *const PointLightBlock as *const _,
+        );
+        gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        gl::BindBufferBase(gl::UNIFORM_BUFFER, POINT_LIGHT_BINDING, self.buffer);
+    }
+
+    pub unsafe fn delete(&mut self) {
+        gl::DeleteBuffers(1, &self.buffer);
+        self.buffer = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn directional_lights_shine_down_their_node_s_negative_z() {
+        let mut root = SceneNode::new();
+        let mut arm = SceneNode::new();
+        let mut sun = SceneNode::new();
+        // Pitched down a quarter turn, so -Z points down
+        arm.rotation = glm::vec3(-FRAC_PI_2, 0.0, 0.0);
+        arm.position = glm::vec3(5.0, 100.0, 0.0);
+        sun.light = Some(Light::Directional {
+            color: glm::vec3(1.0, 0.5, 0.25),
+            intensity: 2.0,
+        });
+        arm.add_child(&sun);
+        root.add_child(&arm);
+
+        let lighting = Lighting::gather(&root, &glm::vec3(1.0, 2.0, 3.0));
+        assert_eq!(lighting.camera_position, glm::vec3(1.0, 2.0, 3.0));
+        assert_eq!(lighting.directional.len(), 1);
+        let light = lighting.directional[0];
+        assert!(glm::distance(&light.direction, &glm::vec3(0.0, -1.0, 0.0)) < 1e-5);
+        assert_eq!(light.color, glm::vec3(2.0, 1.0, 0.5));
+    }
+
+    #[test]
+    fn nodes_without_lights_give_off_none() {
+        let mut root = SceneNode::new();
+        let child = SceneNode::new();
+        root.add_child(&child);
+        let lighting = Lighting::gather(&root, &glm::zero());
+        assert!(lighting.directional.is_empty() && lighting.points.is_empty());
+        assert_eq!(lighting.point_light_block(0).count[0], 0);
+    }
+
+    #[test]
+    fn point_lights_are_where_their_nodes_are() {
+        let mut root = SceneNode::new();
+        let mut helicopter = SceneNode::new();
+        let mut lamp = SceneNode::new();
+        helicopter.position = glm::vec3(10.0, 20.0, 30.0);
+        lamp.position = glm::vec3(0.0, -1.0, 0.0);
+        lamp.light = Some(Light::Point {
+            color: glm::vec3(1.0, 0.5, 0.0),
+            intensity: 2.0,
+            range: 15.0,
+            casts_shadows: false,
+        });
+        helicopter.add_child(&lamp);
+        root.add_child(&helicopter);
+
+        let lighting = Lighting::gather(&root, &glm::zero());
+        assert_eq!(
+            lighting.points,
+            vec![PointLight {
+                position: glm::vec3(10.0, 19.0, 30.0),
+                color: glm::vec3(2.0, 1.0, 0.0),
+                range: 15.0,
+                casts_shadows: false,
+            }]
+        );
+        let block = lighting.point_light_block(MAX_SHADOWED_POINT_LIGHTS);
+        assert_eq!(block.count[0], 1);
+        assert_eq!(
+            block.lights[0],
+            [[10.0, 19.0, 30.0, 15.0], [2.0, 1.0, 0.0, -1.0]]
+        );
+        assert_eq!(size_of::<PointLightBlock>(), 16 + 32 * MAX_POINT_LIGHTS);
+    }
+
+    #[test]
+    fn the_point_lights_nearest_the_camera_are_kept() {
+        let lighting = Lighting {
+            points: (0..MAX_POINT_LIGHTS + 4)
+                .rev()
+                .map(|i| PointLight {
+                    position: glm::vec3(i as f32, 0.0, 0.0),
+                    color: glm::vec3(1.0, 1.0, 1.0),
+                    range: 10.0,
+                    casts_shadows: i % 2 == 1,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let block = lighting.point_light_block(2);
+        assert_eq!(block.count[0], MAX_POINT_LIGHTS as i32);
+        assert!(block
+            .lights
+            .iter()
+            .all(|light| light[0][0] < MAX_POINT_LIGHTS as f32));
+
+        // The nearest of those casting shadows get the maps there are
+        let maps: Vec<f32> = block.lights[..4].iter().map(|light| light[1][3]).collect();
+        assert_eq!(maps, vec![-1.0, 0.0, -1.0, 1.0]);
+        assert!(block.lights[4..].iter().all(|light| light[1][3] == -1.0));
+        let shadowed = lighting.shadowed_point_lights(2);
+        assert_eq!(shadowed.len(), 2);
+        assert_eq!(shadowed[1].position.x, 3.0);
+    }
+}
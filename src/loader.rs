@@ -0,0 +1,153 @@
+use crate::assets::Texture;
+use crate::error::{MeshError, TextureError};
+use crate::mesh::{self, Mesh};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// A background thread that loads models from disk, so parsing a large OBJ never stalls the
+// render thread. Requests can be sent from any thread, e.g. the event loop when a file is
+// dropped onto the window. Loaded meshes have to be uploaded to the GPU by the render thread,
+// as that is the only thread with an OpenGL context.
+pub struct AssetLoader {
+    requests: Sender<PathBuf>,
+    results: Receiver<LoadedModel>,
+}
+
+pub struct LoadedModel {
+    pub path: PathBuf,
+    pub meshes: Result<Vec<Mesh>, MeshError>,
+}
+
+impl AssetLoader {
+    pub fn spawn() -> AssetLoader {
+        let (request_sender, request_receiver) = channel::<PathBuf>();
+        let (result_sender, result_receiver) = channel();
+
+        thread::spawn(move || {
+            // Runs until every request sender has been dropped
+            for path in request_receiver {
+                let meshes = mesh::load_obj(&path);
+                if result_sender.send(LoadedModel { path, meshes }).is_err() {
+                    break;
+                }
+            }
+        });
+
+        AssetLoader {
+            requests: request_sender,
+            results: result_receiver,
+        }
+    }
+
+    // A handle other threads can use to request models
+    pub fn requester(&self) -> Sender<PathBuf> {
+        self.requests.clone()
+    }
+
+    // Models that have finished loading since the last call
+    pub fn finished(&self) -> Vec<LoadedModel> {
+        self.results.try_iter().collect()
+    }
+}
+
+// How the meshes of a model file are built, e.g. `mesh::load_obj`. A file that changed is
+// reloaded the same way it was loaded the first time.
+pub type ModelLoader = fn(&Path) -> Result<Vec<Mesh>, MeshError>;
+
+#[derive(Clone, Copy)]
+pub enum WatchedFile {
+    Model(ModelLoader),
+    Texture,
+}
+
+pub enum Reloaded {
+    Model {
+        path: PathBuf,
+        meshes: Result<Vec<Mesh>, MeshError>,
+    },
+    Texture {
+        path: PathBuf,
+        texture: Result<Texture, TextureError>,
+    },
+}
+
+// A background thread that checks files for changes and loads them again when they do, so models
+// and textures can be edited while the program runs. Files are polled, as they are few and
+// checking their modification times is cheap. Editors often write a file in several steps, so a
+// reload that fails is logged and retried on the next change, keeping the old version meanwhile.
+pub struct HotReloader {
+    files: Sender<(PathBuf, WatchedFile)>,
+    results: Receiver<Reloaded>,
+}
+
+impl HotReloader {
+    // Watch files under `dir`, checking them every `interval`
+    pub fn spawn(dir: PathBuf, interval: Duration) -> HotReloader {
+        let (file_sender, file_receiver) = channel::<(PathBuf, WatchedFile)>();
+        let (result_sender, result_receiver) = channel();
+
+        thread::spawn(move || {
+            let mut files: HashMap<PathBuf, (WatchedFile, Option<SystemTime>)> = HashMap::new();
+            loop {
+                match file_receiver.recv_timeout(interval) {
+                    Ok((path, kind)) => {
+                        if path.starts_with(&dir) {
+                            let modified = modified_time(&path);
+                            files.insert(path, (kind, modified));
+                        }
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    // The assets were dropped
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                for (path, (kind, modified)) in files.iter_mut() {
+                    let now_modified = modified_time(path);
+                    if now_modified.is_none() || now_modified == *modified {
+                        continue;
+                    }
+                    *modified = now_modified;
+                    let path = path.clone();
+                    let reloaded = match kind {
+                        WatchedFile::Model(load) => {
+                            let meshes = load(&path);
+                            Reloaded::Model { path, meshes }
+                        }
+                        WatchedFile::Texture => {
+                            let texture = Texture::load(&path);
+                            Reloaded::Texture { path, texture }
+                        }
+                    };
+                    if result_sender.send(reloaded).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        HotReloader {
+            files: file_sender,
+            results: result_receiver,
+        }
+    }
+
+    // Reload `path` whenever it changes, if it is in the watched directory
+    pub fn watch(&self, path: &Path, kind: WatchedFile) {
+        let _ = self.files.send((path.to_path_buf(), kind));
+    }
+
+    // Files that were reloaded since the last call
+    pub fn finished(&self) -> Vec<Reloaded> {
+        self.results.try_iter().collect()
+    }
+}
+
+// None for missing files, e.g. while an editor replaces them, which are skipped until they are back
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
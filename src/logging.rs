@@ -0,0 +1,61 @@
+// Diagnostics are logged through the `log` crate and printed by env_logger.
+//
+// Messages are filtered per module with the RUST_LOG environment variable, e.g. `RUST_LOG=warn` or
+// `RUST_LOG=info,gloom_rs::app=debug`, and everything at info level and above is shown by default.
+// Applications call `init` once before `run`. Warnings and errors can also be mirrored to the
+// window title, so they aren't missed while the terminal is hidden behind the window.
+use log::{Level, Log, Metadata, Record};
+use std::sync::Mutex;
+
+// Warnings logged since the event loop last took them, if mirroring is on
+static MIRRORED_WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct Logger {
+    inner: env_logger::Logger,
+    mirror_warnings: bool,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        self.inner.log(record);
+        if self.mirror_warnings && record.level() <= Level::Warn {
+            if let Ok(mut warnings) = MIRRORED_WARNINGS.lock() {
+                warnings.push(record.args().to_string());
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+// Start logging to stderr. Does nothing if a logger has already been set.
+pub fn init(mirror_warnings: bool) {
+    let inner =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).build();
+    let max_level = inner.filter();
+    let logger = Logger {
+        inner,
+        mirror_warnings,
+    };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+// Warnings and errors logged since the last call, oldest first. Always empty unless `init` was
+// asked to mirror them.
+pub fn take_warnings() -> Vec<String> {
+    MIRRORED_WARNINGS
+        .lock()
+        .map(|mut warnings| std::mem::take(&mut *warnings))
+        .unwrap_or_default()
+}
@@ -0,0 +1,87 @@
+// What surfaces are made of, for physically based shading.
+//
+// Nodes with a `Material` are shaded by the simple pipeline with the metallic-roughness model used
+// by glTF and most engines, rather than Phong's: light reflecting off the surface is split into
+// what scatters into it and back out, the diffuse part, and what bounces off it mirror-like, the
+// specular part. How rough the surface is spreads the mirror-like reflection out into a dull sheen
+// (the GGX distribution of microfacets), and reflections get stronger towards grazing angles
+// (Schlick's approximation of Fresnel). Metals have no diffuse part at all and tint their
+// reflections with their albedo, while other surfaces reflect about 4% of the light untinted.
+// The renderer sets the material on the pipeline with every draw, see `DrawCall::material`, and
+// nodes without one are still shaded with Phong. Flat surfaces can also mirror the scene around
+// them, see `reflection`.
+
+// Rougher than perfectly smooth, as a mirror's highlight from a point light is infinitely small
+// and flickers between pixels instead
+pub const MIN_ROUGHNESS: f32 = 0.04;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    pub albedo: glm::Vec3,   // The surface's color, times the mesh's vertex colors
+    pub metallic: f32,       // From 0 for paint, stone and the like, to 1 for bare metal
+    pub roughness: f32,      // From `MIN_ROUGHNESS` for polished to 1 for dull
+    pub emissive: glm::Vec3, // Light given off by the surface itself, however it is lit
+    pub reflectivity: f32,   // How much of the planar reflection shows looking straight at it
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material::new(0.0, 0.5)
+    }
+}
+
+impl Material {
+    // A white surface, so the colors are the mesh's own. Out of range values are clamped.
+    pub const fn new(metallic: f32, roughness: f32) -> Material {
+        Material {
+            albedo: glm::Vec3::new(1.0, 1.0, 1.0),
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness: roughness.clamp(MIN_ROUGHNESS, 1.0),
+            emissive: glm::Vec3::new(0.0, 0.0, 0.0),
+            reflectivity: 0.0,
+        }
+    }
+
+    pub fn with_albedo(mut self, albedo: glm::Vec3) -> Material {
+        self.albedo = albedo;
+        self
+    }
+
+    pub fn with_emissive(mut self, emissive: glm::Vec3) -> Material {
+        self.emissive = emissive;
+        self
+    }
+
+    // Clamped from 0, not reflecting at all, to 1, a perfect mirror
+    pub const fn with_reflectivity(mut self, reflectivity: f32) -> Material {
+        self.reflectivity = reflectivity.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn reflects(&self) -> bool {
+        self.reflectivity > 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn materials_stay_within_what_the_shader_can_shade() {
+        let mirror = Material::new(1.5, 0.0);
+        assert_eq!(mirror.metallic, 1.0);
+        assert_eq!(mirror.roughness, MIN_ROUGHNESS);
+
+        let chalk = Material::new(-1.0, 2.0).with_albedo(glm::vec3(0.9, 0.9, 0.85));
+        assert_eq!(chalk.metallic, 0.0);
+        assert_eq!(chalk.roughness, 1.0);
+        assert_eq!(chalk.albedo, glm::vec3(0.9, 0.9, 0.85));
+        assert_eq!(chalk.emissive, glm::vec3(0.0, 0.0, 0.0));
+        assert!(!chalk.reflects());
+
+        let ice = Material::new(0.0, 0.1).with_reflectivity(1.2);
+        assert_eq!(ice.reflectivity, 1.0);
+        assert!(ice.reflects());
+    }
+}
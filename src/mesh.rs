@@ -1,4 +1,5 @@
-use tobj;
+use crate::error::MeshError;
+use log::{debug, info};
 
 // internal helper
 fn generate_color_vec(color: [f32; 4], num: usize) -> Vec<f32> {
@@ -7,15 +8,25 @@ fn generate_color_vec(color: [f32; 4], num: usize) -> Vec<f32> {
 
 // Mesh
 
+#[derive(Clone)]
 pub struct Mesh {
     pub vertices    : Vec<f32>,
     pub normals     : Vec<f32>,
     pub colors      : Vec<f32>,
+    pub uvs         : Vec<f32>,        // Optional, two per vertex
+    pub tangents    : Vec<f32>,        // Optional, four per vertex, see compute_tangents
+    pub joints      : Vec<f32>,        // Optional, the four joints moving each vertex, see skeleton
+    pub weights     : Vec<f32>,        // Optional, how much each of those four moves it
     pub indices     : Vec<u32>,
     pub index_count : i32,
 }
 
 impl Mesh {
+    // Bounding box of the mesh in its own coordinate space
+    pub fn bounds(&self) -> Option<crate::toolbox::Aabb> {
+        crate::toolbox::Aabb::from_points(&self.vertices)
+    }
+
     pub fn from(mesh: tobj::Mesh, color: [f32; 4]) -> Self {
         let num_verts = mesh.positions.len() / 3;
         let index_count = mesh.indices.len() as i32;
@@ -24,17 +35,369 @@ impl Mesh {
             normals: mesh.normals,
             indices: mesh.indices,
             colors: generate_color_vec(color, num_verts),
+            uvs: mesh.texcoords,
+            tangents: vec![],
+            joints: vec![],
+            weights: vec![],
             index_count,
         }
     }
 }
 
+// Load every model in an OBJ file as a separate mesh. Missing normals are generated.
+pub fn load_obj(path: &std::path::Path) -> Result<Vec<Mesh>, MeshError> {
+    info!("Loading {}...", path.display());
+    let (models, _materials)
+        = tobj::load_obj(path,
+            &tobj::LoadOptions{
+                triangulate: true,
+                single_index: true,
+                ..Default::default()
+            }
+        ).map_err(|source| MeshError::Load { path: path.to_path_buf(), source })?;
+
+    // Models without faces (e.g. only lines or points) have nothing to draw
+    let models: Vec<tobj::Model> = models.into_iter().filter(|m| !m.mesh.indices.is_empty()).collect();
+    if models.is_empty() {
+        return Err(MeshError::Empty { path: path.to_path_buf() });
+    }
+
+    Ok(models.into_iter().map(|model| {
+        let mut mesh = model.mesh;
+        if mesh.normals.len() != mesh.positions.len() {
+            mesh.normals = compute_normals(&mesh.positions, &mesh.indices);
+        }
+        Mesh::from(mesh, [0.8, 0.8, 0.8, 1.0])
+    }).collect())
+}
+
+// Smooth per-vertex normals, averaged from the faces around each vertex weighted by their area
+pub fn compute_normals(positions: &[f32], indices: &[u32]) -> Vec<f32> {
+    let vertex = |i: usize| glm::vec3(positions[i*3], positions[i*3 + 1], positions[i*3 + 2]);
+    let mut normals = vec![glm::vec3(0.0, 0.0, 0.0); positions.len() / 3];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        let face_normal = glm::cross(&(vertex(b) - vertex(a)), &(vertex(c) - vertex(a)));
+        for i in [a, b, c] {
+            normals[i] += face_normal;
+        }
+    }
+    normals.iter().flat_map(|n| {
+        let n = if glm::length(n) > 0.0 { glm::normalize(n) } else { glm::vec3(0.0, 1.0, 0.0) };
+        [n.x, n.y, n.z]
+    }).collect()
+}
+
+// Per-vertex tangents for normal mapping, four per vertex: the direction the U of the UVs grows in,
+// then in w whether the V grows along cross(normal, tangent) (1) or against it (-1), for mirrored
+// UVs. The shaders rebuild the bitangent from those. Averaged from the faces around each vertex,
+// like `compute_normals`, and made perpendicular to the vertex's normal.
+pub fn compute_tangents(positions: &[f32], normals: &[f32], uvs: &[f32], indices: &[u32]) -> Vec<f32> {
+    let vertex = |i: usize| glm::vec3(positions[i*3], positions[i*3 + 1], positions[i*3 + 2]);
+    let uv = |i: usize| glm::vec2(uvs[i*2], uvs[i*2 + 1]);
+    let vertex_count = positions.len() / 3;
+    let mut tangents = vec![glm::vec3(0.0, 0.0, 0.0); vertex_count];
+    let mut bitangents = vec![glm::vec3(0.0, 0.0, 0.0); vertex_count];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        let (edge1, edge2) = (vertex(b) - vertex(a), vertex(c) - vertex(a));
+        let (step1, step2) = (uv(b) - uv(a), uv(c) - uv(a));
+        let determinant = step1.x * step2.y - step2.x * step1.y;
+        if determinant.abs() < f32::EPSILON {
+            continue; // The UVs don't span the face
+        }
+        let tangent = (edge1 * step2.y - edge2 * step1.y) / determinant;
+        let bitangent = (edge2 * step1.x - edge1 * step2.x) / determinant;
+        for i in [a, b, c] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+    (0..vertex_count).flat_map(|i| {
+        let normal = glm::vec3(normals[i*3], normals[i*3 + 1], normals[i*3 + 2]);
+        let mut tangent = tangents[i] - normal * glm::dot(&normal, &tangents[i]);
+        if glm::length(&tangent) < 1e-6 {
+            // No UVs to follow, so any direction along the surface
+            let axis = if normal.x.abs() < 0.9 { glm::vec3(1.0, 0.0, 0.0) }
+                       else { glm::vec3(0.0, 1.0, 0.0) };
+            tangent = axis - normal * glm::dot(&normal, &axis);
+        }
+        let tangent = glm::normalize(&tangent);
+        let mirrored = glm::dot(&glm::cross(&normal, &tangent), &bitangents[i]) < 0.0;
+        let handedness = if mirrored { -1.0 } else { 1.0 };
+        [tangent.x, tangent.y, tangent.z, handedness]
+    }).collect()
+}
+
+// The disc a rotor sweeps turning about `axis` (0 for X, 1 for Y and 2 for Z) through `hub`, made
+// of `segments` slices and seen from both sides, to show where the blades turn too fast to see
+pub fn rotor_disc(rotor: &Mesh, axis: usize, hub: &glm::Vec3, segments: u32) -> Mesh {
+    // The plane of the disc, with `u`, `v` and the axis right-handed
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let mut radius: f32 = 0.0;
+    let (mut low, mut high) = (hub[axis], hub[axis]);
+    for point in rotor.vertices.chunks_exact(3) {
+        radius = radius.max((point[u] - hub[u]).hypot(point[v] - hub[v]));
+        low = low.min(point[axis]);
+        high = high.max(point[axis]);
+    }
+    let mut center = *hub;
+    center[axis] = (low + high) / 2.0;
+
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut indices = vec![];
+    for side in [1.0, -1.0] {
+        let first = (vertices.len() / 3) as u32;
+        let mut normal = glm::vec3(0.0, 0.0, 0.0);
+        normal[axis] = side;
+        // The center, then around the rim
+        for i in 0..=segments {
+            let mut point = center;
+            if i > 0 {
+                let angle = std::f32::consts::TAU * i as f32 / segments as f32;
+                point[u] += radius * angle.cos();
+                point[v] += radius * angle.sin();
+            }
+            vertices.extend_from_slice(point.as_slice());
+            normals.extend_from_slice(normal.as_slice());
+        }
+        // Counterclockwise seen from the side the normal points to
+        for i in 1..=segments {
+            let next = i % segments + 1;
+            if side > 0.0 {
+                indices.extend_from_slice(&[first, first + i, first + next]);
+            } else {
+                indices.extend_from_slice(&[first, first + next, first + i]);
+            }
+        }
+    }
+
+    let index_count = indices.len() as i32;
+    Mesh {
+        colors: generate_color_vec([0.2, 0.2, 0.2, 1.0], vertices.len() / 3),
+        vertices,
+        normals,
+        uvs: vec![],
+        tangents: vec![],
+        joints: vec![],
+        weights: vec![],
+        indices,
+        index_count,
+    }
+}
+
+// A landing pad: a round slab `radius` across, with its top at Y = 0 and its side reaching down to
+// Y = -1, so it can be scaled to stand on uneven ground. The top is gray, ringed with `ring_color`.
+pub fn landing_pad(radius: f32, segments: u32, ring_color: [f32; 4]) -> Mesh {
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut colors = vec![];
+    let mut indices = vec![];
+    let around = |i: u32, r: f32, y: f32| {
+        let angle = std::f32::consts::TAU * i as f32 / segments as f32;
+        [r * angle.cos(), y, -r * angle.sin()]
+    };
+
+    // Rings of the top, each with its own vertices so the colors don't blend: the gray middle out
+    // to `inner`, then the colored ring out to the rim
+    let inner = radius * 0.8;
+    let gray = [0.55, 0.55, 0.55, 1.0];
+    for (from, to, color) in [(0.0, inner, gray), (inner, radius, ring_color)] {
+        let first = (vertices.len() / 3) as u32;
+        for i in 0..segments {
+            vertices.extend_from_slice(&around(i, from, 0.0));
+            vertices.extend_from_slice(&around(i, to, 0.0));
+            for _ in 0..2 {
+                normals.extend_from_slice(&[0.0, 1.0, 0.0]);
+                colors.extend_from_slice(&color);
+            }
+        }
+        for i in 0..segments {
+            let (a, b) = (first + 2 * i, first + 2 * ((i + 1) % segments));
+            indices.extend_from_slice(&[a, a + 1, b + 1, a, b + 1, b]);
+        }
+    }
+
+    // The side, facing out
+    let first = (vertices.len() / 3) as u32;
+    for i in 0..segments {
+        let top = around(i, radius, 0.0);
+        vertices.extend_from_slice(&top);
+        vertices.extend_from_slice(&around(i, radius, -1.0));
+        for _ in 0..2 {
+            normals.extend_from_slice(&[top[0] / radius, 0.0, top[2] / radius]);
+            colors.extend_from_slice(&ring_color);
+        }
+    }
+    for i in 0..segments {
+        let (a, b) = (first + 2 * i, first + 2 * ((i + 1) % segments));
+        indices.extend_from_slice(&[a, a + 1, b + 1, a, b + 1, b]);
+    }
+
+    let index_count = indices.len() as i32;
+    Mesh {
+        vertices,
+        normals,
+        colors,
+        uvs         : vec![],
+        tangents    : vec![],
+        joints      : vec![],
+        weights     : vec![],
+        indices,
+        index_count,
+    }
+}
+
+// A UV sphere of `radius` around the origin, in `color`, with `rings` bands from pole to pole, each
+// split into `segments` around Y
+pub fn sphere(radius: f32, rings: u32, segments: u32, color: [f32; 4]) -> Mesh {
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    for ring in 0..=rings {
+        let polar = std::f32::consts::PI * ring as f32 / rings as f32;
+        for i in 0..=segments {
+            let angle = std::f32::consts::TAU * i as f32 / segments as f32;
+            let normal = [polar.sin() * angle.cos(), polar.cos(), -polar.sin() * angle.sin()];
+            vertices.extend(normal.iter().map(|n| n * radius));
+            normals.extend_from_slice(&normal);
+        }
+    }
+    let mut indices = vec![];
+    for ring in 0..rings {
+        for i in 0..segments {
+            let a = ring * (segments + 1) + i;
+            let b = a + segments + 1;
+            indices.extend_from_slice(&[a, b, b + 1, a, b + 1, a + 1]);
+        }
+    }
+
+    let vertex_count = vertices.len() / 3;
+    let index_count = indices.len() as i32;
+    Mesh {
+        vertices,
+        normals,
+        colors      : generate_color_vec(color, vertex_count),
+        uvs         : vec![],
+        tangents    : vec![],
+        joints      : vec![],
+        weights     : vec![],
+        indices,
+        index_count,
+    }
+}
+
+// A square `size` across, lying flat around the origin and facing up, in `color`
+pub fn square(size: f32, color: [f32; 4]) -> Mesh {
+    let half = size / 2.0;
+    let vertices = vec![
+        -half, 0.0, -half,
+        -half, 0.0,  half,
+         half, 0.0,  half,
+         half, 0.0, -half,
+    ];
+    let normals = [0.0, 1.0, 0.0].repeat(4);
+    let indices = vec![0, 1, 2, 0, 2, 3];
+    Mesh {
+        vertices,
+        normals,
+        colors      : generate_color_vec(color, 4),
+        uvs         : vec![],
+        tangents    : vec![],
+        joints      : vec![],
+        weights     : vec![],
+        indices,
+        index_count : 6,
+    }
+}
+
+// A windsock skinned to a chain of joints, for posing with a `skeleton::Skeleton`. Joint 0 holds the
+// pole, which stands `pole_height` tall on the origin. Joints 1 to `sock_joints` are spaced evenly
+// along the sock, which is `length` long and sticks out from the top of the pole down +X, and every
+// vertex of the sock follows the two joints either side of it, so it bends smoothly between them.
+pub fn windsock(pole_height: f32, length: f32, sock_joints: u32, segments: u32) -> Mesh {
+    let mut mesh = Mesh {
+        vertices    : vec![],
+        normals     : vec![],
+        colors      : vec![],
+        uvs         : vec![],
+        tangents    : vec![],
+        joints      : vec![],
+        weights     : vec![],
+        indices     : vec![],
+        index_count : 0,
+    };
+    let gray = [0.6, 0.6, 0.6, 1.0];
+    let pole = [0.0, 0.0, 0.0, 0.0];
+    let whole = [1.0, 0.0, 0.0, 0.0];
+    let pole_rings = [0.0, pole_height].map(|y| Ring {
+        center: glm::vec3(0.0, y, 0.0), radius: 0.1, joints: pole, weights: whole, color: gray,
+    });
+    skinned_tube(&mut mesh, &pole_rings, &glm::vec3(0.0, 0.0, 1.0), &glm::vec3(1.0, 0.0, 0.0), segments);
+
+    // Stripes of orange and white, four rings to a joint, narrowing towards the end
+    let sock_joints = sock_joints.max(2);
+    let spacing = length / (sock_joints - 1) as f32;
+    let ring_count = (sock_joints - 1) * 4 + 1;
+    let sock_rings: Vec<Ring> = (0..ring_count).map(|i| {
+        let along = i as f32 / (ring_count - 1) as f32;
+        let from_joint = along * length / spacing;
+        let joint = (from_joint.floor() as u32).min(sock_joints - 2);
+        let weight = from_joint - joint as f32;
+        let stripe = (along * 5.0).floor() as u32 % 2;
+        Ring {
+            center  : glm::vec3(along * length, pole_height, 0.0),
+            radius  : 0.6 - 0.3 * along,
+            joints  : [(joint + 1) as f32, (joint + 2) as f32, 0.0, 0.0],
+            weights : [1.0 - weight, weight, 0.0, 0.0],
+            color   : if stripe == 0 { [1.0, 0.45, 0.1, 1.0] } else { [0.95, 0.95, 0.95, 1.0] },
+        }
+    }).collect();
+    skinned_tube(&mut mesh, &sock_rings, &glm::vec3(0.0, 1.0, 0.0), &glm::vec3(0.0, 0.0, 1.0), segments);
+
+    mesh.index_count = mesh.indices.len() as i32;
+    mesh
+}
+
+// A ring of a tube, and the joints its vertices follow
+struct Ring {
+    center  : glm::Vec3,
+    radius  : f32,
+    joints  : [f32; 4],
+    weights : [f32; 4],
+    color   : [f32; 4],
+}
+
+// Add the side of a tube through `rings` to `mesh`, with `segments` vertices around each ring, open
+// at both ends. `u` and `v` span the plane of the rings, and `u` × `v` points from each to the next.
+fn skinned_tube(mesh: &mut Mesh, rings: &[Ring], u: &glm::Vec3, v: &glm::Vec3, segments: u32) {
+    let first = (mesh.vertices.len() / 3) as u32;
+    for ring in rings {
+        for i in 0..segments {
+            let angle = std::f32::consts::TAU * i as f32 / segments as f32;
+            let out = u * angle.cos() + v * angle.sin();
+            mesh.vertices.extend_from_slice((ring.center + out * ring.radius).as_slice());
+            mesh.normals.extend_from_slice(out.as_slice());
+            mesh.colors.extend_from_slice(&ring.color);
+            mesh.joints.extend_from_slice(&ring.joints);
+            mesh.weights.extend_from_slice(&ring.weights);
+        }
+    }
+    // Counterclockwise seen from outside
+    for k in 0..rings.len().saturating_sub(1) as u32 {
+        for i in 0..segments {
+            let (a, b) = (first + k * segments + i, first + k * segments + (i + 1) % segments);
+            mesh.indices.extend_from_slice(&[a, b, b + segments, a, b + segments, a + segments]);
+        }
+    }
+}
+
 // Lunar terrain
 
 pub struct Terrain;
 impl Terrain {
-    pub fn load(path: &str) -> Mesh {
-        println!("Loading terrain model...");
+    pub fn load(path: &std::path::Path) -> Result<Mesh, MeshError> {
+        info!("Loading terrain model...");
         let before = std::time::Instant::now();
         let (models, _materials)
             = tobj::load_obj(path,
@@ -43,25 +406,25 @@ impl Terrain {
                     single_index: true,
                     ..Default::default()
                 }
-            ).expect("Failed to load terrain model");
+            ).map_err(|source| MeshError::Load { path: path.to_path_buf(), source })?;
         let after = std::time::Instant::now();
-        println!("Done in {:.3}ms.", after.duration_since(before).as_micros() as f32 / 1e3);
+        info!("Done in {:.3}ms.", after.duration_since(before).as_micros() as f32 / 1e3);
 
-        if models.len() > 1 || models.len() == 0 {
-            panic!("Please use a model with a single mesh!")
+        if models.len() != 1 {
+            return Err(MeshError::NotSingleMesh { path: path.to_path_buf(), count: models.len() });
             // You could try merging the vertices and indices
             // of the separate meshes into a single mesh.
             // I'll leave that as an optional exercise. ;)
         }
 
         let terrain = models[0].to_owned();
-        println!("Loaded {} with {} points and {} triangles.",
+        debug!("Loaded {} with {} points and {} triangles.",
             terrain.name,
             terrain.mesh.positions.len() /3,
             terrain.mesh.indices.len() / 3,
         );
 
-        Mesh::from(terrain.mesh, [1.0, 1.0, 1.0, 1.0])
+        Ok(Mesh::from(terrain.mesh, [1.0, 1.0, 1.0, 1.0]))
     }
 }
 
@@ -79,7 +442,7 @@ pub struct Helicopter {
 use std::ops::Index;
 impl Index<usize> for Helicopter {
     type Output = Mesh;
-    fn index<'a>(&'a self, i: usize) -> &'a Mesh {
+    fn index(&self, i: usize) -> &Mesh {
         match i {
             0 => &self.body,
             1 => &self.main_rotor,
@@ -91,8 +454,8 @@ impl Index<usize> for Helicopter {
 }
 
 impl Helicopter {
-    pub fn load(path: &str) -> Self {
-        println!("Loading helicopter model...");
+    pub fn load(path: &std::path::Path) -> Result<Self, MeshError> {
+        info!("Loading helicopter model...");
         let before = std::time::Instant::now();
         let (models, _materials)
             = tobj::load_obj(path,
@@ -101,24 +464,26 @@ impl Helicopter {
                     single_index: true,
                     ..Default::default()
                 }
-            ).expect("Failed to load helicopter model");
+            ).map_err(|source| MeshError::Load { path: path.to_path_buf(), source })?;
         let after = std::time::Instant::now();
-        println!("Done in {:.3}ms!", after.duration_since(before).as_micros() as f32 / 1e3);
+        info!("Done in {:.3}ms!", after.duration_since(before).as_micros() as f32 / 1e3);
 
         for model in &models {
-            println!("Loaded {} with {} points and {} triangles.", model.name, model.mesh.positions.len() / 3, model.mesh.indices.len() / 3);
+            debug!("Loaded {} with {} points and {} triangles.", model.name, model.mesh.positions.len() / 3, model.mesh.indices.len() / 3);
         }
 
-        let body_model = models.iter().find(|m| m.name == "Body_body").expect("Incorrect model file!").to_owned();
-        let door_model = models.iter().find(|m| m.name == "Door_door").expect("Incorrect model file!").to_owned();
-        let main_rotor_model = models.iter().find(|m| m.name == "Main_Rotor_main_rotor").expect("Incorrect model file!").to_owned();
-        let tail_rotor_model = models.iter().find(|m| m.name == "Tail_Rotor_tail_rotor").expect("Incorrect model file!").to_owned();
+        let find = |name: &'static str| models.iter().find(|m| m.name == name).cloned()
+            .ok_or(MeshError::MissingModel { path: path.to_path_buf(), name });
+        let body_model = find("Body_body")?;
+        let door_model = find("Door_door")?;
+        let main_rotor_model = find("Main_Rotor_main_rotor")?;
+        let tail_rotor_model = find("Tail_Rotor_tail_rotor")?;
 
-        Helicopter {
+        Ok(Helicopter {
             body:       Mesh::from(body_model.mesh,         [0.3, 0.3, 0.3, 1.0]),
             door:       Mesh::from(door_model.mesh,         [0.1, 0.1, 0.3, 1.0]),
             main_rotor: Mesh::from(main_rotor_model.mesh,   [0.3, 0.1, 0.1, 1.0]),
             tail_rotor: Mesh::from(tail_rotor_model.mesh,   [0.1, 0.3, 0.1, 1.0]),
-        }
+        })
     }
 }
@@ -0,0 +1,423 @@
+// A spatial index over the world-space bounds of scene nodes.
+//
+// Testing every node against the camera or a ray gets slow once there are thousands of them. The
+// octree splits the world into nested cells, each node living in the smallest cell that contains
+// its bounds, so whole cells can be skipped at once: cells outside the camera's view, cells the
+// ray misses or enters behind the closest hit so far, and cells away from a point of interest.
+//
+// Nodes are indexed by their address, which the scene graph keeps stable. Only nodes with bounds
+// are indexed, the rest are never culled or picked. `update_scene` is meant to be called every
+// frame, and only moves a node between cells when it has left its cell.
+use crate::scene_graph::SceneNode;
+use crate::toolbox::{Aabb, Containment, Frustum, Ray};
+use std::collections::HashMap;
+
+// Cells holding more nodes than this are split, unless they are already this deep
+const SPLIT_THRESHOLD: usize = 8;
+const MAX_DEPTH: u32 = 8;
+
+struct Entry {
+    local_bounds: Aabb,
+    transform: glm::Mat4, // From the node's space to world space
+    bounds: Aabb,         // In world space
+    cell: usize,
+}
+
+struct Cell {
+    bounds: Aabb,
+    depth: u32,
+    // Index of the first of eight consecutive children, if the cell has been split
+    children: Option<usize>,
+    nodes: Vec<*mut SceneNode>,
+}
+
+pub struct Octree {
+    // The first cell is the root. Cells are never merged again once split, they just stay empty.
+    cells: Vec<Cell>,
+    entries: HashMap<*mut SceneNode, Entry>,
+}
+
+impl Octree {
+    // An empty octree over `world`. Nodes reaching outside of it are kept in the root cell.
+    pub fn new(world: Aabb) -> Octree {
+        Octree {
+            cells: vec![Cell {
+                bounds: world,
+                depth: 0,
+                children: None,
+                nodes: vec![],
+            }],
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Index `root` and every node with bounds below it where they are now
+    pub fn update_scene(&mut self, root: &SceneNode) {
+        self.update_node(root, &glm::identity());
+    }
+
+    fn update_node(&mut self, node: &SceneNode, transformation_so_far: &glm::Mat4) {
+        let transform = transformation_so_far * node.local_transform();
+        if let Some(bounds) = node.bounds {
+            self.update(
+                node as *const SceneNode as *mut SceneNode,
+                bounds,
+                transform,
+            );
+        }
+        for &child in &node.children {
+            self.update_node(unsafe { &*child }, &transform);
+        }
+    }
+
+    // Index `node`, whose `bounds` in its own space are placed in the world by `transform`
+    pub fn update(&mut self, node: *mut SceneNode, bounds: Aabb, transform: glm::Mat4) {
+        let world_bounds = bounds.transformed(&transform);
+        if let Some(entry) = self.entries.get_mut(&node) {
+            entry.local_bounds = bounds;
+            entry.transform = transform;
+            entry.bounds = world_bounds;
+            // Still inside its cell, and not small enough for any of the cell's children
+            let cell = &self.cells[entry.cell];
+            let inside = entry.cell == 0 || cell.bounds.contains(&world_bounds);
+            let fits_child = cell.children.is_some_and(|first| {
+                (first..first + 8).any(|child| self.cells[child].bounds.contains(&world_bounds))
+            });
+            if inside && !fits_child {
+                return;
+            }
+            self.remove(node);
+        }
+        let cell = self.insert(node, &world_bounds);
+        self.entries.insert(
+            node,
+            Entry {
+                local_bounds: bounds,
+                transform,
+                bounds: world_bounds,
+                cell,
+            },
+        );
+        self.split_if_full(cell);
+    }
+
+    pub fn remove(&mut self, node: *mut SceneNode) {
+        if let Some(entry) = self.entries.remove(&node) {
+            let nodes = &mut self.cells[entry.cell].nodes;
+            if let Some(index) = nodes.iter().position(|&n| n == node) {
+                nodes.swap_remove(index);
+            }
+        }
+    }
+
+    // Put `node` into the smallest existing cell containing `bounds`, returning the cell
+    fn insert(&mut self, node: *mut SceneNode, bounds: &Aabb) -> usize {
+        let mut cell = 0;
+        while let Some(first) = self.cells[cell].children {
+            match (first..first + 8).find(|&child| self.cells[child].bounds.contains(bounds)) {
+                Some(child) => cell = child,
+                None => break,
+            }
+        }
+        self.cells[cell].nodes.push(node);
+        cell
+    }
+
+    fn split_if_full(&mut self, cell: usize) {
+        let Cell {
+            bounds,
+            depth,
+            children,
+            ref nodes,
+        } = self.cells[cell];
+        if children.is_some() || nodes.len() <= SPLIT_THRESHOLD || depth >= MAX_DEPTH {
+            return;
+        }
+
+        let first = self.cells.len();
+        let center = bounds.center();
+        for octant in 0..8 {
+            let pick = |axis: usize, low: f32, high: f32| {
+                if octant & (1 << axis) == 0 {
+                    (low, center[axis])
+                } else {
+                    (center[axis], high)
+                }
+            };
+            let (min_x, max_x) = pick(0, bounds.min.x, bounds.max.x);
+            let (min_y, max_y) = pick(1, bounds.min.y, bounds.max.y);
+            let (min_z, max_z) = pick(2, bounds.min.z, bounds.max.z);
+            self.cells.push(Cell {
+                bounds: Aabb {
+                    min: glm::vec3(min_x, min_y, min_z),
+                    max: glm::vec3(max_x, max_y, max_z),
+                },
+                depth: depth + 1,
+                children: None,
+                nodes: vec![],
+            });
+        }
+        self.cells[cell].children = Some(first);
+
+        // Move the nodes that fit into a child down, which may fill up that child in turn
+        let nodes = std::mem::take(&mut self.cells[cell].nodes);
+        for node in nodes {
+            let bounds = self.entries[&node].bounds;
+            let new_cell = self.insert(node, &bounds);
+            if let Some(entry) = self.entries.get_mut(&node) {
+                entry.cell = new_cell;
+            }
+        }
+        for child in first..first + 8 {
+            self.split_if_full(child);
+        }
+    }
+
+    // Mark the indexed nodes outside `frustum` as culled, and the rest as not. Returns how many
+    // were culled.
+    pub fn cull(&self, frustum: &Frustum) -> usize {
+        self.cull_cell(0, frustum, Containment::Intersecting)
+    }
+
+    fn cull_cell(&self, cell: usize, frustum: &Frustum, parent: Containment) -> usize {
+        let cell_ref = &self.cells[cell];
+        // The root may hold nodes reaching outside of it, so it can't be classified as a whole
+        let containment = if cell == 0 {
+            parent
+        } else {
+            frustum.classify_within(&cell_ref.bounds, parent)
+        };
+        let mut culled_count = 0;
+        for &node in &cell_ref.nodes {
+            let culled = frustum.classify_within(&self.entries[&node].bounds, containment)
+                == Containment::Outside;
+            unsafe { (*node).culled = culled };
+            culled_count += culled as usize;
+        }
+        if let Some(first) = cell_ref.children {
+            for child in first..first + 8 {
+                culled_count += self.cull_cell(child, frustum, containment);
+            }
+        }
+        culled_count
+    }
+
+    // Find the closest indexed node whose bounds are hit by a world-space ray, like
+    // `SceneNode::pick`. Returns the distance along the ray together with the node.
+    pub fn pick(&self, ray: &Ray) -> Option<(f32, *mut SceneNode)> {
+        let mut closest = None;
+        self.pick_cell(0, ray, &mut closest);
+        closest
+    }
+
+    fn pick_cell(&self, cell: usize, ray: &Ray, closest: &mut Option<(f32, *mut SceneNode)>) {
+        let cell = &self.cells[cell];
+        for &node in &cell.nodes {
+            let entry = &self.entries[&node];
+            if entry.bounds.intersect_ray(ray).is_none() {
+                continue;
+            }
+            // Test in the node's own coordinate space, where its bounds are axis aligned
+            let local_ray = ray.transformed(&glm::inverse(&entry.transform));
+            if let Some(t) = entry.local_bounds.intersect_ray(&local_ray) {
+                if closest.is_none_or(|(closest_t, _)| t < closest_t) {
+                    *closest = Some((t, node));
+                }
+            }
+        }
+
+        // Visit the children in the order the ray enters them, skipping those behind the closest
+        // hit so far. Nodes lie within their cell, so nothing in those can be any closer.
+        if let Some(first) = cell.children {
+            let mut children: Vec<(f32, usize)> = (first..first + 8)
+                .filter_map(|child| {
+                    let t = self.cells[child].bounds.intersect_ray(ray)?;
+                    Some((t, child))
+                })
+                .collect();
+            children.sort_by(|a, b| a.0.total_cmp(&b.0));
+            for (t, child) in children {
+                if closest.is_some_and(|(closest_t, _)| closest_t < t) {
+                    break;
+                }
+                self.pick_cell(child, ray, closest);
+            }
+        }
+    }
+
+    // Every indexed node whose bounds are hit by a world-space ray, with the distance along the
+    // ray to where it enters them, nearest first
+    pub fn along_ray(&self, ray: &Ray) -> Vec<(f32, *mut SceneNode)> {
+        let mut hits = vec![];
+        self.along_ray_cell(0, ray, &mut hits);
+        hits.sort_by(|a, b| a.0.total_cmp(&b.0));
+        hits
+    }
+
+    fn along_ray_cell(&self, cell: usize, ray: &Ray, hits: &mut Vec<(f32, *mut SceneNode)>) {
+        let cell_ref = &self.cells[cell];
+        if cell != 0 && cell_ref.bounds.intersect_ray(ray).is_none() {
+            return;
+        }
+        for &node in &cell_ref.nodes {
+            let entry = &self.entries[&node];
+            if entry.bounds.intersect_ray(ray).is_none() {
+                continue;
+            }
+            let local_ray = ray.transformed(&glm::inverse(&entry.transform));
+            if let Some(t) = entry.local_bounds.intersect_ray(&local_ray) {
+                hits.push((t, node));
+            }
+        }
+        if let Some(first) = cell_ref.children {
+            for child in first..first + 8 {
+                self.along_ray_cell(child, ray, hits);
+            }
+        }
+    }
+
+    // How an indexed node was placed in the world at the last update
+    pub fn transform(&self, node: *mut SceneNode) -> Option<&glm::Mat4> {
+        self.entries.get(&node).map(|entry| &entry.transform)
+    }
+
+    // The indexed nodes whose bounds reach within `radius` of `center`
+    pub fn within(&self, center: &glm::Vec3, radius: f32) -> Vec<*mut SceneNode> {
+        let mut found = vec![];
+        self.within_cell(0, center, radius, &mut found);
+        found
+    }
+
+    fn within_cell(
+        &self,
+        cell: usize,
+        center: &glm::Vec3,
+        radius: f32,
+        found: &mut Vec<*mut SceneNode>,
+    ) {
+        let cell_ref = &self.cells[cell];
+        if cell != 0 && !cell_ref.bounds.intersects_sphere(center, radius) {
+            return;
+        }
+        found.extend(
+            cell_ref
+                .nodes
+                .iter()
+                .filter(|&node| self.entries[node].bounds.intersects_sphere(center, radius)),
+        );
+        if let Some(first) = cell_ref.children {
+            for child in first..first + 8 {
+                self.within_cell(child, center, radius, found);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene_graph::Node;
+
+    fn world() -> Aabb {
+        Aabb {
+            min: glm::vec3(-100.0, -100.0, -100.0),
+            max: glm::vec3(100.0, 100.0, 100.0),
+        }
+    }
+
+    // A root with a unit box at each of `positions`
+    fn scene(positions: &[glm::Vec3]) -> (Node, Vec<Node>) {
+        let mut root = SceneNode::new();
+        let children: Vec<Node> = positions
+            .iter()
+            .map(|&position| {
+                let mut node = SceneNode::new();
+                node.position = position;
+                node.bounds = Some(Aabb {
+                    min: glm::vec3(-0.5, -0.5, -0.5),
+                    max: glm::vec3(0.5, 0.5, 0.5),
+                });
+                node
+            })
+            .collect();
+        for child in &children {
+            root.add_child(child);
+        }
+        (root, children)
+    }
+
+    fn pointer(node: &SceneNode) -> *mut SceneNode {
+        node as *const SceneNode as *mut SceneNode
+    }
+
+    #[test]
+    fn cull_marks_the_nodes_outside_the_frustum() {
+        // Enough nodes in a line down -Z and behind the camera for cells to be split
+        let positions: Vec<glm::Vec3> = (-40..40).map(|z| glm::vec3(0.0, 0.0, z as f32)).collect();
+        let (root, children) = scene(&positions);
+        let mut octree = Octree::new(world());
+        octree.update_scene(&root);
+        assert_eq!(octree.len(), positions.len());
+
+        let frustum = Frustum::from_matrix(&glm::perspective(1.0, 1.0, 1.0, 30.0));
+        let culled = octree.cull(&frustum);
+        for (node, position) in children.iter().zip(&positions) {
+            let visible = -30.5 <= position.z && position.z <= -0.5;
+            assert_eq!(node.culled, !visible, "node at z = {}", position.z);
+        }
+        assert_eq!(culled, children.iter().filter(|node| node.culled).count());
+    }
+
+    #[test]
+    fn moved_nodes_are_culled_where_they_are_now() {
+        let (root, mut children) = scene(&[glm::vec3(0.0, 0.0, -10.0)]);
+        let mut octree = Octree::new(world());
+        let frustum = Frustum::from_matrix(&glm::perspective(1.0, 1.0, 1.0, 30.0));
+        octree.update_scene(&root);
+        assert_eq!(octree.cull(&frustum), 0);
+
+        children[0].position = glm::vec3(0.0, 0.0, 10.0);
+        octree.update_scene(&root);
+        assert_eq!(octree.cull(&frustum), 1);
+        assert!(root[0].culled);
+
+        octree.remove(pointer(&children[0]));
+        assert!(octree.is_empty());
+    }
+
+    #[test]
+    fn pick_and_within_find_indexed_nodes() {
+        let (root, children) = scene(&[
+            glm::vec3(0.0, 0.0, -10.0),
+            glm::vec3(0.0, 0.0, -20.0),
+            glm::vec3(30.0, 0.0, 0.0),
+        ]);
+        let mut octree = Octree::new(world());
+        octree.update_scene(&root);
+
+        let ray = Ray {
+            origin: glm::zero(),
+            direction: glm::vec3(0.0, 0.0, -1.0),
+        };
+        let (t, node) = octree.pick(&ray).unwrap();
+        assert!((t - 9.5).abs() < 1e-5);
+        assert_eq!(node, pointer(&children[0]));
+        let along: Vec<*mut SceneNode> = octree
+            .along_ray(&ray)
+            .into_iter()
+            .map(|hit| hit.1)
+            .collect();
+        assert_eq!(along, vec![pointer(&children[0]), pointer(&children[1])]);
+
+        let nearby = octree.within(&glm::vec3(25.0, 0.0, 0.0), 5.0);
+        assert_eq!(nearby, vec![pointer(&children[2])]);
+    }
+}
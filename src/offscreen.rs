@@ -0,0 +1,382 @@
+// Rendering to images rather than to a window
+use crate::error::{GlError, RenderError};
+use crate::gl_check;
+
+// A framebuffer object with a color and a depth buffer, for headless contexts which have no
+// default framebuffer to draw to, or for rendering at a different size than the window
+pub struct OffscreenTarget {
+    fbo: u32,
+    renderbuffers: [u32; 2],
+}
+
+impl OffscreenTarget {
+    // Create the framebuffer and bind it, so everything drawn from now on ends up in it. If it
+    // can't be created, no framebuffer is left bound.
+    pub unsafe fn new(width: u32, height: u32) -> Result<OffscreenTarget, RenderError> {
+        let mut fbo = 0;
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+
+        let mut renderbuffers = [0; 2];
+        gl::GenRenderbuffers(2, renderbuffers.as_mut_ptr());
+        let attachments = [
+            (gl::RGBA8, gl::COLOR_ATTACHMENT0),
+            (gl::DEPTH_COMPONENT24, gl::DEPTH_ATTACHMENT),
+        ];
+        for (&renderbuffer, (format, attachment)) in renderbuffers.iter().zip(attachments) {
+            gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+            let storage = gl_check!(gl::RenderbufferStorage(
+                gl::RENDERBUFFER,
+                format,
+                width as i32,
+                height as i32
+            ));
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                attachment,
+                gl::RENDERBUFFER,
+                renderbuffer,
+            );
+            if let Err(e) = storage {
+                OffscreenTarget { fbo, renderbuffers }.delete();
+                return Err(e.into());
+            }
+        }
+        gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+
+        let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+        let target = OffscreenTarget { fbo, renderbuffers };
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            target.delete();
+            return Err(RenderError::IncompleteFramebuffer(status));
+        }
+
+        Ok(target)
+    }
+
+    // Draw into this framebuffer from now on
+    pub unsafe fn bind(&self) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+    }
+
+    // Stretch the color buffer, which is `size` large, over the default framebuffer of `window_size`,
+    // and draw into the default framebuffer from now on
+    pub unsafe fn blit_to_window(
+        &self,
+        size: (u32, u32),
+        window_size: (u32, u32),
+    ) -> Result<(), GlError> {
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+        let blit = gl_check!(gl::BlitFramebuffer(
+            0,
+            0,
+            size.0 as i32,
+            size.1 as i32,
+            0,
+            0,
+            window_size.0 as i32,
+            window_size.1 as i32,
+            gl::COLOR_BUFFER_BIT,
+            gl::LINEAR,
+        ));
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        blit
+    }
+
+    pub unsafe fn delete(self) {
+        gl::DeleteFramebuffers(1, &self.fbo);
+        gl::DeleteRenderbuffers(2, self.renderbuffers.as_ptr());
+    }
+}
+
+// A framebuffer object drawing into textures rather than renderbuffers, so later passes can sample
+// what was drawn, e.g. the depth of the scene as seen from a light for shadows
+pub struct Framebuffer {
+    fbo: u32,
+    size: (u32, u32),
+    samples: u32, // Per pixel, 0 unless multisampled
+    color_textures: Vec<u32>,
+    depth_texture: Option<u32>,
+}
+
+impl Framebuffer {
+    // A framebuffer of `size` with a color texture of each of `color_formats`, attached in order,
+    // and a depth texture of `depth_format` if there is one. Formats are sized internal formats like
+    // `gl::RGBA8` and `gl::DEPTH_COMPONENT24`. Without color textures, nothing but depth is drawn.
+    pub unsafe fn new(
+        size: (u32, u32),
+        color_formats: &[u32],
+        depth_format: Option<u32>,
+    ) -> Result<Framebuffer, RenderError> {
+        Framebuffer::multisampled(size, color_formats, depth_format, 0)
+    }
+
+    // Like `new`, but with `samples` samples per pixel, smoothing the edges of what is drawn. The
+    // textures are `gl::TEXTURE_2D_MULTISAMPLE`s, which can't be filtered, so they are resolved
+    // into a framebuffer made with `new` before being looked up, see `resolve`. With 0 samples,
+    // this is `new`.
+    pub unsafe fn multisampled(
+        size: (u32, u32),
+        color_formats: &[u32],
+        depth_format: Option<u32>,
+        samples: u32,
+    ) -> Result<Framebuffer, RenderError> {
+        let previous = bound_framebuffer();
+        let mut framebuffer = Framebuffer {
+            fbo: 0,
+            size,
+            samples,
+            color_textures: vec![],
+            depth_texture: None,
+        };
+        gl::GenFramebuffers(1, &mut framebuffer.fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer.fbo);
+
+        let attach = |format: u32, attachment: u32| -> Result<u32, GlError> {
+            let mut texture = 0;
+            gl::GenTextures(1, &mut texture);
+            let target = if samples > 0 {
+                gl::TEXTURE_2D_MULTISAMPLE
+            } else {
+                gl::TEXTURE_2D
+            };
+            gl::BindTexture(target, texture);
+            let storage = if samples > 0 {
+                gl_check!(gl::TexStorage2DMultisample(
+                    target,
+                    samples as i32,
+                    format,
+                    size.0 as i32,
+                    size.1 as i32,
+                    gl::TRUE
+                ))
+            } else {
+                let storage = gl_check!(gl::TexStorage2D(
+                    target,
+                    1,
+                    format,
+                    size.0 as i32,
+                    size.1 as i32
+                ));
+                gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+                gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+                gl::TexParameteri(target, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+                gl::TexParameteri(target, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+                storage
+            };
+            gl::BindTexture(target, 0);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment, target, texture, 0);
+            storage
+                .map(|_| texture)
+                .inspect_err(|_| gl::DeleteTextures(1, &texture))
+        };
+        let mut result = Ok(());
+        for (i, &format) in color_formats.iter().enumerate() {
+            match attach(format, gl::COLOR_ATTACHMENT0 + i as u32) {
+                Ok(texture) => framebuffer.color_textures.push(texture),
+                Err(e) => result = Err(e.into()),
+            }
+        }
+        if let Some(format) = depth_format {
+            match attach(format, gl::DEPTH_ATTACHMENT) {
+                Ok(texture) => framebuffer.depth_texture = Some(texture),
+                Err(e) => result = Err(e.into()),
+            }
+        }
+        let draw_buffers: Vec<u32> = (0..framebuffer.color_textures.len() as u32)
+            .map(|i| gl::COLOR_ATTACHMENT0 + i)
+            .collect();
+        if draw_buffers.is_empty() {
+            gl::DrawBuffer(gl::NONE);
+            gl::ReadBuffer(gl::NONE);
+        } else {
+            gl::DrawBuffers(draw_buffers.len() as i32, draw_buffers.as_ptr());
+        }
+        if result.is_ok() {
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                result = Err(RenderError::IncompleteFramebuffer(status));
+            }
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, previous);
+
+        match result {
+            Ok(()) => Ok(framebuffer),
+            Err(e) => {
+                framebuffer.delete();
+                Err(e)
+            }
+        }
+    }
+
+    // A framebuffer drawing depth only, into a texture of `layers` layers of `size` texels square in
+    // `depth_format`. The texture is a `gl::TEXTURE_2D_ARRAY`, e.g. for the cascades of the sun's
+    // shadows, or a `gl::TEXTURE_CUBE_MAP_ARRAY` with six layers to a cube, one per face, e.g. for
+    // the shadows of point lights. All layers are attached, for geometry shaders to pick with
+    // `gl_Layer`, until `attach_layer` picks one.
+    pub unsafe fn layered_depth(
+        target: u32,
+        size: u32,
+        layers: u32,
+        depth_format: u32,
+    ) -> Result<Framebuffer, RenderError> {
+        let previous = bound_framebuffer();
+        let mut framebuffer = Framebuffer {
+            fbo: 0,
+            size: (size, size),
+            samples: 0,
+            color_textures: vec![],
+            depth_texture: None,
+        };
+        gl::GenFramebuffers(1, &mut framebuffer.fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer.fbo);
+
+        let mut texture = 0;
+        gl::GenTextures(1, &mut texture);
+        framebuffer.depth_texture = Some(texture);
+        gl::BindTexture(target, texture);
+        let storage = gl_check!(gl::TexStorage3D(
+            target,
+            1,
+            depth_format,
+            size as i32,
+            size as i32,
+            layers as i32
+        ));
+        gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        for wrap in [gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+            gl::TexParameteri(target, wrap, gl::CLAMP_TO_EDGE as i32);
+        }
+        gl::BindTexture(target, 0);
+        gl::FramebufferTexture(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, texture, 0);
+        gl::DrawBuffer(gl::NONE);
+        gl::ReadBuffer(gl::NONE);
+
+        let mut result = storage.map_err(RenderError::from);
+        if result.is_ok() {
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                result = Err(RenderError::IncompleteFramebuffer(status));
+            }
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, previous);
+
+        match result {
+            Ok(()) => Ok(framebuffer),
+            Err(e) => {
+                framebuffer.delete();
+                Err(e)
+            }
+        }
+    }
+
+    // Draw into `layer` of the depth texture of a `layered_depth` framebuffer alone, or into all
+    // of its layers again with None. The framebuffer must be bound.
+    pub unsafe fn attach_layer(&self, layer: Option<u32>) {
+        if let Some(texture) = self.depth_texture {
+            match layer {
+                Some(layer) => gl::FramebufferTextureLayer(
+                    gl::FRAMEBUFFER,
+                    gl::DEPTH_ATTACHMENT,
+                    texture,
+                    0,
+                    layer as i32,
+                ),
+                None => gl::FramebufferTexture(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, texture, 0),
+            }
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn color_texture(&self, index: usize) -> u32 {
+        self.color_textures[index]
+    }
+
+    pub fn depth_texture(&self) -> Option<u32> {
+        self.depth_texture
+    }
+
+    // Draw into this framebuffer, all of it, from now on
+    pub unsafe fn bind(&self) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        gl::Viewport(0, 0, self.size.0 as i32, self.size.1 as i32);
+    }
+
+    // Average the samples of every pixel of this multisampled framebuffer into `into`, a plain one
+    // of the same size and formats, the first color texture and the depth, which is picked from the
+    // samples rather than averaged. What is bound for drawing stays bound.
+    pub unsafe fn resolve(&self, into: &Framebuffer) -> Result<(), GlError> {
+        let previous = bound_framebuffer();
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, into.fbo);
+        let (width, height) = (self.size.0 as i32, self.size.1 as i32);
+        let mut mask = 0;
+        if !self.color_textures.is_empty() {
+            mask |= gl::COLOR_BUFFER_BIT;
+        }
+        if self.depth_texture.is_some() {
+            mask |= gl::DEPTH_BUFFER_BIT;
+        }
+        let blit = gl_check!(gl::BlitFramebuffer(
+            0,
+            0,
+            width,
+            height,
+            0,
+            0,
+            width,
+            height,
+            mask,
+            gl::NEAREST
+        ));
+        gl::BindFramebuffer(gl::FRAMEBUFFER, previous);
+        blit
+    }
+
+    pub unsafe fn delete(self) {
+        gl::DeleteFramebuffers(1, &self.fbo);
+        gl::DeleteTextures(
+            self.color_textures.len() as i32,
+            self.color_textures.as_ptr(),
+        );
+        if let Some(texture) = &self.depth_texture {
+            gl::DeleteTextures(1, texture);
+        }
+    }
+}
+
+// The framebuffer currently bound for drawing, to restore after drawing somewhere else
+pub unsafe fn bound_framebuffer() -> u32 {
+    let mut framebuffer = 0;
+    gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut framebuffer);
+    framebuffer as u32
+}
+
+// Read the pixels of the framebuffer bound for reading. OpenGL stores rows bottom to top, so they
+// are flipped to get an upright image.
+pub unsafe fn read_pixels(width: u32, height: u32) -> Result<image::RgbImage, GlError> {
+    let mut pixels = vec![0u8; width as usize * height as usize * 3];
+    gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+    gl_check!(gl::ReadPixels(
+        0,
+        0,
+        width as i32,
+        height as i32,
+        gl::RGB,
+        gl::UNSIGNED_BYTE,
+        pixels.as_mut_ptr() as *mut std::ffi::c_void,
+    ))?;
+    let mut image = image::RgbImage::from_raw(width, height, pixels)
+        .expect("Pixel buffer has the size of the image");
+    image::imageops::flip_vertical_in_place(&mut image);
+    Ok(image)
+}
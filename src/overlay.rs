@@ -0,0 +1,265 @@
+// Renderer statistics drawn over the scene during development: frame rate, a graph of recent
+// frame times, the backend's `RenderStats` with the time each pass took, GPU memory and where the
+// camera is.
+//
+// Applications count frames with `frame` every update, and draw the overlay last in `render`
+// with statistics of what they drew, see `OverlayStats`. A status line, like whether reloading the
+// shaders worked, is shown for a few seconds with `status`, even while the statistics are hidden.
+// It slides in and fades out again.
+use crate::backend::gl::GlBackend;
+use crate::backend::{MemoryUsage, PipelineHandle, RenderStats};
+use crate::text::TextRenderer;
+use crate::toolbox::{Ease, Easing, Tween};
+use std::collections::VecDeque;
+
+// Frame times kept for the graph, one bar each
+const GRAPH_FRAMES: usize = 240;
+// Frame time at the top of the graph, in seconds. Longer frames are cut off.
+const GRAPH_MAX_FRAME_TIME: f32 = 1.0 / 20.0;
+const GRAPH_HEIGHT: f32 = 60.0;
+// Screen pixels per font pixel
+const SCALE: f32 = 2.0;
+const MARGIN: f32 = 8.0;
+
+const TEXT_COLOR: [f32; 4] = [0.9, 0.95, 1.0, 1.0];
+const BACKGROUND_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+// Bars are green within the budget of 60 FPS, yellow within 30 FPS and red beyond
+const FAST_COLOR: [f32; 4] = [0.3, 0.85, 0.4, 1.0];
+const SLOW_COLOR: [f32; 4] = [0.95, 0.8, 0.2, 1.0];
+const VERY_SLOW_COLOR: [f32; 4] = [0.95, 0.3, 0.25, 1.0];
+const BUDGET_LINE_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.35];
+const ERROR_COLOR: [f32; 4] = [1.0, 0.45, 0.4, 1.0];
+// How long a status line is shown, in seconds, of which it slides in during the first and fades
+// out during the last
+const STATUS_TIME: f32 = 4.0;
+const STATUS_SLIDE_TIME: f32 = 0.35;
+const STATUS_FADE_TIME: f32 = 0.6;
+
+// What the application drew this frame, as far as the overlay can't tell by itself
+pub struct OverlayStats {
+    pub render: RenderStats, // Before the overlay's own draws
+    pub memory: MemoryUsage,
+    pub camera_position: glm::Vec3,
+}
+
+struct Status {
+    message: String,
+    failed: bool,
+    remaining: f32,    // In seconds
+    slide: Tween<f32>, // How far it has slid in from the left, 0-1
+}
+
+pub struct DebugOverlay {
+    pub visible: bool,
+    frame_times: VecDeque<f32>, // In seconds, the latest last
+    status: Option<Status>,
+    text: TextRenderer,
+}
+
+impl DebugOverlay {
+    // Hidden until `visible` is set
+    pub unsafe fn new() -> DebugOverlay {
+        DebugOverlay {
+            visible: false,
+            frame_times: VecDeque::with_capacity(GRAPH_FRAMES),
+            status: None,
+            text: TextRenderer::new(),
+        }
+    }
+
+    // Count a frame that took `frame_time` seconds. Frames are counted while hidden as well, so
+    // the graph is full as soon as it is shown.
+    pub fn frame(&mut self, frame_time: f32) {
+        if self.frame_times.len() == GRAPH_FRAMES {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+        if let Some(status) = &mut self.status {
+            status.remaining -= frame_time;
+            status.slide.advance(frame_time);
+            if status.remaining <= 0.0 {
+                self.status = None;
+            }
+        }
+    }
+
+    // Show `message` for a few seconds, in red if something `failed`. Only its first line fits, so
+    // a colon introducing the rest is left out as well.
+    pub fn status(&mut self, message: &str, failed: bool) {
+        self.status = Some(Status {
+            message: message
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches(':')
+                .to_string(),
+            failed,
+            remaining: STATUS_TIME,
+            slide: Tween::new(0.0, 1.0, STATUS_SLIDE_TIME, Easing::Expo(Ease::Out)),
+        });
+    }
+
+    // Show `message` as the status line while something is being typed into it, from frame to
+    // frame. It stays on screen while this is called and only slides in the first time.
+    pub fn prompt(&mut self, message: &str) {
+        match &mut self.status {
+            Some(status) if !status.failed => {
+                status.message = message.to_string();
+                status.remaining = STATUS_TIME;
+            }
+            _ => self.status(message, false),
+        }
+    }
+
+    // Whether `draw` has anything to draw
+    pub fn is_shown(&self) -> bool {
+        self.visible || self.status.is_some()
+    }
+
+    // Average frame time over the last second or so
+    fn average_frame_time(&self) -> f32 {
+        let mut total = 0.0;
+        let mut frames = 0;
+        for &frame_time in self.frame_times.iter().rev() {
+            if total >= 1.0 {
+                break;
+            }
+            total += frame_time;
+            frames += 1;
+        }
+        total / frames.max(1) as f32
+    }
+
+    // Draw the overlay over everything drawn so far, if it is shown, with `pipeline` loaded from
+    // the `overlay` shaders
+    pub unsafe fn draw(
+        &mut self,
+        backend: &mut GlBackend,
+        pipeline: PipelineHandle,
+        viewport_size: (u32, u32),
+        stats: &OverlayStats,
+    ) {
+        let mut status_y = 0.0;
+        if self.visible {
+            status_y = self.draw_statistics(stats);
+        }
+        if let Some(status) = &self.status {
+            let mut color = if status.failed {
+                ERROR_COLOR
+            } else {
+                TEXT_COLOR
+            };
+            let mut background = BACKGROUND_COLOR;
+            let opacity = Easing::Quad(Ease::Out).apply(status.remaining / STATUS_FADE_TIME);
+            color[3] *= opacity;
+            background[3] *= opacity;
+            let width = TextRenderer::text_width(&status.message, SCALE) + 2.0 * MARGIN;
+            let height = TextRenderer::line_height(SCALE) + 2.0 * MARGIN;
+            let x = (status.slide.value() - 1.0) * width;
+            self.text.rect(x, status_y, width, height, background);
+            self.text
+                .text(x + MARGIN, status_y + MARGIN, SCALE, color, &status.message);
+        }
+        self.text.draw(backend, pipeline, viewport_size);
+    }
+
+    // Queue the statistics and the graph, returning the height they take
+    fn draw_statistics(&mut self, stats: &OverlayStats) -> f32 {
+        let frame_time = self.average_frame_time();
+        let render = &stats.render;
+        let memory = &stats.memory;
+        let position = &stats.camera_position;
+        let mut lines = vec![
+            format!(
+                "{:.0} FPS, {:.2} ms",
+                1.0 / frame_time.max(1e-6),
+                frame_time * 1e3
+            ),
+            format!("Draw calls: {}", render.draw_calls),
+            format!("Triangles: {}", render.triangles),
+            format!("State changes: {}", render.state_changes),
+            format!("Culled nodes: {}", render.culled_nodes),
+            format!(
+                "Meshes: {} ({})",
+                memory.meshes,
+                megabytes(memory.buffer_bytes)
+            ),
+            format!(
+                "Textures: {} ({})",
+                memory.textures,
+                megabytes(memory.texture_bytes)
+            ),
+        ];
+        for pass in &render.passes {
+            let gpu_time = pass
+                .gpu_time
+                .map(|time| format!(", GPU {:.2} ms", time * 1e3))
+                .unwrap_or_default();
+            lines.push(format!(
+                "{}: CPU {:.2} ms{}",
+                pass.name,
+                pass.cpu_time * 1e3,
+                gpu_time
+            ));
+        }
+        if let Some(available) = memory.available_bytes {
+            lines.push(format!("Free video memory: {}", megabytes(available)));
+        }
+        lines.push(format!(
+            "Camera: {:.1} {:.1} {:.1}",
+            position.x, position.y, position.z
+        ));
+
+        let line_height = TextRenderer::line_height(SCALE);
+        let text_width = lines
+            .iter()
+            .map(|line| TextRenderer::text_width(line, SCALE))
+            .fold(0.0, f32::max);
+        let width = text_width.max(GRAPH_FRAMES as f32) + 2.0 * MARGIN;
+        let height = lines.len() as f32 * line_height + GRAPH_HEIGHT + 3.0 * MARGIN;
+        self.text.rect(0.0, 0.0, width, height, BACKGROUND_COLOR);
+        for (i, line) in lines.iter().enumerate() {
+            let y = MARGIN + i as f32 * line_height;
+            self.text.text(MARGIN, y, SCALE, TEXT_COLOR, line);
+        }
+
+        // One bar per frame, growing up from the bottom of the graph
+        let graph_bottom = height - MARGIN;
+        let bar_height =
+            |frame_time: f32| (frame_time / GRAPH_MAX_FRAME_TIME).min(1.0) * GRAPH_HEIGHT;
+        for (i, &frame_time) in self.frame_times.iter().enumerate() {
+            let color = if frame_time <= 1.0 / 60.0 {
+                FAST_COLOR
+            } else if frame_time <= 1.0 / 30.0 {
+                SLOW_COLOR
+            } else {
+                VERY_SLOW_COLOR
+            };
+            let bar = bar_height(frame_time);
+            let x = MARGIN + (GRAPH_FRAMES - self.frame_times.len() + i) as f32;
+            self.text.rect(x, graph_bottom - bar, 1.0, bar, color);
+        }
+        let budget_y = graph_bottom - bar_height(1.0 / 60.0);
+        self.text.rect(
+            MARGIN,
+            budget_y,
+            GRAPH_FRAMES as f32,
+            1.0,
+            BUDGET_LINE_COLOR,
+        );
+        height
+    }
+
+    // The old buffers went away with the context
+    pub unsafe fn context_recreated(&mut self) {
+        self.text = TextRenderer::new();
+    }
+
+    pub unsafe fn delete(&mut self) {
+        self.text.delete();
+    }
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
@@ -0,0 +1,194 @@
+use gloom_rs::gamepad::GamepadState;
+use gloom_rs::input::KeyState;
+use gloom_rs::toolbox::{self, Transform};
+use winit::keyboard::KeyCode;
+
+// Keyboard and gamepad flight controls for a piloted helicopter body.
+//  W/S, Up/Down, right stick up/down: cyclic, tilting the nose down to speed up forwards and up
+//                                     to slow down
+//  A/D, right stick left/right:       cyclic, rolling left and right to drift sideways
+//  Space/LShift, left stick up/down:  collective up and down, to climb and descend. Let go to
+//                                     hold the altitude.
+//  Left/Right, left stick left/right: tail rotor, to yaw
+// The sticks fly as far as they are pushed, the keys as if pushed all the way.
+// The controls don't move the helicopter, they only tilt it and set the collective. The rotor's
+// thrust points straight up out of the body, so tilting it trades lift for speed, and what it
+// does is simulated with forces in the simulation's fixed steps.
+
+// What a helicopter is like to fly. Lengths are in world units, so about metres.
+#[derive(Clone, Copy, Debug)]
+pub struct FlightModel {
+    pub mass: f32,            // Kilograms
+    pub gravity: f32,         // Metres per second squared
+    pub max_lift: f32,        // Newtons of thrust at full collective
+    pub linear_drag: f32,     // Newtons per metre per second of speed
+    pub quadratic_drag: f32,  // Newtons per square of the speed, the most of the drag at speed
+    pub max_tilt: f32,        // Radians of pitch and roll at full cyclic
+    pub tilt_stiffness: f32,  // How hard the cyclic pulls towards the tilt asked for, per radian
+    pub yaw_torque: f32,      // Turning about Y at full pedal, in radians per second squared
+    pub angular_damping: f32, // How quickly turning dies down on its own, per second
+    pub ground_friction: f32, // How quickly sliding along the ground dies down, per second
+    pub skid_height: f32,     // How far below the body's origin it touches the ground
+}
+
+impl Default for FlightModel {
+    // A helicopter cruising at about 30 m/s at full tilt and turning 90° a second at most
+    fn default() -> FlightModel {
+        FlightModel {
+            mass: 2000.0,
+            gravity: 9.81,
+            max_lift: 30000.0,
+            linear_drag: 100.0,
+            quadratic_drag: 5.0,
+            max_tilt: 0.35,
+            tilt_stiffness: 30.0,
+            yaw_torque: 12.5,
+            angular_damping: 8.0,
+            ground_friction: 4.0,
+            skid_height: 1.57,
+        }
+    }
+}
+
+// How far each control is moved, from -1 to 1
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Controls {
+    pub cyclic_forward: f32,
+    pub cyclic_left: f32,
+    pub pedal_left: f32,
+    pub collective_up: f32,
+}
+
+impl Controls {
+    pub fn from_keys(keys: &KeyState) -> Controls {
+        let axis = |positive: &[KeyCode], negative: &[KeyCode]| {
+            let held = |keys_for: &[KeyCode]| keys_for.iter().any(|&key| keys.is_held(key));
+            held(positive) as i32 as f32 - held(negative) as i32 as f32
+        };
+        Controls {
+            cyclic_forward: axis(
+                &[KeyCode::KeyW, KeyCode::ArrowUp],
+                &[KeyCode::KeyS, KeyCode::ArrowDown],
+            ),
+            cyclic_left: axis(&[KeyCode::KeyA], &[KeyCode::KeyD]),
+            pedal_left: axis(&[KeyCode::ArrowLeft], &[KeyCode::ArrowRight]),
+            collective_up: axis(&[KeyCode::Space], &[KeyCode::ShiftLeft]),
+        }
+    }
+
+    // These controls and the gamepad's sticks together
+    pub fn with_gamepad(self, gamepad: &GamepadState) -> Controls {
+        let add = |control: f32, stick: f32| (control + stick).clamp(-1.0, 1.0);
+        Controls {
+            cyclic_forward: add(self.cyclic_forward, gamepad.right_stick.1),
+            cyclic_left: add(self.cyclic_left, -gamepad.right_stick.0),
+            pedal_left: add(self.pedal_left, -gamepad.left_stick.0),
+            collective_up: add(self.collective_up, gamepad.left_stick.1),
+        }
+    }
+}
+
+// How a piloted helicopter is moving, besides where its body is
+#[derive(Clone, Copy, Debug)]
+pub struct FlightPhysics {
+    pub model: FlightModel,
+    pub velocity: glm::Vec3,
+    pub angular_velocity: glm::Vec3, // Rates of the body's rotation angles, radians per second
+    pub collective: f32,             // Share of the rotor's full lift, 0-1
+    pub grounded: bool,
+}
+
+impl FlightPhysics {
+    // How fast the collective lever moves, in shares of its range per second
+    const COLLECTIVE_RATE: f32 = 1.5;
+    // Collective taken off per metre per second climbed, to hold the altitude when let go
+    const ALTITUDE_HOLD: f32 = 0.01;
+    // Collective with the collective control all the way up and down, in shares above or below
+    // hovering
+    const CLIMB: f32 = 0.35;
+    const DESCENT: f32 = 0.35;
+
+    // Taking over a helicopter moving at `velocity`, hovering
+    pub fn new(model: FlightModel, velocity: glm::Vec3) -> FlightPhysics {
+        FlightPhysics {
+            model,
+            velocity,
+            angular_velocity: glm::zero(),
+            collective: model.mass * model.gravity / model.max_lift,
+            grounded: false,
+        }
+    }
+
+    // Advance `body` by a step of `delta_time` seconds flown with `controls`, keeping it above
+    // `ground`, the height of the terrain below it if known
+    pub fn step(
+        &mut self,
+        body: &mut Transform,
+        controls: &Controls,
+        ground: Option<f32>,
+        delta_time: f32,
+    ) {
+        let model = self.model;
+        let Controls {
+            cyclic_forward,
+            cyclic_left,
+            pedal_left,
+            collective_up,
+        } = *controls;
+
+        // The collective that would hover with the body tilted as it is
+        let tilt = body.rotation.x.cos() * body.rotation.z.cos();
+        let hover = model.mass * model.gravity / model.max_lift / tilt.max(0.5);
+        let wanted = if collective_up > 0.0 {
+            hover + Self::CLIMB * collective_up
+        } else if collective_up < 0.0 {
+            hover + Self::DESCENT * collective_up
+        } else {
+            hover - Self::ALTITUDE_HOLD * self.velocity.y
+        };
+        let change = Self::COLLECTIVE_RATE * delta_time;
+        self.collective += (wanted.clamp(0.0, 1.0) - self.collective).clamp(-change, change);
+
+        // Springs pull the pitch and the roll towards what the cyclic asks for, nose down being a
+        // negative pitch, and level on the ground, while the pedals turn the tail
+        let (target_pitch, target_roll) = if self.grounded {
+            (0.0, 0.0)
+        } else {
+            (
+                -cyclic_forward * model.max_tilt,
+                cyclic_left * model.max_tilt,
+            )
+        };
+        let angular_acceleration = glm::vec3(
+            model.tilt_stiffness * (target_pitch - body.rotation.x),
+            model.yaw_torque * pedal_left,
+            model.tilt_stiffness * (target_roll - body.rotation.z),
+        ) - self.angular_velocity * model.angular_damping;
+        self.angular_velocity += angular_acceleration * delta_time;
+        body.rotation += self.angular_velocity * delta_time;
+
+        let up = glm::quat_rotate_vec3(
+            &toolbox::quat_from_euler(&body.rotation),
+            &glm::vec3(0.0, 1.0, 0.0),
+        );
+        let thrust = up * self.collective * model.max_lift;
+        let weight = glm::vec3(0.0, -model.mass * model.gravity, 0.0);
+        let speed = glm::length(&self.velocity);
+        let drag = -self.velocity * (model.linear_drag + model.quadratic_drag * speed);
+        self.velocity += (thrust + weight + drag) / model.mass * delta_time;
+        body.position += self.velocity * delta_time;
+
+        // The ground stops a fall, and drags on the skids while sitting on it
+        self.grounded = false;
+        if let Some(ground) = ground.map(|ground| ground + model.skid_height) {
+            if body.position.y <= ground {
+                body.position.y = ground;
+                self.velocity.y = self.velocity.y.max(0.0);
+                let friction = (-model.ground_friction * delta_time).exp();
+                self.velocity.x *= friction;
+                self.velocity.z *= friction;
+                self.grounded = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,473 @@
+// Post-processing: filtering the drawn scene on its way to the screen.
+//
+// While a `PostProcess` is begun, the scene is drawn into a texture rather than straight into the
+// framebuffer, in half floats, so colors brighter than white aren't cut off. Ending it draws the
+// texture into the framebuffer with the post pipeline, a triangle covering the screen whose
+// fragment shader filters it, one step at a time, see `Step`.
+//
+// The scene is drawn in high dynamic range: a sunlit surface also lit by a lamp is brighter than
+// white, and stays brighter than the surface next to it, which the lamp doesn't reach. What the
+// screen can show ends at white, though, so the last step scales the colors by the exposure and
+// tone maps them, squeezing all of the range into what can be shown, see `ToneMapping`.
+//
+// Bloom makes the brightest parts of the scene, like emissive materials and lamps, glow. The
+// parts brighter than a threshold are picked out into a texture half the size of the screen,
+// blurred with a Gaussian blur, which is separable into a horizontal and a vertical pass, between
+// two such textures, and then added back over the scene.
+//
+// Depth of field blurs what is nearer or further than the focus distance, like a camera's lens
+// does, before anything else: the further out of focus, the larger the circle of confusion, the
+// disc each point is spread over, which is gathered from samples spiralling out from each pixel.
+// Samples behind the pixel only count as far as the pixel's own circle reaches, so what is in
+// focus stays sharp in front of a blurred background.
+//
+// Motion blur smears what moved across the screen since the last frame along the way it moved,
+// like it would be while a camera's shutter is open, rather than showing it sharp in one place per
+// frame. Fast-turning rotors look like they turn, not like they jump. The simple pipeline draws the
+// scene once more into a texture of velocities, each mesh's vertices where they are now and where
+// they were last frame, with their nodes' model matrices then and the camera's view then. Where no
+// mesh was drawn, the sky moves with the camera alone, reprojected from the far plane. The source
+// is then averaged along each pixel's velocity, after the depth of field and before the bloom.
+//
+// With multisampling, the scene's textures have several samples per pixel, smoothing the edges of
+// what is drawn into them, like the window's framebuffer would. Those can't be filtered, so the
+// samples of each pixel are resolved into plain textures before the first step reads them. Without
+// it, FXAA smooths the edges afterwards instead, cheaply: the tone mapped scene is drawn into a
+// texture of its own, and wherever the brightness of its pixels changes sharply, the edge running
+// through them is followed a few pixels along, and the pixels blended across it.
+use crate::backend::gl::GlBackend;
+use crate::backend::{Backend, PipelineHandle};
+use crate::deferred::{self, Pass};
+use crate::error::RenderError;
+use crate::offscreen::{self, Framebuffer};
+use log::warn;
+use std::fmt;
+use std::str::FromStr;
+
+// Where the textures filtered by a step are bound, clear of those of the scene, see
+// `deferred::GBUFFER_TEXTURE_UNIT`. The step's source comes first, then the blurred bloom.
+pub const POST_TEXTURE_UNIT: u32 = 9;
+// Where the scene's depth is bound for the depth of field, past the units of every other pass,
+// and then its velocities for the motion blur
+pub const POST_DEPTH_TEXTURE_UNIT: u32 = 16;
+pub const POST_VELOCITY_TEXTURE_UNIT: u32 = 17;
+
+// More blur passes than this take longer than they're worth, each being two draws over a quarter
+// of the screen
+pub const MAX_BLUR_PASSES: u32 = 16;
+
+// Circles of confusion wider than this many pixels spread the depth of field's samples too thin
+pub const MAX_FOCUS_BLUR: f32 = 24.0;
+
+// Streaks longer than this many pixels spread the motion blur's samples too thin
+pub const MAX_MOTION_BLUR: f32 = 64.0;
+
+// What the post pipeline draws, as its `postStep` uniform
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Bright = 0,       // The parts of the source brighter than the threshold
+    Blur = 1,         // The source blurred along `blurDirection`
+    Composite = 2,    // The source with the bloom added, exposed and tone mapped
+    DepthOfField = 3, // The source blurred by how far out of focus it is
+    MotionBlur = 4,   // The source blurred along how far it moved since the last frame
+    Fxaa = 5,         // The source, already tone mapped, smoothed along its edges
+}
+
+// How colors brighter than white are brought into what the screen shows, as the post pipeline's
+// `toneMapping` uniform
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMapping {
+    Clip = 0,     // Cut off at white, as if drawn straight to the screen
+    Reinhard = 1, // x / (1 + x), which darkens everything a bit and never quite reaches white
+    #[default]
+    Aces = 2, // The filmic curve of the Academy Color Encoding System, as fitted by Narkowicz
+}
+
+impl ToneMapping {
+    pub const ALL: [ToneMapping; 3] = [ToneMapping::Clip, ToneMapping::Reinhard, ToneMapping::Aces];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ToneMapping::Clip => "clip",
+            ToneMapping::Reinhard => "reinhard",
+            ToneMapping::Aces => "aces",
+        }
+    }
+}
+
+impl fmt::Display for ToneMapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ToneMapping {
+    type Err = ();
+    fn from_str(name: &str) -> Result<ToneMapping, ()> {
+        ToneMapping::ALL
+            .iter()
+            .copied()
+            .find(|tone_mapping| tone_mapping.name() == name)
+            .ok_or(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BloomSettings {
+    pub threshold: f32,   // How bright colors must be to glow, 1 being white
+    pub intensity: f32,   // How much of the glow is added over the scene
+    pub blur_passes: u32, // Horizontal and vertical blurs, each spreading the glow further
+}
+
+impl Default for BloomSettings {
+    fn default() -> BloomSettings {
+        BloomSettings {
+            threshold: 1.0,
+            intensity: 0.8,
+            blur_passes: 4,
+        }
+    }
+}
+
+// Where the camera is focused, for depth of field
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthOfField {
+    pub focus_distance: f32, // From the camera, in the world
+    pub focus_range: f32,    // How much nearer or further things get fully blurred
+    pub max_blur: f32,       // Across the circle of confusion of what is fully blurred, in pixels
+}
+
+impl Default for DepthOfField {
+    fn default() -> DepthOfField {
+        DepthOfField {
+            focus_distance: 40.0,
+            focus_range: 30.0,
+            max_blur: 10.0,
+        }
+    }
+}
+
+// How long the camera's shutter is open, for motion blur
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MotionBlur {
+    pub shutter: f32, // Share of the frame, so 1 smears what moved all the way back to where it was
+    pub max_blur: f32, // The longest streak, in pixels
+}
+
+impl Default for MotionBlur {
+    fn default() -> MotionBlur {
+        MotionBlur {
+            shutter: 0.5,
+            max_blur: 32.0,
+        }
+    }
+}
+
+pub struct PostProcess {
+    scene: Framebuffer,
+    resolved: Option<Framebuffer>, // The scene's samples averaged, if it is multisampled
+    focused: Framebuffer,          // The scene through the lens, with depth of field
+    velocity: Framebuffer, // How far each pixel moved since the last frame, see `begin_velocity`
+    composited: Framebuffer, // The tone mapped scene, for FXAA
+    bloom: [Framebuffer; 2], // Half size, blurred from one into the other and back
+    vao: u32,              // Empty, as the vertex shader makes the triangle up itself
+    // From where the sky is on the screen now to where it was last frame
+    reprojection: glm::Mat4,
+    previous_framebuffer: u32,
+    previous_viewport: [i32; 4],
+}
+
+impl PostProcess {
+    // Post-processing for a viewport of `size`, with the scene drawn with `samples` samples per
+    // pixel, or without multisampling if 0
+    pub unsafe fn new(size: (u32, u32), samples: u32) -> Result<PostProcess, RenderError> {
+        let half = ((size.0 / 2).max(1), (size.1 / 2).max(1));
+        let depth = Some(gl::DEPTH_COMPONENT24);
+        // The scene, focused, velocity, composited and bloom framebuffers, in that order, then the
+        // resolved scene if there is one. Colors are only brought down to what the screen shows by
+        // the composite step.
+        let mut layout = vec![
+            (size, gl::RGBA16F, depth, samples),
+            (size, gl::RGBA16F, None, 0),
+            (size, gl::RGBA16F, depth, 0),
+            (size, gl::RGBA8, None, 0),
+            (half, gl::RGBA16F, None, 0),
+            (half, gl::RGBA16F, None, 0),
+        ];
+        if samples > 0 {
+            layout.push((size, gl::RGBA16F, depth, 0));
+        }
+        let mut framebuffers = Vec::with_capacity(layout.len());
+        for (size, format, depth, samples) in layout {
+            match Framebuffer::multisampled(size, &[format], depth, samples) {
+                Ok(framebuffer) => framebuffers.push(framebuffer),
+                Err(e) => {
+                    for framebuffer in framebuffers {
+                        framebuffer.delete();
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        let mut framebuffers = framebuffers.into_iter();
+        let mut next = || framebuffers.next().expect("Every framebuffer was made");
+        let mut vao = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        Ok(PostProcess {
+            scene: next(),
+            focused: next(),
+            velocity: next(),
+            composited: next(),
+            bloom: [next(), next()],
+            resolved: framebuffers.next(),
+            vao,
+            reprojection: glm::identity(),
+            previous_framebuffer: 0,
+            previous_viewport: [0; 4],
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.scene.size()
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.scene.samples()
+    }
+
+    // What has been drawn of the scene since `begin`, for filtering it before `end`, but not while
+    // drawing into it. Multisampled, it is resolved first, which is what costs.
+    pub unsafe fn resolve_scene(&self) -> u32 {
+        self.resolved_scene().color_texture(0)
+    }
+
+    // The scene's framebuffer with one sample per pixel, which is itself without multisampling
+    unsafe fn resolved_scene(&self) -> &Framebuffer {
+        match &self.resolved {
+            Some(resolved) => {
+                if let Err(e) = self.scene.resolve(resolved) {
+                    warn!("Failed to resolve the scene's samples: {}", e);
+                }
+                resolved
+            }
+            None => &self.scene,
+        }
+    }
+
+    // Draw into the scene's texture, cleared to `clear_color`, from now on, until `end`
+    pub unsafe fn begin(&mut self, clear_color: &glm::Vec4) {
+        self.previous_framebuffer = offscreen::bound_framebuffer();
+        gl::GetIntegerv(gl::VIEWPORT, self.previous_viewport.as_mut_ptr());
+        self.scene.bind();
+        gl::ClearBufferfv(gl::COLOR, 0, clear_color.as_ptr());
+        gl::ClearBufferfv(gl::DEPTH, 0, &1.0);
+    }
+
+    // Have `pipeline`, the simple one, draw how far the meshes drawn from now on moved on the screen
+    // since the last frame, until `end_velocity`, for motion blur. They are seen with
+    // `view_projection` now and were seen with `previous_view_projection` last frame.
+    pub unsafe fn begin_velocity(
+        &mut self,
+        backend: &GlBackend,
+        pipeline: PipelineHandle,
+        view_projection: &glm::Mat4,
+        previous_view_projection: &glm::Mat4,
+    ) {
+        self.reprojection = previous_view_projection * glm::inverse(view_projection);
+        self.velocity.bind();
+        gl::ClearBufferfv(gl::COLOR, 0, glm::Vec4::zeros().as_ptr());
+        gl::ClearBufferfv(gl::DEPTH, 0, &1.0);
+        deferred::set_pass(backend, pipeline, Pass::Velocity);
+        let location = backend
+            .uniforms(pipeline)
+            .get("previousViewProjection")
+            .map_or(-1, |uniform| uniform.location);
+        gl::ProgramUniformMatrix4fv(
+            backend.program_id(pipeline),
+            location,
+            1,
+            gl::FALSE,
+            previous_view_projection.as_ptr(),
+        );
+    }
+
+    // Go back to drawing the scene with `pipeline`
+    pub unsafe fn end_velocity(&self, backend: &GlBackend, pipeline: PipelineHandle) {
+        deferred::set_pass(backend, pipeline, Pass::Forward);
+        self.scene.bind();
+    }
+
+    // Filter the scene with `pipeline`, the post one, into where `begin` was called, and draw
+    // there from now on. Nothing but the scene's colors is drawn there, not its depth. The colors
+    // are multiplied by `exposure` before they are tone mapped. The scene's depth is turned back
+    // into distances with `projection`, which it was drawn with, for the depth of field. Motion
+    // blur takes the velocities drawn since `begin_velocity`. With `fxaa`, the edges are smoothed
+    // last.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn end(
+        &self,
+        backend: &mut GlBackend,
+        pipeline: PipelineHandle,
+        depth_of_field: Option<&DepthOfField>,
+        motion_blur: Option<&MotionBlur>,
+        bloom: Option<&BloomSettings>,
+        tone_mapping: ToneMapping,
+        exposure: f32,
+        projection: &glm::Mat4,
+        fxaa: bool,
+    ) {
+        backend.set_pipeline(pipeline);
+        let program = backend.program_id(pipeline);
+        let uniforms = backend.uniforms(pipeline);
+        let location = |name: &str| uniforms.get(name).map_or(-1, |uniform| uniform.location);
+        let step = location("postStep");
+        gl::ProgramUniform1i(program, location("source"), POST_TEXTURE_UNIT as i32);
+        gl::ProgramUniform1i(program, location("bloom"), POST_TEXTURE_UNIT as i32 + 1);
+
+        gl::BindVertexArray(self.vao);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::BLEND);
+        // The triangle covers the screen even when the scene is drawn in wireframe
+        let mut polygon_mode = [gl::FILL as i32; 2];
+        gl::GetIntegerv(gl::POLYGON_MODE, polygon_mode.as_mut_ptr());
+        gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+
+        let bind = |texture: u32, unit: u32| {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+        };
+        let draw = |step_value: Step| {
+            gl::ProgramUniform1i(program, step, step_value as i32);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        };
+
+        // What the steps after the depth of field and the motion blur filter
+        let scene = self.resolved_scene();
+        let mut source = scene.color_texture(0);
+        if let Some(focus) = depth_of_field {
+            gl::ProgramUniform1i(program, location("depth"), POST_DEPTH_TEXTURE_UNIT as i32);
+            let depth_projection = [projection[(2, 2)], projection[(2, 3)]];
+            gl::ProgramUniform2fv(
+                program,
+                location("depthProjection"),
+                1,
+                depth_projection.as_ptr(),
+            );
+            gl::ProgramUniform1f(program, location("focusDistance"), focus.focus_distance);
+            gl::ProgramUniform1f(program, location("focusRange"), focus.focus_range);
+            let max_blur = focus.max_blur.clamp(0.0, MAX_FOCUS_BLUR);
+            gl::ProgramUniform1f(program, location("maxFocusBlur"), max_blur);
+            self.focused.bind();
+            bind(source, POST_TEXTURE_UNIT);
+            bind(scene.depth_texture().unwrap_or(0), POST_DEPTH_TEXTURE_UNIT);
+            draw(Step::DepthOfField);
+            source = self.focused.color_texture(0);
+        }
+
+        // Into whichever of the two full-size textures isn't the source, as the scene has already
+        // been filtered if the focused one is
+        if let Some(motion_blur) = motion_blur {
+            gl::ProgramUniform1i(
+                program,
+                location("velocity"),
+                POST_VELOCITY_TEXTURE_UNIT as i32,
+            );
+            gl::ProgramUniformMatrix4fv(
+                program,
+                location("skyReprojection"),
+                1,
+                gl::FALSE,
+                self.reprojection.as_ptr(),
+            );
+            gl::ProgramUniform1f(program, location("shutter"), motion_blur.shutter.max(0.0));
+            let max_blur = motion_blur.max_blur.clamp(0.0, MAX_MOTION_BLUR);
+            gl::ProgramUniform1f(program, location("maxMotionBlur"), max_blur);
+            let target = if source == scene.color_texture(0) {
+                &self.focused
+            } else {
+                scene
+            };
+            target.bind();
+            bind(source, POST_TEXTURE_UNIT);
+            bind(self.velocity.color_texture(0), POST_VELOCITY_TEXTURE_UNIT);
+            draw(Step::MotionBlur);
+            source = target.color_texture(0);
+        }
+
+        // Without bloom, nothing is added to the scene
+        bind(self.bloom[0].color_texture(0), POST_TEXTURE_UNIT + 1);
+        let intensity = bloom.map_or(0.0, |bloom| bloom.intensity);
+        gl::ProgramUniform1f(program, location("bloomIntensity"), intensity);
+        if let Some(bloom) = bloom.filter(|bloom| bloom.intensity > 0.0) {
+            gl::ProgramUniform1f(program, location("bloomThreshold"), bloom.threshold);
+            self.bloom[0].bind();
+            bind(source, POST_TEXTURE_UNIT);
+            draw(Step::Bright);
+
+            let direction = location("blurDirection");
+            for _ in 0..bloom.blur_passes {
+                for (from, to, across) in [(0, 1, [1.0, 0.0]), (1, 0, [0.0, 1.0])] {
+                    self.bloom[to].bind();
+                    bind(self.bloom[from].color_texture(0), POST_TEXTURE_UNIT);
+                    gl::ProgramUniform2fv(program, direction, 1, across.as_ptr());
+                    draw(Step::Blur);
+                }
+            }
+        }
+
+        let restore = || {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.previous_framebuffer);
+            let [x, y, width, height] = self.previous_viewport;
+            gl::Viewport(x, y, width, height);
+        };
+        if fxaa {
+            self.composited.bind();
+        } else {
+            restore();
+        }
+        bind(source, POST_TEXTURE_UNIT);
+        gl::ProgramUniform1i(program, location("toneMapping"), tone_mapping as i32);
+        gl::ProgramUniform1f(program, location("exposure"), exposure);
+        draw(Step::Composite);
+
+        if fxaa {
+            restore();
+            bind(self.composited.color_texture(0), POST_TEXTURE_UNIT);
+            draw(Step::Fxaa);
+        }
+
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::PolygonMode(gl::FRONT_AND_BACK, polygon_mode[0] as u32);
+        gl::Enable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::BindVertexArray(0);
+    }
+
+    pub unsafe fn delete(self) {
+        self.scene.delete();
+        if let Some(resolved) = self.resolved {
+            resolved.delete();
+        }
+        self.focused.delete();
+        self.velocity.delete();
+        self.composited.delete();
+        for framebuffer in self.bloom {
+            framebuffer.delete();
+        }
+        gl::DeleteVertexArrays(1, &self.vao);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tone_mappings_are_named_both_ways() {
+        for tone_mapping in ToneMapping::ALL {
+            assert_eq!(tone_mapping.to_string().parse(), Ok(tone_mapping));
+        }
+        assert_eq!("gamma".parse::<ToneMapping>(), Err(()));
+        assert_eq!(ToneMapping::default(), ToneMapping::Aces);
+    }
+}
@@ -0,0 +1,126 @@
+use std::fs;
+use std::path::PathBuf;
+
+// The props placed in the demo scene by hand, saved with Ctrl+S and placed again on the next run.
+//
+// The file is plain text with one prop per line, a parked helicopter by its position or a model
+// dropped onto the window by its position, scale and path:
+//   helicopter 12.5 3 -40
+//   model 0 10 -30 0.25 resources/teapot.obj
+// Lines starting with `#` are comments.
+pub const SCENE_PATH: &str = "gloom.scene";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlacedModel {
+    pub path: PathBuf,
+    pub position: glm::Vec3,
+    pub scale: f32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Props {
+    pub helicopters: Vec<glm::Vec3>,
+    pub models: Vec<PlacedModel>,
+}
+
+impl Props {
+    // Load the props saved at `path`. A missing file is an empty scene.
+    pub fn load(path: &str) -> Result<Props, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => contents.parse().map_err(|e| format!("{}:{}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Props::default()),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_string()).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+impl std::str::FromStr for Props {
+    type Err = String;
+
+    // Errors start with the line number
+    fn from_str(contents: &str) -> Result<Props, String> {
+        let mut props = Props::default();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let malformed = || format!("{}: malformed prop `{}`", line_number + 1, line);
+            let mut words = line.splitn(6, ' ');
+            let kind = words.next().unwrap_or_default();
+            let mut number = || -> Result<f32, String> {
+                words
+                    .next()
+                    .and_then(|word| word.parse().ok())
+                    .ok_or_else(malformed)
+            };
+            let position = glm::vec3(number()?, number()?, number()?);
+            match kind {
+                "helicopter" => props.helicopters.push(position),
+                "model" => {
+                    let scale = number()?;
+                    let path = words.next().ok_or_else(malformed)?;
+                    props.models.push(PlacedModel {
+                        path: PathBuf::from(path),
+                        position,
+                        scale,
+                    });
+                }
+                _ => return Err(malformed()),
+            }
+        }
+        Ok(props)
+    }
+}
+
+impl std::fmt::Display for Props {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "# gloom-rs scene")?;
+        for position in &self.helicopters {
+            writeln!(f, "helicopter {} {} {}", position.x, position.y, position.z)?;
+        }
+        for model in &self.models {
+            let position = model.position;
+            writeln!(
+                f,
+                "model {} {} {} {} {}",
+                position.x,
+                position.y,
+                position.z,
+                model.scale,
+                model.path.display()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn props_are_read_back_as_saved() {
+        let props = Props {
+            helicopters: vec![glm::vec3(12.5, 3.0, -40.0), glm::vec3(0.1, 0.2, 0.3)],
+            models: vec![PlacedModel {
+                path: PathBuf::from("resources/a teapot.obj"),
+                position: glm::vec3(0.0, 10.0, -30.0),
+                scale: 0.25,
+            }],
+        };
+        assert_eq!(props.to_string().parse(), Ok(props));
+    }
+
+    #[test]
+    fn malformed_props_name_their_line() {
+        let error = "# comment\nhelicopter 1 2\n".parse::<Props>().unwrap_err();
+        assert!(error.starts_with("2: "), "{}", error);
+        assert!("lamp 1 2 3".parse::<Props>().is_err());
+        assert!("model 1 2 3 1".parse::<Props>().is_err());
+    }
+}
@@ -0,0 +1,157 @@
+// Trading image quality for frame rate.
+//
+// The governor watches how long frames take and steps down through a list of quality levels when
+// they run over the budget of the target frame rate, and back up once there is plenty of time to
+// spare. The render loop applies the resolution scale by drawing into a smaller framebuffer and
+// stretching it over the window. Applications read `Context::quality` to scale their own costly
+// effects, e.g. shadow map resolution or particle counts, by `detail`.
+use log::info;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quality {
+    pub resolution_scale: f32, // Fraction of the window's width and height that is rendered
+    pub detail: f32,           // Fraction of the full effect detail, from 0 to 1
+}
+
+impl Quality {
+    pub const FULL: Quality = Quality {
+        resolution_scale: 1.0,
+        detail: 1.0,
+    };
+}
+
+// From the best to the cheapest
+const LEVELS: [Quality; 5] = [
+    Quality::FULL,
+    Quality {
+        resolution_scale: 1.0,
+        detail: 0.5,
+    },
+    Quality {
+        resolution_scale: 0.85,
+        detail: 0.5,
+    },
+    Quality {
+        resolution_scale: 0.7,
+        detail: 0.25,
+    },
+    Quality {
+        resolution_scale: 0.5,
+        detail: 0.25,
+    },
+];
+
+// Frames slower than this part of the budget count as over it, and faster than this as idle
+const OVER_BUDGET: f32 = 1.1;
+const IDLE: f32 = 0.7;
+// How long frames have to stay over budget or idle before the quality changes. Raising it waits
+// longer, so a level that was just too slow isn't tried again right away.
+const LOWER_AFTER: f32 = 0.5;
+const RAISE_AFTER: f32 = 3.0;
+
+pub struct QualityGovernor {
+    frame_budget: f32, // Seconds
+    level: usize,
+    average_frame_time: f32, // Smoothed, so single slow frames don't change anything
+    over_budget_for: f32,
+    idle_for: f32,
+}
+
+impl QualityGovernor {
+    pub fn new(target_fps: u32) -> QualityGovernor {
+        let frame_budget = 1.0 / target_fps.max(1) as f32;
+        QualityGovernor {
+            frame_budget,
+            level: 0,
+            average_frame_time: frame_budget,
+            over_budget_for: 0.0,
+            idle_for: 0.0,
+        }
+    }
+
+    pub fn quality(&self) -> Quality {
+        LEVELS[self.level]
+    }
+
+    // Account for a frame that took `frame_time` seconds. Returns the new quality if it changed.
+    pub fn frame(&mut self, frame_time: f32) -> Option<Quality> {
+        self.average_frame_time += (frame_time - self.average_frame_time) * 0.1;
+        let load = self.average_frame_time / self.frame_budget;
+
+        self.over_budget_for = if load > OVER_BUDGET {
+            self.over_budget_for + frame_time
+        } else {
+            0.0
+        };
+        self.idle_for = if load < IDLE {
+            self.idle_for + frame_time
+        } else {
+            0.0
+        };
+
+        let level = if self.over_budget_for > LOWER_AFTER && self.level + 1 < LEVELS.len() {
+            self.level + 1
+        } else if self.idle_for > RAISE_AFTER && self.level > 0 {
+            self.level - 1
+        } else {
+            return None;
+        };
+        self.level = level;
+        self.over_budget_for = 0.0;
+        self.idle_for = 0.0;
+        let quality = self.quality();
+        info!(
+            "Frames take {:.1}ms against a budget of {:.1}ms, rendering at {:.0}% resolution with \
+             {:.0}% detail",
+            self.average_frame_time * 1e3,
+            self.frame_budget * 1e3,
+            quality.resolution_scale * 100.0,
+            quality.detail * 100.0
+        );
+        Some(quality)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feed frames of `frame_time` for `seconds`, returning the levels stepped to
+    fn run(governor: &mut QualityGovernor, frame_time: f32, seconds: f32) -> Vec<Quality> {
+        let frames = (seconds / frame_time) as usize;
+        (0..frames)
+            .filter_map(|_| governor.frame(frame_time))
+            .collect()
+    }
+
+    #[test]
+    fn slow_frames_lower_the_quality_one_level_at_a_time_down_to_the_last() {
+        let mut governor = QualityGovernor::new(60);
+        // A single slow frame is smoothed away
+        assert_eq!(governor.frame(0.1), None);
+        assert_eq!(run(&mut governor, 1.0 / 60.0, 1.0), vec![]);
+
+        let stepped = run(&mut governor, 1.0 / 20.0, 0.8);
+        assert_eq!(stepped, vec![LEVELS[1]]);
+        assert_eq!(run(&mut governor, 1.0 / 20.0, 30.0), LEVELS[2..].to_vec());
+        assert_eq!(governor.quality(), LEVELS[LEVELS.len() - 1]);
+    }
+
+    #[test]
+    fn quality_is_raised_only_after_a_while_of_spare_time() {
+        let mut governor = QualityGovernor::new(60);
+        run(&mut governor, 1.0 / 20.0, 3.0);
+        let lowered = governor.level;
+        assert!(lowered >= 2);
+
+        // Frames just within the budget are neither over it nor idle, so the quality stays
+        assert_eq!(run(&mut governor, 1.0 / 60.0, 30.0), vec![]);
+        assert_eq!(governor.level, lowered);
+
+        // With time to spare it comes back up, a level per RAISE_AFTER, and no further than full
+        assert_eq!(run(&mut governor, 1.0 / 120.0, RAISE_AFTER * 0.9), vec![]);
+        let raised = run(&mut governor, 1.0 / 120.0, 60.0);
+        assert_eq!(raised.len(), lowered);
+        assert_eq!(governor.quality(), Quality::FULL);
+    }
+}
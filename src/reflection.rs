@@ -0,0 +1,139 @@
+// Planar reflections: mirror-like surfaces, such as water or ice, reflecting the scene around them.
+//
+// A flat mirror shows the scene as seen by a camera mirrored in its plane. So before the scene is
+// drawn, it is drawn once more into a `PlanarReflection`, the size of the viewport, with the view
+// mirrored, see `mirror`. Whatever is below the plane would show up in the reflection as if it were
+// above it, so it is cut off by a clip plane: the simple vertex shader writes how far above the
+// plane each vertex is to `gl_ClipDistance`, and OpenGL clips away the parts below it.
+//
+// Drawing the scene afterwards, reflective surfaces (see `Material::reflectivity`) are drawn over
+// their reflection: each fragment looks the reflection up where it is on the screen, as the mirrored
+// camera's view lines up with the camera's on the plane, and mixes it in, more so towards grazing
+// angles (Schlick). The reflective surfaces themselves are left out of their reflection, and only
+// one plane is mirrored, so all of them had better lie in it.
+//
+// The simple pipeline draws reflective surfaces forward, as the G-buffer has no room for how
+// reflective they are, so they are drawn after the rest of the scene, with the see-through meshes.
+use crate::backend::gl::GlBackend;
+use crate::backend::PipelineHandle;
+use crate::error::RenderError;
+use crate::offscreen::{self, Framebuffer};
+
+// Where the reflection is bound while drawing, clear of the image-based lighting's maps
+pub const REFLECTION_TEXTURE_UNIT: u32 = 14;
+
+// The matrix mirroring points in `plane`, whose xyz is its normal, of length 1, and w its distance
+// from the origin against the normal, so points on it have `dot(normal, point) + w = 0`
+pub fn mirror(plane: &glm::Vec4) -> glm::Mat4 {
+    let normal = plane.xyz();
+    let mut matrix =
+        glm::mat3_to_mat4(&(glm::Mat3::identity() - 2.0 * normal * normal.transpose()));
+    matrix.set_column(3, &(-2.0 * plane.w * normal).push(1.0));
+    matrix
+}
+
+// Have `pipeline`, the simple one, draw the scene mirrored in `plane`, with what is below it cut
+// off, or as usual again with None. The camera at `camera_position` is mirrored too, for the
+// highlights.
+pub unsafe fn set_mirror(
+    backend: &GlBackend,
+    pipeline: PipelineHandle,
+    plane: Option<&glm::Vec4>,
+    camera_position: &glm::Vec3,
+) {
+    let program = backend.program_id(pipeline);
+    let uniforms = backend.uniforms(pipeline);
+    let location = |name: &str| uniforms.get(name).map_or(-1, |uniform| uniform.location);
+    let camera = match plane {
+        Some(plane) => (mirror(plane) * camera_position.push(1.0)).xyz(),
+        None => *camera_position,
+    };
+    gl::ProgramUniform3fv(program, location("cameraPosition"), 1, camera.as_ptr());
+    let clip_plane = plane.copied().unwrap_or_else(glm::Vec4::zeros);
+    gl::ProgramUniform4fv(program, location("clipPlane"), 1, clip_plane.as_ptr());
+    // Mirroring turns the triangles' corners the other way around, which would cull their fronts
+    if plane.is_some() {
+        gl::Enable(gl::CLIP_DISTANCE0);
+        gl::FrontFace(gl::CW);
+    } else {
+        gl::Disable(gl::CLIP_DISTANCE0);
+        gl::FrontFace(gl::CCW);
+    }
+}
+
+pub struct PlanarReflection {
+    framebuffer: Framebuffer,
+    previous_framebuffer: u32,
+    previous_viewport: [i32; 4],
+}
+
+impl PlanarReflection {
+    pub unsafe fn new(size: (u32, u32)) -> Result<PlanarReflection, RenderError> {
+        let framebuffer = Framebuffer::new(size, &[gl::RGBA16F], Some(gl::DEPTH_COMPONENT24))?;
+        Ok(PlanarReflection {
+            framebuffer,
+            previous_framebuffer: 0,
+            previous_viewport: [0; 4],
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.framebuffer.size()
+    }
+
+    // Draw into the reflection, cleared to `clear_color`, from now on, until `end`
+    pub unsafe fn begin(&mut self, clear_color: &glm::Vec4) {
+        self.previous_framebuffer = offscreen::bound_framebuffer();
+        gl::GetIntegerv(gl::VIEWPORT, self.previous_viewport.as_mut_ptr());
+        self.framebuffer.bind();
+        gl::ClearBufferfv(gl::COLOR, 0, clear_color.as_ptr());
+        gl::ClearBufferfv(gl::DEPTH, 0, &1.0);
+    }
+
+    // Go back to drawing where `begin` was called
+    pub unsafe fn end(&self) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.previous_framebuffer);
+        let [x, y, width, height] = self.previous_viewport;
+        gl::Viewport(x, y, width, height);
+    }
+
+    // Have `pipeline`, the simple one, draw reflective surfaces over the reflection, or without it
+    // if not `enabled`
+    pub unsafe fn apply(&self, backend: &GlBackend, pipeline: PipelineHandle, enabled: bool) {
+        let program = backend.program_id(pipeline);
+        let uniforms = backend.uniforms(pipeline);
+        let location = |name: &str| uniforms.get(name).map_or(-1, |uniform| uniform.location);
+        gl::ProgramUniform1i(program, location("reflections"), enabled as i32);
+        gl::ProgramUniform1i(
+            program,
+            location("reflectionMap"),
+            REFLECTION_TEXTURE_UNIT as i32,
+        );
+        gl::ActiveTexture(gl::TEXTURE0 + REFLECTION_TEXTURE_UNIT);
+        gl::BindTexture(gl::TEXTURE_2D, self.framebuffer.color_texture(0));
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+
+    pub unsafe fn delete(self) {
+        self.framebuffer.delete();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrors_swap_the_sides_of_their_plane() {
+        // The plane Y = 2, facing up
+        let plane = glm::vec4(0.0, 1.0, 0.0, -2.0);
+        let matrix = mirror(&plane);
+        let mirrored = matrix * glm::vec4(3.0, 5.0, -1.0, 1.0);
+        assert!((mirrored - glm::vec4(3.0, -1.0, -1.0, 1.0)).norm() < 1e-6);
+        // Directions only flip across it
+        let up = matrix * glm::vec4(0.0, 1.0, 0.0, 0.0);
+        assert!((up - glm::vec4(0.0, -1.0, 0.0, 0.0)).norm() < 1e-6);
+        // And mirroring twice is no mirroring at all
+        assert!((matrix * matrix - glm::Mat4::identity()).norm() < 1e-6);
+    }
+}
@@ -0,0 +1,314 @@
+// Drawing with OpenGL: uploading meshes, setting up a fresh context and drawing the scene graph
+use crate::arena::FrameArena;
+use crate::backend::{self, Backend};
+use crate::commands::{Command, CommandList, DrawCommand};
+use crate::error::GlError;
+use crate::gl_check;
+use crate::mesh::Mesh;
+use crate::scene_graph::SceneNode;
+use crate::util;
+use log::info;
+use rayon::prelude::*;
+use std::{mem, os::raw::c_void, ptr};
+
+// == // Helper functions to make interacting with OpenGL a little bit prettier. You *WILL* need these! // == //
+
+// Get the size of an arbitrary array of numbers measured in bytes
+// Example usage:  byte_size_of_array(my_array)
+fn byte_size_of_array<T>(val: &[T]) -> isize {
+    std::mem::size_of_val(val) as isize
+}
+
+// Get the OpenGL-compatible pointer to an arbitrary array of numbers
+// Example usage:  pointer_to_array(my_array)
+fn pointer_to_array<T>(val: &[T]) -> *const c_void {
+    &val[0] as *const T as *const c_void
+}
+
+// Get the size of the given type in bytes
+// Example usage:  size_of::<u64>()
+fn size_of<T>() -> i32 {
+    mem::size_of::<T>() as i32
+}
+
+// Get an offset in bytes for n units of type T, represented as a relative pointer
+// Example usage:  offset::<u64>(4)
+fn offset<T>(n: u32) -> *const c_void {
+    (n * mem::size_of::<T>() as u32) as *const T as *const c_void
+}
+
+// Get a null pointer (equivalent to an offset of 0)
+// ptr::null()
+
+// A vertex array object, along with how many indices there are to draw from it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Vao {
+    pub id: u32,
+    pub index_count: i32,
+}
+
+impl Vao {
+    // Upload a mesh into a new VAO. Positions, colors and normals always get a buffer each, at
+    // attribute locations 0, 1 and 2, while UVs (3), tangents (4), joints (5) and weights (6) only
+    // get one if the mesh has them for every vertex. Fails in debug builds if OpenGL rejects any of the buffers, which
+    // leaves the VAO and its buffers behind.
+    pub unsafe fn from_mesh(mesh: &Mesh) -> Result<Vao, GlError> {
+        unsafe {
+            let mut id = 0;
+            let mut ibo = 0;
+
+            gl::GenVertexArrays(1, &mut id);
+            gl::BindVertexArray(id);
+
+            // Index Buffer Object
+            gl::GenBuffers(1, &mut ibo);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ibo);
+            gl_check!(gl::BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                byte_size_of_array(&mesh.indices),
+                pointer_to_array(&mesh.indices),
+                gl::STATIC_DRAW,
+            ))?;
+
+            attribute_buffer(0, 3, &mesh.vertices)?;
+            attribute_buffer(1, 4, &mesh.colors)?;
+            attribute_buffer(2, 3, &mesh.normals)?;
+            let vertex_count = mesh.vertices.len() / 3;
+            if vertex_count > 0 && mesh.uvs.len() == vertex_count * 2 {
+                attribute_buffer(3, 2, &mesh.uvs)?;
+            }
+            if vertex_count > 0 && mesh.tangents.len() == vertex_count * 4 {
+                attribute_buffer(4, 4, &mesh.tangents)?;
+            }
+            if vertex_count > 0 && mesh.joints.len() == vertex_count * 4 {
+                attribute_buffer(5, 4, &mesh.joints)?;
+            }
+            if vertex_count > 0 && mesh.weights.len() == vertex_count * 4 {
+                attribute_buffer(6, 4, &mesh.weights)?;
+            }
+
+            gl::BindVertexArray(0);
+
+            Ok(Vao {
+                id,
+                index_count: mesh.index_count,
+            })
+        }
+    }
+}
+
+// Fill a new buffer with `data` and feed it to the bound VAO's attribute at `location`, with
+// `components` floats per vertex
+unsafe fn attribute_buffer(location: u32, components: i32, data: &[f32]) -> Result<(), GlError> {
+    unsafe {
+        let mut buffer = 0;
+        gl::GenBuffers(1, &mut buffer);
+        gl::BindBuffer(gl::ARRAY_BUFFER, buffer);
+        gl_check!(gl::BufferData(
+            gl::ARRAY_BUFFER,
+            byte_size_of_array(data),
+            pointer_to_array(data),
+            gl::STATIC_DRAW,
+        ))?;
+        gl_check!(gl::VertexAttribPointer(
+            location,
+            components,
+            gl::FLOAT,
+            gl::FALSE,
+            components * size_of::<f32>(),
+            offset::<f32>(0),
+        ))?;
+        gl_check!(gl::EnableVertexAttribArray(location))
+    }
+}
+
+// Set up the OpenGL state the renderer expects on a freshly created context, returning what the
+// context can do
+pub unsafe fn init_gl(multisampling: bool) -> util::GlCapabilities {
+    gl::Enable(gl::DEPTH_TEST);
+    gl::DepthFunc(gl::LESS);
+    gl::Enable(gl::CULL_FACE);
+    if multisampling {
+        gl::Enable(gl::MULTISAMPLE);
+    } else {
+        gl::Disable(gl::MULTISAMPLE);
+    }
+    gl::Enable(gl::BLEND);
+    gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+    // Debug output needs OpenGL 4.3 or KHR_debug, neither of which macOS has
+    let capabilities = util::GlCapabilities::detect();
+    let features = &capabilities.features;
+    if features.debug_output {
+        // Off by default in contexts not created for debugging
+        gl::Enable(gl::DEBUG_OUTPUT);
+        gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+        gl::DebugMessageCallback(Some(util::debug_callback), ptr::null());
+    }
+
+    // Print some diagnostics
+    info!("{}: {}", capabilities.vendor, capabilities.renderer);
+    info!("OpenGL\t: {}", capabilities.version_string);
+    info!("GLSL\t: {}", capabilities.glsl_version);
+    let yes_no = |supported| if supported { "yes" } else { "no" };
+    info!(
+        "Debug output: {}, compute shaders: {}, storage buffers: {}, buffer storage: {}",
+        yes_no(features.debug_output),
+        yes_no(features.compute_shaders),
+        yes_no(features.storage_buffers),
+        yes_no(features.buffer_storage)
+    );
+    capabilities
+}
+
+// Draw `node` and its children, each with its own model-view-projection matrix
+pub fn draw_scene(
+    node: &SceneNode,
+    view_projection_matrix: &glm::Mat4,
+    transformation_so_far: &glm::Mat4,
+    backend: &mut dyn Backend,
+) {
+    let local_transform = node.local_transform();
+
+    let combined_transform = transformation_so_far * local_transform;
+
+    let mvp_matrix = view_projection_matrix * combined_transform;
+
+    if node.is_drawn() {
+        backend.draw(&backend::DrawCall {
+            mesh: backend::MeshHandle(node.vao_id),
+            index_count: node.index_count,
+            transform: &mvp_matrix,
+            model: &combined_transform,
+            previous_model: node.previous_model.as_ref().unwrap_or(&combined_transform),
+            highlight: if node.selected { 1.0 } else { 0.0 },
+            opacity: node.opacity,
+            joints: node.joints.as_deref().unwrap_or_default(),
+            normal_map: node.normal_map,
+            material: node.material,
+        });
+    }
+
+    for &child in &node.children {
+        draw_scene(
+            unsafe { &*child },
+            view_projection_matrix,
+            &combined_transform,
+            backend,
+        );
+    }
+}
+
+// Lets scene nodes be read from rayon's worker threads. Nodes are only read while recording, and
+// the caller waits for that to finish before touching the scene again.
+#[derive(Clone, Copy)]
+struct SharedNode(*const SceneNode);
+unsafe impl Send for SharedNode {}
+unsafe impl Sync for SharedNode {}
+
+// Record the draws `draw_scene` would make at the end of a command list, without touching OpenGL,
+// so it can be done on any thread and replayed on the one with the context. The root's subtrees,
+// e.g. one per helicopter, are traversed in parallel on rayon's thread pool, which pays off once
+// the scene has hundreds of them. The draws come out in the same order either way.
+//
+// The draws of each subtree are counted first, so every subtree can be recorded straight into its
+// own part of the list. The bookkeeping goes in `arena`, and reusing the list between frames means
+// recording doesn't allocate at all.
+pub fn record_scene(
+    root: &SceneNode,
+    view_projection_matrix: &glm::Mat4,
+    commands: &mut CommandList,
+    arena: &FrameArena,
+) {
+    let root_transform = root.local_transform();
+    if root.is_drawn() {
+        commands.draw(node_draw(root, view_projection_matrix, root_transform));
+    }
+
+    let counts = arena.alloc_slice(root.children.iter().map(|&child| (SharedNode(child), 0)));
+    counts
+        .par_iter_mut()
+        .with_min_len(16)
+        .for_each(|(subtree, count)| *count = count_draws(unsafe { &*subtree.0 }));
+
+    let total = counts.iter().map(|&(_, count)| count).sum();
+    let mut slots = commands.append_slots(total);
+    let parts = arena.alloc_slice(counts.iter().map(|&(subtree, count)| {
+        let (part, rest) = std::mem::take(&mut slots).split_at_mut(count);
+        slots = rest;
+        (subtree, part)
+    }));
+    parts
+        .par_iter_mut()
+        .with_min_len(16)
+        .for_each(|(subtree, part)| {
+            record_node(
+                unsafe { &*subtree.0 },
+                view_projection_matrix,
+                &root_transform,
+                &mut part.iter_mut(),
+            );
+        });
+}
+
+// The nodes `record_scene` draws, in the order it draws them
+pub fn drawn_nodes(root: &SceneNode) -> Vec<&SceneNode> {
+    let mut nodes = vec![];
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if node.is_drawn() {
+            nodes.push(node);
+        }
+        stack.extend(node.children.iter().rev().map(|&child| unsafe { &*child }));
+    }
+    nodes
+}
+
+fn count_draws(node: &SceneNode) -> usize {
+    let children: usize = node
+        .children
+        .iter()
+        .map(|&child| count_draws(unsafe { &*child }))
+        .sum();
+    children + node.is_drawn() as usize
+}
+
+fn record_node(
+    node: &SceneNode,
+    view_projection_matrix: &glm::Mat4,
+    transformation_so_far: &glm::Mat4,
+    slots: &mut std::slice::IterMut<Command>,
+) {
+    let combined_transform = transformation_so_far * node.local_transform();
+    if node.is_drawn() {
+        if let Some(slot) = slots.next() {
+            *slot = Command::Draw(node_draw(node, view_projection_matrix, combined_transform));
+        }
+    }
+    for &child in &node.children {
+        record_node(
+            unsafe { &*child },
+            view_projection_matrix,
+            &combined_transform,
+            slots,
+        );
+    }
+}
+fn node_draw(
+    node: &SceneNode,
+    view_projection_matrix: &glm::Mat4,
+    model: glm::Mat4,
+) -> DrawCommand {
+    DrawCommand {
+        mesh: backend::MeshHandle(node.vao_id),
+        index_count: node.index_count,
+        transform: view_projection_matrix * model,
+        // Nodes drawn for the first time haven't moved
+        previous_model: node.previous_model.unwrap_or(model),
+        model,
+        highlight: if node.selected { 1.0 } else { 0.0 },
+        opacity: node.opacity,
+        joints: node.joints.clone(),
+        normal_map: node.normal_map,
+        material: node.material,
+    }
+}
@@ -0,0 +1,382 @@
+use crate::input::{FrameInput, InputCollector, InputEvent};
+use serde::de::value::StrDeserializer;
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use winit::event::MouseButton;
+use winit::keyboard::KeyCode;
+
+// Recording and deterministic replay of user input.
+//
+// A recording is a text file with one timestamped event per line:
+//   0 resize 1024 768
+//   0.016666668 key KeyW down
+//   0.23333333 button Right up
+//   0.25 motion 3.5 -1
+//   0.25 cursor 4 -1
+//   0.25 position 412 300
+//   0.26666668 scroll 1
+//   0.26666668 pan 0 12
+//   0.26666668 zoom 0.05
+//   0.3 char 113
+//   0.5 end
+// Timestamps are seconds since the first frame, written with as many digits as it takes to read
+// back the exact same f32. During replay the events are fed back on a fixed timestep clock that
+// starts at zero, and each frame gets the events recorded at or before its time. A recording made
+// with the same fixed step (--deterministic) therefore replays frame for frame, and the `end` line
+// keeps the frames after the last event.
+
+pub const REPLAY_TIMESTEP: f32 = 1.0 / 60.0;
+
+// Time of a frame on the fixed timestep clock that replays and --deterministic runs share. Both
+// compute it the same way, so the times written by a deterministic recording compare equal to the
+// times of the replayed frames.
+pub fn fixed_step_time(frame: u32) -> f32 {
+    frame as f32 * REPLAY_TIMESTEP
+}
+
+pub struct InputRecorder {
+    file: BufWriter<File>,
+    window_size: (u32, u32),
+    cursor_position: Option<(f32, f32)>,
+    last_time: Option<f32>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &str) -> std::io::Result<InputRecorder> {
+        Ok(InputRecorder {
+            file: BufWriter::new(File::create(path)?),
+            window_size: (0, 0),
+            cursor_position: None,
+            last_time: None,
+        })
+    }
+
+    // Write the input events that happened during one frame
+    pub fn record(&mut self, time: f32, input: &FrameInput) -> std::io::Result<()> {
+        self.last_time = Some(time);
+        let keys = &input.keys;
+        for &key in keys.released() {
+            // A key that is still held was released and pressed again within this frame
+            if keys.is_held(key) {
+                writeln!(self.file, "{} key {:?} up", time, key)?;
+            }
+        }
+        for &key in keys.pressed() {
+            writeln!(self.file, "{} key {:?} down", time, key)?;
+        }
+        for &key in keys.released() {
+            if !keys.is_held(key) {
+                writeln!(self.file, "{} key {:?} up", time, key)?;
+            }
+        }
+
+        let buttons = &input.buttons;
+        for &button in buttons.pressed() {
+            writeln!(self.file, "{} button {:?} down", time, button)?;
+        }
+        for &button in buttons.released() {
+            writeln!(self.file, "{} button {:?} up", time, button)?;
+        }
+
+        if input.mouse_delta != (0.0, 0.0) {
+            let (dx, dy) = input.mouse_delta;
+            writeln!(self.file, "{} motion {} {}", time, dx, dy)?;
+        }
+        if input.cursor_delta != (0.0, 0.0) {
+            let (dx, dy) = input.cursor_delta;
+            writeln!(self.file, "{} cursor {} {}", time, dx, dy)?;
+        }
+        if input.cursor_position != self.cursor_position {
+            self.cursor_position = input.cursor_position;
+            if let Some((x, y)) = input.cursor_position {
+                writeln!(self.file, "{} position {} {}", time, x, y)?;
+            }
+        }
+        if input.scroll_delta != 0.0 {
+            writeln!(self.file, "{} scroll {}", time, input.scroll_delta)?;
+        }
+        if input.pan_delta != (0.0, 0.0) {
+            let (dx, dy) = input.pan_delta;
+            writeln!(self.file, "{} pan {} {}", time, dx, dy)?;
+        }
+        if input.zoom_delta != 0.0 {
+            writeln!(self.file, "{} zoom {}", time, input.zoom_delta)?;
+        }
+        for c in input.text.chars() {
+            writeln!(self.file, "{} char {}", time, c as u32)?;
+        }
+        if input.window_size != self.window_size {
+            self.window_size = input.window_size;
+            let (width, height) = input.window_size;
+            writeln!(self.file, "{} resize {} {}", time, width, height)?;
+        }
+        Ok(())
+    }
+}
+
+// Mark when the last frame was recorded, so that frames without input at the end of the recording
+// are replayed too
+impl Drop for InputRecorder {
+    fn drop(&mut self) {
+        if let Some(time) = self.last_time {
+            let _ = writeln!(self.file, "{} end", time);
+        }
+    }
+}
+
+pub struct InputReplay {
+    events: Vec<(f32, InputEvent)>,
+    next_event: usize,
+    end_time: f32,
+    frames: u32,
+    time: f32,
+    input: InputCollector,
+}
+
+impl InputReplay {
+    pub fn load(path: &str, window_size: (u32, u32)) -> Result<InputReplay, String> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut events = vec![];
+        let mut end_time = 0.0f32;
+        for (line_number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("{}: {}", path, e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (time, event) = parse_event(&line).ok_or_else(|| {
+                format!("{}:{}: malformed event `{}`", path, line_number + 1, line)
+            })?;
+            end_time = end_time.max(time);
+            events.extend(event.map(|event| (time, event)));
+        }
+        events.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Ok(InputReplay {
+            events,
+            next_event: 0,
+            end_time,
+            frames: 0,
+            time: 0.0,
+            input: InputCollector::new(window_size),
+        })
+    }
+
+    // True once the frame at the time of the last recorded event or frame has been returned
+    pub fn is_finished(&self) -> bool {
+        self.frames > 0 && self.time >= self.end_time
+    }
+
+    // Time on the fixed timestep clock of the frame that was last returned by `next_frame`
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    // Collect the input for the next frame and advance the clock by one fixed timestep. The first
+    // frame is at time zero, like the first recorded frame.
+    pub fn next_frame(&mut self) -> FrameInput {
+        self.time = fixed_step_time(self.frames);
+        self.frames += 1;
+
+        while let Some((event_time, event)) = self.events.get(self.next_event) {
+            if *event_time > self.time {
+                break;
+            }
+            self.input.apply(event.clone());
+            self.next_event += 1;
+        }
+        self.input.take_frame()
+    }
+}
+
+// Parse one line of a recording into its time and event. `end` lines have no event.
+fn parse_event(line: &str) -> Option<(f32, Option<InputEvent>)> {
+    let mut words = line.split_whitespace();
+    let time = words.next()?.parse().ok()?;
+    let event = match words.next()? {
+        "key" => InputEvent::Key(parse_key(words.next()?)?, parse_state(words.next()?)?),
+        "button" => InputEvent::Button(parse_button(words.next()?)?, parse_state(words.next()?)?),
+        "motion" => InputEvent::Motion(words.next()?.parse().ok()?, words.next()?.parse().ok()?),
+        "cursor" => InputEvent::Cursor(words.next()?.parse().ok()?, words.next()?.parse().ok()?),
+        "position" => InputEvent::Position(Some((
+            words.next()?.parse().ok()?,
+            words.next()?.parse().ok()?,
+        ))),
+        "scroll" => InputEvent::Scroll(words.next()?.parse().ok()?),
+        "pan" => InputEvent::Pan(words.next()?.parse().ok()?, words.next()?.parse().ok()?),
+        "zoom" => InputEvent::Zoom(words.next()?.parse().ok()?),
+        "char" => InputEvent::Char(char::from_u32(words.next()?.parse().ok()?)?),
+        "resize" => InputEvent::Resize(words.next()?.parse().ok()?, words.next()?.parse().ok()?),
+        "end" => return Some((time, None)),
+        _ => return None,
+    };
+    Some((time, Some(event)))
+}
+
+fn parse_state(word: &str) -> Option<bool> {
+    match word {
+        "down" => Some(true),
+        "up" => Some(false),
+        _ => None,
+    }
+}
+
+// Key names are written with their Debug representation, which matches the variant names winit
+// uses when deserializing keys
+fn parse_key(name: &str) -> Option<KeyCode> {
+    let name: StrDeserializer<serde::de::value::Error> = name.into_deserializer();
+    KeyCode::deserialize(name).ok()
+}
+
+fn parse_button(name: &str) -> Option<MouseButton> {
+    match name {
+        "Left" => Some(MouseButton::Left),
+        "Right" => Some(MouseButton::Right),
+        "Middle" => Some(MouseButton::Middle),
+        "Back" => Some(MouseButton::Back),
+        "Forward" => Some(MouseButton::Forward),
+        _ => {
+            let number = name.strip_prefix("Other(")?.strip_suffix(')')?;
+            Some(MouseButton::Other(number.parse().ok()?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_path(name: &str) -> String {
+        let file = format!("gloom-replay-{}-{}.txt", std::process::id(), name);
+        std::env::temp_dir()
+            .join(file)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    // Frames of scripted input: W pressed in one frame and released in the next, the mouse moved
+    // later on, and a few frames without input at the end
+    fn recorded_frames() -> Vec<FrameInput> {
+        let mut input = InputCollector::new((800, 600));
+        let mut frames = vec![];
+        for frame in 0..10 {
+            match frame {
+                1 => input.apply(InputEvent::Key(KeyCode::KeyW, true)),
+                2 => input.apply(InputEvent::Key(KeyCode::KeyW, false)),
+                3 => input.apply(InputEvent::Key(KeyCode::KeyW, true)),
+                4 => {
+                    input.apply(InputEvent::Key(KeyCode::KeyW, false));
+                    input.apply(InputEvent::Button(MouseButton::Other(4), true));
+                    input.apply(InputEvent::Motion(3.5, -1.0));
+                    input.apply(InputEvent::Char('é'));
+                }
+                5 => input.apply(InputEvent::Resize(1024, 768)),
+                _ => {}
+            }
+            frames.push(input.take_frame());
+        }
+        frames
+    }
+
+    #[test]
+    fn events_are_parsed_back_as_recorded() {
+        assert_eq!(
+            parse_event("0.016666668 key KeyW down"),
+            Some((REPLAY_TIMESTEP, Some(InputEvent::Key(KeyCode::KeyW, true))))
+        );
+        assert_eq!(
+            parse_event("0.25 button Other(4) up"),
+            Some((0.25, Some(InputEvent::Button(MouseButton::Other(4), false))))
+        );
+        assert_eq!(
+            parse_event("0 position 412 300"),
+            Some((0.0, Some(InputEvent::Position(Some((412.0, 300.0))))))
+        );
+        assert_eq!(parse_event("0.5 end"), Some((0.5, None)));
+        assert_eq!(parse_event("0.5 key NoSuchKey down"), None);
+        assert_eq!(parse_event("0.5 jump"), None);
+    }
+
+    #[test]
+    fn deterministic_recordings_replay_frame_for_frame() {
+        let path = temporary_path("frames");
+        let recorded = recorded_frames();
+        {
+            let mut recorder = InputRecorder::create(&path).unwrap();
+            for (frame, input) in recorded.iter().enumerate() {
+                recorder
+                    .record(frame as f32 * REPLAY_TIMESTEP, input)
+                    .unwrap();
+            }
+        }
+
+        let mut replay = InputReplay::load(&path, (800, 600)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for (frame, recorded) in recorded.iter().enumerate() {
+            assert!(!replay.is_finished(), "finished before frame {}", frame);
+            let replayed = replay.next_frame();
+            assert_eq!(replay.time(), frame as f32 * REPLAY_TIMESTEP);
+            assert_eq!(
+                replayed.keys.pressed(),
+                recorded.keys.pressed(),
+                "frame {}",
+                frame
+            );
+            assert_eq!(
+                replayed.keys.released(),
+                recorded.keys.released(),
+                "frame {}",
+                frame
+            );
+            assert_eq!(
+                replayed.keys.held(),
+                recorded.keys.held(),
+                "frame {}",
+                frame
+            );
+            assert_eq!(replayed.buttons.pressed(), recorded.buttons.pressed());
+            assert_eq!(
+                replayed.mouse_delta, recorded.mouse_delta,
+                "frame {}",
+                frame
+            );
+            assert_eq!(replayed.text, recorded.text, "frame {}", frame);
+            assert_eq!(
+                replayed.window_size, recorded.window_size,
+                "frame {}",
+                frame
+            );
+        }
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn deterministic_runs_replay_the_same_number_of_frames() {
+        let path = temporary_path("deterministic");
+        let frames = 90;
+        {
+            let mut recorder = InputRecorder::create(&path).unwrap();
+            let mut input = InputCollector::new((800, 600));
+            for frame in 0..frames {
+                if frame == 30 {
+                    input.apply(InputEvent::Key(KeyCode::Space, true));
+                }
+                recorder
+                    .record(fixed_step_time(frame), &input.take_frame())
+                    .unwrap();
+            }
+        }
+
+        let mut replay = InputReplay::load(&path, (800, 600)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut replayed = 0;
+        while !replay.is_finished() {
+            let input = replay.next_frame();
+            assert_eq!(replay.time(), fixed_step_time(replayed));
+            assert_eq!(input.keys.just_pressed(KeyCode::Space), replayed == 30);
+            replayed += 1;
+        }
+        assert_eq!(replayed, frames);
+    }
+}
@@ -1,7 +1,17 @@
 extern crate nalgebra_glm as glm;
 
+use crate::audio::Sound;
+use crate::backend::TextureHandle;
+use crate::lighting::Light;
+use crate::material::Material;
+use crate::renderer::Vao;
+use crate::skeleton::{SkeletalAnimation, Skeleton};
+use crate::toolbox::{self, Aabb, AnimationClip, Ray, Transform};
+
+use std::collections::HashMap;
 use std::mem::ManuallyDrop;
 use std::pin::Pin;
+use std::sync::Arc;
 
 // Used to create an unholy abomination upon which you should not cast your gaze. This ended up
 // being a necessity due to wanting to keep the code written by students as "straight forward" as
@@ -17,13 +27,25 @@ use std::pin::Pin;
 pub type Node = ManuallyDrop<Pin<Box<SceneNode>>>;
 
 pub struct SceneNode {
+    pub name            : String,      // What I'm called, for debugging
     pub position        : glm::Vec3,   // Where I should be in relation to my parent
-    pub rotation        : glm::Vec3,   // How I should be rotated, around the X, the Y and the Z axes
+    pub rotation        : glm::Vec3,   // How I should be rotated, yawing about Y, then pitching about X and rolling about Z
     pub scale           : glm::Vec3,   // How I should be scaled
     pub reference_point : glm::Vec3,   // The point I shall rotate and scale about
 
     pub vao_id      : u32,             // What I should draw
     pub index_count : i32,             // How much of it there is to draw
+    pub bounds      : Option<Aabb>,    // The space my mesh occupies, if I can be picked
+    pub opacity     : f32,             // How solid I look, from 0 for not at all to 1
+    pub selected    : bool,            // Whether I have been picked with the mouse
+    pub batched     : bool,            // Whether my mesh is drawn as part of a static batch instead
+    pub culled      : bool,            // Whether I am out of the camera's view, see Octree::cull
+    pub sound       : Option<Sound>,   // What I sound like, if anything, see audio::SpatialAudio
+    pub joints      : Option<Arc<[glm::Mat4]>>, // How my skinned mesh is posed, see skeleton::Skeleton
+    pub light       : Option<Light>,   // What light I give off, if any, see lighting::Lighting
+    pub normal_map  : Option<TextureHandle>, // Bumps on my surface too fine for my mesh, if it has UVs and tangents
+    pub material    : Option<Material>, // What I am made of, see material::Material. Shaded with Phong if None.
+    pub previous_model : Option<glm::Mat4>, // Where I was in the world last frame, for motion blur, see remember_transforms
 
     pub children: Vec<*mut SceneNode>, // Those I command
 }
@@ -32,28 +54,147 @@ impl SceneNode {
 
     pub fn new() -> Node {
         ManuallyDrop::new(Pin::new(Box::new(SceneNode {
+            name            : String::new(),
             position        : glm::zero(),
             rotation        : glm::zero(),
             scale           : glm::vec3(1.0, 1.0, 1.0),
             reference_point : glm::zero(),
             vao_id          : 0,
             index_count     : -1,
+            bounds          : None,
+            opacity         : 1.0,
+            selected        : false,
+            batched         : false,
+            culled          : false,
+            sound           : None,
+            joints          : None,
+            light           : None,
+            normal_map      : None,
+            material        : None,
+            previous_model  : None,
             children        : vec![],
         })))
     }
 
-    pub fn from_vao(vao_id: u32, index_count: i32) -> Node {
+    pub fn from_vao(vao: Vao) -> Node {
         ManuallyDrop::new(Pin::new(Box::new(SceneNode {
+            name            : String::new(),
             position        : glm::zero(),
             rotation        : glm::zero(),
             scale           : glm::vec3(1.0, 1.0, 1.0),
             reference_point : glm::zero(),
-            vao_id,
-            index_count,
+            vao_id          : vao.id,
+            index_count     : vao.index_count,
+            bounds          : None,
+            opacity         : 1.0,
+            selected        : false,
+            batched         : false,
+            culled          : false,
+            sound           : None,
+            joints          : None,
+            light           : None,
+            normal_map      : None,
+            material        : None,
+            previous_model  : None,
             children: vec![],
         })))
     }
 
+    // Whether I have a mesh to draw on my own and can be seen. Batched nodes are drawn anyway while
+    // selected, to show the highlight.
+    pub fn is_drawn(&self) -> bool {
+        self.vao_id != 0 && !self.culled && self.opacity > 0.0 && (!self.batched || self.selected)
+    }
+
+    // My transformation relative to my parent
+    pub fn local_transform(&self) -> glm::Mat4 {
+        toolbox::compose_transform(&self.position, &self.rotation, &self.scale, &self.reference_point)
+    }
+
+    // My position, rotation and scale
+    pub fn transform(&self) -> Transform {
+        Transform { position: self.position, rotation: self.rotation, scale: self.scale }
+    }
+
+    pub fn set_transform(&mut self, transform: &Transform) {
+        self.position = transform.position;
+        self.rotation = transform.rotation;
+        self.scale = transform.scale;
+    }
+
+    // Pose me as I am `time` seconds into playing `clip`
+    pub fn animate(&mut self, clip: &AnimationClip, time: f32) {
+        let mut transform = self.transform();
+        clip.apply(time, &mut transform);
+        self.set_transform(&transform);
+    }
+
+    // Pose my skinned mesh as it is `time` seconds into playing `animation` on `skeleton`
+    pub fn animate_skeleton(&mut self, skeleton: &Skeleton, animation: &SkeletalAnimation, time: f32) {
+        let pose = animation.sample(skeleton, time);
+        self.joints = Some(skeleton.joint_matrices(&pose).into());
+    }
+
+    // Find the closest node below me whose bounds are hit by a world-space ray. Returns the
+    // distance along the ray together with the node.
+    pub fn pick(&self, ray: &Ray, transformation_so_far: &glm::Mat4) -> Option<(f32, *mut SceneNode)> {
+        let transform = transformation_so_far * self.local_transform();
+
+        let mut closest = None;
+        if let Some(bounds) = &self.bounds {
+            // Test in my own coordinate space, where my bounds are axis aligned
+            let local_ray = ray.transformed(&glm::inverse(&transform));
+            if let Some(t) = bounds.intersect_ray(&local_ray) {
+                closest = Some((t, self as *const SceneNode as *mut SceneNode));
+            }
+        }
+
+        for &child in &self.children {
+            let hit = unsafe { (*child).pick(ray, &transform) };
+            if let Some((t, node)) = hit {
+                if closest.is_none_or(|(closest_t, _)| t < closest_t) {
+                    closest = Some((t, node));
+                }
+            }
+        }
+        closest
+    }
+
+    // Point myself and my children at new VAOs, e.g. after they were uploaded to a new context
+    pub fn remap_vao_ids(&mut self, vao_ids: &HashMap<u32, u32>) {
+        if let Some(&vao_id) = vao_ids.get(&self.vao_id) {
+            self.vao_id = vao_id;
+        }
+        for &child in &self.children {
+            unsafe { (*child).remap_vao_ids(vao_ids) }
+        }
+    }
+
+    // Update the index counts of my and my children's meshes, e.g. after they were reloaded
+    pub fn update_index_counts(&mut self, index_counts: &HashMap<u32, i32>) {
+        if let Some(&index_count) = index_counts.get(&self.vao_id) {
+            self.index_count = index_count;
+        }
+        for &child in &self.children {
+            unsafe { (*child).update_index_counts(index_counts) }
+        }
+    }
+
+    // Remember where I and my children are in the world, as they were drawn this frame, so the
+    // next frame can tell how far they moved since
+    pub fn remember_transforms(&mut self, transformation_so_far: &glm::Mat4) {
+        let transform = transformation_so_far * self.local_transform();
+        self.previous_model = Some(transform);
+        for &child in &self.children {
+            unsafe { (*child).remember_transforms(&transform) }
+        }
+    }
+
+    // Whether `node` is me or below me
+    pub fn contains(&self, node: *const SceneNode) -> bool {
+        std::ptr::eq(self, node) || self.children.iter().any(|&child| unsafe { (*child).contains(node) })
+    }
+
     pub fn add_child(&mut self, child: &SceneNode) {
         self.children.push(child as *const SceneNode as *mut SceneNode)
     }
@@ -74,6 +215,7 @@ impl SceneNode {
     pub fn print(&self) {
         println!(
 "SceneNode {{
+    Name:      {}
     VAO:       {}
     Indices:   {}
     Children:  {}
@@ -81,6 +223,7 @@ impl SceneNode {
     Rotation:  [{:.2}, {:.2}, {:.2}]
     Reference: [{:.2}, {:.2}, {:.2}]
 }}",
+            self.name,
             self.vao_id,
             self.index_count,
             self.children.len(),
@@ -116,3 +259,139 @@ impl IndexMut<usize> for SceneNode {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_bounds() -> Option<Aabb> {
+        Some(Aabb { min: glm::vec3(-0.5, -0.5, -0.5), max: glm::vec3(0.5, 0.5, 0.5) })
+    }
+
+    fn world_position(transform: &glm::Mat4) -> glm::Vec3 {
+        (transform * glm::vec4(0.0, 0.0, 0.0, 1.0)).xyz()
+    }
+
+    #[test]
+    fn local_transform_places_me_relative_to_my_parent() {
+        let mut node = SceneNode::new();
+        node.position = glm::vec3(1.0, 2.0, 3.0);
+        node.rotation = glm::vec3(0.0, 1.0, 0.0);
+        node.reference_point = glm::vec3(0.5, 0.0, 0.0);
+        let expected = toolbox::compose_transform(&node.position, &node.rotation, &node.scale, &node.reference_point);
+        assert_eq!(node.local_transform(), expected);
+    }
+
+    #[test]
+    fn transforms_compose_down_the_tree() {
+        let mut parent = SceneNode::new();
+        let mut child = SceneNode::new();
+        parent.position = glm::vec3(10.0, 0.0, 0.0);
+        parent.rotation = glm::vec3(0.0, std::f32::consts::FRAC_PI_2, 0.0);
+        child.position = glm::vec3(0.0, 0.0, -2.0);
+        parent.add_child(&child);
+
+        // The child is moved along the parent's rotated -Z, which points down -X
+        let transform = parent.local_transform() * parent[0].local_transform();
+        assert!(glm::distance(&world_position(&transform), &glm::vec3(8.0, 0.0, 0.0)) < 1e-5);
+    }
+
+    #[test]
+    fn remembered_transforms_stay_behind_when_nodes_move() {
+        let mut parent = SceneNode::new();
+        let mut child = SceneNode::new();
+        child.position = glm::vec3(0.0, 0.0, -2.0);
+        parent.add_child(&child);
+        assert!(parent[0].previous_model.is_none());
+
+        parent.remember_transforms(&glm::identity());
+        parent.position = glm::vec3(5.0, 0.0, 0.0);
+        let remembered = parent[0].previous_model.unwrap();
+        assert!(glm::distance(&world_position(&remembered), &glm::vec3(0.0, 0.0, -2.0)) < 1e-5);
+    }
+
+    #[test]
+    fn pick_finds_the_closest_node_through_its_parents() {
+        let mut root = SceneNode::new();
+        let mut near = SceneNode::new();
+        let mut far = SceneNode::new();
+        root.position = glm::vec3(0.0, 0.0, -5.0);
+        near.bounds = unit_bounds();
+        near.position = glm::vec3(0.0, 0.0, 2.0);
+        far.bounds = unit_bounds();
+        far.scale = glm::vec3(2.0, 2.0, 2.0);
+        root.add_child(&far);
+        root.add_child(&near);
+
+        let ray = Ray { origin: glm::zero(), direction: glm::vec3(0.0, 0.0, -1.0) };
+        let (t, node) = root.pick(&ray, &glm::identity()).unwrap();
+        assert!((t - 2.5).abs() < 1e-5);
+        assert_eq!(node, &**near as *const SceneNode as *mut SceneNode);
+
+        let beside = Ray { origin: glm::vec3(0.8, 0.0, 0.0), ..ray };
+        let (t, node) = root.pick(&beside, &glm::identity()).unwrap();
+        assert!((t - 4.0).abs() < 1e-5, "only the scaled up node is wide enough");
+        assert_eq!(node, &**far as *const SceneNode as *mut SceneNode);
+
+        let above = Ray { origin: glm::vec3(0.0, 3.0, 0.0), ..ray };
+        assert!(root.pick(&above, &glm::identity()).is_none());
+    }
+
+    #[test]
+    fn animate_only_sets_what_the_clip_animates() {
+        let mut node = SceneNode::new();
+        node.position = glm::vec3(1.0, 2.0, 3.0);
+        node.rotation = glm::vec3(0.5, 0.0, 0.0);
+        let spin = toolbox::Track::new(toolbox::Interpolation::Linear, vec![(0.0, 0.0), (1.0, 2.0)]);
+        let clip = AnimationClip::new(1.0, toolbox::Looping::Once).with_channel(toolbox::Channel::Angle(2, spin));
+        node.animate(&clip, 0.25);
+        assert_eq!(node.position, glm::vec3(1.0, 2.0, 3.0));
+        assert_eq!(node.rotation, glm::vec3(0.5, 0.0, 0.5));
+    }
+
+    #[test]
+    fn contains_finds_nodes_anywhere_below() {
+        let mut root = SceneNode::new();
+        let mut child = SceneNode::new();
+        let grandchild = SceneNode::new();
+        let stranger = SceneNode::new();
+        child.add_child(&grandchild);
+        root.add_child(&child);
+        assert!(root.contains(&**root));
+        assert!(root.contains(&**grandchild));
+        assert!(!child.contains(&**root));
+        assert!(!root.contains(&**stranger));
+    }
+
+    #[test]
+    fn only_visible_nodes_with_meshes_are_drawn() {
+        let mut node = SceneNode::new();
+        assert!(!node.is_drawn());
+        node.vao_id = 1;
+        assert!(node.is_drawn());
+        node.batched = true;
+        assert!(!node.is_drawn());
+        node.selected = true;
+        assert!(node.is_drawn());
+        node.culled = true;
+        assert!(!node.is_drawn());
+        node.culled = false;
+        node.opacity = 0.0;
+        assert!(!node.is_drawn());
+    }
+
+    #[test]
+    fn vao_ids_and_index_counts_are_updated_down_the_tree() {
+        let mut root = SceneNode::new();
+        let mut child = SceneNode::new();
+        root.vao_id = 1;
+        child.vao_id = 2;
+        root.add_child(&child);
+
+        root.remap_vao_ids(&[(1, 10), (2, 20)].iter().copied().collect());
+        root.update_index_counts(&[(20, 36)].iter().copied().collect());
+        assert_eq!((root.vao_id, root[0].vao_id), (10, 20));
+        assert_eq!((root.index_count, root[0].index_count), (-1, 36));
+    }
+}
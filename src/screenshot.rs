@@ -0,0 +1,177 @@
+// Screenshots saved as PNG files.
+//
+// The pixels are copied into a pixel buffer object, which lets the GPU finish the frame in its own
+// time instead of stalling the render thread in glReadPixels. Once a fence says the copy is done,
+// the pixels are mapped and handed to a background thread for encoding.
+use log::{info, warn};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SCREENSHOT_DIR: &str = "screenshots";
+
+struct PendingScreenshot {
+    pbo: u32,
+    fence: gl::types::GLsync,
+    width: u32,
+    height: u32,
+    path: PathBuf,
+}
+
+#[derive(Default)]
+pub struct Screenshots {
+    pending: Vec<PendingScreenshot>,
+    saving: Vec<thread::JoinHandle<()>>, // Threads encoding finished screenshots
+}
+
+impl Screenshots {
+    pub fn new() -> Screenshots {
+        Screenshots {
+            pending: vec![],
+            saving: vec![],
+        }
+    }
+
+    // Start reading back the framebuffer bound for reading. Call this before swapping buffers,
+    // as the back buffer is undefined afterwards.
+    pub unsafe fn capture(&mut self, width: u32, height: u32) {
+        let mut pbo = 0;
+        gl::GenBuffers(1, &mut pbo);
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, pbo);
+        gl::BufferData(
+            gl::PIXEL_PACK_BUFFER,
+            (width * height * 3) as isize,
+            std::ptr::null(),
+            gl::STREAM_READ,
+        );
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        // With a pixel pack buffer bound, the pointer is an offset into the buffer
+        gl::ReadPixels(
+            0,
+            0,
+            width as i32,
+            height as i32,
+            gl::RGB,
+            gl::UNSIGNED_BYTE,
+            std::ptr::null_mut(),
+        );
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        let fence = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+
+        self.pending.push(PendingScreenshot {
+            pbo,
+            fence,
+            width,
+            height,
+            path: PathBuf::from(SCREENSHOT_DIR).join(format!("screenshot_{}.png", timestamp())),
+        });
+    }
+
+    // Save the screenshots the GPU has finished copying. Call this once per frame.
+    pub unsafe fn poll(&mut self) {
+        let mut i = 0;
+        while i < self.pending.len() {
+            let status = gl::ClientWaitSync(self.pending[i].fence, 0, 0);
+            if status != gl::ALREADY_SIGNALED && status != gl::CONDITION_SATISFIED {
+                i += 1;
+                continue;
+            }
+            let screenshot = self.pending.remove(i);
+            gl::DeleteSync(screenshot.fence);
+
+            let size = (screenshot.width * screenshot.height * 3) as usize;
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, screenshot.pbo);
+            let mapped = gl::MapBuffer(gl::PIXEL_PACK_BUFFER, gl::READ_ONLY) as *const u8;
+            let pixels = if mapped.is_null() {
+                None
+            } else {
+                Some(std::slice::from_raw_parts(mapped, size).to_vec())
+            };
+            gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+            gl::DeleteBuffers(1, &screenshot.pbo);
+
+            match pixels {
+                Some(pixels) => self.saving.push(save(
+                    screenshot.path,
+                    screenshot.width,
+                    screenshot.height,
+                    pixels,
+                )),
+                None => warn!("Failed to read back {}", screenshot.path.display()),
+            }
+        }
+        self.saving.retain(|saving| !saving.is_finished());
+    }
+
+    // Wait until every screenshot taken so far is written to disk, e.g. before exiting
+    pub unsafe fn finish(&mut self) {
+        for screenshot in &self.pending {
+            gl::ClientWaitSync(screenshot.fence, gl::SYNC_FLUSH_COMMANDS_BIT, u64::MAX);
+        }
+        self.poll();
+        for saving in self.saving.drain(..) {
+            let _ = saving.join();
+        }
+    }
+}
+
+// Encode and write the image on a background thread, since PNG compression takes a while
+fn save(path: PathBuf, width: u32, height: u32, pixels: Vec<u8>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut image = image::RgbImage::from_raw(width, height, pixels)
+            .expect("Pixel buffer has the size of the image");
+        // OpenGL stores rows bottom to top
+        image::imageops::flip_vertical_in_place(&mut image);
+        let saved = std::fs::create_dir_all(SCREENSHOT_DIR)
+            .map_err(|e| e.to_string())
+            .and_then(|_| image.save(&path).map_err(|e| e.to_string()));
+        match saved {
+            Ok(()) => info!(
+                "Saved {}x{} screenshot to {}",
+                width,
+                height,
+                path.display()
+            ),
+            Err(e) => warn!("Failed to save {}: {}", path.display(), e),
+        }
+    })
+}
+
+// The current UTC time as e.g. `2024-03-01_13-37-00_123`, which sorts chronologically and is a
+// valid file name everywhere
+pub fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = now.as_secs();
+    let (hour, minute, second) = (seconds / 3600 % 24, seconds / 60 % 60, seconds % 60);
+
+    // Convert days since 1970-01-01 to a calendar date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}_{:03}",
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        now.subsec_millis()
+    )
+}
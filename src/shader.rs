@@ -1,13 +1,25 @@
-use gl;
 use std::{
     ptr,
     str,
+    collections::HashMap,
     ffi::CString,
     path::Path,
 };
+use crate::error::ShaderError;
+use log::info;
 
 pub struct Shader {
     pub program_id: u32,
+    // The program's active uniforms by name, looked up once after linking
+    uniforms: HashMap<String, ActiveUniform>,
+}
+
+// What introspection tells about a uniform the program uses
+#[derive(Clone, Copy, Debug)]
+pub struct ActiveUniform {
+    pub location : i32,
+    pub kind     : u32, // The GLSL type, e.g. gl::FLOAT_VEC3
+    pub size     : i32, // Number of elements, 1 unless it is an array
 }
 
 pub struct ShaderBuilder {
@@ -25,10 +37,14 @@ pub enum ShaderType {
 }
 
 impl Shader {
-    // Make sure the shader is active before calling this
-    pub unsafe fn get_uniform_location(&self, name: &str) -> i32 {
-        let name_cstr = CString::new(name).expect("CString::new failed");
-        gl::GetUniformLocation(self.program_id, name_cstr.as_ptr())
+    // The location of a uniform, or -1 if the program doesn't use it. Setting a uniform at -1 is
+    // silently ignored by OpenGL, like for uniforms the compiler optimized away.
+    pub fn get_uniform_location(&self, name: &str) -> i32 {
+        self.uniforms.get(name).map_or(-1, |uniform| uniform.location)
+    }
+
+    pub fn uniforms(&self) -> &HashMap<String, ActiveUniform> {
+        &self.uniforms
     }
 
     pub unsafe fn activate(&self) {
@@ -36,9 +52,9 @@ impl Shader {
     }
 }
 
-impl Into<gl::types::GLenum> for ShaderType {
-    fn into(self) -> gl::types::GLenum {
-        match self {
+impl From<ShaderType> for gl::types::GLenum {
+    fn from(shader_type: ShaderType) -> gl::types::GLenum {
+        match shader_type {
             ShaderType::Vertex                  => { gl::VERTEX_SHADER          },
             ShaderType::Fragment                => { gl::FRAGMENT_SHADER        },
             ShaderType::TessellationControl     => { gl::TESS_CONTROL_SHADER    },
@@ -49,16 +65,36 @@ impl Into<gl::types::GLenum> for ShaderType {
 }
 
 impl ShaderType {
-    fn from_ext(ext: &std::ffi::OsStr) -> Result<ShaderType, String> {
-        match ext.to_str().expect("Failed to read extension") {
-            "vert" => { Ok(ShaderType::Vertex) },
-            "frag" => { Ok(ShaderType::Fragment) },
-            "tcs"  => { Ok(ShaderType::TessellationControl) },
-            "tes"  => { Ok(ShaderType::TessellationEvaluation) },
-            "geom" => { Ok(ShaderType::Geometry) },
-            e => { Err(e.to_string()) },
+    fn from_ext(ext: &std::ffi::OsStr) -> Option<ShaderType> {
+        match ext.to_str()? {
+            "vert" => { Some(ShaderType::Vertex) },
+            "frag" => { Some(ShaderType::Fragment) },
+            "tcs"  => { Some(ShaderType::TessellationControl) },
+            "tes"  => { Some(ShaderType::TessellationEvaluation) },
+            "geom" => { Some(ShaderType::Geometry) },
+            _ => { None },
+        }
+    }
+}
+
+// Lower the `#version` directive of a shader to what the current context supports, e.g. 410 on
+// macOS. Shaders that rely on newer features must check `util::GlFeatures` and provide fallbacks.
+unsafe fn fit_version_to_context(shader_src: &str) -> String {
+    let (major, minor) = crate::util::get_gl_version();
+    let supported = major * 100 + minor * 10;
+    let mut lines: Vec<&str> = shader_src.lines().collect();
+    let directive;
+    if let Some(line) = lines.iter_mut().find(|line| line.trim_start().starts_with("#version")) {
+        let mut words = line.split_whitespace().skip(1);
+        let version: Option<u32> = words.next().and_then(|v| v.parse().ok());
+        if let Some(version) = version.filter(|&v| v > supported) {
+            let profile = words.next().unwrap_or("core");
+            info!("Compiling GLSL {} shader as {} {}", version, supported, profile);
+            directive = format!("#version {} {}", supported, profile);
+            *line = &directive;
         }
     }
+    lines.join("\n")
 }
 
 impl ShaderBuilder {
@@ -69,86 +105,131 @@ impl ShaderBuilder {
         }
     }
 
-    pub unsafe fn attach_file(self, shader_path: &str) -> ShaderBuilder {
+    pub unsafe fn attach_file(self, shader_path: &str) -> Result<ShaderBuilder, ShaderError> {
         let path = Path::new(shader_path);
-        if let Some(extension) = path.extension() {
-            let shader_type = ShaderType::from_ext(extension)
-                .expect("Failed to parse file extension.");
-            let shader_src = std::fs::read_to_string(path)
-                .expect(&format!("Failed to read shader source. {}", shader_path));
-            self.compile_shader(&shader_src, shader_type)
-        } else {
-            panic!("Failed to read extension of file with path: {}", shader_path);
+        let shader_type = path.extension()
+            .and_then(ShaderType::from_ext)
+            .ok_or_else(|| ShaderError::UnknownType { path: shader_path.to_string() })?;
+        let shader_src = std::fs::read_to_string(path)
+            .map_err(|source| ShaderError::Read { path: shader_path.to_string(), source })?;
+        let builder = self.compile_shader(&shader_src, shader_type)
+            .map_err(|log| ShaderError::Compile { path: shader_path.to_string(), log })?;
+        if let Some(&shader) = builder.shaders.last() {
+            crate::util::label_object(gl::SHADER, shader, shader_path);
         }
+        Ok(builder)
     }
 
-    pub unsafe fn compile_shader(mut self, shader_src: &str, shader_type: ShaderType) -> ShaderBuilder {
+    // Compile a shader from source, returning the compiler's log if it fails
+    pub unsafe fn compile_shader(mut self, shader_src: &str, shader_type: ShaderType) -> Result<ShaderBuilder, String> {
         let shader = gl::CreateShader(shader_type.into());
-        let c_str_shader = CString::new(shader_src.as_bytes()).unwrap();
+        let shader_src = fit_version_to_context(shader_src);
+        let c_str_shader = CString::new(shader_src.as_bytes())
+            .map_err(|_| "Shader source contains a nul byte".to_string())?;
         gl::ShaderSource(shader, 1, &c_str_shader.as_ptr(), ptr::null());
         gl::CompileShader(shader);
 
-        if !self.check_shader_errors(shader) {
-            panic!("Shader failed to compile.");
+        if let Err(log) = self.check_shader_errors(shader) {
+            gl::DeleteShader(shader);
+            return Err(log);
         }
 
         self.shaders.push(shader);
 
-        self
+        Ok(self)
     }
 
-    unsafe fn check_shader_errors(&self, shader_id: u32) -> bool {
+    unsafe fn check_shader_errors(&self, shader_id: u32) -> Result<(), String> {
         let mut success = i32::from(gl::FALSE);
-        let mut info_log = Vec::with_capacity(512);
-        info_log.set_len(512 - 1);
+        let mut info_log = vec![0u8; 512];
         gl::GetShaderiv(shader_id, gl::COMPILE_STATUS, &mut success);
         if success != i32::from(gl::TRUE) {
+            let mut length = 0;
             gl::GetShaderInfoLog(
                 shader_id,
                 512,
-                ptr::null_mut(),
+                &mut length,
                 info_log.as_mut_ptr() as *mut gl::types::GLchar,
             );
-            println!("ERROR::Shader Compilation Failed!\n{}", String::from_utf8_lossy(&info_log));
-            return false;
+            return Err(String::from_utf8_lossy(&info_log[..length as usize]).into_owned());
         }
-        true
+        Ok(())
     }
 
-    unsafe fn check_linker_errors(&self) -> bool {
+    unsafe fn check_linker_errors(&self) -> Result<(), String> {
         let mut success = i32::from(gl::FALSE);
-        let mut info_log = Vec::with_capacity(512);
-        info_log.set_len(512 - 1);
+        let mut info_log = vec![0u8; 512];
         gl::GetProgramiv(self.program_id, gl::LINK_STATUS, &mut success);
         if success != i32::from(gl::TRUE) {
+            let mut length = 0;
             gl::GetProgramInfoLog(
                 self.program_id,
                 512,
-                ptr::null_mut(),
+                &mut length,
                 info_log.as_mut_ptr() as *mut gl::types::GLchar,
             );
-            println!("ERROR::SHADER::PROGRAM::COMPILATION_FAILED\n{}", String::from_utf8_lossy(&info_log));
-            return false;
+            return Err(String::from_utf8_lossy(&info_log[..length as usize]).into_owned());
         }
-        true
+        Ok(())
     }
 
-    #[must_use = "The shader program is useless if not stored in a variable."]
-    pub unsafe fn link(self) -> Shader {
+    pub unsafe fn link(self) -> Result<Shader, ShaderError> {
         for &shader in &self.shaders {
             gl::AttachShader(self.program_id, shader);
         }
         gl::LinkProgram(self.program_id);
 
-        // todo:: use this to make safer abstraction
-        self.check_linker_errors();
+        let linked = self.check_linker_errors();
 
         for &shader in &self.shaders {
             gl::DeleteShader(shader);
         }
 
-        Shader {
-            program_id: self.program_id
+        if let Err(log) = linked {
+            gl::DeleteProgram(self.program_id);
+            return Err(ShaderError::Link { log });
+        }
+
+        Ok(Shader {
+            program_id: self.program_id,
+            uniforms: active_uniforms(self.program_id),
+        })
+    }
+}
+
+// Ask a linked program for all its active uniforms. Arrays are listed by their first element, e.g.
+// `lights[0]`, and are also stored under their bare name.
+unsafe fn active_uniforms(program_id: u32) -> HashMap<String, ActiveUniform> {
+    let mut count = 0;
+    gl::GetProgramiv(program_id, gl::ACTIVE_UNIFORMS, &mut count);
+    let mut max_length = 0;
+    gl::GetProgramiv(program_id, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_length);
+
+    let mut uniforms = HashMap::new();
+    let mut name = vec![0u8; max_length.max(1) as usize];
+    for index in 0..count as u32 {
+        let (mut length, mut size, mut kind) = (0, 0, 0);
+        gl::GetActiveUniform(
+            program_id,
+            index,
+            name.len() as i32,
+            &mut length,
+            &mut size,
+            &mut kind,
+            name.as_mut_ptr() as *mut gl::types::GLchar,
+        );
+        let uniform_name = String::from_utf8_lossy(&name[..length as usize]).into_owned();
+        let c_name = CString::new(uniform_name.as_bytes()).unwrap();
+        let location = gl::GetUniformLocation(program_id, c_name.as_ptr());
+        // Uniforms in blocks have no location of their own
+        if location < 0 {
+            continue;
+        }
+        let uniform = ActiveUniform { location, kind, size };
+        if let Some(array_name) = uniform_name.strip_suffix("[0]") {
+            uniforms.insert(array_name.to_string(), uniform);
         }
+        uniforms.insert(uniform_name, uniform);
     }
+    uniforms
 }
@@ -0,0 +1,474 @@
+// Shadows cast by the sun, with a shadow map.
+//
+// Before the scene is drawn, it is drawn once more as seen from the sun, by the `shadow` pipeline
+// into nothing but the depth texture of a `ShadowMap`. Whatever is nearest the sun there is lit,
+// and anything further along the light behind it is in shadow. When the scene is drawn for the
+// camera, the simple fragment shader takes every fragment into the sun's view with
+// `lightSpaceMatrices` and compares how deep it is with what the map has: deeper means something is
+// in the way. As the sun shines the same way everywhere, its view is an orthographic box.
+//
+// A single box over the whole terrain would spread the map so thin that shadows near the camera
+// came out as blocks. Instead the camera's view is cut by distance into `CASCADES` slices, each
+// further one longer, and each slice gets a box of its own around it in a layer of the map. Near
+// the camera, where a texel is seen largest, the box is smallest and the shadows sharpest. The
+// fragment shader picks the slice by how far ahead of the camera the fragment is.
+//
+// Point lights shine every way, so their shadows go in cube maps, one per light in
+// `PointShadowMaps`. Rather than the depth, each face has how far what is nearest the light in its
+// direction is, as a fraction of the light's range. The scene is drawn once per light by the
+// `point_shadow` pipeline, whose geometry shader draws every triangle into all six faces. The simple
+// fragment shader compares the fragment's distance from the light with what the cube has in the
+// fragment's direction.
+use crate::backend::gl::GlBackend;
+use crate::backend::PipelineHandle;
+use crate::error::RenderError;
+use crate::lighting::{PointLight, MAX_SHADOWED_POINT_LIGHTS};
+use crate::offscreen::{self, Framebuffer};
+
+// The texture units the maps are bound to while drawing, clear of unit 0 where other textures go
+pub const SHADOW_TEXTURE_UNIT: u32 = 1;
+pub const POINT_SHADOW_TEXTURE_UNIT: u32 = 2;
+
+// Closer to a point light than this, nothing casts a shadow
+const POINT_SHADOW_NEAR: f32 = 0.1;
+
+// How many slices the camera's view is cut into, as many as `lightSpaceMatrices` in `simple.frag`
+pub const CASCADES: usize = 4;
+// Where the slices are cut, between evenly (0) and each as many times longer than the last (1)
+const SPLIT_BLEND: f32 = 0.75;
+
+// How far the box reaches along the light on either side of its center, in units of its radius
+// but at least `MIN_DEPTH_REACH`, so tall things outside the sphere the box is around still cast
+// shadows into it, even into the small boxes near the camera
+const DEPTH_REACH: f32 = 2.0;
+const MIN_DEPTH_REACH: f32 = 150.0;
+
+// How far ahead of the camera each slice of its view ends, for a view from `near` to `far`
+pub fn cascade_splits(near: f32, far: f32) -> [f32; CASCADES] {
+    std::array::from_fn(|i| {
+        let fraction = (i + 1) as f32 / CASCADES as f32;
+        let logarithmic = near * (far / near).powf(fraction);
+        let even = near + (far - near) * fraction;
+        SPLIT_BLEND * logarithmic + (1.0 - SPLIT_BLEND) * even
+    })
+}
+
+// The center and radius of a sphere around the slice of a camera's view from `near` to `far` ahead
+// of it, for a camera at `inverse_view` with a vertical field of view of `fov_y` radians. The radius
+// is rounded up to whole units, so it stays the same as the camera turns.
+pub fn view_slice_sphere(
+    inverse_view: &glm::Mat4,
+    fov_y: f32,
+    aspect: f32,
+    near: f32,
+    far: f32,
+) -> (glm::Vec3, f32) {
+    let corners = [near, far].map(|depth| {
+        let half_height = depth * (fov_y / 2.0).tan();
+        glm::vec3(half_height * aspect, half_height, -depth)
+    });
+    let center = glm::vec3(0.0, 0.0, -(near + far) / 2.0);
+    let radius = corners
+        .iter()
+        .map(|corner| glm::distance(corner, &center))
+        .fold(0.0, f32::max)
+        .ceil();
+    let world_center = inverse_view * glm::vec4(center.x, center.y, center.z, 1.0);
+    (world_center.xyz(), radius)
+}
+
+// The view and projection of a light shining along `direction`, for a shadow map of `resolution`
+// texels square. It takes everything within `radius` of `center` into the map. The center is moved
+// by less than a texel to keep to whole texels across the light, so the edges of the shadows don't
+// shimmer as the box follows the camera.
+pub fn light_space_matrix(
+    direction: &glm::Vec3,
+    center: &glm::Vec3,
+    radius: f32,
+    resolution: u32,
+) -> glm::Mat4 {
+    let direction = glm::normalize(direction);
+    // Any up will do that isn't along the light
+    let up = if direction.y.abs() > 0.99 {
+        glm::vec3(0.0, 0.0, 1.0)
+    } else {
+        glm::vec3(0.0, 1.0, 0.0)
+    };
+    let rotation = glm::look_at(&glm::zero(), &direction, &up);
+    let texel = 2.0 * radius / resolution as f32;
+    let across = rotation * glm::vec4(center.x, center.y, center.z, 1.0);
+    let snapped = glm::vec4(
+        (across.x / texel).round() * texel,
+        (across.y / texel).round() * texel,
+        across.z,
+        1.0,
+    );
+    let center = (glm::inverse(&rotation) * snapped).xyz();
+
+    let reach = (DEPTH_REACH * radius).max(MIN_DEPTH_REACH);
+    let eye = center - direction * reach;
+    let view = glm::look_at(&eye, &center, &up);
+    let projection = glm::ortho(-radius, radius, -radius, radius, 0.0, 2.0 * reach);
+    projection * view
+}
+
+// The depth of the scene as seen from the sun, a layer per slice of the camera's view, and how to
+// get there
+pub struct ShadowMap {
+    framebuffer: Framebuffer,
+    // The view and projection of the sun for each slice, and how far ahead of the camera the slice
+    // ends, as worked out by `fit`
+    pub light_spaces: [glm::Mat4; CASCADES],
+    pub splits: [f32; CASCADES],
+    camera_forward: glm::Vec3,
+    // What was drawn into before `begin`, to go back to at `end`
+    previous_framebuffer: u32,
+    previous_viewport: [i32; 4],
+}
+
+impl ShadowMap {
+    // A map of `resolution` texels square for each slice
+    pub unsafe fn new(resolution: u32) -> Result<ShadowMap, RenderError> {
+        let target = gl::TEXTURE_2D_ARRAY;
+        let framebuffer =
+            Framebuffer::layered_depth(target, resolution, CASCADES as u32, gl::DEPTH_COMPONENT24)?;
+        if let Some(texture) = framebuffer.depth_texture() {
+            gl::BindTexture(target, texture);
+            // Sampled with comparisons, which filtering blends into soft edges
+            gl::TexParameteri(
+                target,
+                gl::TEXTURE_COMPARE_MODE,
+                gl::COMPARE_REF_TO_TEXTURE as i32,
+            );
+            gl::TexParameteri(target, gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL as i32);
+            // Beyond the map, everything is lit
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_BORDER as i32);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_BORDER as i32);
+            let border = [1.0f32; 4];
+            gl::TexParameterfv(target, gl::TEXTURE_BORDER_COLOR, border.as_ptr());
+            gl::BindTexture(target, 0);
+        }
+        Ok(ShadowMap {
+            framebuffer,
+            light_spaces: [glm::identity(); CASCADES],
+            splits: [0.0; CASCADES],
+            camera_forward: glm::vec3(0.0, 0.0, -1.0),
+            previous_framebuffer: 0,
+            previous_viewport: [0; 4],
+        })
+    }
+
+    pub fn resolution(&self) -> u32 {
+        self.framebuffer.size().0
+    }
+
+    // Fit the slices to the view of a camera at `view_matrix`, with a vertical field of view of
+    // `fov_y` radians, shadowing from `near` ahead of it to `far`, for a sun shining along
+    // `direction`
+    pub fn fit(
+        &mut self,
+        direction: &glm::Vec3,
+        view_matrix: &glm::Mat4,
+        fov_y: f32,
+        aspect: f32,
+        near: f32,
+        far: f32,
+    ) {
+        let inverse_view = glm::inverse(view_matrix);
+        self.camera_forward = -inverse_view.column(2).xyz();
+        self.splits = cascade_splits(near, far);
+        let resolution = self.resolution();
+        for (i, light_space) in self.light_spaces.iter_mut().enumerate() {
+            let start = if i == 0 { near } else { self.splits[i - 1] };
+            let (center, radius) =
+                view_slice_sphere(&inverse_view, fov_y, aspect, start, self.splits[i]);
+            *light_space = light_space_matrix(direction, &center, radius, resolution);
+        }
+    }
+
+    // Draw into the map from now on, until `end`, a slice at a time from `begin_cascade`. Back faces
+    // are drawn too, as the terrain is open underneath, and depths are pushed back a little from the
+    // light, against surfaces shadowing themselves.
+    pub unsafe fn begin(&mut self) {
+        self.previous_framebuffer = offscreen::bound_framebuffer();
+        gl::GetIntegerv(gl::VIEWPORT, self.previous_viewport.as_mut_ptr());
+        self.framebuffer.bind();
+        gl::Disable(gl::CULL_FACE);
+        gl::Enable(gl::POLYGON_OFFSET_FILL);
+        gl::PolygonOffset(2.0, 4.0);
+    }
+
+    // Draw into the layer of slice `cascade` from now on, with `light_spaces[cascade]` as the draws'
+    // view-projection matrix
+    pub unsafe fn begin_cascade(&self, cascade: usize) {
+        self.framebuffer.attach_layer(Some(cascade as u32));
+        gl::Clear(gl::DEPTH_BUFFER_BIT);
+    }
+
+    // Go back to drawing where `begin` was called
+    pub unsafe fn end(&self) {
+        gl::Disable(gl::POLYGON_OFFSET_FILL);
+        gl::Enable(gl::CULL_FACE);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.previous_framebuffer);
+        let [x, y, width, height] = self.previous_viewport;
+        gl::Viewport(x, y, width, height);
+    }
+
+    // Have `pipeline`, whose shaders declare `shadows`, `lightSpaceMatrices`, `cascadeSplits`,
+    // `cameraForward` and `shadowMap`, shade with the map, or without shadows unless `enabled`
+    pub unsafe fn apply(&self, backend: &GlBackend, pipeline: PipelineHandle, enabled: bool) {
+        let program = backend.program_id(pipeline);
+        let uniforms = backend.uniforms(pipeline);
+        let location = |name: &str| uniforms.get(name).map_or(-1, |uniform| uniform.location);
+        if let Some(texture) = self.framebuffer.depth_texture() {
+            gl::ActiveTexture(gl::TEXTURE0 + SHADOW_TEXTURE_UNIT);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, texture);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+        gl::ProgramUniform1i(program, location("shadows"), enabled as i32);
+        gl::ProgramUniform1i(program, location("shadowMap"), SHADOW_TEXTURE_UNIT as i32);
+        gl::ProgramUniformMatrix4fv(
+            program,
+            location("lightSpaceMatrices"),
+            CASCADES as i32,
+            gl::FALSE,
+            self.light_spaces[0].as_ptr(),
+        );
+        gl::ProgramUniform1fv(
+            program,
+            location("cascadeSplits"),
+            CASCADES as i32,
+            self.splits.as_ptr(),
+        );
+        gl::ProgramUniform3fv(
+            program,
+            location("cameraForward"),
+            1,
+            self.camera_forward.as_ptr(),
+        );
+    }
+
+    pub unsafe fn delete(self) {
+        self.framebuffer.delete();
+    }
+}
+
+// The view and projection of each face of the cube map around a point light at `position`, in the
+// order of the cube map's faces: +X, -X, +Y, -Y, +Z and -Z. Faces are seen from inside the cube,
+// which is why up is down for the four faces around it.
+pub fn cube_face_matrices(position: &glm::Vec3, range: f32) -> [glm::Mat4; 6] {
+    #[rustfmt::skip]
+    let faces = [
+        (glm::vec3( 1.0,  0.0,  0.0), glm::vec3(0.0, -1.0,  0.0)),
+        (glm::vec3(-1.0,  0.0,  0.0), glm::vec3(0.0, -1.0,  0.0)),
+        (glm::vec3( 0.0,  1.0,  0.0), glm::vec3(0.0,  0.0,  1.0)),
+        (glm::vec3( 0.0, -1.0,  0.0), glm::vec3(0.0,  0.0, -1.0)),
+        (glm::vec3( 0.0,  0.0,  1.0), glm::vec3(0.0, -1.0,  0.0)),
+        (glm::vec3( 0.0,  0.0, -1.0), glm::vec3(0.0, -1.0,  0.0)),
+    ];
+    let projection = glm::perspective(1.0, std::f32::consts::FRAC_PI_2, POINT_SHADOW_NEAR, range);
+    faces.map(|(forward, up)| projection * glm::look_at(position, &(position + forward), &up))
+}
+
+// A view-projection matrix whose frustum is a box around everything within `range` of `position`,
+// for culling to what a point light reaches
+pub fn reach_matrix(position: &glm::Vec3, range: f32) -> glm::Mat4 {
+    let eye = position + glm::vec3(0.0, 0.0, range);
+    let view = glm::look_at(&eye, position, &glm::vec3(0.0, 1.0, 0.0));
+    glm::ortho(-range, range, -range, range, 0.0, 2.0 * range) * view
+}
+
+// How far the scene is from up to `MAX_SHADOWED_POINT_LIGHTS` point lights, in every direction
+pub struct PointShadowMaps {
+    framebuffer: Framebuffer,
+    previous_framebuffer: u32,
+    previous_viewport: [i32; 4],
+}
+
+impl PointShadowMaps {
+    // Cube maps of `resolution` texels square on each face
+    pub unsafe fn new(resolution: u32) -> Result<PointShadowMaps, RenderError> {
+        let framebuffer = Framebuffer::layered_depth(
+            gl::TEXTURE_CUBE_MAP_ARRAY,
+            resolution,
+            MAX_SHADOWED_POINT_LIGHTS as u32 * 6,
+            gl::DEPTH_COMPONENT24,
+        )?;
+        if let Some(texture) = framebuffer.depth_texture() {
+            let target = gl::TEXTURE_CUBE_MAP_ARRAY;
+            gl::BindTexture(target, texture);
+            gl::TexParameteri(
+                target,
+                gl::TEXTURE_COMPARE_MODE,
+                gl::COMPARE_REF_TO_TEXTURE as i32,
+            );
+            gl::TexParameteri(target, gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL as i32);
+            gl::BindTexture(target, 0);
+        }
+        Ok(PointShadowMaps {
+            framebuffer,
+            previous_framebuffer: 0,
+            previous_viewport: [0; 4],
+        })
+    }
+
+    // Draw into the maps from now on, until `end`, clearing them all. Back faces are drawn too, as
+    // the lights are often under things like the terrain, whose backs face them.
+    pub unsafe fn begin(&mut self) {
+        self.previous_framebuffer = offscreen::bound_framebuffer();
+        gl::GetIntegerv(gl::VIEWPORT, self.previous_viewport.as_mut_ptr());
+        self.framebuffer.bind();
+        gl::Clear(gl::DEPTH_BUFFER_BIT);
+        gl::Disable(gl::CULL_FACE);
+    }
+
+    // Draw the shadows of `light` into cube map `index` with `pipeline`, whose shaders declare
+    // `faceMatrices`, `cubeMap`, `lightPosition` and `lightRange`. Draws should be made with the
+    // model matrix alone as their view-projection matrix, as the geometry shader projects them.
+    pub unsafe fn set_light(
+        &self,
+        backend: &GlBackend,
+        pipeline: PipelineHandle,
+        index: usize,
+        light: &PointLight,
+    ) {
+        let program = backend.program_id(pipeline);
+        let uniforms = backend.uniforms(pipeline);
+        let location = |name: &str| uniforms.get(name).map_or(-1, |uniform| uniform.location);
+        let faces = cube_face_matrices(&light.position, light.range);
+        gl::ProgramUniformMatrix4fv(
+            program,
+            location("faceMatrices"),
+            6,
+            gl::FALSE,
+            faces[0].as_ptr(),
+        );
+        gl::ProgramUniform1i(program, location("cubeMap"), index as i32);
+        gl::ProgramUniform3fv(
+            program,
+            location("lightPosition"),
+            1,
+            light.position.as_ptr(),
+        );
+        gl::ProgramUniform1f(program, location("lightRange"), light.range);
+    }
+
+    // Go back to drawing where `begin` was called
+    pub unsafe fn end(&self) {
+        gl::Enable(gl::CULL_FACE);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.previous_framebuffer);
+        let [x, y, width, height] = self.previous_viewport;
+        gl::Viewport(x, y, width, height);
+    }
+
+    // Have `pipeline`, whose shaders declare `pointShadowMaps`, shade with the maps
+    pub unsafe fn apply(&self, backend: &GlBackend, pipeline: PipelineHandle) {
+        let program = backend.program_id(pipeline);
+        let location = backend
+            .uniforms(pipeline)
+            .get("pointShadowMaps")
+            .map_or(-1, |uniform| uniform.location);
+        if let Some(texture) = self.framebuffer.depth_texture() {
+            gl::ActiveTexture(gl::TEXTURE0 + POINT_SHADOW_TEXTURE_UNIT);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP_ARRAY, texture);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+        gl::ProgramUniform1i(program, location, POINT_SHADOW_TEXTURE_UNIT as i32);
+    }
+
+    pub unsafe fn delete(self) {
+        self.framebuffer.delete();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(matrix: &glm::Mat4, point: glm::Vec3) -> glm::Vec3 {
+        let clip = matrix * glm::vec4(point.x, point.y, point.z, 1.0);
+        clip.xyz() / clip.w
+    }
+
+    #[test]
+    fn the_light_s_view_is_centered_and_deepens_along_the_light() {
+        let direction = glm::vec3(0.8, -0.5, 0.6);
+        let center = glm::vec3(10.0, 0.0, -20.0);
+        let matrix = light_space_matrix(&direction, &center, 50.0, 1024);
+
+        let middle = project(&matrix, center);
+        assert!(middle.xy().norm() < 0.01);
+        assert!(middle.z.abs() < 1e-4);
+        let further = project(&matrix, center + glm::normalize(&direction) * 10.0);
+        assert!(further.z > middle.z);
+        // Everything within the radius is in the map
+        for offset in [glm::vec3(49.0, 0.0, 0.0), glm::vec3(0.0, 49.0, 0.0)] {
+            let point = project(&matrix, center + offset);
+            assert!(point.x.abs() <= 1.0 && point.y.abs() <= 1.0 && point.z.abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn moving_less_than_a_texel_keeps_the_map_in_place() {
+        let direction = glm::vec3(0.0, -1.0, 0.0);
+        // Texels are 0.1 across
+        let a = light_space_matrix(&direction, &glm::vec3(0.01, 0.0, 0.0), 51.2, 1024);
+        let b = light_space_matrix(&direction, &glm::vec3(0.03, 0.0, 0.0), 51.2, 1024);
+        assert!((a - b).abs().max() < 1e-6);
+        let c = light_space_matrix(&direction, &glm::vec3(0.3, 0.0, 0.0), 51.2, 1024);
+        assert!((a - c).abs().max() > 1e-3);
+    }
+
+    #[test]
+    fn slices_get_longer_further_from_the_camera() {
+        let splits = cascade_splits(1.0, 400.0);
+        assert!((splits[CASCADES - 1] - 400.0).abs() < 1e-3);
+        let lengths: Vec<f32> = (0..CASCADES)
+            .map(|i| splits[i] - if i == 0 { 1.0 } else { splits[i - 1] })
+            .collect();
+        assert!(lengths.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn slice_spheres_hold_the_whole_slice() {
+        let view = glm::look_at(
+            &glm::vec3(10.0, 5.0, 0.0),
+            &glm::vec3(10.0, 5.0, -1.0),
+            &glm::vec3(0.0, 1.0, 0.0),
+        );
+        let inverse_view = glm::inverse(&view);
+        let (fov_y, aspect) = (1.0_f32, 1.5);
+        let (center, radius) = view_slice_sphere(&inverse_view, fov_y, aspect, 10.0, 30.0);
+        assert!(glm::distance(&center, &glm::vec3(10.0, 5.0, -20.0)) < 1e-4);
+        assert_eq!(radius, radius.round());
+        for depth in [10.0, 30.0] {
+            let half_height = depth * (fov_y / 2.0).tan();
+            for (x, y) in [(1.0, 1.0), (-1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)] {
+                let corner = glm::vec4(x * half_height * aspect, y * half_height, -depth, 1.0);
+                let corner = (inverse_view * corner).xyz();
+                assert!(glm::distance(&corner, &center) <= radius);
+            }
+        }
+    }
+
+    #[test]
+    fn cube_faces_look_along_the_axes() {
+        let position = glm::vec3(5.0, 10.0, -3.0);
+        let faces = cube_face_matrices(&position, 20.0);
+        let axes = [
+            glm::vec3(1.0, 0.0, 0.0),
+            glm::vec3(-1.0, 0.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+            glm::vec3(0.0, -1.0, 0.0),
+            glm::vec3(0.0, 0.0, 1.0),
+            glm::vec3(0.0, 0.0, -1.0),
+        ];
+        for (face, axis) in faces.iter().zip(axes) {
+            // Points along the face's axis are in the middle of it, deeper the further they are
+            let near = project(face, position + axis * 5.0);
+            let far = project(face, position + axis * 15.0);
+            assert!(near.xy().norm() < 1e-4 && far.xy().norm() < 1e-4);
+            assert!(-1.0 < near.z && near.z < far.z && far.z < 1.0);
+        }
+    }
+}
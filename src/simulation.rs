@@ -0,0 +1,236 @@
+// Simulating the scene on a dedicated update thread.
+//
+// Animation and game logic that doesn't need OpenGL can run on its own thread at a fixed rate, so
+// heavy per-node logic doesn't eat into the frame budget. After every step the update thread
+// publishes a snapshot of what should be drawn, keeping the previous one as well, and the render
+// thread draws a state interpolated between the two. The rendered frame lags at most one step
+// behind the simulation, and motion stays smooth whatever the frame rate.
+//
+// Replays and captures must produce the same frames every time, so there the simulation is
+// stepped inline on the render thread by the frame clock instead, see `Simulator::inline`.
+use crate::timing::FixedTimestep;
+use crate::toolbox::Transform;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Blending between two states, `self` at `t` = 0 and `next` at `t` = 1
+pub trait Interpolate {
+    fn interpolate(&self, next: &Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, next: &f32, t: f32) -> f32 {
+        self + (next - self) * t
+    }
+}
+
+impl Interpolate for glm::Vec3 {
+    fn interpolate(&self, next: &glm::Vec3, t: f32) -> glm::Vec3 {
+        glm::lerp(self, next, t)
+    }
+}
+
+impl Interpolate for Transform {
+    fn interpolate(&self, next: &Transform, t: f32) -> Transform {
+        Transform {
+            position: self.position.interpolate(&next.position, t),
+            rotation: self.rotation.interpolate(&next.rotation, t),
+            scale: self.scale.interpolate(&next.scale, t),
+        }
+    }
+}
+
+// Elements are blended pairwise. If elements were added or removed, there is nothing to blend
+// them with, so the newer list is used as is.
+impl<T: Interpolate + Clone> Interpolate for Vec<T> {
+    fn interpolate(&self, next: &Vec<T>, t: f32) -> Vec<T> {
+        if self.len() != next.len() {
+            return next.clone();
+        }
+        self.iter()
+            .zip(next)
+            .map(|(current, next)| current.interpolate(next, t))
+            .collect()
+    }
+}
+
+// Scene logic stepped at a fixed rate, possibly on another thread than the one rendering it
+pub trait Simulation: Send + 'static {
+    // What the render thread tells the simulation, e.g. which keys are held
+    type Input: Send + 'static;
+    // The state the render thread needs for drawing
+    type Snapshot: Interpolate + Clone + Send + Sync + 'static;
+
+    // Called with every input sent since the previous step, right before the step
+    fn input(&mut self, input: Self::Input);
+
+    fn step(&mut self, delta_time: f32);
+
+    fn snapshot(&self) -> Self::Snapshot;
+}
+
+// The two latest snapshots. The lock is only held to swap pointers, never while stepping or
+// drawing, so neither thread waits on the other's work.
+struct Published<T> {
+    previous: Arc<T>,
+    current: Arc<T>,
+    time: Instant, // When the current snapshot was due
+}
+
+enum Runner<S: Simulation> {
+    Thread {
+        published: Arc<Mutex<Published<S::Snapshot>>>,
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    },
+    Inline {
+        simulation: S,
+        inputs: Receiver<S::Input>,
+        timestep: FixedTimestep,
+        previous: S::Snapshot,
+        current: S::Snapshot,
+    },
+}
+
+pub struct Simulator<S: Simulation> {
+    step: f32,
+    inputs: Sender<S::Input>,
+    runner: Runner<S>,
+}
+
+impl<S: Simulation> Simulator<S> {
+    // Step the simulation every `step` seconds of real time on an update thread of its own. The
+    // thread stops when the simulator is dropped.
+    pub fn spawn(mut simulation: S, step: f32) -> Simulator<S> {
+        let (inputs, receiver) = channel();
+        let initial = Arc::new(simulation.snapshot());
+        let published = Arc::new(Mutex::new(Published {
+            previous: Arc::clone(&initial),
+            current: initial,
+            time: Instant::now(),
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread_published = Arc::clone(&published);
+        let thread_stop = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            let step_duration = Duration::from_secs_f32(step);
+            let mut next_step = Instant::now() + step_duration;
+            while !thread_stop.load(Ordering::Relaxed) {
+                let now = Instant::now();
+                if now < next_step {
+                    thread::sleep(next_step - now);
+                    continue;
+                }
+                // Far behind, e.g. after the machine was suspended, so skip ahead instead of
+                // catching up with a burst of steps
+                if now - next_step > step_duration * 5 {
+                    next_step = now;
+                }
+                for input in receiver.try_iter() {
+                    simulation.input(input);
+                }
+                simulation.step(step);
+                let snapshot = Arc::new(simulation.snapshot());
+                if let Ok(mut published) = thread_published.lock() {
+                    published.previous = std::mem::replace(&mut published.current, snapshot);
+                    published.time = next_step;
+                }
+                next_step += step_duration;
+            }
+        });
+
+        Simulator {
+            step,
+            inputs,
+            runner: Runner::Thread {
+                published,
+                stop,
+                thread: Some(thread),
+            },
+        }
+    }
+
+    // Step the simulation on the calling thread from `advance`, by the frame clock rather than
+    // real time, so the same frame times always give the same states
+    pub fn inline(simulation: S, step: f32) -> Simulator<S> {
+        let (inputs, receiver) = channel();
+        let initial = simulation.snapshot();
+        Simulator {
+            step,
+            inputs,
+            runner: Runner::Inline {
+                simulation,
+                inputs: receiver,
+                timestep: FixedTimestep::new(step),
+                previous: initial.clone(),
+                current: initial,
+            },
+        }
+    }
+
+    // Pass input to the simulation, applied before its next step
+    pub fn send(&self, input: S::Input) {
+        // Only fails if the update thread panicked, which leaves the last snapshot on screen
+        let _ = self.inputs.send(input);
+    }
+
+    // Run the steps that fit into a frame of `delta_time` seconds. Does nothing when the
+    // simulation runs on its own thread.
+    pub fn advance(&mut self, delta_time: f32) {
+        if let Runner::Inline {
+            simulation,
+            inputs,
+            timestep,
+            previous,
+            current,
+        } = &mut self.runner
+        {
+            for input in inputs.try_iter() {
+                simulation.input(input);
+            }
+            for _ in 0..timestep.advance(delta_time) {
+                simulation.step(self.step);
+                *previous = std::mem::replace(current, simulation.snapshot());
+            }
+        }
+    }
+
+    // The state to draw now, between the two latest steps
+    pub fn snapshot(&self) -> S::Snapshot {
+        match &self.runner {
+            Runner::Thread { published, .. } => {
+                let (previous, current, time) = {
+                    let published = published.lock().unwrap_or_else(|e| e.into_inner());
+                    (
+                        Arc::clone(&published.previous),
+                        Arc::clone(&published.current),
+                        published.time,
+                    )
+                };
+                let alpha = time.elapsed().as_secs_f32() / self.step;
+                previous.interpolate(&current, alpha.clamp(0.0, 1.0))
+            }
+            Runner::Inline {
+                timestep,
+                previous,
+                current,
+                ..
+            } => previous.interpolate(current, timestep.alpha()),
+        }
+    }
+}
+
+impl<S: Simulation> Drop for Simulator<S> {
+    fn drop(&mut self) {
+        if let Runner::Thread { stop, thread, .. } = &mut self.runner {
+            stop.store(true, Ordering::Relaxed);
+            if let Some(thread) = thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+// Skeletal animation: meshes bent by a hierarchy of joints, like a windsock swaying in the wind or
+// a rigged character.
+//
+// A `Skeleton` is a tree of joints, each placed relative to its parent like a scene node. Skinned
+// meshes give every vertex four joints and how much each of them moves it, see `Mesh::joints` and
+// `Mesh::weights`. Posing the skeleton gives a matrix per joint, which takes a vertex from where it
+// was modelled to where the joint's movement since then puts it. The vertex shader blends those
+// matrices by the vertex's weights, so meshes are skinned on the GPU and only the joint matrices
+// change from frame to frame. They are set on the node drawing the mesh, see `SceneNode::joints`.
+//
+// A `SkeletalAnimation` plays an `AnimationClip` on each joint it moves, like the channels of an
+// animation in a glTF file target the translation, rotation and scale of the nodes of a skin.
+// `gltf::load` reads the skeletons and animations of rigged models from such files.
+use crate::toolbox::{self, AnimationClip, Transform};
+
+// As many joints as the vertex shader has room for, see `jointMatrices` in `simple.vert`
+pub const MAX_JOINTS: usize = 64;
+
+#[derive(Clone, Debug)]
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>, // Which comes before the joint in its skeleton
+    pub rest: Transform,       // Relative to the parent, where the joint is when not animated
+    // From the space of the mesh to the joint's, in the pose the mesh was modelled in
+    pub inverse_bind: glm::Mat4,
+}
+
+impl Joint {
+    pub fn new(name: &str, parent: Option<usize>, rest: Transform) -> Joint {
+        Joint {
+            name: name.to_string(),
+            parent,
+            rest,
+            inverse_bind: glm::identity(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+}
+
+impl Skeleton {
+    // Parents must come before their children, so poses can be worked out in a single pass
+    pub fn new(joints: Vec<Joint>) -> Skeleton {
+        assert!(
+            joints.len() <= MAX_JOINTS,
+            "A skeleton has at most {} joints",
+            MAX_JOINTS
+        );
+        for (i, joint) in joints.iter().enumerate() {
+            assert!(
+                joint.parent.is_none_or(|parent| parent < i),
+                "Joint {} comes before its parent",
+                joint.name
+            );
+        }
+        Skeleton { joints }
+    }
+
+    // Bind the mesh in the rest pose, which is how meshes made for a skeleton are usually modelled
+    pub fn bound_at_rest(mut self) -> Skeleton {
+        let rest = self.world_transforms(&self.rest_pose());
+        for (joint, transform) in self.joints.iter_mut().zip(rest) {
+            joint.inverse_bind = glm::inverse(&transform);
+        }
+        self
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.joints.iter().map(|joint| joint.rest).collect()
+    }
+
+    // Where the joints are in the space of the mesh in `pose`, which has the transform of each
+    // joint relative to its parent
+    pub fn world_transforms(&self, pose: &[Transform]) -> Vec<glm::Mat4> {
+        let mut transforms: Vec<glm::Mat4> = Vec::with_capacity(self.joints.len());
+        for (joint, local) in self.joints.iter().zip(pose) {
+            let local = toolbox::compose_transform(
+                &local.position,
+                &local.rotation,
+                &local.scale,
+                &glm::zero(),
+            );
+            let transform = match joint.parent {
+                Some(parent) => transforms[parent] * local,
+                None => local,
+            };
+            transforms.push(transform);
+        }
+        transforms
+    }
+
+    // The matrices skinning the mesh in `pose`, for the vertex shader
+    pub fn joint_matrices(&self, pose: &[Transform]) -> Vec<glm::Mat4> {
+        self.world_transforms(pose)
+            .iter()
+            .zip(&self.joints)
+            .map(|(transform, joint)| transform * joint.inverse_bind)
+            .collect()
+    }
+}
+
+// Clips played back together on the joints of a skeleton, e.g. a walk cycle
+#[derive(Clone, Debug, Default)]
+pub struct SkeletalAnimation {
+    pub name: String,
+    pub channels: Vec<(usize, AnimationClip)>, // The joint each clip moves
+}
+
+impl SkeletalAnimation {
+    pub fn new(name: &str) -> SkeletalAnimation {
+        SkeletalAnimation {
+            name: name.to_string(),
+            channels: vec![],
+        }
+    }
+
+    pub fn with_joint(mut self, joint: usize, clip: AnimationClip) -> SkeletalAnimation {
+        self.channels.push((joint, clip));
+        self
+    }
+
+    // How long the longest of the clips is
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .map(|(_, clip)| clip.duration)
+            .fold(0.0, f32::max)
+    }
+
+    // The pose of `skeleton` `time` seconds into the animation. Joints without a clip, and what
+    // their clips don't animate, stay as they are at rest.
+    pub fn sample(&self, skeleton: &Skeleton, time: f32) -> Vec<Transform> {
+        let mut pose = skeleton.rest_pose();
+        for (joint, clip) in &self.channels {
+            if let Some(transform) = pose.get_mut(*joint) {
+                clip.apply(time, transform);
+            }
+        }
+        pose
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toolbox::{Channel, Interpolation, Looping, Track};
+    use std::f32::consts::FRAC_PI_2;
+
+    // A root at the origin with an arm reaching up from it, and a hand at the end of the arm
+    fn arm() -> Skeleton {
+        let up = Transform {
+            position: glm::vec3(0.0, 2.0, 0.0),
+            ..Default::default()
+        };
+        Skeleton::new(vec![
+            Joint::new("root", None, Transform::default()),
+            Joint::new("arm", Some(0), up),
+            Joint::new("hand", Some(1), up),
+        ])
+        .bound_at_rest()
+    }
+
+    fn moved(matrix: &glm::Mat4, point: glm::Vec3) -> glm::Vec3 {
+        (matrix * glm::vec4(point.x, point.y, point.z, 1.0)).xyz()
+    }
+
+    #[test]
+    fn the_rest_pose_leaves_the_mesh_as_it_was_modelled() {
+        let skeleton = arm();
+        for matrix in skeleton.joint_matrices(&skeleton.rest_pose()) {
+            assert!((matrix - glm::Mat4::identity()).abs().max() < 1e-5);
+        }
+        assert_eq!(skeleton.find("hand"), Some(2));
+        assert_eq!(skeleton.find("tail"), None);
+    }
+
+    #[test]
+    fn turning_a_joint_carries_its_children_along() {
+        let skeleton = arm();
+        let mut pose = skeleton.rest_pose();
+        // Tip the arm over about Z, towards -X
+        pose[1].rotation.z = FRAC_PI_2;
+        let matrices = skeleton.joint_matrices(&pose);
+
+        let tip = glm::vec3(0.0, 6.0, 0.0);
+        let elbow = glm::vec3(0.0, 3.0, 0.0);
+        assert!(glm::distance(&moved(&matrices[0], tip), &tip) < 1e-5);
+        assert!(glm::distance(&moved(&matrices[1], elbow), &glm::vec3(-1.0, 2.0, 0.0)) < 1e-5);
+        assert!(glm::distance(&moved(&matrices[2], tip), &glm::vec3(-4.0, 2.0, 0.0)) < 1e-5);
+    }
+
+    #[test]
+    fn animations_move_only_the_joints_they_have_clips_for() {
+        let skeleton = arm();
+        let wave = Track::new(Interpolation::Linear, vec![(0.0, 0.0), (2.0, 1.0)]);
+        let clip = AnimationClip::new(2.0, Looping::Repeat).with_channel(Channel::Angle(2, wave));
+        let animation = SkeletalAnimation::new("wave").with_joint(2, clip);
+        assert_eq!(animation.duration(), 2.0);
+
+        let pose = animation.sample(&skeleton, 1.0);
+        assert_eq!(pose[0], skeleton.joints[0].rest);
+        assert_eq!(pose[1], skeleton.joints[1].rest);
+        assert!((pose[2].rotation.z - 0.5).abs() < 1e-6);
+        assert_eq!(pose[2].position, skeleton.joints[2].rest.position);
+    }
+}
@@ -0,0 +1,283 @@
+// A sky drawn behind everything, from a cube map.
+//
+// A cube map is six square images, one per face of a cube around the camera, looked up by
+// direction rather than by texture coordinates: the face is picked by which axis the direction
+// points along the most, and the other two axes say where on the face. The sky is far enough away
+// that only which way the camera looks matters, not where it is, so it moves with the camera.
+//
+// The skybox pipeline draws a triangle covering the screen at the far plane, depth 1, after the
+// solid parts of the scene, with the depth test passing where it is equal, so it only shows where
+// nothing else was drawn. Each fragment turns its place on the screen back into a direction with
+// the inverse of the camera's view and projection, without the view's translation, and looks the
+// sky up in that direction.
+//
+// The faces are loaded from six images, or made from one equirectangular image, which has the
+// whole sky unrolled around the horizon like a map of the world, e.g. an HDR panorama. Colors are
+// kept in floats, so a sky brighter than white blooms and is tone mapped, see `post`.
+use crate::backend::gl::GlBackend;
+use crate::backend::{Backend, PipelineHandle};
+use crate::error::{GlError, RenderError, TextureError};
+use crate::gl_check;
+use image::Rgb32FImage;
+use std::path::Path;
+
+// Where the cube map is bound while drawing, clear of the scene's and post-processing's textures
+pub const SKYBOX_TEXTURE_UNIT: u32 = 11;
+
+// The faces in OpenGL's order, +X, -X, +Y, -Y, +Z and -Z, by the names of their image files
+pub const FACE_NAMES: [&str; 6] = ["right", "left", "top", "bottom", "front", "back"];
+
+// The six faces of a cube map, in the order of `FACE_NAMES`, squares of one size
+pub type Faces = [Rgb32FImage; 6];
+
+// The direction through (`u`, `v`) on `face`, each from -1 to 1, with -1 at the first column and
+// the first row of the face's image. Not normalized.
+pub fn face_direction(face: usize, u: f32, v: f32) -> glm::Vec3 {
+    match face {
+        0 => glm::vec3(1.0, -v, -u),
+        1 => glm::vec3(-1.0, -v, u),
+        2 => glm::vec3(u, 1.0, v),
+        3 => glm::vec3(u, -1.0, -v),
+        4 => glm::vec3(u, -v, 1.0),
+        _ => glm::vec3(-u, -v, -1.0),
+    }
+}
+
+// The face `direction` points at, and where on it, undoing `face_direction`
+pub fn face_at(direction: &glm::Vec3) -> (usize, f32, f32) {
+    let [x, y, z] = [direction.x, direction.y, direction.z];
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    if ax >= ay && ax >= az {
+        if x > 0.0 {
+            (0, -z / ax, -y / ax)
+        } else {
+            (1, z / ax, -y / ax)
+        }
+    } else if ay >= az {
+        if y > 0.0 {
+            (2, x / ay, z / ay)
+        } else {
+            (3, x / ay, -z / ay)
+        }
+    } else if z > 0.0 {
+        (4, x / az, -y / az)
+    } else {
+        (5, -x / az, -y / az)
+    }
+}
+
+// Load the faces from `path`: a directory with an image named after each of `FACE_NAMES`, like
+// `top.png`, or a single equirectangular image, made into faces `face_size` texels across
+pub fn load(path: &Path, face_size: u32) -> Result<Faces, TextureError> {
+    if path.is_dir() {
+        load_faces(path)
+    } else {
+        Ok(from_equirect(&open(path)?, face_size))
+    }
+}
+
+fn open(path: &Path) -> Result<Rgb32FImage, TextureError> {
+    image::open(path)
+        .map(|image| image.to_rgb32f())
+        .map_err(|source| TextureError::Load {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+fn load_faces(dir: &Path) -> Result<Faces, TextureError> {
+    let [right, left, top, bottom, front, back] =
+        FACE_NAMES.map(|name| open(&face_path(dir, name)));
+    let faces = [right?, left?, top?, bottom?, front?, back?];
+    let size = faces[0].width();
+    if faces
+        .iter()
+        .any(|face| face.width() != size || face.height() != size)
+    {
+        return Err(TextureError::Faces {
+            path: dir.to_path_buf(),
+        });
+    }
+    Ok(faces)
+}
+
+// The image in `dir` named `name`, whatever kind of image it is, or a PNG if there is none, so the
+// error says what was looked for
+fn face_path(dir: &Path, name: &str) -> std::path::PathBuf {
+    std::fs::read_dir(dir)
+        .ok()
+        .and_then(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .find(|path| path.file_stem().is_some_and(|stem| stem == name))
+        })
+        .unwrap_or_else(|| dir.join(format!("{}.png", name)))
+}
+
+// Faces `face_size` texels across, looked up in `panorama`, whose columns go once around the
+// horizon, starting behind -Z and turning towards +X, and whose rows go from straight up to
+// straight down
+pub fn from_equirect(panorama: &Rgb32FImage, face_size: u32) -> Faces {
+    let (width, height) = panorama.dimensions();
+    std::array::from_fn(|face| {
+        Rgb32FImage::from_fn(face_size, face_size, |x, y| {
+            let [u, v] = [x, y].map(|i| 2.0 * (i as f32 + 0.5) / face_size as f32 - 1.0);
+            let direction = glm::normalize(&face_direction(face, u, v));
+            let longitude = direction.x.atan2(-direction.z) / std::f32::consts::TAU + 0.5;
+            let latitude = 0.5 - direction.y.clamp(-1.0, 1.0).asin() / std::f32::consts::PI;
+            let column = (longitude * width as f32) as u32 % width;
+            let row = ((latitude * height as f32) as u32).min(height - 1);
+            *panorama.get_pixel(column, row)
+        })
+    })
+}
+
+pub struct Skybox {
+    texture: u32,
+    vao: u32, // Empty, as the vertex shader makes the triangle up itself
+}
+
+impl Skybox {
+    pub unsafe fn new(faces: &Faces) -> Result<Skybox, RenderError> {
+        let size = faces[0].width() as i32;
+        // Mipmapped down to a texel, so the sky can be looked up blurred, see `environment`
+        let levels = 32 - size.leading_zeros() as i32;
+        let mut texture = 0;
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, texture);
+        let mut result: Result<(), GlError> = gl_check!(gl::TexStorage2D(
+            gl::TEXTURE_CUBE_MAP,
+            levels,
+            gl::RGB16F,
+            size,
+            size
+        ));
+        for (i, face) in faces.iter().enumerate() {
+            if result.is_err() {
+                break;
+            }
+            result = gl_check!(gl::TexSubImage2D(
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32,
+                0,
+                0,
+                0,
+                size,
+                size,
+                gl::RGB,
+                gl::FLOAT,
+                face.as_ptr() as *const std::ffi::c_void
+            ));
+        }
+        gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+        gl::TexParameteri(
+            gl::TEXTURE_CUBE_MAP,
+            gl::TEXTURE_MIN_FILTER,
+            gl::LINEAR_MIPMAP_LINEAR as i32,
+        );
+        gl::TexParameteri(
+            gl::TEXTURE_CUBE_MAP,
+            gl::TEXTURE_MAG_FILTER,
+            gl::LINEAR as i32,
+        );
+        for wrap in [gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, wrap, gl::CLAMP_TO_EDGE as i32);
+        }
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+        // Filter across the edges between the faces too, so they don't show as seams
+        gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+
+        let mut vao = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        let skybox = Skybox { texture, vao };
+        match result {
+            Ok(()) => Ok(skybox),
+            Err(e) => {
+                skybox.delete();
+                Err(e.into())
+            }
+        }
+    }
+
+    // The cube map, for lighting the scene with the sky
+    pub fn texture(&self) -> u32 {
+        self.texture
+    }
+
+    // Draw the sky with `pipeline`, the skybox one, where nothing has been drawn yet, as seen with
+    // `view` and `projection`
+    pub unsafe fn draw(
+        &self,
+        backend: &mut GlBackend,
+        pipeline: PipelineHandle,
+        view: &glm::Mat4,
+        projection: &glm::Mat4,
+    ) {
+        // Turned like the camera, but not moved with it
+        let rotation = glm::mat3_to_mat4(&glm::mat4_to_mat3(view));
+        let inverse = glm::inverse(&(projection * rotation));
+        backend.set_pipeline(pipeline);
+        let program = backend.program_id(pipeline);
+        let uniforms = backend.uniforms(pipeline);
+        let location = |name: &str| uniforms.get(name).map_or(-1, |uniform| uniform.location);
+        gl::ProgramUniformMatrix4fv(
+            program,
+            location("inverseViewProjection"),
+            1,
+            gl::FALSE,
+            inverse.as_ptr(),
+        );
+        gl::ProgramUniform1i(program, location("sky"), SKYBOX_TEXTURE_UNIT as i32);
+
+        gl::ActiveTexture(gl::TEXTURE0 + SKYBOX_TEXTURE_UNIT);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.texture);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindVertexArray(self.vao);
+        gl::DepthFunc(gl::LEQUAL);
+        gl::DepthMask(gl::FALSE);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::DepthMask(gl::TRUE);
+        gl::DepthFunc(gl::LESS);
+        gl::BindVertexArray(0);
+    }
+
+    pub unsafe fn delete(self) {
+        gl::DeleteTextures(1, &self.texture);
+        gl::DeleteVertexArrays(1, &self.vao);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directions_find_their_way_back_to_their_face() {
+        for face in 0..6 {
+            for (u, v) in [(0.0, 0.0), (0.5, -0.25), (-0.9, 0.7)] {
+                let direction = face_direction(face, u, v) * 3.0;
+                let (found, found_u, found_v) = face_at(&direction);
+                assert_eq!(found, face);
+                assert!((found_u - u).abs() < 1e-6 && (found_v - v).abs() < 1e-6);
+            }
+        }
+        // The faces are where their names say
+        assert_eq!(face_at(&glm::vec3(0.0, 1.0, 0.0)).0, 2);
+        assert_eq!(face_at(&glm::vec3(0.0, 0.0, -1.0)).0, 5);
+    }
+
+    #[test]
+    fn panoramas_put_the_horizon_around_the_middle_faces() {
+        // Bright sky above the horizon, dark ground below it
+        let panorama = Rgb32FImage::from_fn(64, 32, |_, y| {
+            image::Rgb(if y < 16 { [1.0, 1.0, 1.0] } else { [0.0; 3] })
+        });
+        let faces = from_equirect(&panorama, 8);
+        assert!(faces[2].pixels().all(|pixel| pixel.0 == [1.0; 3]));
+        assert!(faces[3].pixels().all(|pixel| pixel.0 == [0.0; 3]));
+        for side in [0, 1, 4, 5] {
+            assert_eq!(faces[side].get_pixel(3, 0).0, [1.0; 3]);
+            assert_eq!(faces[side].get_pixel(3, 7).0, [0.0; 3]);
+        }
+    }
+}
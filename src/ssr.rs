@@ -0,0 +1,155 @@
+// Screen-space reflections: shiny surfaces reflecting what is on the screen, without drawing the
+// scene again.
+//
+// Drawn deferred, the G-buffer has where every pixel's surface is and which way it faces, so after
+// the resolve pass has lit it, the way the camera's view bounces off each physically shaded surface
+// can be followed through the scene, in steps: each step is projected onto the screen, and once it
+// is just behind the surface the G-buffer has there, the ray has hit it, and the lit scene is looked
+// up there. The reflection is mixed in as much as the surface reflects (Schlick), the less the
+// rougher it is, and faded out towards the edges of the screen and the end of the ray, where what
+// it would hit is missing.
+//
+// Only what is on the screen can be reflected, so rays leaving it, going towards the camera or into
+// the sky find nothing, and the surface keeps the sky's reflection (see `environment`). The scene is
+// looked up in the post-processing's texture, so the demo post-processes while reflecting.
+//
+// Tracing draws into a texture of its own, as the scene can't be read where it is drawn, and the
+// reflections are then drawn over the scene, mixed in by their alpha.
+use crate::backend::gl::GlBackend;
+use crate::backend::{Backend, PipelineHandle};
+use crate::deferred::GBuffer;
+use crate::error::RenderError;
+use crate::offscreen::{self, Framebuffer};
+
+// Where the scene, then the traced reflections, are bound while drawing, clear of the planar
+// reflection's unit
+pub const SSR_TEXTURE_UNIT: u32 = 15;
+
+// More steps than this take longer than they're worth, every pixel taking all of them on a miss
+pub const MAX_SSR_STEPS: u32 = 256;
+
+// What the ssr pipeline draws, as its `ssrStep` uniform
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    Trace = 0,     // The reflections, with how much of each shows in alpha
+    Composite = 1, // The reflections, over the scene
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SsrSettings {
+    pub max_distance: f32, // How far the rays are followed, in the world
+    pub thickness: f32,    // How far behind a surface a ray still hits it, rather than passing it
+    pub steps: u32,        // Along each ray, more of them finding thinner things
+    pub intensity: f32,    // How much of the reflections is mixed in
+}
+
+impl Default for SsrSettings {
+    fn default() -> SsrSettings {
+        SsrSettings {
+            max_distance: 60.0,
+            thickness: 1.5,
+            steps: 48,
+            intensity: 1.0,
+        }
+    }
+}
+
+pub struct ScreenSpaceReflections {
+    framebuffer: Framebuffer,
+    vao: u32, // Empty, as the vertex shader makes the triangle up itself
+}
+
+impl ScreenSpaceReflections {
+    pub unsafe fn new(size: (u32, u32)) -> Result<ScreenSpaceReflections, RenderError> {
+        let framebuffer = Framebuffer::new(size, &[gl::RGBA16F], None)?;
+        let mut vao = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        Ok(ScreenSpaceReflections { framebuffer, vao })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.framebuffer.size()
+    }
+
+    // Trace the surfaces in `gbuffer` through `scene`, the lit scene's texture, with `pipeline`,
+    // the ssr one, and draw what they reflect over what is bound, as seen by the camera at
+    // `camera_position` with `view_projection`
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn draw(
+        &self,
+        backend: &mut GlBackend,
+        pipeline: PipelineHandle,
+        gbuffer: &GBuffer,
+        scene: u32,
+        view_projection: &glm::Mat4,
+        camera_position: &glm::Vec3,
+        settings: &SsrSettings,
+    ) {
+        backend.set_pipeline(pipeline);
+        gbuffer.bind_textures(backend, &[pipeline]);
+        let program = backend.program_id(pipeline);
+        let uniforms = backend.uniforms(pipeline);
+        let location = |name: &str| uniforms.get(name).map_or(-1, |uniform| uniform.location);
+        let step = location("ssrStep");
+        gl::ProgramUniformMatrix4fv(
+            program,
+            location("viewProjection"),
+            1,
+            gl::FALSE,
+            view_projection.as_ptr(),
+        );
+        gl::ProgramUniform3fv(
+            program,
+            location("cameraPosition"),
+            1,
+            camera_position.as_ptr(),
+        );
+        gl::ProgramUniform1f(program, location("maxDistance"), settings.max_distance);
+        gl::ProgramUniform1f(program, location("thickness"), settings.thickness);
+        let steps = settings.steps.min(MAX_SSR_STEPS) as i32;
+        gl::ProgramUniform1i(program, location("steps"), steps);
+        gl::ProgramUniform1f(program, location("intensity"), settings.intensity);
+        gl::ProgramUniform1i(program, location("source"), SSR_TEXTURE_UNIT as i32);
+
+        let previous_framebuffer = offscreen::bound_framebuffer();
+        let mut previous_viewport = [0; 4];
+        gl::GetIntegerv(gl::VIEWPORT, previous_viewport.as_mut_ptr());
+        gl::BindVertexArray(self.vao);
+        gl::Disable(gl::DEPTH_TEST);
+        // The triangle covers the screen even when the scene is drawn in wireframe
+        let mut polygon_mode = [gl::FILL as i32; 2];
+        gl::GetIntegerv(gl::POLYGON_MODE, polygon_mode.as_mut_ptr());
+        gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+        let bind = |texture: u32| {
+            gl::ActiveTexture(gl::TEXTURE0 + SSR_TEXTURE_UNIT);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::ActiveTexture(gl::TEXTURE0);
+        };
+
+        self.framebuffer.bind();
+        gl::Disable(gl::BLEND);
+        bind(scene);
+        gl::ProgramUniform1i(program, step, Step::Trace as i32);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+        // The reflections come out already scaled by how much of them shows
+        gl::BindFramebuffer(gl::FRAMEBUFFER, previous_framebuffer);
+        let [x, y, width, height] = previous_viewport;
+        gl::Viewport(x, y, width, height);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+        bind(self.framebuffer.color_texture(0));
+        gl::ProgramUniform1i(program, step, Step::Composite as i32);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+        gl::PolygonMode(gl::FRONT_AND_BACK, polygon_mode[0] as u32);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::BindVertexArray(0);
+    }
+
+    pub unsafe fn delete(self) {
+        self.framebuffer.delete();
+        gl::DeleteVertexArrays(1, &self.vao);
+    }
+}
@@ -0,0 +1,148 @@
+// Vertex data regenerated every frame, like particles, debug lines and text, streamed to the GPU
+// without waiting for draws that still read last frame's data.
+//
+// The buffer is split into one region per frame in flight. Where buffer storage is supported
+// (OpenGL 4.4 or ARB_buffer_storage, so not on macOS), the buffer is mapped once, persistently and
+// coherently, and every write goes straight into the next region. A fence set after the region was
+// last drawn from says when it may be overwritten, which is normally long before it comes around
+// again. Elsewhere each write orphans the buffer with glBufferData, so the driver hands out fresh
+// storage instead of stalling, and uploads with glBufferSubData.
+use crate::util;
+use std::ffi::c_void;
+use std::ptr;
+
+// Frames the GPU may lag behind before a write has to wait for it
+const REGIONS: usize = 3;
+
+// Region sizes are kept a multiple of this, so every region starts suitably aligned for any
+// vertex attribute
+const ALIGNMENT: usize = 256;
+
+pub struct StreamBuffer {
+    buffer: u32,
+    region_size: usize, // In bytes
+    current: usize,     // The region written last
+    fences: [gl::types::GLsync; REGIONS],
+    mapped: *mut u8, // Null when orphaning instead
+    persistent: bool,
+}
+
+impl StreamBuffer {
+    // A buffer holding up to `region_size` bytes per write. It grows when more is written.
+    pub unsafe fn new(region_size: usize) -> StreamBuffer {
+        let mut stream = StreamBuffer {
+            buffer: 0,
+            region_size: 0,
+            current: 0,
+            fences: [ptr::null(); REGIONS],
+            mapped: ptr::null_mut(),
+            persistent: util::GlFeatures::detect().buffer_storage,
+        };
+        stream.allocate(region_size);
+        stream
+    }
+
+    // The buffer to bind for drawing from what was written
+    pub fn buffer(&self) -> u32 {
+        self.buffer
+    }
+
+    // Copy `data` into the buffer, returning the offset in bytes it starts at. Draws reading it
+    // must be issued before the next write, followed by `fence`.
+    pub unsafe fn write<T: Copy>(&mut self, data: &[T]) -> usize {
+        let bytes = std::mem::size_of_val(data);
+        if bytes > self.region_size {
+            self.delete();
+            self.allocate(bytes.next_power_of_two());
+        }
+        if !self.persistent {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.buffer);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                self.region_size as isize,
+                ptr::null(),
+                gl::STREAM_DRAW,
+            );
+            gl::BufferSubData(
+                gl::ARRAY_BUFFER,
+                0,
+                bytes as isize,
+                data.as_ptr() as *const c_void,
+            );
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            return 0;
+        }
+
+        self.current = (self.current + 1) % REGIONS;
+        let fence = std::mem::replace(&mut self.fences[self.current], ptr::null());
+        if !fence.is_null() {
+            // Flush the first time around, or the fence might never be submitted and signaled
+            let mut flags = gl::SYNC_FLUSH_COMMANDS_BIT;
+            while gl::ClientWaitSync(fence, flags, 1_000_000) == gl::TIMEOUT_EXPIRED {
+                flags = 0;
+            }
+            gl::DeleteSync(fence);
+        }
+        let offset = self.current * self.region_size;
+        ptr::copy_nonoverlapping(data.as_ptr() as *const u8, self.mapped.add(offset), bytes);
+        offset
+    }
+
+    // Mark the end of the draws reading the last write, so its region isn't overwritten before
+    // the GPU is done with them
+    pub unsafe fn fence(&mut self) {
+        if self.persistent {
+            let fence = &mut self.fences[self.current];
+            if !fence.is_null() {
+                gl::DeleteSync(*fence);
+            }
+            *fence = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+        }
+    }
+
+    pub unsafe fn delete(&mut self) {
+        for fence in self.fences.iter_mut() {
+            if !fence.is_null() {
+                gl::DeleteSync(*fence);
+                *fence = ptr::null();
+            }
+        }
+        if !self.mapped.is_null() {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.buffer);
+            gl::UnmapBuffer(gl::ARRAY_BUFFER);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            self.mapped = ptr::null_mut();
+        }
+        gl::DeleteBuffers(1, &self.buffer);
+        self.buffer = 0;
+    }
+
+    unsafe fn allocate(&mut self, region_size: usize) {
+        self.region_size = region_size.max(1).div_ceil(ALIGNMENT) * ALIGNMENT;
+        gl::GenBuffers(1, &mut self.buffer);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.buffer);
+        if self.persistent {
+            let size = (self.region_size * REGIONS) as isize;
+            let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+            gl::BufferStorage(gl::ARRAY_BUFFER, size, ptr::null(), flags);
+            self.mapped = gl::MapBufferRange(gl::ARRAY_BUFFER, 0, size, flags) as *mut u8;
+            // Mapping can still fail, e.g. when out of address space
+            if self.mapped.is_null() {
+                self.persistent = false;
+                gl::DeleteBuffers(1, &self.buffer);
+                gl::GenBuffers(1, &mut self.buffer);
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.buffer);
+            }
+        }
+        if !self.persistent {
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                self.region_size as isize,
+                ptr::null(),
+                gl::STREAM_DRAW,
+            );
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        self.current = 0;
+    }
+}
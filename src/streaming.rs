@@ -0,0 +1,223 @@
+// Loading parts of the world as the camera comes near, and unloading them again as it leaves.
+//
+// The world is split into regions, e.g. a terrain chunk with the props on it, each with a center
+// and a way to build its meshes. Regions within the load distance of the camera are loaded on a
+// background thread, and unloaded once they are further away than the unload distance. The gap
+// between the two keeps a camera moving back and forth across the edge of a region from loading
+// and unloading it every frame. Only what is near the camera is ever in memory, so the world can
+// be far larger than what fits.
+//
+// Loaded meshes are handed to `Assets` a few regions per frame, to be uploaded by the render loop,
+// so a burst of regions finishing together doesn't stall a single frame with uploads.
+//
+// Replays and captures must produce the same frames every time, so there the streamer can be made
+// to wait for the regions it requested, see `set_blocking`.
+use crate::assets::{Assets, Handle};
+use crate::backend::Backend;
+use crate::error::MeshError;
+use crate::mesh::Mesh;
+use log::{debug, warn};
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+// Builds the meshes of a region, e.g. by reading them from disk. Runs on the streaming thread.
+pub type RegionLoader = Arc<dyn Fn() -> Result<Vec<Mesh>, MeshError> + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RegionId(usize);
+
+// What changed about the regions during `Streamer::update`
+pub enum StreamEvent {
+    // The region's meshes are queued for upload, in the order its loader returned them
+    Loaded {
+        region: RegionId,
+        meshes: Vec<Handle<Mesh>>,
+    },
+    // The region's meshes have been released, so nodes drawing them must go as well
+    Unloaded(RegionId),
+}
+
+enum RegionState {
+    Unloaded,
+    // Waiting for the streaming thread. Results of older requests are stale and thrown away.
+    Loading(u64),
+    Loaded(Vec<Handle<Mesh>>),
+    // Loading failed, so it isn't tried again
+    Failed,
+}
+
+struct Region {
+    center: glm::Vec3,
+    loader: RegionLoader,
+    state: RegionState,
+}
+
+struct Loaded {
+    region: RegionId,
+    request: u64,
+    meshes: Result<Vec<Mesh>, MeshError>,
+}
+
+pub struct Streamer {
+    // Distances from the camera, measured across the ground (the XZ plane)
+    load_distance: f32,
+    unload_distance: f32,
+    // How many loaded regions are handed to `Assets` per update at most
+    uploads_per_update: usize,
+    regions: Vec<Region>,
+    next_request: u64,
+    in_flight: usize, // Requests the streaming thread hasn't answered yet
+    blocking: bool,
+    requests: Sender<(RegionId, u64, RegionLoader)>,
+    results: Receiver<Loaded>,
+    ready: VecDeque<Loaded>,
+}
+
+impl Streamer {
+    // Load regions closer than `load_distance` and unload those further away than
+    // `unload_distance`, which has to be larger
+    pub fn new(load_distance: f32, unload_distance: f32, uploads_per_update: usize) -> Streamer {
+        assert!(unload_distance > load_distance);
+        let (requests, request_receiver) = channel::<(RegionId, u64, RegionLoader)>();
+        let (result_sender, results) = channel();
+
+        thread::spawn(move || {
+            // Runs until the streamer is dropped
+            for (region, request, loader) in request_receiver {
+                let meshes = loader();
+                let loaded = Loaded {
+                    region,
+                    request,
+                    meshes,
+                };
+                if result_sender.send(loaded).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Streamer {
+            load_distance,
+            unload_distance,
+            uploads_per_update,
+            regions: vec![],
+            next_request: 0,
+            in_flight: 0,
+            blocking: false,
+            requests,
+            results,
+            ready: VecDeque::new(),
+        }
+    }
+
+    // Add a region centered at `center`, unloaded until the camera comes near
+    pub fn add_region(&mut self, center: glm::Vec3, loader: RegionLoader) -> RegionId {
+        self.regions.push(Region {
+            center,
+            loader,
+            state: RegionState::Unloaded,
+        });
+        RegionId(self.regions.len() - 1)
+    }
+
+    // Wait in `update` for the regions requested to finish loading, so when they appear only
+    // depends on where the camera went
+    pub fn set_blocking(&mut self, blocking: bool) {
+        self.blocking = blocking;
+    }
+
+    pub fn loaded_count(&self) -> usize {
+        self.regions
+            .iter()
+            .filter(|region| matches!(region.state, RegionState::Loaded(_)))
+            .count()
+    }
+
+    // Request the regions the camera has come near, unload those it has left, and queue the
+    // meshes of some of the regions that finished loading for upload
+    pub fn update(
+        &mut self,
+        camera: &glm::Vec3,
+        assets: &mut Assets,
+        backend: &mut dyn Backend,
+    ) -> Vec<StreamEvent> {
+        let mut events = vec![];
+        for (index, region) in self.regions.iter_mut().enumerate() {
+            let offset = region.center - camera;
+            let distance = glm::length(&glm::vec2(offset.x, offset.z));
+            match &region.state {
+                RegionState::Unloaded if distance < self.load_distance => {
+                    let request = self.next_request;
+                    self.next_request += 1;
+                    let loader = Arc::clone(&region.loader);
+                    if self
+                        .requests
+                        .send((RegionId(index), request, loader))
+                        .is_ok()
+                    {
+                        region.state = RegionState::Loading(request);
+                        self.in_flight += 1;
+                    }
+                }
+                RegionState::Loading(_) if distance > self.unload_distance => {
+                    region.state = RegionState::Unloaded;
+                }
+                RegionState::Loaded(_) if distance > self.unload_distance => {
+                    let state = std::mem::replace(&mut region.state, RegionState::Unloaded);
+                    if let RegionState::Loaded(meshes) = state {
+                        for mesh in meshes {
+                            assets.release(backend, mesh);
+                        }
+                    }
+                    debug!("Streamed out region {}", index);
+                    events.push(StreamEvent::Unloaded(RegionId(index)));
+                }
+                _ => {}
+            }
+        }
+
+        while self.blocking && self.in_flight > 0 {
+            match self.results.recv() {
+                Ok(loaded) => {
+                    self.in_flight -= 1;
+                    self.ready.push_back(loaded);
+                }
+                Err(_) => break,
+            }
+        }
+        for loaded in self.results.try_iter() {
+            self.in_flight -= 1;
+            self.ready.push_back(loaded);
+        }
+        let mut uploaded = 0;
+        while uploaded < self.uploads_per_update {
+            let Some(loaded) = self.ready.pop_front() else {
+                break;
+            };
+            let region = &mut self.regions[loaded.region.0];
+            // Left behind while loading
+            if !matches!(region.state, RegionState::Loading(request) if request == loaded.request) {
+                continue;
+            }
+            match loaded.meshes {
+                Ok(meshes) => {
+                    let meshes: Vec<_> = meshes.into_iter().map(|m| assets.add_mesh(m)).collect();
+                    region.state = RegionState::Loaded(meshes.clone());
+                    debug!("Streamed in region {}", loaded.region.0);
+                    events.push(StreamEvent::Loaded {
+                        region: loaded.region,
+                        meshes,
+                    });
+                    uploaded += 1;
+                }
+                Err(e) => {
+                    warn!("Failed to stream in a region: {}", e);
+                    region.state = RegionState::Failed;
+                }
+            }
+        }
+        events
+    }
+}
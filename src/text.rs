@@ -0,0 +1,180 @@
+// Text and filled rectangles drawn over the scene in screen space, e.g. for the debug overlay.
+// Like debug lines, they are collected anew every frame and streamed to the GPU through a
+// `StreamBuffer`.
+//
+// The font is a built-in 5x7 bitmap font covering printable ASCII, so no font files or textures
+// are needed. Every lit pixel of a glyph is drawn as a small quad, which is plenty fast for a few
+// lines of statistics.
+use crate::backend::gl::GlBackend;
+use crate::backend::{Backend, PipelineHandle};
+use crate::stream_buffer::StreamBuffer;
+use std::ffi::c_void;
+
+// Floats per vertex: position in pixels and color, in the layout of the overlay pipeline
+const VERTEX_FLOATS: usize = 6;
+
+// Glyph size in font pixels, plus one pixel of spacing between characters and two between lines
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+const ADVANCE: u32 = GLYPH_WIDTH + 1;
+const LINE_ADVANCE: u32 = GLYPH_HEIGHT + 2;
+
+// The glyphs of ' ' to '~', five columns each, with the lowest bit as the top row
+#[rustfmt::skip]
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00], [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00], [0x14, 0x08, 0x3E, 0x08, 0x14], [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02], [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31], [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00], [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06], [0x32, 0x49, 0x79, 0x41, 0x3E],
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x09, 0x01],
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41], [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31], [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x3F, 0x40, 0x38, 0x40, 0x3F], [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x07, 0x08, 0x70, 0x08, 0x07], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40], [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20], [0x38, 0x44, 0x44, 0x48, 0x7F],
+    [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7E, 0x09, 0x01, 0x02], [0x0C, 0x52, 0x52, 0x52, 0x3E],
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3D, 0x00],
+    [0x7F, 0x10, 0x28, 0x44, 0x00], [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38], [0x7C, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7C], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C],
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], [0x00, 0x00, 0x7F, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00], [0x08, 0x04, 0x08, 0x10, 0x08],
+];
+
+pub struct TextRenderer {
+    vertices: Vec<f32>,
+    stream: StreamBuffer,
+    vao: u32,
+}
+
+impl TextRenderer {
+    pub unsafe fn new() -> TextRenderer {
+        let mut vao = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        for attribute in 0..2 {
+            gl::EnableVertexAttribArray(attribute);
+        }
+        gl::BindVertexArray(0);
+        TextRenderer {
+            vertices: vec![],
+            stream: StreamBuffer::new(256 * 1024),
+            vao,
+        }
+    }
+
+    // Height of a line of text at `scale` screen pixels per font pixel, spacing included
+    pub fn line_height(scale: f32) -> f32 {
+        LINE_ADVANCE as f32 * scale
+    }
+
+    pub fn text_width(text: &str, scale: f32) -> f32 {
+        (text.chars().count() as u32 * ADVANCE) as f32 * scale
+    }
+
+    // A rectangle with its top left corner at `x`, `y`, in pixels from the top left of the screen
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: [f32; 4]) {
+        let corners = [
+            (x, y),
+            (x, y + height),
+            (x + width, y + height),
+            (x + width, y),
+        ];
+        for index in [0, 1, 2, 0, 2, 3] {
+            let (corner_x, corner_y) = corners[index];
+            self.vertices.extend_from_slice(&[corner_x, corner_y]);
+            self.vertices.extend_from_slice(&color);
+        }
+    }
+
+    // A line of text starting at `x`, `y` like `rect`, with `scale` screen pixels per font pixel.
+    // Characters outside printable ASCII are drawn as '?'.
+    pub fn text(&mut self, x: f32, y: f32, scale: f32, color: [f32; 4], text: &str) {
+        for (i, c) in text.chars().enumerate() {
+            let glyph = match c {
+                ' '..='~' => &FONT[c as usize - ' ' as usize],
+                _ => &FONT['?' as usize - ' ' as usize],
+            };
+            let glyph_x = x + (i as u32 * ADVANCE) as f32 * scale;
+            for (column, bits) in glyph.iter().enumerate() {
+                for row in 0..GLYPH_HEIGHT {
+                    if bits & (1 << row) != 0 {
+                        self.rect(
+                            glyph_x + column as f32 * scale,
+                            y + row as f32 * scale,
+                            scale,
+                            scale,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // Draw what was collected since the last call with `pipeline`, over everything drawn so far,
+    // and clear it. `pipeline` is left current.
+    pub unsafe fn draw(
+        &mut self,
+        backend: &mut GlBackend,
+        pipeline: PipelineHandle,
+        viewport_size: (u32, u32),
+    ) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        let offset = self.stream.write(&self.vertices);
+
+        // Point the attributes at this frame's region of the buffer
+        let stride = (VERTEX_FLOATS * std::mem::size_of::<f32>()) as i32;
+        let attributes = [(0, 2, 0), (1, 4, 2)];
+        gl::BindVertexArray(self.vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.stream.buffer());
+        for (location, size, first_float) in attributes {
+            let attribute_offset = offset + first_float * std::mem::size_of::<f32>();
+            gl::VertexAttribPointer(
+                location,
+                size,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                attribute_offset as *const c_void,
+            );
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        gl::BindVertexArray(0);
+
+        // Pixels from the top left, with y pointing down, which flips the winding of the quads
+        let (width, height) = (viewport_size.0 as f32, viewport_size.1 as f32);
+        let projection = glm::ortho(0.0, width, height, 0.0, -1.0, 1.0);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::CULL_FACE);
+        backend.set_pipeline(pipeline);
+        let count = (self.vertices.len() / VERTEX_FLOATS) as i32;
+        backend.draw_triangles(self.vao, count, &projection);
+        gl::Enable(gl::CULL_FACE);
+        gl::Enable(gl::DEPTH_TEST);
+        self.stream.fence();
+        self.vertices.clear();
+    }
+
+    pub unsafe fn delete(&mut self) {
+        self.stream.delete();
+        gl::DeleteVertexArrays(1, &self.vao);
+    }
+}
@@ -0,0 +1,147 @@
+// Frame timing: fixed timestep updates, frame rate limiting and frame statistics
+use crate::backend::{MemoryUsage, RenderStats};
+use std::time::{Duration, Instant};
+
+// Rate at which animation and flight are simulated, independent of the frame rate
+pub const SIMULATION_TIMESTEP: f32 = 1.0 / 60.0;
+
+// Splits variable frame times into a whole number of fixed simulation steps. Time that doesn't
+// add up to a full step is carried over to the next frame, and `alpha` says how far the rendered
+// frame is between the previous and the current simulation state.
+pub struct FixedTimestep {
+    pub step: f32,
+    pub max_steps: u32, // Drop time beyond this many steps per frame instead of falling further behind
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    // Tolerance for frame times that are a whole number of steps give or take rounding errors,
+    // e.g. during replays, so they always produce the same number of steps
+    const EPSILON: f32 = 1e-6;
+
+    pub fn new(step: f32) -> FixedTimestep {
+        FixedTimestep {
+            step,
+            max_steps: 5,
+            accumulator: 0.0,
+        }
+    }
+
+    // Add a frame's worth of real time and return the number of steps to simulate
+    pub fn advance(&mut self, delta_time: f32) -> u32 {
+        self.accumulator += delta_time;
+        let mut steps = 0;
+        while self.accumulator >= self.step - Self::EPSILON {
+            self.accumulator -= self.step;
+            steps += 1;
+            if steps == self.max_steps {
+                self.accumulator = self.accumulator.min(self.step);
+                break;
+            }
+        }
+        steps
+    }
+
+    // Interpolation factor between the previous and the current simulation state, in [0, 1]
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step).clamp(0.0, 1.0)
+    }
+}
+
+// Limits the frame rate by sleeping until the next frame is due. Useful with vsync off, where
+// the render loop would otherwise run as fast as it can.
+pub struct FramePacer {
+    frame_duration: Duration,
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    pub fn new(fps: u32) -> FramePacer {
+        FramePacer {
+            frame_duration: Duration::from_secs_f64(1.0 / fps as f64),
+            next_frame: Instant::now(),
+        }
+    }
+
+    // Wait until it's time to start the next frame. Sleeping is only accurate to about a
+    // millisecond, so the last stretch is spent yielding instead.
+    pub fn wait(&mut self) {
+        self.next_frame += self.frame_duration;
+        let now = Instant::now();
+        if self.next_frame <= now {
+            // Running behind, so don't try to catch up with a burst of frames
+            self.next_frame = now;
+            return;
+        }
+        let remaining = self.next_frame - now;
+        if remaining > Duration::from_millis(2) {
+            std::thread::sleep(remaining - Duration::from_millis(1));
+        }
+        while Instant::now() < self.next_frame {
+            std::thread::yield_now();
+        }
+    }
+}
+
+// Frame statistics averaged over one reporting interval
+#[derive(Clone, Copy, Debug)]
+pub struct FrameStats {
+    pub fps: f32,
+    pub frame_time: f32,         // Average time between frames, in seconds
+    pub longest_frame_time: f32, // In seconds, to show stutter the average hides
+    pub draw_calls: u32,         // Average per frame
+    pub memory: MemoryUsage,     // Filled in by the render loop
+}
+
+// Collects frame times and draw calls, and reports `FrameStats` once per interval
+pub struct FrameStatsCounter {
+    interval: Duration,
+    interval_start: Instant,
+    previous_frame: Instant,
+    frames: u32,
+    draw_calls: u64,
+    longest_frame_time: f32,
+}
+
+impl FrameStatsCounter {
+    pub fn new(interval: Duration) -> FrameStatsCounter {
+        let now = Instant::now();
+        FrameStatsCounter {
+            interval,
+            interval_start: now,
+            previous_frame: now,
+            frames: 0,
+            draw_calls: 0,
+            longest_frame_time: 0.0,
+        }
+    }
+
+    // Count a finished frame, drawn as `stats` says. Returns the statistics when a full interval
+    // has passed.
+    pub fn frame(&mut self, stats: &RenderStats) -> Option<FrameStats> {
+        let now = Instant::now();
+        let frame_time = now.duration_since(self.previous_frame).as_secs_f32();
+        self.previous_frame = now;
+        self.frames += 1;
+        self.draw_calls += stats.draw_calls as u64;
+        self.longest_frame_time = self.longest_frame_time.max(frame_time);
+
+        let elapsed = now.duration_since(self.interval_start);
+        if elapsed < self.interval {
+            return None;
+        }
+        let elapsed = elapsed.as_secs_f32();
+        let stats = FrameStats {
+            fps: self.frames as f32 / elapsed,
+            frame_time: elapsed / self.frames as f32,
+            longest_frame_time: self.longest_frame_time,
+            draw_calls: (self.draw_calls / self.frames as u64) as u32,
+            memory: MemoryUsage::default(),
+        };
+        self.interval_start = now;
+        self.frames = 0;
+        self.draw_calls = 0;
+        self.longest_frame_time = 0.0;
+        Some(stats)
+    }
+}
@@ -1,36 +1,1657 @@
 extern crate nalgebra_glm as glm;
-use std::f64::consts::PI;
-
-pub struct Heading {
-    pub x     : f32,
-    pub z     : f32,
-    pub roll  : f32, // measured in radians
-    pub pitch : f32, // measured in radians
-    pub yaw   : f32, // measured in radians
-}
-
-pub fn simple_heading_animation(time: f32) -> Heading {
-    let t             = time as f64;
-    let step          = 0.05f64;
-    let path_size     = 15f64;
-    let circuit_speed = 0.8f64;
-
-    let xpos      = path_size * (2.0 * (t+ 0.0) * circuit_speed).sin();
-    let xpos_next = path_size * (2.0 * (t+step) * circuit_speed).sin();
-    let zpos      = 3.0 * path_size * ((t+ 0.0) * circuit_speed).cos();
-    let zpos_next = 3.0 * path_size * ((t+step) * circuit_speed).cos();
-
-    let delta_pos = glm::vec2(xpos_next - xpos, zpos_next - zpos);
-
-    let roll  = (t * circuit_speed).cos() * 0.5;
-    let pitch = -0.175 * glm::length(&delta_pos);
-    let yaw   = PI + delta_pos.x.atan2(delta_pos.y);
-
-    Heading {
-        x     : xpos  as f32,
-        z     : zpos  as f32,
-        roll  : roll  as f32,
-        pitch : pitch as f32,
-        yaw   : yaw   as f32,
+
+// The transformation placing something at `position`, rotated around the X, the Y and then the Z
+// axis and scaled, both about `reference_point`, like a scene node relative to its parent
+pub fn compose_transform(position: &glm::Vec3, rotation: &glm::Vec3, scale: &glm::Vec3, reference_point: &glm::Vec3) -> glm::Mat4 {
+    let translation = glm::translation(position);
+    let rotation = glm::quat_to_mat4(&quat_from_euler(rotation));
+    let scaling = glm::scaling(scale);
+
+    let translation_to_origin = glm::translation(&-reference_point);
+    let translation_back = glm::translation(reference_point);
+
+    translation * translation_back * rotation * translation_to_origin * scaling
+}
+
+// Smoothly accelerate from 0 and decelerate into 1 (a.k.a. smoothstep)
+pub fn ease_in_out(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Which end of a transition an easing curve eases: starting slowly, stopping slowly or both
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ease {
+    In,
+    Out,
+    InOut,
+}
+
+// The standard easing curves, mapping progress in [0, 1] to how far along a transition is. Elastic
+// and bounce overshoot or undershoot before they settle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    Smooth, // `ease_in_out`
+    Quad(Ease),
+    Cubic(Ease),
+    Expo(Ease),
+    Elastic(Ease),
+    Bounce(Ease),
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        let (ease_in, ease): (fn(f32) -> f32, Ease) = match *self {
+            Easing::Linear        => return t,
+            Easing::Smooth        => return ease_in_out(t),
+            Easing::Quad(ease)    => (|t| t * t, ease),
+            Easing::Cubic(ease)   => (|t| t * t * t, ease),
+            Easing::Expo(ease)    => (|t| if t <= 0.0 { 0.0 } else { 2f32.powf(10.0 * t - 10.0) }, ease),
+            Easing::Elastic(ease) => (elastic_in, ease),
+            Easing::Bounce(ease)  => (|t| 1.0 - bounce_out(1.0 - t), ease),
+        };
+        // The out and in-out curves are the in curve mirrored
+        match ease {
+            Ease::In => ease_in(t),
+            Ease::Out => 1.0 - ease_in(1.0 - t),
+            Ease::InOut if t < 0.5 => ease_in(2.0 * t) / 2.0,
+            Ease::InOut => 1.0 - ease_in(2.0 - 2.0 * t) / 2.0,
+        }
+    }
+}
+
+// A spring winding up before it lets go
+fn elastic_in(t: f32) -> f32 {
+    if t <= 0.0 || t >= 1.0 {
+        return t;
+    }
+    let period = 2.0 * std::f32::consts::PI / 3.0;
+    -2f32.powf(10.0 * t - 10.0) * ((10.0 * t - 10.75) * period).sin()
+}
+
+// A ball dropped onto the floor, bouncing lower and lower
+fn bounce_out(t: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
+
+// Uniform Catmull-Rom spline through p1 and p2, using p0 and p3 to shape the tangents. t in [0, 1]
+pub fn catmull_rom(p0: &glm::Vec3, p1: &glm::Vec3, p2: &glm::Vec3, p3: &glm::Vec3, t: f32) -> glm::Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+// Time used to drive animations. It only advances when `tick` is called, so it can be paused,
+// stepped one frame at a time and sped up or slowed down independently of the wall clock.
+pub struct AnimationClock {
+    pub time: f32,
+    pub paused: bool,
+    time_scale: f32,
+}
+
+impl AnimationClock {
+    pub const MIN_TIME_SCALE: f32 = 0.1;
+    pub const MAX_TIME_SCALE: f32 = 10.0;
+    pub const STEP: f32 = 1.0 / 60.0;
+
+    pub fn new() -> AnimationClock {
+        AnimationClock {
+            time: 0.0,
+            paused: false,
+            time_scale: 1.0,
+        }
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.clamp(Self::MIN_TIME_SCALE, Self::MAX_TIME_SCALE);
+    }
+
+    // Advance by a frame's worth of real time, returning the scaled animation delta time
+    pub fn tick(&mut self, real_delta_time: f32) -> f32 {
+        if self.paused {
+            return 0.0;
+        }
+        let delta_time = real_delta_time * self.time_scale;
+        self.time += delta_time;
+        delta_time
+    }
+
+    // Advance a single fixed step, typically while paused
+    pub fn step(&mut self) {
+        self.time += Self::STEP * self.time_scale;
+    }
+}
+
+impl Default for AnimationClock {
+    fn default() -> AnimationClock {
+        AnimationClock::new()
+    }
+}
+
+// What an `AnimationClip` animates: a scene node's position, rotation and scale
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub position : glm::Vec3,
+    pub rotation : glm::Vec3, // Angles about the X, the Y and the Z axes, like `SceneNode::rotation`
+    pub scale    : glm::Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform {
+            position : glm::zero(),
+            rotation : glm::zero(),
+            scale    : glm::vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+// The rotation `compose_transform` makes of angles about the X, the Y and the Z axis: turning
+// about Y, then about the turned X and last about the turned Z, like yaw, pitch and roll
+pub fn quat_from_euler(angles: &glm::Vec3) -> glm::Quat {
+    let half = angles * 0.5;
+    glm::quat(0.0, half.y.sin(), 0.0, half.y.cos())
+        * glm::quat(half.x.sin(), 0.0, 0.0, half.x.cos())
+        * glm::quat(0.0, 0.0, half.z.sin(), half.z.cos())
+}
+
+// The angles about the X, the Y and the Z axis which `compose_transform` turns into the same
+// rotation as `q`
+pub fn euler_angles(q: &glm::Quat) -> glm::Vec3 {
+    let m = glm::quat_to_mat3(&glm::quat_normalize(q));
+    glm::vec3(
+        (-m[(1, 2)]).clamp(-1.0, 1.0).asin(),
+        m[(0, 2)].atan2(m[(2, 2)]),
+        m[(1, 0)].atan2(m[(1, 1)]),
+    )
+}
+
+// The rotation turning something facing down -Z, like models and cameras, to face `direction`,
+// with its top towards `up` as far as it can be
+pub fn look_rotation(direction: &glm::Vec3, up: &glm::Vec3) -> glm::Quat {
+    let back = -glm::normalize(direction);
+    // Looking straight along `up`, any other direction will do for the top
+    let up = if glm::cross(up, &back).norm() > 1e-6 { *up } else { glm::vec3(back.y, back.z, back.x) };
+    let right = glm::normalize(&glm::cross(&up, &back));
+    let top = glm::cross(&back, &right);
+    glm::mat3_to_quat(&glm::mat3(right.x, top.x, back.x, right.y, top.y, back.y, right.z, top.z, back.z))
+}
+
+// Spherical interpolation from `a` to `b`, the short way around at a constant speed
+pub fn slerp(a: &glm::Quat, b: &glm::Quat, t: f32) -> glm::Quat {
+    glm::quat_slerp(a, &same_side(a, b), t)
+}
+
+// Normalized linear interpolation from `a` to `b`: the same path as `slerp`, but faster in the
+// middle, and cheaper to compute
+pub fn nlerp(a: &glm::Quat, b: &glm::Quat, t: f32) -> glm::Quat {
+    glm::quat_normalize(&glm::lerp(&a.coords, &same_side(a, b).coords, t).into())
+}
+
+// How a track blends from one keyframe to the next
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    Cubic, // Catmull-Rom through the keys, for motion without sudden changes of speed
+}
+
+// What a clip does after its last keyframe
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Looping {
+    Once,       // Hold the last keys
+    Repeat,     // Start over. Tracks should end with the keys they start with.
+    Accumulate, // Start over from where the last time ended, e.g. for something spinning
+}
+
+// A value a keyframe can hold
+pub trait Keyframe: Copy {
+    fn lerp(&self, next: &Self, t: f32) -> Self;
+
+    // Uniform Catmull-Rom from `current` to `next`, using `previous` and `after` to shape the tangents
+    fn cubic(previous: &Self, current: &Self, next: &Self, after: &Self, t: f32) -> Self;
+
+    // Move on by `cycles` times the change from `first` to `last`
+    fn offset(&self, first: &Self, last: &Self, cycles: f32) -> Self;
+}
+
+impl Keyframe for f32 {
+    fn lerp(&self, next: &f32, t: f32) -> f32 {
+        self + (next - self) * t
+    }
+
+    fn cubic(previous: &f32, current: &f32, next: &f32, after: &f32, t: f32) -> f32 {
+        let t2 = t * t;
+        let t3 = t2 * t;
+        (current * 2.0
+            + (next - previous) * t
+            + (previous * 2.0 - current * 5.0 + next * 4.0 - after) * t2
+            + (current * 3.0 - previous - next * 3.0 + after) * t3)
+            * 0.5
+    }
+
+    fn offset(&self, first: &f32, last: &f32, cycles: f32) -> f32 {
+        self + (last - first) * cycles
+    }
+}
+
+impl Keyframe for glm::Vec3 {
+    fn lerp(&self, next: &glm::Vec3, t: f32) -> glm::Vec3 {
+        glm::lerp(self, next, t)
+    }
+
+    fn cubic(previous: &glm::Vec3, current: &glm::Vec3, next: &glm::Vec3, after: &glm::Vec3, t: f32) -> glm::Vec3 {
+        catmull_rom(previous, current, next, after, t)
+    }
+
+    fn offset(&self, first: &glm::Vec3, last: &glm::Vec3, cycles: f32) -> glm::Vec3 {
+        self + (last - first) * cycles
+    }
+}
+
+// Orientations. A quaternion and its negation are the same rotation, so keys are flipped to the
+// side of their neighbours before blending, to take the short way around.
+impl Keyframe for glm::Quat {
+    fn lerp(&self, next: &glm::Quat, t: f32) -> glm::Quat {
+        slerp(self, next, t)
+    }
+
+    fn cubic(previous: &glm::Quat, current: &glm::Quat, next: &glm::Quat, after: &glm::Quat, t: f32) -> glm::Quat {
+        let previous = same_side(current, previous);
+        let next = same_side(current, next);
+        let after = same_side(&next, after);
+        let imaginary = catmull_rom(&previous.imag(), &current.imag(), &next.imag(), &after.imag(), t);
+        let real = f32::cubic(&previous.w, &current.w, &next.w, &after.w, t);
+        glm::quat_normalize(&glm::quat(imaginary.x, imaginary.y, imaginary.z, real))
+    }
+
+    fn offset(&self, first: &glm::Quat, last: &glm::Quat, cycles: f32) -> glm::Quat {
+        let change = glm::quat_normalize(&same_side(&glm::Quat::identity(), &(last * glm::quat_conjugate(first))));
+        let angle = 2.0 * change.w.clamp(-1.0, 1.0).acos();
+        if angle < 1e-6 {
+            return *self;
+        }
+        let axis = change.imag() / (angle / 2.0).sin();
+        glm::quat_angle_axis(angle * cycles, &axis) * self
+    }
+}
+
+// `q`, or its negation if that is closer to `reference`
+fn same_side(reference: &glm::Quat, q: &glm::Quat) -> glm::Quat {
+    if glm::quat_dot(reference, q) < 0.0 { -q } else { *q }
+}
+
+// Keyframes of a single value, as (time, value) pairs in order of time
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    pub keys          : Vec<(f32, T)>,
+    pub interpolation : Interpolation,
+}
+
+impl<T: Keyframe> Track<T> {
+    pub fn new(interpolation: Interpolation, keys: Vec<(f32, T)>) -> Track<T> {
+        assert!(!keys.is_empty(), "A track needs at least one keyframe");
+        Track { keys, interpolation }
+    }
+
+    // The value at `time`, holding the first and the last key outside of them. For cubic tracks
+    // that start over, the keys past either end continue around the loop, so the motion stays
+    // smooth where it does.
+    pub fn sample(&self, time: f32, looping: Looping) -> T {
+        let keys = &self.keys;
+        let next = keys.partition_point(|&(key_time, _)| key_time <= time);
+        if next == 0 {
+            return keys[0].1;
+        }
+        if next == keys.len() {
+            return keys[keys.len() - 1].1;
+        }
+        let ((start, current), (end, next_value)) = (keys[next - 1], keys[next]);
+        let t = (time - start) / (end - start);
+        match self.interpolation {
+            Interpolation::Linear => current.lerp(&next_value, t),
+            Interpolation::Cubic => {
+                let previous = self.key_around(next as isize - 2, looping);
+                let after = self.key_around(next as isize + 1, looping);
+                T::cubic(&previous, &current, &next_value, &after, t)
+            }
+        }
+    }
+
+    // The value of the key at `index`, which may be one before the first or one after the last
+    fn key_around(&self, index: isize, looping: Looping) -> T {
+        let keys = &self.keys;
+        let last = keys.len() - 1;
+        let (first_value, last_value) = (keys[0].1, keys[last].1);
+        // Looping tracks end where they start, so the first and the last key are the same one
+        let (cycles, value) = if index < 0 {
+            (-1.0, keys[last.saturating_sub(1)].1)
+        } else if index as usize > last {
+            (1.0, keys[1.min(last)].1)
+        } else {
+            return keys[index as usize].1;
+        };
+        match looping {
+            Looping::Once => if cycles < 0.0 { first_value } else { last_value },
+            Looping::Repeat => value,
+            Looping::Accumulate => value.offset(&first_value, &last_value, cycles),
+        }
+    }
+}
+
+// A track and the property it animates
+#[derive(Clone, Debug)]
+pub enum Channel {
+    Position(Track<glm::Vec3>),
+    Rotation(Track<glm::Vec3>),
+    Orientation(Track<glm::Quat>), // Set as the rotation angles, see `euler_angles`
+    Scale(Track<glm::Vec3>),
+    Angle(usize, Track<f32>),      // The rotation about a single axis: 0 for X, 1 for Y or 2 for Z
+}
+
+// Tracks played back together, e.g. a helicopter flying one lap of its path
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub duration : f32,
+    pub looping  : Looping,
+    pub speed    : f32, // Playback speed, negative to play backwards
+    pub channels : Vec<Channel>,
+}
+
+impl AnimationClip {
+    pub fn new(duration: f32, looping: Looping) -> AnimationClip {
+        AnimationClip {
+            duration,
+            looping,
+            speed: 1.0,
+            channels: vec![],
+        }
+    }
+
+    pub fn with_channel(mut self, channel: Channel) -> AnimationClip {
+        self.channels.push(channel);
+        self
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> AnimationClip {
+        self.speed = speed;
+        self
+    }
+
+    // Where in the clip playback is after `time` seconds, and how many times it has started over
+    pub fn clip_time(&self, time: f32) -> (f32, f32) {
+        let time = time * self.speed;
+        if self.looping == Looping::Once || self.duration <= 0.0 {
+            return (time.clamp(0.0, self.duration.max(0.0)), 0.0);
+        }
+        let cycles = (time / self.duration).floor();
+        (time - cycles * self.duration, cycles)
+    }
+
+    // Set the properties the clip animates to what they are `time` seconds into playback, leaving
+    // the rest of `transform` alone
+    pub fn apply(&self, time: f32, transform: &mut Transform) {
+        let (clip_time, cycles) = self.clip_time(time);
+        for channel in &self.channels {
+            match channel {
+                Channel::Position(track)    => transform.position = self.sample(track, clip_time, cycles),
+                Channel::Rotation(track)    => transform.rotation = self.sample(track, clip_time, cycles),
+                Channel::Orientation(track) => transform.rotation = euler_angles(&self.sample(track, clip_time, cycles)),
+                Channel::Scale(track)       => transform.scale    = self.sample(track, clip_time, cycles),
+                Channel::Angle(axis, track) => transform.rotation[*axis] = self.sample(track, clip_time, cycles),
+            }
+        }
+    }
+
+    fn sample<T: Keyframe>(&self, track: &Track<T>, clip_time: f32, cycles: f32) -> T {
+        let value = track.sample(clip_time, self.looping);
+        match self.looping {
+            Looping::Accumulate if cycles != 0.0 => {
+                let (first, last) = (track.keys[0].1, track.keys[track.keys.len() - 1].1);
+                value.offset(&first, &last, cycles)
+            }
+            _ => value,
+        }
+    }
+}
+
+// How the points of a `Spline` shape it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplineKind {
+    CatmullRom, // Through every point
+    Bezier,     // Cubic Bézier segments: a point, two control points, a point, two control points...
+}
+
+// A curve through 3D points, parameterized by the segment it is in plus how far along it: 1.5 is
+// halfway along the second segment. A table of arc lengths converts parameters to distances along
+// the curve and back, for moving along it at a constant speed.
+#[derive(Clone, Debug)]
+pub struct Spline {
+    kind    : SplineKind,
+    points  : Vec<glm::Vec3>,
+    closed  : bool,        // Whether the curve goes on from its last point back to the first
+    lengths : Vec<f32>,    // The distance along the curve at every step of the parameter
+}
+
+impl Spline {
+    // Steps of the arc length table per segment
+    const STEPS: usize = 16;
+
+    pub fn catmull_rom(points: Vec<glm::Vec3>, closed: bool) -> Spline {
+        assert!(points.len() >= 2, "A spline needs at least two points");
+        Spline::new(SplineKind::CatmullRom, points, closed)
+    }
+
+    // Neighbouring segments share their end points. Curves ending with the point they start at are
+    // closed, but only smoothly if the control points on either side line up.
+    pub fn bezier(points: Vec<glm::Vec3>) -> Spline {
+        assert!(points.len() >= 4 && points.len() % 3 == 1, "Bézier splines take 3n + 1 points");
+        Spline::new(SplineKind::Bezier, points, false)
+    }
+
+    fn new(kind: SplineKind, points: Vec<glm::Vec3>, closed: bool) -> Spline {
+        let mut spline = Spline { kind, points, closed, lengths: vec![0.0] };
+        let steps = spline.segments() * Spline::STEPS;
+        let mut previous = spline.point(0.0);
+        for step in 1..=steps {
+            let point = spline.point(step as f32 / Spline::STEPS as f32);
+            let length = spline.lengths[step - 1] + glm::distance(&previous, &point);
+            spline.lengths.push(length);
+            previous = point;
+        }
+        spline
+    }
+
+    pub fn points(&self) -> &[glm::Vec3] {
+        &self.points
+    }
+
+    pub fn segments(&self) -> usize {
+        match self.kind {
+            SplineKind::CatmullRom if self.closed => self.points.len(),
+            SplineKind::CatmullRom => self.points.len() - 1,
+            SplineKind::Bezier => (self.points.len() - 1) / 3,
+        }
+    }
+
+    pub fn length(&self) -> f32 {
+        self.lengths[self.lengths.len() - 1]
+    }
+
+    // The segment the parameter `u` is in, and how far along it, clamped to the curve
+    fn locate(&self, u: f32) -> (usize, f32) {
+        let segments = self.segments();
+        let u = u.clamp(0.0, segments as f32);
+        let segment = (u.floor() as usize).min(segments - 1);
+        (segment, u - segment as f32)
+    }
+
+    // The four points shaping a segment
+    fn segment_points(&self, segment: usize) -> [glm::Vec3; 4] {
+        let points = &self.points;
+        match self.kind {
+            SplineKind::CatmullRom => {
+                // Open curves repeat their end points, so they pass through the first and the last
+                let count = points.len() as isize;
+                let point = |i: isize| if self.closed { points[i.rem_euclid(count) as usize] } else { points[i.clamp(0, count - 1) as usize] };
+                let i = segment as isize;
+                [point(i - 1), point(i), point(i + 1), point(i + 2)]
+            }
+            SplineKind::Bezier => {
+                let i = segment * 3;
+                [points[i], points[i + 1], points[i + 2], points[i + 3]]
+            }
+        }
+    }
+
+    pub fn point(&self, u: f32) -> glm::Vec3 {
+        let (segment, t) = self.locate(u);
+        let [p0, p1, p2, p3] = self.segment_points(segment);
+        match self.kind {
+            SplineKind::CatmullRom => catmull_rom(&p0, &p1, &p2, &p3, t),
+            SplineKind::Bezier => {
+                let s = 1.0 - t;
+                p0 * (s * s * s) + p1 * (3.0 * s * s * t) + p2 * (3.0 * s * t * t) + p3 * (t * t * t)
+            }
+        }
+    }
+
+    // The derivative of the curve at `u`, pointing the way the parameter increases
+    pub fn tangent(&self, u: f32) -> glm::Vec3 {
+        let (segment, t) = self.locate(u);
+        let [p0, p1, p2, p3] = self.segment_points(segment);
+        match self.kind {
+            SplineKind::CatmullRom => {
+                ((p2 - p0)
+                    + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * (2.0 * t)
+                    + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * (3.0 * t * t))
+                    * 0.5
+            }
+            SplineKind::Bezier => {
+                let s = 1.0 - t;
+                (p1 - p0) * (3.0 * s * s) + (p2 - p1) * (6.0 * s * t) + (p3 - p2) * (3.0 * t * t)
+            }
+        }
+    }
+
+    // How far along the curve the parameter `u` is
+    pub fn distance_at(&self, u: f32) -> f32 {
+        let step = u.clamp(0.0, self.segments() as f32) * Spline::STEPS as f32;
+        let i = (step.floor() as usize).min(self.lengths.len() - 2);
+        self.lengths[i].lerp(&self.lengths[i + 1], step - i as f32)
+    }
+
+    // The parameter `distance` along the curve. Closed curves wrap around, open ones are clamped.
+    pub fn parameter_at(&self, distance: f32) -> f32 {
+        let length = self.length();
+        let distance = if self.closed && length > 0.0 { distance.rem_euclid(length) } else { distance.clamp(0.0, length) };
+        let i = self.lengths.partition_point(|&l| l <= distance).clamp(1, self.lengths.len() - 1);
+        let (start, end) = (self.lengths[i - 1], self.lengths[i]);
+        let t = if end > start { (distance - start) / (end - start) } else { 0.0 };
+        (i - 1) as f32 / Spline::STEPS as f32 + t / Spline::STEPS as f32
+    }
+
+    // The parameter of the point on the curve closest to `point`, found from the nearest step of
+    // the arc length table and refined with a few Newton steps
+    pub fn closest_point(&self, point: &glm::Vec3) -> f32 {
+        let steps = self.lengths.len();
+        let distance_to = |u: f32| glm::distance2(&self.point(u), point);
+        let mut u = (0..steps)
+            .map(|step| step as f32 / Spline::STEPS as f32)
+            .min_by(|&a, &b| distance_to(a).total_cmp(&distance_to(b)))
+            .unwrap();
+        let step = 1.0 / Spline::STEPS as f32;
+        let (low, high) = ((u - step).max(0.0), (u + step).min(self.segments() as f32));
+        for _ in 0..8 {
+            // Minimize the squared distance, using a numerical second derivative
+            let h = 1e-3;
+            let slope = |u: f32| glm::dot(&(self.point(u) - point), &self.tangent(u));
+            let (value, change) = (slope(u), (slope(u + h) - slope(u - h)) / (2.0 * h));
+            if change <= 0.0 {
+                break;
+            }
+            u = (u - value / change).clamp(low, high);
+        }
+        u
+    }
+}
+
+// Ways to fly around, e.g. for helicopters. Each pattern is a path in time, and the heading follows
+// from how it is flown: facing the way it goes, nose down the faster it goes and banking into turns.
+#[derive(Clone, Debug)]
+pub enum FlightPattern {
+    // Crossing over `center`, out to `half_width` either side along X and `half_length` along Z,
+    // a lap every `lap_time` seconds
+    FigureEight { center: glm::Vec3, half_width: f32, half_length: f32, lap_time: f32 },
+    // Circling `center` at `radius`, a lap every `lap_time` seconds, clockwise seen from above if
+    // that is negative
+    Orbit { center: glm::Vec3, radius: f32, lap_time: f32 },
+    // Along a closed route through waypoints at `speed` units per second
+    Patrol { route: Spline, speed: f32 },
+    // Over to above `to` and down onto it, taking `duration` seconds in all, then staying there
+    Land { from: glm::Vec3, to: glm::Vec3, duration: f32 },
+}
+
+impl FlightPattern {
+    // Nose down per unit per second of speed, up to a limit
+    const PITCH: f32 = 0.25 / 30.0;
+    const MAX_PITCH: f32 = 0.35;
+    // Roll per radian per second of turning, up to a limit
+    const BANK: f32 = 0.35;
+    const MAX_BANK: f32 = 0.5;
+    // Share of a landing spent getting above the landing spot
+    const APPROACH: f32 = 0.7;
+
+    pub fn position(&self, time: f32) -> glm::Vec3 {
+        match self {
+            FlightPattern::FigureEight { center, half_width, half_length, lap_time } => {
+                let angle = std::f32::consts::TAU * time / lap_time;
+                center + glm::vec3(half_width * (2.0 * angle).sin(), 0.0, half_length * angle.cos())
+            }
+            FlightPattern::Orbit { center, radius, lap_time } => {
+                let angle = std::f32::consts::TAU * time / lap_time;
+                center + glm::vec3(angle.sin(), 0.0, angle.cos()) * *radius
+            }
+            FlightPattern::Patrol { route, speed } => route.point(route.parameter_at(time * speed)),
+            FlightPattern::Land { from, to, duration } => {
+                let progress = (time / duration).clamp(0.0, 1.0);
+                let ease = Easing::Cubic(Ease::InOut);
+                let above = glm::vec3(to.x, from.y.max(to.y), to.z);
+                let mut position = glm::lerp(from, &above, ease.apply(progress / Self::APPROACH));
+                let descent = (progress - Self::APPROACH) / (1.0 - Self::APPROACH);
+                position.y = above.y.lerp(&to.y, ease.apply(descent));
+                position
+            }
+        }
+    }
+
+    // When `position` is passed, or the closest to it, for patterns going round and round
+    pub fn closest_time(&self, position: &glm::Vec3) -> Option<f32> {
+        match self {
+            FlightPattern::Orbit { center, lap_time, .. } => {
+                let offset = position - center;
+                Some(offset.x.atan2(offset.z).rem_euclid(std::f32::consts::TAU) / std::f32::consts::TAU * lap_time)
+            }
+            FlightPattern::Patrol { route, speed } => Some(route.distance_at(route.closest_point(position)) / speed),
+            FlightPattern::FigureEight { .. } | FlightPattern::Land { .. } => None,
+        }
+    }
+
+    // How fast and which way the pattern goes `time` seconds in
+    pub fn velocity(&self, time: f32) -> glm::Vec3 {
+        let step = 0.05;
+        (self.position(time + step) - self.position(time - step)) / (2.0 * step)
+    }
+
+    // Whether the pattern has come to an end on the ground `time` seconds in
+    pub fn has_landed(&self, time: f32) -> bool {
+        matches!(self, FlightPattern::Land { duration, .. } if time >= *duration)
+    }
+
+    // Place `body` where the pattern is `time` seconds in. The yaw is kept within half a turn of
+    // `body`'s, so blending between two poses turns the short way, and held while hovering.
+    pub fn apply(&self, time: f32, body: &mut Transform) {
+        let step = 0.05;
+        // Models face -Z
+        let heading = |velocity: glm::Vec3| (-velocity.x).atan2(-velocity.z);
+        let near = |angle: f32, reference: f32| reference + (angle - reference + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU) - std::f32::consts::PI;
+
+        body.position = self.position(time);
+        let current = self.velocity(time);
+        let speed = current.xz().norm();
+        if speed < 0.1 {
+            body.rotation = glm::vec3(0.0, body.rotation.y, 0.0);
+            return;
+        }
+        let yaw = near(heading(current), body.rotation.y);
+        let turn = near(heading(self.velocity(time + step)), yaw) - near(heading(self.velocity(time - step)), yaw);
+        let turn_rate = turn / (2.0 * step);
+        body.rotation = glm::vec3(
+            -(speed * Self::PITCH).min(Self::MAX_PITCH),
+            yaw,
+            (turn_rate * Self::BANK).clamp(-Self::MAX_BANK, Self::MAX_BANK),
+        );
+    }
+}
+
+// A value moving from `from` to `to` over `duration` seconds along an easing curve, e.g. a door
+// sliding open. Retargeting it halfway sets off from wherever it is.
+#[derive(Clone, Copy, Debug)]
+pub struct Tween<T> {
+    pub from     : T,
+    pub to       : T,
+    pub duration : f32,
+    pub easing   : Easing,
+    elapsed      : f32,
+}
+
+impl<T: Keyframe> Tween<T> {
+    pub fn new(from: T, to: T, duration: f32, easing: Easing) -> Tween<T> {
+        Tween { from, to, duration, easing, elapsed: 0.0 }
+    }
+
+    // Staying at `value` until retargeted
+    pub fn at_rest(value: T, easing: Easing) -> Tween<T> {
+        Tween::new(value, value, 0.0, easing)
+    }
+
+    pub fn value(&self) -> T {
+        let t = if self.duration > 0.0 { self.elapsed / self.duration } else { 1.0 };
+        self.from.lerp(&self.to, self.easing.apply(t))
+    }
+
+    pub fn advance(&mut self, delta_time: f32) -> T {
+        self.elapsed = (self.elapsed + delta_time).min(self.duration);
+        self.value()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    // Move on to `to` from the current value, taking `duration` seconds
+    pub fn retarget(&mut self, to: T, duration: f32) {
+        *self = Tween::new(self.value(), to, duration, self.easing);
+    }
+}
+
+// Seeded gradient (Perlin) noise in one to three dimensions: smooth, between about -1 and 1, zero
+// at whole coordinates and the same for the same seed on every run and every machine
+#[derive(Clone)]
+pub struct Noise {
+    permutation: [u8; 512], // 0-255 shuffled, twice over, so lookups don't need wrapping
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Noise {
+        // Shuffled with splitmix64 rather than `rand`, whose sequences may change between versions
+        let mut state = seed;
+        let mut next = || {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+        let mut values = [0u8; 256];
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = i as u8;
+        }
+        for i in (1..256).rev() {
+            values.swap(i, (next() % (i as u64 + 1)) as usize);
+        }
+        let mut permutation = [0u8; 512];
+        for (i, value) in permutation.iter_mut().enumerate() {
+            *value = values[i & 255];
+        }
+        Noise { permutation }
+    }
+
+    fn hash(&self, i: usize) -> usize {
+        self.permutation[i] as usize
+    }
+
+    pub fn noise1(&self, x: f32) -> f32 {
+        let (i, x) = lattice(x);
+        let gradient = |hash: usize, x: f32| if hash & 1 == 0 { x } else { -x };
+        let (a, b) = (gradient(self.hash(i), x), gradient(self.hash(i + 1), x - 1.0));
+        // At most a half either way, between two opposite gradients
+        2.0 * a.lerp(&b, fade(x))
+    }
+
+    pub fn noise2(&self, x: f32, y: f32) -> f32 {
+        let ((i, x), (j, y)) = (lattice(x), lattice(y));
+        // Eight directions around the circle
+        let gradient = |hash: usize, x: f32, y: f32| {
+            let (u, v) = if hash & 4 == 0 { (x, y) } else { (y, x) };
+            let u = if hash & 1 == 0 { u } else { -u };
+            let v = if hash & 2 == 0 { v } else { -v };
+            if hash & 8 == 0 { u } else { (u + v) * std::f32::consts::FRAC_1_SQRT_2 }
+        };
+        let (a, b) = (self.hash(i) + j, self.hash(i + 1) + j);
+        let (u, v) = (fade(x), fade(y));
+        let bottom = gradient(self.hash(a), x, y).lerp(&gradient(self.hash(b), x - 1.0, y), u);
+        let top = gradient(self.hash(a + 1), x, y - 1.0).lerp(&gradient(self.hash(b + 1), x - 1.0, y - 1.0), u);
+        std::f32::consts::SQRT_2 * bottom.lerp(&top, v)
+    }
+
+    pub fn noise3(&self, x: f32, y: f32, z: f32) -> f32 {
+        let ((i, x), (j, y), (k, z)) = (lattice(x), lattice(y), lattice(z));
+        // Perlin's twelve directions towards the edges of a cube
+        let gradient = |hash: usize, x: f32, y: f32, z: f32| {
+            let hash = hash & 15;
+            let u = if hash < 8 { x } else { y };
+            let v = if hash < 4 { y } else if hash == 12 || hash == 14 { x } else { z };
+            (if hash & 1 == 0 { u } else { -u }) + (if hash & 2 == 0 { v } else { -v })
+        };
+        let (a, b) = (self.hash(i) + j, self.hash(i + 1) + j);
+        let (aa, ab, ba, bb) = (self.hash(a) + k, self.hash(a + 1) + k, self.hash(b) + k, self.hash(b + 1) + k);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        let near = {
+            let bottom = gradient(self.hash(aa), x, y, z).lerp(&gradient(self.hash(ba), x - 1.0, y, z), u);
+            let top = gradient(self.hash(ab), x, y - 1.0, z).lerp(&gradient(self.hash(bb), x - 1.0, y - 1.0, z), u);
+            bottom.lerp(&top, v)
+        };
+        let far = {
+            let bottom = gradient(self.hash(aa + 1), x, y, z - 1.0).lerp(&gradient(self.hash(ba + 1), x - 1.0, y, z - 1.0), u);
+            let top = gradient(self.hash(ab + 1), x, y - 1.0, z - 1.0).lerp(&gradient(self.hash(bb + 1), x - 1.0, y - 1.0, z - 1.0), u);
+            bottom.lerp(&top, v)
+        };
+        near.lerp(&far, w)
+    }
+
+    pub fn fbm1(&self, x: f32, fbm: &Fbm) -> f32 {
+        fbm.sum(|frequency, shift| self.noise1(x * frequency + shift))
+    }
+
+    pub fn fbm2(&self, x: f32, y: f32, fbm: &Fbm) -> f32 {
+        fbm.sum(|frequency, shift| self.noise2(x * frequency + shift, y * frequency - shift))
+    }
+
+    pub fn fbm3(&self, x: f32, y: f32, z: f32, fbm: &Fbm) -> f32 {
+        fbm.sum(|frequency, shift| self.noise3(x * frequency + shift, y * frequency - shift, z * frequency + shift))
+    }
+
+    // A smoothly swirling direction at `point`, each component up to about 1, e.g. for blowing
+    // particles around
+    pub fn turbulence(&self, point: &glm::Vec3, fbm: &Fbm) -> glm::Vec3 {
+        // The same field sampled far apart for each component, so they don't move together
+        let sample = |offset: f32| self.fbm3(point.x + offset, point.y - offset, point.z + offset * 0.5, fbm);
+        glm::vec3(sample(0.0), sample(31.4), sample(-57.2))
+    }
+}
+
+// The lattice cell a coordinate is in, wrapped to the permutation, and where in the cell it is
+fn lattice(x: f32) -> (usize, f32) {
+    let floor = x.floor();
+    ((floor as i64 & 255) as usize, x - floor)
+}
+
+// Perlin's quintic, easing between lattice points without a seam in the second derivative
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+// Fractal Brownian motion: octaves of noise, each `lacunarity` times the frequency and `gain`
+// times the amplitude of the one before, adding detail at every scale. Sums are scaled back to
+// about -1 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fbm {
+    pub octaves    : u32,
+    pub lacunarity : f32,
+    pub gain       : f32,
+}
+
+impl Default for Fbm {
+    fn default() -> Fbm {
+        Fbm { octaves: 4, lacunarity: 2.0, gain: 0.5 }
+    }
+}
+
+impl Fbm {
+    // `sample(frequency, shift)` is the noise at the point scaled by `frequency` and moved by
+    // `shift`, which differs per octave so octaves don't all cross zero at the origin
+    fn sum(&self, sample: impl Fn(f32, f32) -> f32) -> f32 {
+        let (mut total, mut amplitude, mut frequency, mut amplitudes) = (0.0, 1.0, 1.0, 0.0);
+        for octave in 0..self.octaves {
+            total += amplitude * sample(frequency, octave as f32 * 17.31);
+            amplitudes += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        if amplitudes > 0.0 { total / amplitudes } else { 0.0 }
+    }
+}
+
+// A half-line starting at `origin`. The direction does not need to be normalized, which lets a ray
+// be moved between coordinate spaces with a matrix while keeping its distances comparable.
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin    : glm::Vec3,
+    pub direction : glm::Vec3,
+}
+
+impl Ray {
+    pub fn at(&self, t: f32) -> glm::Vec3 {
+        self.origin + self.direction * t
+    }
+
+    // Apply an affine transformation to the ray
+    pub fn transformed(&self, matrix: &glm::Mat4) -> Ray {
+        Ray {
+            origin    : (matrix * self.origin.push(1.0)).xyz(),
+            direction : (matrix * self.direction.push(0.0)).xyz(),
+        }
+    }
+
+    // Ray through a pixel of the window, e.g. under the mouse cursor
+    pub fn from_screen(pixel: (f32, f32), window_size: (u32, u32), view_projection: &glm::Mat4) -> Ray {
+        let ndc_x = 2.0 * pixel.0 / window_size.0 as f32 - 1.0;
+        let ndc_y = 1.0 - 2.0 * pixel.1 / window_size.1 as f32;
+
+        let inverse = glm::inverse(view_projection);
+        let unproject = |z: f32| {
+            let point = inverse * glm::vec4(ndc_x, ndc_y, z, 1.0);
+            point.xyz() / point.w
+        };
+        let near = unproject(-1.0);
+        let far  = unproject(1.0);
+
+        Ray {
+            origin    : near,
+            direction : glm::normalize(&(far - near)),
+        }
+    }
+}
+
+// Axis-aligned bounding box
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min : glm::Vec3,
+    pub max : glm::Vec3,
+}
+
+impl Aabb {
+    // Bounds of a flat list of xyz coordinates, like Mesh::vertices
+    pub fn from_points(points: &[f32]) -> Option<Aabb> {
+        let mut chunks = points.chunks_exact(3);
+        let first = chunks.next()?;
+        let mut aabb = Aabb {
+            min : glm::vec3(first[0], first[1], first[2]),
+            max : glm::vec3(first[0], first[1], first[2]),
+        };
+        for p in chunks {
+            aabb.min = glm::min2(&aabb.min, &glm::vec3(p[0], p[1], p[2]));
+            aabb.max = glm::max2(&aabb.max, &glm::vec3(p[0], p[1], p[2]));
+        }
+        Some(aabb)
+    }
+
+    // Distance along the ray to where it enters the box (0 if it starts inside), using the slab method
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        let mut t_near = 0.0_f32;
+        let mut t_far  = f32::INFINITY;
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse_direction;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse_direction;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // max/min with a NaN (ray parallel to and on a slab plane) keep the other operand
+            t_near = t_near.max(t0);
+            t_far  = t_far.min(t1);
+            if t_near > t_far {
+                return None;
+            }
+        }
+        Some(t_near)
+    }
+
+    // Bounds of this box after an affine transformation, which are usually larger than the box
+    pub fn transformed(&self, matrix: &glm::Mat4) -> Aabb {
+        let translation = glm::vec3(matrix[(0, 3)], matrix[(1, 3)], matrix[(2, 3)]);
+        let mut aabb = Aabb { min: translation, max: translation };
+        for row in 0..3 {
+            for column in 0..3 {
+                let a = matrix[(row, column)] * self.min[column];
+                let b = matrix[(row, column)] * self.max[column];
+                aabb.min[row] += a.min(b);
+                aabb.max[row] += a.max(b);
+            }
+        }
+        aabb
+    }
+
+    pub fn center(&self) -> glm::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn contains(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis] <= other.min[axis] && other.max[axis] <= self.max[axis])
+    }
+
+    pub fn intersects_sphere(&self, center: &glm::Vec3, radius: f32) -> bool {
+        let closest = glm::clamp_vec(center, &self.min, &self.max);
+        glm::distance2(&closest, center) <= radius * radius
+    }
+}
+
+// How much of a box is inside a frustum
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Containment {
+    Outside,
+    Intersecting,
+    Inside,
+}
+
+// The volume a camera sees, as six planes facing inwards, each stored as (normal, distance)
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    pub planes : [glm::Vec4; 6],
+}
+
+impl Frustum {
+    // Extract the planes from a view-projection matrix (Gribb & Hartmann). With a
+    // projection matrix alone the planes are in view space, with view and projection in world space.
+    pub fn from_matrix(view_projection: &glm::Mat4) -> Frustum {
+        let row = |i: usize| view_projection.row(i).transpose();
+        let mut planes = [
+            row(3) + row(0), // Left
+            row(3) - row(0), // Right
+            row(3) + row(1), // Bottom
+            row(3) - row(1), // Top
+            row(3) + row(2), // Near
+            row(3) - row(2), // Far
+        ];
+        for plane in &mut planes {
+            *plane /= glm::length(&plane.xyz());
+        }
+        Frustum { planes }
+    }
+
+    // Conservative: boxes near the corners of the frustum may be reported as intersecting it
+    // while being outside
+    pub fn classify(&self, aabb: &Aabb) -> Containment {
+        let mut containment = Containment::Inside;
+        for plane in &self.planes {
+            let normal = plane.xyz();
+            // The corners furthest along and against the plane's normal
+            let positive = glm::vec3(
+                if normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if normal.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            let negative = glm::vec3(
+                if normal.x >= 0.0 { aabb.min.x } else { aabb.max.x },
+                if normal.y >= 0.0 { aabb.min.y } else { aabb.max.y },
+                if normal.z >= 0.0 { aabb.min.z } else { aabb.max.z },
+            );
+            if glm::dot(&normal, &positive) + plane.w < 0.0 {
+                return Containment::Outside;
+            }
+            if glm::dot(&normal, &negative) + plane.w < 0.0 {
+                containment = Containment::Intersecting;
+            }
+        }
+        containment
+    }
+
+    // How much of a box is inside, given how much of a box containing it is. Only boxes whose
+    // container intersects the frustum have to be classified themselves.
+    pub fn classify_within(&self, aabb: &Aabb, container: Containment) -> Containment {
+        match container {
+            Containment::Intersecting => self.classify(aabb),
+            _ => container,
+        }
+    }
+}
+
+// Distance along the ray to where it hits the triangle abc, using the Möller-Trumbore algorithm.
+// Both sides of the triangle count as a hit.
+pub fn intersect_triangle(ray: &Ray, a: &glm::Vec3, b: &glm::Vec3, c: &glm::Vec3) -> Option<f32> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = glm::cross(&ray.direction, &edge2);
+    let determinant = glm::dot(&edge1, &p);
+    if determinant.abs() < 1e-8 {
+        return None; // The ray is parallel to the triangle
+    }
+    let inverse_determinant = 1.0 / determinant;
+
+    let s = ray.origin - a;
+    let u = glm::dot(&s, &p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = glm::cross(&s, &edge1);
+    let v = glm::dot(&ray.direction, &q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = glm::dot(&edge2, &q) * inverse_determinant;
+    if t >= 0.0 { Some(t) } else { None }
+}
+
+// Distance along the ray to the closest triangle it hits in an indexed triangle mesh, like the
+// vertices and indices of a Mesh
+pub fn intersect_mesh(ray: &Ray, vertices: &[f32], indices: &[u32]) -> Option<f32> {
+    let vertex = |i: u32| {
+        let i = i as usize * 3;
+        glm::vec3(vertices[i], vertices[i + 1], vertices[i + 2])
+    };
+    indices
+        .chunks_exact(3)
+        .filter_map(|triangle| {
+            intersect_triangle(ray, &vertex(triangle[0]), &vertex(triangle[1]), &vertex(triangle[2]))
+        })
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+// The top of a mesh seen from above, sampled on a grid, for finding the ground under a point
+// without testing every triangle. Outside the grid the edges carry on.
+#[derive(Clone, Debug)]
+pub struct HeightField {
+    pub origin  : glm::Vec2,   // X and Z of the first sample
+    pub spacing : f32,         // Between samples, along both X and Z
+    pub columns : usize,       // Samples along X
+    pub rows    : usize,       // Samples along Z
+    heights     : Vec<f32>,    // Row by row, negative infinity where there is no surface
+}
+
+impl HeightField {
+    // Sample the highest of the triangles of `vertices` and `indices` every `spacing` units
+    pub fn from_mesh(vertices: &[f32], indices: &[u32], spacing: f32) -> Option<HeightField> {
+        let bounds = Aabb::from_points(vertices)?;
+        let columns = ((bounds.max.x - bounds.min.x) / spacing).ceil() as usize + 1;
+        let rows = ((bounds.max.z - bounds.min.z) / spacing).ceil() as usize + 1;
+        let mut heights = vec![f32::NEG_INFINITY; columns * rows];
+        let vertex = |i: u32| glm::vec3(vertices[i as usize * 3], vertices[i as usize * 3 + 1], vertices[i as usize * 3 + 2]);
+        for triangle in indices.chunks_exact(3) {
+            let (a, b, c) = (vertex(triangle[0]), vertex(triangle[1]), vertex(triangle[2]));
+            // Twice the triangle's area seen from above, nothing for triangles standing on edge
+            let area = (b.z - c.z) * (a.x - c.x) + (c.x - b.x) * (a.z - c.z);
+            if area.abs() < 1e-9 {
+                continue;
+            }
+            let cell = |value: f32, min: f32| ((value - min) / spacing).max(0.0);
+            let (first_column, last_column) = (cell(a.x.min(b.x).min(c.x), bounds.min.x).ceil() as usize, cell(a.x.max(b.x).max(c.x), bounds.min.x).floor() as usize);
+            let (first_row, last_row) = (cell(a.z.min(b.z).min(c.z), bounds.min.z).ceil() as usize, cell(a.z.max(b.z).max(c.z), bounds.min.z).floor() as usize);
+            for row in first_row..=last_row.min(rows - 1) {
+                for column in first_column..=last_column.min(columns - 1) {
+                    let (x, z) = (bounds.min.x + column as f32 * spacing, bounds.min.z + row as f32 * spacing);
+                    let weight_a = ((b.z - c.z) * (x - c.x) + (c.x - b.x) * (z - c.z)) / area;
+                    let weight_b = ((c.z - a.z) * (x - c.x) + (a.x - c.x) * (z - c.z)) / area;
+                    let weight_c = 1.0 - weight_a - weight_b;
+                    if weight_a >= -1e-4 && weight_b >= -1e-4 && weight_c >= -1e-4 {
+                        let height = &mut heights[row * columns + column];
+                        *height = height.max(weight_a * a.y + weight_b * b.y + weight_c * c.y);
+                    }
+                }
+            }
+        }
+        Some(HeightField { origin: bounds.min.xz(), spacing, columns, rows, heights })
+    }
+
+    // The height at `x`, `z`, blended from the samples around it, or the highest of them next to
+    // holes in the surface
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        let column = ((x - self.origin.x) / self.spacing).clamp(0.0, (self.columns - 1) as f32);
+        let row = ((z - self.origin.y) / self.spacing).clamp(0.0, (self.rows - 1) as f32);
+        let (left, top) = (column.floor() as usize, row.floor() as usize);
+        let (right, bottom) = ((left + 1).min(self.columns - 1), (top + 1).min(self.rows - 1));
+        let sample = |column: usize, row: usize| self.heights[row * self.columns + column];
+        let corners = [sample(left, top), sample(right, top), sample(left, bottom), sample(right, bottom)];
+        if corners.iter().all(|height| height.is_finite()) {
+            let (s, t) = (column.fract(), row.fract());
+            let near = corners[0] + (corners[1] - corners[0]) * s;
+            let far = corners[2] + (corners[3] - corners[2]) * s;
+            return Some(near + (far - near) * t);
+        }
+        corners.iter().copied().filter(|height| height.is_finite()).reduce(f32::max)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    fn assert_close(a: &glm::Vec3, b: &glm::Vec3) {
+        assert!(glm::distance(a, b) < 1e-4, "{} is not close to {}", a, b);
+    }
+
+    fn transform_point(matrix: &glm::Mat4, point: &glm::Vec3) -> glm::Vec3 {
+        (matrix * point.push(1.0)).xyz()
+    }
+
+    fn unit_box(center: glm::Vec3) -> Aabb {
+        Aabb { min: center - glm::vec3(0.5, 0.5, 0.5), max: center + glm::vec3(0.5, 0.5, 0.5) }
+    }
+
+    // A camera at the origin looking down -Z, seeing from 1 to 100 units away
+    fn camera_frustum() -> Frustum {
+        let projection = glm::perspective(1.0, 1.0, 1.0, 100.0);
+        Frustum::from_matrix(&projection)
+    }
+
+    #[test]
+    fn compose_transform_defaults_to_identity() {
+        let transform = compose_transform(&glm::zero(), &glm::zero(), &glm::vec3(1.0, 1.0, 1.0), &glm::zero());
+        assert_eq!(transform, glm::Mat4::identity());
+    }
+
+    #[test]
+    fn compose_transform_scales_then_rotates_then_translates() {
+        let position = glm::vec3(1.0, 2.0, 3.0);
+        let rotation = glm::vec3(0.0, std::f32::consts::FRAC_PI_2, 0.0);
+        let transform = compose_transform(&position, &rotation, &glm::vec3(2.0, 2.0, 2.0), &glm::zero());
+        // Scaled to (2, 0, 0), rotated a quarter turn around Y to (0, 0, -2) and moved
+        assert_close(&transform_point(&transform, &glm::vec3(1.0, 0.0, 0.0)), &glm::vec3(1.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn compose_transform_rotates_about_the_reference_point() {
+        let reference_point = glm::vec3(4.0, 0.0, -2.0);
+        let rotation = glm::vec3(0.3, 1.2, -0.7);
+        let transform = compose_transform(&glm::zero(), &rotation, &glm::vec3(1.0, 1.0, 1.0), &reference_point);
+        assert_close(&transform_point(&transform, &reference_point), &reference_point);
+    }
+
+    #[test]
+    fn tracks_pass_through_their_keys() {
+        let keys = vec![(0.0, 0.0), (1.0, 2.0), (2.0, 1.0), (4.0, 5.0)];
+        for &interpolation in &[Interpolation::Linear, Interpolation::Cubic] {
+            let track = Track::new(interpolation, keys.clone());
+            for &(time, value) in &keys {
+                assert!((track.sample(time, Looping::Once) - value).abs() < 1e-5);
+            }
+            assert_eq!(track.sample(-1.0, Looping::Once), 0.0);
+            assert_eq!(track.sample(9.0, Looping::Once), 5.0);
+        }
+        let linear = Track::new(Interpolation::Linear, keys);
+        assert!((linear.sample(3.0, Looping::Once) - 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn repeating_clips_start_over() {
+        let track = Track::new(Interpolation::Cubic, vec![(0.0, glm::zero()), (1.0, glm::vec3(1.0, 0.0, 0.0)), (2.0, glm::zero())]);
+        let clip = AnimationClip::new(2.0, Looping::Repeat).with_channel(Channel::Position(track));
+        let (mut first, mut later) = (Transform::default(), Transform::default());
+        clip.apply(0.5, &mut first);
+        clip.apply(6.5, &mut later);
+        assert_close(&first.position, &later.position);
+        assert_eq!(later.scale, glm::vec3(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn accumulating_clips_keep_going() {
+        // A rotor turning 10 radians a second
+        let turn = Track::new(Interpolation::Linear, vec![(0.0, 0.0), (1.0, TAU)]);
+        let clip = AnimationClip::new(1.0, Looping::Accumulate).with_channel(Channel::Angle(1, turn)).with_speed(10.0 / TAU);
+        let mut rotor = Transform::default();
+        for &time in &[0.0, 0.3, 1.7, 12.0] {
+            clip.apply(time, &mut rotor);
+            assert!((rotor.rotation.y - time * 10.0).abs() < 1e-3, "{} at {}", rotor.rotation.y, time);
+        }
+    }
+
+    #[test]
+    fn quat_from_euler_matches_rotating_about_each_axis_in_turn() {
+        let angles = glm::vec3(0.3, -1.1, 2.0);
+        let matrices = glm::rotation(angles.y, &glm::vec3(0.0, 1.0, 0.0))
+            * glm::rotation(angles.x, &glm::vec3(1.0, 0.0, 0.0))
+            * glm::rotation(angles.z, &glm::vec3(0.0, 0.0, 1.0));
+        let rotation = glm::quat_to_mat4(&quat_from_euler(&angles));
+        let point = glm::vec3(1.0, -2.0, 0.5);
+        assert_close(&transform_point(&rotation, &point), &transform_point(&matrices, &point));
+        assert_close(&euler_angles(&quat_from_euler(&angles)), &angles);
+    }
+
+    #[test]
+    fn look_rotation_faces_the_direction_upright() {
+        let up = glm::vec3(0.0, 1.0, 0.0);
+        for direction in &[glm::vec3(1.0, 0.0, 0.0), glm::vec3(-1.0, 2.0, 3.0), glm::vec3(0.0, 0.0, -1.0), glm::vec3(0.0, -1.0, 0.0)] {
+            let rotation = look_rotation(direction, &up);
+            assert_close(&glm::quat_rotate_vec3(&rotation, &glm::vec3(0.0, 0.0, -1.0)), &glm::normalize(direction));
+            // The right side stays level
+            assert!(glm::quat_rotate_vec3(&rotation, &glm::vec3(1.0, 0.0, 0.0)).y.abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn slerp_and_nlerp_take_the_short_way_around() {
+        let up = glm::vec3(0.0, 1.0, 0.0);
+        let (a, b) = (glm::quat_angle_axis(0.2, &up), -glm::quat_angle_axis(1.0, &up));
+        for interpolate in &[slerp, nlerp] {
+            assert_close(&euler_angles(&interpolate(&a, &b, 0.0)), &glm::vec3(0.0, 0.2, 0.0));
+            assert_close(&euler_angles(&interpolate(&a, &b, 1.0)), &glm::vec3(0.0, 1.0, 0.0));
+            assert_close(&euler_angles(&interpolate(&a, &b, 0.5)), &glm::vec3(0.0, 0.6, 0.0));
+        }
+        // Only slerp turns at a constant speed
+        assert_close(&euler_angles(&slerp(&a, &b, 0.25)), &glm::vec3(0.0, 0.4, 0.0));
+    }
+
+    #[test]
+    fn euler_angles_undo_compose_transform() {
+        let angles = glm::vec3(0.3, -1.1, 2.0);
+        let rotation = glm::quat_angle_axis(angles.y, &glm::vec3(0.0, 1.0, 0.0))
+            * glm::quat_angle_axis(angles.x, &glm::vec3(1.0, 0.0, 0.0))
+            * glm::quat_angle_axis(angles.z, &glm::vec3(0.0, 0.0, 1.0));
+        let expected = compose_transform(&glm::zero(), &angles, &glm::vec3(1.0, 1.0, 1.0), &glm::zero());
+        let found = compose_transform(&glm::zero(), &euler_angles(&rotation), &glm::vec3(1.0, 1.0, 1.0), &glm::zero());
+        for axis in &[glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0), glm::vec3(0.0, 0.0, 1.0)] {
+            assert_close(&transform_point(&found, axis), &transform_point(&expected, axis));
+        }
+    }
+
+    #[test]
+    fn orientations_blend_the_short_way_around() {
+        let up = glm::vec3(0.0, 1.0, 0.0);
+        let start = glm::quat_angle_axis(0.2, &up);
+        // The same rotation as 0.6 radians, from the other side
+        let end = -glm::quat_angle_axis(0.6, &up);
+        let track = Track::new(Interpolation::Linear, vec![(0.0, start), (1.0, end)]);
+        let halfway = euler_angles(&track.sample(0.5, Looping::Once));
+        assert_close(&halfway, &glm::vec3(0.0, 0.4, 0.0));
+    }
+
+    #[test]
+    fn ease_in_out_is_clamped_and_symmetric() {
+        assert_eq!(ease_in_out(-1.0), 0.0);
+        assert_eq!(ease_in_out(0.0), 0.0);
+        assert_eq!(ease_in_out(0.5), 0.5);
+        assert_eq!(ease_in_out(1.0), 1.0);
+        assert_eq!(ease_in_out(2.0), 1.0);
+        assert!((ease_in_out(0.2) + ease_in_out(0.8) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn splines_pass_through_their_points() {
+        let points = vec![glm::vec3(0.0, 0.0, 0.0), glm::vec3(4.0, 1.0, 0.0), glm::vec3(5.0, 3.0, -2.0), glm::vec3(1.0, 0.0, 2.0)];
+        for &closed in &[false, true] {
+            let spline = Spline::catmull_rom(points.clone(), closed);
+            for (i, point) in points.iter().enumerate() {
+                assert_close(&spline.point(i as f32), point);
+            }
+        }
+        let closed = Spline::catmull_rom(points.clone(), true);
+        assert_eq!(closed.segments(), 4);
+        assert_close(&closed.point(4.0), &points[0]);
+        let bezier = Spline::bezier(points.clone());
+        assert_close(&bezier.point(0.0), &points[0]);
+        assert_close(&bezier.point(1.0), &points[3]);
+    }
+
+    #[test]
+    fn tangents_are_the_derivative() {
+        let points = vec![glm::vec3(0.0, 0.0, 0.0), glm::vec3(4.0, 1.0, 0.0), glm::vec3(5.0, 3.0, -2.0), glm::vec3(1.0, 0.0, 2.0)];
+        for spline in &[Spline::catmull_rom(points.clone(), false), Spline::bezier(points)] {
+            for &u in &[0.1, 0.5, 0.9] {
+                let h = 1e-3;
+                let difference = (spline.point(u + h) - spline.point(u - h)) / (2.0 * h);
+                assert!(glm::distance(&difference, &spline.tangent(u)) < 1e-2, "{} at {}", spline.tangent(u), u);
+            }
+        }
+    }
+
+    #[test]
+    fn arc_lengths_space_points_evenly() {
+        // Control points bunched up at the start of a straight line make the parameter speed up
+        let line = Spline::bezier(vec![glm::zero(), glm::vec3(0.1, 0.0, 0.0), glm::vec3(0.2, 0.0, 0.0), glm::vec3(9.0, 0.0, 0.0)]);
+        assert!((line.length() - 9.0).abs() < 1e-3);
+        for &distance in &[0.0, 1.0, 4.5, 8.0, 9.0] {
+            let u = line.parameter_at(distance);
+            assert!((line.point(u).x - distance).abs() < 0.05, "{} is not at {}", line.point(u).x, distance);
+            assert!((line.distance_at(u) - distance).abs() < 1e-3);
+        }
+        assert_eq!(line.parameter_at(20.0), 1.0);
+
+        let square = vec![glm::zero(), glm::vec3(1.0, 0.0, 0.0), glm::vec3(1.0, 0.0, 1.0), glm::vec3(0.0, 0.0, 1.0)];
+        let ring = Spline::catmull_rom(square, true);
+        assert!((ring.parameter_at(ring.length() + 0.1) - ring.parameter_at(0.1)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn closest_point_finds_the_nearest_part_of_the_curve() {
+        let arc = Spline::catmull_rom(vec![glm::vec3(-4.0, 0.0, 0.0), glm::vec3(0.0, 0.0, 2.0), glm::vec3(4.0, 0.0, 0.0)], false);
+        let u = arc.closest_point(&glm::vec3(0.3, 5.0, 6.0));
+        let point = arc.point(u);
+        // Nothing else along the curve is closer
+        let distance = glm::distance(&point, &glm::vec3(0.3, 5.0, 6.0));
+        for i in 0..=200 {
+            let other = arc.point(i as f32 / 100.0);
+            assert!(glm::distance(&other, &glm::vec3(0.3, 5.0, 6.0)) >= distance - 1e-4);
+        }
+    }
+
+    #[test]
+    fn flight_patterns_face_the_way_they_go() {
+        let route = Spline::catmull_rom(vec![glm::zero(), glm::vec3(20.0, 0.0, 0.0), glm::vec3(20.0, 0.0, 20.0), glm::vec3(0.0, 0.0, 20.0)], true);
+        let patterns = [
+            FlightPattern::FigureEight { center: glm::zero(), half_width: 15.0, half_length: 45.0, lap_time: 8.0 },
+            FlightPattern::Orbit { center: glm::vec3(0.0, 10.0, 0.0), radius: 40.0, lap_time: -10.0 },
+            FlightPattern::Patrol { route, speed: 10.0 },
+        ];
+        for pattern in &patterns {
+            let mut body = Transform::default();
+            for i in 0..100 {
+                let time = i as f32 * 0.1;
+                pattern.apply(time, &mut body);
+                let travel = pattern.position(time + 0.01) - pattern.position(time);
+                let facing = glm::rotate_y_vec3(&glm::vec3(0.0, 0.0, -1.0), body.rotation.y);
+                assert!(glm::dot(&glm::normalize(&travel), &facing) > 0.99, "{:?} faces away at {}", pattern, time);
+                assert!(body.rotation.x < 0.0, "flying forwards with the nose down");
+            }
+        }
+    }
+
+    #[test]
+    fn orbits_bank_into_the_turn() {
+        let orbit = FlightPattern::Orbit { center: glm::zero(), radius: 40.0, lap_time: 10.0 };
+        let mut body = Transform::default();
+        orbit.apply(1.0, &mut body);
+        assert!((glm::length(&body.position) - 40.0).abs() < 1e-3);
+        // Counterclockwise seen from above is turning left, which rolls to the left
+        assert!(body.rotation.z > 0.0);
+        assert!((orbit.closest_time(&(body.position * 2.0)).unwrap() - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn landings_end_level_on_the_spot() {
+        let spot = glm::vec3(30.0, -2.0, 10.0);
+        let land = FlightPattern::Land { from: glm::vec3(0.0, 20.0, 0.0), to: spot, duration: 5.0 };
+        let mut body = Transform::default();
+        land.apply(2.0, &mut body);
+        assert!(body.position.y == 20.0, "still approaching");
+        assert!(!land.has_landed(4.9));
+        land.apply(6.0, &mut body);
+        assert!(land.has_landed(6.0));
+        assert_close(&body.position, &spot);
+        assert_eq!((body.rotation.x, body.rotation.z), (0.0, 0.0));
+        assert_eq!(land.closest_time(&spot), None);
+    }
+
+    #[test]
+    fn easings_start_at_0_and_end_at_1() {
+        for &ease in &[Ease::In, Ease::Out, Ease::InOut] {
+            for &easing in &[Easing::Quad(ease), Easing::Cubic(ease), Easing::Expo(ease), Easing::Elastic(ease), Easing::Bounce(ease)] {
+                assert!(easing.apply(0.0).abs() < 1e-3, "{:?} starts at {}", easing, easing.apply(0.0));
+                assert!((easing.apply(1.0) - 1.0).abs() < 1e-3, "{:?} ends at {}", easing, easing.apply(1.0));
+                assert_eq!(easing.apply(-1.0), easing.apply(0.0));
+            }
+        }
+        assert_eq!(Easing::Quad(Ease::In).apply(0.5), 0.25);
+        assert_eq!(Easing::Quad(Ease::Out).apply(0.5), 0.75);
+        assert_eq!(Easing::Cubic(Ease::InOut).apply(0.5), 0.5);
+        assert!(Easing::Elastic(Ease::Out).apply(0.2) > 1.0, "elastic overshoots");
+    }
+
+    #[test]
+    fn noise_is_seeded_and_zero_on_the_lattice() {
+        let (noise, same, other) = (Noise::new(7), Noise::new(7), Noise::new(8));
+        assert_eq!(noise.noise3(1.5, 2.25, -0.75), same.noise3(1.5, 2.25, -0.75));
+        assert_ne!(noise.noise2(1.5, 2.25), other.noise2(1.5, 2.25));
+        assert_eq!(noise.noise1(3.0), 0.0);
+        assert_eq!(noise.noise2(-4.0, 300.0), 0.0);
+        assert_eq!(noise.noise3(1.0, 2.0, 3.0), 0.0);
+    }
+
+    #[test]
+    fn noise_is_smooth_and_within_range() {
+        let noise = Noise::new(1);
+        let fbm = Fbm::default();
+        let h = 1e-3;
+        for i in 0..2000 {
+            let x = i as f32 * 0.137 - 50.0;
+            let (y, z) = (x * 0.61 + 3.0, x * -0.37);
+            let samples = [noise.noise1(x), noise.noise2(x, y), noise.noise3(x, y, z), noise.fbm2(x, y, &fbm), noise.fbm3(x, y, z, &fbm)];
+            for value in &samples {
+                assert!(value.abs() <= 1.1, "{} is out of range at {}", value, x);
+            }
+            assert!((noise.noise3(x + h, y, z) - samples[2]).abs() < 0.01, "jumps at {}", x);
+            assert!((noise.fbm1(x + h, &fbm) - noise.fbm1(x, &fbm)).abs() < 0.01, "jumps at {}", x);
+        }
+    }
+
+    #[test]
+    fn tweens_retarget_from_where_they_are() {
+        let mut tween = Tween::new(0.0, 2.0, 1.0, Easing::Linear);
+        assert_eq!(tween.advance(0.25), 0.5);
+        tween.retarget(0.0, 0.5);
+        assert_eq!(tween.value(), 0.5);
+        assert_eq!(tween.advance(1.0), 0.0);
+        assert!(tween.is_done());
+        assert_eq!(Tween::at_rest(3.0, Easing::Linear).value(), 3.0);
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_the_inner_points() {
+        let points = [glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 2.0, 0.0), glm::vec3(3.0, 2.0, 1.0), glm::vec3(4.0, 0.0, 1.0)];
+        let [p0, p1, p2, p3] = points;
+        assert_close(&catmull_rom(&p0, &p1, &p2, &p3, 0.0), &p1);
+        assert_close(&catmull_rom(&p0, &p1, &p2, &p3, 1.0), &p2);
+    }
+
+    #[test]
+    fn animation_clock_scales_pauses_and_steps() {
+        let mut clock = AnimationClock::new();
+        clock.set_time_scale(2.0);
+        assert_eq!(clock.tick(0.5), 1.0);
+        clock.paused = true;
+        assert_eq!(clock.tick(0.5), 0.0);
+        clock.step();
+        assert!((clock.time - (1.0 + 2.0 * AnimationClock::STEP)).abs() < 1e-6);
+
+        clock.set_time_scale(100.0);
+        assert_eq!(clock.time_scale(), AnimationClock::MAX_TIME_SCALE);
+        clock.set_time_scale(0.0);
+        assert_eq!(clock.time_scale(), AnimationClock::MIN_TIME_SCALE);
+    }
+
+    #[test]
+    fn aabb_from_points_covers_them_all() {
+        assert!(Aabb::from_points(&[]).is_none());
+        let aabb = Aabb::from_points(&[1.0, -2.0, 3.0, -1.0, 4.0, 0.0, 0.5, 0.5, 5.0]).unwrap();
+        assert_eq!(aabb.min, glm::vec3(-1.0, -2.0, 0.0));
+        assert_eq!(aabb.max, glm::vec3(1.0, 4.0, 5.0));
+    }
+
+    #[test]
+    fn rays_hit_boxes_in_front_of_them() {
+        let aabb = unit_box(glm::vec3(0.0, 0.0, -5.0));
+        let towards = Ray { origin: glm::zero(), direction: glm::vec3(0.0, 0.0, -1.0) };
+        let away = Ray { origin: glm::zero(), direction: glm::vec3(0.0, 0.0, 1.0) };
+        let inside = Ray { origin: glm::vec3(0.0, 0.0, -5.0), direction: glm::vec3(1.0, 0.0, 0.0) };
+        let past = Ray { origin: glm::vec3(2.0, 0.0, 0.0), direction: glm::vec3(0.0, 0.0, -1.0) };
+        assert!((aabb.intersect_ray(&towards).unwrap() - 4.5).abs() < 1e-6);
+        assert_eq!(aabb.intersect_ray(&away), None);
+        assert_eq!(aabb.intersect_ray(&inside), Some(0.0));
+        assert_eq!(aabb.intersect_ray(&past), None);
+    }
+
+    #[test]
+    fn transformed_rays_keep_their_distances() {
+        let ray = Ray { origin: glm::vec3(1.0, 0.0, 0.0), direction: glm::vec3(0.0, 0.0, -1.0) };
+        let matrix = glm::translation(&glm::vec3(0.0, 3.0, 0.0)) * glm::scaling(&glm::vec3(2.0, 2.0, 2.0));
+        let transformed = ray.transformed(&matrix);
+        assert_close(&transformed.at(1.0), &transform_point(&matrix, &ray.at(1.0)));
+    }
+
+    #[test]
+    fn rays_from_the_screen_center_go_straight_ahead() {
+        let view_projection = glm::perspective(1.0, 1.0, 1.0, 100.0) * glm::translation(&glm::vec3(0.0, 0.0, -10.0));
+        let ray = Ray::from_screen((400.0, 300.0), (800, 600), &view_projection);
+        assert_close(&ray.origin, &glm::vec3(0.0, 0.0, 9.0));
+        assert_close(&ray.direction, &glm::vec3(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn transformed_aabbs_bound_the_transformed_corners() {
+        let aabb = Aabb { min: glm::vec3(-1.0, -2.0, -3.0), max: glm::vec3(1.0, 2.0, 3.0) };
+        let matrix = glm::translation(&glm::vec3(5.0, 0.0, 0.0)) * glm::rotation(0.7, &glm::vec3(0.2, 1.0, 0.1).normalize());
+        let transformed = aabb.transformed(&matrix);
+        for corner in 0..8 {
+            let point = glm::vec3(
+                if corner & 1 == 0 { aabb.min.x } else { aabb.max.x },
+                if corner & 2 == 0 { aabb.min.y } else { aabb.max.y },
+                if corner & 4 == 0 { aabb.min.z } else { aabb.max.z },
+            );
+            let point = transform_point(&matrix, &point);
+            assert!((0..3).all(|axis| transformed.min[axis] - 1e-4 <= point[axis] && point[axis] <= transformed.max[axis] + 1e-4));
+        }
+        assert_close(&transformed.center(), &glm::vec3(5.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn height_fields_follow_the_surface_and_carry_on_past_its_edges() {
+        // A slope rising one unit along X for every two, over a 10 by 10 square
+        let vertices = [0.0, 0.0, 0.0, 10.0, 5.0, 0.0, 10.0, 5.0, 10.0, 0.0, 0.0, 10.0];
+        let field = HeightField::from_mesh(&vertices, &[0, 2, 1, 0, 3, 2], 1.0).unwrap();
+        assert_eq!((field.columns, field.rows), (11, 11));
+        assert!((field.height_at(4.0, 7.0).unwrap() - 2.0).abs() < 1e-4);
+        assert!((field.height_at(3.5, 2.25).unwrap() - 1.75).abs() < 1e-4);
+        assert!((field.height_at(20.0, -5.0).unwrap() - 5.0).abs() < 1e-4);
+        assert!(HeightField::from_mesh(&[], &[], 1.0).is_none());
+    }
+
+    #[test]
+    fn aabbs_contain_and_touch() {
+        let outer = unit_box(glm::zero());
+        let inner = Aabb { min: glm::vec3(-0.25, -0.25, -0.25), max: glm::vec3(0.25, 0.25, 0.25) };
+        assert!(outer.contains(&inner));
+        assert!(!inner.contains(&outer));
+        assert!(outer.intersects_sphere(&glm::vec3(1.0, 0.0, 0.0), 0.6));
+        assert!(!outer.intersects_sphere(&glm::vec3(1.0, 1.0, 0.0), 0.6));
+    }
+
+    #[test]
+    fn frustum_planes_face_inwards_with_unit_normals() {
+        let frustum = camera_frustum();
+        for plane in &frustum.planes {
+            assert!((glm::length(&plane.xyz()) - 1.0).abs() < 1e-5);
+            // The point the camera looks at is in front of every plane
+            assert!(glm::dot(&plane.xyz(), &glm::vec3(0.0, 0.0, -10.0)) + plane.w > 0.0);
+        }
+        let distance = |plane: glm::Vec4, z: f32| glm::dot(&plane.xyz(), &glm::vec3(0.0, 0.0, z)) + plane.w;
+        assert!(distance(frustum.planes[4], -1.0).abs() < 1e-4, "the near plane is at z = -1");
+        assert!(distance(frustum.planes[5], -100.0).abs() < 1e-2, "the far plane is at z = -100");
+    }
+
+    #[test]
+    fn frustum_world_planes_follow_the_camera() {
+        // Looking down +X from x = -10, a box at the origin is in view and one behind the camera is not
+        let view = glm::look_at(&glm::vec3(-10.0, 0.0, 0.0), &glm::zero(), &glm::vec3(0.0, 1.0, 0.0));
+        let frustum = Frustum::from_matrix(&(glm::perspective(1.0, 1.0, 1.0, 100.0) * view));
+        assert_eq!(frustum.classify(&unit_box(glm::zero())), Containment::Inside);
+        assert_eq!(frustum.classify(&unit_box(glm::vec3(-20.0, 0.0, 0.0))), Containment::Outside);
+    }
+
+    #[test]
+    fn frustum_classifies_boxes() {
+        let frustum = camera_frustum();
+        assert_eq!(frustum.classify(&unit_box(glm::vec3(0.0, 0.0, -10.0))), Containment::Inside);
+        assert_eq!(frustum.classify(&unit_box(glm::vec3(0.0, 0.0, 10.0))), Containment::Outside);
+        assert_eq!(frustum.classify(&unit_box(glm::vec3(0.0, 0.0, -100.0))), Containment::Intersecting);
+        assert_eq!(frustum.classify(&unit_box(glm::vec3(50.0, 0.0, -10.0))), Containment::Outside);
+        assert_eq!(frustum.classify(&unit_box(glm::vec3(0.0, 0.0, -0.5))), Containment::Intersecting);
+    }
+
+    #[test]
+    fn frustum_only_classifies_boxes_within_intersecting_ones() {
+        let frustum = camera_frustum();
+        let behind = unit_box(glm::vec3(0.0, 0.0, 10.0));
+        assert_eq!(frustum.classify_within(&behind, Containment::Inside), Containment::Inside);
+        assert_eq!(frustum.classify_within(&behind, Containment::Outside), Containment::Outside);
+        assert_eq!(frustum.classify_within(&behind, Containment::Intersecting), Containment::Outside);
+    }
+
+    #[test]
+    fn rays_hit_both_sides_of_triangles() {
+        let (a, b, c) = (glm::vec3(-1.0, -1.0, -2.0), glm::vec3(1.0, -1.0, -2.0), glm::vec3(0.0, 1.0, -2.0));
+        let forwards = Ray { origin: glm::zero(), direction: glm::vec3(0.0, 0.0, -1.0) };
+        let backwards = Ray { origin: glm::vec3(0.0, 0.0, -4.0), direction: glm::vec3(0.0, 0.0, 1.0) };
+        let beside = Ray { origin: glm::vec3(2.0, 0.0, 0.0), direction: glm::vec3(0.0, 0.0, -1.0) };
+        let parallel = Ray { origin: glm::zero(), direction: glm::vec3(1.0, 0.0, 0.0) };
+        assert!((intersect_triangle(&forwards, &a, &b, &c).unwrap() - 2.0).abs() < 1e-6);
+        assert!((intersect_triangle(&backwards, &a, &b, &c).unwrap() - 2.0).abs() < 1e-6);
+        assert_eq!(intersect_triangle(&beside, &a, &b, &c), None);
+        assert_eq!(intersect_triangle(&parallel, &a, &b, &c), None);
+    }
+
+    #[test]
+    fn rays_hit_the_closest_triangle_of_a_mesh() {
+        // Two quads facing the ray, at z = -3 and z = -2
+        let vertices = [
+            -1.0, -1.0, -3.0,  1.0, -1.0, -3.0,  1.0, 1.0, -3.0,  -1.0, 1.0, -3.0,
+            -1.0, -1.0, -2.0,  1.0, -1.0, -2.0,  1.0, 1.0, -2.0,  -1.0, 1.0, -2.0,
+        ];
+        let indices = [0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7];
+        let ray = Ray { origin: glm::vec3(0.2, 0.3, 0.0), direction: glm::vec3(0.0, 0.0, -1.0) };
+        assert!((intersect_mesh(&ray, &vertices, &indices).unwrap() - 2.0).abs() < 1e-6);
+        assert_eq!(intersect_mesh(&ray, &vertices, &[]), None);
     }
 }
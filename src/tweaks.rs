@@ -0,0 +1,325 @@
+// Uniforms tuned while the application runs, like lighting constants.
+//
+// A uniform is made tweakable by a `@tweak` comment after its declaration in the shader source:
+//
+//     uniform float ambient = 0.1;                         // @tweak 0 1
+//     uniform vec3 lightColor = vec3(1.0);                 // @tweak color
+//     uniform vec3 lightDirection = vec3(0.0, -1.0, 0.0);  // @tweak
+//
+// Two numbers give a slider's range, `color` a color picker, and nothing at all plain number
+// fields. Only float, vec2, vec3 and vec4 uniforms can be tweaked. Introspecting the program tells
+// which of the tagged uniforms are active, and their values in the program, set by the
+// initializers in the source, are where tweaking starts. `apply` sets the tweaked values on the
+// program every frame, so they outlive the context, and `export` writes them back into the source
+// as the new initializers. Reloading the shaders starts over from the source, so export first.
+use crate::backend::gl::{shader_paths, GlBackend};
+use crate::backend::PipelineHandle;
+use log::{debug, warn};
+use std::io;
+
+// How a tweak is edited
+#[derive(Clone, Debug, PartialEq)]
+pub enum Widget {
+    Slider(f32, f32), // Between a minimum and a maximum
+    Color,
+    Number,
+}
+
+pub struct Tweak {
+    pub name: String,
+    pub widget: Widget,
+    pub values: Vec<f32>, // One per component
+    path: String,         // The shader file declaring it
+}
+
+impl Tweak {
+    fn glsl_type(&self) -> &'static str {
+        ["float", "vec2", "vec3", "vec4"][self.values.len() - 1]
+    }
+}
+
+// A uniform tagged with `@tweak` in a shader's source
+#[derive(Debug, PartialEq)]
+struct Declaration {
+    name: String,
+    components: usize,
+    widget: Widget,
+}
+
+#[derive(Default)]
+pub struct Tweaks {
+    tweaks: Vec<Tweak>,
+}
+
+impl Tweaks {
+    // The tagged uniforms of the pipeline compiled from the shaders named `name`, with the values
+    // they have in the program
+    pub unsafe fn load(backend: &GlBackend, pipeline: PipelineHandle, name: &str) -> Tweaks {
+        let program = backend.program_id(pipeline);
+        let uniforms = backend.uniforms(pipeline);
+        let mut tweaks = vec![];
+        for path in shader_paths(name) {
+            let source = match std::fs::read_to_string(&path) {
+                Ok(source) => source,
+                Err(e) => {
+                    warn!("Failed to read {} for its tweaks: {}", path, e);
+                    continue;
+                }
+            };
+            for declaration in declarations(&source) {
+                let kind = [gl::FLOAT, gl::FLOAT_VEC2, gl::FLOAT_VEC3, gl::FLOAT_VEC4]
+                    [declaration.components - 1];
+                let uniform = match uniforms.get(&declaration.name) {
+                    Some(uniform) if uniform.kind == kind && uniform.size == 1 => uniform,
+                    Some(_) => {
+                        warn!(
+                            "Only floats and vectors can be tweaked, not {}",
+                            declaration.name
+                        );
+                        continue;
+                    }
+                    // Unused uniforms are optimized away
+                    None => {
+                        debug!("{} is never used, so it can't be tweaked", declaration.name);
+                        continue;
+                    }
+                };
+                let mut values = [0.0; 4];
+                gl::GetUniformfv(program, uniform.location, values.as_mut_ptr());
+                tweaks.push(Tweak {
+                    name: declaration.name,
+                    widget: declaration.widget,
+                    values: values[..declaration.components].to_vec(),
+                    path: path.clone(),
+                });
+            }
+        }
+        Tweaks { tweaks }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tweaks.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tweak> {
+        self.tweaks.iter()
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Tweak> {
+        self.tweaks.iter_mut().find(|tweak| tweak.name == name)
+    }
+
+    // Set the tweaked values on the pipeline's program
+    pub unsafe fn apply(&self, backend: &GlBackend, pipeline: PipelineHandle) {
+        let program = backend.program_id(pipeline);
+        let uniforms = backend.uniforms(pipeline);
+        for tweak in &self.tweaks {
+            let location = match uniforms.get(&tweak.name) {
+                Some(uniform) => uniform.location,
+                None => continue,
+            };
+            let values = tweak.values.as_ptr();
+            match tweak.values.len() {
+                1 => gl::ProgramUniform1fv(program, location, 1, values),
+                2 => gl::ProgramUniform2fv(program, location, 1, values),
+                3 => gl::ProgramUniform3fv(program, location, 1, values),
+                _ => gl::ProgramUniform4fv(program, location, 1, values),
+            }
+        }
+    }
+
+    // Write the tweaked values into the shader sources as the initializers of their uniforms.
+    // Returns how many were written.
+    pub fn export(&self) -> io::Result<usize> {
+        let mut paths: Vec<&str> = self
+            .tweaks
+            .iter()
+            .map(|tweak| tweak.path.as_str())
+            .collect();
+        paths.dedup();
+        let mut exported = 0;
+        for path in paths {
+            let source = std::fs::read_to_string(path)?;
+            let mut lines: Vec<String> = source.lines().map(str::to_string).collect();
+            for line in &mut lines {
+                let declaration = match parse_declaration(line) {
+                    Some(declaration) => declaration,
+                    None => continue,
+                };
+                let tweak = self.tweaks.iter().find(|tweak| {
+                    tweak.path == path
+                        && tweak.name == declaration.name
+                        && tweak.values.len() == declaration.components
+                });
+                if let Some(tweak) = tweak {
+                    *line = with_initializer(line, &literal(tweak.glsl_type(), &tweak.values));
+                    exported += 1;
+                }
+            }
+            let mut source = lines.join("\n");
+            source.push('\n');
+            std::fs::write(path, source)?;
+        }
+        Ok(exported)
+    }
+
+    // A widget for each tweak, and a button exporting them
+    #[cfg(feature = "egui")]
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        for tweak in &mut self.tweaks {
+            ui.horizontal(|ui| {
+                ui.label(&tweak.name);
+                match (&tweak.widget, tweak.values.len()) {
+                    (Widget::Color, 3) => {
+                        let mut color = [tweak.values[0], tweak.values[1], tweak.values[2]];
+                        ui.color_edit_button_rgb(&mut color);
+                        tweak.values.copy_from_slice(&color);
+                    }
+                    (Widget::Color, 4) => {
+                        let mut color = [0.0; 4];
+                        color.copy_from_slice(&tweak.values);
+                        ui.color_edit_button_rgba_unmultiplied(&mut color);
+                        tweak.values.copy_from_slice(&color);
+                    }
+                    (&Widget::Slider(min, max), _) => {
+                        for value in &mut tweak.values {
+                            ui.add(egui::Slider::new(value, min..=max));
+                        }
+                    }
+                    _ => {
+                        for value in &mut tweak.values {
+                            ui.add(egui::DragValue::new(value).speed(0.01));
+                        }
+                    }
+                }
+            });
+        }
+        if ui.button("Export to shaders").clicked() {
+            match self.export() {
+                Ok(exported) => log::info!("Exported {} tweaked uniforms", exported),
+                Err(e) => warn!("Failed to export the tweaked uniforms: {}", e),
+            }
+        }
+    }
+}
+
+fn declarations(source: &str) -> Vec<Declaration> {
+    source.lines().filter_map(parse_declaration).collect()
+}
+
+// A line like `uniform vec3 lightColor = vec3(1.0); // @tweak color`
+fn parse_declaration(line: &str) -> Option<Declaration> {
+    let (code, comment) = line.split_once("//")?;
+    let mut arguments = comment.trim().strip_prefix("@tweak")?.split_whitespace();
+    let declaration = code
+        .trim()
+        .strip_prefix("uniform")?
+        .trim()
+        .strip_suffix(';')?;
+    let declaration = declaration.split('=').next()?;
+    let mut words = declaration.split_whitespace();
+    let components = match words.next()? {
+        "float" => 1,
+        "vec2" => 2,
+        "vec3" => 3,
+        "vec4" => 4,
+        glsl_type => {
+            warn!("Uniforms of type {} can't be tweaked", glsl_type);
+            return None;
+        }
+    };
+    let name = words.next()?.to_string();
+    let widget = match (arguments.next(), arguments.next()) {
+        (Some("color"), _) => Widget::Color,
+        (Some(min), Some(max)) => Widget::Slider(min.parse().ok()?, max.parse().ok()?),
+        _ => Widget::Number,
+    };
+    Some(Declaration {
+        name,
+        components,
+        widget,
+    })
+}
+
+// `values` as a GLSL literal of `glsl_type`, e.g. `vec2(0.5, 1.0)`
+fn literal(glsl_type: &str, values: &[f32]) -> String {
+    let values: Vec<String> = values.iter().map(|value| format!("{:?}", value)).collect();
+    match values.len() {
+        1 => values[0].clone(),
+        _ => format!("{}({})", glsl_type, values.join(", ")),
+    }
+}
+
+// A parsed declaration with its initializer replaced by `initializer`, keeping the comment
+fn with_initializer(line: &str, initializer: &str) -> String {
+    let (code, comment) = line.split_at(line.find("//").unwrap_or(line.len()));
+    let end = code.rfind(';').unwrap_or(code.len());
+    let name_end = code[..end].find('=').unwrap_or(end);
+    format!(
+        "{} = {}{}{}",
+        code[..name_end].trim_end(),
+        initializer,
+        &code[end..],
+        comment
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tagged_uniforms_are_parsed() {
+        let source = "\
+            uniform float highlight;\n\
+            uniform float ambient = 0.1; // @tweak 0 1\n\
+            uniform vec3 lightColor = vec3(1.0); // @tweak color\n\
+            uniform vec2 offset; // @tweak\n\
+            uniform vec3 other = vec3(0.0); // A comment\n\
+            uniform mat4 transform; // @tweak\n";
+        let declarations = declarations(source);
+        assert_eq!(
+            declarations,
+            vec![
+                Declaration {
+                    name: "ambient".to_string(),
+                    components: 1,
+                    widget: Widget::Slider(0.0, 1.0),
+                },
+                Declaration {
+                    name: "lightColor".to_string(),
+                    components: 3,
+                    widget: Widget::Color,
+                },
+                Declaration {
+                    name: "offset".to_string(),
+                    components: 2,
+                    widget: Widget::Number,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn literals_are_valid_glsl() {
+        assert_eq!(literal("float", &[1.0]), "1.0");
+        assert_eq!(literal("vec3", &[0.8, -0.5, 0.0]), "vec3(0.8, -0.5, 0.0)");
+    }
+
+    #[test]
+    fn exporting_replaces_initializers_and_keeps_comments() {
+        assert_eq!(
+            with_initializer("  uniform float ambient = 0.1;  // @tweak 0 1", "0.25"),
+            "  uniform float ambient = 0.25;  // @tweak 0 1"
+        );
+        assert_eq!(
+            with_initializer("uniform vec2 offset; // @tweak", "vec2(1.0, 2.0)"),
+            "uniform vec2 offset = vec2(1.0, 2.0); // @tweak"
+        );
+        let exported = with_initializer(
+            "uniform vec3 c = vec3(1.0); // @tweak color",
+            "vec3(0.5, 0.5, 0.5)",
+        );
+        assert_eq!(parse_declaration(&exported).unwrap().name, "c");
+    }
+}
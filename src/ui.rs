@@ -0,0 +1,222 @@
+// Panels drawn with egui over the scene, for tweaking it while the application runs. Enabled with
+// the `egui` feature.
+//
+// egui runs on the render thread like everything else. `run` feeds it the frame's input and lets
+// the application build its panels, which usually happens at the start of `update`, so the rest
+// of the update can leave alone whatever egui used: `wants_pointer_input` and
+// `wants_keyboard_input` say whether the mouse is over a panel or a text field has focus. `paint`
+// then draws the panels in a pass of their own, last in `render`.
+use crate::error::RenderError;
+use crate::input::{FrameInput, Modifier};
+use crate::window::GlContext;
+use egui::epaint::ClippedPrimitive;
+use egui::{Event, PointerButton, Pos2, RawInput, Rect, TexturesDelta, ViewportId};
+use egui_glow::glow;
+use egui_glow::Painter;
+use std::sync::Arc;
+use winit::event::MouseButton;
+
+const BUTTONS: [(MouseButton, PointerButton); 3] = [
+    (MouseButton::Left, PointerButton::Primary),
+    (MouseButton::Right, PointerButton::Secondary),
+    (MouseButton::Middle, PointerButton::Middle),
+];
+
+pub struct Ui {
+    context: egui::Context,
+    painter: Painter,
+    // What the last `run` produced, painted by `paint`
+    primitives: Vec<ClippedPrimitive>,
+    textures_delta: TexturesDelta,
+    screen_size: egui::Vec2, // In points
+}
+
+impl Ui {
+    pub unsafe fn new(gl: &GlContext) -> Result<Ui, RenderError> {
+        Ok(Ui {
+            context: egui::Context::default(),
+            painter: create_painter(gl)?,
+            primitives: vec![],
+            textures_delta: TexturesDelta::default(),
+            screen_size: egui::Vec2::ZERO,
+        })
+    }
+
+    // Lay out the panels `build` adds for this frame, with `input` and `elapsed` seconds since
+    // the first frame. `pixels_per_point` is the window's scale factor, which makes the panels
+    // the same size on high DPI screens.
+    pub fn run(
+        &mut self,
+        input: &FrameInput,
+        elapsed: f32,
+        pixels_per_point: f32,
+        build: impl FnMut(&egui::Context),
+    ) {
+        let raw_input = raw_input(input, elapsed, pixels_per_point);
+        self.screen_size = raw_input
+            .screen_rect
+            .map_or(egui::Vec2::ZERO, |rect| rect.size());
+        let mut build = build;
+        let output = self.context.run_ui(raw_input, |ui| build(ui.ctx()));
+        // Textures changed by frames that weren't painted still have to be uploaded
+        self.textures_delta.append(output.textures_delta);
+        self.primitives = self
+            .context
+            .tessellate(output.shapes, output.pixels_per_point);
+    }
+
+    // Whether the mouse is over a panel or dragging something in one, so clicks and mouse
+    // movement shouldn't reach the scene
+    pub fn wants_pointer_input(&self) -> bool {
+        self.context.egui_wants_pointer_input()
+    }
+
+    // Whether a text field has focus, so keys shouldn't reach the scene. Other widgets keep the
+    // focus after being clicked too, but only need keys for moving it on.
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.context.text_edit_focused()
+    }
+
+    // Draw the panels laid out by the last `run` over everything drawn so far. Viewports of
+    // another size than the window, like those of large screenshots, get the panels scaled to fit.
+    pub unsafe fn paint(&mut self, viewport_size: (u32, u32)) {
+        if self.screen_size.x <= 0.0 {
+            return;
+        }
+        // The painter leaves scissoring on and changes the blend function, and the scene may be
+        // drawn in wireframe
+        let mut polygon_mode = [0; 2];
+        gl::GetIntegerv(gl::POLYGON_MODE, polygon_mode.as_mut_ptr());
+        gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+
+        let pixels_per_point = viewport_size.0 as f32 / self.screen_size.x;
+        self.painter.paint_and_update_textures(
+            [viewport_size.0, viewport_size.1],
+            pixels_per_point,
+            &self.primitives,
+            &mut self.textures_delta,
+        );
+
+        gl::Disable(gl::SCISSOR_TEST);
+        gl::Enable(gl::CULL_FACE);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        gl::PolygonMode(gl::FRONT_AND_BACK, polygon_mode[0] as u32);
+        gl::BindVertexArray(0);
+    }
+
+    // The painter's objects, and egui's font texture, went away with the context. Starting over
+    // with a fresh egui context makes it upload its textures again, at the cost of closed panels
+    // and lost scroll positions.
+    pub unsafe fn context_recreated(&mut self, gl: &GlContext) -> Result<(), RenderError> {
+        let painter = create_painter(gl)?;
+        // Deleting the old objects could delete new ones that were given the same names
+        std::mem::forget(std::mem::replace(&mut self.painter, painter));
+        self.context = egui::Context::default();
+        self.primitives.clear();
+        self.textures_delta = TexturesDelta::default();
+        Ok(())
+    }
+}
+
+impl Drop for Ui {
+    // Applications are dropped on the render thread, before the context
+    fn drop(&mut self) {
+        self.painter.destroy();
+    }
+}
+
+unsafe fn create_painter(gl: &GlContext) -> Result<Painter, RenderError> {
+    let glow = glow::Context::from_loader_function_cstr(|symbol| gl.get_proc_address(symbol));
+    Ok(Painter::new(Arc::new(glow), "", None, false)?)
+}
+
+// Translate a frame's input into egui's. Cursor positions are in physical pixels, and egui works in
+// points.
+fn raw_input(input: &FrameInput, elapsed: f32, pixels_per_point: f32) -> RawInput {
+    let keys = &input.keys;
+    let modifiers = egui::Modifiers {
+        alt: keys.modifier_held(Modifier::Alt),
+        ctrl: keys.modifier_held(Modifier::Ctrl),
+        shift: keys.modifier_held(Modifier::Shift),
+        mac_cmd: false,
+        command: keys.modifier_held(Modifier::Ctrl),
+    };
+    let (width, height) = input.window_size;
+    let screen_size = egui::vec2(width as f32, height as f32) / pixels_per_point;
+    let mut events = vec![Event::ModifiersChanged(modifiers)];
+
+    let cursor = input
+        .cursor_position
+        .map(|(x, y)| Pos2::new(x, y) / pixels_per_point);
+    match cursor {
+        Some(position) => events.push(Event::PointerMoved(position)),
+        None => events.push(Event::PointerGone),
+    }
+    let buttons = &input.buttons;
+    for &(button, pointer_button) in BUTTONS.iter() {
+        // Quick clicks go down and up within a frame, and the button's state says which came last
+        let changes = if buttons.is_held(button) {
+            [
+                (buttons.just_released(button), false),
+                (buttons.just_pressed(button), true),
+            ]
+        } else {
+            [
+                (buttons.just_pressed(button), true),
+                (buttons.just_released(button), false),
+            ]
+        };
+        for &(changed, pressed) in changes.iter() {
+            if let Some(position) = cursor.filter(|_| changed) {
+                events.push(Event::PointerButton {
+                    pos: position,
+                    button: pointer_button,
+                    pressed,
+                    modifiers,
+                });
+            }
+        }
+    }
+    if input.scroll_delta != 0.0 {
+        events.push(Event::MouseWheel {
+            unit: egui::MouseWheelUnit::Line,
+            delta: egui::vec2(0.0, input.scroll_delta),
+            phase: egui::TouchPhase::Move,
+            modifiers,
+        });
+    }
+
+    // egui knows winit's key names, e.g. `KeyA` or `ArrowLeft`
+    let key_events = keys
+        .pressed()
+        .iter()
+        .map(|key| (key, true))
+        .chain(keys.released().iter().map(|key| (key, false)));
+    for (key, pressed) in key_events {
+        if let Some(key) = egui::Key::from_name(&format!("{:?}", key)) {
+            events.push(Event::Key {
+                key,
+                physical_key: None,
+                pressed,
+                repeat: false,
+                modifiers,
+            });
+        }
+    }
+    // Backspace and the like arrive as keys as well
+    let text: String = input.text.chars().filter(|c| !c.is_control()).collect();
+    if !text.is_empty() {
+        events.push(Event::Text(text));
+    }
+
+    let mut raw_input = RawInput {
+        screen_rect: Some(Rect::from_min_size(Pos2::ZERO, screen_size)),
+        time: Some(elapsed as f64),
+        events,
+        ..Default::default()
+    };
+    let viewport = raw_input.viewports.entry(ViewportId::ROOT).or_default();
+    viewport.native_pixels_per_point = Some(pixels_per_point);
+    raw_input
+}
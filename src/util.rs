@@ -1,32 +1,415 @@
-use std::ffi::CString;
-use libc;
+use crate::error::GlError;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::CStr;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+// Make an OpenGL call, and in debug builds check whether it raised an error, e.g. from a wrong
+// enum or stride, which OpenGL otherwise ignores silently while drawing nothing. Evaluates to a
+// `Result` with the call's return value, or a `GlError` naming the call and where it was made.
+//     let shader = gl_check!(gl::CreateShader(gl::VERTEX_SHADER))?;
+#[macro_export]
+macro_rules! gl_check {
+    ($module:ident $(:: $function:ident)* ($($argument:expr),* $(,)?)) => {{
+        let value = $module $(:: $function)* ($($argument),*);
+        let call = concat!(stringify!($module) $(, "::", stringify!($function))*);
+        $crate::util::check_gl_error(call, file!(), line!()).map(|()| value)
+    }};
+}
+
+// Return the first error raised since the last check, clearing the rest. Release builds don't
+// check, since glGetError waits for the driver to catch up with every call made so far.
+pub unsafe fn check_gl_error(call: &'static str, file: &'static str, line: u32) -> Result<(), GlError> {
+    if !cfg!(debug_assertions) { return Ok(()) }
+    let code = gl::GetError();
+    if code == gl::NO_ERROR { return Ok(()) }
+    while gl::GetError() != gl::NO_ERROR {}
+    Err(GlError { call, code, file, line })
+}
+
+pub fn gl_error_name(code: u32) -> String {
+    match code {
+        gl::INVALID_ENUM                  => "GL_INVALID_ENUM".to_string(),
+        gl::INVALID_VALUE                 => "GL_INVALID_VALUE".to_string(),
+        gl::INVALID_OPERATION             => "GL_INVALID_OPERATION".to_string(),
+        gl::INVALID_FRAMEBUFFER_OPERATION => "GL_INVALID_FRAMEBUFFER_OPERATION".to_string(),
+        gl::OUT_OF_MEMORY                 => "GL_OUT_OF_MEMORY".to_string(),
+        gl::STACK_UNDERFLOW               => "GL_STACK_UNDERFLOW".to_string(),
+        gl::STACK_OVERFLOW                => "GL_STACK_OVERFLOW".to_string(),
+        _                                 => format!("error 0x{:x}", code),
+    }
+}
 
 pub unsafe fn get_gl_string(name: gl::types::GLenum) -> String {
     std::ffi::CStr::from_ptr(gl::GetString(name) as *mut libc::c_char).to_string_lossy().to_string()
 }
 
-// Debug callback to panic upon encountering any OpenGL error
+// How important a debug message is, from the least to the most
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DebugSeverity { Notification, Low, Medium, High }
+
+impl DebugSeverity {
+    fn from_gl(severity: u32) -> DebugSeverity {
+        match severity {
+            gl::DEBUG_SEVERITY_HIGH   => DebugSeverity::High,
+            gl::DEBUG_SEVERITY_MEDIUM => DebugSeverity::Medium,
+            gl::DEBUG_SEVERITY_LOW    => DebugSeverity::Low,
+            _                         => DebugSeverity::Notification,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DebugSeverity::Notification => "notification",
+            DebugSeverity::Low          => "low",
+            DebugSeverity::Medium       => "medium",
+            DebugSeverity::High         => "high",
+        }
+    }
+}
+
+impl std::str::FromStr for DebugSeverity {
+    type Err = ();
+    fn from_str(name: &str) -> Result<DebugSeverity, ()> {
+        [DebugSeverity::Notification, DebugSeverity::Low, DebugSeverity::Medium, DebugSeverity::High]
+            .iter().copied().find(|severity| severity.name() == name).ok_or(())
+    }
+}
+
+// The sources of debug messages, by the names used in the config file
+pub const DEBUG_SOURCES: [(u32, &str); 6] = [
+    (gl::DEBUG_SOURCE_API,             "api"),
+    (gl::DEBUG_SOURCE_WINDOW_SYSTEM,   "window_system"),
+    (gl::DEBUG_SOURCE_SHADER_COMPILER, "shader_compiler"),
+    (gl::DEBUG_SOURCE_THIRD_PARTY,     "third_party"),
+    (gl::DEBUG_SOURCE_APPLICATION,     "application"),
+    (gl::DEBUG_SOURCE_OTHER,           "other"),
+];
+
+fn debug_source_name(source: u32) -> &'static str {
+    DEBUG_SOURCES.iter().find(|&&(s, _)| s == source).map_or("other", |&(_, name)| name)
+}
+
+fn debug_type_name(e_type: u32) -> &'static str {
+    match e_type {
+        gl::DEBUG_TYPE_ERROR               => "error",
+        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated behavior",
+        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR  => "undefined behavior",
+        gl::DEBUG_TYPE_PORTABILITY         => "portability issue",
+        gl::DEBUG_TYPE_PERFORMANCE         => "performance issue",
+        gl::DEBUG_TYPE_MARKER              => "marker",
+        gl::DEBUG_TYPE_PUSH_GROUP          => "group push",
+        gl::DEBUG_TYPE_POP_GROUP           => "group pop",
+        _                                  => "message",
+    }
+}
+
+// Which debug messages are logged, see `set_debug_filter`
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugFilter {
+    pub min_severity:    DebugSeverity, // Less severe messages are dropped
+    pub ignored_sources: Vec<u32>,      // GL_DEBUG_SOURCE_* values, see `DEBUG_SOURCES`
+    pub ignored_ids:     Vec<u32>,      // Driver specific message ids, as printed in the log
+    pub panic_on_error:  bool,          // Panic at the offending call on errors, in debug builds only
+    pub rate_limit:      u32,           // Most messages logged per id and second, 0 for no limit
+}
+
+impl Default for DebugFilter {
+    fn default() -> Self {
+        DebugFilter {
+            min_severity:    DebugSeverity::Low,
+            ignored_sources: vec![],
+            ignored_ids:     vec![],
+            panic_on_error:  false,
+            rate_limit:      10,
+        }
+    }
+}
+
+// Debug messages kept for crash reports, see `recent_debug_messages`
+const RECENT_MESSAGES: usize = 32;
+
+struct DebugOutput {
+    filter:       DebugFilter,
+    window_start: Instant,            // Start of the second messages are being counted for
+    counts:       HashMap<u32, u32>, // Messages seen this second, by id
+    recent:       VecDeque<String>,  // The latest logged messages, the latest last
+}
+
+impl DebugOutput {
+    fn new(filter: DebugFilter) -> DebugOutput {
+        DebugOutput { filter, window_start: Instant::now(), counts: HashMap::new(), recent: VecDeque::new() }
+    }
+}
+
+static DEBUG_OUTPUT: Mutex<Option<DebugOutput>> = Mutex::new(None);
+
+fn debug_output() -> MutexGuard<'static, Option<DebugOutput>> {
+    DEBUG_OUTPUT.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Change which debug messages `debug_callback` logs. Takes effect right away, on any thread.
+pub fn set_debug_filter(filter: DebugFilter) {
+    let mut output = debug_output();
+    match output.as_mut() {
+        Some(output) => output.filter = filter,
+        None => *output = Some(DebugOutput::new(filter)),
+    }
+}
+
+pub fn debug_filter() -> DebugFilter {
+    debug_output().as_ref().map(|output| output.filter.clone()).unwrap_or_default()
+}
+
+// The last debug messages `debug_callback` logged, oldest first
+pub fn recent_debug_messages() -> Vec<String> {
+    debug_output().as_ref().map(|output| output.recent.iter().cloned().collect()).unwrap_or_default()
+}
+
+// Debug callback logging the OpenGL messages that pass the filter set with `set_debug_filter`.
+// Only the driver calls it, with a valid message. Output is synchronous, so a panic on an error
+// happens inside the call that raised it, aborting with a backtrace that points there.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "system" fn debug_callback(
     source: u32, e_type: u32, id: u32,
-    severity: u32, _length: i32,
+    severity: u32, length: i32,
     msg: *const libc::c_char, _data: *mut std::ffi::c_void
 ) {
-    if e_type != gl::DEBUG_TYPE_ERROR { return }
-    if severity == gl::DEBUG_SEVERITY_HIGH ||
-       severity == gl::DEBUG_SEVERITY_MEDIUM ||
-       severity == gl::DEBUG_SEVERITY_LOW
+    let severity = DebugSeverity::from_gl(severity);
+    let mut guard = debug_output();
+    let output = guard.get_or_insert_with(|| DebugOutput::new(DebugFilter::default()));
+    let filter = &output.filter;
+    if severity < filter.min_severity
+        || filter.ignored_sources.contains(&source)
+        || filter.ignored_ids.contains(&id)
     {
-        let severity_string = match severity {
-            gl::DEBUG_SEVERITY_HIGH => "high",
-            gl::DEBUG_SEVERITY_MEDIUM => "medium",
-            gl::DEBUG_SEVERITY_LOW => "low",
-            _ => "unknown",
+        return;
+    }
+
+    // Drivers may repeat the same notification every draw call, so only so many per second pass
+    if output.window_start.elapsed() >= Duration::from_secs(1) {
+        for (&id, &count) in &output.counts {
+            if filter.rate_limit != 0 && count > filter.rate_limit {
+                log::warn!("{}: Dropped {} more debug messages", id, count - filter.rate_limit);
+            }
+        }
+        output.counts.clear();
+        output.window_start = Instant::now();
+    }
+    let count = output.counts.entry(id).or_insert(0);
+    *count += 1;
+    let is_error = e_type == gl::DEBUG_TYPE_ERROR;
+    let panic = is_error && filter.panic_on_error && cfg!(debug_assertions);
+    if filter.rate_limit != 0 && *count > filter.rate_limit && !panic {
+        return;
+    }
+
+    // The message belongs to the driver, so it is only borrowed. Messages repeating what the
+    // application passed in, e.g. debug group names, may not be nul terminated.
+    let error_message = if length >= 0 {
+        String::from_utf8_lossy(unsafe { std::slice::from_raw_parts(msg as *const u8, length as usize) })
+    } else {
+        unsafe { CStr::from_ptr(msg) }.to_string_lossy()
+    };
+    let message = format!("{}: {} of severity {} raised from {}: {}",
+        id, capitalize(debug_type_name(e_type)), severity.name(), debug_source_name(source), error_message);
+    if output.recent.len() == RECENT_MESSAGES {
+        output.recent.pop_front();
+    }
+    output.recent.push_back(message.clone());
+    // Crash reports read the recent messages while panicking
+    drop(guard);
+    if panic {
+        panic!("{}", message);
+    }
+    if is_error || severity == DebugSeverity::High { log::error!("{}", message) }
+    else if severity == DebugSeverity::Notification { log::debug!("{}", message) }
+    else { log::warn!("{}", message) }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
+// Name an OpenGL object, e.g. a VAO (gl::VERTEX_ARRAY) or a texture (gl::TEXTURE), so graphics
+// debuggers like RenderDoc and apitrace show the label rather than a bare number. Does nothing
+// without KHR_debug.
+pub unsafe fn label_object(identifier: u32, name: u32, label: &str) {
+    if !gl::ObjectLabel::is_loaded() { return }
+    gl::ObjectLabel(identifier, name, label.len() as i32, label.as_ptr() as *const libc::c_char);
+}
+
+// The label given to an OpenGL object with `label_object`, if any
+pub unsafe fn object_label(identifier: u32, name: u32) -> Option<String> {
+    if !gl::GetObjectLabel::is_loaded() || name == 0 { return None }
+    let mut buffer = [0u8; 256];
+    let mut length = 0;
+    gl::GetObjectLabel(identifier, name, buffer.len() as i32, &mut length, buffer.as_mut_ptr() as *mut libc::c_char);
+    if length <= 0 { return None }
+    Some(String::from_utf8_lossy(&buffer[..length as usize]).into_owned())
+}
+
+// Group the calls until the matching `pop_debug_group` under `name` in graphics debuggers, e.g.
+// one group per render pass. Groups nest. Does nothing without KHR_debug.
+pub unsafe fn push_debug_group(name: &str) {
+    if !gl::PushDebugGroup::is_loaded() { return }
+    gl::PushDebugGroup(gl::DEBUG_SOURCE_APPLICATION, 0, name.len() as i32, name.as_ptr() as *const libc::c_char);
+}
+
+pub unsafe fn pop_debug_group() {
+    if !gl::PopDebugGroup::is_loaded() { return }
+    gl::PopDebugGroup();
+}
+
+// The optional OpenGL features the current context supports, by version or extension. macOS only
+// offers OpenGL 4.1, so anything newer must be checked here and given a fallback.
+#[derive(Clone, Debug)]
+pub struct GlFeatures {
+    pub debug_output: bool,    // glDebugMessageCallback, core in 4.3 or GL_KHR_debug
+    pub compute_shaders: bool, // core in 4.3 or GL_ARB_compute_shader
+    pub storage_buffers: bool, // Shader storage buffer objects, core in 4.3 or GL_ARB_shader_storage_buffer_object
+    pub buffer_storage: bool,  // Immutable, persistently mappable buffers, core in 4.4 or GL_ARB_buffer_storage
+    pub video_memory_info: VideoMemoryInfo,
+}
+
+// How the driver can be asked for free video memory, if at all
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum VideoMemoryInfo {
+    #[default]
+    Unavailable,
+    Nvx, // GL_NVX_gpu_memory_info, on NVIDIA
+    Ati, // GL_ATI_meminfo, on AMD
+}
+
+impl GlFeatures {
+    pub unsafe fn detect() -> GlFeatures {
+        GlFeatures::from_extensions(get_gl_version(), &get_gl_extensions())
+    }
+
+    fn from_extensions(version: (u32, u32), extensions: &[String]) -> GlFeatures {
+        let supports = |core: (u32, u32), extension: &str| {
+            version >= core || extensions.iter().any(|e| e == extension)
         };
-        unsafe {
-            let string = CString::from_raw(msg as *mut libc::c_char);
-            let error_message = String::from_utf8_lossy(string.as_bytes()).to_string();
-            panic!("{}: Error of severity {} raised from {}: {}\n",
-                id, severity_string, source, error_message);
+        GlFeatures {
+            debug_output:    supports((4, 3), "GL_KHR_debug") && gl::DebugMessageCallback::is_loaded(),
+            compute_shaders: supports((4, 3), "GL_ARB_compute_shader"),
+            storage_buffers: supports((4, 3), "GL_ARB_shader_storage_buffer_object"),
+            buffer_storage:  supports((4, 4), "GL_ARB_buffer_storage") && gl::BufferStorage::is_loaded(),
+            video_memory_info:
+                if extensions.iter().any(|e| e == "GL_NVX_gpu_memory_info") { VideoMemoryInfo::Nvx }
+                else if extensions.iter().any(|e| e == "GL_ATI_meminfo") { VideoMemoryInfo::Ati }
+                else { VideoMemoryInfo::Unavailable },
+        }
+    }
+}
+
+// What the current context is and what it can do, detected once when it is set up, see
+// `renderer::init_gl`, and printed by `--print-caps`
+#[derive(Clone, Debug)]
+pub struct GlCapabilities {
+    pub vendor:                 String,
+    pub renderer:               String,
+    pub version:                (u32, u32),
+    pub version_string:         String,
+    pub glsl_version:           String,
+    pub max_texture_size:       u32, // Width and height, in texels
+    pub max_renderbuffer_size:  u32, // Width and height, in pixels
+    pub max_samples:            u32, // For multisampled framebuffers
+    pub max_vertex_attributes:  u32,
+    pub max_uniform_block_size: u64, // In bytes
+    pub max_uniform_bindings:   u32, // Uniform buffers bound at once
+    pub max_storage_block_size: u64, // In bytes, 0 without storage buffers
+    pub max_storage_bindings:   u32, // Storage buffers bound at once, 0 without storage buffers
+    pub extensions:             Vec<String>,
+    pub features:               GlFeatures,
+}
+
+impl GlCapabilities {
+    pub unsafe fn detect() -> GlCapabilities {
+        let integer = |name| { let mut value = 0; gl::GetIntegerv(name, &mut value); value.max(0) as u32 };
+        let integer64 = |name| { let mut value = 0; gl::GetInteger64v(name, &mut value); value.max(0) as u64 };
+        let version = get_gl_version();
+        let extensions = get_gl_extensions();
+        let features = GlFeatures::from_extensions(version, &extensions);
+        GlCapabilities {
+            vendor:                 get_gl_string(gl::VENDOR),
+            renderer:               get_gl_string(gl::RENDERER),
+            version,
+            version_string:         get_gl_string(gl::VERSION),
+            glsl_version:           get_gl_string(gl::SHADING_LANGUAGE_VERSION),
+            max_texture_size:       integer(gl::MAX_TEXTURE_SIZE),
+            max_renderbuffer_size:  integer(gl::MAX_RENDERBUFFER_SIZE),
+            max_samples:            integer(gl::MAX_SAMPLES),
+            max_vertex_attributes:  integer(gl::MAX_VERTEX_ATTRIBS),
+            max_uniform_block_size: integer64(gl::MAX_UNIFORM_BLOCK_SIZE),
+            max_uniform_bindings:   integer(gl::MAX_UNIFORM_BUFFER_BINDINGS),
+            max_storage_block_size: if features.storage_buffers { integer64(gl::MAX_SHADER_STORAGE_BLOCK_SIZE) } else { 0 },
+            max_storage_bindings:   if features.storage_buffers { integer(gl::MAX_SHADER_STORAGE_BUFFER_BINDINGS) } else { 0 },
+            extensions,
+            features,
+        }
+    }
+
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.iter().any(|e| e == name)
+    }
+}
+
+impl std::fmt::Display for GlCapabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let yes_no = |supported| if supported { "yes" } else { "no" };
+        writeln!(f, "Vendor                  : {}", self.vendor)?;
+        writeln!(f, "Renderer                : {}", self.renderer)?;
+        writeln!(f, "OpenGL                  : {}", self.version_string)?;
+        writeln!(f, "GLSL                    : {}", self.glsl_version)?;
+        writeln!(f, "Max texture size        : {}", self.max_texture_size)?;
+        writeln!(f, "Max renderbuffer size   : {}", self.max_renderbuffer_size)?;
+        writeln!(f, "Max samples             : {}", self.max_samples)?;
+        writeln!(f, "Max vertex attributes   : {}", self.max_vertex_attributes)?;
+        writeln!(f, "Max uniform block size  : {} bytes", self.max_uniform_block_size)?;
+        writeln!(f, "Uniform buffer bindings : {}", self.max_uniform_bindings)?;
+        writeln!(f, "Max storage block size  : {} bytes", self.max_storage_block_size)?;
+        writeln!(f, "Storage buffer bindings : {}", self.max_storage_bindings)?;
+        writeln!(f, "Debug output            : {}", yes_no(self.features.debug_output))?;
+        writeln!(f, "Compute shaders         : {}", yes_no(self.features.compute_shaders))?;
+        writeln!(f, "Storage buffers         : {}", yes_no(self.features.storage_buffers))?;
+        writeln!(f, "Buffer storage          : {}", yes_no(self.features.buffer_storage))?;
+        writeln!(f, "Video memory info       : {:?}", self.features.video_memory_info)?;
+        writeln!(f, "Extensions ({}):", self.extensions.len())?;
+        for extension in &self.extensions {
+            writeln!(f, "    {}", extension)?;
         }
+        Ok(())
     }
 }
+
+// Free video memory in bytes, as far as the driver will tell
+pub unsafe fn get_available_video_memory(info: VideoMemoryInfo) -> Option<u64> {
+    const GPU_MEMORY_INFO_CURRENT_AVAILABLE_VIDMEM_NVX: u32 = 0x9049;
+    const TEXTURE_FREE_MEMORY_ATI: u32 = 0x87FC;
+    // Both report kilobytes. ATI_meminfo fills in four values, the first being the free total.
+    let mut kilobytes = [0; 4];
+    match info {
+        VideoMemoryInfo::Nvx => gl::GetIntegerv(GPU_MEMORY_INFO_CURRENT_AVAILABLE_VIDMEM_NVX, kilobytes.as_mut_ptr()),
+        VideoMemoryInfo::Ati => gl::GetIntegerv(TEXTURE_FREE_MEMORY_ATI, kilobytes.as_mut_ptr()),
+        VideoMemoryInfo::Unavailable => return None,
+    }
+    Some(kilobytes[0] as u64 * 1024)
+}
+
+pub unsafe fn get_gl_version() -> (u32, u32) {
+    let (mut major, mut minor) = (0, 0);
+    gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+    gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+    (major as u32, minor as u32)
+}
+
+pub unsafe fn get_gl_extensions() -> Vec<String> {
+    let mut count = 0;
+    gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+    (0..count as u32).map(|i| {
+        std::ffi::CStr::from_ptr(gl::GetStringi(gl::EXTENSIONS, i) as *const libc::c_char)
+            .to_string_lossy().to_string()
+    }).collect()
+}
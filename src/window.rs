@@ -0,0 +1,428 @@
+// Window and OpenGL context creation with winit and glutin.
+//
+// The window, display, surface and context are created on the event loop's thread, which is the
+// only place windows can be created. The context is then handed to the render thread, which makes
+// it current and keeps it for the rest of the program. In headless mode there is no window or event
+// loop at all, and the context renders into framebuffer objects only.
+use crate::error::RenderError;
+use glutin::config::{Config, ConfigTemplateBuilder, GlConfig};
+use glutin::context::{
+    AsRawContext, ContextApi, ContextAttributesBuilder, GlProfile, NotCurrentContext,
+    NotCurrentGlContext, PossiblyCurrentContext, PossiblyCurrentGlContext, RawContext, Robustness,
+    Version,
+};
+use glutin::display::{Display, GetGlDisplay, GlDisplay};
+use glutin::surface::{GlSurface, Surface, SwapInterval, WindowSurface};
+use glutin_winit::GlWindow as _;
+use std::ffi::{c_void, CStr, CString};
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use winit::dpi::PhysicalSize;
+use winit::event_loop::ActiveEventLoop;
+use winit::raw_window_handle::HasWindowHandle;
+use winit::window::{Window, WindowAttributes};
+
+// A window with an OpenGL context that is not yet current on any thread
+pub struct GlWindow {
+    target: Target,
+    config: Config,
+    context: NotCurrentContext,
+}
+
+// What a context draws to
+#[allow(clippy::large_enum_variant)] // There are only ever one or two of these
+enum Target {
+    // The window is shared with the event loop, which updates its title
+    Window {
+        window: Arc<Window>,
+        surface: Surface<WindowSurface>,
+    },
+    // Nothing, apart from framebuffer objects of the given size
+    Headless {
+        size: PhysicalSize<u32>,
+    },
+}
+
+// Create a window and an OpenGL context for it. `samples` is the number of samples per pixel for
+// multisampling, where 0 disables it. Drivers only offer certain counts, so the closest available
+// one is used.
+pub fn create(
+    event_loop: &ActiveEventLoop,
+    attributes: WindowAttributes,
+    samples: u16,
+) -> Result<GlWindow, RenderError> {
+    let mut template = ConfigTemplateBuilder::new().with_alpha_size(8);
+    if samples > 0 {
+        template = template.with_multisampling(samples.min(u8::MAX as u16) as u8);
+    }
+    let (window, config) = glutin_winit::DisplayBuilder::new()
+        .with_window_attributes(Some(attributes))
+        .build(event_loop, template, |configs| {
+            configs
+                .min_by_key(|config| (config.num_samples() as i32 - samples as i32).abs())
+                .expect("No OpenGL configurations available")
+        })
+        .map_err(|e| RenderError::Window(e.to_string()))?;
+    let window = window.ok_or_else(|| RenderError::Window("no window was created".to_string()))?;
+
+    let context = create_context(&config, Some(&window), None)?;
+    let surface_attributes = window
+        .build_surface_attributes(Default::default())
+        .map_err(|e| RenderError::Window(e.to_string()))?;
+    let surface = unsafe {
+        config
+            .display()
+            .create_window_surface(&config, &surface_attributes)?
+    };
+
+    Ok(GlWindow {
+        target: Target::Window {
+            window: Arc::new(window),
+            surface,
+        },
+        config,
+        context,
+    })
+}
+
+// Create another window with a context that shares textures, buffers and shaders with the context
+// of `main`. Vertex array objects are never shared, so they have to be created for each context.
+pub fn create_shared(
+    event_loop: &ActiveEventLoop,
+    attributes: WindowAttributes,
+    main: &GlWindow,
+) -> Result<GlWindow, RenderError> {
+    let window = glutin_winit::finalize_window(event_loop, attributes, &main.config)
+        .map_err(|e| RenderError::Window(e.to_string()))?;
+    let context = create_context(
+        &main.config,
+        Some(&window),
+        Some(main.context.raw_context()),
+    )?;
+    let surface_attributes = window
+        .build_surface_attributes(Default::default())
+        .map_err(|e| RenderError::Window(e.to_string()))?;
+    let surface = unsafe {
+        main.config
+            .display()
+            .create_window_surface(&main.config, &surface_attributes)?
+    };
+
+    Ok(GlWindow {
+        target: Target::Window {
+            window: Arc::new(window),
+            surface,
+        },
+        config: main.config.clone(),
+        context,
+    })
+}
+
+// Create a context without a window through EGL, which can render offscreen without a display
+// server, e.g. on a CI machine
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+pub fn create_headless(width: u32, height: u32) -> Result<GlWindow, RenderError> {
+    use glutin::api::egl;
+    use glutin::config::ConfigSurfaceTypes;
+
+    let device = egl::device::Device::query_devices()?
+        .next()
+        .ok_or(RenderError::Unsupported("No EGL devices available"))?;
+    let display = Display::Egl(unsafe { egl::display::Display::with_device(&device, None)? });
+    let template = ConfigTemplateBuilder::new()
+        .with_alpha_size(8)
+        .with_surface_type(ConfigSurfaceTypes::empty())
+        .build();
+    let config =
+        unsafe { display.find_configs(template)? }
+            .next()
+            .ok_or(RenderError::Unsupported(
+                "No OpenGL configurations available",
+            ))?;
+    let context = create_context(&config, None, None)?;
+
+    Ok(GlWindow {
+        target: Target::Headless {
+            size: PhysicalSize::new(width, height),
+        },
+        config,
+        context,
+    })
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub fn create_headless(_width: u32, _height: u32) -> Result<GlWindow, RenderError> {
+    Err(RenderError::Unsupported(
+        "Headless rendering needs EGL, which isn't available on this platform",
+    ))
+}
+
+// Core profile versions to try, newest first. macOS stops at 4.1, and features from later versions
+// are only used when `util::GlFeatures` reports them.
+const GL_VERSIONS: [(u8, u8); 7] = [(4, 6), (4, 5), (4, 4), (4, 3), (4, 2), (4, 1), (3, 3)];
+
+// Ask for the newest core profile context available. Each version is first tried with a context
+// that reports GPU resets instead of silently misbehaving, then without when robustness isn't
+// supported.
+fn create_context(
+    config: &Config,
+    window: Option<&Window>,
+    share: Option<RawContext>,
+) -> Result<NotCurrentContext, RenderError> {
+    let display = config.display();
+    let raw_window_handle = window
+        .and_then(|window| window.window_handle().ok())
+        .map(|handle| handle.as_raw());
+    let mut last_error = None;
+    for (major, minor) in GL_VERSIONS {
+        let attributes = || {
+            let builder = ContextAttributesBuilder::new()
+                .with_context_api(ContextApi::OpenGl(Some(Version::new(major, minor))))
+                .with_profile(GlProfile::Core);
+            match share {
+                Some(share) => builder.with_sharing(&SharedContext(share)),
+                None => builder,
+            }
+        };
+        let robust_attributes = attributes()
+            .with_robustness(Robustness::RobustLoseContextOnReset)
+            .build(raw_window_handle);
+        let context = unsafe {
+            display
+                .create_context(config, &robust_attributes)
+                .or_else(|_| display.create_context(config, &attributes().build(raw_window_handle)))
+        };
+        match context {
+            Ok(context) => return Ok(context),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    let (major, minor) = GL_VERSIONS[GL_VERSIONS.len() - 1];
+    Err(RenderError::NoContext {
+        major,
+        minor,
+        source: last_error.expect("No OpenGL versions to try"),
+    })
+}
+
+// The context to share objects with when creating another one
+struct SharedContext(RawContext);
+
+impl AsRawContext for SharedContext {
+    fn raw_context(&self) -> RawContext {
+        self.0
+    }
+}
+
+impl Target {
+    fn window(&self) -> Option<&Window> {
+        match self {
+            Target::Window { window, .. } => Some(window.as_ref()),
+            Target::Headless { .. } => None,
+        }
+    }
+
+    fn make_current(
+        &self,
+        context: NotCurrentContext,
+    ) -> Result<PossiblyCurrentContext, glutin::error::Error> {
+        match self {
+            Target::Window { surface, .. } => context.make_current(surface),
+            Target::Headless { .. } => match context {
+                #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+                NotCurrentContext::Egl(context) => context
+                    .make_current_surfaceless()
+                    .map(PossiblyCurrentContext::Egl),
+                #[allow(unreachable_patterns)]
+                _ => Err(
+                    glutin::error::ErrorKind::NotSupported("surfaceless contexts need EGL").into(),
+                ),
+            },
+        }
+    }
+}
+
+impl GlWindow {
+    // The window, unless the context is headless
+    pub fn window(&self) -> Option<&Window> {
+        self.target.window()
+    }
+
+    // Another reference to the window, for the event loop to keep once the context has moved to
+    // the render thread
+    pub fn shared_window(&self) -> Option<Arc<Window>> {
+        match &self.target {
+            Target::Window { window, .. } => Some(Arc::clone(window)),
+            Target::Headless { .. } => None,
+        }
+    }
+
+    // Make the context current on the calling thread and load the OpenGL functions
+    pub fn make_current(self) -> Result<GlContext, RenderError> {
+        let context = self.target.make_current(self.context)?;
+        let display = self.config.display();
+        load_gl(&display);
+        Ok(GlContext {
+            target: self.target,
+            display,
+            config: self.config,
+            context,
+        })
+    }
+}
+
+// A window, or a headless target, with an OpenGL context that is current on the render thread
+pub struct GlContext {
+    target: Target,
+    display: Display,
+    config: Config,
+    context: PossiblyCurrentContext,
+}
+
+impl GlContext {
+    // The window, unless the context is headless
+    pub fn window(&self) -> Option<&Window> {
+        self.target.window()
+    }
+
+    // Size of the window, or of the offscreen framebuffer for headless contexts
+    pub fn size(&self) -> PhysicalSize<u32> {
+        match &self.target {
+            Target::Window { window, .. } => window.inner_size(),
+            Target::Headless { size } => *size,
+        }
+    }
+
+    // Resize the surface to match the window. Zero sizes, which happen while minimized, are ignored.
+    pub fn resize(&self, size: PhysicalSize<u32>) {
+        if let (Target::Window { surface, .. }, Some(width), Some(height)) = (
+            &self.target,
+            NonZeroU32::new(size.width),
+            NonZeroU32::new(size.height),
+        ) {
+            surface.resize(&self.context, width, height);
+        }
+    }
+
+    // Address of an OpenGL function, for libraries that load their own bindings, like egui's
+    // painter
+    pub fn get_proc_address(&self, symbol: &CStr) -> *const c_void {
+        self.display.get_proc_address(symbol)
+    }
+
+    // Present the frame. Headless contexts have nothing to present.
+    pub fn swap_buffers(&self) -> Result<(), glutin::error::Error> {
+        match &self.target {
+            Target::Window { surface, .. } => surface.swap_buffers(&self.context),
+            Target::Headless { .. } => Ok(()),
+        }
+    }
+
+    pub fn set_vsync(&self, vsync: bool) -> Result<(), glutin::error::Error> {
+        let interval = if vsync {
+            SwapInterval::Wait(NonZeroU32::MIN)
+        } else {
+            SwapInterval::DontWait
+        };
+        match &self.target {
+            Target::Window { surface, .. } => surface.set_swap_interval(&self.context, interval),
+            Target::Headless { .. } => Ok(()),
+        }
+    }
+
+    // Vsync that shows frames which missed a refresh right away, tearing briefly instead of
+    // waiting a whole refresh. Needs the GLX or WGL swap_control_tear extension.
+    pub fn set_adaptive_vsync(&self) -> Result<(), glutin::error::Error> {
+        use glutin::display::GetDisplayExtensions;
+        use glutin::error::ErrorKind;
+        use std::ffi::c_int;
+
+        let not_supported = || ErrorKind::NotSupported("adaptive vsync isn't supported").into();
+        let surface = match &self.target {
+            Target::Window { surface, .. } => surface,
+            Target::Headless { .. } => return Err(not_supported()),
+        };
+        // A negative swap interval enables adaptive vsync
+        match (&self.display, surface) {
+            #[cfg(all(
+                unix,
+                not(any(target_os = "macos", target_os = "ios", target_os = "android"))
+            ))]
+            (Display::Glx(display), Surface::Glx(surface)) => {
+                use glutin::display::AsRawDisplay;
+                use glutin::display::RawDisplay;
+                use glutin::surface::{AsRawSurface, RawSurface};
+
+                if !display.extensions().contains("GLX_EXT_swap_control_tear") {
+                    return Err(not_supported());
+                }
+                let (RawDisplay::Glx(raw_display), RawSurface::Glx(drawable)) =
+                    (display.raw_display(), surface.raw_surface())
+                else {
+                    return Err(not_supported());
+                };
+                let swap_interval =
+                    display.get_proc_address(&CString::new("glXSwapIntervalEXT").unwrap());
+                if swap_interval.is_null() {
+                    return Err(not_supported());
+                }
+                let swap_interval: unsafe extern "C" fn(*const c_void, u64, c_int) =
+                    unsafe { std::mem::transmute(swap_interval) };
+                unsafe { swap_interval(raw_display, drawable, -1) };
+                Ok(())
+            }
+            #[cfg(windows)]
+            (Display::Wgl(display), Surface::Wgl(_)) => {
+                if !display.extensions().contains("WGL_EXT_swap_control_tear") {
+                    return Err(not_supported());
+                }
+                let swap_interval =
+                    display.get_proc_address(&CString::new("wglSwapIntervalEXT").unwrap());
+                if swap_interval.is_null() {
+                    return Err(not_supported());
+                }
+                let swap_interval: unsafe extern "system" fn(c_int) -> c_int =
+                    unsafe { std::mem::transmute(swap_interval) };
+                match unsafe { swap_interval(-1) } {
+                    0 => Err(std::io::Error::last_os_error().into()),
+                    _ => Ok(()),
+                }
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(not_supported()),
+        }
+    }
+
+    // Make this context current again after another one was, e.g. the context of a second window
+    pub fn make_current(&self) -> Result<(), glutin::error::Error> {
+        match (&self.target, &self.context) {
+            (Target::Window { surface, .. }, context) => context.make_current(surface),
+            #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+            (Target::Headless { .. }, PossiblyCurrentContext::Egl(context)) => {
+                context.make_current_surfaceless()
+            }
+            #[allow(unreachable_patterns)]
+            _ => {
+                Err(glutin::error::ErrorKind::NotSupported("surfaceless contexts need EGL").into())
+            }
+        }
+    }
+
+    // Replace the context with a fresh one for the same target, e.g. after the old one was lost in
+    // a GPU reset. Every OpenGL object has to be created again afterwards. A context that shared
+    // objects with another one has to be given the recreated `share` context.
+    pub fn recreate(&mut self, share: Option<&GlContext>) -> Result<(), RenderError> {
+        let share = share.map(|share| share.context.raw_context());
+        let context = create_context(&self.config, self.target.window(), share)?;
+        self.context = self.target.make_current(context)?;
+        load_gl(&self.display);
+        Ok(())
+    }
+}
+
+fn load_gl(display: &Display) {
+    gl::load_with(|symbol| {
+        let symbol = CString::new(symbol).unwrap();
+        display.get_proc_address(&symbol) as *const _
+    });
+}